@@ -0,0 +1,174 @@
+//! A small contacts service using every starlight crate: phone numbers validated with
+//! starlight-utils, errors derived with starlight-i18n and answered in the request
+//! locale, the axum middleware stack, and an [`AxumService`] run by the starlight-tokio
+//! [`ServiceManager`] until Ctrl-C.
+//!
+//! ```sh
+//! cargo run -p starlight-axum --example fullstack
+//! curl -X PUT localhost:8080/contacts/0912345678 -H 'content-type: application/json' -d '{"name": "Lan"}'
+//! curl -H 'accept-language: vi' localhost:8080/contacts/12ab
+//! ```
+//!
+//! `tests/fullstack_test.rs` runs the same service.
+
+use serde::{Deserialize, Serialize};
+use starlight_axum::axum::Router;
+use starlight_axum::axum::extract::{FromRequestParts, State};
+use starlight_axum::axum::http::StatusCode;
+use starlight_axum::axum::http::request::Parts;
+use starlight_axum::axum::routing::get;
+use starlight_axum::error::{ErrorMapperLayer, HandlerError, I18nError};
+use starlight_axum::middleware::locale::LocaleLayer;
+use starlight_axum::stack::StackBuilder;
+use starlight_axum::starlight_tokio::{ServiceManager, shutdown_signal};
+use starlight_axum::{AxumService, Json, Listener, MeterLifecycleObserver};
+use starlight_i18n::I18nCode;
+use starlight_protocol::i18n::Catalog;
+use starlight_utils::{PhoneNumber, normalize_phone};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(I18nCode, Debug, Clone)]
+#[i18n(protocol)]
+pub enum ContactsError {
+    #[i18n_code("contacts.internal")]
+    Internal,
+    #[i18n_code("contacts.invalid_phone")]
+    InvalidPhone,
+    #[i18n_code("contacts.not_found")]
+    NotFound,
+}
+
+impl I18nError for ContactsError {
+    fn status(&self) -> StatusCode {
+        match self {
+            ContactsError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+            ContactsError::InvalidPhone => StatusCode::UNPROCESSABLE_ENTITY,
+            ContactsError::NotFound => StatusCode::NOT_FOUND,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidPhone(String);
+
+impl std::fmt::Display for InvalidPhone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} is not a phone number", self.0)
+    }
+}
+
+impl std::error::Error for InvalidPhone {}
+
+#[derive(Debug)]
+pub struct UnknownContact(String);
+
+impl std::fmt::Display for UnknownContact {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "no contact with phone {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownContact {}
+
+/// The `{phone}` path segment normalized to E.164, Vietnam being the default country.
+pub struct Phone(pub PhoneNumber);
+
+impl<S: Send + Sync> FromRequestParts<S> for Phone {
+    type Rejection = HandlerError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let starlight_axum::axum::extract::Path(raw) =
+            starlight_axum::axum::extract::Path::<String>::from_request_parts(parts, state).await?;
+        match normalize_phone(&raw, "VN") {
+            Some(number) => Ok(Phone(number)),
+            None => Err(InvalidPhone(raw).into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Contact {
+    pub name: String,
+    pub phone: String,
+    pub country: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewContact {
+    pub name: String,
+}
+
+type Contacts = Arc<Mutex<HashMap<String, Contact>>>;
+
+async fn put_contact(
+    State(contacts): State<Contacts>,
+    Phone(number): Phone,
+    Json(new): Json<NewContact>,
+) -> Json<Contact> {
+    let contact = Contact {
+        name: new.name,
+        phone: number.e164.clone(),
+        country: number.iso_country.map(str::to_owned),
+    };
+    let mut contacts = contacts.lock().unwrap_or_else(|e| e.into_inner());
+    contacts.insert(number.e164, contact.clone());
+    Json(contact)
+}
+
+async fn get_contact(State(contacts): State<Contacts>, Phone(number): Phone) -> Result<Json<Contact>, HandlerError> {
+    let contacts = contacts.lock().unwrap_or_else(|e| e.into_inner());
+    let contact = contacts.get(&number.e164).cloned().ok_or(UnknownContact(number.e164))?;
+    Ok(Json(contact))
+}
+
+pub fn catalog() -> Catalog {
+    Catalog::new()
+        .with("en", "contacts.internal", "Something went wrong")
+        .with("en", "contacts.invalid_phone", "This is not a valid phone number")
+        .with("en", "contacts.not_found", "No contact has this phone number")
+        .with("vi", "contacts.internal", "Đã xảy ra lỗi")
+        .with("vi", "contacts.invalid_phone", "Số điện thoại không hợp lệ")
+        .with("vi", "contacts.not_found", "Không có liên hệ nào với số điện thoại này")
+}
+
+fn map_error(err: &anyhow::Error) -> Option<ContactsError> {
+    if err.is::<InvalidPhone>() {
+        Some(ContactsError::InvalidPhone)
+    } else if err.is::<UnknownContact>() {
+        Some(ContactsError::NotFound)
+    } else {
+        None
+    }
+}
+
+pub fn app() -> Router {
+    let router = Router::new()
+        .route("/contacts/{phone}", get(get_contact).put(put_contact))
+        .with_state(Contacts::default())
+        .layer(
+            ErrorMapperLayer::new(ContactsError::Internal)
+                .with_mapper(map_error)
+                .with_translator(Arc::new(catalog())),
+        )
+        .layer(LocaleLayer::new(["en", "vi"], "en"));
+    StackBuilder::recommended(Duration::from_secs(10))
+        .build(router)
+        .expect("the recommended stack is valid")
+}
+
+pub fn service(listener: Listener) -> AxumService {
+    AxumService::new(listener, app()).with_name("contacts")
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let listener = Listener::tcp(([127, 0, 0, 1], 8080).into());
+    ServiceManager::new()
+        .with_observer(MeterLifecycleObserver::new())
+        .with_service(service(listener).with_flush_telemetry(false))
+        .run(shutdown_signal())
+        .await
+        .into_result()
+}
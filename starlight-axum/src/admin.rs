@@ -0,0 +1,317 @@
+use crate::config::{AppConfig, DynamicConfig, TelemetryConfig, dump_redacted};
+use crate::logger::recent_errors;
+use crate::middleware::maintenance::MaintenanceSwitch;
+use crate::middleware::slow::SlowRequestLog;
+use crate::serve::connection_stats;
+use axum::Router;
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{Request, State};
+use axum::http::{StatusCode, header};
+use axum::middleware::{Next, from_fn_with_state};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+
+/// `GET /admin/errors`: the events kept by [`crate::logger::RecentErrorsLayer`] as
+/// JSON, newest first. Requests must carry `Authorization: Bearer <token>`.
+///
+/// Merge it into the router of an internal listener; nothing is mounted by default.
+/// Fails with [`AdminRouterError::EmptyToken`] if `token` is empty or only whitespace.
+pub fn errors_router(token: impl Into<String>) -> Result<Router, AdminRouterError> {
+    let token = bearer_token(token)?;
+    Ok(Router::new()
+        .route("/admin/errors", get(|| async { Json(recent_errors()) }))
+        .route_layer(from_fn_with_state(token, require_token)))
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct MaintenanceState {
+    pub enabled: bool,
+}
+
+/// `GET /admin/maintenance` reports and `PUT /admin/maintenance` with
+/// `{"enabled": true}` sets the maintenance switch. Requests must carry
+/// `Authorization: Bearer <token>`.
+///
+/// Keep `/admin` allowlisted in the [`MaintenanceLayer`](crate::middleware::maintenance::MaintenanceLayer)
+/// so the switch can be turned off again. Fails with [`AdminRouterError::EmptyToken`] if
+/// `token` is empty or only whitespace.
+pub fn maintenance_router(token: impl Into<String>, switch: MaintenanceSwitch) -> Result<Router, AdminRouterError> {
+    let token = bearer_token(token)?;
+    let current = switch.clone();
+    Ok(Router::new()
+        .route(
+            "/admin/maintenance",
+            get(move || async move { Json(MaintenanceState { enabled: current.is_enabled() }) }).put(
+                move |Json(state): Json<MaintenanceState>| async move {
+                    switch.set(state.enabled);
+                    Json(state)
+                },
+            ),
+        )
+        .route_layer(from_fn_with_state(token, require_token)))
+}
+
+/// An empty token would let `Authorization: Bearer ` through.
+fn bearer_token(token: impl Into<String>) -> Result<Arc<str>, AdminRouterError> {
+    let token = token.into();
+    if token.trim().is_empty() {
+        return Err(AdminRouterError::EmptyToken);
+    }
+    Ok(Arc::from(token))
+}
+
+/// Answers 401 with the admin [`error`] body unless the request carries
+/// `Authorization: Bearer <token>`.
+async fn require_token(State(token): State<Arc<str>>, req: Request, next: Next) -> Response {
+    let presented = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match presented {
+        Some(presented) if constant_time_eq(presented.as_bytes(), token.as_bytes()) => next.run(req).await,
+        _ => error(StatusCode::UNAUTHORIZED, "unauthorized", "a valid bearer token is required"),
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// What the binary was built from, served by [`AdminRouter`] on `GET /admin/build`.
+/// Capture it with [`build_info!`](crate::build_info).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct BuildInfo {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub commit: Option<&'static str>,
+    pub rustc: Option<&'static str>,
+}
+
+/// The [`BuildInfo`] of the crate calling it. `commit` and `rustc` come from the
+/// `GIT_COMMIT` and `RUSTC_VERSION` environment variables at compile time, e.g. set by
+/// a build script with `cargo:rustc-env=GIT_COMMIT=...`.
+#[macro_export]
+macro_rules! build_info {
+    () => {
+        $crate::admin::BuildInfo {
+            name: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+            commit: option_env!("GIT_COMMIT"),
+            rustc: option_env!("RUSTC_VERSION"),
+        }
+    };
+}
+
+/// `{"data": ...}`, the body of successful admin responses.
+fn data(value: impl Serialize) -> Response {
+    Json(serde_json::json!({ "data": value })).into_response()
+}
+
+/// `{"error": code, "message": ...}`, the body of failed admin responses.
+fn error(status: StatusCode, code: &str, message: impl fmt::Display) -> Response {
+    (status, Json(serde_json::json!({ "error": code, "message": message.to_string() }))).into_response()
+}
+
+#[derive(Debug, Clone)]
+enum AdminAuth {
+    Token(String),
+    Transport,
+    Unauthenticated,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdminRouterError {
+    /// Neither a token, `mtls_only` nor `dangerously_unauthenticated` was chosen.
+    MissingAuthentication,
+    /// The token given to `with_token` is empty or only whitespace.
+    EmptyToken,
+}
+
+impl fmt::Display for AdminRouterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdminRouterError::MissingAuthentication => f.write_str(
+                "the admin router needs a token or mtls_only(), or dangerously_unauthenticated() to serve it openly",
+            ),
+            AdminRouterError::EmptyToken => f.write_str("the admin router token must not be empty"),
+        }
+    }
+}
+
+impl std::error::Error for AdminRouterError {}
+
+/// The operational endpoints under one prefix (`/admin` by default) with one
+/// authentication. Every feature is off until enabled on the [`AdminRouterBuilder`]:
+///
+/// - `GET /admin/errors`: [`recent_errors`], see [`crate::logger::RecentErrorsLayer`]
+/// - `GET` and `PUT /admin/maintenance`: the [`MaintenanceSwitch`]
+/// - `GET /admin/config`: the [`dump_redacted`] telemetry configuration, or the
+///   effective [`AppConfig`] with [`AdminRouterBuilder::with_app_config`]
+/// - `GET /admin/build`: the [`BuildInfo`]
+/// - `GET /admin/slow-requests`: the [`SlowRequestLog`], slowest first
+/// - `GET /admin/connections`: the [`connection_stats`] of every listener
+///
+/// Responses are `{"data": ...}` or `{"error": code, "message": ...}`; paths of disabled
+/// features answer 404.
+pub struct AdminRouter;
+
+impl AdminRouter {
+    pub fn builder() -> AdminRouterBuilder {
+        AdminRouterBuilder {
+            prefix: "/admin".to_owned(),
+            auth: None,
+            recent_errors: false,
+            maintenance: None,
+            config: None,
+            build_info: None,
+            slow_requests: None,
+            connections: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AdminRouterBuilder {
+    prefix: String,
+    auth: Option<AdminAuth>,
+    recent_errors: bool,
+    maintenance: Option<MaintenanceSwitch>,
+    config: Option<ServedConfig>,
+    build_info: Option<BuildInfo>,
+    slow_requests: Option<SlowRequestLog>,
+    connections: bool,
+}
+
+#[derive(Debug, Clone)]
+enum ServedConfig {
+    Telemetry(Box<TelemetryConfig>),
+    App(DynamicConfig<AppConfig>),
+}
+
+impl AdminRouterBuilder {
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Requires `Authorization: Bearer <token>`.
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.auth = Some(AdminAuth::Token(token.into()));
+        self
+    }
+
+    /// Leaves authentication to the listener. Only serve the router with
+    /// [`serve_tls`](crate::serve_tls) and a [`TlsConfig`](crate::tls::TlsConfig) requiring
+    /// client certificates.
+    pub fn mtls_only(mut self) -> Self {
+        self.auth = Some(AdminAuth::Transport);
+        self
+    }
+
+    /// Serves the endpoints to anyone who can reach them.
+    pub fn dangerously_unauthenticated(mut self) -> Self {
+        self.auth = Some(AdminAuth::Unauthenticated);
+        self
+    }
+
+    pub fn with_recent_errors(mut self) -> Self {
+        self.recent_errors = true;
+        self
+    }
+
+    pub fn with_maintenance(mut self, switch: MaintenanceSwitch) -> Self {
+        self.maintenance = Some(switch);
+        self
+    }
+
+    pub fn with_config(mut self, config: TelemetryConfig) -> Self {
+        self.config = Some(ServedConfig::Telemetry(Box::new(config)));
+        self
+    }
+
+    /// Serves the values in effect, following reloads of a config from
+    /// [`AppConfig::watch`], instead of the [`AdminRouterBuilder::with_config`] one.
+    pub fn with_app_config(mut self, config: impl Into<DynamicConfig<AppConfig>>) -> Self {
+        self.config = Some(ServedConfig::App(config.into()));
+        self
+    }
+
+    /// Usually `with_build_info(starlight_axum::build_info!())`.
+    pub fn with_build_info(mut self, build_info: BuildInfo) -> Self {
+        self.build_info = Some(build_info);
+        self
+    }
+
+    /// Usually the [`SlowRequestLayer::log`](crate::middleware::slow::SlowRequestLayer::log)
+    /// of the service's router.
+    pub fn with_slow_requests(mut self, log: SlowRequestLog) -> Self {
+        self.slow_requests = Some(log);
+        self
+    }
+
+    /// Serves the [`connection_stats`] of the listeners of this process.
+    pub fn with_connections(mut self) -> Self {
+        self.connections = true;
+        self
+    }
+
+    pub fn build(self) -> Result<Router, AdminRouterError> {
+        let auth = self.auth.ok_or(AdminRouterError::MissingAuthentication)?;
+        if matches!(auth, AdminAuth::Unauthenticated) {
+            warn!("admin endpoints under {} are served without authentication", self.prefix);
+        }
+
+        let mut router = Router::new();
+        if self.recent_errors {
+            router = router.route("/errors", get(|| async { data(recent_errors()) }));
+        }
+        if let Some(switch) = self.maintenance {
+            let current = switch.clone();
+            router = router.route(
+                "/maintenance",
+                get(move || async move { data(MaintenanceState { enabled: current.is_enabled() }) }).put(
+                    move |state: Result<Json<MaintenanceState>, JsonRejection>| async move {
+                        match state {
+                            Ok(Json(state)) => {
+                                switch.set(state.enabled);
+                                data(state)
+                            }
+                            Err(rejection) => error(StatusCode::BAD_REQUEST, "invalid_request", rejection.body_text()),
+                        }
+                    },
+                ),
+            );
+        }
+        match self.config {
+            Some(ServedConfig::Telemetry(config)) => {
+                let dump = Arc::new(dump_redacted(&config));
+                router = router.route("/config", get(move || async move { data(&*dump) }));
+            }
+            Some(ServedConfig::App(config)) => {
+                router = router.route("/config", get(move || async move { data(config.get().dump_redacted()) }));
+            }
+            None => {}
+        }
+        if let Some(build_info) = self.build_info {
+            router = router.route("/build", get(move || async move { data(build_info) }));
+        }
+        if let Some(log) = self.slow_requests {
+            router = router.route("/slow-requests", get(move || async move { data(log.slowest()) }));
+        }
+        if self.connections {
+            router = router.route("/connections", get(|| async { data(connection_stats()) }));
+        }
+        router = router.fallback(|| async { error(StatusCode::NOT_FOUND, "not_found", "no such admin endpoint") });
+
+        let router = match auth {
+            AdminAuth::Token(token) => router.layer(from_fn_with_state(bearer_token(token)?, require_token)),
+            AdminAuth::Transport | AdminAuth::Unauthenticated => router,
+        };
+        Ok(Router::new().nest(&self.prefix, router))
+    }
+}
@@ -0,0 +1,280 @@
+use crate::attrs;
+use crate::meter::GLOBAL_METER;
+use axum::extract::{MatchedPath, Request};
+use axum::response::Response;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Histogram, Meter};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+use tracing::Span;
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static CURRENT: Cell<*const Counters> = const { Cell::new(std::ptr::null()) };
+}
+
+/// A global allocator counting the allocations of the request being polled on the
+/// current thread, for [`AllocationLayer`]:
+///
+/// ```ignore
+/// #[global_allocator]
+/// static ALLOC: CountingAlloc = CountingAlloc::system();
+/// ```
+///
+/// Outside of requests it only forwards to the wrapped allocator.
+#[derive(Debug, Default)]
+pub struct CountingAlloc<A = System> {
+    inner: A,
+}
+
+impl CountingAlloc<System> {
+    pub const fn system() -> Self {
+        CountingAlloc { inner: System }
+    }
+}
+
+impl<A> CountingAlloc<A> {
+    pub const fn new(inner: A) -> Self {
+        CountingAlloc { inner }
+    }
+
+    /// Whether a `CountingAlloc` is the global allocator, known once it allocated.
+    pub fn is_installed() -> bool {
+        INSTALLED.load(Ordering::Relaxed)
+    }
+}
+
+fn record(allocated: usize, freed: usize) {
+    if !INSTALLED.load(Ordering::Relaxed) {
+        INSTALLED.store(true, Ordering::Relaxed);
+    }
+    // Threads being torn down have no counters left.
+    let _ = CURRENT.try_with(|current| {
+        // SAFETY: the pointer is set while a `Tracked` future holding the `Arc` polls.
+        if let Some(counters) = unsafe { current.get().as_ref() } {
+            counters.record(allocated, freed);
+        }
+    });
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for CountingAlloc<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { self.inner.alloc(layout) };
+        if !ptr.is_null() {
+            record(layout.size(), 0);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { self.inner.alloc_zeroed(layout) };
+        if !ptr.is_null() {
+            record(layout.size(), 0);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { self.inner.dealloc(ptr, layout) };
+        record(0, layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = unsafe { self.inner.realloc(ptr, layout, new_size) };
+        if !new.is_null() {
+            record(new_size, layout.size());
+        }
+        new
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    allocated: AtomicU64,
+    live: AtomicI64,
+    peak: AtomicI64,
+}
+
+impl Counters {
+    fn record(&self, allocated: usize, freed: usize) {
+        let change = allocated as i64 - freed as i64;
+        self.allocated.fetch_add(allocated as u64, Ordering::Relaxed);
+        let live = self.live.fetch_add(change, Ordering::Relaxed) + change;
+        self.peak.fetch_max(live, Ordering::Relaxed);
+    }
+
+    fn stats(&self) -> AllocationStats {
+        AllocationStats {
+            allocated_bytes: self.allocated.load(Ordering::Relaxed),
+            peak_bytes: self.peak.load(Ordering::Relaxed).max(0) as u64,
+        }
+    }
+}
+
+/// What a request allocated, stored as a response extension by [`AllocationLayer`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AllocationStats {
+    /// Total bytes allocated, including memory freed again.
+    pub allocated_bytes: u64,
+    /// The most bytes the request held at once.
+    pub peak_bytes: u64,
+}
+
+#[derive(Debug, Clone)]
+struct AllocationConfig {
+    warn_peak_bytes: u64,
+    allocated: Histogram<u64>,
+    peak: Histogram<u64>,
+}
+
+/// Measures the memory each request allocates while its handler is polled, when
+/// [`CountingAlloc`] is the global allocator; otherwise requests pass through untouched.
+///
+/// The [`AllocationStats`] are set as the `alloc.bytes` and `alloc.peak_bytes`
+/// attributes of the current span, recorded on the `http.server.request.allocated` and
+/// `http.server.request.peak_allocated` histograms, and a warning naming the route is
+/// logged when the peak exceeds the threshold (64 MiB by default). Memory is attributed
+/// to the request polling on the thread, so tasks it spawns and bodies streamed after
+/// the response head are not counted, and frees of memory allocated elsewhere lower the
+/// peak.
+#[derive(Debug, Clone)]
+pub struct AllocationLayer {
+    config: Arc<AllocationConfig>,
+}
+
+impl Default for AllocationLayer {
+    fn default() -> Self {
+        Self::with_meter(&GLOBAL_METER)
+    }
+}
+
+impl AllocationLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_meter(meter: &Meter) -> Self {
+        AllocationLayer {
+            config: Arc::new(AllocationConfig {
+                warn_peak_bytes: 64 * 1024 * 1024,
+                allocated: meter
+                    .u64_histogram("http.server.request.allocated")
+                    .with_description("Bytes allocated while handling requests")
+                    .with_unit("By")
+                    .build(),
+                peak: meter
+                    .u64_histogram("http.server.request.peak_allocated")
+                    .with_description("Most bytes held at once while handling requests")
+                    .with_unit("By")
+                    .build(),
+            }),
+        }
+    }
+
+    pub fn with_warn_threshold(mut self, peak_bytes: u64) -> Self {
+        Arc::make_mut(&mut self.config).warn_peak_bytes = peak_bytes;
+        self
+    }
+}
+
+impl<S> Layer<S> for AllocationLayer {
+    type Service = AllocationService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AllocationService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AllocationService<S> {
+    inner: S,
+    layer: AllocationLayer,
+}
+
+impl<S, B> Service<Request<B>> for AllocationService<S>
+where
+    S: Service<Request<B>, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        if !CountingAlloc::<System>::is_installed() {
+            return Box::pin(self.inner.call(req));
+        }
+        let route = req
+            .extensions()
+            .get::<MatchedPath>()
+            .map_or_else(|| req.uri().path().to_owned(), |path| path.as_str().to_owned());
+        let config = self.layer.config.clone();
+        let counters = Arc::new(Counters::default());
+        let future = Tracked {
+            inner: self.inner.call(req),
+            counters: counters.clone(),
+        };
+        let span = Span::current();
+        Box::pin(async move {
+            let mut response = future.await?;
+            let stats = counters.stats();
+            attrs::record(
+                &span,
+                attrs! { "alloc.bytes" => stats.allocated_bytes, "alloc.peak_bytes" => stats.peak_bytes },
+            );
+            let attributes = [KeyValue::new("http.route", route.clone())];
+            config.allocated.record(stats.allocated_bytes, &attributes);
+            config.peak.record(stats.peak_bytes, &attributes);
+            if stats.peak_bytes > config.warn_peak_bytes {
+                warn!(
+                    "{} held {} bytes at peak, {} allocated in total",
+                    route, stats.peak_bytes, stats.allocated_bytes
+                );
+            }
+            response.extensions_mut().insert(stats);
+            Ok(response)
+        })
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// Attributes the allocations made while polling `inner` to `counters`.
+    struct Tracked<F> {
+        #[pin]
+        inner: F,
+        counters: Arc<Counters>,
+    }
+}
+
+impl<F: Future> Future for Tracked<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let this = self.project();
+        let _restore = Restore(CURRENT.with(|current| current.replace(Arc::as_ptr(this.counters))));
+        this.inner.poll(cx)
+    }
+}
+
+/// Puts back the counters of the enclosing request, also when polling panics.
+struct Restore(*const Counters);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        CURRENT.with(|current| current.set(self.0));
+    }
+}
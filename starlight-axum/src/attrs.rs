@@ -0,0 +1,188 @@
+//! The [`attrs!`](crate::attrs!) macro, building attributes for spans and metrics:
+//!
+//! ```
+//! use starlight_axum::attrs;
+//!
+//! let route = "/orders/{id}";
+//! let attributes = attrs! { "http.route" => route, "http.response.status_code" => 200u16 };
+//! assert_eq!(attributes[1].value, 200i64.into());
+//! ```
+//!
+//! Keys are literals checked at compile time: lowercase ASCII letters, digits and `_`
+//! in segments separated by single dots, starting with a letter. `attrs! { "HTTP Route" => .. }`
+//! fails to build with "invalid attribute key HTTP Route".
+//!
+//! Keys naming per-user values, such as `user_id` or `email`, would make a series per
+//! user; they log a warning the first time each place records them, unless marked
+//! `#[allow_high_cardinality]`, e.g. for span attributes, which are not aggregated:
+//!
+//! ```
+//! # let user = "u-42";
+//! let attributes = starlight_axum::attrs! { #[allow_high_cardinality] "user.id" => user };
+//! ```
+
+use opentelemetry::{Array, StringValue, Value};
+use std::borrow::Cow;
+use std::sync::Arc;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+pub use opentelemetry::KeyValue;
+
+/// Keys, or last segments of keys, holding a value per user.
+pub const HIGH_CARDINALITY_KEYS: &[&str] = &[
+    "user_id",
+    "user.id",
+    "enduser.id",
+    "email",
+    "phone",
+    "session_id",
+    "session.id",
+];
+
+/// Builds `[KeyValue; N]` from `"key" => value` pairs, for metric recordings as
+/// `&attrs! { .. }` and for spans through [`record`]. Values are strings, booleans,
+/// integers, floats or [`Value`]s, see [`AttributeValue`]. See the [module](crate::attrs)
+/// for the checks of the keys.
+#[macro_export]
+macro_rules! attrs {
+    () => {
+        [] as [$crate::attrs::KeyValue; 0]
+    };
+    ($($(#[$marker:ident])? $key:literal => $value:expr),+ $(,)?) => {
+        [$($crate::__attr!($(#[$marker])? $key => $value)),*]
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __attr {
+    (#[allow_high_cardinality] $key:literal => $value:expr) => {{
+        // An item rather than an inline `const` block, so `cargo check` evaluates it too.
+        const _: () = ::std::assert!(
+            $crate::attrs::is_valid_key($key),
+            ::std::concat!("invalid attribute key ", $key)
+        );
+        $crate::attrs::KeyValue::new($key, $crate::attrs::AttributeValue::into_value($value))
+    }};
+    ($key:literal => $value:expr) => {{
+        // An item rather than an inline `const` block, so `cargo check` evaluates it too.
+        const _: () = ::std::assert!(
+            $crate::attrs::is_valid_key($key),
+            ::std::concat!("invalid attribute key ", $key)
+        );
+        if const { $crate::attrs::is_high_cardinality($key) } {
+            static WARNED: ::std::sync::Once = ::std::sync::Once::new();
+            WARNED.call_once(|| $crate::attrs::warn_high_cardinality($key, ::std::file!(), ::std::line!()));
+        }
+        $crate::attrs::KeyValue::new($key, $crate::attrs::AttributeValue::into_value($value))
+    }};
+}
+
+/// Sets `attributes` on the OpenTelemetry span of `span`.
+pub fn record(span: &Span, attributes: impl IntoIterator<Item = KeyValue>) {
+    for attribute in attributes {
+        span.set_attribute(attribute.key, attribute.value);
+    }
+}
+
+/// A value of an [`attrs!`](crate::attrs!) attribute. Integers are recorded as `i64`,
+/// `u64` and `usize` saturating at `i64::MAX`.
+pub trait AttributeValue {
+    fn into_value(self) -> Value;
+}
+
+macro_rules! attribute_values {
+    ($($t:ty => |$v:ident| $value:expr),* $(,)?) => {
+        $(impl AttributeValue for $t {
+            fn into_value(self) -> Value {
+                let $v = self;
+                $value
+            }
+        })*
+    };
+}
+
+attribute_values! {
+    Value => |v| v,
+    &'static str => |v| Value::from(v),
+    String => |v| Value::from(v),
+    Cow<'static, str> => |v| Value::from(v),
+    Arc<str> => |v| Value::from(v),
+    StringValue => |v| Value::String(v),
+    Array => |v| Value::Array(v),
+    bool => |v| Value::Bool(v),
+    i8 => |v| Value::I64(v.into()),
+    i16 => |v| Value::I64(v.into()),
+    i32 => |v| Value::I64(v.into()),
+    i64 => |v| Value::I64(v),
+    u8 => |v| Value::I64(v.into()),
+    u16 => |v| Value::I64(v.into()),
+    u32 => |v| Value::I64(v.into()),
+    u64 => |v| Value::I64(i64::try_from(v).unwrap_or(i64::MAX)),
+    usize => |v| Value::I64(i64::try_from(v).unwrap_or(i64::MAX)),
+    f32 => |v| Value::F64(v.into()),
+    f64 => |v| Value::F64(v),
+}
+
+#[doc(hidden)]
+pub const fn is_valid_key(key: &str) -> bool {
+    let bytes = key.as_bytes();
+    if bytes.is_empty() || !bytes[0].is_ascii_lowercase() || bytes[bytes.len() - 1] == b'.' {
+        return false;
+    }
+    let mut i = 0;
+    while i < bytes.len() {
+        let valid = match bytes[i] {
+            b'a'..=b'z' | b'0'..=b'9' | b'_' => true,
+            b'.' => bytes[i + 1] != b'.',
+            _ => false,
+        };
+        if !valid {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+#[doc(hidden)]
+pub const fn is_high_cardinality(key: &str) -> bool {
+    let mut i = 0;
+    while i < HIGH_CARDINALITY_KEYS.len() {
+        if ends_with_segment(key.as_bytes(), HIGH_CARDINALITY_KEYS[i].as_bytes()) {
+            return true;
+        }
+        i += 1;
+    }
+    false
+}
+
+/// Whether `key` is `segment` or ends with `.segment`.
+const fn ends_with_segment(key: &[u8], segment: &[u8]) -> bool {
+    if key.len() < segment.len() {
+        return false;
+    }
+    let start = key.len() - segment.len();
+    if start > 0 && key[start - 1] != b'.' {
+        return false;
+    }
+    let mut i = 0;
+    while i < segment.len() {
+        if key[start + i] != segment[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+#[doc(hidden)]
+pub fn warn_high_cardinality(key: &str, file: &str, line: u32) {
+    warn!(
+        attribute.key = key,
+        location = %format_args!("{}:{}", file, line),
+        "deprecated: attribute {} has a value per user; mark it #[allow_high_cardinality] if intended",
+        key
+    );
+}
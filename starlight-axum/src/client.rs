@@ -0,0 +1,464 @@
+use crate::deadline::{self, X_REQUEST_DEADLINE};
+use crate::egress::{EgressPolicy, EgressResolver};
+use crate::meter::GLOBAL_METER;
+use axum::body::Body;
+use axum::http::{HeaderValue, Method, Request, Response, StatusCode, header};
+use axum::response::IntoResponse;
+use http_body::Body as _;
+use http_body_util::BodyExt;
+use hyper_util::client::legacy::Client;
+use hyper_util::client::legacy::connect::dns::GaiResolver;
+use hyper_util::client::legacy::connect::{Connect, HttpConnector};
+use hyper_util::rt::TokioExecutor;
+use opentelemetry::metrics::{Counter, Histogram};
+use opentelemetry::{KeyValue, global};
+use opentelemetry_http::HeaderInjector;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+use tower::retry::budget::{Budget, TpsBudget};
+use tracing::Instrument;
+use tracing::field::Empty;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+mod circuit_breaker;
+
+pub use circuit_breaker::{
+    CIRCUIT_BREAKER_STATE, CIRCUIT_BREAKER_TRANSITIONS, CircuitBreaker, CircuitBreakerPolicy, CircuitState,
+};
+
+static CLIENT_DURATION: LazyLock<Histogram<f64>> = LazyLock::new(|| {
+    GLOBAL_METER
+        .f64_histogram("http.client.request.duration")
+        .with_description("Duration of outbound HTTP requests")
+        .with_unit("s")
+        .build()
+});
+
+static CLIENT_RETRIES: LazyLock<Counter<u64>> = LazyLock::new(|| {
+    GLOBAL_METER
+        .u64_counter("http.client.retries")
+        .with_description("Outbound HTTP attempts resent after a failed attempt")
+        .build()
+});
+
+static CLIENT_HEDGES: LazyLock<Counter<u64>> = LazyLock::new(|| {
+    GLOBAL_METER
+        .u64_counter("http.client.hedges")
+        .with_description("Outbound HTTP attempts sent while an earlier one was still pending")
+        .build()
+});
+
+static CLIENT_BUDGET_EXHAUSTED: LazyLock<Counter<u64>> = LazyLock::new(|| {
+    GLOBAL_METER
+        .u64_counter("http.client.retry_budget.exhausted")
+        .with_description("Retries and hedges not sent because the retry budget was spent")
+        .build()
+});
+
+#[derive(Debug)]
+pub enum ClientError {
+    /// The deadline of the request being handled passed before or during the call.
+    DeadlineExceeded,
+    /// The client's own timeout elapsed.
+    Timeout(Duration),
+    Request(hyper_util::client::legacy::Error),
+    /// The request body could not be buffered for resending.
+    Body(axum::Error),
+    /// The egress policy does not allow calling this `host:port`.
+    EgressDenied(String),
+    /// The [`CircuitBreaker`] of this `host:port` is open, and probes it again after
+    /// `retry_after`.
+    CircuitOpen { destination: String, retry_after: Duration },
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::DeadlineExceeded => f.write_str("request deadline exceeded"),
+            ClientError::Timeout(timeout) => write!(f, "request timed out after {:?}", timeout),
+            ClientError::Request(err) => write!(f, "request failed: {}", err),
+            ClientError::Body(err) => write!(f, "failed to read the request body: {}", err),
+            ClientError::EgressDenied(destination) => write!(f, "egress to {} is not allowed", destination),
+            ClientError::CircuitOpen { destination, .. } => write!(f, "the circuit to {} is open", destination),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::Request(err) => Some(err),
+            ClientError::Body(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// An open circuit is `503 Service Unavailable` with a `Retry-After` of the time left
+/// before the destination is probed, in whole seconds.
+impl IntoResponse for ClientError {
+    fn into_response(self) -> axum::response::Response {
+        if let ClientError::CircuitOpen { retry_after, .. } = &self {
+            let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            let headers = [(header::RETRY_AFTER, seconds.max(1).to_string())];
+            return (StatusCode::SERVICE_UNAVAILABLE, headers, self.to_string()).into_response();
+        }
+        let status = match self {
+            ClientError::DeadlineExceeded | ClientError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            ClientError::Request(_) => StatusCode::BAD_GATEWAY,
+            ClientError::Body(_) | ClientError::EgressDenied(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ClientError::CircuitOpen { .. } => StatusCode::SERVICE_UNAVAILABLE,
+        };
+        (status, self.to_string()).into_response()
+    }
+}
+
+/// How [`TracedClient`] resends idempotent requests to one destination.
+///
+/// A request is retried after a connection error, a per-try timeout or a 502, 503 or 504
+/// response, up to `max_attempts` attempts in total. With a hedge delay, another attempt
+/// is sent when none has answered within the delay; the first answer wins and the other
+/// attempts are cancelled. Every retry and hedge is withdrawn from the client's retry
+/// budget.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    per_try_timeout: Option<Duration>,
+    hedge_delay: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RetryPolicy {
+    /// Two attempts, without per-try timeout or hedging.
+    pub fn new() -> Self {
+        RetryPolicy {
+            max_attempts: 2,
+            per_try_timeout: None,
+            hedge_delay: None,
+        }
+    }
+
+    /// Attempts in total, including the first one.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn with_per_try_timeout(mut self, timeout: Duration) -> Self {
+        self.per_try_timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_hedge_delay(mut self, delay: Duration) -> Self {
+        self.hedge_delay = Some(delay);
+        self
+    }
+}
+
+/// Methods which can be sent twice without changing the outcome.
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE | Method::PUT | Method::DELETE
+    )
+}
+
+fn is_retryable(result: &Result<Response<Body>, ClientError>) -> bool {
+    match result {
+        Ok(response) => matches!(
+            response.status(),
+            StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+        ),
+        Err(ClientError::Request(_) | ClientError::Timeout(_)) => true,
+        Err(_) => false,
+    }
+}
+
+/// HTTP client which traces each call, propagates the trace context and the request
+/// deadline, and records `http.client.request.duration`.
+///
+/// Inside a handler behind [`DeadlineLayer`](crate::deadline::DeadlineLayer) the call
+/// gets the smaller of the client timeout and what is left of the deadline, and an
+/// already expired deadline fails without sending anything.
+///
+/// Idempotent requests to a destination with a [`RetryPolicy`] are retried and hedged
+/// within the same timeout and deadline, see [`TracedClient::with_retry_policy`].
+///
+/// With an [`EgressPolicy`], calls to destinations it denies fail with
+/// [`ClientError::EgressDenied`] before anything is sent, and with a [`CircuitBreaker`]
+/// calls to a destination whose breaker is open fail with [`ClientError::CircuitOpen`].
+#[derive(Debug, Clone)]
+pub struct TracedClient<C = HttpConnector> {
+    inner: Client<C, Body>,
+    timeout: Option<Duration>,
+    policies: Arc<HashMap<String, RetryPolicy>>,
+    budget: Arc<TpsBudget>,
+    egress: Option<EgressPolicy>,
+    breaker: Option<CircuitBreaker>,
+}
+
+impl TracedClient {
+    pub fn new() -> Self {
+        Self::with_connector(HttpConnector::new())
+    }
+
+    /// A client limited to what `policy` allows, whose connector also checks the
+    /// addresses names resolve to against the CIDR blocks of the policy.
+    pub fn restricted(policy: EgressPolicy) -> TracedClient<HttpConnector<EgressResolver>> {
+        let resolver = policy.resolver(GaiResolver::new());
+        TracedClient::with_connector(HttpConnector::new_with_resolver(resolver)).with_egress_policy(policy)
+    }
+}
+
+impl Default for TracedClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Connect + Clone + Send + Sync + 'static> TracedClient<C> {
+    /// A client over a custom connector, e.g. one adding TLS.
+    pub fn with_connector(connector: C) -> Self {
+        TracedClient {
+            inner: Client::builder(TokioExecutor::new()).build(connector),
+            timeout: Some(Duration::from_secs(30)),
+            policies: Arc::default(),
+            budget: Arc::new(TpsBudget::new(Duration::from_secs(10), 10, 0.2)),
+            egress: None,
+            breaker: None,
+        }
+    }
+
+    /// Checks every call against `policy`. Resolved addresses are only checked by a
+    /// connector resolving through [`EgressPolicy::resolver`].
+    pub fn with_egress_policy(mut self, policy: EgressPolicy) -> Self {
+        self.egress = Some(policy);
+        self
+    }
+
+    /// Fails fast calling destinations `breaker` found failing. A breaker can be shared
+    /// by several clients, which then see the same states.
+    pub fn with_circuit_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.breaker = Some(breaker);
+        self
+    }
+
+    /// Retries and hedges idempotent requests to `host` according to `policy`.
+    pub fn with_retry_policy(mut self, host: impl Into<String>, policy: RetryPolicy) -> Self {
+        Arc::make_mut(&mut self.policies).insert(host.into(), policy);
+        self
+    }
+
+    /// Limits retries and hedges, across all destinations, to `retry_percent` of the
+    /// requests sent in the last `ttl` (between 1 and 60 seconds) on top of
+    /// `min_per_sec`. The default allows 20% over 10 seconds plus 10 per second.
+    pub fn with_retry_budget(mut self, ttl: Duration, min_per_sec: u32, retry_percent: f32) -> Self {
+        self.budget = Arc::new(TpsBudget::new(ttl, min_per_sec, retry_percent));
+        self
+    }
+
+    /// The timeout of each call when no shorter deadline applies; `None` disables it.
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub async fn request(&self, mut req: Request<Body>) -> Result<Response<Body>, ClientError> {
+        let remaining = deadline::remaining();
+        if remaining.is_some_and(|remaining| remaining.is_zero()) {
+            return Err(ClientError::DeadlineExceeded);
+        }
+        let limited_by_deadline = match (remaining, self.timeout) {
+            (Some(remaining), Some(timeout)) => remaining <= timeout,
+            (remaining, _) => remaining.is_some(),
+        };
+        let budget = match (remaining, self.timeout) {
+            (Some(remaining), Some(timeout)) => Some(remaining.min(timeout)),
+            (remaining, timeout) => remaining.or(timeout),
+        };
+
+        let method = req.method().clone();
+        let host = req.uri().host().unwrap_or_default().to_owned();
+        let destination = req.uri().authority().map_or_else(|| host.clone(), ToString::to_string);
+        let span = info_span!(
+            "http.client.request",
+            otel.name = %method,
+            otel.kind = "client",
+            otel.status_code = Empty,
+            http.request.method = %method,
+            url.full = %req.uri(),
+            server.address = %host,
+            http.response.status_code = Empty,
+            http.request.resend_count = Empty,
+        );
+        let context = span.context();
+        global::get_text_map_propagator(|prop| prop.inject_context(&context, &mut HeaderInjector(req.headers_mut())));
+        if let Some(remaining) = remaining {
+            req.headers_mut()
+                .insert(X_REQUEST_DEADLINE, HeaderValue::from(remaining.as_millis() as u64));
+        }
+
+        let started = Instant::now();
+        let allowed = self.egress.as_ref().is_none_or(|egress| egress.check(req.uri(), &span));
+        let (permit, open) = match self.breaker.as_ref().filter(|_| allowed) {
+            Some(breaker) => {
+                let destination = circuit_breaker::destination(req.uri());
+                match breaker.acquire(&destination) {
+                    Ok(permit) => (Some(permit), None),
+                    Err(retry_after) => (None, Some(ClientError::CircuitOpen { destination, retry_after })),
+                }
+            }
+            None => (None, None),
+        };
+        let policy = self.policies.get(&host).filter(|_| is_idempotent(&method));
+        let call = async {
+            match policy {
+                Some(policy) => self.request_with_policy(req, policy, &host, &span).await,
+                None => send(self.inner.clone(), req, None).await,
+            }
+        };
+        let result = async {
+            if !allowed {
+                return Err(ClientError::EgressDenied(destination));
+            }
+            if let Some(open) = open {
+                return Err(open);
+            }
+            match budget {
+                Some(budget) => match tokio::time::timeout(budget, call).await {
+                    Ok(result) => result,
+                    Err(_) if limited_by_deadline => Err(ClientError::DeadlineExceeded),
+                    Err(_) => Err(ClientError::Timeout(budget)),
+                },
+                None => call.await,
+            }
+        }
+        .instrument(span.clone())
+        .await;
+        if let Some(permit) = permit {
+            permit.record(&result);
+        }
+
+        let mut labels = vec![
+            KeyValue::new("http.request.method", method.to_string()),
+            KeyValue::new("server.address", host),
+        ];
+        match &result {
+            Ok(response) => {
+                span.record("http.response.status_code", response.status().as_u16());
+                if response.status().is_server_error() {
+                    span.record("otel.status_code", "ERROR");
+                }
+                labels.push(KeyValue::new("http.response.status_code", response.status().as_u16() as i64));
+            }
+            Err(err) => {
+                span.record("otel.status_code", "ERROR");
+                let error_type = match err {
+                    ClientError::DeadlineExceeded => "deadline_exceeded",
+                    ClientError::Timeout(_) => "timeout",
+                    ClientError::Request(_) => "request",
+                    ClientError::Body(_) => "body",
+                    ClientError::EgressDenied(_) => "egress_denied",
+                    ClientError::CircuitOpen { .. } => "circuit_open",
+                };
+                labels.push(KeyValue::new("error.type", error_type));
+            }
+        }
+        CLIENT_DURATION.record(started.elapsed().as_secs_f64(), &labels);
+
+        result
+    }
+
+    /// Sends `req` until an attempt succeeds or is not retryable, the attempts are spent
+    /// or the budget runs out. Requests with a streaming body are sent once.
+    async fn request_with_policy(
+        &self,
+        req: Request<Body>,
+        policy: &RetryPolicy,
+        host: &str,
+        span: &tracing::Span,
+    ) -> Result<Response<Body>, ClientError> {
+        self.budget.deposit();
+        if req.body().size_hint().exact().is_none() {
+            return send(self.inner.clone(), req, policy.per_try_timeout).await;
+        }
+        let (parts, body) = req.into_parts();
+        let body = body.collect().await.map_err(ClientError::Body)?.to_bytes();
+        let labels = [KeyValue::new("server.address", host.to_owned())];
+
+        // Dropping the set cancels the attempts still pending.
+        let mut attempts = JoinSet::new();
+        let send_attempt = |attempts: &mut JoinSet<_>| {
+            let req = Request::from_parts(parts.clone(), Body::from(body.clone()));
+            attempts.spawn(send(self.inner.clone(), req, policy.per_try_timeout).instrument(span.clone()));
+        };
+        send_attempt(&mut attempts);
+        let mut sent = 1;
+        let mut hedging = policy.hedge_delay.is_some();
+        loop {
+            let hedge = async {
+                match policy.hedge_delay {
+                    Some(delay) if hedging && sent < policy.max_attempts => tokio::time::sleep(delay).await,
+                    _ => std::future::pending().await,
+                }
+            };
+            tokio::select! {
+                Some(joined) = attempts.join_next() => {
+                    let result = joined.unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic()));
+                    if !is_retryable(&result) {
+                        return result;
+                    }
+                    if !attempts.is_empty() {
+                        // A hedged attempt may still answer.
+                        continue;
+                    }
+                    if sent >= policy.max_attempts {
+                        return result;
+                    }
+                    if !self.budget.withdraw() {
+                        CLIENT_BUDGET_EXHAUSTED.add(1, &labels);
+                        return result;
+                    }
+                    CLIENT_RETRIES.add(1, &labels);
+                    send_attempt(&mut attempts);
+                    sent += 1;
+                    span.record("http.request.resend_count", sent - 1);
+                }
+                _ = hedge => {
+                    if self.budget.withdraw() {
+                        CLIENT_HEDGES.add(1, &labels);
+                        send_attempt(&mut attempts);
+                        sent += 1;
+                        span.record("http.request.resend_count", sent - 1);
+                    } else {
+                        CLIENT_BUDGET_EXHAUSTED.add(1, &labels);
+                        hedging = false;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// One attempt, bounded by `timeout`.
+async fn send<C>(client: Client<C, Body>, req: Request<Body>, timeout: Option<Duration>) -> Result<Response<Body>, ClientError>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    let call = client.request(req);
+    let result = match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, call)
+            .await
+            .map_err(|_| ClientError::Timeout(timeout))?,
+        None => call.await,
+    };
+    result.map(|response| response.map(Body::new)).map_err(ClientError::Request)
+}
@@ -0,0 +1,372 @@
+use super::ClientError;
+use crate::meter::GLOBAL_METER;
+use axum::body::Body;
+use axum::http::uri::Scheme;
+use axum::http::{Response, Uri};
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Counter, Meter, ObservableGauge};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The gauge of the state of each destination's breaker: 0 closed, 1 half-open, 2 open.
+pub const CIRCUIT_BREAKER_STATE: &str = "http.client.circuit_breaker.state";
+/// The counter of the state changes of the breakers, labelled with the new state.
+pub const CIRCUIT_BREAKER_TRANSITIONS: &str = "http.client.circuit_breaker.transitions";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Calls go through and their outcomes are counted.
+    Closed,
+    /// Calls fail with [`ClientError::CircuitOpen`] until the cool-down is over.
+    Open,
+    /// A few probe calls go through; the others fail as when open.
+    HalfOpen,
+}
+
+impl CircuitState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CircuitState::Closed => "closed",
+            CircuitState::Open => "open",
+            CircuitState::HalfOpen => "half_open",
+        }
+    }
+
+    fn gauge_value(&self) -> u64 {
+        match self {
+            CircuitState::Closed => 0,
+            CircuitState::HalfOpen => 1,
+            CircuitState::Open => 2,
+        }
+    }
+}
+
+/// When a [`CircuitBreaker`] opens and how it closes again.
+///
+/// The breaker of a destination opens after `consecutive_failures` failed calls in a
+/// row, or when at least `failure_rate` of the calls of the last `window` failed, once
+/// there were `min_calls` of them. After `cool_down` the next calls are probes, up to
+/// `probes` at a time: the breaker closes when `probes` of them succeeded and opens
+/// again as soon as one fails.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CircuitBreakerPolicy {
+    failure_rate: f64,
+    min_calls: usize,
+    consecutive_failures: u32,
+    window: Duration,
+    cool_down: Duration,
+    probes: u32,
+}
+
+impl Default for CircuitBreakerPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CircuitBreakerPolicy {
+    /// Opens after 5 failures in a row, or half of at least 10 calls within 30 seconds
+    /// failing, and probes with one call after 30 seconds.
+    pub fn new() -> Self {
+        CircuitBreakerPolicy {
+            failure_rate: 0.5,
+            min_calls: 10,
+            consecutive_failures: 5,
+            window: Duration::from_secs(30),
+            cool_down: Duration::from_secs(30),
+            probes: 1,
+        }
+    }
+
+    /// The share of failed calls, between 0 and 1, which opens the breaker.
+    pub fn with_failure_rate(mut self, failure_rate: f64) -> Self {
+        self.failure_rate = failure_rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// The calls needed within the window before the failure rate is considered.
+    pub fn with_min_calls(mut self, min_calls: usize) -> Self {
+        self.min_calls = min_calls.max(1);
+        self
+    }
+
+    /// The failures in a row which open the breaker; 0 only considers the failure rate.
+    pub fn with_consecutive_failures(mut self, consecutive_failures: u32) -> Self {
+        self.consecutive_failures = consecutive_failures;
+        self
+    }
+
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    pub fn with_cool_down(mut self, cool_down: Duration) -> Self {
+        self.cool_down = cool_down;
+        self
+    }
+
+    pub fn with_probes(mut self, probes: u32) -> Self {
+        self.probes = probes.max(1);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    Closed,
+    Open { until: Instant },
+    HalfOpen { probing: u32, succeeded: u32 },
+}
+
+impl State {
+    fn public(&self) -> CircuitState {
+        match self {
+            State::Closed => CircuitState::Closed,
+            State::Open { .. } => CircuitState::Open,
+            State::HalfOpen { .. } => CircuitState::HalfOpen,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Destination {
+    state: State,
+    /// When each call of the window ended and whether it failed, oldest first.
+    outcomes: VecDeque<(Instant, bool)>,
+    consecutive_failures: u32,
+}
+
+impl Destination {
+    fn new() -> Self {
+        Destination {
+            state: State::Closed,
+            outcomes: VecDeque::new(),
+            consecutive_failures: 0,
+        }
+    }
+}
+
+type Destinations = Arc<Mutex<HashMap<String, Destination>>>;
+
+#[derive(Debug)]
+struct Inner {
+    policy: CircuitBreakerPolicy,
+    destinations: Destinations,
+    transitions: Counter<u64>,
+    _gauge: ObservableGauge<u64>,
+}
+
+/// Stops calling a failing destination for a while, so that its callers fail fast with
+/// [`ClientError::CircuitOpen`] instead of waiting on timeouts, and the destination gets
+/// time to recover. See [`CircuitBreakerPolicy`] for when it opens and closes.
+///
+/// Destinations are `host:port`, each with its own state. Clones share the states, as do
+/// the clones of a [`TracedClient`](super::TracedClient) using the breaker.
+///
+/// Connection errors, timeouts and `5xx` responses are failures; calls failing because
+/// of the caller's deadline or the egress policy are not counted. The state of each
+/// destination is reported by the [`CIRCUIT_BREAKER_STATE`] gauge and its changes by the
+/// [`CIRCUIT_BREAKER_TRANSITIONS`] counter, both labelled with `server.address`.
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    inner: Arc<Inner>,
+}
+
+impl CircuitBreaker {
+    pub fn new(policy: CircuitBreakerPolicy) -> Self {
+        Self::with_meter(policy, &GLOBAL_METER)
+    }
+
+    pub fn with_meter(policy: CircuitBreakerPolicy, meter: &Meter) -> Self {
+        let destinations = Destinations::default();
+        let observed = destinations.clone();
+        let gauge = meter
+            .u64_observable_gauge(CIRCUIT_BREAKER_STATE)
+            .with_description("State of the circuit breaker of each destination: 0 closed, 1 half-open, 2 open")
+            .with_callback(move |observer| {
+                for (destination, entry) in observed.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+                    observer.observe(
+                        entry.state.public().gauge_value(),
+                        &[KeyValue::new("server.address", destination.clone())],
+                    );
+                }
+            })
+            .build();
+        let transitions = meter
+            .u64_counter(CIRCUIT_BREAKER_TRANSITIONS)
+            .with_description("State changes of the circuit breakers of outbound HTTP destinations")
+            .build();
+        CircuitBreaker {
+            inner: Arc::new(Inner {
+                policy,
+                destinations,
+                transitions,
+                _gauge: gauge,
+            }),
+        }
+    }
+
+    /// The state of the breaker of `destination`, a `host:port`. An open breaker stays
+    /// open until a call after the cool-down probes the destination.
+    pub fn state(&self, destination: &str) -> CircuitState {
+        self.lock()
+            .get(destination)
+            .map_or(CircuitState::Closed, |entry| entry.state.public())
+    }
+
+    /// Lets a call to `destination` through, or the time left before the breaker probes.
+    pub(crate) fn acquire(&self, destination: &str) -> Result<Permit, Duration> {
+        let now = Instant::now();
+        let mut destinations = self.lock();
+        let entry = destinations
+            .entry(destination.to_owned())
+            .or_insert_with(Destination::new);
+        let probe = match entry.state {
+            State::Closed => false,
+            State::Open { until } if now < until => return Err(until - now),
+            State::Open { .. } => {
+                self.transition(
+                    destination,
+                    entry,
+                    State::HalfOpen {
+                        probing: 1,
+                        succeeded: 0,
+                    },
+                );
+                true
+            }
+            State::HalfOpen { probing, succeeded } if probing < self.inner.policy.probes => {
+                entry.state = State::HalfOpen {
+                    probing: probing + 1,
+                    succeeded,
+                };
+                true
+            }
+            State::HalfOpen { .. } => return Err(Duration::ZERO),
+        };
+        Ok(Permit {
+            breaker: self.clone(),
+            destination: destination.to_owned(),
+            probe,
+            failed: None,
+        })
+    }
+
+    fn record(&self, destination: &str, probe: bool, failed: Option<bool>) {
+        let policy = &self.inner.policy;
+        let now = Instant::now();
+        let mut destinations = self.lock();
+        let Some(entry) = destinations.get_mut(destination) else {
+            return;
+        };
+        match (entry.state, probe, failed) {
+            (State::Closed, false, Some(failed)) => {
+                entry.outcomes.push_back((now, failed));
+                while entry
+                    .outcomes
+                    .front()
+                    .is_some_and(|(ended, _)| now.duration_since(*ended) > policy.window)
+                {
+                    entry.outcomes.pop_front();
+                }
+                entry.consecutive_failures = if failed { entry.consecutive_failures + 1 } else { 0 };
+                let failures = entry.outcomes.iter().filter(|(_, failed)| *failed).count();
+                let calls = entry.outcomes.len();
+                if (policy.consecutive_failures > 0 && entry.consecutive_failures >= policy.consecutive_failures)
+                    || (calls >= policy.min_calls && failures as f64 >= policy.failure_rate * calls as f64)
+                {
+                    self.transition(
+                        destination,
+                        entry,
+                        State::Open {
+                            until: now + policy.cool_down,
+                        },
+                    );
+                }
+            }
+            (State::HalfOpen { probing, succeeded }, true, failed) => {
+                let probing = probing.saturating_sub(1);
+                match failed {
+                    Some(true) => self.transition(
+                        destination,
+                        entry,
+                        State::Open {
+                            until: now + policy.cool_down,
+                        },
+                    ),
+                    Some(false) if succeeded + 1 >= policy.probes => self.transition(destination, entry, State::Closed),
+                    Some(false) => {
+                        entry.state = State::HalfOpen {
+                            probing,
+                            succeeded: succeeded + 1,
+                        }
+                    }
+                    // The probe was not counted, another call may probe instead.
+                    None => entry.state = State::HalfOpen { probing, succeeded },
+                }
+            }
+            // Calls let through before the state changed.
+            _ => {}
+        }
+    }
+
+    fn transition(&self, destination: &str, entry: &mut Destination, state: State) {
+        entry.state = state;
+        entry.outcomes.clear();
+        entry.consecutive_failures = 0;
+        let state = state.public();
+        if state == CircuitState::Open {
+            warn!("circuit breaker of {} opened", destination);
+        } else {
+            info!("circuit breaker of {} is {}", destination, state.as_str());
+        }
+        self.inner.transitions.add(
+            1,
+            &[
+                KeyValue::new("server.address", destination.to_owned()),
+                KeyValue::new("state", state.as_str()),
+            ],
+        );
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Destination>> {
+        self.inner.destinations.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A call let through by the breaker, whose outcome is recorded when it is dropped.
+/// Dropped without one, e.g. when the call is cancelled, a probe leaves its place to
+/// another call.
+#[derive(Debug)]
+pub(crate) struct Permit {
+    breaker: CircuitBreaker,
+    destination: String,
+    probe: bool,
+    failed: Option<bool>,
+}
+
+impl Permit {
+    pub(crate) fn record(mut self, result: &Result<Response<Body>, ClientError>) {
+        self.failed = match result {
+            Ok(response) => Some(response.status().is_server_error()),
+            Err(ClientError::Request(_) | ClientError::Timeout(_)) => Some(true),
+            Err(_) => None,
+        };
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.breaker.record(&self.destination, self.probe, self.failed);
+    }
+}
+
+/// The `host:port` of `uri`, with the default port of its scheme.
+pub(crate) fn destination(uri: &Uri) -> String {
+    let port = uri
+        .port_u16()
+        .unwrap_or(if uri.scheme() == Some(&Scheme::HTTPS) { 443 } else { 80 });
+    format!("{}:{}", uri.host().unwrap_or_default(), port)
+}
@@ -0,0 +1,488 @@
+use super::{DynamicConfig, TelemetryConfig, dump_redacted};
+use crate::deadline::DeadlineLayer;
+use crate::middleware::cors::{CorsConfig, CorsConfigError};
+use crate::serve::Listener;
+use axum::http::{HeaderName, Method};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Prefix of the environment variables overriding [`AppConfig`] fields.
+pub const ENV_PREFIX: &str = "STARLIGHT__";
+
+/// Everything a service built on this crate is configured with, loaded from a file by
+/// [`AppConfig::load`].
+///
+/// Each setting comes from, in increasing precedence:
+///
+/// 1. the defaults below,
+/// 2. the config file, YAML (`.yaml` or `.yml`), JSON (`.json`) or TOML (any other
+///    extension),
+/// 3. `STARLIGHT__<SECTION>__<FIELD>` environment variables, e.g.
+///    `STARLIGHT__SERVER__BIND=0.0.0.0:9090`. Lists are comma separated.
+///
+/// The file may reference environment variables as `${VAR}`, or `${VAR:-default}` when
+/// the variable is optional; they are substituted as-is before the file is parsed.
+///
+/// ```toml
+/// [telemetry]
+/// service_name = "orders"
+/// service_version = "1.4.0"
+/// otlp_endpoint = "http://${COLLECTOR_HOST:-localhost}:4317"
+///
+/// [server]
+/// bind = "0.0.0.0:8080"
+///
+/// [cors]
+/// allowed_origins = ["https://*.example.com"]
+/// ```
+///
+/// The OTLP exporters still read their own `OTEL_EXPORTER_OTLP_*` variables; the
+/// corresponding `telemetry` fields are only validated.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppConfig {
+    pub telemetry: TelemetryConfig,
+    pub server: ServerConfig,
+    pub cors: CorsSettings,
+    pub rate_limit: RateLimitConfig,
+    pub timeouts: TimeoutConfig,
+    pub maintenance: MaintenanceSettings,
+}
+
+/// Where to accept connections, see [`ServerConfig::listener`].
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: SocketAddr,
+    /// Listens on this unix socket instead of `bind`.
+    pub unix_socket: Option<PathBuf>,
+    /// Permissions of the unix socket file, e.g. `0o660`.
+    pub socket_mode: Option<u32>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind: SocketAddr::from(([0, 0, 0, 0], 8080)),
+            unix_socket: None,
+            socket_mode: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn listener(&self) -> Listener {
+        #[cfg(unix)]
+        if let Some(path) = &self.unix_socket {
+            let listener = Listener::unix(path);
+            return match self.socket_mode {
+                Some(mode) => listener.with_mode(mode),
+                None => listener,
+            };
+        }
+        Listener::tcp(self.bind)
+    }
+}
+
+/// The settings of [`CorsConfig`]. `"*"` allows any origin, method or header, or exposes
+/// any header; unset methods keep the [`CorsConfig`] defaults and unset headers allow any.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsSettings {
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Option<Vec<String>>,
+    pub allowed_headers: Option<Vec<String>>,
+    pub expose_headers: Vec<String>,
+    pub allow_credentials: bool,
+    pub max_age_secs: Option<u64>,
+}
+
+impl CorsSettings {
+    pub fn cors_config(&self) -> Result<CorsConfig, CorsConfigError> {
+        let is_any = |items: &[String]| items.iter().any(|item| item == "*");
+        let headers = |names: &[String]| {
+            names
+                .iter()
+                .map(|name| HeaderName::from_bytes(name.as_bytes()).map_err(|_| CorsConfigError::new(format!("invalid header {:?}", name))))
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        let mut config = CorsConfig::new();
+        if is_any(&self.allowed_origins) {
+            config = config.allow_any_origin();
+        } else {
+            for origin in &self.allowed_origins {
+                config = config.allow_origin(origin);
+            }
+        }
+        match &self.allowed_methods {
+            Some(methods) if is_any(methods) => config = config.allow_any_method(),
+            Some(methods) => {
+                let methods = methods
+                    .iter()
+                    .map(|method| Method::from_bytes(method.as_bytes()).map_err(|_| CorsConfigError::new(format!("invalid method {:?}", method))))
+                    .collect::<Result<Vec<_>, _>>()?;
+                config = config.allow_methods(methods);
+            }
+            None => {}
+        }
+        if let Some(allowed) = self.allowed_headers.as_deref().filter(|allowed| !is_any(allowed)) {
+            config = config.allow_headers(headers(allowed)?);
+        }
+        config = if is_any(&self.expose_headers) {
+            config.expose_any_header()
+        } else {
+            config.expose_headers(headers(&self.expose_headers)?)
+        };
+        config = config.allow_credentials(self.allow_credentials);
+        if let Some(max_age) = self.max_age_secs {
+            config = config.max_age(Duration::from_secs(max_age));
+        }
+        Ok(config)
+    }
+}
+
+/// Limits of the [`RateLimitLayer`](crate::middleware::rate_limit::RateLimitLayer);
+/// `None` disables limiting.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub requests_per_second: Option<u32>,
+    /// Requests allowed above the rate in a burst, `requests_per_second` if unset.
+    pub burst: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeoutConfig {
+    /// Timeout of requests without an `x-request-deadline`, see [`DeadlineLayer`].
+    pub request_secs: u64,
+    /// Timeout of outgoing calls, for
+    /// [`TracedClient::with_timeout`](crate::client::TracedClient::with_timeout).
+    pub client_secs: u64,
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        TimeoutConfig {
+            request_secs: 30,
+            client_secs: 30,
+        }
+    }
+}
+
+impl TimeoutConfig {
+    pub fn request(&self) -> Duration {
+        Duration::from_secs(self.request_secs)
+    }
+
+    pub fn client(&self) -> Duration {
+        Duration::from_secs(self.client_secs)
+    }
+
+    pub fn deadline_layer(&self) -> DeadlineLayer {
+        DeadlineLayer::new(self.request())
+    }
+}
+
+/// Paths still served in maintenance mode, see
+/// [`MaintenanceLayer::with_dynamic_allowed_prefixes`](crate::middleware::maintenance::MaintenanceLayer::with_dynamic_allowed_prefixes).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MaintenanceSettings {
+    pub allowed_prefixes: Vec<String>,
+}
+
+impl Default for MaintenanceSettings {
+    fn default() -> Self {
+        MaintenanceSettings {
+            allowed_prefixes: vec!["/health".to_owned(), "/admin".to_owned()],
+        }
+    }
+}
+
+/// Why [`AppConfig::load`] failed. Errors name the file and the offending key or line.
+#[derive(Debug)]
+pub enum AppConfigError {
+    Read { path: PathBuf, source: io::Error },
+    Syntax { path: PathBuf, line: usize, message: String },
+    /// `${variable}` is used on `line` without a default and is not set.
+    MissingVariable { path: PathBuf, line: usize, variable: String },
+    /// A field has the wrong type or value. `variable` names the environment variable
+    /// it came from, if any.
+    Invalid {
+        path: PathBuf,
+        key: String,
+        variable: Option<String>,
+        message: String,
+    },
+}
+
+impl fmt::Display for AppConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppConfigError::Read { path, source } => write!(f, "{}: cannot read config: {}", path.display(), source),
+            AppConfigError::Syntax { path, line, message } => write!(f, "{}:{}: {}", path.display(), line, message),
+            AppConfigError::MissingVariable { path, line, variable } => {
+                write!(f, "{}:{}: environment variable {} is not set", path.display(), line, variable)
+            }
+            AppConfigError::Invalid {
+                path,
+                key,
+                variable: Some(variable),
+                message,
+            } => write!(f, "{}: {} (from {}): {}", path.display(), key, variable, message),
+            AppConfigError::Invalid { path, key, message, .. } => write!(f, "{}: {}: {}", path.display(), key, message),
+        }
+    }
+}
+
+impl std::error::Error for AppConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AppConfigError::Read { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl AppConfig {
+    /// Loads `path` with the process environment, see [`AppConfig`] for the precedence.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, AppConfigError> {
+        Self::load_with_env(path, std::env::vars())
+    }
+
+    /// Like [`AppConfig::load`] with the given environment instead of the process's.
+    pub fn load_with_env<I, K, V>(path: impl AsRef<Path>, env: I) -> Result<Self, AppConfigError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let path = path.as_ref();
+        let env: HashMap<String, String> = env.into_iter().map(|(key, value)| (key.into(), value.into())).collect();
+        let text = std::fs::read_to_string(path).map_err(|source| AppConfigError::Read {
+            path: path.to_owned(),
+            source,
+        })?;
+        let text = interpolate(&text, &env).map_err(|(line, variable)| AppConfigError::MissingVariable {
+            path: path.to_owned(),
+            line,
+            variable,
+        })?;
+
+        let mut value = parse(path, &text)?;
+
+        let template = serde_json::to_value(AppConfig::default()).expect("the config serializes");
+        let mut overridden = HashMap::new();
+        for (variable, raw) in &env {
+            let Some(key) = variable.strip_prefix(ENV_PREFIX) else { continue };
+            let key: Vec<String> = key.split("__").map(str::to_ascii_lowercase).collect();
+            let invalid = |message: String| AppConfigError::Invalid {
+                path: path.to_owned(),
+                key: key.join("."),
+                variable: Some(variable.clone()),
+                message,
+            };
+            // Optional fields default to null, the file's value may still tell their type.
+            let lookup = |root| key.iter().try_fold(root, |value: &Value, field| value.get(field));
+            let expected = lookup(&template).filter(|value| !value.is_null()).or_else(|| lookup(&value));
+            let parsed = coerce(raw, expected).map_err(invalid)?;
+            set(&mut value, &key, parsed).map_err(invalid)?;
+            overridden.insert(key.join("."), variable.clone());
+        }
+
+        let config: AppConfig = serde_path_to_error::deserialize(value).map_err(|err| {
+            let key = err.path().to_string();
+            AppConfigError::Invalid {
+                path: path.to_owned(),
+                variable: overridden.get(&key).cloned(),
+                message: err.into_inner().to_string(),
+                key,
+            }
+        })?;
+        config.cors.cors_config().map_err(|err| AppConfigError::Invalid {
+            path: path.to_owned(),
+            key: "cors".to_owned(),
+            variable: None,
+            message: err.to_string(),
+        })?;
+        Ok(config)
+    }
+}
+
+impl AppConfig {
+    /// Loads `path` like [`AppConfig::load`], then reloads it on SIGHUP or when the file
+    /// changes, checked every 5 seconds. Needs a tokio runtime.
+    ///
+    /// A reloaded file is validated like the first one and replaces the whole config at
+    /// once; one that fails is logged and the current config stays. Sections are followed
+    /// with [`DynamicConfig::map`], e.g. `config.map(|config| config.rate_limit.clone())`
+    /// for [`RateLimitLayer`](crate::middleware::rate_limit::RateLimitLayer). Settings
+    /// used only at startup, such as `server` and `telemetry`, keep their first values
+    /// wherever they were read. Reloading stops once every handle is dropped.
+    pub fn watch(path: impl AsRef<Path>) -> Result<DynamicConfig<AppConfig>, AppConfigError> {
+        Self::watch_every(path, Duration::from_secs(5))
+    }
+
+    /// Like [`AppConfig::watch`], checking the file for changes every `interval`.
+    pub fn watch_every(path: impl AsRef<Path>, interval: Duration) -> Result<DynamicConfig<AppConfig>, AppConfigError> {
+        let path = path.as_ref().to_owned();
+        let config = DynamicConfig::new(Self::load(&path)?);
+        let handle = config.downgrade();
+        tokio::spawn(async move {
+            #[cfg(unix)]
+            let mut hangup =
+                tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()).map_err(|err| {
+                    warn!("cannot reload the configuration on SIGHUP: {}", err);
+                });
+            let mut modified = modified_at(&path);
+            loop {
+                #[cfg(unix)]
+                let signalled = tokio::select! {
+                    _ = tokio::time::sleep(interval) => false,
+                    Some(()) = async {
+                        match &mut hangup {
+                            Ok(signal) => signal.recv().await,
+                            Err(()) => std::future::pending().await,
+                        }
+                    } => true,
+                };
+                #[cfg(not(unix))]
+                let signalled = {
+                    tokio::time::sleep(interval).await;
+                    false
+                };
+
+                let Some(config) = handle.upgrade() else { break };
+                let changed = modified_at(&path);
+                if signalled || changed != modified {
+                    modified = changed;
+                    reload(&path, &config);
+                }
+            }
+        });
+        Ok(config)
+    }
+
+    /// The effective config with telemetry secrets masked, see [`dump_redacted`].
+    pub fn dump_redacted(&self) -> Value {
+        let mut dump = serde_json::to_value(self).expect("the config serializes");
+        dump["telemetry"] = dump_redacted(&self.telemetry);
+        dump
+    }
+}
+
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+fn reload(path: &Path, config: &DynamicConfig<AppConfig>) {
+    match AppConfig::load(path) {
+        Ok(reloaded) if reloaded == *config.get() => debug!(path = %path.display(), "configuration unchanged"),
+        Ok(reloaded) => {
+            config.set(reloaded);
+            info!(path = %path.display(), "configuration reloaded");
+        }
+        Err(err) => error!(path = %path.display(), "keeping the current configuration: {}", err),
+    }
+}
+
+/// Parses `text` by the extension of `path`.
+fn parse(path: &Path, text: &str) -> Result<Value, AppConfigError> {
+    let syntax = |line, message| AppConfigError::Syntax {
+        path: path.to_owned(),
+        line,
+        message,
+    };
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("json") => serde_json::from_str(text).map_err(|err| syntax(err.line(), err.to_string())),
+        Some("yaml" | "yml") => serde_yaml::from_str(text)
+            .map_err(|err| syntax(err.location().map_or(0, |location| location.line()), err.to_string())),
+        _ => toml::from_str(text).map_err(|err| {
+            let line = err.span().map_or(0, |span| text[..span.start].matches('\n').count() + 1);
+            syntax(line, err.message().to_owned())
+        }),
+    }
+}
+
+/// Substitutes `${VAR}` and `${VAR:-default}`, failing with the line and name of the first
+/// unset variable without a default.
+fn interpolate(text: &str, env: &HashMap<String, String>) -> Result<String, (usize, String)> {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        let Some(end) = rest[start..].find('}') else { break };
+        result.push_str(&rest[..start]);
+        let reference = &rest[start + 2..start + end];
+        let (variable, default) = match reference.split_once(":-") {
+            Some((variable, default)) => (variable, Some(default)),
+            None => (reference, None),
+        };
+        match env.get(variable).map(String::as_str).or(default) {
+            Some(value) => result.push_str(value),
+            None => {
+                let line = text[..text.len() - rest.len() + start].matches('\n').count() + 1;
+                return Err((line, variable.to_owned()));
+            }
+        }
+        rest = &rest[start + end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Parses an environment variable as the type of the default value it replaces.
+fn coerce(raw: &str, expected: Option<&Value>) -> Result<Value, String> {
+    let list = |raw: &str| {
+        Value::Array(
+            raw.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(Value::from)
+                .collect(),
+        )
+    };
+    match expected {
+        Some(Value::String(_)) => Ok(Value::from(raw)),
+        Some(Value::Bool(_)) => match raw.trim() {
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            _ => Err(format!("expected true or false, got {:?}", raw)),
+        },
+        Some(Value::Number(_)) => serde_json::from_str::<serde_json::Number>(raw.trim())
+            .map(Value::Number)
+            .map_err(|_| format!("expected a number, got {:?}", raw)),
+        Some(Value::Array(_)) => Ok(list(raw)),
+        Some(Value::Object(_)) => Err("is a section, set its fields instead".to_owned()),
+        // Optional or unknown: lists, numbers and booleans as written, anything else as a string.
+        Some(Value::Null) | None if raw.contains(',') => Ok(list(raw)),
+        Some(Value::Null) | None => Ok(serde_json::from_str::<Value>(raw.trim())
+            .ok()
+            .filter(|value| value.is_number() || value.is_boolean())
+            .unwrap_or_else(|| Value::from(raw))),
+    }
+}
+
+fn set(root: &mut Value, key: &[String], value: Value) -> Result<(), String> {
+    let (last, sections) = key.split_last().ok_or("empty key")?;
+    let mut table = root;
+    for section in sections {
+        table = table
+            .as_object_mut()
+            .ok_or_else(|| format!("{} is not a section", section))?
+            .entry(section.clone())
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+    table
+        .as_object_mut()
+        .ok_or_else(|| format!("{} is not a section", key.join(".")))?
+        .insert(last.clone(), value);
+    Ok(())
+}
@@ -0,0 +1,111 @@
+use std::fmt;
+use std::sync::{Arc, Mutex, Weak};
+use tokio::sync::watch;
+
+type Derived<T> = Box<dyn Fn(&T) + Send + Sync>;
+
+struct Shared<T> {
+    value: watch::Sender<Arc<T>>,
+    derived: Mutex<Vec<Derived<T>>>,
+}
+
+/// A setting which can change while the service runs, such as the rate limit of an
+/// [`AppConfig`](super::AppConfig) reloaded by [`AppConfig::watch`](super::AppConfig::watch).
+/// Clones share the value, and readers see each new value as a whole.
+///
+/// Middleware taking `impl Into<DynamicConfig<T>>` also accepts a plain `T` that never
+/// changes.
+pub struct DynamicConfig<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Clone for DynamicConfig<T> {
+    fn clone(&self) -> Self {
+        DynamicConfig {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for DynamicConfig<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DynamicConfig")
+            .field(&**self.shared.value.borrow())
+            .finish()
+    }
+}
+
+impl<T: Default + Send + Sync + 'static> Default for DynamicConfig<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: Send + Sync + 'static> From<T> for DynamicConfig<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: Send + Sync + 'static> DynamicConfig<T> {
+    pub fn new(value: T) -> Self {
+        DynamicConfig {
+            shared: Arc::new(Shared {
+                value: watch::Sender::new(Arc::new(value)),
+                derived: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// The current value. Later updates do not change it.
+    pub fn get(&self) -> Arc<T> {
+        self.shared.value.borrow().clone()
+    }
+
+    /// Publishes `value` to every clone and to the configs [`map`](Self::map)ped from it.
+    pub fn set(&self, value: T) {
+        let value = Arc::new(value);
+        self.shared.value.send_replace(value.clone());
+        for derive in self.shared.derived.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+            derive(&value);
+        }
+    }
+
+    /// Notified on every update.
+    pub fn subscribe(&self) -> watch::Receiver<Arc<T>> {
+        self.shared.value.subscribe()
+    }
+
+    /// A config following a part of this one, such as a section of the file, updated with it.
+    pub fn map<U, F>(&self, f: F) -> DynamicConfig<U>
+    where
+        U: Send + Sync + 'static,
+        F: Fn(&T) -> U + Send + Sync + 'static,
+    {
+        let mapped = DynamicConfig::new(f(&self.get()));
+        let target = mapped.clone();
+        self.shared
+            .derived
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(Box::new(move |value| target.set(f(value))));
+        mapped
+    }
+
+    /// A handle which does not keep the config alive, for background reloading.
+    pub(crate) fn downgrade(&self) -> WeakDynamicConfig<T> {
+        WeakDynamicConfig {
+            shared: Arc::downgrade(&self.shared),
+        }
+    }
+}
+
+pub(crate) struct WeakDynamicConfig<T> {
+    shared: Weak<Shared<T>>,
+}
+
+impl<T> WeakDynamicConfig<T> {
+    pub(crate) fn upgrade(&self) -> Option<DynamicConfig<T>> {
+        self.shared.upgrade().map(|shared| DynamicConfig { shared })
+    }
+}
@@ -0,0 +1,201 @@
+//! Flushing telemetry when the process panics or exits, when buffered spans and logs
+//! matter most.
+//!
+//! Install a [`PanicHook`] once telemetry is configured, e.g. after
+//! [`config_oltp`](crate::oltp::config_oltp). On a panic it logs the payload, location
+//! and backtrace through `tracing` under the `starlight::panic` target, flushes the
+//! providers within the flush timeout, and then runs the hook it replaced, so the
+//! unwind or abort goes on as before. Panics which are caught on their way up, by the
+//! [`ErrorMapperLayer`](crate::error::ErrorMapperLayer), a [`HookLayer`] or a
+//! [`ServiceManager`](starlight_tokio::ServiceManager), are logged but not flushed for,
+//! since the process goes on. Exit through [`exit`] instead of `std::process::exit` to
+//! flush first as well.
+//!
+//! [`HookLayer`]: crate::middleware::hooks::HookLayer
+
+use crate::logger::try_get_logger_provider;
+use crate::meter::try_get_meter_provider;
+use crate::task::panic_message;
+use crate::tracer::try_get_tracer_provider;
+use opentelemetry_sdk::logs::SdkLoggerProvider;
+use opentelemetry_sdk::metrics::SdkMeterProvider;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use std::backtrace::Backtrace;
+use std::cell::Cell;
+use std::panic::{PanicHookInfo, UnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Once, PoisonError, RwLock, mpsc};
+use std::time::Duration;
+
+static INSTALLED: RwLock<Option<PanicHook>> = RwLock::new(None);
+static SET_HOOK: Once = Once::new();
+/// Set while a panic or [`exit`] is flushing, so a panic during the flush does not flush
+/// again.
+static FLUSHING: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// How many [`catch_unwind`] calls are running on this thread.
+    static CATCHING: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Same as [`std::panic::catch_unwind`], marking the panics it catches as not ending the
+/// process, so the [`PanicHook`] does not flush for them.
+pub(crate) fn catch_unwind<R>(f: impl FnOnce() -> R + UnwindSafe) -> std::thread::Result<R> {
+    struct Guard;
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            CATCHING.set(CATCHING.get() - 1);
+        }
+    }
+    CATCHING.set(CATCHING.get() + 1);
+    let _guard = Guard;
+    std::panic::catch_unwind(f)
+}
+
+/// Whether a panic on this thread right now is caught before it can end the process.
+fn panic_is_caught() -> bool {
+    !cfg!(panic = "abort") && (CATCHING.get() > 0 || starlight_tokio::panic_is_caught())
+}
+
+/// The providers flushed on panic and on [`exit`]. Those not given are the ones set up
+/// by [`config_oltp`](crate::oltp::config_oltp), if any.
+#[derive(Debug, Clone)]
+pub struct PanicHook {
+    tracer_provider: Option<SdkTracerProvider>,
+    meter_provider: Option<SdkMeterProvider>,
+    logger_provider: Option<SdkLoggerProvider>,
+    flush_timeout: Duration,
+}
+
+impl Default for PanicHook {
+    fn default() -> Self {
+        PanicHook {
+            tracer_provider: None,
+            meter_provider: None,
+            logger_provider: None,
+            flush_timeout: Duration::from_secs(3),
+        }
+    }
+}
+
+impl PanicHook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_tracer_provider(mut self, provider: SdkTracerProvider) -> Self {
+        self.tracer_provider = Some(provider);
+        self
+    }
+
+    pub fn with_meter_provider(mut self, provider: SdkMeterProvider) -> Self {
+        self.meter_provider = Some(provider);
+        self
+    }
+
+    pub fn with_logger_provider(mut self, provider: SdkLoggerProvider) -> Self {
+        self.logger_provider = Some(provider);
+        self
+    }
+
+    /// How long a panic or [`exit`] waits for the exporters, 3 seconds by default.
+    pub fn with_flush_timeout(mut self, timeout: Duration) -> Self {
+        self.flush_timeout = timeout;
+        self
+    }
+
+    /// Sets the process panic hook, replacing the providers of a hook installed before.
+    pub fn install(self) {
+        *INSTALLED.write().unwrap_or_else(PoisonError::into_inner) = Some(self);
+        SET_HOOK.call_once(|| {
+            let previous = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                on_panic(info);
+                previous(info);
+            }));
+        });
+    }
+
+    fn flush(&self) {
+        if let Some(provider) = self.tracer_provider.as_ref().or(try_get_tracer_provider()) {
+            let _ = provider.force_flush();
+        }
+        if let Some(provider) = self.meter_provider.as_ref().or(try_get_meter_provider()) {
+            let _ = provider.force_flush();
+        }
+        if let Some(provider) = self.logger_provider.as_ref().or(try_get_logger_provider()) {
+            let _ = provider.force_flush();
+        }
+    }
+}
+
+/// Flushes the providers of the installed hook, giving up after its flush timeout.
+/// Returns at once when a flush is already running.
+fn flush_installed() {
+    if FLUSHING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let hook = INSTALLED
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+        .unwrap_or_default();
+    let timeout = hook.flush_timeout;
+    // On another thread, so an exporter that hangs cannot hold up the panic for longer
+    // than the timeout.
+    let (done, flushed) = mpsc::channel();
+    let flushing = std::thread::Builder::new().name("starlight-flush".to_owned()).spawn({
+        let hook = hook.clone();
+        move || {
+            hook.flush();
+            let _ = done.send(());
+        }
+    });
+    match flushing {
+        Ok(_) => {
+            if flushed.recv_timeout(timeout).is_err() {
+                eprintln!("telemetry was not flushed within {:?}", timeout);
+            }
+        }
+        Err(_) => hook.flush(),
+    }
+    FLUSHING.store(false, Ordering::SeqCst);
+}
+
+fn on_panic(info: &PanicHookInfo<'_>) {
+    if FLUSHING.load(Ordering::SeqCst) {
+        return;
+    }
+    let panic = panic_message(info.payload());
+    let location = info.location().map(ToString::to_string).unwrap_or_default();
+    error!(
+        target: "starlight::panic",
+        panic,
+        location = %location,
+        backtrace = %Backtrace::force_capture(),
+        "panicked at {}: {}",
+        location,
+        panic
+    );
+    if !panic_is_caught() {
+        flush_installed();
+    }
+}
+
+/// Flushes telemetry like a panic does, then exits the process with `code`.
+pub fn exit(code: i32) -> ! {
+    flush_installed();
+    std::process::exit(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_panics_inside_catch_unwind_are_caught() {
+        assert!(!panic_is_caught());
+        assert_eq!(catch_unwind(panic_is_caught).ok(), Some(true));
+        assert!(!panic_is_caught());
+    }
+}
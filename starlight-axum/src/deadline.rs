@@ -0,0 +1,192 @@
+use crate::config::{DynamicConfig, TimeoutConfig};
+use axum::extract::{FromRequestParts, Request};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use tokio::time::Instant;
+use tower::{Layer, Service};
+
+pub const X_REQUEST_DEADLINE: &str = "x-request-deadline";
+
+tokio::task_local! {
+    static CURRENT: Deadline;
+}
+
+/// The point in time by which the caller needs an answer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deadline(Instant);
+
+impl Deadline {
+    pub fn after(budget: Duration) -> Self {
+        Deadline(Instant::now() + budget)
+    }
+
+    pub fn instant(&self) -> Instant {
+        self.0
+    }
+
+    pub fn remaining(&self) -> Duration {
+        self.0.saturating_duration_since(Instant::now())
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.remaining().is_zero()
+    }
+
+    /// Parses `x-request-deadline`: milliseconds remaining or an RFC 3339 timestamp.
+    /// The budget is capped at `max` so a skewed caller clock cannot extend it.
+    pub fn from_headers(headers: &HeaderMap, max: Duration) -> Option<Self> {
+        let value = headers.get(X_REQUEST_DEADLINE)?.to_str().ok()?.trim();
+        let budget = match value.parse::<u64>() {
+            Ok(millis) => Duration::from_millis(millis),
+            Err(_) => {
+                let at = OffsetDateTime::parse(value, &Rfc3339).ok()?;
+                (at - OffsetDateTime::now_utc()).try_into().unwrap_or(Duration::ZERO)
+            }
+        };
+        Some(Deadline::after(budget.min(max)))
+    }
+
+    /// Runs `future` with this deadline visible to [`current`] and [`remaining`].
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        CURRENT.scope(self, future).await
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Deadline {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<Deadline>().copied().ok_or((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Deadline is missing, is DeadlineLayer installed?",
+        ))
+    }
+}
+
+/// The deadline of the request being handled, if any.
+pub fn current() -> Option<Deadline> {
+    CURRENT.try_with(|deadline| *deadline).ok()
+}
+
+/// The budget left for the request being handled, if it has a deadline.
+pub fn remaining() -> Option<Duration> {
+    current().map(|deadline| deadline.remaining())
+}
+
+/// Lets a route behind [`DeadlineLayer`] run past the deadline, see
+/// [`StreamingLayer`](crate::middleware::streaming::StreamingLayer).
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DeadlineExemption(Arc<AtomicBool>);
+
+impl DeadlineExemption {
+    pub(crate) fn exempt(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    fn is_exempt(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Request timeout driven by the caller's `x-request-deadline`.
+///
+/// Requests without the header get `default_timeout`. The deadline is stored as a
+/// [`Deadline`] extension and made current for the handler, so
+/// [`TracedClient`](crate::client::TracedClient) calls use what is left of it. Requests
+/// that arrive expired, or run past the deadline, are answered with 504, except on
+/// routes behind a [`StreamingLayer`](crate::middleware::streaming::StreamingLayer).
+#[derive(Debug, Clone)]
+pub struct DeadlineLayer {
+    max: Option<Duration>,
+    default_timeout: DynamicConfig<Duration>,
+}
+
+impl DeadlineLayer {
+    pub fn new(default_timeout: Duration) -> Self {
+        DeadlineLayer {
+            max: None,
+            default_timeout: DynamicConfig::new(default_timeout),
+        }
+    }
+
+    /// Follows the `request_secs` of `timeouts` as it changes, e.g. in a watched
+    /// [`AppConfig`](crate::config::AppConfig).
+    pub fn dynamic(timeouts: &DynamicConfig<TimeoutConfig>) -> Self {
+        DeadlineLayer {
+            max: None,
+            default_timeout: timeouts.map(TimeoutConfig::request),
+        }
+    }
+
+    /// The longest budget accepted from a caller; defaults to the default timeout.
+    pub fn with_max(mut self, max: Duration) -> Self {
+        self.max = Some(max);
+        self
+    }
+}
+
+impl<S> Layer<S> for DeadlineLayer {
+    type Service = DeadlineService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        DeadlineService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DeadlineService<S> {
+    inner: S,
+    layer: DeadlineLayer,
+}
+
+impl<S> Service<Request> for DeadlineService<S>
+where
+    S: Service<Request, Response = Response, Error = Infallible>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        let default_timeout = *self.layer.default_timeout.get();
+        let deadline = Deadline::from_headers(req.headers(), self.layer.max.unwrap_or(default_timeout))
+            .unwrap_or_else(|| Deadline::after(default_timeout));
+        if deadline.is_expired() {
+            return Box::pin(async { Ok(deadline_exceeded()) });
+        }
+
+        let exemption = DeadlineExemption::default();
+        req.extensions_mut().insert(deadline);
+        req.extensions_mut().insert(exemption.clone());
+        let mut future = Box::pin(deadline.scope(self.inner.call(req)));
+        Box::pin(async move {
+            match tokio::time::timeout_at(deadline.instant(), &mut future).await {
+                Ok(result) => result,
+                Err(_) if exemption.is_exempt() => future.await,
+                Err(_) => Ok(deadline_exceeded()),
+            }
+        })
+    }
+}
+
+fn deadline_exceeded() -> Response {
+    (StatusCode::GATEWAY_TIMEOUT, "request deadline exceeded").into_response()
+}
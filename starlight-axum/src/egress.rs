@@ -0,0 +1,356 @@
+//! Which destinations a [`TracedClient`](crate::client::TracedClient) may call.
+//!
+//! ```ignore
+//! let policy = EgressPolicy::new()
+//!     .allow_host_port("api.stripe.com", 443)
+//!     .allow_host("*.internal.example.com")
+//!     .allow_cidr("10.20.0.0/16".parse()?)
+//!     .with_on_violation(OnViolation::Log);
+//! let client = TracedClient::restricted(policy);
+//! ```
+//!
+//! Everything not allowed is denied. Every decision is recorded as an event on the
+//! request span and counted on `http.client.egress.decisions`, labelled with the
+//! `decision` and whether it was `enforced`, so a policy can be rolled out with
+//! [`OnViolation::Log`] and switched to blocking once the denials stop.
+
+use crate::meter::GLOBAL_METER;
+use axum::http::Uri;
+use hyper_util::client::legacy::connect::dns::{GaiResolver, Name};
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Counter, Meter};
+use std::fmt;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::Service;
+use tracing::Span;
+
+pub const EGRESS_DECISIONS: &str = "http.client.egress.decisions";
+
+/// What happens to a call the policy does not allow.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnViolation {
+    /// Fail the call with [`ClientError::EgressDenied`](crate::client::ClientError::EgressDenied).
+    #[default]
+    Block,
+    /// Log and count the violation, and send the call anyway.
+    Log,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EgressDecision {
+    Allowed,
+    Denied,
+}
+
+impl EgressDecision {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EgressDecision::Allowed => "allowed",
+            EgressDecision::Denied => "denied",
+        }
+    }
+}
+
+/// A block of IP addresses such as `10.0.0.0/8` or `fd00::/8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// Fails when `prefix` is longer than the address.
+    pub fn new(addr: IpAddr, prefix: u8) -> Result<Self, String> {
+        let bits = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if prefix > bits {
+            return Err(format!("prefix /{} is longer than the {} bits of {}", prefix, bits, addr));
+        }
+        Ok(Cidr { addr, prefix })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(block), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(block) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(block), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(block) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    /// An address with a prefix length, or a single address.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match value.trim().split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (value.trim(), None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| format!("{:?} is not an IP address", addr))?;
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse()
+                .map_err(|_| format!("{:?} is not a prefix length", prefix))?,
+            None if addr.is_ipv4() => 32,
+            None => 128,
+        };
+        Cidr::new(addr, prefix)
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum HostPattern {
+    Exact(String),
+    /// `*.example.com`, kept as `.example.com`.
+    Subdomains(String),
+}
+
+impl HostPattern {
+    fn parse(pattern: &str) -> Self {
+        let pattern = normalize(pattern);
+        match pattern.strip_prefix('*') {
+            Some(suffix) if suffix.starts_with('.') => HostPattern::Subdomains(suffix.to_owned()),
+            _ => HostPattern::Exact(pattern),
+        }
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        match self {
+            HostPattern::Exact(exact) => host == exact,
+            HostPattern::Subdomains(suffix) => host.len() > suffix.len() && host.ends_with(suffix.as_str()),
+        }
+    }
+}
+
+/// Lowercase, without the brackets of IPv6 literals and the dot of absolute names.
+fn normalize(host: &str) -> String {
+    let host = host.trim();
+    let host = host.strip_prefix('[').and_then(|host| host.strip_suffix(']')).unwrap_or(host);
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+#[derive(Debug, Clone)]
+struct Rules {
+    hosts: Vec<(HostPattern, Option<u16>)>,
+    cidrs: Vec<(Cidr, Option<u16>)>,
+    on_violation: OnViolation,
+    decisions: Counter<u64>,
+}
+
+/// An allowlist of the hosts and ports a client may call, see the [module](self).
+///
+/// Host patterns are exact names such as `api.example.com`, or `*.example.com` for
+/// every subdomain of `example.com` but not `example.com` itself. IP literals are
+/// allowed by [`EgressPolicy::allow_cidr`] or an exact pattern of the address.
+///
+/// With CIDR blocks, names are also checked once resolved, so an allowed name that
+/// resolves, or is rebound, to an address outside every block is denied too. That check
+/// needs the client's connector to resolve through [`EgressPolicy::resolver`], as
+/// [`TracedClient::restricted`](crate::client::TracedClient::restricted) does.
+#[derive(Debug, Clone)]
+pub struct EgressPolicy {
+    rules: Arc<Rules>,
+}
+
+impl Default for EgressPolicy {
+    fn default() -> Self {
+        EgressPolicy {
+            rules: Arc::new(Rules {
+                hosts: Vec::new(),
+                cidrs: Vec::new(),
+                on_violation: OnViolation::default(),
+                decisions: decisions(&GLOBAL_METER),
+            }),
+        }
+    }
+}
+
+fn decisions(meter: &Meter) -> Counter<u64> {
+    meter
+        .u64_counter(EGRESS_DECISIONS)
+        .with_description("Outbound HTTP calls checked against the egress policy")
+        .build()
+}
+
+impl EgressPolicy {
+    /// A policy denying everything.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows `pattern` on any port.
+    pub fn allow_host(mut self, pattern: &str) -> Self {
+        self.rules_mut().hosts.push((HostPattern::parse(pattern), None));
+        self
+    }
+
+    pub fn allow_host_port(mut self, pattern: &str, port: u16) -> Self {
+        self.rules_mut().hosts.push((HostPattern::parse(pattern), Some(port)));
+        self
+    }
+
+    /// Allows the addresses of `cidr` on any port.
+    pub fn allow_cidr(mut self, cidr: Cidr) -> Self {
+        self.rules_mut().cidrs.push((cidr, None));
+        self
+    }
+
+    pub fn allow_cidr_port(mut self, cidr: Cidr, port: u16) -> Self {
+        self.rules_mut().cidrs.push((cidr, Some(port)));
+        self
+    }
+
+    /// Blocks by default.
+    pub fn with_on_violation(mut self, on_violation: OnViolation) -> Self {
+        self.rules_mut().on_violation = on_violation;
+        self
+    }
+
+    pub fn with_meter(mut self, meter: &Meter) -> Self {
+        self.rules_mut().decisions = decisions(meter);
+        self
+    }
+
+    fn rules_mut(&mut self) -> &mut Rules {
+        Arc::make_mut(&mut self.rules)
+    }
+
+    /// Whether calls to `host` on `port` are allowed, without recording anything.
+    pub fn decide(&self, host: &str, port: u16) -> EgressDecision {
+        let host = normalize(host);
+        let port_matches = |allowed: &Option<u16>| allowed.is_none_or(|allowed| allowed == port);
+        let by_name = self
+            .rules
+            .hosts
+            .iter()
+            .any(|(pattern, allowed)| pattern.matches(&host) && port_matches(allowed));
+        let by_address = host.parse::<IpAddr>().is_ok_and(|ip| {
+            self.rules
+                .cidrs
+                .iter()
+                .any(|(cidr, allowed)| cidr.contains(ip) && port_matches(allowed))
+        });
+        if by_name || by_address {
+            EgressDecision::Allowed
+        } else {
+            EgressDecision::Denied
+        }
+    }
+
+    /// Decides a call to `uri` and records the decision on `span`; `false` when the call
+    /// must not be sent.
+    pub(crate) fn check(&self, uri: &Uri, span: &Span) -> bool {
+        let host = uri.host().unwrap_or_default();
+        let default_port = if uri.scheme_str() == Some("https") { 443 } else { 80 };
+        let port = uri.port_u16().unwrap_or(default_port);
+        self.record(self.decide(host, port), host, Some(port), span)
+    }
+
+    fn record(&self, decision: EgressDecision, host: &str, port: Option<u16>, span: &Span) -> bool {
+        let enforced = self.rules.on_violation == OnViolation::Block;
+        let port = port.map(|port| port as i64);
+        match decision {
+            EgressDecision::Allowed => {
+                info!(target: "starlight::egress", parent: span, host, port, decision = "allowed", "egress allowed");
+            }
+            EgressDecision::Denied => {
+                warn!(target: "starlight::egress", parent: span, host, port, decision = "denied", enforced, "egress denied");
+            }
+        }
+        let mut labels = vec![KeyValue::new("decision", decision.as_str())];
+        if decision == EgressDecision::Denied {
+            labels.push(KeyValue::new("enforced", enforced));
+        }
+        self.rules.decisions.add(1, &labels);
+        decision == EgressDecision::Allowed || !enforced
+    }
+
+    /// The addresses `name` resolved to which may be connected to. Without CIDR blocks
+    /// every address is.
+    fn check_resolved(&self, name: &Name, addrs: Vec<SocketAddr>, span: &Span) -> std::io::Result<Vec<SocketAddr>> {
+        if self.rules.cidrs.is_empty() {
+            return Ok(addrs);
+        }
+        let resolved = addrs.len();
+        let allowed: Vec<SocketAddr> = addrs
+            .into_iter()
+            .filter(|addr| {
+                let inside = self.rules.cidrs.iter().any(|(cidr, _)| cidr.contains(addr.ip()));
+                let decision = if inside { EgressDecision::Allowed } else { EgressDecision::Denied };
+                self.record(decision, &addr.ip().to_string(), None, span)
+            })
+            .collect();
+        if allowed.is_empty() && resolved > 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                format!("{} resolved only to addresses the egress policy denies", name),
+            ));
+        }
+        Ok(allowed)
+    }
+
+    /// Wraps the resolver of a connector so resolved addresses are checked against the
+    /// CIDR blocks before connecting, e.g.
+    /// `HttpConnector::new_with_resolver(policy.resolver(GaiResolver::new()))`.
+    pub fn resolver<R>(&self, inner: R) -> EgressResolver<R> {
+        EgressResolver {
+            inner,
+            policy: self.clone(),
+        }
+    }
+}
+
+/// A resolver dropping the addresses an [`EgressPolicy`] denies, see
+/// [`EgressPolicy::resolver`]. Fails when none is left.
+#[derive(Debug, Clone)]
+pub struct EgressResolver<R = GaiResolver> {
+    inner: R,
+    policy: EgressPolicy,
+}
+
+impl<R> Service<Name> for EgressResolver<R>
+where
+    R: Service<Name>,
+    R::Response: Iterator<Item = SocketAddr>,
+    R::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    R::Future: Send + 'static,
+{
+    type Response = std::vec::IntoIter<SocketAddr>;
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let policy = self.policy.clone();
+        let span = Span::current();
+        let resolving = self.inner.call(name.clone());
+        Box::pin(async move {
+            let addrs = resolving.await.map_err(Into::into)?.collect();
+            let allowed = policy.check_resolved(&name, addrs, &span)?;
+            Ok(allowed.into_iter())
+        })
+    }
+}
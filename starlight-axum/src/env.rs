@@ -0,0 +1,286 @@
+//! Typed environment variables.
+//!
+//! ```ignore
+//! let env = env::with_prefix("STARLIGHT_");
+//! let mut errors = EnvErrors::new();
+//! let workers = errors.check(env.required::<usize>("WORKERS"));
+//! let drain = errors.check(env.duration("DRAIN_TIMEOUT"));
+//! let origins = errors.check(env.list::<String>("CORS_ORIGINS"));
+//! errors.into_result()?;
+//! ```
+//!
+//! Empty values count as unset. Errors name the full variable and why its value was
+//! refused, and [`EnvErrors`] gathers them so a service reports every problem at once.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnvErrorKind {
+    Missing,
+    NotUnicode,
+    Invalid { value: String, reason: String },
+}
+
+/// A variable that is missing or could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvError {
+    /// The full name, prefix included.
+    pub variable: String,
+    pub kind: EnvErrorKind,
+}
+
+impl fmt::Display for EnvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            EnvErrorKind::Missing => write!(f, "{} is not set", self.variable),
+            EnvErrorKind::NotUnicode => write!(f, "{} is not valid unicode", self.variable),
+            EnvErrorKind::Invalid { value, reason } => {
+                write!(f, "{}={:?} is invalid: {}", self.variable, value, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EnvError {}
+
+/// Every [`EnvError`] found while reading a configuration.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnvErrors(Vec<EnvError>);
+
+impl EnvErrors {
+    pub fn new() -> Self {
+        EnvErrors::default()
+    }
+
+    /// The value of `result`, keeping its error for later.
+    pub fn check<T>(&mut self, result: Result<T, EnvError>) -> Option<T> {
+        result.map_err(|err| self.0.push(err)).ok()
+    }
+
+    pub fn errors(&self) -> &[EnvError] {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn into_result(self) -> Result<(), EnvErrors> {
+        if self.0.is_empty() { Ok(()) } else { Err(self) }
+    }
+}
+
+impl fmt::Display for EnvErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors: Vec<String> = self.0.iter().map(ToString::to_string).collect();
+        write!(f, "invalid environment: {}", errors.join("; "))
+    }
+}
+
+impl std::error::Error for EnvErrors {}
+
+impl From<EnvError> for EnvErrors {
+    fn from(err: EnvError) -> Self {
+        EnvErrors(vec![err])
+    }
+}
+
+/// Reads variables from the process environment, or from a fixed set with
+/// [`Env::from_vars`], under an optional prefix.
+#[derive(Debug, Clone, Default)]
+pub struct Env {
+    prefix: String,
+    vars: Option<Arc<HashMap<String, String>>>,
+}
+
+impl Env {
+    pub fn new() -> Self {
+        Env::default()
+    }
+
+    /// Variables from `vars` instead of the process environment, e.g. for tests.
+    pub fn from_vars(vars: impl IntoIterator<Item = (String, String)>) -> Self {
+        Env {
+            prefix: String::new(),
+            vars: Some(Arc::new(vars.into_iter().collect())),
+        }
+    }
+
+    /// Look keys up as `prefix` followed by the key, after any prefix already set.
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix.push_str(prefix);
+        self
+    }
+
+    /// The name `key` is looked up as.
+    pub fn variable(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    fn lookup(&self, key: &str) -> Result<Option<(String, String)>, EnvError> {
+        let variable = self.variable(key);
+        let value = match &self.vars {
+            Some(vars) => vars.get(&variable).cloned(),
+            None => match std::env::var(&variable) {
+                Ok(value) => Some(value),
+                Err(std::env::VarError::NotPresent) => None,
+                Err(std::env::VarError::NotUnicode(_)) => {
+                    return Err(EnvError {
+                        variable,
+                        kind: EnvErrorKind::NotUnicode,
+                    });
+                }
+            },
+        };
+        Ok(value
+            .filter(|value| !value.trim().is_empty())
+            .map(|value| (variable, value)))
+    }
+
+    fn parse<T>(&self, key: &str, parse: impl FnOnce(&str) -> Result<T, String>) -> Result<Option<T>, EnvError> {
+        let Some((variable, value)) = self.lookup(key)? else {
+            return Ok(None);
+        };
+        match parse(value.trim()) {
+            Ok(parsed) => Ok(Some(parsed)),
+            Err(reason) => Err(EnvError {
+                variable,
+                kind: EnvErrorKind::Invalid { value, reason },
+            }),
+        }
+    }
+
+    pub fn required<T>(&self, key: &str) -> Result<T, EnvError>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        self.optional(key)?.ok_or_else(|| EnvError {
+            variable: self.variable(key),
+            kind: EnvErrorKind::Missing,
+        })
+    }
+
+    pub fn optional<T>(&self, key: &str) -> Result<Option<T>, EnvError>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        self.parse(key, |value| value.parse().map_err(|err: T::Err| err.to_string()))
+    }
+
+    /// A boolean, see [`parse_bool`].
+    pub fn flag(&self, key: &str) -> Result<Option<bool>, EnvError> {
+        self.parse(key, parse_bool)
+    }
+
+    /// A duration, see [`parse_duration`].
+    pub fn duration(&self, key: &str) -> Result<Option<Duration>, EnvError> {
+        self.parse(key, parse_duration)
+    }
+
+    /// A comma-separated list, empty when unset, see [`parse_list`].
+    pub fn list<T>(&self, key: &str) -> Result<Vec<T>, EnvError>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        Ok(self.parse(key, parse_list)?.unwrap_or_default())
+    }
+}
+
+/// Variables of the process environment named `prefix` followed by the key.
+pub fn with_prefix(prefix: &str) -> Env {
+    Env::new().with_prefix(prefix)
+}
+
+pub fn required<T>(key: &str) -> Result<T, EnvError>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    Env::new().required(key)
+}
+
+pub fn optional<T>(key: &str) -> Result<Option<T>, EnvError>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    Env::new().optional(key)
+}
+
+pub fn flag(key: &str) -> Result<Option<bool>, EnvError> {
+    Env::new().flag(key)
+}
+
+pub fn duration(key: &str) -> Result<Option<Duration>, EnvError> {
+    Env::new().duration(key)
+}
+
+pub fn list<T>(key: &str) -> Result<Vec<T>, EnvError>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    Env::new().list(key)
+}
+
+/// `1`, `true`, `yes` and `on` are true, `0`, `false`, `no` and `off` false, in any case.
+pub fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" => Ok(false),
+        _ => Err("expected one of 1, true, yes, on, 0, false, no, off".to_owned()),
+    }
+}
+
+/// A number followed by `ms`, `s`, `m`, `h` or `d`, possibly several as in `1h30m`.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let mut rest = value.trim();
+    if rest.is_empty() {
+        return Err("empty duration".to_owned());
+    }
+    let mut total = Duration::ZERO;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let amount: u64 = rest[..digits]
+            .parse()
+            .map_err(|_| format!("expected a number at {:?}", rest))?;
+        rest = &rest[digits..];
+        let unit = rest.find(|c: char| c.is_ascii_digit()).unwrap_or(rest.len());
+        let seconds = |factor: u64| amount.checked_mul(factor).map(Duration::from_secs);
+        let part = match &rest[..unit] {
+            "ms" => Some(Duration::from_millis(amount)),
+            "s" => seconds(1),
+            "m" => seconds(60),
+            "h" => seconds(60 * 60),
+            "d" => seconds(24 * 60 * 60),
+            "" => return Err(format!("{} is missing a unit (ms, s, m, h or d)", amount)),
+            other => return Err(format!("unknown unit {:?}, expected ms, s, m, h or d", other)),
+        };
+        rest = &rest[unit..];
+        total = part
+            .and_then(|part| total.checked_add(part))
+            .ok_or_else(|| "duration is too long".to_owned())?;
+    }
+    Ok(total)
+}
+
+/// Comma-separated items, trimmed, skipping empty ones.
+pub fn parse_list<T>(value: &str) -> Result<Vec<T>, String>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| item.parse().map_err(|err: T::Err| format!("item {:?}: {}", item, err)))
+        .collect()
+}
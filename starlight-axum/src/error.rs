@@ -0,0 +1,236 @@
+//! The last line of error handling: [`ErrorMapperLayer`] answers handler errors and
+//! panics with the i18n error envelope of [`I18nErrorResponse`].
+
+use crate::middleware::locale::{I18nErrorResponse, Locale};
+use crate::crash::catch_unwind;
+use crate::task::panic_message;
+use axum::extract::Request;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use opentelemetry::trace::Status;
+use starlight_protocol::i18n::{Catalog, CldrFormatter, I18nCode, Translator, ValueFormatter};
+use std::convert::Infallible;
+use std::fmt;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::pin::{Pin, pin};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// An error with an i18n code and the status it is answered with, usually an enum
+/// deriving `I18nCode` with `#[i18n(protocol)]`.
+pub trait I18nError: I18nCode + Clone + Send + Sync + 'static {
+    fn status(&self) -> StatusCode;
+}
+
+/// Any error returned by a handler, for `?` in handlers returning
+/// `Result<T, HandlerError>`. Answered with a bare 500 until an [`ErrorMapperLayer`]
+/// turns it into the error envelope.
+pub struct HandlerError(anyhow::Error);
+
+impl HandlerError {
+    pub fn error(&self) -> &anyhow::Error {
+        &self.0
+    }
+}
+
+impl fmt::Debug for HandlerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl<E: Into<anyhow::Error>> From<E> for HandlerError {
+    fn from(error: E) -> Self {
+        HandlerError(error.into())
+    }
+}
+
+/// The error of a [`HandlerError`] response, for the [`ErrorMapperLayer`].
+#[derive(Clone)]
+struct Unhandled(Arc<anyhow::Error>);
+
+impl IntoResponse for HandlerError {
+    fn into_response(self) -> Response {
+        let mut response = StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        response.extensions_mut().insert(Unhandled(Arc::new(self.0)));
+        response
+    }
+}
+
+type Mapper<E> = dyn Fn(&anyhow::Error) -> Option<E> + Send + Sync;
+
+/// Answers [`HandlerError`]s and panics of the handlers behind it with an
+/// [`I18nErrorResponse`] in the request [`Locale`] (`en` without a
+/// [`LocaleLayer`](crate::middleware::locale::LocaleLayer)).
+///
+/// Errors are turned into an `E` by the mapper, usually by downcasting them to known
+/// types, otherwise into the fallback, as panics are. The status comes from
+/// [`I18nError::status`], the body only from the i18n code and its translation, so the
+/// error messages never reach the client. They are recorded on the current span
+/// instead: the i18n code as `error.type` and the whole chain of causes as
+/// `error.chain`, with an error status and an error event for 5xx.
+pub struct ErrorMapperLayer<E> {
+    fallback: E,
+    mapper: Arc<Mapper<E>>,
+    translator: Arc<dyn Translator>,
+    formatter: Arc<dyn ValueFormatter>,
+}
+
+impl<E: Clone> Clone for ErrorMapperLayer<E> {
+    fn clone(&self) -> Self {
+        ErrorMapperLayer {
+            fallback: self.fallback.clone(),
+            mapper: self.mapper.clone(),
+            translator: self.translator.clone(),
+            formatter: self.formatter.clone(),
+        }
+    }
+}
+
+impl<E: fmt::Debug> fmt::Debug for ErrorMapperLayer<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ErrorMapperLayer")
+            .field("fallback", &self.fallback)
+            .finish_non_exhaustive()
+    }
+}
+
+/// What went wrong in the handler.
+enum Failure {
+    Error(Arc<anyhow::Error>),
+    Panic(String),
+}
+
+impl<E: I18nError> ErrorMapperLayer<E> {
+    /// Answers every error with `fallback`, such as `ApiError::Internal`.
+    pub fn new(fallback: E) -> Self {
+        ErrorMapperLayer {
+            fallback,
+            mapper: Arc::new(|_| None),
+            translator: Arc::new(Catalog::new()),
+            formatter: Arc::new(CldrFormatter),
+        }
+    }
+
+    /// Picks the variant for an error, `None` for the fallback.
+    ///
+    /// ```ignore
+    /// ErrorMapperLayer::new(ApiError::Internal).with_mapper(|err| {
+    ///     err.downcast_ref::<sqlx::Error>()
+    ///         .filter(|err| matches!(err, sqlx::Error::RowNotFound))
+    ///         .map(|_| ApiError::NotFound)
+    /// })
+    /// ```
+    pub fn with_mapper<F>(mut self, mapper: F) -> Self
+    where
+        F: Fn(&anyhow::Error) -> Option<E> + Send + Sync + 'static,
+    {
+        self.mapper = Arc::new(mapper);
+        self
+    }
+
+    /// Translates the messages; they are the i18n codes without one.
+    pub fn with_translator(mut self, translator: Arc<dyn Translator>) -> Self {
+        self.translator = translator;
+        self
+    }
+
+    /// Formats the numbers and dates in the messages, [`CldrFormatter`] by default.
+    pub fn with_formatter(mut self, formatter: Arc<dyn ValueFormatter>) -> Self {
+        self.formatter = formatter;
+        self
+    }
+
+    fn respond(&self, failure: Failure, locale: &Locale, span: &Span) -> Response {
+        let (error, chain) = match failure {
+            Failure::Error(err) => (
+                (self.mapper)(&err).unwrap_or_else(|| self.fallback.clone()),
+                format!("{:#}", err),
+            ),
+            Failure::Panic(message) => (self.fallback.clone(), format!("panicked: {}", message)),
+        };
+        let status = error.status();
+        let code = error.get_i18n_code();
+        span.set_attribute("error.type", code);
+        span.set_attribute("error.chain", chain.clone());
+        if status.is_server_error() {
+            error!(parent: span, error.type = code, error.chain = %chain, "request failed");
+            span.set_status(Status::error(chain));
+        } else {
+            debug!(parent: span, error.type = code, error.chain = %chain, "request failed");
+        }
+        I18nErrorResponse::with_formatter(status, error, locale, &*self.translator, &*self.formatter).into_response()
+    }
+}
+
+impl<S, E: Clone> Layer<S> for ErrorMapperLayer<E> {
+    type Service = ErrorMapperService<S, E>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ErrorMapperService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ErrorMapperService<S, E> {
+    inner: S,
+    layer: ErrorMapperLayer<E>,
+}
+
+impl<S, E> Service<Request> for ErrorMapperService<S, E>
+where
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send,
+    E: I18nError,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+        let locale = req
+            .extensions()
+            .get::<Locale>()
+            .cloned()
+            .unwrap_or_else(|| Locale::new("en"));
+        let span = Span::current();
+
+        Box::pin(async move {
+            let outcome = match catch_unwind(AssertUnwindSafe(|| inner.call(req))) {
+                Ok(future) => {
+                    let mut future = pin!(future);
+                    std::future::poll_fn(|cx| match catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(cx))) {
+                        Ok(Poll::Ready(Ok(response))) => Poll::Ready(Ok(response)),
+                        Ok(Poll::Ready(Err(never))) => match never {},
+                        Ok(Poll::Pending) => Poll::Pending,
+                        Err(payload) => Poll::Ready(Err(payload)),
+                    })
+                    .await
+                }
+                Err(payload) => Err(payload),
+            };
+            let failure = match outcome {
+                Ok(mut response) => match response.extensions_mut().remove::<Unhandled>() {
+                    Some(Unhandled(err)) => Failure::Error(err),
+                    None => return Ok(response),
+                },
+                Err(payload) => Failure::Panic(panic_message(&*payload).to_owned()),
+            };
+            Ok(layer.respond(failure, &locale, &span))
+        })
+    }
+}
@@ -0,0 +1,331 @@
+use crate::meter::GLOBAL_METER;
+use axum::http::{HeaderMap, Request, Response};
+use base64::Engine;
+use http_body::{Body, Frame, SizeHint};
+use opentelemetry::metrics::Histogram;
+use opentelemetry::propagation::Injector;
+use opentelemetry::trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState};
+use opentelemetry::{Context as OtelContext, KeyValue, global};
+use opentelemetry_http::HeaderExtractor;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, LazyLock};
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tower::{Layer, Service};
+use tracing::{Instrument, Span};
+use tracing::field::Empty;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+static SERVER_DURATION: LazyLock<Histogram<f64>> = LazyLock::new(|| {
+    GLOBAL_METER
+        .f64_histogram("rpc.server.duration")
+        .with_description("Duration of inbound gRPC calls")
+        .with_unit("s")
+        .build()
+});
+
+static CLIENT_DURATION: LazyLock<Histogram<f64>> = LazyLock::new(|| {
+    GLOBAL_METER
+        .f64_histogram("rpc.client.duration")
+        .with_description("Duration of outbound gRPC calls")
+        .with_unit("s")
+        .build()
+});
+
+const GRPC_TRACE_BIN: &str = "grpc-trace-bin";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Server,
+    Client,
+}
+
+/// Tracing and metrics for tonic servers ([`GrpcTraceLayer::server`]) and clients
+/// ([`GrpcTraceLayer::client`]).
+///
+/// Spans are named `package.Service/Method`. The server side continues the caller's
+/// trace from `traceparent` or, failing that, `grpc-trace-bin`; the client side
+/// injects `traceparent` into the outgoing metadata. Every status except OK and
+/// NotFound marks the span as failed unless configured otherwise.
+#[derive(Debug, Clone)]
+pub struct GrpcTraceLayer {
+    side: Side,
+    non_error_codes: Arc<[i32]>,
+}
+
+impl GrpcTraceLayer {
+    pub fn server() -> Self {
+        Self::new(Side::Server)
+    }
+
+    pub fn client() -> Self {
+        Self::new(Side::Client)
+    }
+
+    fn new(side: Side) -> Self {
+        GrpcTraceLayer {
+            side,
+            non_error_codes: Arc::from([0, 5]),
+        }
+    }
+
+    /// gRPC status codes which should not mark the span as an error.
+    pub fn with_non_error_codes(mut self, codes: impl IntoIterator<Item = i32>) -> Self {
+        self.non_error_codes = codes.into_iter().collect();
+        self
+    }
+}
+
+impl<S> Layer<S> for GrpcTraceLayer {
+    type Service = GrpcTraceService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        GrpcTraceService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GrpcTraceService<S> {
+    inner: S,
+    layer: GrpcTraceLayer,
+}
+
+/// Splits `/package.Service/Method` into its service and method.
+pub fn parse_grpc_path(path: &str) -> Option<(&str, &str)> {
+    let (service, method) = path.strip_prefix('/')?.split_once('/')?;
+    (!service.is_empty() && !method.is_empty() && !method.contains('/')).then_some((service, method))
+}
+
+/// Decodes the binary OpenCensus trace context carried in `grpc-trace-bin`.
+pub fn parse_grpc_trace_bin(value: &str) -> Option<SpanContext> {
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(value.trim_end_matches('='))
+        .or_else(|_| base64::engine::general_purpose::STANDARD_NO_PAD.decode(value.trim_end_matches('=')))
+        .ok()?;
+    // version 0, then field 0 (trace id, 16 bytes), field 1 (span id, 8 bytes), field 2 (options, 1 byte)
+    if bytes.len() < 29 || bytes[0] != 0 || bytes[1] != 0 || bytes[18] != 1 || bytes[27] != 2 {
+        return None;
+    }
+    let trace_id = TraceId::from_bytes(bytes[2..18].try_into().ok()?);
+    let span_id = SpanId::from_bytes(bytes[19..27].try_into().ok()?);
+    let flags = if bytes[28] & 1 == 1 {
+        TraceFlags::SAMPLED
+    } else {
+        TraceFlags::default()
+    };
+    let context = SpanContext::new(trace_id, span_id, flags, true, TraceState::default());
+    context.is_valid().then_some(context)
+}
+
+fn extract_parent(headers: &HeaderMap) -> OtelContext {
+    let context = global::get_text_map_propagator(|prop| prop.extract(&HeaderExtractor(headers)));
+    if context.span().span_context().is_valid() {
+        return context;
+    }
+    headers
+        .get(GRPC_TRACE_BIN)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_grpc_trace_bin)
+        .map(|span_context| OtelContext::new().with_remote_span_context(span_context))
+        .unwrap_or(context)
+}
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            axum::http::HeaderName::from_bytes(key.as_bytes()),
+            axum::http::HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+fn grpc_status(headers: &HeaderMap) -> Option<i32> {
+    headers.get("grpc-status")?.to_str().ok()?.parse().ok()
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for GrpcTraceService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+    ResBody: Body,
+{
+    type Response = Response<GrpcBody<ResBody>>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let (service, method) = parse_grpc_path(req.uri().path()).unwrap_or(("unknown", "unknown"));
+        let name = format!("{}/{}", service, method);
+        let kind = match self.layer.side {
+            Side::Server => "server",
+            Side::Client => "client",
+        };
+        let span = info_span!(
+            "grpc.request",
+            otel.name = %name,
+            otel.kind = kind,
+            otel.status_code = Empty,
+            rpc.system = "grpc",
+            rpc.service = %service,
+            rpc.method = %method,
+            rpc.grpc.status_code = Empty,
+        );
+        let labels = vec![
+            KeyValue::new("rpc.system", "grpc"),
+            KeyValue::new("rpc.service", service.to_owned()),
+            KeyValue::new("rpc.method", method.to_owned()),
+        ];
+
+        match self.layer.side {
+            Side::Server => {
+                let _ = span.set_parent(extract_parent(req.headers()));
+            }
+            Side::Client => {
+                let context = span.context();
+                global::get_text_map_propagator(|prop| {
+                    prop.inject_context(&context, &mut HeaderInjector(req.headers_mut()))
+                });
+            }
+        }
+
+        let recorder = Recorder {
+            span: span.clone(),
+            side: self.layer.side,
+            non_error_codes: self.layer.non_error_codes.clone(),
+            labels,
+            started: Instant::now(),
+        };
+        let future = self.inner.call(req).instrument(span);
+
+        Box::pin(async move {
+            let response = future.await?;
+            let (parts, body) = response.into_parts();
+            let mut recorder = Some(recorder);
+            // Trailers-only responses carry the status in the headers.
+            if let Some(status) = grpc_status(&parts.headers)
+                && let Some(recorder) = recorder.take()
+            {
+                recorder.finish(status);
+            }
+            Ok(Response::from_parts(parts, GrpcBody { inner: body, recorder }))
+        })
+    }
+}
+
+struct Recorder {
+    span: Span,
+    side: Side,
+    non_error_codes: Arc<[i32]>,
+    labels: Vec<KeyValue>,
+    started: Instant,
+}
+
+impl Recorder {
+    fn finish(mut self, status: i32) {
+        self.span.record("rpc.grpc.status_code", status);
+        if !self.non_error_codes.contains(&status) {
+            self.span.record("otel.status_code", "ERROR");
+        }
+        self.labels.push(KeyValue::new("rpc.grpc.status_code", status as i64));
+        let histogram = match self.side {
+            Side::Server => &SERVER_DURATION,
+            Side::Client => &CLIENT_DURATION,
+        };
+        histogram.record(self.started.elapsed().as_secs_f64(), &self.labels);
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// Response body which records the gRPC status once the trailers arrive.
+    pub struct GrpcBody<B> {
+        #[pin]
+        inner: B,
+        recorder: Option<Recorder>,
+    }
+}
+
+impl<B: Body> Body for GrpcBody<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let frame = std::task::ready!(this.inner.poll_frame(cx));
+        match &frame {
+            Some(Ok(frame)) => {
+                if let Some(status) = frame.trailers_ref().and_then(grpc_status)
+                    && let Some(recorder) = this.recorder.take()
+                {
+                    recorder.finish(status);
+                }
+            }
+            // A broken stream never delivers a status; report it as UNKNOWN.
+            Some(Err(_)) => {
+                if let Some(recorder) = this.recorder.take() {
+                    recorder.finish(2);
+                }
+            }
+            None => {
+                if let Some(recorder) = this.recorder.take() {
+                    recorder.finish(0);
+                }
+            }
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_service_and_method() {
+        assert_eq!(
+            parse_grpc_path("/helloworld.Greeter/SayHello"),
+            Some(("helloworld.Greeter", "SayHello"))
+        );
+        assert_eq!(parse_grpc_path("/health"), None);
+        assert_eq!(parse_grpc_path("/a/b/c"), None);
+    }
+
+    #[test]
+    fn decodes_grpc_trace_bin() {
+        let mut bytes = vec![0u8, 0];
+        bytes.extend_from_slice(&[0x4b; 16]);
+        bytes.push(1);
+        bytes.extend_from_slice(&[0x2a; 8]);
+        bytes.extend_from_slice(&[2, 1]);
+        let encoded = base64::engine::general_purpose::STANDARD.encode(&bytes);
+
+        let context = parse_grpc_trace_bin(&encoded).unwrap();
+        assert_eq!(context.trace_id(), TraceId::from_bytes([0x4b; 16]));
+        assert_eq!(context.span_id(), SpanId::from_bytes([0x2a; 8]));
+        assert!(context.is_sampled());
+        assert!(context.is_remote());
+        assert!(parse_grpc_trace_bin("not base64!").is_none());
+    }
+}
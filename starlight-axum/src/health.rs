@@ -0,0 +1,225 @@
+use axum::extract::Request;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::task::{Context, Poll};
+use starlight_tokio::CancellationToken;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+use tower::{Layer, Service};
+
+mod checks;
+
+pub use checks::{CheckReport, CheckStatus, Criticality, HEALTH_CHECK_UP, HealthCheck, HealthChecks};
+
+/// The readiness of a server, as reported by `GET /health/ready`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub draining: bool,
+    pub in_flight: usize,
+    /// Milliseconds since shutdown started.
+    pub drain_elapsed_ms: Option<u64>,
+    /// The [`HealthChecks`] of [`readiness_router_with_checks`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub checks: Vec<CheckReport>,
+}
+
+impl IntoResponse for Readiness {
+    fn into_response(self) -> Response {
+        let status = if self.ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+        (status, Json(self)).into_response()
+    }
+}
+
+#[derive(Debug, Default)]
+struct Tracker {
+    active: AtomicUsize,
+    draining_since: OnceLock<Instant>,
+    idle: Notify,
+    shutdown: CancellationToken,
+}
+
+/// Counts the requests a server is handling and whether it is shutting down, so the
+/// readiness probe goes unready as soon as the drain starts.
+///
+/// [`serve_many_with_tracker`](crate::serve::serve_many_with_tracker) and
+/// [`AxumService::with_tracker`](crate::AxumService::with_tracker) count their requests
+/// with it and start the drain on shutdown; [`readiness_router`] reports it. A request
+/// is counted until its response head is produced, streamed bodies are not waited for;
+/// long-lived connections such as [`GracefulWebSocket`](crate::ws::GracefulWebSocket)
+/// hold a [`InFlightGuard`] of their own. The layer stores the tracker as a request
+/// extension for them.
+#[derive(Debug, Clone, Default)]
+pub struct InFlightTracker {
+    tracker: Arc<Tracker>,
+}
+
+impl InFlightTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts a request until the returned guard is dropped.
+    pub fn track(&self) -> InFlightGuard {
+        self.tracker.active.fetch_add(1, Ordering::AcqRel);
+        InFlightGuard {
+            tracker: self.tracker.clone(),
+        }
+    }
+
+    /// A layer counting every request passing through it.
+    pub fn layer(&self) -> InFlightLayer {
+        InFlightLayer { tracker: self.clone() }
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.tracker.active.load(Ordering::Acquire)
+    }
+
+    /// Marks the server unready and cancels the [`InFlightTracker::shutdown_token`].
+    /// Later calls keep the first start time.
+    pub fn start_drain(&self) {
+        if self.tracker.draining_since.set(Instant::now()).is_ok() {
+            info!("draining {} in-flight requests", self.in_flight());
+            self.tracker.shutdown.cancel();
+        }
+    }
+
+    /// Cancelled when the drain starts, for connections that have to close themselves.
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.tracker.shutdown.clone()
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.tracker.draining_since.get().is_some()
+    }
+
+    pub fn drain_elapsed(&self) -> Option<Duration> {
+        self.tracker.draining_since.get().map(Instant::elapsed)
+    }
+
+    pub fn readiness(&self) -> Readiness {
+        let drain_elapsed = self.drain_elapsed();
+        Readiness {
+            ready: drain_elapsed.is_none(),
+            draining: drain_elapsed.is_some(),
+            in_flight: self.in_flight(),
+            drain_elapsed_ms: drain_elapsed.map(|elapsed| elapsed.as_millis() as u64),
+            checks: Vec::new(),
+        }
+    }
+
+    /// [`InFlightTracker::readiness`] with the results of `checks`, unready while a
+    /// critical check fails.
+    pub async fn readiness_with_checks(&self, checks: &HealthChecks) -> Readiness {
+        let reports = checks.run().await;
+        let mut readiness = self.readiness();
+        readiness.ready &= !reports.iter().any(|report| report.status == CheckStatus::Down);
+        readiness.checks = reports;
+        readiness
+    }
+
+    /// Waits until no request is in flight, for up to `timeout`. Returns whether it is
+    /// idle.
+    pub async fn wait_idle(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let notified = self.tracker.idle.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if self.in_flight() == 0 {
+                return true;
+            }
+            if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                return self.in_flight() == 0;
+            }
+        }
+    }
+}
+
+/// Keeps a request counted by [`InFlightTracker`].
+#[derive(Debug)]
+pub struct InFlightGuard {
+    tracker: Arc<Tracker>,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if self.tracker.active.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.tracker.idle.notify_waiters();
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InFlightLayer {
+    tracker: InFlightTracker,
+}
+
+impl<S> Layer<S> for InFlightLayer {
+    type Service = InFlightService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        InFlightService {
+            inner,
+            tracker: self.tracker.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InFlightService<S> {
+    inner: S,
+    tracker: InFlightTracker,
+}
+
+impl<S, B> Service<Request<B>> for InFlightService<S>
+where
+    S: Service<Request<B>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        req.extensions_mut().insert(self.tracker.clone());
+        let guard = self.tracker.track();
+        let future = self.inner.call(req);
+        Box::pin(async move {
+            let response = future.await;
+            drop(guard);
+            response
+        })
+    }
+}
+
+/// `GET /health/ready`: 200 with the [`Readiness`] as JSON while serving, 503 once the
+/// drain started, with the requests still in flight and how long the drain has taken.
+/// Served behind the tracked router, the probe counts itself as in flight.
+pub fn readiness_router(tracker: InFlightTracker) -> Router {
+    Router::new().route("/health/ready", get(move || async move { tracker.readiness() }))
+}
+
+/// [`readiness_router`] also running `checks` on every probe: 503 while a
+/// [`Criticality::Critical`] check fails, 200 with the failing degraded and
+/// informational checks listed otherwise.
+pub fn readiness_router_with_checks(tracker: InFlightTracker, checks: HealthChecks) -> Router {
+    let checks = Arc::new(checks);
+    Router::new().route(
+        "/health/ready",
+        get(move || async move { tracker.readiness_with_checks(&checks).await }),
+    )
+}
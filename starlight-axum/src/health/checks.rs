@@ -0,0 +1,269 @@
+use crate::meter::GLOBAL_METER;
+use opentelemetry::metrics::{Meter, ObservableGauge};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+
+pub const HEALTH_CHECK_UP: &str = "health.check.up";
+
+/// How much a failing check matters to readiness.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Criticality {
+    /// The service cannot work without it: a failure makes the service unready.
+    Critical,
+    /// The service works worse without it, e.g. without recommendations.
+    Degraded,
+    /// Only reported.
+    Informational,
+}
+
+impl Criticality {
+    fn as_str(self) -> &'static str {
+        match self {
+            Criticality::Critical => "critical",
+            Criticality::Degraded => "degraded",
+            Criticality::Informational => "informational",
+        }
+    }
+
+    /// The status of a failing check.
+    fn failure(self) -> CheckStatus {
+        match self {
+            Criticality::Critical => CheckStatus::Down,
+            Criticality::Degraded => CheckStatus::Degraded,
+            Criticality::Informational => CheckStatus::Failed,
+        }
+    }
+}
+
+/// The outcome of one check: `up`, or how its failure counts, `down` for
+/// [`Criticality::Critical`], `degraded` for [`Criticality::Degraded`] and `failed` for
+/// [`Criticality::Informational`]. `skipped` when a check it requires did not pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Up,
+    Down,
+    Degraded,
+    Failed,
+    Skipped,
+}
+
+/// One check in the [`Readiness`](super::Readiness) body.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckReport {
+    pub name: String,
+    pub criticality: Criticality,
+    pub status: CheckStatus,
+    /// Why the check failed or was skipped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+type Probe = dyn Fn() -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>> + Send + Sync;
+
+/// A dependency probed by the readiness endpoint, such as a database ping.
+#[derive(Clone)]
+pub struct HealthCheck {
+    name: String,
+    criticality: Criticality,
+    requires: Vec<String>,
+    timeout: Duration,
+    probe: Arc<Probe>,
+}
+
+impl fmt::Debug for HealthCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HealthCheck")
+            .field("name", &self.name)
+            .field("criticality", &self.criticality)
+            .field("requires", &self.requires)
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HealthCheck {
+    /// A check passing when `probe` returns `Ok` within 5 seconds.
+    pub fn new<F, Fut>(name: impl Into<String>, criticality: Criticality, probe: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        HealthCheck {
+            name: name.into(),
+            criticality,
+            requires: Vec::new(),
+            timeout: Duration::from_secs(5),
+            probe: Arc::new(move || Box::pin(probe())),
+        }
+    }
+
+    /// Runs only after the check named `other` passed, and is skipped otherwise: a check
+    /// through a proxy need not time out again once the proxy is known down.
+    pub fn requires(mut self, other: impl Into<String>) -> Self {
+        self.requires.push(other.into());
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    async fn run(self) -> CheckReport {
+        let started = Instant::now();
+        let error = match tokio::time::timeout(self.timeout, (self.probe)()).await {
+            Ok(Ok(())) => None,
+            Ok(Err(err)) => Some(format!("{:#}", err)),
+            Err(_) => Some(format!("timed out after {:?}", self.timeout)),
+        };
+        let status = match error {
+            None => CheckStatus::Up,
+            Some(_) => self.criticality.failure(),
+        };
+        self.report(status, error, started.elapsed())
+    }
+
+    fn report(&self, status: CheckStatus, error: Option<String>, duration: Duration) -> CheckReport {
+        CheckReport {
+            name: self.name.clone(),
+            criticality: self.criticality,
+            status,
+            error,
+            duration_ms: duration.as_millis() as u64,
+        }
+    }
+}
+
+/// The last status of each check, for the gauge.
+type LastStatus = Arc<Mutex<HashMap<String, (Criticality, bool)>>>;
+
+/// The checks of [`readiness_router_with_checks`](super::readiness_router_with_checks).
+///
+/// Checks run concurrently, except that a check runs after those it
+/// [`requires`](HealthCheck::requires); a check whose requirement did not pass, is
+/// unknown or is part of a cycle is `skipped`. Only a failing [`Criticality::Critical`]
+/// check makes the service unready. Each check reports 1 while up and 0 otherwise on
+/// the `health.check.up` gauge, labelled with `health.check.name` and
+/// `health.check.criticality`.
+#[derive(Debug, Clone)]
+pub struct HealthChecks {
+    checks: Vec<HealthCheck>,
+    last: LastStatus,
+    _gauge: ObservableGauge<u64>,
+}
+
+impl Default for HealthChecks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HealthChecks {
+    pub fn new() -> Self {
+        Self::with_meter(&GLOBAL_METER)
+    }
+
+    pub fn with_meter(meter: &Meter) -> Self {
+        let last = LastStatus::default();
+        let observed = last.clone();
+        let gauge = meter
+            .u64_observable_gauge(HEALTH_CHECK_UP)
+            .with_description("1 while the health check passes")
+            .with_callback(move |observer| {
+                for (name, (criticality, up)) in observed.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+                    let attributes = crate::attrs! {
+                        "health.check.name" => name.clone(),
+                        "health.check.criticality" => criticality.as_str(),
+                    };
+                    observer.observe(*up as u64, &attributes);
+                }
+            })
+            .build();
+        HealthChecks {
+            checks: Vec::new(),
+            last,
+            _gauge: gauge,
+        }
+    }
+
+    pub fn with_check(mut self, check: HealthCheck) -> Self {
+        self.checks.push(check);
+        self
+    }
+
+    /// Runs every check, reported in the order they were added.
+    pub async fn run(&self) -> Vec<CheckReport> {
+        let mut reports: HashMap<String, CheckReport> = HashMap::new();
+        let mut pending: Vec<&HealthCheck> = self.checks.iter().collect();
+        while !pending.is_empty() {
+            let mut running = JoinSet::new();
+            let mut spawned = HashMap::new();
+            let mut waiting = Vec::new();
+            let mut skipped = false;
+            for check in pending {
+                let failed = check.requires.iter().find(|other| {
+                    reports
+                        .get(*other)
+                        .is_some_and(|report| report.status != CheckStatus::Up)
+                });
+                if let Some(failed) = failed {
+                    let error = format!("requires {}, which did not pass", failed);
+                    reports.insert(
+                        check.name.clone(),
+                        check.report(CheckStatus::Skipped, Some(error), Duration::ZERO),
+                    );
+                    skipped = true;
+                } else if check.requires.iter().all(|other| reports.contains_key(other)) {
+                    spawned.insert(running.spawn(check.clone().run()).id(), check);
+                } else {
+                    waiting.push(check);
+                }
+            }
+
+            if running.is_empty() && !skipped {
+                // Nothing can run any more: the requirements are unknown or circular.
+                for check in waiting.drain(..) {
+                    let error = format!("requires unknown or circular checks {:?}", check.requires);
+                    warn!(check = %check.name, "health check {}", error);
+                    reports.insert(
+                        check.name.clone(),
+                        check.report(CheckStatus::Skipped, Some(error), Duration::ZERO),
+                    );
+                }
+            }
+            while let Some(joined) = running.join_next_with_id().await {
+                let report = match joined {
+                    Ok((_, report)) => report,
+                    Err(err) => {
+                        let check = spawned[&err.id()];
+                        let error = Some(format!("panicked: {}", err));
+                        check.report(check.criticality.failure(), error, Duration::ZERO)
+                    }
+                };
+                reports.insert(report.name.clone(), report);
+            }
+            pending = waiting;
+        }
+
+        let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+        self.checks
+            .iter()
+            .filter_map(|check| reports.remove(&check.name))
+            .inspect(|report| {
+                last.insert(
+                    report.name.clone(),
+                    (report.criticality, report.status == CheckStatus::Up),
+                );
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,208 @@
+//! Child spans for the databases, caches and queues a handler calls, without the
+//! OpenTelemetry API.
+//!
+//! ```ignore
+//! let user = op("db.query", &[("db.system", "postgres")], repo.find(id)).await?;
+//!
+//! let span = Span::current();
+//! let hit = span
+//!     .child("cache.get", &[("db.system", "redis")])
+//!     .with_attribute("db.operation.name", "GET")
+//!     .run(cache.get(&key))
+//!     .await?;
+//! ```
+//!
+//! Queries are logged with [`query`], which keeps the values of their parameters out of
+//! the spans.
+
+mod query;
+
+pub use query::{MAX_STATEMENT_LEN, QueryGuard, QueryLogger, Reveal, query};
+
+use crate::deadline::{self, Deadline};
+use crate::meter::GLOBAL_METER;
+use opentelemetry::metrics::Histogram;
+use opentelemetry::trace::SpanKind;
+use opentelemetry::{Key, KeyValue, Value};
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::LazyLock;
+use std::time::Instant;
+use tracing::field::Empty;
+use tracing::{Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+static OPERATION_DURATION: LazyLock<Histogram<f64>> = LazyLock::new(|| {
+    GLOBAL_METER
+        .f64_histogram("starlight.operation.duration")
+        .with_description("Duration of operations run through instrument::op")
+        .with_unit("s")
+        .build()
+});
+
+/// Why an [`Operation`] failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OperationError<E> {
+    /// The operation returned an error.
+    Failed(E),
+    /// The request deadline passed first; the operation was dropped.
+    DeadlineExceeded,
+}
+
+impl<E> OperationError<E> {
+    /// The error of the operation, `None` when it ran out of time.
+    pub fn into_inner(self) -> Option<E> {
+        match self {
+            OperationError::Failed(err) => Some(err),
+            OperationError::DeadlineExceeded => None,
+        }
+    }
+}
+
+impl<E: Display> Display for OperationError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OperationError::Failed(err) => err.fmt(f),
+            OperationError::DeadlineExceeded => f.write_str("request deadline exceeded"),
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for OperationError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            OperationError::Failed(err) => Some(err),
+            OperationError::DeadlineExceeded => None,
+        }
+    }
+}
+
+/// A span for one call to a dependency, started by [`Operation::run`].
+///
+/// The span is a client span named `name` under the parent, carries the attributes,
+/// and is marked as an error when the operation returns `Err`. The duration is also
+/// recorded on `starlight.operation.duration`. Operations inside a handler behind
+/// [`DeadlineLayer`](crate::deadline::DeadlineLayer) are dropped when the request
+/// deadline passes.
+#[derive(Debug, Clone)]
+pub struct Operation {
+    parent: Span,
+    name: String,
+    kind: SpanKind,
+    attributes: Vec<KeyValue>,
+    deadline: Option<Deadline>,
+}
+
+impl Operation {
+    /// An operation under the current span.
+    pub fn new(name: impl Into<String>) -> Self {
+        Operation::child_of(&Span::current(), name)
+    }
+
+    pub fn child_of(parent: &Span, name: impl Into<String>) -> Self {
+        Operation {
+            parent: parent.clone(),
+            name: name.into(),
+            kind: SpanKind::Client,
+            attributes: Vec::new(),
+            deadline: deadline::current(),
+        }
+    }
+
+    pub fn with_attribute(mut self, key: impl Into<Key>, value: impl Into<Value>) -> Self {
+        self.attributes.push(KeyValue::new(key, value));
+        self
+    }
+
+    pub fn with_attributes(mut self, attributes: &[(&'static str, &str)]) -> Self {
+        for (key, value) in attributes {
+            self.attributes.push(KeyValue::new(*key, value.to_string()));
+        }
+        self
+    }
+
+    /// Defaults to a client span.
+    pub fn with_kind(mut self, kind: SpanKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Replaces the deadline of the request being handled, e.g. for an operation
+    /// spawned outside of it.
+    pub fn with_deadline(mut self, deadline: Option<Deadline>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    pub async fn run<F, T, E>(self, future: F) -> Result<T, OperationError<E>>
+    where
+        F: Future<Output = Result<T, E>>,
+        E: Display,
+    {
+        let kind = match self.kind {
+            SpanKind::Client => "client",
+            SpanKind::Server => "server",
+            SpanKind::Producer => "producer",
+            SpanKind::Consumer => "consumer",
+            SpanKind::Internal => "internal",
+        };
+        let span = info_span!(
+            parent: &self.parent,
+            "operation",
+            otel.name = %self.name,
+            otel.kind = kind,
+            otel.status_code = Empty,
+            otel.status_description = Empty,
+        );
+        for attribute in &self.attributes {
+            span.set_attribute(attribute.key.clone(), attribute.value.clone());
+        }
+
+        let started = Instant::now();
+        let result = async {
+            match self.deadline {
+                Some(deadline) => match tokio::time::timeout_at(deadline.instant(), future).await {
+                    Ok(result) => result.map_err(OperationError::Failed),
+                    Err(_) => Err(OperationError::DeadlineExceeded),
+                },
+                None => future.await.map_err(OperationError::Failed),
+            }
+        }
+        .instrument(span.clone())
+        .await;
+
+        let mut labels = vec![KeyValue::new("operation.name", self.name)];
+        if let Err(err) = &result {
+            let error_type = match err {
+                OperationError::Failed(_) => std::any::type_name::<E>(),
+                OperationError::DeadlineExceeded => "deadline_exceeded",
+            };
+            span.record("otel.status_code", "ERROR");
+            span.record("otel.status_description", tracing::field::display(err));
+            span.set_attribute("error.type", error_type);
+            labels.push(KeyValue::new("error.type", error_type));
+        }
+        OPERATION_DURATION.record(started.elapsed().as_secs_f64(), &labels);
+        result
+    }
+}
+
+/// Runs `future` as an [`Operation`] named `name` under the current span.
+pub async fn op<F, T, E>(name: &str, attributes: &[(&'static str, &str)], future: F) -> Result<T, OperationError<E>>
+where
+    F: Future<Output = Result<T, E>>,
+    E: Display,
+{
+    Operation::new(name).with_attributes(attributes).run(future).await
+}
+
+/// Starts [`Operation`]s under a span, usually the request span.
+pub trait SpanExt {
+    fn child(&self, name: &str, attributes: &[(&'static str, &str)]) -> Operation;
+}
+
+impl SpanExt for Span {
+    fn child(&self, name: &str, attributes: &[(&'static str, &str)]) -> Operation {
+        Operation::child_of(self, name).with_attributes(attributes)
+    }
+}
@@ -0,0 +1,306 @@
+use crate::meter::GLOBAL_METER;
+use opentelemetry::metrics::Histogram;
+use opentelemetry::{Key, KeyValue, Value};
+use std::fmt::Display;
+use std::sync::LazyLock;
+use std::time::Instant;
+use tracing::Span;
+use tracing::field::Empty;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Statements longer than this many characters are cut in `db.statement`.
+pub const MAX_STATEMENT_LEN: usize = 2048;
+
+static QUERY_DURATION: LazyLock<Histogram<f64>> = LazyLock::new(|| {
+    GLOBAL_METER
+        .f64_histogram("db.client.operation.duration")
+        .with_description("Duration of queries logged through instrument::query")
+        .with_unit("s")
+        .build()
+});
+
+/// Which bound parameter to log and how much of it, by its position in the
+/// parameters given to [`query`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reveal {
+    /// The whole value.
+    Full(usize),
+    /// Only the last characters of the value, e.g. of a card number.
+    Last(usize, usize),
+}
+
+impl Reveal {
+    fn position(&self) -> usize {
+        match self {
+            Reveal::Full(position) | Reveal::Last(position, _) => *position,
+        }
+    }
+
+    fn apply(&self, value: &dyn Display) -> String {
+        let value = value.to_string();
+        match self {
+            Reveal::Full(_) => value,
+            Reveal::Last(_, count) => {
+                let skip = value.chars().count().saturating_sub(*count);
+                format!("…{}", value.chars().skip(skip).collect::<String>())
+            }
+        }
+    }
+}
+
+/// Logs queries as child spans, whichever database client runs them.
+///
+/// The span is a client span named `span_name` with the statement in `db.statement`,
+/// its literals replaced by `?`, comments removed and cut at [`MAX_STATEMENT_LEN`], and
+/// the number of bound parameters in `db.query.parameter_count`. Their values are left
+/// out unless [`QueryGuard::log_params`] reveals some.
+pub trait QueryLogger {
+    fn query<'a>(&self, span_name: &str, sql: &str, params: &'a [&'a dyn Display]) -> QueryGuard<'a>;
+}
+
+impl QueryLogger for Span {
+    fn query<'a>(&self, span_name: &str, sql: &str, params: &'a [&'a dyn Display]) -> QueryGuard<'a> {
+        let span = info_span!(
+            parent: self,
+            "query",
+            otel.name = %span_name,
+            otel.kind = "client",
+            otel.status_code = Empty,
+            otel.status_description = Empty,
+        );
+        span.set_attribute("db.statement", normalize(sql));
+        span.set_attribute("db.query.parameter_count", params.len() as i64);
+        QueryGuard {
+            span,
+            name: span_name.to_owned(),
+            params,
+            started: Instant::now(),
+            error_type: None,
+        }
+    }
+}
+
+/// Logs a query under the current span, see [`QueryLogger`].
+///
+/// ```ignore
+/// let params: [&dyn Display; 3] = [&name, &email, &age];
+/// let guard = query("db.insert_user", sql, &params).log_params(&[Reveal::Full(2)]);
+/// let done = sqlx::query(sql).bind(&name).bind(&email).bind(age)
+///     .execute(&pool)
+///     .instrument(guard.span().clone())
+///     .await?;
+/// guard.rows_affected(done.rows_affected());
+/// ```
+pub fn query<'a>(span_name: &str, sql: &str, params: &'a [&'a dyn Display]) -> QueryGuard<'a> {
+    Span::current().query(span_name, sql, params)
+}
+
+/// The span of a query, ended when dropped. The duration is also recorded on
+/// `db.client.operation.duration`.
+pub struct QueryGuard<'a> {
+    span: Span,
+    name: String,
+    params: &'a [&'a dyn Display],
+    started: Instant,
+    error_type: Option<&'static str>,
+}
+
+impl std::fmt::Debug for QueryGuard<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QueryGuard")
+            .field("span", &self.span)
+            .field("name", &self.name)
+            .field("params", &self.params.len())
+            .finish()
+    }
+}
+
+impl QueryGuard<'_> {
+    /// The query span, to instrument the future running the query with.
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    pub fn with_attribute(self, key: impl Into<Key>, value: impl Into<Value>) -> Self {
+        self.span.set_attribute(key, value);
+        self
+    }
+
+    /// Logs the parameters at the given positions as `db.query.parameter.<position>`;
+    /// the others are never logged. Positions past the parameters are ignored.
+    pub fn log_params(self, reveal: &[Reveal]) -> Self {
+        for reveal in reveal {
+            if let Some(value) = self.params.get(reveal.position()) {
+                self.span.set_attribute(
+                    format!("db.query.parameter.{}", reveal.position()),
+                    reveal.apply(*value),
+                );
+            }
+        }
+        self
+    }
+
+    pub fn rows_affected(&self, rows: u64) {
+        self.span.set_attribute("db.response.rows_affected", rows as i64);
+    }
+
+    /// Marks the query as failed with `err`.
+    pub fn error<E: Display>(&mut self, err: &E) {
+        let error_type = std::any::type_name::<E>();
+        self.span.record("otel.status_code", "ERROR");
+        self.span.record("otel.status_description", tracing::field::display(err));
+        self.span.set_attribute("error.type", error_type);
+        self.error_type = Some(error_type);
+    }
+}
+
+impl Drop for QueryGuard<'_> {
+    fn drop(&mut self) {
+        let mut labels = vec![KeyValue::new("operation.name", self.name.clone())];
+        if let Some(error_type) = self.error_type {
+            labels.push(KeyValue::new("error.type", error_type));
+        }
+        QUERY_DURATION.record(self.started.elapsed().as_secs_f64(), &labels);
+    }
+}
+
+/// `sql` with its string and number literals replaced by `?`, comments removed,
+/// whitespace collapsed and cut at [`MAX_STATEMENT_LEN`] characters. Placeholders such
+/// as `$1`, `?` or `:name` and quoted identifiers are kept.
+fn normalize(sql: &str) -> String {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut out = String::with_capacity(sql.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let previous = out.chars().last();
+        match c {
+            '\'' => {
+                // Prefixed strings such as E'..' or X'..'.
+                if out.ends_with(['E', 'e', 'N', 'n', 'X', 'x', 'B', 'b'])
+                    && !out[..out.len() - 1].ends_with(is_identifier)
+                {
+                    out.pop();
+                }
+                i = skip_quoted(&chars, i + 1, '\'');
+                out.push('?');
+            }
+            '"' | '`' => {
+                let end = skip_quoted(&chars, i + 1, c);
+                out.extend(&chars[i..end]);
+                i = end;
+            }
+            '$' if chars
+                .get(i + 1)
+                .is_some_and(|next| *next == '$' || next.is_alphabetic()) =>
+            {
+                match dollar_quoted(&chars, i) {
+                    Some(end) => {
+                        out.push('?');
+                        i = end;
+                    }
+                    None => {
+                        out.push(c);
+                        i += 1;
+                    }
+                }
+            }
+            '-' if chars.get(i + 1) == Some(&'-') => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                push_space(&mut out);
+            }
+            '/' if chars.get(i + 1) == Some(&'*') => {
+                i += 2;
+                while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                    i += 1;
+                }
+                i = (i + 2).min(chars.len());
+                push_space(&mut out);
+            }
+            c if c.is_ascii_digit() && !previous.is_some_and(|p| is_identifier(p) || p == '$') => {
+                i += 1;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '.') {
+                    // The sign of an exponent, as in 1e-5.
+                    if matches!(chars[i], 'e' | 'E') && matches!(chars.get(i + 1), Some('+' | '-')) {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                out.push('?');
+            }
+            c if c.is_whitespace() => {
+                push_space(&mut out);
+                i += 1;
+            }
+            c => {
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+    let out = out.trim();
+    match out.char_indices().nth(MAX_STATEMENT_LEN) {
+        Some((end, _)) => format!("{}...", &out[..end]),
+        None => out.to_owned(),
+    }
+}
+
+fn is_identifier(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn push_space(out: &mut String) {
+    if !out.is_empty() && !out.ends_with(' ') {
+        out.push(' ');
+    }
+}
+
+/// The index after the quote closing the quoted text starting at `start`, where the
+/// quote is escaped by doubling it or by a backslash.
+fn skip_quoted(chars: &[char], mut start: usize, quote: char) -> usize {
+    while start < chars.len() {
+        if chars[start] == '\\' {
+            start += 2;
+        } else if chars[start] == quote {
+            if chars.get(start + 1) != Some(&quote) {
+                return start + 1;
+            }
+            start += 2;
+        } else {
+            start += 1;
+        }
+    }
+    chars.len()
+}
+
+/// The index after a `$tag$..$tag$` string starting at `start`, `None` when the `$`
+/// does not open one.
+fn dollar_quoted(chars: &[char], start: usize) -> Option<usize> {
+    let tag_len = chars[start + 1..].iter().position(|c| *c == '$')? + 2;
+    let tag = &chars[start..start + tag_len];
+    if !tag[1..tag_len - 1].iter().all(|c| is_identifier(*c)) {
+        return None;
+    }
+    let body = start + tag_len;
+    let end = (body..=chars.len().saturating_sub(tag_len)).find(|i| chars[*i..].starts_with(tag))?;
+    Some(end + tag_len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_literals_and_keeps_placeholders() {
+        assert_eq!(
+            normalize("SELECT * FROM \"user's\" WHERE id = $1 AND t2.name = :name -- by 'x'\n LIMIT 10"),
+            "SELECT * FROM \"user's\" WHERE id = $1 AND t2.name = :name LIMIT ?"
+        );
+        assert_eq!(
+            normalize("UPDATE t SET a = E'it\\'s', b = 'O''Brien', c = $$raw$$, d = -1.5e-3 /* why */"),
+            "UPDATE t SET a = ?, b = ?, c = ?, d = -?"
+        );
+    }
+}
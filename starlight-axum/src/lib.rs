@@ -4,6 +4,39 @@ pub mod tracer;
 pub mod resource;
 pub mod oltp;
 pub mod middleware;
+pub mod admin;
+#[cfg(feature = "alloc")]
+pub mod alloc;
+pub mod attrs;
+pub mod client;
+pub mod config;
+pub mod crash;
+pub mod deadline;
+pub mod egress;
+pub mod env;
+pub mod error;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod health;
+pub mod instrument;
+#[cfg(feature = "multipart")]
+pub mod multipart;
+pub mod negotiate;
+pub mod pagination;
+#[cfg(feature = "profiling")]
+pub mod profiling;
+pub mod rejection;
+pub mod serve;
+pub mod service;
+pub mod slo;
+pub mod stack;
+pub mod staticfiles;
+pub mod task;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod tls;
+#[cfg(feature = "ws")]
+pub mod ws;
 
 #[macro_use]
 extern crate tracing as internal_tracing;
@@ -13,12 +46,10 @@ pub use axum;
 pub use time;
 pub use tower;
 pub use tower_http;
+pub use starlight_tokio;
 
-pub(crate) fn get_env_or_panic(variable: &str) -> String {
-    std::env::var(variable).expect(format!("{} is not set", variable).as_str())
-}
-
-pub(crate) fn get_env_or_default(variable: &str, default: String) -> String {
-    std::env::var(variable).unwrap_or(default)
-}
+pub use health::InFlightTracker;
+pub use rejection::{Json, Path, Query};
+pub use serve::{Listener, ListenerConfig, serve_many, serve_tls};
+pub use service::{AxumService, MeterLifecycleObserver};
 
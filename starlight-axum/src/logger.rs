@@ -1,14 +1,27 @@
+use crate::env::{self, EnvError};
+use crate::oltp::{FailoverExporter, FailoverPolicy, HeadersConfig};
 use crate::resource::get_resource;
-use opentelemetry_otlp::{LogExporter, WithExportConfig};
 use opentelemetry_sdk::logs::SdkLoggerProvider;
-use std::fmt::Debug;
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::{self, Debug};
 use std::net::SocketAddr;
-use std::sync::OnceLock;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use tracing::field::{Field, Visit};
 use time::{OffsetDateTime, format_description};
 use time_tz::ToTimezone;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
 use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::Layer;
+use tracing_subscriber::filter::ParseError;
 use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::layer::Context;
 use tracing_subscriber::registry::LookupSpan;
 
 static SDK_LOGGER_PROVIDER: OnceLock<SdkLoggerProvider> = OnceLock::new();
@@ -18,14 +31,29 @@ pub fn get_logger_provider() -> &'static SdkLoggerProvider {
         .expect("Failed to get a logger provider")
 }
 
+pub(crate) fn try_get_logger_provider() -> Option<&'static SdkLoggerProvider> {
+    SDK_LOGGER_PROVIDER.get()
+}
+
 pub fn get_or_init_logger_provider(oltp_grpc_url: &str) -> SdkLoggerProvider {
+    get_or_init_logger_provider_with_failover(&[oltp_grpc_url.to_owned()], FailoverPolicy::default())
+}
+
+/// Same as [`get_or_init_logger_provider`], failing over between `endpoints` in order.
+pub fn get_or_init_logger_provider_with_failover(endpoints: &[String], policy: FailoverPolicy) -> SdkLoggerProvider {
+    get_or_init_logger_provider_with_headers(endpoints, policy, &HeadersConfig::default())
+}
+
+/// Same as [`get_or_init_logger_provider_with_failover`], sending `headers` to the collector.
+pub fn get_or_init_logger_provider_with_headers(
+    endpoints: &[String],
+    policy: FailoverPolicy,
+    headers: &HeadersConfig,
+) -> SdkLoggerProvider {
     SDK_LOGGER_PROVIDER
         .get_or_init(|| {
-            let exporter = LogExporter::builder()
-                .with_tonic()
-                .with_endpoint(oltp_grpc_url)
-                .build()
-                .expect("Failed to create LogExporter");
+            let exporter =
+                FailoverExporter::logs_with_headers(endpoints, policy, headers).expect("Failed to create LogExporter");
 
             SdkLoggerProvider::builder()
                 .with_batch_exporter(exporter)
@@ -142,6 +170,277 @@ where
     }
 }
 
+/// Where a log sink writes to.
+#[derive(Debug, Clone)]
+pub enum LogOutput {
+    Stdout,
+    Stderr,
+    File {
+        directory: PathBuf,
+        file_name_prefix: String,
+        rotation: Rotation,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// The colored [`CustomLogFormatter`] line format.
+    #[default]
+    Text,
+    Json,
+}
+
+/// One log destination. `filter` uses `RUST_LOG` directive syntax (e.g.
+/// `"access_log=info,sqlx=off"`) and is applied on top of the global filter;
+/// an empty filter lets every event through.
+#[derive(Debug, Clone)]
+pub struct SinkConfig {
+    pub filter: String,
+    pub output: LogOutput,
+    pub format: LogFormat,
+}
+
+/// The set of log sinks. An event is written to every sink whose filter accepts it.
+#[derive(Debug, Clone)]
+pub struct LoggerConfig {
+    sinks: Vec<SinkConfig>,
+    recent_errors: usize,
+}
+
+type BoxedLayer<S> = Box<dyn Layer<S> + Send + Sync + 'static>;
+
+/// Why a [`LoggerConfig`] could not be built or turned into layers.
+#[derive(Debug)]
+pub enum LoggerError {
+    /// `CARGO_PKG_NAME`, which names the file of the default sinks, is not set.
+    Env(EnvError),
+    /// The filter of a sink is not valid `RUST_LOG` directive syntax.
+    InvalidFilter { filter: String, source: ParseError },
+}
+
+impl fmt::Display for LoggerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoggerError::Env(err) => write!(f, "cannot name the log file: {}", err),
+            LoggerError::InvalidFilter { filter, source } => {
+                write!(f, "invalid sink filter {:?}: {}", filter, source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LoggerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoggerError::Env(err) => Some(err),
+            LoggerError::InvalidFilter { source, .. } => Some(source),
+        }
+    }
+}
+
+impl LoggerConfig {
+    /// A configuration without any sink. There is no `Default`, since the default sinks
+    /// are those of [`LoggerConfig::from_env`], which can fail.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        LoggerConfig {
+            sinks: Vec::new(),
+            recent_errors: 0,
+        }
+    }
+
+    /// A minutely rolling file under `.logs` named after `CARGO_PKG_NAME`, plus stdout,
+    /// both in the text format.
+    pub fn from_env() -> Result<Self, LoggerError> {
+        let file_name_prefix = env::required("CARGO_PKG_NAME").map_err(LoggerError::Env)?;
+        Ok(LoggerConfig::new()
+            .with_sink(SinkConfig {
+                filter: String::new(),
+                output: LogOutput::File {
+                    directory: PathBuf::from(".logs"),
+                    file_name_prefix,
+                    rotation: Rotation::MINUTELY,
+                },
+                format: LogFormat::Text,
+            })
+            .with_sink(SinkConfig {
+                filter: String::new(),
+                output: LogOutput::Stdout,
+                format: LogFormat::Text,
+            }))
+    }
+
+    pub fn with_sink(mut self, sink: SinkConfig) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    /// Keep the last `capacity` WARN and ERROR events for [`recent_errors`].
+    pub fn with_recent_errors(mut self, capacity: usize) -> Self {
+        self.recent_errors = capacity;
+        self
+    }
+
+    pub fn sinks(&self) -> &[SinkConfig] {
+        &self.sinks
+    }
+
+    /// Build one `tracing_subscriber` layer per sink. File sinks write through a
+    /// non-blocking worker; keep the returned guards alive to flush them on exit.
+    /// Fails on the first sink whose filter does not parse, before any file is opened.
+    pub fn build_layers<S>(&self) -> Result<(Vec<BoxedLayer<S>>, Vec<WorkerGuard>), LoggerError>
+    where
+        S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let mut filters = Vec::with_capacity(self.sinks.len());
+        for sink in &self.sinks {
+            if sink.filter.is_empty() {
+                filters.push(None);
+                continue;
+            }
+            let filter = EnvFilter::try_new(&sink.filter).map_err(|source| LoggerError::InvalidFilter {
+                filter: sink.filter.clone(),
+                source,
+            })?;
+            filters.push(Some(filter));
+        }
+
+        let mut layers = Vec::with_capacity(self.sinks.len());
+        let mut guards = Vec::new();
+
+        for (sink, filter) in self.sinks.iter().zip(filters) {
+            let writer = match &sink.output {
+                LogOutput::Stdout => BoxMakeWriter::new(std::io::stdout),
+                LogOutput::Stderr => BoxMakeWriter::new(std::io::stderr),
+                LogOutput::File {
+                    directory,
+                    file_name_prefix,
+                    rotation,
+                } => {
+                    let appender = RollingFileAppender::new(rotation.clone(), directory, file_name_prefix);
+                    let (writer, guard) = tracing_appender::non_blocking(appender);
+                    guards.push(guard);
+                    BoxMakeWriter::new(writer)
+                }
+            };
+
+            let layer = tracing_subscriber::fmt::layer().with_writer(writer);
+            let layer: BoxedLayer<S> = match (sink.format, filter) {
+                (LogFormat::Text, None) => layer.event_format(CustomLogFormatter).boxed(),
+                (LogFormat::Text, Some(filter)) => layer.event_format(CustomLogFormatter).with_filter(filter).boxed(),
+                (LogFormat::Json, None) => layer.json().boxed(),
+                (LogFormat::Json, Some(filter)) => layer.json().with_filter(filter).boxed(),
+            };
+            layers.push(layer);
+        }
+        if self.recent_errors > 0 {
+            layers.push(RecentErrorsLayer::new(self.recent_errors).boxed());
+        }
+        #[cfg(feature = "profiling")]
+        layers.push(crate::profiling::ProfiledSpans::new().boxed());
+
+        Ok((layers, guards))
+    }
+}
+
+/// A WARN or ERROR event kept by [`RecentErrorsLayer`].
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordedEvent {
+    #[serde(with = "time::serde::rfc3339")]
+    pub timestamp: OffsetDateTime,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+    pub trace_id: Option<String>,
+    pub fields: BTreeMap<String, String>,
+}
+
+struct RecentErrors {
+    capacity: AtomicUsize,
+    entries: Mutex<VecDeque<RecordedEvent>>,
+}
+
+static RECENT_ERRORS: RecentErrors = RecentErrors {
+    capacity: AtomicUsize::new(0),
+    entries: Mutex::new(VecDeque::new()),
+};
+
+/// The events kept by [`RecentErrorsLayer`], newest first.
+pub fn recent_errors() -> Vec<RecordedEvent> {
+    let entries = RECENT_ERRORS.entries.lock().unwrap_or_else(|e| e.into_inner());
+    entries.iter().rev().cloned().collect()
+}
+
+/// Keeps the last `capacity` WARN and ERROR events in memory for [`recent_errors`].
+///
+/// Logging never waits on the buffer: an event that arrives while the buffer is
+/// locked by another thread is dropped rather than recorded.
+#[derive(Debug)]
+pub struct RecentErrorsLayer;
+
+impl RecentErrorsLayer {
+    pub fn new(capacity: usize) -> Self {
+        RECENT_ERRORS.capacity.store(capacity, Ordering::Relaxed);
+        RecentErrorsLayer
+    }
+}
+
+#[derive(Default)]
+struct EventFields {
+    message: String,
+    fields: BTreeMap<String, String>,
+}
+
+impl Visit for EventFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_owned();
+        } else {
+            self.fields.insert(field.name().to_owned(), value.to_owned());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            self.fields.insert(field.name().to_owned(), format!("{:?}", value));
+        }
+    }
+}
+
+impl<S: tracing::Subscriber> Layer<S> for RecentErrorsLayer {
+    fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+        use opentelemetry::trace::TraceContextExt;
+
+        let level = *event.metadata().level();
+        let capacity = RECENT_ERRORS.capacity.load(Ordering::Relaxed);
+        if level > tracing::Level::WARN || capacity == 0 {
+            return;
+        }
+
+        let mut fields = EventFields::default();
+        event.record(&mut fields);
+        let span_context = tracing::Span::current().context().span().span_context().clone();
+        let recorded = RecordedEvent {
+            timestamp: OffsetDateTime::now_utc(),
+            level: level.to_string(),
+            target: event.metadata().target().to_owned(),
+            message: fields.message,
+            trace_id: span_context.is_valid().then(|| span_context.trace_id().to_string()),
+            fields: fields.fields,
+        };
+
+        let Ok(mut entries) = RECENT_ERRORS.entries.try_lock() else {
+            return;
+        };
+        entries.push_back(recorded);
+        while entries.len() > capacity {
+            entries.pop_front();
+        }
+    }
+}
+
 use axum::body::Body;
 use axum::body::Bytes;
 use axum::extract::Request;
@@ -248,3 +547,56 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn sinks_receive_only_their_targets() {
+        let directory = std::env::temp_dir().join(format!("starlight-sinks-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        let file = |name: &str, filter: &str| SinkConfig {
+            filter: filter.to_owned(),
+            output: LogOutput::File {
+                directory: directory.clone(),
+                file_name_prefix: name.to_owned(),
+                rotation: Rotation::NEVER,
+            },
+            format: LogFormat::Json,
+        };
+        let config = LoggerConfig::new()
+            .with_sink(file("access.log", "access_log=info"))
+            .with_sink(file("app.log", "app=debug"));
+
+        let (layers, guards) = config.build_layers().unwrap();
+        let subscriber = tracing_subscriber::registry().with(layers);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(target: "access_log", "GET /users 200");
+            tracing::debug!(target: "app", "user cache warmed");
+            tracing::info!(target: "sqlx", "select 1");
+        });
+        drop(guards);
+
+        let access = std::fs::read_to_string(directory.join("access.log")).unwrap();
+        let app = std::fs::read_to_string(directory.join("app.log")).unwrap();
+        assert!(access.contains("GET /users 200"));
+        assert!(!access.contains("user cache warmed"));
+        assert!(app.contains("user cache warmed"));
+        assert!(!app.contains("GET /users 200"));
+        assert!(!access.contains("select 1") && !app.contains("select 1"));
+    }
+
+    #[test]
+    fn invalid_sink_filters_are_reported() {
+        let config = LoggerConfig::new().with_sink(SinkConfig {
+            filter: "access_log=[".to_owned(),
+            output: LogOutput::Stdout,
+            format: LogFormat::Text,
+        });
+
+        let err = config.build_layers::<tracing_subscriber::Registry>().err().unwrap();
+        assert!(matches!(&err, LoggerError::InvalidFilter { filter, .. } if filter == "access_log=["));
+    }
+}
@@ -1,12 +1,165 @@
-use crate::get_env_or_panic;
-use crate::resource::get_resource;
+mod observations;
+
+pub use observations::{FinalObservations, shutdown_meter_provider};
+
+use crate::config::{ConfigError, TelemetryConfig};
+use crate::oltp::{FailoverExporter, FailoverPolicy, HeadersConfig};
+use crate::resource::{get_resource, service_from_env};
 use opentelemetry::metrics::Meter;
 use opentelemetry::{InstrumentationScope, global};
-use opentelemetry_otlp::{MetricExporter, WithExportConfig};
+use opentelemetry_sdk::metrics::exporter::PushMetricExporter;
 use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
+use std::fmt;
+use std::str::FromStr;
 use std::sync::{LazyLock, OnceLock};
 use std::time::Duration;
 
+/// How exported sums and histograms relate to the previous export, as the
+/// `OTEL_EXPORTER_OTLP_METRICS_TEMPORALITY_PREFERENCE` of the OTLP exporter spec.
+///
+/// The preference applies per instrument kind: `Delta` makes counters, histograms and
+/// gauges delta and keeps up-down counters cumulative; `LowMemory` makes only
+/// synchronous counters and histograms delta.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Temporality {
+    #[default]
+    Cumulative,
+    Delta,
+    LowMemory,
+}
+
+impl FromStr for Temporality {
+    type Err = String;
+
+    /// The spec values, in any case: `cumulative`, `delta` or `lowmemory`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "cumulative" => Ok(Temporality::Cumulative),
+            "delta" => Ok(Temporality::Delta),
+            "lowmemory" => Ok(Temporality::LowMemory),
+            _ => Err(format!("expected cumulative, delta or lowmemory, got {:?}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Temporality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Temporality::Cumulative => "cumulative",
+            Temporality::Delta => "delta",
+            Temporality::LowMemory => "lowmemory",
+        })
+    }
+}
+
+impl From<Temporality> for opentelemetry_sdk::metrics::Temporality {
+    fn from(temporality: Temporality) -> Self {
+        match temporality {
+            Temporality::Cumulative => opentelemetry_sdk::metrics::Temporality::Cumulative,
+            Temporality::Delta => opentelemetry_sdk::metrics::Temporality::Delta,
+            Temporality::LowMemory => opentelemetry_sdk::metrics::Temporality::LowMemory,
+        }
+    }
+}
+
+/// How metrics are exported: the temporality asked of the OTLP exporter, how often it
+/// exports and how long an export may take.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MeterConfig {
+    pub temporality: Temporality,
+    pub export_interval: Duration,
+    /// `None` leaves it to the exporter, which honors `OTEL_EXPORTER_OTLP_TIMEOUT`.
+    pub export_timeout: Option<Duration>,
+}
+
+impl Default for MeterConfig {
+    /// Cumulative, exported every 5 seconds.
+    fn default() -> Self {
+        MeterConfig {
+            temporality: Temporality::Cumulative,
+            export_interval: Duration::from_secs(5),
+            export_timeout: None,
+        }
+    }
+}
+
+impl MeterConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_temporality(mut self, temporality: Temporality) -> Self {
+        self.temporality = temporality;
+        self
+    }
+
+    pub fn with_export_interval(mut self, interval: Duration) -> Self {
+        self.export_interval = interval;
+        self
+    }
+
+    pub fn with_export_timeout(mut self, timeout: Duration) -> Self {
+        self.export_timeout = Some(timeout);
+        self
+    }
+
+    /// The settings of `config`, read from `OTEL_EXPORTER_OTLP_METRICS_TEMPORALITY_PREFERENCE`,
+    /// `OTEL_METRIC_EXPORT_INTERVAL` and `OTEL_METRIC_EXPORT_TIMEOUT` (milliseconds) by
+    /// [`TelemetryConfig::from_env`]. Every problem is reported, including an export
+    /// timeout longer than the interval, which would let exports pile up.
+    pub fn from_telemetry(config: &TelemetryConfig) -> Result<Self, Vec<ConfigError>> {
+        let mut errors = Vec::new();
+        let mut meter = MeterConfig::default();
+
+        if let Some(temporality) = &config.metrics_temporality {
+            match temporality.parse() {
+                Ok(temporality) => meter.temporality = temporality,
+                Err(message) => errors.push(ConfigError::new("metrics.temporality", message)),
+            }
+        }
+        let millis = |field: &'static str, value: &str, errors: &mut Vec<ConfigError>| match value.trim().parse() {
+            Ok(0) => {
+                errors.push(ConfigError::new(field, "must be greater than 0"));
+                None
+            }
+            Ok(millis) => Some(Duration::from_millis(millis)),
+            Err(_) => {
+                errors.push(ConfigError::new(field, format!("expected milliseconds, got {:?}", value)));
+                None
+            }
+        };
+        if let Some(interval) = &config.metric_export_interval
+            && let Some(interval) = millis("metrics.export_interval", interval, &mut errors)
+        {
+            meter.export_interval = interval;
+        }
+        if let Some(timeout) = &config.metric_export_timeout {
+            meter.export_timeout = millis("metrics.export_timeout", timeout, &mut errors);
+        }
+        if let Some(timeout) = meter.export_timeout
+            && timeout > meter.export_interval
+        {
+            errors.push(ConfigError::new(
+                "metrics.export_timeout",
+                format!(
+                    "{:?} is longer than the export interval of {:?}",
+                    timeout, meter.export_interval
+                ),
+            ));
+        }
+
+        if errors.is_empty() { Ok(meter) } else { Err(errors) }
+    }
+
+    /// A reader exporting to `exporter` at the export interval. The temporality is the
+    /// exporter's, as configured by [`FailoverExporter::metrics_with_config`].
+    pub fn periodic_reader<E: PushMetricExporter>(&self, exporter: E) -> PeriodicReader<E> {
+        PeriodicReader::builder(exporter)
+            .with_interval(self.export_interval)
+            .build()
+    }
+}
+
 static SDK_METER_PROVIDER: OnceLock<SdkMeterProvider> = OnceLock::new();
 
 pub fn get_meter_provider() -> &'static SdkMeterProvider {
@@ -15,22 +168,42 @@ pub fn get_meter_provider() -> &'static SdkMeterProvider {
         .expect("failed to get meter provider")
 }
 
+pub(crate) fn try_get_meter_provider() -> Option<&'static SdkMeterProvider> {
+    SDK_METER_PROVIDER.get()
+}
+
 pub fn get_or_init_meter_provider(oltp_grpc_url: &str) -> SdkMeterProvider {
+    get_or_init_meter_provider_with_failover(&[oltp_grpc_url.to_owned()], FailoverPolicy::default())
+}
+
+/// Same as [`get_or_init_meter_provider`], failing over between `endpoints` in order.
+pub fn get_or_init_meter_provider_with_failover(endpoints: &[String], policy: FailoverPolicy) -> SdkMeterProvider {
+    get_or_init_meter_provider_with_config(endpoints, policy, &MeterConfig::default())
+}
+
+/// Same as [`get_or_init_meter_provider_with_failover`], exporting as `config` says.
+pub fn get_or_init_meter_provider_with_config(
+    endpoints: &[String],
+    policy: FailoverPolicy,
+    config: &MeterConfig,
+) -> SdkMeterProvider {
+    get_or_init_meter_provider_with_headers(endpoints, policy, config, &HeadersConfig::default())
+}
+
+/// Same as [`get_or_init_meter_provider_with_config`], sending `headers` to the collector.
+pub fn get_or_init_meter_provider_with_headers(
+    endpoints: &[String],
+    policy: FailoverPolicy,
+    config: &MeterConfig,
+    headers: &HeadersConfig,
+) -> SdkMeterProvider {
     SDK_METER_PROVIDER
         .get_or_init(|| {
-            let metric_exporter = MetricExporter::builder()
-                .with_tonic()
-                .with_endpoint(oltp_grpc_url)
-                .with_temporality(opentelemetry_sdk::metrics::Temporality::default())
-                .build()
+            let metric_exporter = FailoverExporter::metrics_with_headers(endpoints, policy, config, headers)
                 .expect("failed to create metric exporter");
 
             SdkMeterProvider::builder()
-                .with_reader(
-                    PeriodicReader::builder(metric_exporter)
-                        .with_interval(Duration::from_secs(5))
-                        .build(),
-                )
+                .with_reader(config.periodic_reader(metric_exporter))
                 .with_resource(get_resource())
                 .build()
         })
@@ -38,9 +211,8 @@ pub fn get_or_init_meter_provider(oltp_grpc_url: &str) -> SdkMeterProvider {
 }
 
 pub static GLOBAL_METER: LazyLock<Meter> = LazyLock::new(|| {
-    let scope = InstrumentationScope::builder(get_env_or_panic("CARGO_PKG_NAME"))
-        .with_version(get_env_or_panic("CARGO_PKG_VERSION"))
-        .build();
+    let (name, version, _) = service_from_env().unwrap_or_else(|errors| panic!("{}", errors));
+    let scope = InstrumentationScope::builder(name).with_version(version).build();
     global::meter_with_scope(scope)
 });
 
@@ -133,3 +305,102 @@ predefine_metrics! {
         unit: "seconds"
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::metrics::MeterProvider;
+    use opentelemetry_sdk::metrics::InMemoryMetricExporterBuilder;
+    use opentelemetry_sdk::metrics::data::{AggregatedMetrics, MetricData};
+
+    /// The value of the `requests` counter, or of the `in_flight` up-down counter, in each
+    /// export, recording `values` one export cycle apart.
+    fn exported(config: MeterConfig, up_down: bool, values: &[i64]) -> Vec<i64> {
+        let exporter = InMemoryMetricExporterBuilder::new()
+            .with_temporality(config.temporality.into())
+            .build();
+        let provider = SdkMeterProvider::builder()
+            .with_reader(config.periodic_reader(exporter.clone()))
+            .build();
+        let meter = provider.meter("test");
+        let counter = meter.u64_counter("requests").build();
+        let up_down_counter = meter.i64_up_down_counter("in_flight").build();
+        for value in values {
+            if up_down {
+                up_down_counter.add(*value, &[]);
+            } else {
+                counter.add(*value as u64, &[]);
+            }
+            provider.force_flush().unwrap();
+        }
+
+        let exports = exporter.get_finished_metrics().unwrap();
+        let name = if up_down { "in_flight" } else { "requests" };
+        exports
+            .iter()
+            .flat_map(|export| export.scope_metrics().flat_map(|scope| scope.metrics()))
+            .filter(|metric| metric.name() == name)
+            .filter_map(|metric| match metric.data() {
+                AggregatedMetrics::U64(MetricData::Sum(sum)) => sum.data_points().map(|p| p.value() as i64).next(),
+                AggregatedMetrics::I64(MetricData::Sum(sum)) => sum.data_points().map(|p| p.value()).next(),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn delta_exports_what_was_recorded_since_the_last_export() {
+        let cumulative = MeterConfig::new();
+        let delta = MeterConfig::new().with_temporality(Temporality::Delta);
+        assert_eq!(exported(cumulative, false, &[2, 3]), [2, 5]);
+        assert_eq!(exported(delta, false, &[2, 3]), [2, 3]);
+        // Up-down counters stay cumulative, as the spec asks.
+        assert_eq!(exported(delta, true, &[2, -1]), [2, 1]);
+        let low_memory = MeterConfig::new().with_temporality(Temporality::LowMemory);
+        assert_eq!(exported(low_memory, false, &[2, 3]), [2, 3]);
+    }
+
+    #[test]
+    fn reads_the_settings_of_the_telemetry_config() {
+        let config = TelemetryConfig {
+            metrics_temporality: Some("Delta".to_owned()),
+            metric_export_interval: Some("60000".to_owned()),
+            metric_export_timeout: Some("30000".to_owned()),
+            ..TelemetryConfig::default()
+        };
+        assert_eq!(
+            MeterConfig::from_telemetry(&config),
+            Ok(MeterConfig {
+                temporality: Temporality::Delta,
+                export_interval: Duration::from_secs(60),
+                export_timeout: Some(Duration::from_secs(30)),
+            })
+        );
+        assert_eq!(
+            MeterConfig::from_telemetry(&TelemetryConfig::default()),
+            Ok(MeterConfig::default())
+        );
+    }
+
+    #[test]
+    fn reports_invalid_and_conflicting_settings() {
+        let config = TelemetryConfig {
+            metrics_temporality: Some("deltas".to_owned()),
+            metric_export_interval: Some("1000".to_owned()),
+            metric_export_timeout: Some("10000".to_owned()),
+            ..TelemetryConfig::default()
+        };
+        let errors: Vec<String> = MeterConfig::from_telemetry(&config)
+            .unwrap_err()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                "metrics.temporality: expected cumulative, delta or lowmemory, got \"deltas\"",
+                "metrics.export_timeout: 10s is longer than the export interval of 1s",
+            ]
+        );
+    }
+}
@@ -0,0 +1,144 @@
+use crate::crash::catch_unwind;
+use opentelemetry_sdk::error::{OTelSdkError, OTelSdkResult};
+use opentelemetry_sdk::metrics::SdkMeterProvider;
+use std::fmt;
+use std::panic::AssertUnwindSafe;
+use std::sync::mpsc;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+type Observation = Box<dyn Fn() + Send + Sync>;
+
+/// Callbacks recording the last values of gauges when the service stops, such as a
+/// drained queue or no request left in flight, so the final export does not leave
+/// dashboards on the last value sampled while it was running.
+///
+/// [`shutdown_oltp`](crate::oltp::shutdown_oltp) and
+/// [`shutdown_oltp_before`](crate::oltp::shutdown_oltp_before) run those of
+/// [`FinalObservations::global`] before exporting one last time.
+///
+/// ```ignore
+/// use opentelemetry::global;
+/// use starlight_axum::meter::FinalObservations;
+///
+/// let depth = global::meter("jobs").u64_gauge("jobs.queue.depth").build();
+/// FinalObservations::global().register("jobs.queue.depth", move || depth.record(0, &[]));
+/// ```
+#[derive(Default)]
+pub struct FinalObservations {
+    observations: Mutex<Vec<(String, Observation)>>,
+}
+
+impl fmt::Debug for FinalObservations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let observations = self.observations.lock().unwrap_or_else(|e| e.into_inner());
+        let names: Vec<&str> = observations.iter().map(|(name, _)| name.as_str()).collect();
+        f.debug_struct("FinalObservations")
+            .field("observations", &names)
+            .finish()
+    }
+}
+
+impl FinalObservations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The observations run when the OTLP providers shut down.
+    pub fn global() -> &'static FinalObservations {
+        static GLOBAL: LazyLock<FinalObservations> = LazyLock::new(FinalObservations::new);
+        &GLOBAL
+    }
+
+    /// Adds `observation`, named for the logs, run after those registered before it.
+    pub fn register(&self, name: impl Into<String>, observation: impl Fn() + Send + Sync + 'static) {
+        let mut observations = self.observations.lock().unwrap_or_else(|e| e.into_inner());
+        observations.push((name.into(), Box::new(observation)));
+    }
+
+    /// Runs every observation; one that panics is logged and the others still run.
+    pub fn observe(&self) {
+        let observations = self.observations.lock().unwrap_or_else(|e| e.into_inner());
+        for (name, observation) in observations.iter() {
+            if catch_unwind(AssertUnwindSafe(observation)).is_err() {
+                warn!("final observation {} panicked", name);
+            }
+        }
+    }
+}
+
+/// Shuts `provider` down after recording `observations`: they run first, then the
+/// metrics are exported one last time and only then is the provider shut down. The
+/// export and the shutdown each give up after `timeout`.
+pub fn shutdown_meter_provider(
+    provider: &SdkMeterProvider,
+    observations: &FinalObservations,
+    timeout: Duration,
+) -> OTelSdkResult {
+    observations.observe();
+
+    let (flushed_tx, flushed_rx) = mpsc::channel();
+    let flushing = provider.clone();
+    std::thread::spawn(move || {
+        let _ = flushed_tx.send(flushing.force_flush());
+    });
+    let flushed = flushed_rx
+        .recv_timeout(timeout)
+        .unwrap_or(Err(OTelSdkError::Timeout(timeout)));
+    if let Err(err) = &flushed {
+        warn!("final metric export failed: {}", err);
+    }
+
+    provider.shutdown_with_timeout(timeout).and(flushed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::metrics::MeterProvider;
+    use opentelemetry_sdk::metrics::data::{AggregatedMetrics, MetricData};
+    use opentelemetry_sdk::metrics::{InMemoryMetricExporter, PeriodicReader};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    /// The values of the `queue.depth` gauge, export by export.
+    fn depths(exporter: &InMemoryMetricExporter) -> Vec<u64> {
+        let exports = exporter.get_finished_metrics().unwrap();
+        exports
+            .iter()
+            .flat_map(|export| export.scope_metrics().flat_map(|scope| scope.metrics()))
+            .filter(|metric| metric.name() == "queue.depth")
+            .filter_map(|metric| match metric.data() {
+                AggregatedMetrics::U64(MetricData::Gauge(gauge)) => gauge.data_points().map(|p| p.value()).next(),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn the_last_export_has_the_final_observations() {
+        let exporter = InMemoryMetricExporter::default();
+        let provider = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(exporter.clone()).build())
+            .build();
+        let depth = provider.meter("test").u64_gauge("queue.depth").build();
+        let observations = FinalObservations::new();
+        let observed = Arc::new(AtomicU64::new(0));
+        let final_depth = depth.clone();
+        let counted = observed.clone();
+        observations.register("queue.depth", move || {
+            counted.fetch_add(1, Ordering::SeqCst);
+            final_depth.record(0, &[]);
+        });
+        observations.register("broken", || panic!("the queue is gone"));
+
+        depth.record(7, &[]);
+        provider.force_flush().unwrap();
+        assert_eq!(depths(&exporter), [7]);
+        assert_eq!(observed.load(Ordering::SeqCst), 0);
+
+        shutdown_meter_provider(&provider, &observations, Duration::from_secs(5)).unwrap();
+        assert_eq!(observed.load(Ordering::SeqCst), 1);
+        assert_eq!(depths(&exporter).last(), Some(&0));
+    }
+}
@@ -1,11 +1,37 @@
+pub mod authz;
+pub mod body_capture;
+pub mod cache;
+pub mod compression;
+pub mod cors;
+pub mod etag;
+pub mod flags;
+pub mod hooks;
+pub mod idempotency;
+pub mod latency_budget;
+pub mod locale;
+pub mod maintenance;
+pub mod normalize_path;
+pub mod queue_time;
+pub mod rate_limit;
+#[cfg(feature = "schema")]
+pub mod schema;
+pub mod singleflight;
+pub mod slow;
+pub mod streaming;
+pub mod tenant;
+pub mod versioning;
+pub mod webhook;
+
 use crate::meter::GLOBAL_METER;
+use crate::middleware::tenant::TenantAttribute;
+use crate::middleware::versioning::ApiVersionAttribute;
 use axum::body::Bytes;
 use axum::extract::Request;
 use axum::http::{HeaderMap, HeaderName};
 use axum::response::Response;
-use opentelemetry::global;
+use opentelemetry::{KeyValue, global};
 use opentelemetry_http::HeaderExtractor;
-use opentelemetry_instrumentation_tower::HTTPMetricsLayer;
+use opentelemetry_instrumentation_tower::{HTTPMetricsLayer, NoOpExtractor, ResponseAttributeExtractor};
 use std::time::Duration;
 use tower::ServiceBuilder;
 use tower::layer::util::{Identity, Stack};
@@ -47,6 +73,40 @@ pub fn oltp_middleware() -> HTTPMetricsLayer {
         .expect("Failed to build HTTP metrics layer")
 }
 
+/// Like [`oltp_middleware`], suffixing the route label of responses negotiated by
+/// [`versioning::ApiVersionLayer`] with their version, see [`ApiVersionAttribute`].
+pub fn oltp_middleware_with_versioning() -> HTTPMetricsLayer<NoOpExtractor, ApiVersionAttribute> {
+    opentelemetry_instrumentation_tower::HTTPMetricsLayerBuilder::builder()
+        .with_meter(GLOBAL_METER.clone())
+        .with_response_extractor::<_, axum::body::Body>(ApiVersionAttribute)
+        .build()
+        .expect("Failed to build HTTP metrics layer")
+}
+
+/// The attributes [`oltp_middleware_with_attributes`] adds to the HTTP server metrics:
+/// the versioned route of [`versioning::ApiVersionLayer`] and the `tenant.id` of
+/// [`tenant::TenantLayer`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ResponseAttributes;
+
+impl<B> ResponseAttributeExtractor<B> for ResponseAttributes {
+    fn extract_attributes(&self, response: &Response<B>) -> Vec<KeyValue> {
+        let mut attributes = ApiVersionAttribute.extract_attributes(response);
+        attributes.extend(TenantAttribute.extract_attributes(response));
+        attributes
+    }
+}
+
+/// Like [`oltp_middleware`], labelling responses with their versioned route and
+/// `tenant.id`, see [`ResponseAttributes`].
+pub fn oltp_middleware_with_attributes() -> HTTPMetricsLayer<NoOpExtractor, ResponseAttributes> {
+    opentelemetry_instrumentation_tower::HTTPMetricsLayerBuilder::builder()
+        .with_meter(GLOBAL_METER.clone())
+        .with_response_extractor::<_, axum::body::Body>(ResponseAttributes)
+        .build()
+        .expect("Failed to build HTTP metrics layer")
+}
+
 pub fn trace_middleware() -> TraceLayer<
     HttpMakeClassifier,
     impl Fn(&Request<axum::body::Body>) -> Span + Clone,
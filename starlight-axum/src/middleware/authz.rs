@@ -0,0 +1,224 @@
+use crate::attrs;
+use axum::extract::{FromRequestParts, Request};
+use axum::http::request::Parts;
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use serde_json::{Map, Value};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+use tracing::Span;
+
+/// The claim holding the granted scopes unless configured otherwise.
+pub const SCOPE_CLAIM: &str = "scope";
+
+/// The verified claims of the caller, stored as an extension by the authentication
+/// middleware.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Claims(pub Map<String, Value>);
+
+impl Claims {
+    pub fn new(claims: Map<String, Value>) -> Self {
+        Claims(claims)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.0.get(name)
+    }
+
+    /// The scopes in claim `name`, either a space separated string (`"a b"`) or an array
+    /// of strings. `None` when the claim is missing or neither.
+    pub fn scopes(&self, name: &str) -> Option<Vec<&str>> {
+        match self.get(name)? {
+            Value::String(scopes) => Some(scopes.split_whitespace().collect()),
+            Value::Array(scopes) => scopes.iter().map(Value::as_str).collect(),
+            _ => None,
+        }
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Claims {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<Claims>()
+            .cloned()
+            .ok_or((StatusCode::UNAUTHORIZED, "the request is not authenticated"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Requirement {
+    Scope(String),
+    All(Vec<Requirement>),
+    Any(Vec<Requirement>),
+}
+
+impl Requirement {
+    /// The scopes missing from `granted`, empty when satisfied.
+    fn missing(&self, granted: &[&str]) -> Vec<String> {
+        match self {
+            Requirement::Scope(scope) if granted.contains(&scope.as_str()) => Vec::new(),
+            Requirement::Scope(scope) => vec![scope.clone()],
+            Requirement::All(requirements) => union(requirements, granted),
+            Requirement::Any(requirements) => {
+                if requirements.iter().any(|requirement| requirement.missing(granted).is_empty()) {
+                    return Vec::new();
+                }
+                union(requirements, granted)
+            }
+        }
+    }
+}
+
+/// The scopes missing from any of `requirements`, without duplicates.
+fn union(requirements: &[Requirement], granted: &[&str]) -> Vec<String> {
+    let mut missing = Vec::new();
+    for scope in requirements.iter().flat_map(|requirement| requirement.missing(granted)) {
+        if !missing.contains(&scope) {
+            missing.push(scope);
+        }
+    }
+    missing
+}
+
+/// Why [`RequireScopes`] refused a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Denied {
+    /// No [`Claims`] extension: answered with 401.
+    Unauthenticated,
+    /// Answered with 403 and a problem details body listing the missing scopes.
+    MissingScopes(Vec<String>),
+}
+
+/// Requires the caller's [`Claims`] to grant scopes, for one route with
+/// `.route_layer(RequireScopes::new(["orders:write"]))`.
+///
+/// `new` requires every scope; [`RequireScopes::all_of`] and [`RequireScopes::any_of`]
+/// combine requirements, e.g. `orders:write` or `admin`. Scopes are read from the
+/// `scope` claim unless [`RequireScopes::with_claim`] names another (`permissions`).
+/// Refusals are answered with 403 and an `application/problem+json` body; the decision
+/// is set as the `authz.decision` attribute of the current span.
+#[derive(Debug, Clone)]
+pub struct RequireScopes {
+    requirement: Arc<Requirement>,
+    claim: Arc<str>,
+}
+
+impl RequireScopes {
+    pub fn new<I, T>(scopes: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        Self::from_requirement(Requirement::All(
+            scopes.into_iter().map(|scope| Requirement::Scope(scope.into())).collect(),
+        ))
+    }
+
+    /// Satisfied when every one of `requirements` is.
+    pub fn all_of(requirements: impl IntoIterator<Item = RequireScopes>) -> Self {
+        Self::from_requirement(Requirement::All(requirements.into_iter().map(Self::into_requirement).collect()))
+    }
+
+    /// Satisfied when at least one of `requirements` is.
+    pub fn any_of(requirements: impl IntoIterator<Item = RequireScopes>) -> Self {
+        Self::from_requirement(Requirement::Any(requirements.into_iter().map(Self::into_requirement).collect()))
+    }
+
+    fn from_requirement(requirement: Requirement) -> Self {
+        RequireScopes {
+            requirement: Arc::new(requirement),
+            claim: Arc::from(SCOPE_CLAIM),
+        }
+    }
+
+    fn into_requirement(self) -> Requirement {
+        Arc::unwrap_or_clone(self.requirement)
+    }
+
+    /// The claim holding the granted scopes, `scope` by default.
+    pub fn with_claim(mut self, claim: impl Into<String>) -> Self {
+        self.claim = Arc::from(claim.into());
+        self
+    }
+
+    /// Checks the scopes granted by `claims`. A missing claim grants nothing.
+    pub fn check(&self, claims: Option<&Claims>) -> Result<(), Denied> {
+        let claims = claims.ok_or(Denied::Unauthenticated)?;
+        let granted = claims.scopes(&self.claim).unwrap_or_default();
+        let missing = self.requirement.missing(&granted);
+        if missing.is_empty() { Ok(()) } else { Err(Denied::MissingScopes(missing)) }
+    }
+}
+
+fn forbidden(missing: Vec<String>) -> Response {
+    let body = serde_json::json!({
+        "type": "about:blank",
+        "title": "Forbidden",
+        "status": 403,
+        "detail": format!("missing required scopes: {}", missing.join(" ")),
+        "missing_scopes": missing,
+    });
+    (
+        StatusCode::FORBIDDEN,
+        [(header::CONTENT_TYPE, "application/problem+json")],
+        body.to_string(),
+    )
+        .into_response()
+}
+
+impl<S> Layer<S> for RequireScopes {
+    type Service = RequireScopesService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequireScopesService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RequireScopesService<S> {
+    inner: S,
+    layer: RequireScopes,
+}
+
+impl<S, B> Service<Request<B>> for RequireScopesService<S>
+where
+    S: Service<Request<B>, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        let span = Span::current();
+        match self.layer.check(req.extensions().get::<Claims>()) {
+            Ok(()) => {
+                attrs::record(&span, attrs! { "authz.decision" => "allowed" });
+                Box::pin(self.inner.call(req))
+            }
+            Err(Denied::Unauthenticated) => {
+                attrs::record(&span, attrs! { "authz.decision" => "unauthenticated" });
+                Box::pin(async { Ok(StatusCode::UNAUTHORIZED.into_response()) })
+            }
+            Err(Denied::MissingScopes(missing)) => {
+                attrs::record(&span, attrs! { "authz.decision" => "denied" });
+                debug!("denied {} {}, missing scopes {:?}", req.method(), req.uri().path(), missing);
+                let response = forbidden(missing);
+                Box::pin(async move { Ok(response) })
+            }
+        }
+    }
+}
@@ -0,0 +1,386 @@
+use axum::body::{Body, Bytes};
+use axum::extract::{MatchedPath, Request};
+use axum::http::{HeaderMap, header};
+use axum::response::Response;
+use http_body::{Frame, SizeHint};
+use serde_json::Value;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+use tracing::Span;
+
+pub const REQUEST_BODY_EVENT: &str = "http.request.body";
+pub const RESPONSE_BODY_EVENT: &str = "http.response.body";
+
+const REDACTED: &str = "***";
+
+type RoutePredicate = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+#[derive(Clone)]
+struct CaptureConfig {
+    max_bytes: usize,
+    content_types: Vec<String>,
+    routes: Option<RoutePredicate>,
+    redacted_fields: Arc<[String]>,
+}
+
+impl CaptureConfig {
+    fn captures_route(&self, route: &str) -> bool {
+        self.routes.as_ref().is_some_and(|routes| routes(route))
+    }
+
+    fn captures_content(&self, headers: &HeaderMap) -> bool {
+        let Some(content_type) = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()) else {
+            return false;
+        };
+        let essence = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        self.content_types.iter().any(|allowed| match allowed.strip_suffix("/*") {
+            Some(prefix) => essence.split('/').next() == Some(prefix),
+            None => essence == *allowed,
+        })
+    }
+}
+
+/// Attaches a prefix of request and response bodies to the current span as the
+/// `http.request.body` and `http.response.body` events.
+///
+/// Nothing is captured unless the matched route passes [`BodyCaptureLayer::with_routes`]
+/// and the content type is allowed (`application/json` by default). At most `max_bytes`
+/// of each body are buffered; the rest streams through untouched. Configured JSON
+/// fields are replaced with `***` before the event is recorded.
+#[derive(Clone)]
+pub struct BodyCaptureLayer {
+    config: Arc<CaptureConfig>,
+}
+
+impl fmt::Debug for BodyCaptureLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BodyCaptureLayer")
+            .field("max_bytes", &self.config.max_bytes)
+            .field("content_types", &self.config.content_types)
+            .field("redacted_fields", &self.config.redacted_fields)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for BodyCaptureLayer {
+    fn default() -> Self {
+        BodyCaptureLayer {
+            config: Arc::new(CaptureConfig {
+                max_bytes: 4096,
+                content_types: vec!["application/json".to_owned()],
+                routes: None,
+                redacted_fields: Arc::from([]),
+            }),
+        }
+    }
+}
+
+impl BodyCaptureLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        Arc::make_mut(&mut self.config).max_bytes = max_bytes;
+        self
+    }
+
+    /// Media types to capture; `text/*` style entries match the whole top-level type.
+    pub fn with_content_types<I, T>(mut self, content_types: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        Arc::make_mut(&mut self.config).content_types = content_types
+            .into_iter()
+            .map(|content_type| content_type.into().to_ascii_lowercase())
+            .collect();
+        self
+    }
+
+    /// Opts routes in, given the matched route (e.g. `/users/{id}`).
+    pub fn with_routes(mut self, predicate: impl Fn(&str) -> bool + Send + Sync + 'static) -> Self {
+        Arc::make_mut(&mut self.config).routes = Some(Arc::new(predicate));
+        self
+    }
+
+    /// JSON object keys whose values are masked, at any depth, compared case-insensitively.
+    pub fn with_redacted_fields<I, T>(mut self, fields: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        Arc::make_mut(&mut self.config).redacted_fields = fields
+            .into_iter()
+            .map(|field| field.into().to_ascii_lowercase())
+            .collect();
+        self
+    }
+}
+
+impl<S> Layer<S> for BodyCaptureLayer {
+    type Service = BodyCaptureService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        BodyCaptureService {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct BodyCaptureService<S> {
+    inner: S,
+    config: Arc<CaptureConfig>,
+}
+
+impl<S> Service<Request> for BodyCaptureService<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let captured = match req.extensions().get::<MatchedPath>() {
+            Some(path) => self.config.captures_route(path.as_str()),
+            None => self.config.captures_route(req.uri().path()),
+        };
+        if !captured {
+            return Box::pin(self.inner.call(req));
+        }
+
+        let span = Span::current();
+        let req = if self.config.captures_content(req.headers()) {
+            let capture = Capture::new(REQUEST_BODY_EVENT, span.clone(), &self.config);
+            req.map(|body| Body::new(CaptureBody { inner: body, capture: Some(capture) }))
+        } else {
+            req
+        };
+
+        let config = self.config.clone();
+        let future = self.inner.call(req);
+        Box::pin(async move {
+            let response = future.await?;
+            if !config.captures_content(response.headers()) {
+                return Ok(response);
+            }
+            let capture = Capture::new(RESPONSE_BODY_EVENT, span, &config);
+            Ok(response.map(|body| Body::new(CaptureBody { inner: body, capture: Some(capture) })))
+        })
+    }
+}
+
+/// The buffered prefix of one body. The event is recorded when the prefix is full, the
+/// body ends, or the body is dropped part way through.
+struct Capture {
+    event: &'static str,
+    span: Span,
+    max_bytes: usize,
+    redacted_fields: Arc<[String]>,
+    buffer: Vec<u8>,
+    truncated: bool,
+}
+
+impl Capture {
+    fn new(event: &'static str, span: Span, config: &CaptureConfig) -> Self {
+        Capture {
+            event,
+            span,
+            max_bytes: config.max_bytes,
+            redacted_fields: config.redacted_fields.clone(),
+            buffer: Vec::new(),
+            truncated: false,
+        }
+    }
+
+    /// Returns true once no more data is wanted.
+    fn push(&mut self, data: &[u8]) -> bool {
+        let room = self.max_bytes - self.buffer.len();
+        let take = data.len().min(room);
+        self.buffer.reserve_exact(take);
+        self.buffer.extend_from_slice(&data[..take]);
+        self.truncated = data.len() > room;
+        self.truncated
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        if self.buffer.is_empty() && !self.truncated {
+            return;
+        }
+        let body = redact_json(&String::from_utf8_lossy(&self.buffer), &self.redacted_fields);
+        info!(
+            parent: &self.span,
+            http.body.captured_bytes = self.buffer.len(),
+            http.body.truncated = self.truncated,
+            http.body = %body,
+            "{}",
+            self.event
+        );
+    }
+}
+
+pin_project_lite::pin_project! {
+    struct CaptureBody {
+        #[pin]
+        inner: Body,
+        capture: Option<Capture>,
+    }
+}
+
+impl http_body::Body for CaptureBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        let this = self.project();
+        let frame = std::task::ready!(this.inner.poll_frame(cx));
+        match &frame {
+            Some(Ok(frame)) => {
+                if let Some(data) = frame.data_ref()
+                    && let Some(capture) = this.capture.as_mut()
+                    && capture.push(data)
+                {
+                    this.capture.take();
+                }
+            }
+            _ => {
+                this.capture.take();
+            }
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Masks the values of `fields` in a JSON document. Truncated documents which no longer
+/// parse are masked textually, including a value cut off by the truncation.
+pub fn redact_json(body: &str, fields: &[String]) -> String {
+    if fields.is_empty() {
+        return body.to_owned();
+    }
+    match serde_json::from_str::<Value>(body) {
+        Ok(mut value) => {
+            redact_value(&mut value, fields);
+            value.to_string()
+        }
+        Err(_) => redact_text(body, fields),
+    }
+}
+
+fn redact_value(value: &mut Value, fields: &[String]) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if fields.iter().any(|field| field.eq_ignore_ascii_case(key)) {
+                    *value = Value::String(REDACTED.to_owned());
+                } else {
+                    redact_value(value, fields);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| redact_value(item, fields)),
+        _ => {}
+    }
+}
+
+fn redact_text(body: &str, fields: &[String]) -> String {
+    let lower = body.to_ascii_lowercase();
+    let mut redacted = String::with_capacity(body.len());
+    let mut pos = 0;
+    while let Some(value_start) = next_redacted_value(&lower, pos, fields) {
+        redacted.push_str(&body[pos..value_start]);
+        redacted.push('"');
+        redacted.push_str(REDACTED);
+        redacted.push('"');
+        pos = value_end(body.as_bytes(), value_start);
+    }
+    redacted.push_str(&body[pos..]);
+    redacted
+}
+
+/// Finds the start of the earliest value after `"field":` at or beyond `from`.
+fn next_redacted_value(lower: &str, from: usize, fields: &[String]) -> Option<usize> {
+    fields
+        .iter()
+        .filter_map(|field| {
+            let quoted = format!("\"{}\"", field);
+            let mut search = from;
+            while let Some(found) = lower[search..].find(&quoted) {
+                let after_key = search + found + quoted.len();
+                let rest = &lower[after_key..];
+                let trimmed = rest.trim_start();
+                if let Some(value) = trimmed.strip_prefix(':') {
+                    let value = value.trim_start();
+                    return Some(lower.len() - value.len());
+                }
+                search = after_key;
+            }
+            None
+        })
+        .min()
+}
+
+/// Returns the index just past the JSON value starting at `start`, or the end of input.
+fn value_end(bytes: &[u8], start: usize) -> usize {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut i = start;
+    while i < bytes.len() {
+        let c = bytes[i];
+        if in_string {
+            if c == b'\\' {
+                i += 1;
+            } else if c == b'"' {
+                in_string = false;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+        } else {
+            match c {
+                b'"' => in_string = true,
+                b'{' | b'[' => depth += 1,
+                b'}' | b']' if depth == 0 => return i,
+                b'}' | b']' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return i + 1;
+                    }
+                }
+                b',' if depth == 0 => return i,
+                _ => {}
+            }
+        }
+        i += 1;
+    }
+    bytes.len()
+}
@@ -0,0 +1,366 @@
+use crate::meter::GLOBAL_METER;
+use axum::body::{Body, Bytes, HttpBody};
+use axum::extract::Request;
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use http_body_util::BodyExt;
+use opentelemetry::metrics::{Counter, Meter};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant, SystemTime};
+use tower::{Layer, Service};
+
+pub const X_CACHE: &str = "x-cache";
+
+/// The longest a response is kept, whatever its `max-age` says.
+const MAX_TTL: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// A buffered response kept for later requests.
+#[derive(Debug, Clone)]
+pub struct CachedResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+    /// For the `Age` header of hits.
+    pub stored_at: SystemTime,
+}
+
+impl CachedResponse {
+    fn into_hit(self) -> Response {
+        let age = SystemTime::now().duration_since(self.stored_at).unwrap_or_default();
+        let mut response = Response::new(Body::from(self.body));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers;
+        let headers = response.headers_mut();
+        headers.insert(header::AGE, HeaderValue::from(age.as_secs()));
+        headers.insert(X_CACHE, HeaderValue::from_static("HIT"));
+        response
+    }
+}
+
+/// Storage for cached responses. Keys combine method, path, query and the values of the
+/// varying headers.
+pub trait CacheStore: Send + Sync + 'static {
+    fn get(&self, key: &str) -> impl Future<Output = Option<CachedResponse>> + Send;
+    fn put(&self, key: &str, response: CachedResponse, ttl: Duration) -> impl Future<Output = ()> + Send;
+}
+
+/// In-process store bounded to `capacity` entries, evicting the least recently used.
+#[derive(Debug)]
+pub struct MemoryCacheStore {
+    capacity: usize,
+    entries: Mutex<MemoryEntries>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryEntries {
+    clock: u64,
+    map: HashMap<String, (CachedResponse, Instant, u64)>,
+}
+
+impl MemoryCacheStore {
+    pub fn new(capacity: usize) -> Self {
+        MemoryCacheStore {
+            capacity: capacity.max(1),
+            entries: Mutex::new(MemoryEntries::default()),
+        }
+    }
+}
+
+impl CacheStore for MemoryCacheStore {
+    async fn get(&self, key: &str) -> Option<CachedResponse> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.clock += 1;
+        let clock = entries.clock;
+        match entries.map.get_mut(key) {
+            Some((response, expires_at, used)) if *expires_at > Instant::now() => {
+                *used = clock;
+                Some(response.clone())
+            }
+            Some(_) => {
+                entries.map.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    async fn put(&self, key: &str, response: CachedResponse, ttl: Duration) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.clock += 1;
+        let clock = entries.clock;
+        if entries.map.len() >= self.capacity && !entries.map.contains_key(key) {
+            let now = Instant::now();
+            entries.map.retain(|_, (_, expires_at, _)| *expires_at > now);
+            if entries.map.len() >= self.capacity {
+                let oldest = entries
+                    .map
+                    .iter()
+                    .min_by_key(|(_, (_, _, used))| *used)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    entries.map.remove(&oldest);
+                }
+            }
+        }
+        let now = Instant::now();
+        let expires_at = now.checked_add(ttl).unwrap_or(now + MAX_TTL);
+        entries.map.insert(key.to_owned(), (response, expires_at, clock));
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CacheConfig {
+    methods: Vec<Method>,
+    statuses: Vec<StatusCode>,
+    vary: Vec<HeaderName>,
+    ttl: Duration,
+    max_body_bytes: u64,
+    hits: Counter<u64>,
+    misses: Counter<u64>,
+}
+
+/// How long a response may be cached according to its `Cache-Control`, `None` when it
+/// must not be: `no-store`, `no-cache`, `private` or `max-age=0`, or when it varies on a
+/// request header the key does not include. `s-maxage` or `max-age` shorten or extend
+/// `default`, up to a year.
+fn cacheable_for(headers: &HeaderMap, default: Duration, vary: &[HeaderName]) -> Option<Duration> {
+    if headers.contains_key(header::SET_COOKIE) {
+        return None;
+    }
+    let varies_elsewhere = headers
+        .get_all(header::VARY)
+        .iter()
+        .flat_map(|value| value.as_bytes().split(|byte| *byte == b','))
+        .map(<[u8]>::trim_ascii)
+        .filter(|name| !name.is_empty())
+        .any(|name| !HeaderName::from_bytes(name).is_ok_and(|name| vary.contains(&name)));
+    if varies_elsewhere {
+        return None;
+    }
+    let mut max_age = None;
+    let mut shared_max_age = None;
+    let directives = headers
+        .get_all(header::CACHE_CONTROL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|directive| directive.trim().to_ascii_lowercase());
+    for directive in directives {
+        match directive.split_once('=') {
+            None if matches!(directive.as_str(), "no-store" | "no-cache" | "private") => return None,
+            Some(("max-age", seconds)) => max_age = seconds.trim_matches('"').parse().ok(),
+            Some(("s-maxage", seconds)) => shared_max_age = seconds.trim_matches('"').parse().ok(),
+            _ => {}
+        }
+    }
+    match shared_max_age.or(max_age) {
+        Some(0) => None,
+        Some(seconds) => Some(Duration::from_secs(seconds).min(MAX_TTL)),
+        None => Some(default),
+    }
+}
+
+/// Caches responses of expensive read endpoints for a short time.
+///
+/// Only 200 responses to GET and HEAD are cached by default, for 10 seconds or the
+/// `s-maxage`/`max-age` the handler sets, with bodies up to 1 MiB. Responses marked
+/// `no-store`, `no-cache` or `private`, or setting a cookie, are never cached. Entries
+/// are keyed on method, path, query and the headers named with
+/// [`ResponseCacheLayer::with_vary`]; requests with `Authorization` or `Cookie` bypass
+/// the cache unless the header is one of them, and responses with a `Vary` on other
+/// headers, or `Vary: *`, are not cached. Hits carry an `Age` header, and every response an
+/// `x-cache: HIT` or `MISS` marker; they are counted on `http.server.cache.hits` and
+/// `http.server.cache.misses`.
+#[derive(Debug)]
+pub struct ResponseCacheLayer<St> {
+    store: Arc<St>,
+    config: Arc<CacheConfig>,
+}
+
+impl<St> Clone for ResponseCacheLayer<St> {
+    fn clone(&self) -> Self {
+        ResponseCacheLayer {
+            store: self.store.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+impl<St: CacheStore> ResponseCacheLayer<St> {
+    pub fn new(store: St) -> Self {
+        let (hits, misses) = counters(&GLOBAL_METER);
+        ResponseCacheLayer {
+            store: Arc::new(store),
+            config: Arc::new(CacheConfig {
+                methods: vec![Method::GET, Method::HEAD],
+                statuses: vec![StatusCode::OK],
+                vary: Vec::new(),
+                ttl: Duration::from_secs(10),
+                max_body_bytes: 1024 * 1024,
+                hits,
+                misses,
+            }),
+        }
+    }
+
+    pub fn with_meter(mut self, meter: &Meter) -> Self {
+        let config = Arc::make_mut(&mut self.config);
+        (config.hits, config.misses) = counters(meter);
+        self
+    }
+
+    pub fn with_methods(mut self, methods: impl IntoIterator<Item = Method>) -> Self {
+        Arc::make_mut(&mut self.config).methods = methods.into_iter().collect();
+        self
+    }
+
+    pub fn with_statuses(mut self, statuses: impl IntoIterator<Item = StatusCode>) -> Self {
+        Arc::make_mut(&mut self.config).statuses = statuses.into_iter().collect();
+        self
+    }
+
+    /// Request headers whose values select different entries, like `accept-language`.
+    pub fn with_vary(mut self, headers: impl IntoIterator<Item = HeaderName>) -> Self {
+        Arc::make_mut(&mut self.config).vary = headers.into_iter().collect();
+        self
+    }
+
+    /// How long responses are kept when they set no `max-age`.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        Arc::make_mut(&mut self.config).ttl = ttl;
+        self
+    }
+
+    pub fn with_max_body_bytes(mut self, max: u64) -> Self {
+        Arc::make_mut(&mut self.config).max_body_bytes = max;
+        self
+    }
+}
+
+fn counters(meter: &Meter) -> (Counter<u64>, Counter<u64>) {
+    let hits = meter
+        .u64_counter("http.server.cache.hits")
+        .with_description("Requests answered from the response cache")
+        .build();
+    let misses = meter
+        .u64_counter("http.server.cache.misses")
+        .with_description("Cacheable requests passed to the handler")
+        .build();
+    (hits, misses)
+}
+
+impl CacheConfig {
+    /// The cache key of `req`, `None` when it bypasses the cache.
+    fn key(&self, req: &Request) -> Option<String> {
+        if !self.methods.contains(req.method()) {
+            return None;
+        }
+        // Never share a response between callers who may be told apart by credentials.
+        for credential in [header::AUTHORIZATION, header::COOKIE] {
+            if req.headers().contains_key(&credential) && !self.vary.contains(&credential) {
+                return None;
+            }
+        }
+        let target = req.uri().path_and_query().map_or("/", |target| target.as_str());
+        let mut key = format!("{} {}", req.method(), target);
+        for name in &self.vary {
+            let values: Vec<&[u8]> = req.headers().get_all(name).iter().map(HeaderValue::as_bytes).collect();
+            key.push_str(&format!("\n{}: {}", name, String::from_utf8_lossy(&values.join(&b','))));
+        }
+        Some(key)
+    }
+}
+
+impl<S, St> Layer<S> for ResponseCacheLayer<St> {
+    type Service = ResponseCacheService<S, St>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ResponseCacheService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ResponseCacheService<S, St> {
+    inner: S,
+    layer: ResponseCacheLayer<St>,
+}
+
+impl<S: Clone, St> Clone for ResponseCacheService<S, St> {
+    fn clone(&self) -> Self {
+        ResponseCacheService {
+            inner: self.inner.clone(),
+            layer: self.layer.clone(),
+        }
+    }
+}
+
+impl<S, St> Service<Request> for ResponseCacheService<S, St>
+where
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send,
+    St: CacheStore,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let Some(key) = self.layer.config.key(&req) else {
+            return Box::pin(inner.call(req));
+        };
+
+        let layer = self.layer.clone();
+        Box::pin(async move {
+            let config = &layer.config;
+            if let Some(cached) = layer.store.get(&key).await {
+                config.hits.add(1, &[]);
+                return Ok(cached.into_hit());
+            }
+            config.misses.add(1, &[]);
+
+            let mut response = inner.call(req).await?;
+            response.headers_mut().insert(X_CACHE, HeaderValue::from_static("MISS"));
+            let fits = response
+                .body()
+                .size_hint()
+                .upper()
+                .is_some_and(|upper| upper <= config.max_body_bytes);
+            let ttl = cacheable_for(response.headers(), config.ttl, &config.vary);
+            let Some(ttl) = ttl.filter(|_| fits && config.statuses.contains(&response.status())) else {
+                return Ok(response);
+            };
+
+            let (parts, body) = response.into_parts();
+            let body = match body.collect().await {
+                Ok(collected) => collected.to_bytes(),
+                Err(err) => {
+                    warn!("failed to buffer response for the cache key {}: {}", key, err);
+                    return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+                }
+            };
+            let cached = CachedResponse {
+                status: parts.status,
+                headers: parts.headers.clone(),
+                body: body.clone(),
+                stored_at: SystemTime::now(),
+            };
+            layer.store.put(&key, cached, ttl).await;
+
+            Ok(Response::from_parts(parts, Body::from(body)))
+        })
+    }
+}
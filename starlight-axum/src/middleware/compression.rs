@@ -0,0 +1,344 @@
+use crate::meter::GLOBAL_METER;
+use axum::body::{Body, Bytes};
+use axum::extract::{MatchedPath, Request};
+use axum::http::header;
+use axum::response::Response;
+use http_body::{Frame, SizeHint};
+use http_body_util::Limited;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::Counter;
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+use tower_http::compression::predicate::{NotForContentType, Predicate, SizeAbove};
+use tower_http::compression::Compression;
+use tower_http::decompression::{DecompressionBody, RequestDecompression};
+
+pub use tower_http::CompressionLevel;
+
+static BYTES_SAVED: LazyLock<Counter<u64>> = LazyLock::new(|| {
+    GLOBAL_METER
+        .u64_counter("http.server.compression.saved")
+        .with_description("Response bytes saved by compression")
+        .with_unit("By")
+        .build()
+});
+
+/// Response compression with gzip, brotli and zstd, and optional request decompression.
+///
+/// Responses smaller than `min_size` bytes, with an excluded content type (already
+/// compressed media by default), or on a disabled route are sent as is. Request
+/// decompression is off unless [`CompressionConfig::with_request_decompression`] is
+/// set; the decompressed body is then capped, and extractors answer 413 beyond it.
+#[derive(Debug, Clone)]
+pub struct CompressionConfig {
+    min_size: u16,
+    excluded_content_types: Vec<String>,
+    disabled_routes: Vec<String>,
+    quality: CompressionLevel,
+    gzip: bool,
+    br: bool,
+    zstd: bool,
+    max_decompressed_bytes: Option<usize>,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        CompressionConfig {
+            min_size: 1024,
+            excluded_content_types: ["image/", "video/", "audio/", "font/woff2", "application/zip", "application/gzip"]
+                .map(str::to_owned)
+                .to_vec(),
+            disabled_routes: Vec::new(),
+            quality: CompressionLevel::Default,
+            gzip: true,
+            br: true,
+            zstd: true,
+            max_decompressed_bytes: None,
+        }
+    }
+}
+
+impl CompressionConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Responses whose size is known and below `min_size` bytes are not compressed.
+    pub fn with_min_size(mut self, min_size: u16) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// Skip responses whose content type starts with `content_type` (e.g. `image/`).
+    pub fn exclude_content_type(mut self, content_type: &str) -> Self {
+        self.excluded_content_types.push(content_type.to_ascii_lowercase());
+        self
+    }
+
+    /// Never compress the matched route; a trailing `*` matches any route with that prefix.
+    pub fn disable_for_route(mut self, route_pattern: &str) -> Self {
+        self.disabled_routes.push(route_pattern.to_owned());
+        self
+    }
+
+    pub fn with_quality(mut self, quality: CompressionLevel) -> Self {
+        self.quality = quality;
+        self
+    }
+
+    pub fn gzip(mut self, enable: bool) -> Self {
+        self.gzip = enable;
+        self
+    }
+
+    pub fn br(mut self, enable: bool) -> Self {
+        self.br = enable;
+        self
+    }
+
+    pub fn zstd(mut self, enable: bool) -> Self {
+        self.zstd = enable;
+        self
+    }
+
+    /// Accept gzip, brotli and zstd request bodies, decompressing at most `max_bytes`.
+    pub fn with_request_decompression(mut self, max_bytes: usize) -> Self {
+        self.max_decompressed_bytes = Some(max_bytes);
+        self
+    }
+
+    pub fn into_layer(self) -> CompressionLayer {
+        CompressionLayer {
+            config: Arc::new(self),
+        }
+    }
+
+    fn is_disabled_route(&self, route: &str) -> bool {
+        self.disabled_routes
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => route.starts_with(prefix),
+                None => route == pattern,
+            })
+    }
+}
+
+/// Marks a response which must be sent uncompressed.
+#[derive(Debug, Clone, Copy)]
+struct SkipCompression;
+
+#[derive(Debug, Clone)]
+pub struct CompressionPredicate {
+    min_size: u16,
+    excluded_content_types: Arc<[String]>,
+}
+
+impl Predicate for CompressionPredicate {
+    fn should_compress<B: http_body::Body>(&self, response: &axum::http::Response<B>) -> bool {
+        if response.extensions().get::<SkipCompression>().is_some() {
+            return false;
+        }
+        let excluded = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_ascii_lowercase)
+            .is_some_and(|content_type| {
+                self.excluded_content_types
+                    .iter()
+                    .any(|excluded| content_type.starts_with(excluded.as_str()))
+            });
+        !excluded
+            && SizeAbove::new(self.min_size)
+                .and(NotForContentType::GRPC)
+                .and(NotForContentType::SSE)
+                .should_compress(response)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompressionLayer {
+    config: Arc<CompressionConfig>,
+}
+
+impl<S> Layer<S> for CompressionLayer {
+    type Service = CompressionService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        let config = &self.config;
+        let decompress = config.max_decompressed_bytes.is_some();
+        let measure = MeasureService {
+            inner,
+            config: config.clone(),
+        };
+        let decompression = RequestDecompression::new(measure)
+            .gzip(decompress)
+            .br(decompress)
+            .zstd(decompress)
+            .deflate(decompress)
+            .pass_through_unaccepted(!decompress);
+        let compression = Compression::new(decompression)
+            .gzip(config.gzip)
+            .br(config.br)
+            .zstd(config.zstd)
+            .deflate(false)
+            .quality(config.quality)
+            .compress_when(CompressionPredicate {
+                min_size: config.min_size,
+                excluded_content_types: config.excluded_content_types.iter().cloned().collect(),
+            });
+        CompressionService { inner: compression }
+    }
+}
+
+#[derive(Clone)]
+pub struct CompressionService<S> {
+    inner: Compression<RequestDecompression<MeasureService<S>>, CompressionPredicate>,
+}
+
+impl<S> Service<Request> for CompressionService<S>
+where
+    S: Service<Request, Response = Response, Error = Infallible> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let future = self.inner.call(req);
+        Box::pin(async move {
+            let response = future.await?;
+            let uncompressed = response.extensions().get::<UncompressedBytes>().cloned();
+            let encoding = response
+                .headers()
+                .get(header::CONTENT_ENCODING)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_owned);
+            Ok(match (uncompressed, encoding) {
+                (Some(UncompressedBytes(uncompressed)), Some(encoding)) => response.map(|body| {
+                    Body::new(CountingBody {
+                        inner: body,
+                        bytes: Arc::default(),
+                        on_end: Some(Box::new(move |compressed| {
+                            let saved = uncompressed.load(Ordering::Relaxed).saturating_sub(compressed);
+                            BYTES_SAVED.add(saved, &[KeyValue::new("http.response.encoding", encoding)]);
+                        })),
+                    })
+                }),
+                _ => response.map(Body::new),
+            })
+        })
+    }
+}
+
+/// Byte count of the response body before compression.
+#[derive(Debug, Clone)]
+struct UncompressedBytes(Arc<AtomicU64>);
+
+/// Innermost service: caps the decompressed request body and marks or measures the response.
+#[derive(Debug, Clone)]
+pub struct MeasureService<S> {
+    inner: S,
+    config: Arc<CompressionConfig>,
+}
+
+impl<S> Service<Request<DecompressionBody<Body>>> for MeasureService<S>
+where
+    S: Service<Request, Response = Response, Error = Infallible>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<DecompressionBody<Body>>) -> Self::Future {
+        let skip = match req.extensions().get::<MatchedPath>() {
+            Some(path) => self.config.is_disabled_route(path.as_str()),
+            None => self.config.is_disabled_route(req.uri().path()),
+        };
+        let req = match self.config.max_decompressed_bytes {
+            Some(max) => req.map(|body| Body::new(Limited::new(body, max))),
+            None => req.map(Body::new),
+        };
+
+        let future = self.inner.call(req);
+        Box::pin(async move {
+            let mut response = future.await?;
+            if skip {
+                response.extensions_mut().insert(SkipCompression);
+                return Ok(response);
+            }
+            let bytes = Arc::new(AtomicU64::new(0));
+            response.extensions_mut().insert(UncompressedBytes(bytes.clone()));
+            Ok(response.map(|body| {
+                Body::new(CountingBody {
+                    inner: body,
+                    bytes,
+                    on_end: None,
+                })
+            }))
+        })
+    }
+}
+
+type OnEnd = Box<dyn FnOnce(u64) + Send>;
+
+pin_project_lite::pin_project! {
+    struct CountingBody<B> {
+        #[pin]
+        inner: B,
+        bytes: Arc<AtomicU64>,
+        on_end: Option<OnEnd>,
+    }
+}
+
+impl<B> http_body::Body for CountingBody<B>
+where
+    B: http_body::Body<Data = Bytes>,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, B::Error>>> {
+        let this = self.project();
+        let frame = std::task::ready!(this.inner.poll_frame(cx));
+        match &frame {
+            Some(Ok(frame)) => {
+                if let Some(data) = frame.data_ref() {
+                    this.bytes.fetch_add(data.len() as u64, Ordering::Relaxed);
+                }
+            }
+            Some(Err(_)) => {
+                this.on_end.take();
+            }
+            None => {
+                if let Some(on_end) = this.on_end.take() {
+                    on_end(this.bytes.load(Ordering::Relaxed));
+                }
+            }
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
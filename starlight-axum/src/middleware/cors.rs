@@ -0,0 +1,420 @@
+use crate::config::DynamicConfig;
+use axum::http::{HeaderName, HeaderValue, Method};
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer, ExposeHeaders};
+
+/// An allowed origin: an exact origin, or `scheme://*.domain` matching any subdomain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OriginPattern {
+    Exact(String),
+    Subdomain { scheme: String, domain: String },
+}
+
+impl OriginPattern {
+    pub fn parse(pattern: &str) -> Self {
+        let pattern = pattern.trim().trim_end_matches('/');
+        match pattern.split_once("://*.") {
+            Some((scheme, domain)) => OriginPattern::Subdomain {
+                scheme: scheme.to_ascii_lowercase(),
+                domain: domain.to_ascii_lowercase(),
+            },
+            None => OriginPattern::Exact(pattern.to_ascii_lowercase()),
+        }
+    }
+
+    pub fn matches(&self, origin: &str) -> bool {
+        let origin = origin.to_ascii_lowercase();
+        match self {
+            OriginPattern::Exact(exact) => origin == *exact,
+            OriginPattern::Subdomain { scheme, domain } => origin
+                .strip_prefix(scheme.as_str())
+                .and_then(|rest| rest.strip_prefix("://"))
+                .and_then(|host| host.strip_suffix(domain.as_str()))
+                .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum AllowedOrigins {
+    Any,
+    List(Vec<OriginPattern>),
+    /// Patterns which can change while the service runs.
+    Dynamic(DynamicConfig<Vec<OriginPattern>>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorsConfigError(String);
+
+impl CorsConfigError {
+    pub(crate) fn new(message: impl Into<String>) -> Self {
+        CorsConfigError(message.into())
+    }
+}
+
+impl fmt::Display for CorsConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid CORS configuration: {}", self.0)
+    }
+}
+
+impl std::error::Error for CorsConfigError {}
+
+/// Builder over tower-http's [`CorsLayer`].
+///
+/// `None` for methods, headers or exposed headers means "any". Preflight handling and the `Vary`
+/// headers come from tower-http.
+#[derive(Debug, Clone)]
+pub struct CorsConfig {
+    origins: AllowedOrigins,
+    methods: Option<Vec<String>>,
+    headers: Option<Vec<String>>,
+    expose_headers: Option<Vec<String>>,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            origins: AllowedOrigins::List(Vec::new()),
+            methods: Some(
+                ["GET", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"]
+                    .map(str::to_owned)
+                    .to_vec(),
+            ),
+            headers: None,
+            expose_headers: Some(Vec::new()),
+            credentials: false,
+            max_age: None,
+        }
+    }
+}
+
+impl CorsConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads `CORS_ALLOWED_ORIGINS`, `CORS_ALLOWED_METHODS`, `CORS_ALLOWED_HEADERS`,
+    /// `CORS_EXPOSE_HEADERS` (comma separated, `*` for any), `CORS_ALLOW_CREDENTIALS`
+    /// and `CORS_MAX_AGE` (seconds). Unset variables keep the defaults.
+    pub fn from_env() -> Result<Self, CorsConfigError> {
+        Self::from_vars(std::env::vars())
+    }
+
+    /// Like [`CorsConfig::from_env`] with the given variables instead of the process's.
+    pub fn from_vars(vars: impl IntoIterator<Item = (String, String)>) -> Result<Self, CorsConfigError> {
+        let vars: HashMap<String, String> = vars.into_iter().collect();
+        let list = |name: &str| -> Option<Vec<String>> {
+            vars.get(name).map(|value| {
+                value
+                    .split(',')
+                    .map(|item| item.trim().to_owned())
+                    .filter(|item| !item.is_empty())
+                    .collect()
+            })
+        };
+        let is_any = |items: &[String]| items.iter().any(|item| item == "*");
+
+        let mut config = CorsConfig::default();
+        if let Some(origins) = list("CORS_ALLOWED_ORIGINS") {
+            config.origins = if is_any(&origins) {
+                AllowedOrigins::Any
+            } else {
+                AllowedOrigins::List(origins.iter().map(|o| OriginPattern::parse(o)).collect())
+            };
+        }
+        if let Some(methods) = list("CORS_ALLOWED_METHODS") {
+            config.methods = (!is_any(&methods)).then_some(methods);
+        }
+        if let Some(headers) = list("CORS_ALLOWED_HEADERS") {
+            config.headers = (!is_any(&headers)).then_some(headers);
+        }
+        if let Some(expose) = list("CORS_EXPOSE_HEADERS") {
+            config.expose_headers = (!is_any(&expose)).then_some(expose);
+        }
+        if let Some(credentials) = vars.get("CORS_ALLOW_CREDENTIALS") {
+            config.credentials = matches!(
+                credentials.trim().to_ascii_lowercase().as_str(),
+                "1" | "true" | "yes" | "on"
+            );
+        }
+        if let Some(max_age) = vars.get("CORS_MAX_AGE") {
+            let secs = max_age.trim().parse::<u64>().map_err(|_| {
+                CorsConfigError(format!("CORS_MAX_AGE must be a number of seconds, got {:?}", max_age))
+            })?;
+            config.max_age = Some(Duration::from_secs(secs));
+        }
+        Ok(config)
+    }
+
+    pub fn allow_any_origin(mut self) -> Self {
+        self.origins = AllowedOrigins::Any;
+        self
+    }
+
+    /// Allow an exact origin or a `https://*.example.com` subdomain pattern.
+    pub fn allow_origin(mut self, pattern: &str) -> Self {
+        let pattern = OriginPattern::parse(pattern);
+        match &mut self.origins {
+            AllowedOrigins::List(list) => list.push(pattern),
+            AllowedOrigins::Any | AllowedOrigins::Dynamic(_) => self.origins = AllowedOrigins::List(vec![pattern]),
+        }
+        self
+    }
+
+    /// Allow the origins and subdomain patterns of `origins` as it changes, e.g. the
+    /// `cors.allowed_origins` of a watched [`AppConfig`](crate::config::AppConfig). `*`
+    /// is not special here: use [`CorsConfig::allow_any_origin`] instead.
+    pub fn allow_dynamic_origins(mut self, origins: &DynamicConfig<Vec<String>>) -> Self {
+        self.origins = AllowedOrigins::Dynamic(
+            origins.map(|origins| origins.iter().map(|origin| OriginPattern::parse(origin)).collect()),
+        );
+        self
+    }
+
+    pub fn allow_methods<I: IntoIterator<Item = Method>>(mut self, methods: I) -> Self {
+        self.methods = Some(methods.into_iter().map(|m| m.to_string()).collect());
+        self
+    }
+
+    pub fn allow_any_method(mut self) -> Self {
+        self.methods = None;
+        self
+    }
+
+    pub fn allow_headers<I: IntoIterator<Item = HeaderName>>(mut self, headers: I) -> Self {
+        self.headers = Some(headers.into_iter().map(|h| h.to_string()).collect());
+        self
+    }
+
+    pub fn expose_headers<I: IntoIterator<Item = HeaderName>>(mut self, headers: I) -> Self {
+        self.expose_headers = Some(headers.into_iter().map(|h| h.to_string()).collect());
+        self
+    }
+
+    pub fn expose_any_header(mut self) -> Self {
+        self.expose_headers = None;
+        self
+    }
+
+    pub fn allow_credentials(mut self, allow: bool) -> Self {
+        self.credentials = allow;
+        self
+    }
+
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn into_layer(self) -> Result<CorsLayer, CorsConfigError> {
+        if self.credentials {
+            // Browsers reject `*` together with credentials, and tower-http panics on it.
+            if matches!(self.origins, AllowedOrigins::Any) {
+                return Err(CorsConfigError(
+                    "credentials cannot be allowed for any origin; list the allowed origins".to_owned(),
+                ));
+            }
+            if self.expose_headers.is_none() {
+                return Err(CorsConfigError(
+                    "credentials cannot be allowed when exposing any header; list the exposed headers".to_owned(),
+                ));
+            }
+        }
+
+        let origins = match self.origins {
+            AllowedOrigins::Any => AllowOrigin::any(),
+            AllowedOrigins::List(patterns) => {
+                AllowOrigin::predicate(move |origin: &HeaderValue, _| {
+                    origin
+                        .to_str()
+                        .is_ok_and(|origin| patterns.iter().any(|p| p.matches(origin)))
+                })
+            }
+            AllowedOrigins::Dynamic(patterns) => AllowOrigin::predicate(move |origin: &HeaderValue, _| {
+                origin
+                    .to_str()
+                    .is_ok_and(|origin| patterns.get().iter().any(|p| p.matches(origin)))
+            }),
+        };
+
+        let methods = match self.methods {
+            Some(methods) => AllowMethods::list(
+                methods
+                    .iter()
+                    .map(|m| {
+                        Method::from_bytes(m.to_ascii_uppercase().as_bytes())
+                            .map_err(|_| CorsConfigError(format!("invalid method {:?}", m)))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?,
+            ),
+            None if self.credentials => AllowMethods::mirror_request(),
+            None => AllowMethods::any(),
+        };
+        let headers = match self.headers {
+            Some(headers) => AllowHeaders::list(parse_header_names(&headers)?),
+            None if self.credentials => AllowHeaders::mirror_request(),
+            None => AllowHeaders::any(),
+        };
+
+        let expose_headers = match self.expose_headers {
+            Some(headers) => ExposeHeaders::list(parse_header_names(&headers)?),
+            None => ExposeHeaders::any(),
+        };
+
+        let mut layer = CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods(methods)
+            .allow_headers(headers)
+            .expose_headers(expose_headers)
+            .allow_credentials(self.credentials);
+        if let Some(max_age) = self.max_age {
+            layer = layer.max_age(max_age);
+        }
+        Ok(layer)
+    }
+}
+
+fn parse_header_names(names: &[String]) -> Result<Vec<HeaderName>, CorsConfigError> {
+    names
+        .iter()
+        .map(|name| {
+            HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| CorsConfigError(format!("invalid header name {:?}", name)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Router;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode, header};
+    use axum::routing::get;
+    use tower::ServiceExt;
+
+    fn app() -> Router {
+        let cors = CorsConfig::new()
+            .allow_origin("https://app.example.org")
+            .allow_origin("https://*.example.com")
+            .allow_credentials(true)
+            .max_age(Duration::from_secs(600))
+            .into_layer()
+            .unwrap();
+        Router::new().route("/items", get(|| async { "ok" })).layer(cors)
+    }
+
+    async fn preflight(origin: &str) -> axum::response::Response {
+        app()
+            .oneshot(
+                Request::options("/items")
+                    .header(header::ORIGIN, origin)
+                    .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn subdomain_pattern_requires_a_subdomain() {
+        let pattern = OriginPattern::parse("https://*.example.com");
+        assert!(pattern.matches("https://api.example.com"));
+        assert!(pattern.matches("https://a.b.example.com"));
+        assert!(!pattern.matches("https://example.com"));
+        assert!(!pattern.matches("https://evilexample.com"));
+        assert!(!pattern.matches("http://api.example.com"));
+    }
+
+    #[test]
+    fn any_origin_with_credentials_is_rejected() {
+        let err = CorsConfig::new()
+            .allow_any_origin()
+            .allow_credentials(true)
+            .into_layer()
+            .unwrap_err();
+        assert!(err.to_string().contains("credentials"));
+    }
+
+    #[test]
+    fn star_exposes_any_header() {
+        let vars = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<Vec<_>>()
+        };
+        let config = CorsConfig::from_vars(vars(&[("CORS_EXPOSE_HEADERS", "*")])).unwrap();
+        assert_eq!(config.expose_headers, None);
+        let config = CorsConfig::from_vars(vars(&[("CORS_EXPOSE_HEADERS", "x-request-id, etag")])).unwrap();
+        assert_eq!(config.expose_headers, Some(vec!["x-request-id".to_owned(), "etag".to_owned()]));
+
+        let config = CorsConfig::from_vars(vars(&[
+            ("CORS_ALLOWED_ORIGINS", "https://app.example.org"),
+            ("CORS_EXPOSE_HEADERS", "*"),
+            ("CORS_ALLOW_CREDENTIALS", "true"),
+        ]))
+        .unwrap();
+        assert!(config.into_layer().unwrap_err().to_string().contains("exposing any header"));
+    }
+
+    #[tokio::test]
+    async fn any_exposed_header_is_a_wildcard() {
+        let cors = CorsConfig::new()
+            .allow_any_origin()
+            .expose_any_header()
+            .into_layer()
+            .unwrap();
+        let response = Router::new()
+            .route("/items", get(|| async { "ok" }))
+            .layer(cors)
+            .oneshot(
+                Request::get("/items")
+                    .header(header::ORIGIN, "https://app.example.org")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[header::ACCESS_CONTROL_EXPOSE_HEADERS], "*");
+    }
+
+    #[tokio::test]
+    async fn preflight_for_allowed_and_subdomain_origins() {
+        for origin in ["https://app.example.org", "https://shop.example.com"] {
+            let response = preflight(origin).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], origin);
+            assert_eq!(response.headers()[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+            assert_eq!(response.headers()[header::ACCESS_CONTROL_MAX_AGE], "600");
+            assert!(response.headers().get_all(header::VARY).iter().any(|v| v
+                .to_str()
+                .unwrap()
+                .contains("origin")));
+        }
+    }
+
+    #[tokio::test]
+    async fn disallowed_origin_gets_no_cors_headers() {
+        let response = preflight("https://evil.test").await;
+        assert!(response.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+
+        let simple = app()
+            .oneshot(
+                Request::get("/items")
+                    .header(header::ORIGIN, "https://evil.test")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(simple.status(), StatusCode::OK);
+        assert!(simple.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    }
+}
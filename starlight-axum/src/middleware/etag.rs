@@ -0,0 +1,347 @@
+use axum::body::{Body, HttpBody};
+use axum::extract::{FromRequestParts, MatchedPath, Request};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, HeaderValue, Method, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use http_body_util::BodyExt;
+use ring::digest::{SHA256, digest};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+type RoutePredicate = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+type EtagFuture<'a> = Pin<Box<dyn Future<Output = Option<String>> + Send + 'a>>;
+
+/// A strong ETag for `body`: the first 128 bits of its SHA-256, quoted.
+pub fn strong_etag(body: &[u8]) -> String {
+    let hash = digest(&SHA256, body);
+    let hex: String = hash.as_ref()[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("\"{}\"", hex)
+}
+
+/// `response` without its body, but with its length, when it answers a HEAD request.
+fn strip_head(response: Response, head: bool) -> Response {
+    if !head {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    if let Some(len) = body.size_hint().exact() {
+        parts.headers.entry(header::CONTENT_LENGTH).or_insert(HeaderValue::from(len));
+    }
+    Response::from_parts(parts, Body::empty())
+}
+
+/// The entity tags of an `If-None-Match`/`If-Match` value, weak ones keeping their
+/// `W/` prefix; `None` for `*`.
+fn parse_tags(value: &str) -> Option<Vec<String>> {
+    if value.trim() == "*" {
+        return None;
+    }
+    let mut tags = Vec::new();
+    let mut rest = value;
+    while let Some(start) = rest.find('"') {
+        let Some(len) = rest[start + 1..].find('"') else { break };
+        let end = start + len + 2;
+        let weak = rest[..start].trim_end().ends_with("W/");
+        let tag = &rest[start..end];
+        tags.push(if weak { format!("W/{}", tag) } else { tag.to_owned() });
+        rest = &rest[end..];
+    }
+    Some(tags)
+}
+
+/// Weak comparison (RFC 9110 13.1.2), used for `If-None-Match`.
+pub(crate) fn none_match(headers: &HeaderMap, etag: &HeaderValue) -> bool {
+    let Ok(etag) = etag.to_str() else { return false };
+    let opaque = etag.trim_start_matches("W/");
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| match parse_tags(value) {
+            None => true,
+            Some(tags) => tags.iter().any(|tag| tag.trim_start_matches("W/") == opaque),
+        })
+}
+
+/// Looks up the current ETag of the resource a write targets, for the `If-Match`
+/// precondition [`EtagLayer::with_current_etag`] enforces. `None` when the resource
+/// does not exist.
+pub trait CurrentEtag: Send + Sync + 'static {
+    fn current_etag(&self, parts: &Parts) -> impl Future<Output = Option<String>> + Send;
+}
+
+/// [`CurrentEtag`] behind a pointer, which its `impl Future` rules out.
+trait DynCurrentEtag: Send + Sync + 'static {
+    fn current_etag<'a>(&'a self, parts: &'a Parts) -> EtagFuture<'a>;
+}
+
+impl<E: CurrentEtag> DynCurrentEtag for E {
+    fn current_etag<'a>(&'a self, parts: &'a Parts) -> EtagFuture<'a> {
+        Box::pin(CurrentEtag::current_etag(self, parts))
+    }
+}
+
+#[derive(Clone)]
+struct EtagConfig {
+    max_bytes: u64,
+    routes: Option<RoutePredicate>,
+    current: Option<Arc<dyn DynCurrentEtag>>,
+}
+
+/// Adds strong ETags to buffered `2xx` responses of opted-in routes and answers matching
+/// `If-None-Match` requests with `304 Not Modified`.
+///
+/// Only `GET` and `HEAD` responses whose length is known and at most `max_bytes` are
+/// buffered; streaming and larger bodies pass through untouched. An `ETag` set by the
+/// handler is kept. `HEAD` requests reach the service as `GET`, so their tag and
+/// `Content-Length` are those of the full body.
+///
+/// With [`EtagLayer::with_current_etag`], writes to opted-in routes carrying `If-Match`
+/// are answered with `412 Precondition Failed` before they reach the handler when the
+/// tag does not match the current one, or the resource does not exist. Without it,
+/// handlers check [`IfMatch`] themselves.
+#[derive(Clone)]
+pub struct EtagLayer {
+    config: Arc<EtagConfig>,
+}
+
+impl fmt::Debug for EtagLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EtagLayer")
+            .field("max_bytes", &self.config.max_bytes)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for EtagLayer {
+    fn default() -> Self {
+        EtagLayer {
+            config: Arc::new(EtagConfig {
+                max_bytes: 256 * 1024,
+                routes: None,
+                current: None,
+            }),
+        }
+    }
+}
+
+impl EtagLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        Arc::make_mut(&mut self.config).max_bytes = max_bytes;
+        self
+    }
+
+    /// Opts routes in, given the matched route (e.g. `/users/{id}`).
+    pub fn with_routes(mut self, predicate: impl Fn(&str) -> bool + Send + Sync + 'static) -> Self {
+        Arc::make_mut(&mut self.config).routes = Some(Arc::new(predicate));
+        self
+    }
+
+    /// Enforces `If-Match` on writes to the opted-in routes, comparing it against the
+    /// ETag `current` looks up.
+    pub fn with_current_etag(mut self, current: impl CurrentEtag) -> Self {
+        Arc::make_mut(&mut self.config).current = Some(Arc::new(current));
+        self
+    }
+}
+
+impl<S> Layer<S> for EtagLayer {
+    type Service = EtagService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        EtagService {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct EtagService<S> {
+    inner: S,
+    config: Arc<EtagConfig>,
+}
+
+impl<S> Service<Request> for EtagService<S>
+where
+    S: Service<Request, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        let route = match req.extensions().get::<MatchedPath>() {
+            Some(path) => path.as_str(),
+            None => req.uri().path(),
+        };
+        let opted_in = self.config.routes.as_ref().is_some_and(|routes| routes(route));
+        if opted_in && !req.method().is_safe() {
+            return self.check_if_match(req);
+        }
+        if !opted_in || !matches!(*req.method(), Method::GET | Method::HEAD) {
+            return Box::pin(self.inner.call(req));
+        }
+
+        let request_headers = req.headers().clone();
+        let max_bytes = self.config.max_bytes;
+        // The tag of a HEAD response is that of the GET body, which is only there to hash.
+        let head = req.method() == Method::HEAD;
+        if head {
+            *req.method_mut() = Method::GET;
+        }
+        let future = self.inner.call(req);
+        Box::pin(async move {
+            let response = future.await?;
+            let fits = response
+                .body()
+                .size_hint()
+                .exact()
+                .is_some_and(|len| len <= max_bytes);
+            if !response.status().is_success() || !fits {
+                return Ok(strip_head(response, head));
+            }
+
+            let (mut parts, body) = response.into_parts();
+            let body = match parts.headers.get(header::ETAG) {
+                Some(_) => body,
+                None => {
+                    let bytes = match body.collect().await {
+                        Ok(collected) => collected.to_bytes(),
+                        Err(err) => {
+                            warn!("failed to buffer response for etag: {}", err);
+                            return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+                        }
+                    };
+                    let etag = HeaderValue::from_str(&strong_etag(&bytes)).expect("hex is a valid header value");
+                    parts.headers.insert(header::ETAG, etag);
+                    Body::from(bytes)
+                }
+            };
+            if !none_match(&request_headers, &parts.headers[header::ETAG]) {
+                return Ok(strip_head(Response::from_parts(parts, body), head));
+            }
+
+            // The representation headers stay so caches can refresh their entry.
+            parts.status = StatusCode::NOT_MODIFIED;
+            parts.headers.remove(header::CONTENT_LENGTH);
+            Ok(Response::from_parts(parts, Body::empty()))
+        })
+    }
+}
+
+impl<S> EtagService<S>
+where
+    S: Service<Request, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    /// Calls the service if the write has no `If-Match`, or one the current ETag matches.
+    fn check_if_match(&mut self, req: Request) -> <Self as Service<Request>>::Future {
+        let if_match = IfMatch::from_headers(req.headers());
+        let Some(current) = self.config.current.clone().filter(|_| if_match.0.is_some()) else {
+            return Box::pin(self.inner.call(req));
+        };
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let (parts, body) = req.into_parts();
+            match current.current_etag(&parts).await {
+                Some(etag) => {
+                    if let Err(failed) = if_match.check(&etag) {
+                        return Ok(failed.into_response());
+                    }
+                }
+                None => return Ok((StatusCode::PRECONDITION_FAILED, "the resource does not exist").into_response()),
+            }
+            inner.call(Request::from_parts(parts, body)).await
+        })
+    }
+}
+
+/// The `If-Match` precondition of a write. Without [`EtagLayer::with_current_etag`],
+/// handlers compare it against the current ETag of the resource before changing it:
+///
+/// ```ignore
+/// async fn update(if_match: IfMatch, ...) -> Result<Json<Doc>, PreconditionFailed> {
+///     let doc = load().await;
+///     if_match.check(&strong_etag(&serde_json::to_vec(&doc)?))?;
+///     ...
+/// }
+/// ```
+///
+/// Requests without the header pass every check.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IfMatch(Option<Vec<String>>);
+
+impl IfMatch {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let mut values = headers.get_all(header::IF_MATCH).iter().filter_map(|v| v.to_str().ok()).peekable();
+        if values.peek().is_none() {
+            return IfMatch(None);
+        }
+        let mut tags = Vec::new();
+        for value in values {
+            match parse_tags(value) {
+                // `*` matches any current representation.
+                None => return IfMatch(Some(vec!["*".to_owned()])),
+                Some(parsed) => tags.extend(parsed),
+            }
+        }
+        IfMatch(Some(tags))
+    }
+
+    /// Strong comparison (RFC 9110 13.1.1): weak tags never match.
+    pub fn matches(&self, current: &str) -> bool {
+        match &self.0 {
+            None => true,
+            Some(tags) => !current.starts_with("W/") && tags.iter().any(|tag| tag == "*" || tag == current),
+        }
+    }
+
+    pub fn check(&self, current: &str) -> Result<(), PreconditionFailed> {
+        if self.matches(current) {
+            Ok(())
+        } else {
+            Err(PreconditionFailed {
+                current: current.to_owned(),
+            })
+        }
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for IfMatch {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(IfMatch::from_headers(&parts.headers))
+    }
+}
+
+/// `412 Precondition Failed`, carrying the current ETag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreconditionFailed {
+    pub current: String,
+}
+
+impl IntoResponse for PreconditionFailed {
+    fn into_response(self) -> Response {
+        let mut response = (StatusCode::PRECONDITION_FAILED, "the resource has been modified").into_response();
+        if let Ok(etag) = HeaderValue::from_str(&self.current) {
+            response.headers_mut().insert(header::ETAG, etag);
+        }
+        response
+    }
+}
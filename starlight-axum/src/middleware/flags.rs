@@ -0,0 +1,301 @@
+use crate::env::{EnvError, EnvErrorKind, EnvErrors, parse_bool};
+use crate::middleware::authz::Claims;
+use crate::middleware::tenant::TenantId;
+use axum::extract::{FromRequestParts, Request};
+use axum::http::request::Parts;
+use axum::http::{HeaderName, StatusCode};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Who a flag is evaluated for.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FlagContext {
+    pub tenant: Option<String>,
+    pub user: Option<String>,
+    pub attributes: HashMap<String, String>,
+}
+
+impl FlagContext {
+    pub fn new() -> Self {
+        FlagContext::default()
+    }
+
+    pub fn with_tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.tenant = Some(tenant.into());
+        self
+    }
+
+    pub fn with_user(mut self, user: impl Into<String>) -> Self {
+        self.user = Some(user.into());
+        self
+    }
+
+    pub fn with_attribute(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.attributes.insert(key.into(), value.into());
+        self
+    }
+
+    /// What percentage rollouts are bucketed on: the user, else the tenant. Anonymous
+    /// requests of no tenant all share one bucket.
+    pub fn rollout_key(&self) -> &str {
+        self.user.as_deref().or(self.tenant.as_deref()).unwrap_or_default()
+    }
+}
+
+/// Decides whether a feature is on, e.g. backed by a flag service.
+pub trait FeatureFlags: Send + Sync + 'static {
+    fn enabled(&self, flag: &str, ctx: &FlagContext) -> bool;
+}
+
+/// How [`EnvFlagProvider`] decides a flag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlagRule {
+    On,
+    Off,
+    /// On for this percentage of rollout keys, always the same ones.
+    Percentage(u8),
+}
+
+impl FlagRule {
+    /// `on`, `off` (or any [`parse_bool`] value) or a percentage such as `25%`.
+    pub fn parse(value: &str) -> Result<FlagRule, String> {
+        let value = value.trim();
+        if let Some(percentage) = value.strip_suffix('%') {
+            return match percentage.trim().parse::<u8>() {
+                Ok(percentage) if percentage <= 100 => Ok(FlagRule::Percentage(percentage)),
+                _ => Err("expected a percentage between 0% and 100%".to_owned()),
+            };
+        }
+        match parse_bool(value) {
+            Ok(true) => Ok(FlagRule::On),
+            Ok(false) => Ok(FlagRule::Off),
+            Err(_) => Err("expected on, off or a percentage such as 25%".to_owned()),
+        }
+    }
+}
+
+/// The bucket, 0 to 99, of `key` for `flag`. FNV-1a, so it is the same in every process
+/// and release.
+pub fn rollout_bucket(flag: &str, key: &str) -> u8 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in flag.bytes().chain([b':']).chain(key.bytes()) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    (hash % 100) as u8
+}
+
+/// Flags set in the environment or configuration. Unknown flags are off.
+#[derive(Debug, Clone, Default)]
+pub struct EnvFlagProvider {
+    flags: HashMap<String, FlagRule>,
+}
+
+impl EnvFlagProvider {
+    pub fn new() -> Self {
+        EnvFlagProvider::default()
+    }
+
+    /// Flags from variables such as `FEATURE_NEW_CHECKOUT=25%`, see [`EnvFlagProvider::from_vars`].
+    pub fn from_env() -> Result<Self, EnvErrors> {
+        Self::from_vars("FEATURE_", std::env::vars())
+    }
+
+    /// Flags from the variables named `prefix` followed by the flag, whose values are
+    /// [`FlagRule`]s. `FEATURE_NEW_CHECKOUT` sets the flag `new_checkout`; flags are
+    /// looked up in lowercase with `-` and `.` read as `_`.
+    pub fn from_vars(prefix: &str, vars: impl IntoIterator<Item = (String, String)>) -> Result<Self, EnvErrors> {
+        let mut provider = EnvFlagProvider::new();
+        let mut errors = EnvErrors::new();
+        for (variable, value) in vars {
+            let Some(flag) = variable.strip_prefix(prefix) else {
+                continue;
+            };
+            let rule = FlagRule::parse(&value).map_err(|reason| EnvError {
+                variable: variable.clone(),
+                kind: EnvErrorKind::Invalid { value, reason },
+            });
+            if let Some(rule) = errors.check(rule) {
+                provider = provider.with_flag(flag, rule);
+            }
+        }
+        errors.into_result().map(|()| provider)
+    }
+
+    pub fn with_flag(mut self, flag: &str, rule: FlagRule) -> Self {
+        self.flags.insert(normalize(flag), rule);
+        self
+    }
+}
+
+fn normalize(flag: &str) -> String {
+    flag.trim().to_ascii_lowercase().replace(['-', '.'], "_")
+}
+
+impl FeatureFlags for EnvFlagProvider {
+    fn enabled(&self, flag: &str, ctx: &FlagContext) -> bool {
+        let flag = normalize(flag);
+        match self.flags.get(&flag) {
+            Some(FlagRule::On) => true,
+            Some(FlagRule::Percentage(percentage)) => rollout_bucket(&flag, ctx.rollout_key()) < *percentage,
+            Some(FlagRule::Off) | None => false,
+        }
+    }
+}
+
+/// The feature flags of a request, installed by [`FeatureFlagLayer`].
+///
+/// Every flag evaluated through [`Flags::enabled`] is recorded on the request span as a
+/// `feature_flag.<flag>` attribute, so traces show which code paths a request took.
+#[derive(Clone)]
+pub struct Flags {
+    provider: Arc<dyn FeatureFlags>,
+    context: FlagContext,
+    span: Span,
+}
+
+impl Flags {
+    pub fn enabled(&self, flag: &str) -> bool {
+        let enabled = self.provider.enabled(flag, &self.context);
+        self.span.set_attribute(format!("feature_flag.{}", flag), enabled);
+        enabled
+    }
+
+    pub fn context(&self) -> &FlagContext {
+        &self.context
+    }
+}
+
+impl std::fmt::Debug for Flags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Flags").field("context", &self.context).finish_non_exhaustive()
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Flags {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<Flags>().cloned().ok_or((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Flags is missing, is FeatureFlagLayer installed?",
+        ))
+    }
+}
+
+#[derive(Clone)]
+struct FlagConfig {
+    provider: Arc<dyn FeatureFlags>,
+    user_claim: String,
+    attribute_headers: Vec<HeaderName>,
+}
+
+/// Installs a [`FeatureFlags`] provider for handlers to take as [`Flags`].
+///
+/// Flags are evaluated for the [`TenantId`] of a
+/// [`TenantLayer`](crate::middleware::tenant::TenantLayer) and the user in the `sub`
+/// claim of the [`Claims`], so this layer goes inside both. Headers named with
+/// [`FeatureFlagLayer::with_attribute_headers`] become attributes of the context.
+#[derive(Clone)]
+pub struct FeatureFlagLayer {
+    config: Arc<FlagConfig>,
+}
+
+impl FeatureFlagLayer {
+    pub fn new(provider: impl FeatureFlags) -> Self {
+        FeatureFlagLayer {
+            config: Arc::new(FlagConfig {
+                provider: Arc::new(provider),
+                user_claim: "sub".to_owned(),
+                attribute_headers: Vec::new(),
+            }),
+        }
+    }
+
+    /// The claim identifying the user, `sub` by default.
+    pub fn with_user_claim(mut self, claim: impl Into<String>) -> Self {
+        self.config_mut().user_claim = claim.into();
+        self
+    }
+
+    pub fn with_attribute_headers(mut self, headers: impl IntoIterator<Item = HeaderName>) -> Self {
+        self.config_mut().attribute_headers = headers.into_iter().collect();
+        self
+    }
+
+    fn config_mut(&mut self) -> &mut FlagConfig {
+        Arc::make_mut(&mut self.config)
+    }
+
+    fn context<B>(&self, req: &Request<B>) -> FlagContext {
+        let config = &self.config;
+        let user = req
+            .extensions()
+            .get::<Claims>()
+            .and_then(|claims| claims.get(&config.user_claim))
+            .map(|user| match user {
+                serde_json::Value::String(user) => user.clone(),
+                other => other.to_string(),
+            });
+        let attributes = config
+            .attribute_headers
+            .iter()
+            .filter_map(|name| {
+                let value = req.headers().get(name)?.to_str().ok()?;
+                Some((name.to_string(), value.to_owned()))
+            })
+            .collect();
+        FlagContext {
+            tenant: req.extensions().get::<TenantId>().map(ToString::to_string),
+            user,
+            attributes,
+        }
+    }
+}
+
+impl<S> Layer<S> for FeatureFlagLayer {
+    type Service = FeatureFlagService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        FeatureFlagService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct FeatureFlagService<S> {
+    inner: S,
+    layer: FeatureFlagLayer,
+}
+
+impl<S, B> Service<Request<B>> for FeatureFlagService<S>
+where
+    S: Service<Request<B>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        let flags = Flags {
+            provider: self.layer.config.provider.clone(),
+            context: self.layer.context(&req),
+            span: Span::current(),
+        };
+        req.extensions_mut().insert(flags);
+        Box::pin(self.inner.call(req))
+    }
+}
@@ -0,0 +1,317 @@
+use crate::crash::catch_unwind;
+use crate::task::panic_message;
+use axum::body::{Body, Bytes, HttpBody};
+use axum::extract::Request;
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header, request, response};
+use axum::response::{IntoResponse, Response};
+use http_body_util::BodyExt;
+use std::convert::Infallible;
+use std::fmt;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::pin::{Pin, pin};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+/// The future of a hook, borrowing what it inspects.
+pub type HookFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+type RequestHook =
+    dyn for<'a> Fn(&'a mut request::Parts, &'a mut BodyBuffer) -> HookFuture<'a, HookDecision> + Send + Sync;
+type ResponseHook = dyn for<'a> Fn(&'a mut response::Parts, &'a mut BodyBuffer) -> HookFuture<'a, ()> + Send + Sync;
+
+/// What a request hook lets happen next.
+#[derive(Debug)]
+pub enum HookDecision {
+    /// Run the next hooks and the handler.
+    Continue,
+    /// Answer with this response instead, skipping the later hooks and the handler.
+    ShortCircuit(Response),
+}
+
+/// The body of a request or response, as far as the [`HookLayer`] buffered it.
+#[derive(Debug, Clone, Default)]
+pub struct BodyBuffer {
+    bytes: Option<Bytes>,
+}
+
+impl BodyBuffer {
+    /// The body, `None` when it is over the budget of the layer and streams through.
+    pub fn get(&self) -> Option<&Bytes> {
+        self.bytes.as_ref()
+    }
+
+    /// Replaces the body, also one that was not buffered.
+    pub fn set(&mut self, body: impl Into<Bytes>) {
+        self.bytes = Some(body.into());
+    }
+}
+
+/// An experiment run by a [`HookLayer`], such as a header rewrite or a canary response
+/// override, as plain closures returning boxed futures:
+///
+/// ```ignore
+/// Hook::new("tag-canary").on_request(|parts, _body| {
+///     Box::pin(async move {
+///         parts.headers.insert("x-canary", HeaderValue::from_static("true"));
+///         HookDecision::Continue
+///     })
+/// })
+/// ```
+#[derive(Clone)]
+pub struct Hook {
+    name: Arc<str>,
+    order: i32,
+    paths: Vec<String>,
+    on_request: Option<Arc<RequestHook>>,
+    on_response: Option<Arc<ResponseHook>>,
+    disabled: Arc<AtomicBool>,
+}
+
+impl fmt::Debug for Hook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hook")
+            .field("name", &self.name)
+            .field("order", &self.order)
+            .field("paths", &self.paths)
+            .field("disabled", &self.is_disabled())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Hook {
+    pub fn new(name: &str) -> Self {
+        Hook {
+            name: Arc::from(name),
+            order: 0,
+            paths: Vec::new(),
+            on_request: None,
+            on_response: None,
+            disabled: Arc::default(),
+        }
+    }
+
+    pub fn on_request<F>(mut self, hook: F) -> Self
+    where
+        F: for<'a> Fn(&'a mut request::Parts, &'a mut BodyBuffer) -> HookFuture<'a, HookDecision>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.on_request = Some(Arc::new(hook));
+        self
+    }
+
+    pub fn on_response<F>(mut self, hook: F) -> Self
+    where
+        F: for<'a> Fn(&'a mut response::Parts, &'a mut BodyBuffer) -> HookFuture<'a, ()> + Send + Sync + 'static,
+    {
+        self.on_response = Some(Arc::new(hook));
+        self
+    }
+
+    /// Hooks with a lower order see the request first and the response last; hooks of
+    /// the same order run in the order they were added.
+    pub fn with_order(mut self, order: i32) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Runs only for paths under one of `prefixes`, instead of every path.
+    pub fn with_paths<I, T>(mut self, prefixes: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.paths = prefixes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Whether the hook panicked and no longer runs.
+    pub fn is_disabled(&self) -> bool {
+        self.disabled.load(Ordering::Relaxed)
+    }
+
+    fn applies(&self, path: &str) -> bool {
+        !self.is_disabled() && (self.paths.is_empty() || self.paths.iter().any(|prefix| path.starts_with(prefix)))
+    }
+
+    /// Runs `hook`, disabling this hook and returning `None` if it panics.
+    async fn isolate<'a, T>(&self, hook: impl FnOnce() -> HookFuture<'a, T>) -> Option<T> {
+        let result = match catch_unwind(AssertUnwindSafe(hook)) {
+            Ok(future) => {
+                let mut future = pin!(future);
+                std::future::poll_fn(|cx| match catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(cx))) {
+                    Ok(poll) => poll.map(Ok),
+                    Err(payload) => Poll::Ready(Err(payload)),
+                })
+                .await
+            }
+            Err(payload) => Err(payload),
+        };
+        result
+            .map_err(|payload| {
+                if !self.disabled.swap(true, Ordering::Relaxed) {
+                    error!(hook = %self.name, panic = %panic_message(&*payload), "hook panicked and was disabled");
+                }
+            })
+            .ok()
+    }
+}
+
+/// Runs [`Hook`]s on the requests and responses through it, for experiments that
+/// should not need a middleware of their own. Hooks run by their order on the request,
+/// in reverse order on the response, and only on the paths they apply to; add the layer
+/// with `route_layer` or to a single route to attach hooks to some routes only.
+///
+/// Bodies up to the budget, 64 KiB by default, are buffered for the hooks; larger ones
+/// or those of unknown size stream through with an empty [`BodyBuffer`]. A hook that
+/// panics is logged and disabled, and the request carries on without it.
+#[derive(Debug, Clone)]
+pub struct HookLayer {
+    hooks: Arc<Vec<Hook>>,
+    body_budget: u64,
+}
+
+impl Default for HookLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HookLayer {
+    pub fn new() -> Self {
+        HookLayer {
+            hooks: Arc::default(),
+            body_budget: 64 * 1024,
+        }
+    }
+
+    pub fn with_hook(mut self, hook: Hook) -> Self {
+        let hooks = Arc::make_mut(&mut self.hooks);
+        hooks.push(hook);
+        hooks.sort_by_key(|hook| hook.order);
+        self
+    }
+
+    /// The largest body buffered for the hooks, in bytes.
+    pub fn with_body_budget(mut self, bytes: u64) -> Self {
+        self.body_budget = bytes;
+        self
+    }
+
+    /// Buffers `body` when it fits the budget.
+    async fn buffer(&self, body: Body) -> Result<(BodyBuffer, Option<Body>), axum::Error> {
+        let fits = body.size_hint().upper().is_some_and(|upper| upper <= self.body_budget);
+        if !fits {
+            return Ok((BodyBuffer::default(), Some(body)));
+        }
+        let bytes = body.collect().await?.to_bytes();
+        Ok((BodyBuffer { bytes: Some(bytes) }, None))
+    }
+}
+
+/// The body to send on: the buffered or replaced one, otherwise the one streaming.
+fn rebuild(headers: &mut HeaderMap, buffer: BodyBuffer, streaming: Option<Body>) -> Body {
+    match (buffer.bytes, streaming) {
+        (Some(bytes), _) => {
+            if headers.contains_key(header::CONTENT_LENGTH) {
+                headers.insert(header::CONTENT_LENGTH, HeaderValue::from(bytes.len()));
+            }
+            Body::from(bytes)
+        }
+        (None, Some(body)) => body,
+        (None, None) => Body::empty(),
+    }
+}
+
+impl<S> Layer<S> for HookLayer {
+    type Service = HookService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        HookService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HookService<S> {
+    inner: S,
+    layer: HookLayer,
+}
+
+impl<S> Service<Request> for HookService<S>
+where
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let hooks: Vec<Hook> = self
+            .layer
+            .hooks
+            .iter()
+            .filter(|hook| hook.applies(req.uri().path()))
+            .cloned()
+            .collect();
+        if hooks.is_empty() {
+            return Box::pin(inner.call(req));
+        }
+
+        let layer = self.layer.clone();
+        Box::pin(async move {
+            let (mut parts, body) = req.into_parts();
+            let (mut body, streaming) = if hooks.iter().any(|hook| hook.on_request.is_some()) {
+                match layer.buffer(body).await {
+                    Ok(buffered) => buffered,
+                    Err(err) => {
+                        debug!("failed to buffer a request for the hooks: {}", err);
+                        return Ok(StatusCode::BAD_REQUEST.into_response());
+                    }
+                }
+            } else {
+                (BodyBuffer::default(), Some(body))
+            };
+            for hook in &hooks {
+                let Some(on_request) = &hook.on_request else { continue };
+                let decision = hook.isolate(|| on_request(&mut parts, &mut body)).await;
+                if let Some(HookDecision::ShortCircuit(response)) = decision {
+                    return Ok(response);
+                }
+            }
+            let body = rebuild(&mut parts.headers, body, streaming);
+
+            let response = inner.call(Request::from_parts(parts, body)).await?;
+            if !hooks.iter().any(|hook| hook.on_response.is_some()) {
+                return Ok(response);
+            }
+            let (mut parts, body) = response.into_parts();
+            let (mut body, streaming) = match layer.buffer(body).await {
+                Ok(buffered) => buffered,
+                Err(err) => {
+                    warn!("failed to buffer a response for the hooks: {}", err);
+                    return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+                }
+            };
+            for hook in hooks.iter().rev() {
+                let Some(on_response) = &hook.on_response else { continue };
+                hook.isolate(|| on_response(&mut parts, &mut body)).await;
+            }
+            let body = rebuild(&mut parts.headers, body, streaming);
+            Ok(Response::from_parts(parts, body))
+        })
+    }
+}
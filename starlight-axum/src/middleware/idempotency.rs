@@ -0,0 +1,327 @@
+use axum::body::{Body, Bytes, HttpBody};
+use axum::extract::Request;
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use http_body_util::BodyExt;
+use ring::digest;
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tower::{Layer, Service};
+
+pub const IDEMPOTENCY_KEY: &str = "idempotency-key";
+pub const IDEMPOTENCY_REPLAYED: &str = "idempotency-replayed";
+
+/// A buffered response kept for replay.
+#[derive(Debug, Clone)]
+pub struct StoredResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl StoredResponse {
+    fn into_replay(self) -> Response {
+        let mut response = Response::new(Body::from(self.body));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers;
+        response
+            .headers_mut()
+            .insert(IDEMPOTENCY_REPLAYED, HeaderValue::from_static("true"));
+        response
+    }
+}
+
+/// Storage for responses to replay. Keys combine method, path, a digest of the caller's
+/// scope headers and the client's key.
+pub trait IdempotencyStore: Send + Sync + 'static {
+    fn get(&self, key: &str) -> impl Future<Output = Option<StoredResponse>> + Send;
+    fn put(&self, key: &str, response: StoredResponse, ttl: Duration) -> impl Future<Output = ()> + Send;
+}
+
+/// In-process store bounded to `capacity` entries, evicting the least recently used.
+#[derive(Debug)]
+pub struct MemoryIdempotencyStore {
+    capacity: usize,
+    entries: Mutex<MemoryEntries>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryEntries {
+    clock: u64,
+    map: HashMap<String, (StoredResponse, Instant, u64)>,
+}
+
+impl MemoryIdempotencyStore {
+    pub fn new(capacity: usize) -> Self {
+        MemoryIdempotencyStore {
+            capacity: capacity.max(1),
+            entries: Mutex::new(MemoryEntries::default()),
+        }
+    }
+}
+
+impl IdempotencyStore for MemoryIdempotencyStore {
+    async fn get(&self, key: &str) -> Option<StoredResponse> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.clock += 1;
+        let clock = entries.clock;
+        match entries.map.get_mut(key) {
+            Some((response, expires_at, used)) if *expires_at > Instant::now() => {
+                *used = clock;
+                Some(response.clone())
+            }
+            Some(_) => {
+                entries.map.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    async fn put(&self, key: &str, response: StoredResponse, ttl: Duration) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.clock += 1;
+        let clock = entries.clock;
+        if entries.map.len() >= self.capacity && !entries.map.contains_key(key) {
+            let now = Instant::now();
+            entries.map.retain(|_, (_, expires_at, _)| *expires_at > now);
+            if entries.map.len() >= self.capacity {
+                let oldest = entries
+                    .map
+                    .iter()
+                    .min_by_key(|(_, (_, _, used))| *used)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    entries.map.remove(&oldest);
+                }
+            }
+        }
+        entries
+            .map
+            .insert(key.to_owned(), (response, Instant::now() + ttl, clock));
+    }
+}
+
+#[derive(Debug, Clone)]
+struct IdempotencyConfig {
+    methods: Vec<Method>,
+    scope_headers: Vec<HeaderName>,
+    ttl: Duration,
+    max_body_bytes: u64,
+}
+
+impl IdempotencyConfig {
+    fn key(&self, req: &Request, client_key: &str) -> String {
+        let mut scope = digest::Context::new(&digest::SHA256);
+        for name in &self.scope_headers {
+            for value in req.headers().get_all(name) {
+                scope.update(value.as_bytes());
+                scope.update(b"\n");
+            }
+            scope.update(b"\0");
+        }
+        format!(
+            "{} {} {} {}",
+            req.method(),
+            req.uri().path(),
+            URL_SAFE_NO_PAD.encode(scope.finish()),
+            client_key
+        )
+    }
+}
+
+/// Replays the stored response when a request is retried with the same `Idempotency-Key`.
+///
+/// Keys are scoped to the caller, by the `Authorization` header unless configured
+/// otherwise, so a client cannot replay the response stored for another one.
+///
+/// Only the configured methods (POST and PATCH by default) are affected. A retry that
+/// arrives while the first request is still running gets a 409. Responses with a 5xx
+/// status or a body larger than the cap are not stored, so the client may retry them.
+#[derive(Debug)]
+pub struct IdempotencyLayer<St> {
+    store: Arc<St>,
+    config: Arc<IdempotencyConfig>,
+    in_flight: Arc<Mutex<HashSet<String>>>,
+}
+
+impl<St> Clone for IdempotencyLayer<St> {
+    fn clone(&self) -> Self {
+        IdempotencyLayer {
+            store: self.store.clone(),
+            config: self.config.clone(),
+            in_flight: self.in_flight.clone(),
+        }
+    }
+}
+
+impl<St: IdempotencyStore> IdempotencyLayer<St> {
+    pub fn new(store: St) -> Self {
+        IdempotencyLayer {
+            store: Arc::new(store),
+            config: Arc::new(IdempotencyConfig {
+                methods: vec![Method::POST, Method::PATCH],
+                scope_headers: vec![header::AUTHORIZATION],
+                ttl: Duration::from_secs(24 * 60 * 60),
+                max_body_bytes: 1024 * 1024,
+            }),
+            in_flight: Arc::default(),
+        }
+    }
+
+    pub fn with_methods(mut self, methods: impl IntoIterator<Item = Method>) -> Self {
+        Arc::make_mut(&mut self.config).methods = methods.into_iter().collect();
+        self
+    }
+
+    /// The headers identifying the caller, e.g. `Authorization` and a tenant header.
+    pub fn with_scope_headers(mut self, headers: impl IntoIterator<Item = HeaderName>) -> Self {
+        Arc::make_mut(&mut self.config).scope_headers = headers.into_iter().collect();
+        self
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        Arc::make_mut(&mut self.config).ttl = ttl;
+        self
+    }
+
+    pub fn with_max_body_bytes(mut self, max: u64) -> Self {
+        Arc::make_mut(&mut self.config).max_body_bytes = max;
+        self
+    }
+}
+
+impl<S, St> Layer<S> for IdempotencyLayer<St> {
+    type Service = IdempotencyService<S, St>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        IdempotencyService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct IdempotencyService<S, St> {
+    inner: S,
+    layer: IdempotencyLayer<St>,
+}
+
+impl<S: Clone, St> Clone for IdempotencyService<S, St> {
+    fn clone(&self) -> Self {
+        IdempotencyService {
+            inner: self.inner.clone(),
+            layer: self.layer.clone(),
+        }
+    }
+}
+
+/// Removes the key from the in-flight set even if the request future is dropped.
+struct InFlightGuard {
+    in_flight: Arc<Mutex<HashSet<String>>>,
+    key: String,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.in_flight
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.key);
+    }
+}
+
+impl<S, St> Service<Request> for IdempotencyService<S, St>
+where
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send,
+    St: IdempotencyStore,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let client_key = req
+            .headers()
+            .get(HeaderName::from_static(IDEMPOTENCY_KEY))
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let Some(client_key) = client_key.filter(|_| self.layer.config.methods.contains(req.method()))
+        else {
+            return Box::pin(inner.call(req));
+        };
+
+        let layer = self.layer.clone();
+        Box::pin(async move {
+            let key = layer.config.key(&req, &client_key);
+            if let Some(stored) = layer.store.get(&key).await {
+                return Ok(stored.into_replay());
+            }
+
+            if !layer
+                .in_flight
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(key.clone())
+            {
+                return Ok((
+                    StatusCode::CONFLICT,
+                    "a request with this idempotency key is already in progress",
+                )
+                    .into_response());
+            }
+            let _guard = InFlightGuard {
+                in_flight: layer.in_flight.clone(),
+                key: key.clone(),
+            };
+            // The first request may have stored its response and left between the lookup
+            // above and claiming the key.
+            if let Some(stored) = layer.store.get(&key).await {
+                return Ok(stored.into_replay());
+            }
+
+            let response = inner.call(req).await?;
+            let fits = response
+                .body()
+                .size_hint()
+                .upper()
+                .is_some_and(|upper| upper <= layer.config.max_body_bytes);
+            if response.status().is_server_error() || !fits {
+                return Ok(response);
+            }
+
+            let (parts, body) = response.into_parts();
+            let body = match body.collect().await {
+                Ok(collected) => collected.to_bytes(),
+                Err(err) => {
+                    warn!("failed to buffer response for idempotency key {}: {}", client_key, err);
+                    return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+                }
+            };
+            let stored = StoredResponse {
+                status: parts.status,
+                headers: parts.headers.clone(),
+                body: body.clone(),
+            };
+            layer.store.put(&key, stored, layer.config.ttl).await;
+
+            Ok(Response::from_parts(parts, Body::from(body)))
+        })
+    }
+}
@@ -0,0 +1,146 @@
+use crate::attrs;
+use crate::meter::GLOBAL_METER;
+use axum::extract::{MatchedPath, Request};
+use axum::response::Response;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Counter, Meter};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tower::{Layer, Service};
+use tracing::Span;
+
+/// Counts the requests which took longer than the budget of their route.
+pub const LATENCY_BUDGET_EXCEEDED: &str = "http.server.latency_budget.exceeded";
+
+/// The latency budget of a route, in the request extensions of the routes layered with
+/// [`budget`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencyBudget(pub Duration);
+
+/// `200.ms()` for `Duration::from_millis(200)`, to declare budgets tersely.
+pub trait Millis {
+    fn ms(self) -> Duration;
+}
+
+impl Millis for u64 {
+    fn ms(self) -> Duration {
+        Duration::from_millis(self)
+    }
+}
+
+/// A [`LatencyBudgetLayer`] of `budget`, for `Router::route_layer`:
+///
+/// ```ignore
+/// Router::new()
+///     .route("/search", get(search).route_layer(budget(200.ms())))
+///     .route("/checkout", post(checkout).route_layer(budget(800.ms())))
+/// ```
+pub fn budget(budget: Duration) -> LatencyBudgetLayer {
+    LatencyBudgetLayer::new(budget)
+}
+
+#[derive(Debug)]
+struct LatencyBudgetConfig {
+    budget: Duration,
+    exceeded: Counter<u64>,
+}
+
+/// Flags the requests of a route which take longer than its budget, the time from
+/// routing to the response head.
+///
+/// The budget is stored as a [`LatencyBudget`] request extension for handlers, and set
+/// as the `latency.budget_ms` attribute of the current span, along with
+/// `latency.budget_exceeded` once the response is ready; under
+/// [`trace_middleware`](crate::middleware::trace_middleware) that is the request span.
+/// Requests over budget are counted on [`LATENCY_BUDGET_EXCEEDED`] with their
+/// `http.route`, and logged as a warning saying how far over they were. Routes without
+/// the layer get none of these.
+#[derive(Debug, Clone)]
+pub struct LatencyBudgetLayer {
+    config: Arc<LatencyBudgetConfig>,
+}
+
+impl LatencyBudgetLayer {
+    pub fn new(budget: Duration) -> Self {
+        Self::with_meter(budget, &GLOBAL_METER)
+    }
+
+    pub fn with_meter(budget: Duration, meter: &Meter) -> Self {
+        LatencyBudgetLayer {
+            config: Arc::new(LatencyBudgetConfig {
+                budget,
+                exceeded: meter
+                    .u64_counter(LATENCY_BUDGET_EXCEEDED)
+                    .with_description("Requests which took longer than the latency budget of their route")
+                    .build(),
+            }),
+        }
+    }
+}
+
+impl<S> Layer<S> for LatencyBudgetLayer {
+    type Service = LatencyBudgetService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        LatencyBudgetService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LatencyBudgetService<S> {
+    inner: S,
+    layer: LatencyBudgetLayer,
+}
+
+impl<S, B> Service<Request<B>> for LatencyBudgetService<S>
+where
+    S: Service<Request<B>, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        let config = self.layer.config.clone();
+        let route = req
+            .extensions()
+            .get::<MatchedPath>()
+            .map_or_else(|| req.uri().path().to_owned(), |path| path.as_str().to_owned());
+        req.extensions_mut().insert(LatencyBudget(config.budget));
+        let span = Span::current();
+        attrs::record(&span, attrs! { "latency.budget_ms" => config.budget.as_millis() as i64 });
+        let started = Instant::now();
+        let future = self.inner.call(req);
+        Box::pin(async move {
+            let result = future.await;
+            let elapsed = started.elapsed();
+            let exceeded = elapsed > config.budget;
+            attrs::record(&span, attrs! { "latency.budget_exceeded" => exceeded });
+            if exceeded {
+                config.exceeded.add(1, &[KeyValue::new("http.route", route.clone())]);
+                warn!(
+                    http.route = %route,
+                    latency.budget_ms = config.budget.as_millis() as u64,
+                    latency.over_ms = (elapsed - config.budget).as_millis() as u64,
+                    "{} took {:?}, {:?} over its budget of {:?}",
+                    route,
+                    elapsed,
+                    elapsed - config.budget,
+                    config.budget
+                );
+            }
+            result
+        })
+    }
+}
@@ -0,0 +1,243 @@
+use axum::Json;
+use axum::extract::{FromRequestParts, Request};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, StatusCode, Uri, header};
+use axum::response::{IntoResponse, Response};
+use starlight_protocol::i18n::{CldrFormatter, I18nCode, Translator, ValueFormatter};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+/// The locale resolved for a request by [`LocaleLayer`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locale(String);
+
+impl Locale {
+    pub fn new(tag: impl Into<String>) -> Self {
+        Locale(tag.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for Locale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Locale {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<Locale>().cloned().ok_or((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Locale is missing, is LocaleLayer installed?",
+        ))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct LocaleConfig {
+    supported: Vec<String>,
+    default: String,
+    query_param: Option<String>,
+    cookie: Option<String>,
+}
+
+/// Resolves the request locale and stores it as a [`Locale`] extension.
+///
+/// Precedence is the query parameter, then the cookie (both only when configured),
+/// then `Accept-Language` ordered by q-value. A tag matches a supported locale exactly
+/// or by its primary language (`en-US` matches `en`); otherwise the default is used.
+#[derive(Debug, Clone)]
+pub struct LocaleLayer {
+    config: Arc<LocaleConfig>,
+}
+
+impl LocaleLayer {
+    pub fn new<I, L>(supported: I, default: impl Into<String>) -> Self
+    where
+        I: IntoIterator<Item = L>,
+        L: Into<String>,
+    {
+        LocaleLayer {
+            config: Arc::new(LocaleConfig {
+                supported: supported.into_iter().map(Into::into).collect(),
+                default: default.into(),
+                query_param: None,
+                cookie: None,
+            }),
+        }
+    }
+
+    pub fn with_query_param(mut self, name: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.config).query_param = Some(name.into());
+        self
+    }
+
+    pub fn with_cookie(mut self, name: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.config).cookie = Some(name.into());
+        self
+    }
+
+    pub fn resolve(&self, uri: &Uri, headers: &HeaderMap) -> Locale {
+        let config = &self.config;
+
+        let from_query = config.query_param.as_deref().and_then(|name| {
+            url::form_urlencoded::parse(uri.query()?.as_bytes())
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value)
+        });
+        let from_cookie = config.cookie.as_deref().and_then(|name| {
+            headers
+                .get_all(header::COOKIE)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(';'))
+                .filter_map(|pair| pair.trim().split_once('='))
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value)
+        });
+
+        let explicit = from_query.as_deref().into_iter().chain(from_cookie);
+        let accepted = headers
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok())
+            .map(parse_accept_language)
+            .unwrap_or_default();
+
+        explicit
+            .chain(accepted.iter().map(String::as_str))
+            .find_map(|tag| self.match_supported(tag))
+            .map(|tag| Locale(tag.to_owned()))
+            .unwrap_or_else(|| Locale(config.default.clone()))
+    }
+
+    fn match_supported(&self, tag: &str) -> Option<&str> {
+        let supported = &self.config.supported;
+        let primary = tag.split(['-', '_']).next().unwrap_or(tag);
+        supported
+            .iter()
+            .find(|s| s.eq_ignore_ascii_case(tag))
+            .or_else(|| supported.iter().find(|s| s.eq_ignore_ascii_case(primary)))
+            .map(String::as_str)
+    }
+}
+
+/// Language tags from an `Accept-Language` value, highest q-value first. Tags with
+/// `q=0` and `*` are dropped.
+pub fn parse_accept_language(value: &str) -> Vec<String> {
+    let mut tags: Vec<(String, f32)> = value
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let tag = parts.next()?.trim();
+            let q = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .map(|q| q.trim().parse::<f32>().unwrap_or(0.0))
+                .unwrap_or(1.0);
+            (!tag.is_empty() && tag != "*" && q > 0.0).then(|| (tag.to_owned(), q))
+        })
+        .collect();
+    // Stable sort keeps the header order between equal q-values.
+    tags.sort_by(|a, b| b.1.total_cmp(&a.1));
+    tags.into_iter().map(|(tag, _)| tag).collect()
+}
+
+impl<S> Layer<S> for LocaleLayer {
+    type Service = LocaleService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        LocaleService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LocaleService<S> {
+    inner: S,
+    layer: LocaleLayer,
+}
+
+impl<S, B> Service<Request<B>> for LocaleService<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        let locale = self.layer.resolve(req.uri(), req.headers());
+        req.extensions_mut().insert(locale);
+        self.inner.call(req)
+    }
+}
+
+/// An error rendered as `{"code": ..., "message": ...}` in the request locale, with the
+/// [parameters](I18nCode::i18n_params) of the error formatted for it. Falls back to the
+/// i18n code when the translator has no message for it.
+#[derive(Debug, Clone)]
+pub struct I18nErrorResponse<E> {
+    status: StatusCode,
+    error: E,
+    locale: Locale,
+    message: String,
+}
+
+impl<E: I18nCode> I18nErrorResponse<E> {
+    pub fn new(status: StatusCode, error: E, locale: &Locale, translator: &dyn Translator) -> Self {
+        Self::with_formatter(status, error, locale, translator, &CldrFormatter)
+    }
+
+    /// [`I18nErrorResponse::new`] formatting the parameters with `formatter`.
+    pub fn with_formatter(
+        status: StatusCode,
+        error: E,
+        locale: &Locale,
+        translator: &dyn Translator,
+        formatter: &dyn ValueFormatter,
+    ) -> Self {
+        let code = error.get_i18n_code();
+        let message = translator
+            .translate_with(locale.as_str(), code, &error.i18n_params(), formatter)
+            .unwrap_or_else(|| code.to_owned());
+        I18nErrorResponse {
+            status,
+            error,
+            locale: locale.clone(),
+            message,
+        }
+    }
+
+    pub fn error(&self) -> &E {
+        &self.error
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl<E: I18nCode> IntoResponse for I18nErrorResponse<E> {
+    fn into_response(self) -> Response {
+        let body = serde_json::json!({
+            "code": self.error.get_i18n_code(),
+            "message": self.message,
+        });
+        let mut response = (self.status, Json(body)).into_response();
+        if let Ok(value) = self.locale.as_str().parse() {
+            response.headers_mut().insert(header::CONTENT_LANGUAGE, value);
+        }
+        response
+    }
+}
@@ -0,0 +1,200 @@
+use crate::config::DynamicConfig;
+use crate::meter::GLOBAL_METER;
+use axum::Json;
+use axum::extract::Request;
+use axum::http::{HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use opentelemetry::metrics::{Meter, ObservableGauge};
+use serde_json::json;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::watch;
+use tower::{Layer, Service};
+
+pub const MAINTENANCE_ACTIVE: &str = "maintenance.active";
+
+/// Turns maintenance mode on and off at runtime. Clones control the same switch.
+#[derive(Debug, Clone)]
+pub struct MaintenanceSwitch {
+    state: Arc<watch::Sender<bool>>,
+}
+
+impl Default for MaintenanceSwitch {
+    fn default() -> Self {
+        Self::new(false)
+    }
+}
+
+impl MaintenanceSwitch {
+    pub fn new(enabled: bool) -> Self {
+        MaintenanceSwitch {
+            state: Arc::new(watch::Sender::new(enabled)),
+        }
+    }
+
+    pub fn enable(&self) {
+        self.set(true);
+    }
+
+    pub fn disable(&self) {
+        self.set(false);
+    }
+
+    pub fn set(&self, enabled: bool) {
+        let previous = self.state.send_replace(enabled);
+        if previous != enabled {
+            info!(maintenance = enabled, "maintenance mode changed");
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        *self.state.borrow()
+    }
+
+    /// Notified on every change, e.g. to pause background jobs during maintenance.
+    pub fn subscribe(&self) -> watch::Receiver<bool> {
+        self.state.subscribe()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct MaintenanceConfig {
+    allowed_prefixes: DynamicConfig<Vec<String>>,
+    retry_after: Duration,
+    message: String,
+}
+
+/// Answers every request with `503 Service Unavailable`, a `Retry-After` header and a
+/// JSON explanation while its [`MaintenanceSwitch`] is on, except for paths under the
+/// allowlisted prefixes (`/health` and `/admin` by default). Prefixes match whole path
+/// segments: `/admin` allows `/admin` and `/admin/users`, not `/administrator`.
+///
+/// The `maintenance.active` gauge reports 1 while the switch is on.
+#[derive(Debug, Clone)]
+pub struct MaintenanceLayer {
+    switch: MaintenanceSwitch,
+    config: Arc<MaintenanceConfig>,
+    _gauge: ObservableGauge<u64>,
+}
+
+impl MaintenanceLayer {
+    pub fn new(switch: MaintenanceSwitch) -> Self {
+        Self::with_meter(switch, &GLOBAL_METER)
+    }
+
+    pub fn with_meter(switch: MaintenanceSwitch, meter: &Meter) -> Self {
+        let observed = switch.clone();
+        let gauge = meter
+            .u64_observable_gauge(MAINTENANCE_ACTIVE)
+            .with_description("1 while the service is in maintenance mode")
+            .with_callback(move |observer| observer.observe(observed.is_enabled() as u64, &[]))
+            .build();
+        MaintenanceLayer {
+            switch,
+            config: Arc::new(MaintenanceConfig {
+                allowed_prefixes: DynamicConfig::new(vec!["/health".to_owned(), "/admin".to_owned()]),
+                retry_after: Duration::from_secs(120),
+                message: "the service is down for maintenance".to_owned(),
+            }),
+            _gauge: gauge,
+        }
+    }
+
+    /// Path prefixes served during maintenance, replacing the defaults.
+    pub fn with_allowed_prefixes<I, T>(self, prefixes: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        let prefixes: Vec<String> = prefixes.into_iter().map(Into::into).collect();
+        self.with_dynamic_allowed_prefixes(prefixes)
+    }
+
+    /// Like [`MaintenanceLayer::with_allowed_prefixes`], following changes of `prefixes`,
+    /// e.g. the `maintenance.allowed_prefixes` of a watched
+    /// [`AppConfig`](crate::config::AppConfig).
+    pub fn with_dynamic_allowed_prefixes(mut self, prefixes: impl Into<DynamicConfig<Vec<String>>>) -> Self {
+        Arc::make_mut(&mut self.config).allowed_prefixes = prefixes.into();
+        self
+    }
+
+    pub fn with_retry_after(mut self, retry_after: Duration) -> Self {
+        Arc::make_mut(&mut self.config).retry_after = retry_after;
+        self
+    }
+
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.config).message = message.into();
+        self
+    }
+}
+
+impl MaintenanceConfig {
+    fn allows(&self, path: &str) -> bool {
+        self.allowed_prefixes.get().iter().any(|prefix| {
+            let prefix = prefix.trim_end_matches('/');
+            path.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+    }
+
+    fn unavailable(&self) -> Response {
+        let retry_after = self.retry_after.as_secs();
+        let mut response = (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({
+                "error": "maintenance",
+                "message": self.message,
+                "retry_after": retry_after,
+            })),
+        )
+            .into_response();
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+        response
+    }
+}
+
+impl<S> Layer<S> for MaintenanceLayer {
+    type Service = MaintenanceService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MaintenanceService {
+            inner,
+            switch: self.switch.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MaintenanceService<S> {
+    inner: S,
+    switch: MaintenanceSwitch,
+    config: Arc<MaintenanceConfig>,
+}
+
+impl<S> Service<Request> for MaintenanceService<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        if self.switch.is_enabled() && !self.config.allows(req.uri().path()) {
+            let response = self.config.unavailable();
+            return Box::pin(async move { Ok(response) });
+        }
+        Box::pin(self.inner.call(req))
+    }
+}
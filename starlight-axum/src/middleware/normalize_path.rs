@@ -0,0 +1,256 @@
+use axum::Router;
+use axum::extract::{OriginalUri, Request};
+use axum::http::uri::PathAndQuery;
+use axum::http::{HeaderValue, StatusCode, Uri, header};
+use axum::response::{IntoResponse, Response};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+/// What [`NormalizePathLayer`] does with trailing slashes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TrailingSlash {
+    /// `/users/` becomes `/users`.
+    #[default]
+    Strip,
+    /// `/users` becomes `/users/`.
+    Require,
+    /// Left as sent.
+    Keep,
+}
+
+/// How [`NormalizePathLayer`] hands a request whose path it changed to the router.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Normalization {
+    /// The request is routed with the normalized path; handlers find the path as sent
+    /// in [`OriginalUri`].
+    #[default]
+    Rewrite,
+    /// The client is sent to the normalized path with `308 Permanent Redirect`.
+    Redirect,
+}
+
+/// A route pattern whose `{param}` segments, and the rest of the path from a
+/// `{*param}` one, keep their case.
+#[derive(Debug, Clone)]
+struct CaseSensitive {
+    segments: Vec<String>,
+}
+
+impl CaseSensitive {
+    /// Whether each segment keeps its case, `None` when `segments` do not match.
+    fn kept(&self, segments: &[&str]) -> Option<Vec<bool>> {
+        let mut kept = Vec::with_capacity(segments.len());
+        for (index, pattern) in self.segments.iter().enumerate() {
+            if pattern.starts_with("{*") {
+                kept.resize(segments.len(), true);
+                return Some(kept);
+            }
+            let segment = segments.get(index)?;
+            if pattern.starts_with('{') {
+                kept.push(true);
+            } else if pattern.eq_ignore_ascii_case(segment) {
+                kept.push(false);
+            } else {
+                return None;
+            }
+        }
+        (kept.len() == segments.len()).then_some(kept)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct NormalizePathConfig {
+    trailing_slash: TrailingSlash,
+    normalization: Normalization,
+    case_insensitive: bool,
+    case_sensitive: Vec<CaseSensitive>,
+    collapse_slashes: bool,
+}
+
+/// Normalizes request paths before they are routed, so `/Users/` and `//users` reach
+/// the `/users` route: duplicate slashes are collapsed, trailing slashes stripped (or
+/// required, see [`TrailingSlash`]) and, when case-insensitive, the path is lowercased
+/// except for the parameters of the [case-sensitive](Self::with_case_sensitive) routes.
+///
+/// The router must run inside the layer, so it is applied with [`wrap`](Self::wrap)
+/// rather than `Router::layer`, after the other layers of the stack. Those then see the
+/// normalized path in the URI and in `MatchedPath`, which name the request span and
+/// label the HTTP metrics.
+#[derive(Debug, Clone)]
+pub struct NormalizePathLayer {
+    config: Arc<NormalizePathConfig>,
+}
+
+impl Default for NormalizePathLayer {
+    fn default() -> Self {
+        NormalizePathLayer {
+            config: Arc::new(NormalizePathConfig {
+                trailing_slash: TrailingSlash::Strip,
+                normalization: Normalization::Rewrite,
+                case_insensitive: false,
+                case_sensitive: Vec::new(),
+                collapse_slashes: true,
+            }),
+        }
+    }
+}
+
+impl NormalizePathLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_trailing_slash(mut self, trailing_slash: TrailingSlash) -> Self {
+        Arc::make_mut(&mut self.config).trailing_slash = trailing_slash;
+        self
+    }
+
+    pub fn with_normalization(mut self, normalization: Normalization) -> Self {
+        Arc::make_mut(&mut self.config).normalization = normalization;
+        self
+    }
+
+    /// Lowercases paths, so routes must be declared in lowercase.
+    pub fn with_case_insensitive(mut self, case_insensitive: bool) -> Self {
+        Arc::make_mut(&mut self.config).case_insensitive = case_insensitive;
+        self
+    }
+
+    /// Keeps the case of the parameters of `route`, e.g. `/files/{id}` for base64 IDs or
+    /// `/assets/{*path}`. Its literal segments still match in any case.
+    pub fn with_case_sensitive(mut self, route: &str) -> Self {
+        let segments = route.split('/').filter(|s| !s.is_empty()).map(str::to_owned).collect();
+        Arc::make_mut(&mut self.config)
+            .case_sensitive
+            .push(CaseSensitive { segments });
+        self
+    }
+
+    /// Defaults to collapsing `//users///` into `/users/` before the trailing slash policy.
+    pub fn with_collapse_slashes(mut self, collapse_slashes: bool) -> Self {
+        Arc::make_mut(&mut self.config).collapse_slashes = collapse_slashes;
+        self
+    }
+
+    /// `router` behind the layer.
+    pub fn wrap(self, router: Router) -> Router {
+        Router::new().fallback_service(self.layer(router))
+    }
+
+    /// The normalized form of `path`.
+    pub fn normalize(&self, path: &str) -> String {
+        let config = &self.config;
+        let trailing = path.len() > 1 && path.ends_with('/');
+        let mut segments: Vec<&str> = path.strip_prefix('/').unwrap_or(path).split('/').collect();
+        if config.collapse_slashes {
+            segments.retain(|segment| !segment.is_empty());
+        } else if segments.last() == Some(&"") {
+            segments.pop();
+        }
+
+        let mut normalized = String::with_capacity(path.len() + 1);
+        let kept = if config.case_insensitive {
+            config.case_sensitive.iter().find_map(|route| route.kept(&segments))
+        } else {
+            Some(vec![true; segments.len()])
+        };
+        for (index, segment) in segments.iter().enumerate() {
+            normalized.push('/');
+            if kept.as_ref().is_some_and(|kept| kept[index]) {
+                normalized.push_str(segment);
+            } else {
+                normalized.push_str(&segment.to_lowercase());
+            }
+        }
+        let trailing = match config.trailing_slash {
+            TrailingSlash::Strip => false,
+            TrailingSlash::Require => true,
+            TrailingSlash::Keep => trailing,
+        };
+        if trailing || normalized.is_empty() {
+            normalized.push('/');
+        }
+        normalized
+    }
+}
+
+impl<S> Layer<S> for NormalizePathLayer {
+    type Service = NormalizePathService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        NormalizePathService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NormalizePathService<S> {
+    inner: S,
+    layer: NormalizePathLayer,
+}
+
+impl<S, B> Service<Request<B>> for NormalizePathService<S>
+where
+    S: Service<Request<B>, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        let path = self.layer.normalize(req.uri().path());
+        if path == req.uri().path() {
+            return Box::pin(self.inner.call(req));
+        }
+        let target = match req.uri().query() {
+            Some(query) => format!("{}?{}", path, query),
+            None => path,
+        };
+        debug!("normalized {} to {}", req.uri().path(), target);
+
+        if self.layer.config.normalization == Normalization::Redirect {
+            let response = match HeaderValue::try_from(target) {
+                Ok(location) => (StatusCode::PERMANENT_REDIRECT, [(header::LOCATION, location)]).into_response(),
+                Err(_) => StatusCode::BAD_REQUEST.into_response(),
+            };
+            return Box::pin(async move { Ok(response) });
+        }
+        let original = req.uri().clone();
+        let mut parts = original.clone().into_parts();
+        parts.path_and_query = PathAndQuery::try_from(target).ok();
+        if let Ok(uri) = Uri::from_parts(parts) {
+            *req.uri_mut() = uri;
+            req.extensions_mut().get_or_insert_with(|| OriginalUri(original));
+        }
+        Box::pin(self.inner.call(req))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_paths() {
+        let layer = NormalizePathLayer::new().with_case_insensitive(true);
+        assert_eq!(layer.normalize("/"), "/");
+        assert_eq!(layer.normalize("//"), "/");
+        assert_eq!(layer.normalize("//Users///42/"), "/users/42");
+
+        let layer = layer
+            .with_trailing_slash(TrailingSlash::Require)
+            .with_collapse_slashes(false);
+        assert_eq!(layer.normalize("/users"), "/users/");
+        assert_eq!(layer.normalize("/a//B"), "/a//b/");
+    }
+}
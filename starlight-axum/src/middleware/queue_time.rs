@@ -0,0 +1,236 @@
+use crate::attrs;
+use crate::meter::GLOBAL_METER;
+use axum::extract::{FromRequestParts, Request};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, HeaderName, StatusCode};
+use opentelemetry::metrics::{Histogram, Meter};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use tower::{Layer, Service};
+use tracing::Span;
+
+pub const X_REQUEST_START: &str = "x-request-start";
+
+/// How the upstream proxy writes the time it received the request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpstreamTimestamp {
+    /// nginx `t=${msec}`: seconds since the epoch with a millisecond fraction.
+    NginxSeconds,
+    /// Heroku style milliseconds since the epoch, with or without `t=`.
+    EpochMillis,
+    /// An RFC 3339 timestamp.
+    Rfc3339,
+}
+
+impl UpstreamTimestamp {
+    pub fn parse(&self, value: &str) -> Option<SystemTime> {
+        let value = value.trim();
+        let number = value.strip_prefix("t=").unwrap_or(value);
+        match self {
+            UpstreamTimestamp::NginxSeconds => {
+                let seconds: f64 = number.parse().ok()?;
+                UNIX_EPOCH.checked_add(Duration::try_from_secs_f64(seconds).ok()?)
+            }
+            // Fewer digits than a millisecond timestamp since 2001 is another format.
+            UpstreamTimestamp::EpochMillis if number.len() >= 12 => {
+                UNIX_EPOCH.checked_add(Duration::from_millis(number.parse().ok()?))
+            }
+            UpstreamTimestamp::EpochMillis => None,
+            UpstreamTimestamp::Rfc3339 => OffsetDateTime::parse(value, &Rfc3339).ok().map(SystemTime::from),
+        }
+    }
+}
+
+/// Time between the upstream proxy receiving the request and this service seeing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueTime(pub Duration);
+
+impl<S: Send + Sync> FromRequestParts<S> for QueueTime {
+    type Rejection = (StatusCode, &'static str);
+
+    /// Only present when the request carried a usable timestamp; extract
+    /// `Option<QueueTime>` to handle requests without one.
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<QueueTime>().copied().ok_or((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "QueueTime is missing, is QueueTimeLayer installed?",
+        ))
+    }
+}
+
+/// The queue time for `start`: negative skew clamps to zero and anything above `max`
+/// is treated as bogus.
+pub fn queue_duration(start: SystemTime, now: SystemTime, max: Duration) -> Option<Duration> {
+    let queued = now.duration_since(start).unwrap_or(Duration::ZERO);
+    (queued <= max).then_some(queued)
+}
+
+#[derive(Debug, Clone)]
+struct QueueTimeConfig {
+    header: HeaderName,
+    formats: Vec<UpstreamTimestamp>,
+    max: Duration,
+    histogram: Histogram<f64>,
+}
+
+/// Measures queue time from the upstream timestamp header (`x-request-start` by default).
+///
+/// The result is stored as a [`QueueTime`] extension, recorded on the
+/// `http.server.queue.duration` histogram and set as the attribute of the same name of
+/// the current span. Requests without a parseable header are left alone.
+#[derive(Debug, Clone)]
+pub struct QueueTimeLayer {
+    config: Arc<QueueTimeConfig>,
+}
+
+impl Default for QueueTimeLayer {
+    fn default() -> Self {
+        Self::with_meter(&GLOBAL_METER)
+    }
+}
+
+impl QueueTimeLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_meter(meter: &Meter) -> Self {
+        QueueTimeLayer {
+            config: Arc::new(QueueTimeConfig {
+                header: HeaderName::from_static(X_REQUEST_START),
+                formats: vec![
+                    UpstreamTimestamp::EpochMillis,
+                    UpstreamTimestamp::NginxSeconds,
+                    UpstreamTimestamp::Rfc3339,
+                ],
+                max: Duration::from_secs(60),
+                histogram: meter
+                    .f64_histogram("http.server.queue.duration")
+                    .with_description("Time requests spent queued before reaching the service")
+                    .with_unit("s")
+                    .build(),
+            }),
+        }
+    }
+
+    pub fn with_header(mut self, header: HeaderName) -> Self {
+        Arc::make_mut(&mut self.config).header = header;
+        self
+    }
+
+    /// Formats to try, in order.
+    pub fn with_formats(mut self, formats: impl IntoIterator<Item = UpstreamTimestamp>) -> Self {
+        Arc::make_mut(&mut self.config).formats = formats.into_iter().collect();
+        self
+    }
+
+    /// Queue times above `max` are dropped as clock skew or a bogus header.
+    pub fn with_max(mut self, max: Duration) -> Self {
+        Arc::make_mut(&mut self.config).max = max;
+        self
+    }
+
+    pub fn measure(&self, headers: &HeaderMap, now: SystemTime) -> Option<QueueTime> {
+        let config = &self.config;
+        let value = headers.get(&config.header)?.to_str().ok()?;
+        let start = config.formats.iter().find_map(|format| format.parse(value))?;
+        queue_duration(start, now, config.max).map(QueueTime)
+    }
+}
+
+impl<S> Layer<S> for QueueTimeLayer {
+    type Service = QueueTimeService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        QueueTimeService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct QueueTimeService<S> {
+    inner: S,
+    layer: QueueTimeLayer,
+}
+
+impl<S, B> Service<Request<B>> for QueueTimeService<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        if let Some(queue_time) = self.layer.measure(req.headers(), SystemTime::now()) {
+            let seconds = queue_time.0.as_secs_f64();
+            self.layer.config.histogram.record(seconds, &[]);
+            attrs::record(&Span::current(), attrs! { "http.server.queue.duration" => seconds });
+            req.extensions_mut().insert(queue_time);
+        }
+        self.inner.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn at(millis: u64) -> SystemTime {
+        UNIX_EPOCH.checked_add(Duration::from_millis(millis)).unwrap()
+    }
+
+    fn measure(value: &str, now: SystemTime) -> Option<Duration> {
+        let mut headers = HeaderMap::new();
+        headers.insert(X_REQUEST_START, HeaderValue::from_str(value).unwrap());
+        QueueTimeLayer::new()
+            .with_max(Duration::from_secs(30))
+            .measure(&headers, now)
+            .map(|queue_time| queue_time.0)
+    }
+
+    #[test]
+    fn parses_each_format() {
+        let now = at(1_700_000_000_250);
+        assert_eq!(measure("t=1700000000.125", now), Some(Duration::from_millis(125)));
+        assert_eq!(measure("1700000000050", now), Some(Duration::from_millis(200)));
+        assert_eq!(measure("t=1700000000050", now), Some(Duration::from_millis(200)));
+        assert_eq!(measure("2023-11-14T22:13:20.100Z", now), Some(Duration::from_millis(150)));
+    }
+
+    #[test]
+    fn clamps_skew_and_drops_bogus_values() {
+        let now = at(1_700_000_000_000);
+        // Upstream clock ahead of ours.
+        assert_eq!(measure("1700000000500", now), Some(Duration::ZERO));
+        // Older than the cap.
+        assert_eq!(measure("1600000000000", now), None);
+        assert_eq!(measure("t=1700000000", now), Some(Duration::ZERO));
+        assert_eq!(measure("soon", now), None);
+    }
+
+    #[test]
+    fn out_of_range_values_are_dropped() {
+        let now = at(1_700_000_000_000);
+        assert_eq!(measure("t=1e30", now), None);
+        assert_eq!(measure("t=1e19", now), None);
+        assert_eq!(measure("t=-1", now), None);
+        assert_eq!(measure("99999999999999999999", now), None);
+    }
+
+    #[test]
+    fn missing_header_is_ignored() {
+        let layer = QueueTimeLayer::new();
+        assert_eq!(layer.measure(&HeaderMap::new(), SystemTime::now()), None);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::config::{DynamicConfig, RateLimitConfig};
+use crate::meter::GLOBAL_METER;
+use axum::Json;
+use axum::extract::Request;
+use axum::http::{HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use opentelemetry::metrics::{Counter, Meter};
+use serde_json::json;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tower::{Layer, Service};
+
+pub const RATE_LIMITED: &str = "http.server.rate_limited";
+
+/// The tokens left and the limits they were counted against.
+#[derive(Debug)]
+struct Bucket {
+    limits: Arc<RateLimitConfig>,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    /// Takes a token for a request, or returns the seconds until the next one.
+    fn take(&mut self, limits: Arc<RateLimitConfig>) -> Result<(), u64> {
+        let Some(rate) = limits.requests_per_second.filter(|rate| *rate > 0) else {
+            return Ok(());
+        };
+        let burst = f64::from(limits.burst.unwrap_or(rate).max(1));
+        let rate = f64::from(rate);
+        let now = Instant::now();
+        if *self.limits != *limits {
+            // New limits start with a full bucket rather than the debt of the old ones.
+            *self = Bucket {
+                limits,
+                tokens: burst,
+                updated: now,
+            };
+        } else {
+            let elapsed = now.duration_since(self.updated).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * rate).min(burst);
+            self.updated = now;
+        }
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - self.tokens) / rate).ceil() as u64)
+        }
+    }
+}
+
+/// Limits the requests through it to `requests_per_second` with bursts of `burst`,
+/// answering the others with `429 Too Many Requests`, a `Retry-After` header and a JSON
+/// explanation. The limit is shared by every request, counted from a token bucket.
+///
+/// Given a [`DynamicConfig`], such as the `rate_limit` section of a watched
+/// [`AppConfig`](crate::config::AppConfig), new limits apply from the next request. The
+/// rejected requests are counted on `http.server.rate_limited`.
+#[derive(Debug, Clone)]
+pub struct RateLimitLayer {
+    limits: DynamicConfig<RateLimitConfig>,
+    bucket: Arc<Mutex<Bucket>>,
+    rejected: Counter<u64>,
+}
+
+impl RateLimitLayer {
+    pub fn new(limits: impl Into<DynamicConfig<RateLimitConfig>>) -> Self {
+        let limits = limits.into();
+        RateLimitLayer {
+            bucket: Arc::new(Mutex::new(Bucket {
+                limits: Arc::new(RateLimitConfig::default()),
+                tokens: 0.0,
+                updated: Instant::now(),
+            })),
+            limits,
+            rejected: rejected(&GLOBAL_METER),
+        }
+    }
+
+    pub fn with_meter(mut self, meter: &Meter) -> Self {
+        self.rejected = rejected(meter);
+        self
+    }
+
+    /// The response to a request over the limit, `None` when it may go through.
+    fn check(&self) -> Option<Response> {
+        let limits = self.limits.get();
+        let taken = self.bucket.lock().unwrap_or_else(|e| e.into_inner()).take(limits);
+        taken.err().map(|retry_after| {
+            self.rejected.add(1, &[]);
+            too_many_requests(retry_after)
+        })
+    }
+}
+
+fn rejected(meter: &Meter) -> Counter<u64> {
+    meter
+        .u64_counter(RATE_LIMITED)
+        .with_description("Requests rejected by the rate limit")
+        .build()
+}
+
+fn too_many_requests(retry_after: u64) -> Response {
+    let mut response = (
+        StatusCode::TOO_MANY_REQUESTS,
+        Json(json!({
+            "error": "rate_limited",
+            "message": "too many requests",
+            "retry_after": retry_after,
+        })),
+    )
+        .into_response();
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    response
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitService<S> {
+    inner: S,
+    layer: RateLimitLayer,
+}
+
+impl<S> Service<Request> for RateLimitService<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        if let Some(response) = self.layer.check() {
+            return Box::pin(async move { Ok(response) });
+        }
+        Box::pin(self.inner.call(req))
+    }
+}
@@ -0,0 +1,412 @@
+//! Validation of JSON request and response bodies against per-route JSON Schemas.
+
+mod json_schema;
+
+pub use json_schema::{JsonSchema, SchemaError, Violation};
+
+use crate::meter::GLOBAL_METER;
+use axum::body::{Body, Bytes};
+use axum::extract::{MatchedPath, Request};
+use axum::http::{HeaderMap, Method, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use http_body::{Body as _, Frame, SizeHint};
+use http_body_util::{BodyExt, LengthLimitError, Limited};
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Counter, Meter};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+pub const SCHEMA_VIOLATIONS: &str = "http.server.schema.violations";
+
+/// What happens to a response which does not match its schema.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResponseValidation {
+    /// The response is sent as it is while it streams, and checked once complete: its
+    /// violations are logged and counted.
+    #[default]
+    LogOnly,
+    /// The response is buffered and checked before it is sent, and replaced with a 500
+    /// problem details body when it does not match.
+    Strict,
+}
+
+#[derive(Debug, Clone, Default)]
+struct RouteSchemas {
+    request: Option<Arc<JsonSchema>>,
+    response: Option<Arc<JsonSchema>>,
+}
+
+#[derive(Debug, Clone)]
+struct SchemaConfig {
+    routes: HashMap<(Method, String), RouteSchemas>,
+    response_validation: ResponseValidation,
+    max_body_bytes: usize,
+    violations: Counter<u64>,
+}
+
+/// Checks JSON bodies against the schemas of their route, applied to a router with
+/// `.route_layer(layer)` so routes are known by their pattern such as `/orders/{id}`.
+///
+/// Requests whose body does not match are answered with 422 and a problem details body
+/// whose reason is `schema.request` and whose `violations` list the JSON pointer and
+/// message of each, before reaching the handler. Responses are checked as configured
+/// by [`ResponseValidation`]. Bodies whose content type is not JSON (`application/json`
+/// or `+json`) and malformed JSON, left to the handler's extractor, are not checked.
+///
+/// Bodies are buffered up to 2 MiB by default: larger requests are answered with 413
+/// and larger responses are not checked. Violations are counted on
+/// `http.server.schema.violations` with the route, method and `direction`, `request`
+/// or `response`.
+///
+/// ```
+/// use starlight_axum::axum::http::Method;
+/// use starlight_axum::middleware::schema::{JsonSchema, SchemaValidationLayer};
+///
+/// let create_user = JsonSchema::parse(r#"{"type": "object", "required": ["email"]}"#).unwrap();
+/// let layer = SchemaValidationLayer::new().with_request(Method::POST, "/users", create_user);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SchemaValidationLayer {
+    config: Arc<SchemaConfig>,
+}
+
+impl Default for SchemaValidationLayer {
+    fn default() -> Self {
+        Self::with_meter(&GLOBAL_METER)
+    }
+}
+
+impl SchemaValidationLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_meter(meter: &Meter) -> Self {
+        SchemaValidationLayer {
+            config: Arc::new(SchemaConfig {
+                routes: HashMap::new(),
+                response_validation: ResponseValidation::default(),
+                max_body_bytes: 2 * 1024 * 1024,
+                violations: meter
+                    .u64_counter(SCHEMA_VIOLATIONS)
+                    .with_description("Places where a body did not match the schema of its route")
+                    .build(),
+            }),
+        }
+    }
+
+    /// The schema of request bodies of `method` on the route pattern `route`.
+    pub fn with_request(mut self, method: Method, route: impl Into<String>, schema: JsonSchema) -> Self {
+        self.route_mut(method, route.into()).request = Some(Arc::new(schema));
+        self
+    }
+
+    /// The schema of response bodies of `method` on the route pattern `route`; only
+    /// successful (2xx) responses are checked.
+    pub fn with_response(mut self, method: Method, route: impl Into<String>, schema: JsonSchema) -> Self {
+        self.route_mut(method, route.into()).response = Some(Arc::new(schema));
+        self
+    }
+
+    pub fn with_response_validation(mut self, validation: ResponseValidation) -> Self {
+        Arc::make_mut(&mut self.config).response_validation = validation;
+        self
+    }
+
+    pub fn with_max_body_bytes(mut self, max_body_bytes: usize) -> Self {
+        Arc::make_mut(&mut self.config).max_body_bytes = max_body_bytes;
+        self
+    }
+
+    fn route_mut(&mut self, method: Method, route: String) -> &mut RouteSchemas {
+        Arc::make_mut(&mut self.config)
+            .routes
+            .entry((method, route))
+            .or_default()
+    }
+}
+
+impl<S> Layer<S> for SchemaValidationLayer {
+    type Service = SchemaValidationService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SchemaValidationService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SchemaValidationService<S> {
+    inner: S,
+    layer: SchemaValidationLayer,
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers.get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok()) else {
+        return false;
+    };
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    essence == "application/json" || (essence.starts_with("application/") && essence.ends_with("+json"))
+}
+
+fn problem(status: StatusCode, reason: &str, detail: String, violations: Option<&[Violation]>) -> Response {
+    let mut body = serde_json::json!({
+        "type": "about:blank",
+        "title": status.canonical_reason().unwrap_or("Error"),
+        "status": status.as_u16(),
+        "detail": detail,
+        "reason": reason,
+    });
+    if let Some(violations) = violations {
+        body["violations"] = serde_json::json!(violations);
+    }
+    (
+        status,
+        [(header::CONTENT_TYPE, "application/problem+json")],
+        body.to_string(),
+    )
+        .into_response()
+}
+
+/// Where a body was checked, for logs and metric attributes.
+#[derive(Clone)]
+struct Target {
+    config: Arc<SchemaConfig>,
+    method: Method,
+    route: String,
+}
+
+impl Target {
+    /// Checks `body`, logging and counting its violations; `None` when it is not JSON.
+    fn check(&self, schema: &JsonSchema, direction: &'static str, body: &[u8]) -> Option<Vec<Violation>> {
+        let document: Value = serde_json::from_slice(body).ok()?;
+        let violations = schema.validate(&document);
+        if !violations.is_empty() {
+            let attributes = [
+                KeyValue::new("http.route", self.route.clone()),
+                KeyValue::new("http.request.method", self.method.to_string()),
+                KeyValue::new("direction", direction),
+            ];
+            self.config.violations.add(violations.len() as u64, &attributes);
+            let listed: Vec<String> = violations.iter().map(Violation::to_string).collect();
+            warn!(
+                "{} body of {} {} does not match its schema: {}",
+                direction,
+                self.method,
+                self.route,
+                listed.join("; ")
+            );
+        }
+        Some(violations)
+    }
+}
+
+impl<S> Service<Request> for SchemaValidationService<S>
+where
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let route = req
+            .extensions()
+            .get::<MatchedPath>()
+            .map_or_else(|| req.uri().path().to_owned(), |path| path.as_str().to_owned());
+        let config = &self.layer.config;
+        let Some(schemas) = config.routes.get(&(req.method().clone(), route.clone())).cloned() else {
+            return Box::pin(inner.call(req));
+        };
+        let target = Target {
+            config: config.clone(),
+            method: req.method().clone(),
+            route,
+        };
+
+        Box::pin(async move {
+            let req = match &schemas.request {
+                Some(schema) if is_json(req.headers()) => match validate_request(req, schema, &target).await {
+                    Ok(req) => req,
+                    Err(response) => return Ok(response),
+                },
+                _ => req,
+            };
+
+            let response = inner.call(req).await?;
+            match &schemas.response {
+                Some(schema) if response.status().is_success() && is_json(response.headers()) => {
+                    Ok(validate_response(response, schema.clone(), target).await)
+                }
+                _ => Ok(response),
+            }
+        })
+    }
+}
+
+async fn validate_request(req: Request, schema: &JsonSchema, target: &Target) -> Result<Request, Response> {
+    let (parts, body) = req.into_parts();
+    let body = match Limited::new(body, target.config.max_body_bytes).collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(err) if err.is::<LengthLimitError>() => {
+            let detail = format!("the body is larger than {} bytes", target.config.max_body_bytes);
+            return Err(problem(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "schema.body_too_large",
+                detail,
+                None,
+            ));
+        }
+        Err(err) => {
+            warn!("failed to buffer request body: {}", err);
+            return Err(StatusCode::BAD_REQUEST.into_response());
+        }
+    };
+
+    match target.check(schema, "request", &body) {
+        Some(violations) if !violations.is_empty() => {
+            let detail = format!(
+                "the body does not match the schema of {} {}",
+                target.method, target.route
+            );
+            Err(problem(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "schema.request",
+                detail,
+                Some(&violations),
+            ))
+        }
+        _ => Ok(Request::from_parts(parts, Body::from(body))),
+    }
+}
+
+async fn validate_response(response: Response, schema: Arc<JsonSchema>, target: Target) -> Response {
+    let max_body_bytes = target.config.max_body_bytes;
+    let size = response.body().size_hint();
+    if size.lower() > max_body_bytes as u64 {
+        debug!(
+            "not checking the response of {} {}: larger than {} bytes",
+            target.method, target.route, max_body_bytes
+        );
+        return response;
+    }
+    if target.config.response_validation == ResponseValidation::LogOnly {
+        let (parts, body) = response.into_parts();
+        let body = CheckedBody {
+            inner: body,
+            check: Some(ResponseCheck {
+                schema,
+                target,
+                buffer: Vec::new(),
+            }),
+        };
+        return Response::from_parts(parts, Body::new(body));
+    }
+
+    if size.upper().is_none_or(|upper| upper > max_body_bytes as u64) {
+        debug!(
+            "not checking the response of {} {}: it may be larger than {} bytes",
+            target.method, target.route, max_body_bytes
+        );
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    let body = match body.collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(err) => {
+            warn!("failed to buffer response body: {}", err);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    match target.check(&schema, "response", &body) {
+        Some(violations) if !violations.is_empty() => {
+            let detail = format!(
+                "the response of {} {} does not match its schema",
+                target.method, target.route
+            );
+            problem(StatusCode::INTERNAL_SERVER_ERROR, "schema.response", detail, None)
+        }
+        _ => Response::from_parts(parts, Body::from(body)),
+    }
+}
+
+/// The part of a response seen so far, checked once it is complete.
+struct ResponseCheck {
+    schema: Arc<JsonSchema>,
+    target: Target,
+    buffer: Vec<u8>,
+}
+
+pin_project_lite::pin_project! {
+    /// Passes the response through, keeping a copy to check at its end.
+    struct CheckedBody {
+        #[pin]
+        inner: Body,
+        check: Option<ResponseCheck>,
+    }
+}
+
+impl http_body::Body for CheckedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        let this = self.project();
+        let frame = this.inner.poll_frame(cx);
+        match &frame {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let (Some(check), Some(data)) = (this.check.as_mut(), frame.data_ref()) {
+                    let max_body_bytes = check.target.config.max_body_bytes;
+                    if check.buffer.len() + data.len() > max_body_bytes {
+                        let target = &check.target;
+                        debug!(
+                            "not checking the response of {} {}: larger than {} bytes",
+                            target.method, target.route, max_body_bytes
+                        );
+                        *this.check = None;
+                    } else {
+                        check.buffer.extend_from_slice(data);
+                    }
+                }
+            }
+            Poll::Ready(None) => {
+                if let Some(check) = this.check.take() {
+                    check.target.check(&check.schema, "response", &check.buffer);
+                }
+            }
+            // A response cut short cannot be checked.
+            Poll::Ready(Some(Err(_))) => *this.check = None,
+            Poll::Pending => {}
+        }
+        frame
+    }
+
+    fn is_end_stream(&self) -> bool {
+        // Reported only once the end was polled, so the check runs.
+        self.check.is_none() && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
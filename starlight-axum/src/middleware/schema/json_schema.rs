@@ -0,0 +1,170 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+use std::path::Path;
+
+/// A JSON Schema, compiled once when the layer is configured.
+///
+/// Validation is done by the `jsonschema` crate, which supports every keyword of drafts
+/// 4 to 2020-12, picked by `$schema` and 2020-12 by default. `format` is asserted
+/// rather than treated as an annotation. References are resolved within the schema
+/// only; a schema which is not valid against its meta-schema is refused when loaded.
+#[derive(Debug, Clone)]
+pub struct JsonSchema {
+    validator: jsonschema::Validator,
+}
+
+/// Why a schema could not be loaded.
+#[derive(Debug)]
+pub struct SchemaError {
+    /// JSON pointer of the offending part of the schema.
+    pub pointer: String,
+    pub message: String,
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid schema at `{}`: {}", self.pointer, self.message)
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+/// A place where a document does not match its schema.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Violation {
+    /// JSON pointer of the value in the document, e.g. `/items/0/price`; empty for the
+    /// document itself.
+    pub pointer: String,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.pointer.as_str() {
+            "" => f.write_str(&self.message),
+            pointer => write!(f, "{}: {}", pointer, self.message),
+        }
+    }
+}
+
+impl JsonSchema {
+    pub fn new(schema: &Value) -> Result<Self, SchemaError> {
+        let validator = jsonschema::options()
+            .should_validate_formats(true)
+            .build(schema)
+            .map_err(|err| SchemaError {
+                pointer: err.instance_path().to_string(),
+                message: err.to_string(),
+            })?;
+        Ok(JsonSchema { validator })
+    }
+
+    /// A schema from its JSON text, e.g. `include_str!("schemas/create_user.json")`.
+    pub fn parse(json: &str) -> Result<Self, SchemaError> {
+        let value = serde_json::from_str(json).map_err(|err| SchemaError {
+            pointer: String::new(),
+            message: err.to_string(),
+        })?;
+        Self::new(&value)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, SchemaError> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path).map_err(|err| SchemaError {
+            pointer: String::new(),
+            message: format!("cannot read {}: {}", path.display(), err),
+        })?;
+        Self::parse(&json)
+    }
+
+    /// Every place `document` does not match the schema, empty when it does.
+    pub fn validate(&self, document: &Value) -> Vec<Violation> {
+        self.validator
+            .iter_errors(document)
+            .map(|err| Violation {
+                pointer: err.instance_path().to_string(),
+                message: err.to_string(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn violations(schema: Value, document: Value) -> Vec<String> {
+        JsonSchema::new(&schema)
+            .unwrap()
+            .validate(&document)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn reports_every_violation_with_its_pointer() {
+        let schema = json!({
+            "type": "object",
+            "required": ["name", "items"],
+            "additionalProperties": false,
+            "properties": {
+                "name": {"type": "string", "minLength": 1},
+                "items": {
+                    "type": "array",
+                    "minItems": 1,
+                    "items": {"$ref": "#/$defs/item"}
+                }
+            },
+            "$defs": {
+                "item": {
+                    "type": "object",
+                    "properties": {
+                        "sku": {"type": "string", "pattern": "^[A-Z]{3}-\\d+$"},
+                        "quantity": {"type": "integer", "minimum": 1}
+                    }
+                }
+            }
+        });
+        let document = json!({
+            "items": [{"sku": "abc", "quantity": 0}, {"sku": "ABC-1", "quantity": 2.5}],
+            "note": "",
+        });
+        let mut found = violations(schema, document);
+        found.sort();
+        assert_eq!(
+            found,
+            [
+                "\"name\" is a required property",
+                "/items/0/quantity: 0 is less than the minimum of 1",
+                "/items/0/sku: \"abc\" does not match \"^[A-Z]{3}-[0-9]+$\"",
+                "/items/1/quantity: 2.5 is not of type \"integer\"",
+                "Additional properties are not allowed ('note' was unexpected)",
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_formats_and_recursive_references() {
+        let schema = json!({"type": "string", "format": "email"});
+        assert!(violations(schema.clone(), json!("ada@example.com")).is_empty());
+        assert_eq!(violations(schema, json!("ada")).len(), 1);
+
+        let tree = json!({
+            "$ref": "#/$defs/node",
+            "$defs": {"node": {"type": "object", "properties": {"children": {"items": {"$ref": "#/$defs/node"}}}}}
+        });
+        assert!(violations(tree.clone(), json!({"children": [{"children": []}]})).is_empty());
+        assert_eq!(violations(tree, json!({"children": [{"children": [1]}]})).len(), 1);
+    }
+
+    #[test]
+    fn refuses_invalid_schemas() {
+        let err = JsonSchema::new(&json!({"properties": {"a": {"minLength": "long"}}})).unwrap_err();
+        assert_eq!(err.pointer, "/properties/a/minLength");
+        assert!(JsonSchema::new(&json!({"type": "text"})).is_err());
+        assert!(JsonSchema::parse("{").is_err());
+    }
+}
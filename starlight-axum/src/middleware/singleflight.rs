@@ -0,0 +1,286 @@
+use crate::meter::GLOBAL_METER;
+use axum::body::{Body, Bytes, HttpBody};
+use axum::extract::Request;
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use http_body_util::BodyExt;
+use opentelemetry::metrics::{Histogram, Meter};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::sync::watch;
+use tower::{Layer, Service};
+
+pub const X_COALESCED: &str = "x-coalesced";
+pub const SINGLEFLIGHT_WAITERS: &str = "http.server.singleflight.waiters";
+
+/// The response of the first request, for the requests which waited for it. `None` when
+/// it is not shared and they run on their own.
+type Outcome = Option<Arc<SharedResponse>>;
+
+#[derive(Debug)]
+struct SharedResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl SharedResponse {
+    fn to_follower(&self) -> Response {
+        let mut response = Response::new(Body::from(self.body.clone()));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers.clone();
+        response
+            .headers_mut()
+            .insert(X_COALESCED, HeaderValue::from_static("true"));
+        response
+    }
+}
+
+#[derive(Debug)]
+struct Flight {
+    outcome: watch::Receiver<Option<Outcome>>,
+    waiters: u64,
+}
+
+#[derive(Debug, Clone)]
+struct SingleflightConfig {
+    key_headers: Vec<HeaderName>,
+    max_body_bytes: u64,
+    share_errors: bool,
+    waiters: Histogram<u64>,
+}
+
+impl SingleflightConfig {
+    /// The key of `req`, `None` when it is not coalesced.
+    fn key(&self, req: &Request) -> Option<String> {
+        if !matches!(*req.method(), Method::GET | Method::HEAD) {
+            return None;
+        }
+        // Never share a response between callers who may be told apart by credentials.
+        for credential in [header::AUTHORIZATION, header::COOKIE] {
+            if req.headers().contains_key(&credential) && !self.key_headers.contains(&credential) {
+                return None;
+            }
+        }
+        let target = req.uri().path_and_query().map_or("/", |target| target.as_str());
+        let mut key = format!("{} {}", req.method(), target);
+        for name in &self.key_headers {
+            let values: Vec<&[u8]> = req.headers().get_all(name).iter().map(HeaderValue::as_bytes).collect();
+            key.push_str(&format!("\n{}: {}", name, String::from_utf8_lossy(&values.join(&b','))));
+        }
+        Some(key)
+    }
+}
+
+/// Coalesces concurrent identical GET and HEAD requests: the first runs, the others wait
+/// for it and get a copy of its response with an `x-coalesced: true` header, so a burst
+/// on a hot endpoint reaches the handler once.
+///
+/// Requests are identical with the same method, path, query and the headers named with
+/// [`SingleflightLayer::with_key_headers`]; requests with `Authorization` or `Cookie`
+/// are not coalesced unless the header is one of them. Responses with bodies over 1 MiB
+/// by default, or a 5xx status unless [`SingleflightLayer::with_share_errors`], are not
+/// shared: the waiting requests then run on their own. The number of waiters of each
+/// request that ran is recorded on `http.server.singleflight.waiters`.
+#[derive(Debug, Clone)]
+pub struct SingleflightLayer {
+    config: Arc<SingleflightConfig>,
+    flights: Arc<Mutex<HashMap<String, Flight>>>,
+}
+
+impl Default for SingleflightLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SingleflightLayer {
+    pub fn new() -> Self {
+        SingleflightLayer {
+            config: Arc::new(SingleflightConfig {
+                key_headers: Vec::new(),
+                max_body_bytes: 1024 * 1024,
+                share_errors: false,
+                waiters: waiters(&GLOBAL_METER),
+            }),
+            flights: Arc::default(),
+        }
+    }
+
+    pub fn with_meter(mut self, meter: &Meter) -> Self {
+        Arc::make_mut(&mut self.config).waiters = waiters(meter);
+        self
+    }
+
+    /// Request headers whose values tell requests apart, like `accept-language`.
+    pub fn with_key_headers(mut self, headers: impl IntoIterator<Item = HeaderName>) -> Self {
+        Arc::make_mut(&mut self.config).key_headers = headers.into_iter().collect();
+        self
+    }
+
+    pub fn with_max_body_bytes(mut self, max: u64) -> Self {
+        Arc::make_mut(&mut self.config).max_body_bytes = max;
+        self
+    }
+
+    /// Whether waiting requests also get a 5xx response, off by default.
+    pub fn with_share_errors(mut self, share_errors: bool) -> Self {
+        Arc::make_mut(&mut self.config).share_errors = share_errors;
+        self
+    }
+}
+
+fn waiters(meter: &Meter) -> Histogram<u64> {
+    meter
+        .u64_histogram(SINGLEFLIGHT_WAITERS)
+        .with_description("Requests which waited for an identical request instead of running")
+        .build()
+}
+
+impl<S> Layer<S> for SingleflightLayer {
+    type Service = SingleflightService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SingleflightService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SingleflightService<S> {
+    inner: S,
+    layer: SingleflightLayer,
+}
+
+/// Ends the flight of the first request, also when its future is dropped: the waiters
+/// then see the sender go and run on their own.
+struct Leader {
+    layer: SingleflightLayer,
+    key: String,
+    outcome: watch::Sender<Option<Outcome>>,
+}
+
+impl Leader {
+    fn finish(self, outcome: Outcome) {
+        let waiters = self
+            .layer
+            .flights
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.key)
+            .map_or(0, |flight| flight.waiters);
+        self.layer.config.waiters.record(waiters, &[]);
+        let _ = self.outcome.send(Some(outcome));
+    }
+}
+
+impl Drop for Leader {
+    fn drop(&mut self) {
+        let mut flights = self.layer.flights.lock().unwrap_or_else(|e| e.into_inner());
+        if flights
+            .get(&self.key)
+            .is_some_and(|flight| flight.outcome.same_channel(&self.outcome.subscribe()))
+        {
+            flights.remove(&self.key);
+        }
+    }
+}
+
+impl<S> Service<Request> for SingleflightService<S>
+where
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let Some(key) = self.layer.config.key(&req) else {
+            return Box::pin(inner.call(req));
+        };
+
+        let layer = self.layer.clone();
+        Box::pin(async move {
+            let waiting = {
+                let mut flights = layer.flights.lock().unwrap_or_else(|e| e.into_inner());
+                match flights.get_mut(&key) {
+                    Some(flight) => {
+                        flight.waiters += 1;
+                        Err(flight.outcome.clone())
+                    }
+                    None => {
+                        let (sender, receiver) = watch::channel(None);
+                        flights.insert(
+                            key.clone(),
+                            Flight {
+                                outcome: receiver,
+                                waiters: 0,
+                            },
+                        );
+                        Ok(sender)
+                    }
+                }
+            };
+
+            let sender = match waiting {
+                Ok(sender) => sender,
+                Err(mut outcome) => {
+                    let shared = match outcome.wait_for(Option::is_some).await {
+                        Ok(outcome) => outcome.clone().flatten(),
+                        Err(_) => None,
+                    };
+                    return match shared {
+                        Some(shared) => Ok(shared.to_follower()),
+                        None => inner.call(req).await,
+                    };
+                }
+            };
+            let leader = Leader {
+                layer: layer.clone(),
+                key,
+                outcome: sender,
+            };
+
+            let response = inner.call(req).await?;
+            let config = &layer.config;
+            let fits = response
+                .body()
+                .size_hint()
+                .upper()
+                .is_some_and(|upper| upper <= config.max_body_bytes);
+            if !fits || (response.status().is_server_error() && !config.share_errors) {
+                leader.finish(None);
+                return Ok(response);
+            }
+
+            let (parts, body) = response.into_parts();
+            let body = match body.collect().await {
+                Ok(collected) => collected.to_bytes(),
+                Err(err) => {
+                    warn!("failed to buffer a coalesced response: {}", err);
+                    leader.finish(None);
+                    return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+                }
+            };
+            leader.finish(Some(Arc::new(SharedResponse {
+                status: parts.status,
+                headers: parts.headers.clone(),
+                body: body.clone(),
+            })));
+            Ok(Response::from_parts(parts, Body::from(body)))
+        })
+    }
+}
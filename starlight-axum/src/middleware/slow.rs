@@ -0,0 +1,325 @@
+use crate::middleware::queue_time::QueueTime;
+use axum::extract::{ConnectInfo, FromRequestParts, MatchedPath, Request};
+use axum::http::StatusCode;
+use axum::http::request::Parts;
+use axum::response::Response;
+use opentelemetry::trace::TraceContextExt;
+use serde::Serialize;
+use starlight_protocol::constants::STARLIGHT_REQUEST_ID;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+use tower::{Layer, Service};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Timing marks of a request, such as the time spent serializing the response, shown
+/// with it when it is slow. Extracted by handlers under a [`SlowRequestLayer`].
+#[derive(Debug, Clone, Default)]
+pub struct Timings {
+    marks: Arc<Mutex<Vec<(&'static str, Duration)>>>,
+}
+
+impl Timings {
+    pub fn record(&self, name: &'static str, duration: Duration) {
+        self.marks.lock().unwrap_or_else(|e| e.into_inner()).push((name, duration));
+    }
+
+    /// Runs `f` and records how long it took.
+    pub fn time<T>(&self, name: &'static str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let value = f();
+        self.record(name, start.elapsed());
+        value
+    }
+
+    fn snapshot(&self) -> Vec<(&'static str, Duration)> {
+        self.marks.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Timings {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<Timings>().cloned().ok_or((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Timings is missing, is SlowRequestLayer installed?",
+        ))
+    }
+}
+
+/// A request over its threshold, as listed by [`SlowRequestLog::slowest`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SlowRequest {
+    #[serde(with = "time::serde::rfc3339")]
+    pub timestamp: OffsetDateTime,
+    pub method: String,
+    /// The matched route, or the path when the layer runs before routing.
+    pub route: String,
+    pub duration_ms: f64,
+    pub status: u16,
+    pub client_ip: Option<String>,
+    pub request_id: Option<String>,
+    pub trace_id: Option<String>,
+    /// `queue` from [`QueueTime`], `handler` until the response, and the [`Timings`]
+    /// recorded by the handler, in milliseconds.
+    pub marks: BTreeMap<String, f64>,
+}
+
+#[derive(Debug)]
+struct TopK {
+    capacity: usize,
+    /// The fastest duration kept once full, in microseconds, so faster requests skip
+    /// the lock.
+    floor_micros: AtomicU64,
+    entries: Mutex<Vec<SlowRequest>>,
+}
+
+/// The `capacity` slowest requests seen by a [`SlowRequestLayer`], served by the
+/// [`AdminRouter`](crate::admin::AdminRouter) on `GET /admin/slow-requests`.
+///
+/// Recording never waits: a request finishing while another holds the list is not
+/// kept.
+#[derive(Debug, Clone)]
+pub struct SlowRequestLog {
+    top: Arc<TopK>,
+}
+
+impl SlowRequestLog {
+    pub fn new(capacity: usize) -> Self {
+        SlowRequestLog {
+            top: Arc::new(TopK {
+                capacity,
+                floor_micros: AtomicU64::new(0),
+                entries: Mutex::new(Vec::with_capacity(capacity)),
+            }),
+        }
+    }
+
+    /// Slowest first.
+    pub fn slowest(&self) -> Vec<SlowRequest> {
+        let mut entries = self.top.entries.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        entries.sort_by(|a, b| b.duration_ms.total_cmp(&a.duration_ms));
+        entries
+    }
+
+    fn record(&self, duration: Duration, request: impl FnOnce() -> SlowRequest) {
+        let top = &self.top;
+        if top.capacity == 0 || (duration.as_micros() as u64) < top.floor_micros.load(Ordering::Relaxed) {
+            return;
+        }
+        let Ok(mut entries) = top.entries.try_lock() else {
+            return;
+        };
+        if entries.len() == top.capacity {
+            let Some((fastest, kept)) = entries
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| a.duration_ms.total_cmp(&b.duration_ms))
+            else {
+                return;
+            };
+            // The floor read above may predate a concurrent insert.
+            if kept.duration_ms >= millis(duration) {
+                return;
+            }
+            entries.swap_remove(fastest);
+        }
+        entries.push(request());
+        if entries.len() == top.capacity {
+            let floor = entries.iter().map(|entry| entry.duration_ms).fold(f64::INFINITY, f64::min);
+            top.floor_micros.store((floor * 1000.0) as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SlowConfig {
+    threshold: Duration,
+    routes: HashMap<String, Duration>,
+    log: SlowRequestLog,
+}
+
+/// Logs requests slower than a threshold at WARN, with their route, duration, status,
+/// client address, request id and timing marks, and keeps the slowest in a
+/// [`SlowRequestLog`].
+///
+/// Per-route thresholds need the matched route, so install the layer with
+/// `Router::route_layer` to use them; with `Router::layer` the path is used instead.
+/// The client address comes from `ConnectInfo`, as set by [`crate::serve`].
+#[derive(Debug, Clone)]
+pub struct SlowRequestLayer {
+    config: Arc<SlowConfig>,
+}
+
+impl SlowRequestLayer {
+    /// Keeps the 20 slowest requests.
+    pub fn new(threshold: Duration) -> Self {
+        Self::with_log(threshold, SlowRequestLog::new(20))
+    }
+
+    pub fn with_log(threshold: Duration, log: SlowRequestLog) -> Self {
+        SlowRequestLayer {
+            config: Arc::new(SlowConfig {
+                threshold,
+                routes: HashMap::new(),
+                log,
+            }),
+        }
+    }
+
+    /// A threshold for one route, such as `/reports/{id}`, instead of the global one.
+    pub fn with_route(mut self, route: impl Into<String>, threshold: Duration) -> Self {
+        Arc::make_mut(&mut self.config).routes.insert(route.into(), threshold);
+        self
+    }
+
+    pub fn log(&self) -> SlowRequestLog {
+        self.config.log.clone()
+    }
+}
+
+impl<S> Layer<S> for SlowRequestLayer {
+    type Service = SlowRequestService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SlowRequestService {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SlowRequestService<S> {
+    inner: S,
+    config: Arc<SlowConfig>,
+}
+
+impl<S> Service<Request> for SlowRequestService<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        let start = Instant::now();
+        let timings = Timings::default();
+        req.extensions_mut().insert(timings.clone());
+
+        let route = match req.extensions().get::<MatchedPath>() {
+            Some(path) => path.as_str().to_owned(),
+            None => req.uri().path().to_owned(),
+        };
+        let threshold = self.config.routes.get(&route).copied().unwrap_or(self.config.threshold);
+        let method = req.method().to_string();
+        let client_ip = req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip().to_string());
+        let request_id = req
+            .headers()
+            .get(STARLIGHT_REQUEST_ID)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+        let queue = req.extensions().get::<QueueTime>().map(|QueueTime(queued)| *queued);
+
+        let config = self.config.clone();
+        let future = self.inner.call(req);
+        Box::pin(async move {
+            let response = future.await?;
+            let elapsed = start.elapsed();
+            if elapsed < threshold {
+                return Ok(response);
+            }
+
+            let mut marks: BTreeMap<String, f64> = timings
+                .snapshot()
+                .into_iter()
+                .map(|(name, duration)| (name.to_owned(), millis(duration)))
+                .collect();
+            marks.insert("handler".to_owned(), millis(elapsed));
+            if let Some(queue) = queue {
+                marks.insert("queue".to_owned(), millis(queue));
+            }
+            let status = response.status().as_u16();
+            warn!(
+                http.route = %route,
+                http.request.method = %method,
+                http.response.status_code = status,
+                duration_ms = millis(elapsed),
+                threshold_ms = millis(threshold),
+                client.address = client_ip.as_deref().unwrap_or(""),
+                request_id = request_id.as_deref().unwrap_or(""),
+                marks = ?marks,
+                "slow request"
+            );
+            config.log.record(elapsed, || {
+                let span_context = Span::current().context().span().span_context().clone();
+                SlowRequest {
+                    timestamp: OffsetDateTime::now_utc(),
+                    method,
+                    route,
+                    duration_ms: millis(elapsed),
+                    status,
+                    client_ip,
+                    request_id,
+                    trace_id: span_context.is_valid().then(|| span_context.trace_id().to_string()),
+                    marks,
+                }
+            });
+            Ok(response)
+        })
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(duration: Duration) -> SlowRequest {
+        SlowRequest {
+            timestamp: OffsetDateTime::now_utc(),
+            method: "GET".to_owned(),
+            route: format!("/{}", duration.as_millis()),
+            duration_ms: millis(duration),
+            status: 200,
+            client_ip: None,
+            request_id: None,
+            trace_id: None,
+            marks: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn a_stale_floor_does_not_evict_slower_requests() {
+        let log = SlowRequestLog::new(2);
+        for millis in [100, 200] {
+            log.record(Duration::from_millis(millis), || request(Duration::from_millis(millis)));
+        }
+        // As read by a request racing the insert which raised the floor.
+        log.top.floor_micros.store(0, Ordering::Relaxed);
+        log.record(Duration::from_millis(50), || request(Duration::from_millis(50)));
+
+        let routes: Vec<_> = log.slowest().into_iter().map(|request| request.route).collect();
+        assert_eq!(routes, ["/200", "/100"]);
+    }
+}
@@ -0,0 +1,232 @@
+use crate::deadline::DeadlineExemption;
+use crate::meter::GLOBAL_METER;
+use axum::body::{Body, Bytes};
+use axum::extract::{MatchedPath, Request};
+use axum::http::{HeaderValue, header};
+use axum::response::Response;
+use http_body::{Frame, SizeHint};
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Counter, Histogram, Meter};
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::{Instant, Sleep};
+use tower::{Layer, Service};
+
+/// Marks a response as a stream for [`StreamingLayer`], for streams it cannot detect:
+/// `(Extension(StreamingResponse), body)`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StreamingResponse;
+
+#[derive(Debug, Clone)]
+struct StreamingConfig {
+    idle_timeout: Duration,
+    first_byte: Histogram<f64>,
+    duration: Histogram<f64>,
+    bytes: Counter<u64>,
+}
+
+/// Serves long-poll and streaming routes, e.g. server-sent events, with
+/// `.route_layer(StreamingLayer::new())`.
+///
+/// Requests through it are exempt from the [`DeadlineLayer`](crate::deadline::DeadlineLayer)
+/// timeout. Responses marked [`StreamingResponse`], of type `text/event-stream` or of
+/// unknown length are streams: they end when no data was written for the idle timeout
+/// (30 seconds by default) instead of after a total timeout, and are measured with
+/// `http.server.time_to_first_byte`, `http.server.stream.duration` and
+/// `http.server.stream.bytes` rather than as one long request.
+#[derive(Debug, Clone)]
+pub struct StreamingLayer {
+    config: Arc<StreamingConfig>,
+}
+
+impl Default for StreamingLayer {
+    fn default() -> Self {
+        Self::with_meter(&GLOBAL_METER)
+    }
+}
+
+impl StreamingLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_meter(meter: &Meter) -> Self {
+        StreamingLayer {
+            config: Arc::new(StreamingConfig {
+                idle_timeout: Duration::from_secs(30),
+                first_byte: meter
+                    .f64_histogram("http.server.time_to_first_byte")
+                    .with_description("Time until streamed responses wrote their first data")
+                    .with_unit("s")
+                    .build(),
+                duration: meter
+                    .f64_histogram("http.server.stream.duration")
+                    .with_description("Duration of streamed responses")
+                    .with_unit("s")
+                    .build(),
+                bytes: meter
+                    .u64_counter("http.server.stream.bytes")
+                    .with_description("Bytes written by streamed responses")
+                    .with_unit("By")
+                    .build(),
+            }),
+        }
+    }
+
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        Arc::make_mut(&mut self.config).idle_timeout = idle_timeout;
+        self
+    }
+}
+
+fn is_stream(response: &Response) -> bool {
+    let event_stream = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/event-stream"));
+    let unknown_length = !response.headers().contains_key(header::CONTENT_LENGTH)
+        && http_body::Body::size_hint(response.body()).exact().is_none();
+    response.extensions().get::<StreamingResponse>().is_some() || event_stream || unknown_length
+}
+
+impl<S> Layer<S> for StreamingLayer {
+    type Service = StreamingService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        StreamingService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StreamingService<S> {
+    inner: S,
+    layer: StreamingLayer,
+}
+
+impl<S> Service<Request> for StreamingService<S>
+where
+    S: Service<Request, Response = Response, Error = Infallible>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        if let Some(exemption) = req.extensions().get::<DeadlineExemption>() {
+            exemption.exempt();
+        }
+        let route = req
+            .extensions()
+            .get::<MatchedPath>()
+            .map_or_else(|| req.uri().path().to_owned(), |path| path.as_str().to_owned());
+        let started = Instant::now();
+        let config = self.layer.config.clone();
+        let future = self.inner.call(req);
+        Box::pin(async move {
+            let response = future.await?;
+            if !is_stream(&response) {
+                return Ok(response);
+            }
+            let (mut parts, body) = response.into_parts();
+            // Proxies such as nginx would otherwise hold the events back.
+            parts
+                .headers
+                .entry("x-accel-buffering")
+                .or_insert(HeaderValue::from_static("no"));
+            let body = StreamBody {
+                inner: body,
+                idle: Box::pin(tokio::time::sleep(config.idle_timeout)),
+                metrics: StreamMetrics {
+                    config,
+                    attributes: [KeyValue::new("http.route", route)],
+                    started,
+                    first_byte: None,
+                    bytes: 0,
+                },
+            };
+            Ok(Response::from_parts(parts, Body::new(body)))
+        })
+    }
+}
+
+/// Recorded when the stream ends, including when the client goes away.
+struct StreamMetrics {
+    config: Arc<StreamingConfig>,
+    attributes: [KeyValue; 1],
+    started: Instant,
+    first_byte: Option<Instant>,
+    bytes: u64,
+}
+
+impl Drop for StreamMetrics {
+    fn drop(&mut self) {
+        let config = &self.config;
+        if let Some(first_byte) = self.first_byte {
+            config
+                .first_byte
+                .record((first_byte - self.started).as_secs_f64(), &self.attributes);
+        }
+        config.duration.record(self.started.elapsed().as_secs_f64(), &self.attributes);
+        config.bytes.add(self.bytes, &self.attributes);
+    }
+}
+
+pin_project_lite::pin_project! {
+    struct StreamBody {
+        #[pin]
+        inner: Body,
+        idle: Pin<Box<Sleep>>,
+        metrics: StreamMetrics,
+    }
+}
+
+impl http_body::Body for StreamBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        let this = self.project();
+        match this.inner.poll_frame(cx) {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let Some(data) = frame.data_ref() {
+                    this.metrics.first_byte.get_or_insert_with(Instant::now);
+                    this.metrics.bytes += data.len() as u64;
+                }
+                let timeout = this.metrics.config.idle_timeout;
+                this.idle.as_mut().reset(Instant::now() + timeout);
+                Poll::Ready(Some(Ok(frame)))
+            }
+            Poll::Pending => {
+                if this.idle.as_mut().poll(cx).is_pending() {
+                    return Poll::Pending;
+                }
+                let timeout = this.metrics.config.idle_timeout;
+                debug!("closing stream idle for {:?}", timeout);
+                let error = std::io::Error::new(std::io::ErrorKind::TimedOut, format!("stream idle for {:?}", timeout));
+                Poll::Ready(Some(Err(axum::Error::new(error))))
+            }
+            other => other,
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
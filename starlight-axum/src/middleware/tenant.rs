@@ -0,0 +1,268 @@
+use crate::attrs;
+use axum::Json;
+use axum::extract::{FromRequestParts, Request};
+use axum::http::request::Parts;
+use axum::http::{Extensions, HeaderMap, HeaderName, StatusCode};
+use axum::response::{IntoResponse, Response};
+use opentelemetry::KeyValue;
+use opentelemetry_instrumentation_tower::ResponseAttributeExtractor;
+use std::collections::HashSet;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+use tracing::Span;
+
+pub const X_TENANT_ID: &str = "x-tenant-id";
+
+/// The tenant of requests whose tenant could not be resolved.
+pub const UNKNOWN_TENANT: &str = "unknown";
+
+/// The metrics attribute of tenants outside the allowlist.
+pub const OTHER_TENANT: &str = "other";
+
+/// The tenant resolved for a request by [`TenantLayer`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TenantId(String);
+
+impl TenantId {
+    pub fn new(id: impl Into<String>) -> Self {
+        TenantId(id.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_unknown(&self) -> bool {
+        self.0 == UNKNOWN_TENANT
+    }
+}
+
+impl std::fmt::Display for TenantId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for TenantId {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<TenantId>().cloned().ok_or((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "TenantId is missing, is TenantLayer installed?",
+        ))
+    }
+}
+
+/// Finds the tenant of a request.
+pub trait TenantResolver: Send + Sync + 'static {
+    fn resolve(&self, headers: &HeaderMap, extensions: &Extensions) -> Option<TenantId>;
+}
+
+/// Reads the tenant from a header, `x-tenant-id` by default.
+#[derive(Debug, Clone)]
+pub struct HeaderTenantResolver {
+    header: HeaderName,
+}
+
+impl HeaderTenantResolver {
+    pub fn new(header: HeaderName) -> Self {
+        HeaderTenantResolver { header }
+    }
+}
+
+impl Default for HeaderTenantResolver {
+    fn default() -> Self {
+        HeaderTenantResolver::new(HeaderName::from_static(X_TENANT_ID))
+    }
+}
+
+impl TenantResolver for HeaderTenantResolver {
+    fn resolve(&self, headers: &HeaderMap, _extensions: &Extensions) -> Option<TenantId> {
+        let value = headers.get(&self.header)?.to_str().ok()?.trim();
+        (!value.is_empty()).then(|| TenantId::new(value))
+    }
+}
+
+/// Reads the tenant from the claims of an authenticated request, stored as a `C`
+/// extension by the authentication middleware:
+///
+/// ```ignore
+/// ClaimsTenantResolver::new(|claims: &Claims| claims.tenant.clone())
+/// ```
+pub struct ClaimsTenantResolver<C, F> {
+    tenant: F,
+    _claims: PhantomData<fn(&C)>,
+}
+
+impl<C, F> ClaimsTenantResolver<C, F>
+where
+    C: Send + Sync + 'static,
+    F: Fn(&C) -> Option<String> + Send + Sync + 'static,
+{
+    pub fn new(tenant: F) -> Self {
+        ClaimsTenantResolver {
+            tenant,
+            _claims: PhantomData,
+        }
+    }
+}
+
+impl<C, F> TenantResolver for ClaimsTenantResolver<C, F>
+where
+    C: Send + Sync + 'static,
+    F: Fn(&C) -> Option<String> + Send + Sync + 'static,
+{
+    fn resolve(&self, _headers: &HeaderMap, extensions: &Extensions) -> Option<TenantId> {
+        let tenant = (self.tenant)(extensions.get::<C>()?)?;
+        (!tenant.is_empty()).then(|| TenantId::new(tenant))
+    }
+}
+
+/// What [`TenantLayer`] does with requests whose tenant could not be resolved.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnresolvedTenant {
+    /// Continue with the [`UNKNOWN_TENANT`].
+    #[default]
+    Unknown,
+    /// Answer 400 Bad Request.
+    Reject,
+}
+
+/// The `tenant.id` metrics attribute of a response.
+#[derive(Debug, Clone)]
+struct TenantLabel(String);
+
+#[derive(Clone)]
+struct TenantConfig {
+    resolver: Arc<dyn TenantResolver>,
+    allowlist: HashSet<String>,
+    unresolved: UnresolvedTenant,
+}
+
+/// Resolves the request tenant and stores it as a [`TenantId`] extension on the request
+/// and the response.
+///
+/// The tenant is set as the `tenant.id` attribute of the current span, the request span
+/// under [`trace_middleware`](crate::middleware::trace_middleware), so traces carry it. Metrics get it through [`TenantAttribute`], but only for tenants in
+/// the allowlist; the others are labelled [`OTHER_TENANT`] to bound the cardinality.
+#[derive(Clone)]
+pub struct TenantLayer {
+    config: Arc<TenantConfig>,
+}
+
+impl TenantLayer {
+    pub fn new(resolver: impl TenantResolver) -> Self {
+        TenantLayer {
+            config: Arc::new(TenantConfig {
+                resolver: Arc::new(resolver),
+                allowlist: HashSet::new(),
+                unresolved: UnresolvedTenant::default(),
+            }),
+        }
+    }
+
+    /// The tenants labelled by their id in metrics.
+    pub fn with_allowlist<I, T>(mut self, tenants: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.config_mut().allowlist = tenants.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_unresolved(mut self, unresolved: UnresolvedTenant) -> Self {
+        self.config_mut().unresolved = unresolved;
+        self
+    }
+
+    fn config_mut(&mut self) -> &mut TenantConfig {
+        Arc::make_mut(&mut self.config)
+    }
+
+    /// The `tenant.id` metrics attribute of `tenant`.
+    pub fn metrics_label<'a>(&self, tenant: &'a TenantId) -> &'a str {
+        if tenant.is_unknown() || self.config.allowlist.contains(tenant.as_str()) {
+            tenant.as_str()
+        } else {
+            OTHER_TENANT
+        }
+    }
+}
+
+impl<S> Layer<S> for TenantLayer {
+    type Service = TenantService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TenantService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct TenantService<S> {
+    inner: S,
+    layer: TenantLayer,
+}
+
+impl<S, B> Service<Request<B>> for TenantService<S>
+where
+    S: Service<Request<B>, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        let config = &self.layer.config;
+        let tenant = match config.resolver.resolve(req.headers(), req.extensions()) {
+            Some(tenant) => tenant,
+            None if config.unresolved == UnresolvedTenant::Reject => {
+                let response = (
+                    StatusCode::BAD_REQUEST,
+                    Json(serde_json::json!({ "error": "tenant could not be resolved" })),
+                )
+                    .into_response();
+                return Box::pin(async move { Ok(response) });
+            }
+            None => TenantId::new(UNKNOWN_TENANT),
+        };
+
+        attrs::record(&Span::current(), attrs! { "tenant.id" => tenant.to_string() });
+        let label = TenantLabel(self.layer.metrics_label(&tenant).to_owned());
+        req.extensions_mut().insert(tenant.clone());
+        let future = self.inner.call(req);
+        Box::pin(async move {
+            let mut response = future.await?;
+            response.extensions_mut().insert(tenant);
+            response.extensions_mut().insert(label);
+            Ok(response)
+        })
+    }
+}
+
+/// Adds the allowlisted `tenant.id` to the HTTP server metrics.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TenantAttribute;
+
+impl<B> ResponseAttributeExtractor<B> for TenantAttribute {
+    fn extract_attributes(&self, response: &axum::http::Response<B>) -> Vec<KeyValue> {
+        response
+            .extensions()
+            .get::<TenantLabel>()
+            .map(|label| vec![KeyValue::new("tenant.id", label.0.clone())])
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,220 @@
+use crate::attrs;
+use axum::Json;
+use axum::extract::{FromRequestParts, MatchedPath, Request, State};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{MethodRouter, any};
+use opentelemetry::KeyValue;
+use opentelemetry_instrumentation_tower::ResponseAttributeExtractor;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::{Layer, Service, ServiceExt};
+use tracing::Span;
+
+pub const X_API_VERSION: &str = "x-api-version";
+
+/// The API version negotiated by [`ApiVersionLayer`], e.g. `ApiVersion(2)` for `v2`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ApiVersion(pub u32);
+
+impl std::fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "v{}", self.0)
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for ApiVersion {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<ApiVersion>().copied().ok_or((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ApiVersion is missing, is ApiVersionLayer installed?",
+        ))
+    }
+}
+
+/// Parses `v2` or `2`.
+fn parse_version(value: &str) -> Option<u32> {
+    let value = value.trim();
+    value.strip_prefix(['v', 'V']).unwrap_or(value).parse().ok()
+}
+
+/// The versions requested through `Accept: application/vnd.<vendor>.v<N>+json`, in order.
+pub fn parse_accept_versions(accept: &str) -> Vec<u32> {
+    accept
+        .split(',')
+        .filter_map(|range| {
+            let essence = range.split(';').next()?.trim();
+            let vendor = essence.strip_prefix("application/vnd.")?;
+            let subtype = vendor.split('+').next()?;
+            parse_version(subtype.rsplit('.').next()?).filter(|_| subtype.contains('.'))
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+struct VersionConfig {
+    supported: Vec<u32>,
+    default: u32,
+}
+
+/// Negotiates the API version and stores it as an [`ApiVersion`] extension on the
+/// request and the response.
+///
+/// The vendor media type in `Accept` wins over the `x-api-version` header; without
+/// either the default version is used. An unsupported version is answered with 406
+/// and the list of supported versions. The version is set as the `api.version`
+/// attribute of the current span, the request span under
+/// [`trace_middleware`](crate::middleware::trace_middleware), and suffixes the route label
+/// of the HTTP server metrics through [`ApiVersionAttribute`].
+#[derive(Debug, Clone)]
+pub struct ApiVersionLayer {
+    config: Arc<VersionConfig>,
+}
+
+impl ApiVersionLayer {
+    pub fn new(supported: impl IntoIterator<Item = u32>, default: u32) -> Self {
+        ApiVersionLayer {
+            config: Arc::new(VersionConfig {
+                supported: supported.into_iter().collect(),
+                default,
+            }),
+        }
+    }
+
+    /// The requested version, which may be unsupported.
+    pub fn requested(&self, headers: &HeaderMap) -> ApiVersion {
+        let from_accept = headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(parse_accept_versions)
+            .next();
+        let from_header = || {
+            headers
+                .get(X_API_VERSION)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_version)
+        };
+        ApiVersion(from_accept.or_else(from_header).unwrap_or(self.config.default))
+    }
+}
+
+impl<S> Layer<S> for ApiVersionLayer {
+    type Service = ApiVersionService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ApiVersionService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ApiVersionService<S> {
+    inner: S,
+    layer: ApiVersionLayer,
+}
+
+fn not_acceptable(requested: Option<ApiVersion>, supported: impl Iterator<Item = u32>) -> Response {
+    let supported: Vec<String> = supported.map(|v| ApiVersion(v).to_string()).collect();
+    let error = match requested {
+        Some(version) => format!("API version {} is not supported", version),
+        None => "API version is not supported".to_owned(),
+    };
+    (
+        StatusCode::NOT_ACCEPTABLE,
+        Json(serde_json::json!({ "error": error, "supported": supported })),
+    )
+        .into_response()
+}
+
+impl<S, B> Service<Request<B>> for ApiVersionService<S>
+where
+    S: Service<Request<B>, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        let version = self.layer.requested(req.headers());
+        let supported = &self.layer.config.supported;
+        if !supported.contains(&version.0) {
+            let response = not_acceptable(Some(version), supported.iter().copied());
+            return Box::pin(async move { Ok(response) });
+        }
+
+        attrs::record(&Span::current(), attrs! { "api.version" => version.to_string() });
+        let route = req
+            .extensions()
+            .get::<MatchedPath>()
+            .map_or_else(|| req.uri().path().to_owned(), |path| path.as_str().to_owned());
+        req.extensions_mut().insert(version);
+        let future = self.inner.call(req);
+        Box::pin(async move {
+            let mut response = future.await?;
+            response.extensions_mut().insert(version);
+            response
+                .extensions_mut()
+                .insert(VersionedRoute(format!("{}@{}", route, version)));
+            Ok(response)
+        })
+    }
+}
+
+/// The route of a negotiated response with the version as suffix, e.g. `/users@v2`.
+#[derive(Debug, Clone)]
+struct VersionedRoute(String);
+
+/// Suffixes the `http.route` label of the HTTP server metrics with the negotiated
+/// version, e.g. `/users@v2`, so each version of a route has its own series. Routes
+/// are the matched ones when [`ApiVersionLayer`] runs after routing, the paths otherwise.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ApiVersionAttribute;
+
+impl<B> ResponseAttributeExtractor<B> for ApiVersionAttribute {
+    fn extract_attributes(&self, response: &axum::http::Response<B>) -> Vec<KeyValue> {
+        response
+            .extensions()
+            .get::<VersionedRoute>()
+            .map(|route| vec![KeyValue::new("http.route", route.0.clone())])
+            .unwrap_or_default()
+    }
+}
+
+/// One path served by a different handler per negotiated version:
+///
+/// ```ignore
+/// Router::new().route("/users", versioned([(1, get(list_users_v1)), (2, get(list_users_v2))]))
+/// ```
+pub fn versioned<S>(routes: impl IntoIterator<Item = (u32, MethodRouter<S>)>) -> MethodRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    let routes: Arc<BTreeMap<u32, MethodRouter<S>>> = Arc::new(routes.into_iter().collect());
+    any(move |State(state): State<S>, req: Request| {
+        let routes = routes.clone();
+        async move {
+            let version = req.extensions().get::<ApiVersion>().copied();
+            match version.and_then(|version| routes.get(&version.0)) {
+                Some(route) => match route.clone().with_state(state).oneshot(req).await {
+                    Ok(response) => response,
+                    Err(never) => match never {},
+                },
+                None => not_acceptable(version, routes.keys().copied()),
+            }
+        }
+    })
+}
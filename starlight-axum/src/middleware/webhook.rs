@@ -0,0 +1,267 @@
+use crate::attrs;
+use axum::Json;
+use axum::body::Body;
+use axum::extract::Request;
+use axum::http::{HeaderMap, HeaderName, StatusCode};
+use axum::response::{IntoResponse, Response};
+use http_body_util::{BodyExt, LengthLimitError, Limited};
+use ring::hmac;
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tower::{Layer, Service};
+use tracing::Span;
+
+pub const X_WEBHOOK_SIGNATURE: &str = "x-webhook-signature";
+pub const X_WEBHOOK_TIMESTAMP: &str = "x-webhook-timestamp";
+pub const X_WEBHOOK_KEY_ID: &str = "x-webhook-key-id";
+
+/// Why a webhook failed verification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerificationError {
+    MissingSignature,
+    MissingTimestamp,
+    InvalidTimestamp,
+    /// The timestamp is outside the replay window.
+    Expired,
+    UnknownKey,
+    InvalidSignature,
+}
+
+impl VerificationError {
+    /// A stable code for the response body and the span.
+    pub fn code(&self) -> &'static str {
+        match self {
+            VerificationError::MissingSignature => "missing_signature",
+            VerificationError::MissingTimestamp => "missing_timestamp",
+            VerificationError::InvalidTimestamp => "invalid_timestamp",
+            VerificationError::Expired => "expired",
+            VerificationError::UnknownKey => "unknown_key",
+            VerificationError::InvalidSignature => "invalid_signature",
+        }
+    }
+}
+
+impl std::fmt::Display for VerificationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            VerificationError::MissingSignature => "the signature header is missing",
+            VerificationError::MissingTimestamp => "the timestamp header is missing",
+            VerificationError::InvalidTimestamp => "the timestamp is not a number of seconds since the epoch",
+            VerificationError::Expired => "the timestamp is outside the replay window",
+            VerificationError::UnknownKey => "the signing key is unknown",
+            VerificationError::InvalidSignature => "the signature does not match the payload",
+        };
+        f.write_str(message)
+    }
+}
+
+impl std::error::Error for VerificationError {}
+
+/// The hex HMAC-SHA256 of `{timestamp}.{body}` with `secret`, as senders put it in the
+/// signature header.
+pub fn sign(secret: &[u8], timestamp: u64, body: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+    let tag = hmac::sign(&key, &signed_payload(timestamp, body));
+    tag.as_ref().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn signed_payload(timestamp: u64, body: &[u8]) -> Vec<u8> {
+    let mut payload = format!("{}.", timestamp).into_bytes();
+    payload.extend_from_slice(body);
+    payload
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+type KeyLookup = dyn Fn(Option<&str>) -> Option<Vec<u8>> + Send + Sync;
+
+#[derive(Clone)]
+struct SignatureConfig {
+    keys: Arc<KeyLookup>,
+    signature_header: HeaderName,
+    timestamp_header: HeaderName,
+    key_id_header: HeaderName,
+    tolerance: Duration,
+    max_body_bytes: usize,
+}
+
+/// Verifies HMAC-SHA256 signatures of webhook deliveries before they reach the handler.
+///
+/// The signature header holds the hex HMAC of `{timestamp}.{raw body}`, see [`sign`].
+/// It may list several comma separated signatures, each optionally prefixed like `v1=`,
+/// so senders can rotate keys; one match is enough. Signatures are compared in constant
+/// time and the timestamp must be within the replay window (5 minutes by default).
+///
+/// The body is buffered for verification, up to 1 MiB by default, and handed to the
+/// handler unchanged. Failures are answered with 401 and a JSON body naming the reason.
+/// The outcome is set as the `webhook.verified` and `webhook.failure` attributes of the
+/// current span.
+#[derive(Clone)]
+pub struct SignatureLayer {
+    config: Arc<SignatureConfig>,
+}
+
+impl SignatureLayer {
+    /// Verifies deliveries signed with a single `secret`.
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        let secret = secret.into();
+        Self::with_key_lookup(move |_| Some(secret.clone()))
+    }
+
+    /// Verifies deliveries with the key named by the key ID header, `None` when the
+    /// delivery has none.
+    pub fn with_key_lookup<F>(lookup: F) -> Self
+    where
+        F: Fn(Option<&str>) -> Option<Vec<u8>> + Send + Sync + 'static,
+    {
+        SignatureLayer {
+            config: Arc::new(SignatureConfig {
+                keys: Arc::new(lookup),
+                signature_header: HeaderName::from_static(X_WEBHOOK_SIGNATURE),
+                timestamp_header: HeaderName::from_static(X_WEBHOOK_TIMESTAMP),
+                key_id_header: HeaderName::from_static(X_WEBHOOK_KEY_ID),
+                tolerance: Duration::from_secs(300),
+                max_body_bytes: 1024 * 1024,
+            }),
+        }
+    }
+
+    pub fn with_signature_header(mut self, header: HeaderName) -> Self {
+        self.config_mut().signature_header = header;
+        self
+    }
+
+    pub fn with_timestamp_header(mut self, header: HeaderName) -> Self {
+        self.config_mut().timestamp_header = header;
+        self
+    }
+
+    pub fn with_key_id_header(mut self, header: HeaderName) -> Self {
+        self.config_mut().key_id_header = header;
+        self
+    }
+
+    /// How far the timestamp may be from now, in either direction.
+    pub fn with_tolerance(mut self, tolerance: Duration) -> Self {
+        self.config_mut().tolerance = tolerance;
+        self
+    }
+
+    /// Larger bodies are answered with 413.
+    pub fn with_max_body_bytes(mut self, max_body_bytes: usize) -> Self {
+        self.config_mut().max_body_bytes = max_body_bytes;
+        self
+    }
+
+    fn config_mut(&mut self) -> &mut SignatureConfig {
+        Arc::make_mut(&mut self.config)
+    }
+
+    /// Checks the signature of a delivery received at `now`.
+    pub fn verify(&self, headers: &HeaderMap, body: &[u8], now: SystemTime) -> Result<(), VerificationError> {
+        let config = &self.config;
+        let header = |name: &HeaderName| headers.get(name).and_then(|value| value.to_str().ok());
+        let signatures = header(&config.signature_header).ok_or(VerificationError::MissingSignature)?;
+        let timestamp: u64 = header(&config.timestamp_header)
+            .ok_or(VerificationError::MissingTimestamp)?
+            .trim()
+            .parse()
+            .map_err(|_| VerificationError::InvalidTimestamp)?;
+
+        let now = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        if now.abs_diff(timestamp) > config.tolerance.as_secs() {
+            return Err(VerificationError::Expired);
+        }
+
+        let secret = (config.keys)(header(&config.key_id_header)).ok_or(VerificationError::UnknownKey)?;
+        let key = hmac::Key::new(hmac::HMAC_SHA256, &secret);
+        let payload = signed_payload(timestamp, body);
+        let matches = signatures
+            .split(',')
+            .map(|signature| signature.trim())
+            .map(|signature| signature.split_once('=').map_or(signature, |(_, hex)| hex))
+            .filter_map(decode_hex)
+            .any(|tag| hmac::verify(&key, &payload, &tag).is_ok());
+        if matches { Ok(()) } else { Err(VerificationError::InvalidSignature) }
+    }
+}
+
+impl<S> Layer<S> for SignatureLayer {
+    type Service = SignatureService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SignatureService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SignatureService<S> {
+    inner: S,
+    layer: SignatureLayer,
+}
+
+fn unauthorized(error: VerificationError) -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        Json(serde_json::json!({ "error": error.code(), "message": error.to_string() })),
+    )
+        .into_response()
+}
+
+impl<S> Service<Request> for SignatureService<S>
+where
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+        let span = Span::current();
+        Box::pin(async move {
+            let (parts, body) = req.into_parts();
+            let body = match Limited::new(body, layer.config.max_body_bytes).collect().await {
+                Ok(collected) => collected.to_bytes(),
+                Err(err) if err.is::<LengthLimitError>() => {
+                    return Ok(StatusCode::PAYLOAD_TOO_LARGE.into_response());
+                }
+                Err(err) => {
+                    warn!("failed to buffer webhook body: {}", err);
+                    return Ok(StatusCode::BAD_REQUEST.into_response());
+                }
+            };
+
+            let verified = layer.verify(&parts.headers, &body, SystemTime::now());
+            attrs::record(&span, attrs! { "webhook.verified" => verified.is_ok() });
+            if let Err(error) = verified {
+                attrs::record(&span, attrs! { "webhook.failure" => error.code() });
+                warn!("rejected webhook to {}: {}", parts.uri.path(), error);
+                return Ok(unauthorized(error));
+            }
+            inner.call(Request::from_parts(parts, Body::from(body))).await
+        })
+    }
+}
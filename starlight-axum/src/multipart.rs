@@ -0,0 +1,367 @@
+//! Streaming `multipart/form-data` uploads with size and content type limits.
+//!
+//! ```ignore
+//! async fn upload(mut form: Multipart) -> Result<StatusCode, Rejection> {
+//!     while let Some(part) = form.next_part().await? {
+//!         match part.data() {
+//!             PartData::Memory(bytes) => store(part.name(), bytes).await,
+//!             PartData::File(file) => store_file(part.name(), file.path()).await,
+//!         }
+//!     }
+//!     Ok(StatusCode::CREATED)
+//! }
+//!
+//! let routes = Router::new()
+//!     .route("/avatars", post(upload))
+//!     .route_layer(Extension(MultipartConfig::new().with_content_types("avatar", ["image/*"])))
+//!     .layer(DefaultBodyLimit::disable());
+//! ```
+//!
+//! axum's `DefaultBodyLimit` of 2 MB still applies underneath; disable or raise it for
+//! routes which accept more.
+
+use crate::meter::GLOBAL_METER;
+use crate::rejection::{Rejection, reject};
+use axum::body::Bytes;
+use axum::extract::{FromRequest, MatchedPath, Request};
+use axum::http::{Extensions, StatusCode};
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Counter, Meter};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use tokio::io::AsyncWriteExt;
+
+pub const UPLOAD_BYTES: &str = "http.server.request.upload.bytes";
+pub const UPLOAD_PROGRESS_EVENT: &str = "http.request.upload.progress";
+
+/// Limits of [`Multipart`], installed for a route with
+/// `.route_layer(Extension(MultipartConfig::new().with_max_total_bytes(..)))`; the
+/// defaults apply without one.
+#[derive(Debug, Clone)]
+pub struct MultipartConfig {
+    max_field_bytes: u64,
+    max_total_bytes: u64,
+    content_types: Arc<HashMap<String, Vec<String>>>,
+    spill_above: Option<u64>,
+    temp_dir: PathBuf,
+    progress_every: u64,
+    uploaded: Counter<u64>,
+}
+
+impl Default for MultipartConfig {
+    fn default() -> Self {
+        Self::with_meter(&GLOBAL_METER)
+    }
+}
+
+impl MultipartConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_meter(meter: &Meter) -> Self {
+        MultipartConfig {
+            max_field_bytes: 10 * 1024 * 1024,
+            max_total_bytes: 50 * 1024 * 1024,
+            content_types: Arc::default(),
+            spill_above: None,
+            temp_dir: std::env::temp_dir(),
+            progress_every: 1024 * 1024,
+            uploaded: meter
+                .u64_counter(UPLOAD_BYTES)
+                .with_unit("By")
+                .with_description("Bytes of multipart uploads received")
+                .build(),
+        }
+    }
+
+    /// 10 MiB by default.
+    pub fn with_max_field_bytes(mut self, bytes: u64) -> Self {
+        self.max_field_bytes = bytes;
+        self
+    }
+
+    /// 50 MiB by default.
+    pub fn with_max_total_bytes(mut self, bytes: u64) -> Self {
+        self.max_total_bytes = bytes;
+        self
+    }
+
+    /// Only accepts the field `name` with one of `content_types`, such as `image/png`
+    /// or `image/*`, or without a content type when the list is empty.
+    pub fn with_content_types<I, T>(mut self, name: impl Into<String>, content_types: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        let allowed = content_types.into_iter().map(|t| t.into().to_ascii_lowercase()).collect();
+        Arc::make_mut(&mut self.content_types).insert(name.into(), allowed);
+        self
+    }
+
+    /// Writes parts larger than `bytes` to a temporary file instead of keeping them in
+    /// memory. Parts stay in memory by default.
+    pub fn with_spill_above(mut self, bytes: u64) -> Self {
+        self.spill_above = Some(bytes);
+        self
+    }
+
+    /// Where spilled parts are written, [`std::env::temp_dir`] by default.
+    pub fn with_temp_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.temp_dir = dir.into();
+        self
+    }
+
+    /// How often a progress event is recorded on the request span, every MiB by default.
+    pub fn with_progress_every(mut self, bytes: u64) -> Self {
+        self.progress_every = bytes.max(1);
+        self
+    }
+
+    fn allows(&self, name: &str, content_type: Option<&str>) -> bool {
+        let Some(allowed) = self.content_types.get(name) else {
+            return true;
+        };
+        let Some(content_type) = content_type else {
+            return allowed.is_empty();
+        };
+        let essence = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        allowed.iter().any(|allowed| match allowed.strip_suffix("/*") {
+            Some(prefix) => essence.split('/').next() == Some(prefix),
+            None => essence == *allowed,
+        })
+    }
+}
+
+fn default_config() -> MultipartConfig {
+    static DEFAULT: OnceLock<MultipartConfig> = OnceLock::new();
+    DEFAULT.get_or_init(MultipartConfig::new).clone()
+}
+
+/// A part spilled to disk, removed when dropped.
+#[derive(Debug)]
+pub struct TempFile {
+    path: PathBuf,
+    len: u64,
+}
+
+impl TempFile {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[derive(Debug)]
+pub enum PartData {
+    Memory(Bytes),
+    File(TempFile),
+}
+
+/// A field of the form, read completely.
+#[derive(Debug)]
+pub struct Part {
+    name: String,
+    file_name: Option<String>,
+    content_type: Option<String>,
+    data: PartData,
+}
+
+impl Part {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn file_name(&self) -> Option<&str> {
+        self.file_name.as_deref()
+    }
+
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    pub fn data(&self) -> &PartData {
+        &self.data
+    }
+
+    pub fn into_data(self) -> PartData {
+        self.data
+    }
+}
+
+/// axum's `Multipart` with the limits of the [`MultipartConfig`], read part by part.
+///
+/// A part over the field limit, or a form over the total limit, is rejected with 413
+/// problem details whose reason is `multipart.field_too_large` or
+/// `multipart.too_large`; a field with a content type that is not allowed with 415 and
+/// `multipart.content_type`. Malformed forms are 400 with `multipart.invalid`. Received
+/// bytes are counted on `http.server.request.upload.bytes`, and an
+/// `http.request.upload.progress` event is recorded on the current span as they add up.
+pub struct Multipart {
+    inner: axum::extract::Multipart,
+    upload: Upload,
+}
+
+/// What [`Multipart`] keeps besides the form, apart so it can be updated while a field
+/// borrows the form.
+struct Upload {
+    config: MultipartConfig,
+    extensions: Extensions,
+    route: String,
+    received: u64,
+    reported: u64,
+}
+
+impl<S: Send + Sync> FromRequest<S> for Multipart {
+    type Rejection = Rejection;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let extensions = req.extensions().clone();
+        let config = extensions.get::<MultipartConfig>().cloned().unwrap_or_else(default_config);
+        let route = extensions.get::<MatchedPath>().map_or("", MatchedPath::as_str).to_owned();
+        match axum::extract::Multipart::from_request(req, state).await {
+            Ok(inner) => Ok(Multipart {
+                inner,
+                upload: Upload {
+                    config,
+                    extensions,
+                    route,
+                    received: 0,
+                    reported: 0,
+                },
+            }),
+            Err(rejection) => Err(reject(
+                &extensions,
+                rejection.status(),
+                "multipart.invalid",
+                rejection.body_text(),
+            )),
+        }
+    }
+}
+
+impl Multipart {
+    /// The next part, `None` after the last one.
+    pub async fn next_part(&mut self) -> Result<Option<Part>, Rejection> {
+        let upload = &mut self.upload;
+        let mut field = match self.inner.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => return Ok(None),
+            Err(err) => return Err(upload.reject(err.status(), "multipart.invalid", err.body_text())),
+        };
+        let name = field.name().unwrap_or_default().to_owned();
+        let file_name = field.file_name().map(str::to_owned);
+        let content_type = field.content_type().map(str::to_owned);
+        if !upload.config.allows(&name, content_type.as_deref()) {
+            let detail = format!(
+                "field `{name}` does not accept {}",
+                content_type.as_deref().unwrap_or("a part without a content type")
+            );
+            return Err(upload.reject(StatusCode::UNSUPPORTED_MEDIA_TYPE, "multipart.content_type", detail));
+        }
+
+        let mut buffer = Vec::new();
+        let mut file: Option<(tokio::fs::File, TempFile)> = None;
+        let mut len = 0u64;
+        loop {
+            let chunk = match field.chunk().await {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => break,
+                Err(err) => return Err(upload.reject(err.status(), "multipart.invalid", err.body_text())),
+            };
+            len += chunk.len() as u64;
+            upload.progress(chunk.len() as u64);
+            if len > upload.config.max_field_bytes {
+                let detail = format!("field `{name}` is over {} bytes", upload.config.max_field_bytes);
+                return Err(upload.reject(StatusCode::PAYLOAD_TOO_LARGE, "multipart.field_too_large", detail));
+            }
+            if upload.received > upload.config.max_total_bytes {
+                let detail = format!("the form is over {} bytes", upload.config.max_total_bytes);
+                return Err(upload.reject(StatusCode::PAYLOAD_TOO_LARGE, "multipart.too_large", detail));
+            }
+
+            if file.is_none() && upload.config.spill_above.is_some_and(|above| len > above) {
+                file = Some(upload.spill(&buffer).await?);
+                buffer = Vec::new();
+            }
+            match &mut file {
+                Some((handle, temp)) => {
+                    handle.write_all(&chunk).await.map_err(|err| upload.write_failed(err))?;
+                    temp.len = len;
+                }
+                None => buffer.extend_from_slice(&chunk),
+            }
+        }
+
+        let data = match file {
+            Some((mut handle, temp)) => {
+                handle.flush().await.map_err(|err| upload.write_failed(err))?;
+                PartData::File(temp)
+            }
+            None => PartData::Memory(Bytes::from(buffer)),
+        };
+        Ok(Some(Part {
+            name,
+            file_name,
+            content_type,
+            data,
+        }))
+    }
+
+    /// Bytes received so far, over all parts.
+    pub fn received(&self) -> u64 {
+        self.upload.received
+    }
+}
+
+impl Upload {
+    async fn spill(&self, buffer: &[u8]) -> Result<(tokio::fs::File, TempFile), Rejection> {
+        let path = self.config.temp_dir.join(format!("starlight-upload-{}", uuid::Uuid::new_v4()));
+        let mut handle = tokio::fs::File::create(&path).await.map_err(|err| self.write_failed(err))?;
+        // From here on the file is removed if the part fails.
+        let temp = TempFile {
+            path,
+            len: buffer.len() as u64,
+        };
+        handle.write_all(buffer).await.map_err(|err| self.write_failed(err))?;
+        Ok((handle, temp))
+    }
+
+    fn progress(&mut self, bytes: u64) {
+        self.received += bytes;
+        self.config
+            .uploaded
+            .add(bytes, &[KeyValue::new("http.route", self.route.clone())]);
+        if self.received - self.reported >= self.config.progress_every {
+            self.reported = self.received;
+            info!(http.upload.received_bytes = self.received, "{}", UPLOAD_PROGRESS_EVENT);
+        }
+    }
+
+    fn write_failed(&self, err: std::io::Error) -> Rejection {
+        error!(route = %self.route, "failed to spill an upload to disk: {}", err);
+        Rejection {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            reason: "multipart.storage",
+            detail: "the upload could not be stored".to_owned(),
+        }
+    }
+
+    fn reject(&self, status: StatusCode, reason: &'static str, detail: String) -> Rejection {
+        reject(&self.extensions, status, reason, detail)
+    }
+}
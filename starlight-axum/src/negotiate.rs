@@ -0,0 +1,306 @@
+use axum::body::Bytes;
+use axum::extract::{FromRequest, FromRequestParts, Request};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+/// A body encoding [`Negotiate`] can read and write. JSON is always available,
+/// MessagePack and CBOR with the `msgpack` and `cbor` features, through `rmp-serde` and
+/// `ciborium`. Structs are written as maps keyed by field name in every format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Format {
+    Json,
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+    #[cfg(feature = "cbor")]
+    Cbor,
+}
+
+impl Format {
+    /// Every available format, in order of preference when the client has none.
+    pub const ALL: &'static [Format] = &[
+        Format::Json,
+        #[cfg(feature = "msgpack")]
+        Format::MessagePack,
+        #[cfg(feature = "cbor")]
+        Format::Cbor,
+    ];
+
+    pub fn media_type(&self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            #[cfg(feature = "msgpack")]
+            Format::MessagePack => "application/msgpack",
+            #[cfg(feature = "cbor")]
+            Format::Cbor => "application/cbor",
+        }
+    }
+
+    /// Whether `essence`, a lowercase media type without parameters, names this format.
+    fn matches(&self, essence: &str) -> bool {
+        match self {
+            Format::Json => essence == "application/json" || essence.ends_with("+json"),
+            #[cfg(feature = "msgpack")]
+            Format::MessagePack => matches!(
+                essence,
+                "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack"
+            ),
+            #[cfg(feature = "cbor")]
+            Format::Cbor => essence == "application/cbor" || essence.ends_with("+cbor"),
+        }
+    }
+
+    /// The format to answer a request with `headers` in: the acceptable one with the
+    /// highest quality, JSON without an `Accept` header.
+    pub fn from_accept(headers: &HeaderMap) -> Result<Format, NegotiationError> {
+        let ranges: Vec<(String, f32)> = headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|item| {
+                let mut parts = item.split(';');
+                let range = parts.next()?.trim().to_ascii_lowercase();
+                let q = parts
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .map(|q| q.trim().parse::<f32>().unwrap_or(0.0))
+                    .unwrap_or(1.0);
+                (!range.is_empty()).then_some((range, q))
+            })
+            .collect();
+        if ranges.is_empty() {
+            return Ok(Format::Json);
+        }
+
+        let mut best: Option<(Format, f32)> = None;
+        for format in Format::ALL {
+            // The most specific matching range decides the quality.
+            let (major, _) = format.media_type().split_once('/').expect("media types have a subtype");
+            let q = ranges
+                .iter()
+                .filter_map(|(range, q)| match range.as_str() {
+                    "*/*" => Some((0, *q)),
+                    range if range.strip_suffix("/*") == Some(major) => Some((1, *q)),
+                    range if format.matches(range) => Some((2, *q)),
+                    _ => None,
+                })
+                .max_by_key(|(specificity, _)| *specificity)
+                .map_or(0.0, |(_, q)| q);
+            if q > 0.0 && best.is_none_or(|(_, best)| q > best) {
+                best = Some((*format, q));
+            }
+        }
+        best.map(|(format, _)| format).ok_or(NegotiationError::NotAcceptable)
+    }
+
+    /// The format of a request body, JSON when there is no `Content-Type`.
+    pub fn from_content_type(headers: &HeaderMap) -> Result<Format, NegotiationError> {
+        let Some(content_type) = headers.get(header::CONTENT_TYPE) else {
+            return Ok(Format::Json);
+        };
+        let essence = content_type
+            .to_str()
+            .unwrap_or_default()
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        Format::ALL
+            .iter()
+            .find(|format| format.matches(&essence))
+            .copied()
+            .ok_or(NegotiationError::UnsupportedMediaType)
+    }
+
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, NegotiationError> {
+        let encoded = match self {
+            Format::Json => serde_json::to_vec(value).map_err(|err| err.to_string()),
+            #[cfg(feature = "msgpack")]
+            Format::MessagePack => rmp_serde::to_vec_named(value).map_err(|err| err.to_string()),
+            #[cfg(feature = "cbor")]
+            Format::Cbor => {
+                let mut encoded = Vec::new();
+                ciborium::into_writer(value, &mut encoded)
+                    .map(|()| encoded)
+                    .map_err(|err| err.to_string())
+            }
+        };
+        encoded.map_err(NegotiationError::Encode)
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, NegotiationError> {
+        let decoded = match self {
+            Format::Json => serde_json::from_slice(bytes).map_err(|err| err.to_string()),
+            #[cfg(feature = "msgpack")]
+            Format::MessagePack => rmp_serde::from_slice(bytes).map_err(|err| err.to_string()),
+            #[cfg(feature = "cbor")]
+            Format::Cbor => ciborium::from_reader(bytes).map_err(|err| err.to_string()),
+        };
+        decoded.map_err(NegotiationError::Decode)
+    }
+}
+
+/// Why a request could not be negotiated, answered with a problem details body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NegotiationError {
+    /// 406: no available format is acceptable to the client.
+    NotAcceptable,
+    /// 415: the body is in a format that is not available.
+    UnsupportedMediaType,
+    /// 400: the body is not a valid value of its format.
+    Decode(String),
+    /// 500: the value could not be encoded.
+    Encode(String),
+}
+
+impl std::fmt::Display for NegotiationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NegotiationError::NotAcceptable => f.write_str("none of the accepted media types is supported"),
+            NegotiationError::UnsupportedMediaType => f.write_str("the content type is not supported"),
+            NegotiationError::Decode(err) => write!(f, "failed to decode the body: {}", err),
+            NegotiationError::Encode(err) => write!(f, "failed to encode the response: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for NegotiationError {}
+
+impl IntoResponse for NegotiationError {
+    fn into_response(self) -> Response {
+        let (status, title) = match self {
+            NegotiationError::NotAcceptable => (StatusCode::NOT_ACCEPTABLE, "Not Acceptable"),
+            NegotiationError::UnsupportedMediaType => (StatusCode::UNSUPPORTED_MEDIA_TYPE, "Unsupported Media Type"),
+            NegotiationError::Decode(_) => (StatusCode::BAD_REQUEST, "Bad Request"),
+            NegotiationError::Encode(ref err) => {
+                error!("failed to encode response: {}", err);
+                (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error")
+            }
+        };
+        let mut body = serde_json::json!({
+            "type": "about:blank",
+            "title": title,
+            "status": status.as_u16(),
+            "detail": self.to_string(),
+        });
+        if matches!(self, NegotiationError::NotAcceptable | NegotiationError::UnsupportedMediaType) {
+            let supported: Vec<&str> = Format::ALL.iter().map(Format::media_type).collect();
+            body["supported"] = serde_json::json!(supported);
+        }
+        (status, [(header::CONTENT_TYPE, "application/problem+json")], body.to_string()).into_response()
+    }
+}
+
+/// The format to answer in, negotiated from `Accept`; rejects with 406.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResponseFormat(pub Format);
+
+impl ResponseFormat {
+    pub fn respond<T>(&self, value: T) -> Negotiate<T> {
+        Negotiate::new(value, self.0)
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for ResponseFormat {
+    type Rejection = NegotiationError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Format::from_accept(&parts.headers).map(ResponseFormat)
+    }
+}
+
+/// A value encoded as JSON, MessagePack or CBOR, whichever the client prefers.
+///
+/// As an extractor it decodes the body by its `Content-Type` (415 when unsupported, 400
+/// when invalid), and `format` is the one negotiated from `Accept` for the answer (406
+/// when none is acceptable), so handlers reply with [`Negotiate::respond`]:
+///
+/// ```ignore
+/// async fn update(order: Negotiate<Order>) -> Negotiate<Order> {
+///     order.respond(save(order.value).await)
+/// }
+/// ```
+///
+/// Handlers without a body take a [`ResponseFormat`] instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Negotiate<T> {
+    pub value: T,
+    pub format: Format,
+}
+
+impl<T> Negotiate<T> {
+    pub fn new(value: T, format: Format) -> Self {
+        Negotiate { value, format }
+    }
+
+    /// `value` in the format of this one.
+    pub fn respond<U>(&self, value: U) -> Negotiate<U> {
+        Negotiate::new(value, self.format)
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T: Serialize> IntoResponse for Negotiate<T> {
+    fn into_response(self) -> Response {
+        match self.format.encode(&self.value) {
+            Ok(body) => (
+                [
+                    (header::CONTENT_TYPE, HeaderValue::from_static(self.format.media_type())),
+                    (header::VARY, HeaderValue::from_static("accept")),
+                ],
+                body,
+            )
+                .into_response(),
+            Err(err) => err.into_response(),
+        }
+    }
+}
+
+impl<T, S> FromRequest<S> for Negotiate<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let format = Format::from_accept(req.headers()).map_err(IntoResponse::into_response)?;
+        let content_format = Format::from_content_type(req.headers()).map_err(IntoResponse::into_response)?;
+        let bytes = Bytes::from_request(req, state).await.map_err(IntoResponse::into_response)?;
+        let value = content_format.decode(&bytes).map_err(IntoResponse::into_response)?;
+        Ok(Negotiate { value, format })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accept(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn negotiates_by_quality_and_specificity() {
+        assert_eq!(Format::from_accept(&HeaderMap::new()), Ok(Format::Json));
+        assert_eq!(Format::from_accept(&accept("text/html, */*;q=0.1")), Ok(Format::Json));
+        assert_eq!(Format::from_accept(&accept("application/problem+json")), Ok(Format::Json));
+        assert_eq!(Format::from_accept(&accept("text/html")), Err(NegotiationError::NotAcceptable));
+
+        #[cfg(all(feature = "msgpack", feature = "cbor"))]
+        {
+            let cbor_first = accept("application/cbor;q=0.9, application/msgpack;q=0.8, */*;q=0.1");
+            assert_eq!(Format::from_accept(&cbor_first), Ok(Format::Cbor));
+            assert_eq!(Format::from_accept(&accept("application/json;q=0, */*")), Ok(Format::MessagePack));
+            assert_eq!(Format::from_accept(&accept("application/cbor, application/x-msgpack")), Ok(Format::MessagePack));
+        }
+    }
+}
@@ -1,64 +1,159 @@
-use crate::logger::{CustomLogFormatter, get_logger_provider, get_or_init_logger_provider};
-use crate::meter::{get_meter_provider, get_or_init_meter_provider};
-use crate::tracer::{get_or_init_tracer_provider, get_tracer_provider};
-use crate::{get_env_or_default, get_env_or_panic};
+mod endpoint;
+mod failover;
+mod headers;
+
+pub use endpoint::{OtlpEndpoint, OtlpProtocol, Signal};
+pub use failover::{FAILOVER_EVENT, FailoverExporter, FailoverPolicy};
+pub use headers::HeadersConfig;
+
+use crate::config::{self, TelemetryConfig};
+use crate::deadline::Deadline;
+use crate::logger::{LoggerConfig, get_logger_provider, get_or_init_logger_provider_with_headers, try_get_logger_provider};
+use crate::meter::{
+    FinalObservations, MeterConfig, get_meter_provider, get_or_init_meter_provider_with_headers,
+    shutdown_meter_provider, try_get_meter_provider,
+};
+use crate::tracer::{get_or_init_tracer_provider_with_headers, get_tracer_provider, try_get_tracer_provider};
+use crate::resource::init_resource;
 use opentelemetry::global;
 use opentelemetry::trace::TracerProvider;
 use opentelemetry_appender_tracing::layer::OpenTelemetryTracingBridge;
 use opentelemetry_sdk::propagation::TraceContextPropagator;
 use std::error::Error;
+use std::time::Duration;
 use tracing_appender::non_blocking::WorkerGuard;
 use tracing_opentelemetry::{MetricsLayer, OpenTelemetryLayer};
 use tracing_subscriber::EnvFilter;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
+/// How long [`shutdown_oltp`] waits for the last metric export, then for the shutdown.
+const FINAL_EXPORT_TIMEOUT: Duration = Duration::from_secs(5);
+
 pub fn config_oltp(
     oltp_grpc_url: &str,
 ) -> Result<WorkerGuard, Box<dyn Error + Send + Sync + 'static>> {
-    let tracer_provider = get_or_init_tracer_provider(oltp_grpc_url);
-    let logger_provider = get_or_init_logger_provider(oltp_grpc_url);
-    let meter_provider = get_or_init_meter_provider(oltp_grpc_url);
+    let mut guards = config_oltp_with_logger(oltp_grpc_url, LoggerConfig::from_env()?)?;
+    guards.pop().ok_or_else(|| "the default logger config has no file sink".into())
+}
+
+/// Same as [`config_oltp`] but with custom log sinks. Returns one guard per file sink.
+///
+/// The configuration is validated first; every problem found is reported in the error.
+pub fn config_oltp_with_logger(
+    oltp_grpc_url: &str,
+    logger_config: LoggerConfig,
+) -> Result<Vec<WorkerGuard>, Box<dyn Error + Send + Sync + 'static>> {
+    config_oltp_with_config(&TelemetryConfig::from_env(oltp_grpc_url), logger_config)
+}
+
+/// Same as [`config_oltp_with_logger`] with settings from `config`, e.g. the `telemetry`
+/// section of an [`AppConfig`](crate::config::AppConfig), instead of the environment.
+pub fn config_oltp_with_config(
+    config: &TelemetryConfig,
+    logger_config: LoggerConfig,
+) -> Result<Vec<WorkerGuard>, Box<dyn Error + Send + Sync + 'static>> {
+    if let Err(errors) = config::validate(config) {
+        let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        return Err(format!("invalid telemetry configuration: {}", errors.join("; ")).into());
+    }
+
+    init_resource(config);
+    // Validated above, so every endpoint parses; this fills in the default port.
+    let endpoints: Vec<String> = std::iter::once(&config.otlp_endpoint)
+        .chain(&config.otlp_fallback_endpoints)
+        .map(|endpoint| OtlpEndpoint::parse("otlp.endpoint", endpoint, OtlpProtocol::Grpc).map(|e| e.to_string()))
+        .collect::<Result<_, _>>()?;
+    let policy = FailoverPolicy::default();
+    // Validated above as well.
+    let headers = HeadersConfig::from_telemetry(config).unwrap_or_default();
+    let tracer_provider = get_or_init_tracer_provider_with_headers(&endpoints, policy, &headers);
+    let logger_provider = get_or_init_logger_provider_with_headers(&endpoints, policy, &headers);
+    let meter_config = MeterConfig::from_telemetry(config).unwrap_or_default();
+    let meter_provider = get_or_init_meter_provider_with_headers(&endpoints, policy, &meter_config, &headers);
     global::set_tracer_provider(tracer_provider.clone());
     global::set_meter_provider(meter_provider.clone());
 
-    let tracer = tracer_provider.tracer(get_env_or_panic("CARGO_PKG_NAME"));
+    let tracer = tracer_provider.tracer(config.service_name.clone().unwrap_or_default());
     // Create a new OpenTelemetryTracingBridge using the above LoggerProvider.
     let layer = OpenTelemetryTracingBridge::new(&logger_provider);
 
-    let file_appender =
-        tracing_appender::rolling::minutely(".logs", get_env_or_panic("CARGO_PKG_NAME"));
-    let (nonblocking_file, _guard_file) = tracing_appender::non_blocking(file_appender);
-
-    let file_logger = tracing_subscriber::fmt::layer()
-        .event_format(CustomLogFormatter)
-        .with_writer(nonblocking_file);
+    let (sinks, guards) = logger_config.build_layers()?;
 
-    let console_logger = tracing_subscriber::fmt::layer()
-        .event_format(CustomLogFormatter)
-        .with_writer(std::io::stdout);
-
-    let log_level_filter = EnvFilter::new(get_env_or_default(
-        "RUST_LOG",
-        "debug,axum_web_server=debug,tower_http=trace".to_owned(),
-    ));
+    let log_level_filter = EnvFilter::try_new(
+        config
+            .rust_log
+            .as_deref()
+            .unwrap_or("debug,axum_web_server=debug,tower_http=trace"),
+    )?;
 
     global::set_text_map_propagator(TraceContextPropagator::new());
     tracing_subscriber::registry()
         .with(log_level_filter)
-        .with(file_logger)
-        .with(console_logger)
+        .with(sinks)
         .with(layer)
         .with(MetricsLayer::new(meter_provider))
         .with(OpenTelemetryLayer::new(tracer))
         .init();
 
-    Ok(_guard_file)
+    info!(config = %config::dump_redacted(config), "telemetry configured");
+    Ok(guards)
 }
 
+/// Shuts the providers down, recording the [`FinalObservations`] just before the last
+/// metric export.
 pub fn shutdown_oltp() -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
     get_tracer_provider().shutdown()?;
-    get_meter_provider().shutdown()?;
+    shutdown_meter_provider(get_meter_provider(), FinalObservations::global(), FINAL_EXPORT_TIMEOUT)?;
     get_logger_provider().shutdown()?;
     Ok(())
 }
+
+/// Export everything buffered so far without shutting the providers down. Does nothing
+/// for providers that were never configured.
+pub fn flush_oltp() -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    if let Some(provider) = try_get_tracer_provider() {
+        provider.force_flush()?;
+    }
+    if let Some(provider) = try_get_meter_provider() {
+        provider.force_flush()?;
+    }
+    if let Some(provider) = try_get_logger_provider() {
+        provider.force_flush()?;
+    }
+    Ok(())
+}
+
+/// Same as [`shutdown_oltp`], giving up at `deadline` so an unreachable collector cannot
+/// hold up the exit. Does nothing for providers that were never configured.
+pub async fn shutdown_oltp_before(deadline: Deadline) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    within(deadline, "shutdown", move || {
+        if let Some(provider) = try_get_tracer_provider() {
+            provider.shutdown_with_timeout(deadline.remaining())?;
+        }
+        if let Some(provider) = try_get_meter_provider() {
+            shutdown_meter_provider(provider, FinalObservations::global(), deadline.remaining())?;
+        }
+        if let Some(provider) = try_get_logger_provider() {
+            provider.shutdown_with_timeout(deadline.remaining())?;
+        }
+        Ok(())
+    })
+    .await
+}
+
+/// Same as [`flush_oltp`], giving up at `deadline`.
+pub async fn flush_oltp_before(deadline: Deadline) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    within(deadline, "flush", flush_oltp).await
+}
+
+/// Runs the blocking `f` until `deadline`; past it, `f` is left to finish in the background.
+async fn within<F>(deadline: Deadline, stage: &str, f: F) -> Result<(), Box<dyn Error + Send + Sync + 'static>>
+where
+    F: FnOnce() -> Result<(), Box<dyn Error + Send + Sync + 'static>> + Send + 'static,
+{
+    match tokio::time::timeout_at(deadline.instant(), tokio::task::spawn_blocking(f)).await {
+        Ok(joined) => joined?,
+        Err(_) => Err(format!("telemetry {} did not finish before the deadline", stage).into()),
+    }
+}
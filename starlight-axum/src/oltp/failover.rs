@@ -0,0 +1,442 @@
+use crate::config::redact_userinfo;
+use crate::meter::MeterConfig;
+use crate::oltp::{HeadersConfig, Signal};
+use opentelemetry_otlp::{ExporterBuildError, WithExportConfig, WithTonicConfig};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::logs::{LogBatch, LogExporter};
+use opentelemetry_sdk::metrics::Temporality;
+use opentelemetry_sdk::metrics::data::ResourceMetrics;
+use opentelemetry_sdk::metrics::exporter::PushMetricExporter;
+use opentelemetry_sdk::trace::{SpanData, SpanExporter};
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The message of the events logged when a [`FailoverExporter`] switches endpoints.
+pub const FAILOVER_EVENT: &str = "otlp.exporter.failover";
+
+/// When a [`FailoverExporter`] gives up on an endpoint, and how often it checks whether
+/// the primary is back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FailoverPolicy {
+    failures_before_switch: u32,
+    probe_interval: Duration,
+    retries: u32,
+}
+
+impl Default for FailoverPolicy {
+    fn default() -> Self {
+        FailoverPolicy {
+            failures_before_switch: 3,
+            probe_interval: Duration::from_secs(30),
+            retries: 0,
+        }
+    }
+}
+
+impl FailoverPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Consecutive failed batches before switching to the next endpoint, 3 by default.
+    pub fn with_failures_before_switch(mut self, failures: u32) -> Self {
+        self.failures_before_switch = failures.max(1);
+        self
+    }
+
+    /// How often a batch is tried on the primary first while another endpoint is active,
+    /// every 30 seconds by default.
+    pub fn with_probe_interval(mut self, interval: Duration) -> Self {
+        self.probe_interval = interval;
+        self
+    }
+
+    /// Tries a failed batch again up to `retries` times on the same endpoint before it
+    /// counts as a failure. The OTLP exporters do not retry on their own.
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+}
+
+#[derive(Debug)]
+struct Health {
+    active: usize,
+    failures: u32,
+    last_probe: Instant,
+}
+
+/// Exports to the first of an ordered list of OTLP endpoints, the primary, and moves on
+/// to the next one after [`FailoverPolicy::with_failures_before_switch`] consecutive
+/// failures. While another endpoint is active the primary is probed with a batch every
+/// [`FailoverPolicy::with_probe_interval`], and exports go back to it once that succeeds.
+///
+/// Every switch is logged as an `otlp.exporter.failover` event: a warning when leaving an
+/// endpoint, info when returning to the primary.
+///
+/// Wraps the span, log and metric exporters alike, see [`FailoverExporter::spans`],
+/// [`FailoverExporter::logs`] and [`FailoverExporter::metrics`].
+#[derive(Debug)]
+pub struct FailoverExporter<E> {
+    signal: &'static str,
+    endpoints: Vec<(String, E)>,
+    policy: FailoverPolicy,
+    health: Mutex<Health>,
+}
+
+impl<E> FailoverExporter<E> {
+    /// `signal` names the pipeline in the failover events, such as `traces`.
+    ///
+    /// Panics without endpoints.
+    pub fn new(signal: &'static str, endpoints: Vec<(String, E)>, policy: FailoverPolicy) -> Self {
+        assert!(!endpoints.is_empty(), "a failover exporter needs at least one endpoint");
+        FailoverExporter {
+            signal,
+            endpoints,
+            policy,
+            health: Mutex::new(Health {
+                active: 0,
+                failures: 0,
+                last_probe: Instant::now(),
+            }),
+        }
+    }
+
+    /// The endpoint batches are exported to.
+    pub fn active_endpoint(&self) -> &str {
+        &self.endpoints[self.health().active].0
+    }
+
+    fn health(&self) -> std::sync::MutexGuard<'_, Health> {
+        self.health.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The primary, when it is time to probe it.
+    fn probe_due(&self) -> Option<usize> {
+        let mut health = self.health();
+        if health.active == 0 || health.last_probe.elapsed() < self.policy.probe_interval {
+            return None;
+        }
+        health.last_probe = Instant::now();
+        Some(0)
+    }
+
+    async fn export_with<'a, F, Fut>(&'a self, export: F) -> OTelSdkResult
+    where
+        F: Fn(&'a E) -> Fut,
+        Fut: Future<Output = OTelSdkResult>,
+    {
+        if let Some(primary) = self.probe_due()
+            && self.attempt(primary, &export).await.is_ok()
+        {
+            return Ok(());
+        }
+        let active = self.health().active;
+        self.attempt(active, &export).await
+    }
+
+    async fn attempt<'a, F, Fut>(&'a self, index: usize, export: &F) -> OTelSdkResult
+    where
+        F: Fn(&'a E) -> Fut,
+        Fut: Future<Output = OTelSdkResult>,
+    {
+        let exporter = &self.endpoints[index].1;
+        let mut result = export(exporter).await;
+        for _ in 0..self.policy.retries {
+            if result.is_ok() {
+                break;
+            }
+            result = export(exporter).await;
+        }
+        self.report(index, &result);
+        result
+    }
+
+    fn report(&self, index: usize, result: &OTelSdkResult) {
+        let mut health = self.health();
+        match result {
+            Ok(()) if index == health.active => health.failures = 0,
+            Ok(()) => {
+                // Only the primary is tried besides the active endpoint.
+                info!(
+                    otlp.signal = self.signal,
+                    otlp.endpoint.from = %redact_userinfo(&self.endpoints[health.active].0),
+                    otlp.endpoint.to = %redact_userinfo(&self.endpoints[index].0),
+                    "{}", FAILOVER_EVENT
+                );
+                health.active = index;
+                health.failures = 0;
+            }
+            Err(err) if index == health.active => {
+                health.failures += 1;
+                if health.failures >= self.policy.failures_before_switch && self.endpoints.len() > 1 {
+                    let next = (index + 1) % self.endpoints.len();
+                    warn!(
+                        otlp.signal = self.signal,
+                        otlp.endpoint.from = %redact_userinfo(&self.endpoints[index].0),
+                        otlp.endpoint.to = %redact_userinfo(&self.endpoints[next].0),
+                        failures = health.failures,
+                        error = %err,
+                        "{}", FAILOVER_EVENT
+                    );
+                    health.active = next;
+                    health.failures = 0;
+                    health.last_probe = Instant::now();
+                }
+            }
+            Err(err) => debug!(otlp.signal = self.signal, error = %err, "primary OTLP endpoint is still failing"),
+        }
+    }
+
+    /// Runs `f` on every endpoint, returning the first error.
+    fn each(&self, mut f: impl FnMut(&E) -> OTelSdkResult) -> OTelSdkResult {
+        self.endpoints.iter().map(|(_, exporter)| f(exporter)).fold(Ok(()), Result::and)
+    }
+
+    fn each_mut(&mut self, mut f: impl FnMut(&mut E) -> OTelSdkResult) -> OTelSdkResult {
+        self.endpoints
+            .iter_mut()
+            .map(|(_, exporter)| f(exporter))
+            .fold(Ok(()), Result::and)
+    }
+}
+
+impl FailoverExporter<opentelemetry_otlp::SpanExporter> {
+    /// gRPC span exporters for `endpoints`, the primary first.
+    pub fn spans(endpoints: &[String], policy: FailoverPolicy) -> Result<Self, ExporterBuildError> {
+        Self::spans_with_headers(endpoints, policy, &HeadersConfig::default())
+    }
+
+    /// Same as [`FailoverExporter::spans`], sending the trace `headers`.
+    pub fn spans_with_headers(
+        endpoints: &[String],
+        policy: FailoverPolicy,
+        headers: &HeadersConfig,
+    ) -> Result<Self, ExporterBuildError> {
+        let exporters = endpoints
+            .iter()
+            .map(|endpoint| {
+                let exporter = opentelemetry_otlp::SpanExporter::builder()
+                    .with_tonic()
+                    .with_endpoint(endpoint)
+                    .with_metadata(headers.metadata(Signal::Traces))
+                    .build()?;
+                Ok((endpoint.clone(), exporter))
+            })
+            .collect::<Result<_, ExporterBuildError>>()?;
+        Ok(Self::new("traces", exporters, policy))
+    }
+}
+
+impl FailoverExporter<opentelemetry_otlp::LogExporter> {
+    /// gRPC log exporters for `endpoints`, the primary first.
+    pub fn logs(endpoints: &[String], policy: FailoverPolicy) -> Result<Self, ExporterBuildError> {
+        Self::logs_with_headers(endpoints, policy, &HeadersConfig::default())
+    }
+
+    /// Same as [`FailoverExporter::logs`], sending the log `headers`.
+    pub fn logs_with_headers(
+        endpoints: &[String],
+        policy: FailoverPolicy,
+        headers: &HeadersConfig,
+    ) -> Result<Self, ExporterBuildError> {
+        let exporters = endpoints
+            .iter()
+            .map(|endpoint| {
+                let exporter = opentelemetry_otlp::LogExporter::builder()
+                    .with_tonic()
+                    .with_endpoint(endpoint)
+                    .with_metadata(headers.metadata(Signal::Logs))
+                    .build()?;
+                Ok((endpoint.clone(), exporter))
+            })
+            .collect::<Result<_, ExporterBuildError>>()?;
+        Ok(Self::new("logs", exporters, policy))
+    }
+}
+
+impl FailoverExporter<opentelemetry_otlp::MetricExporter> {
+    /// gRPC metric exporters for `endpoints`, the primary first.
+    pub fn metrics(endpoints: &[String], policy: FailoverPolicy) -> Result<Self, ExporterBuildError> {
+        Self::metrics_with_config(endpoints, policy, &MeterConfig::default())
+    }
+
+    /// Same as [`FailoverExporter::metrics`] with the temporality and export timeout of
+    /// `config`.
+    pub fn metrics_with_config(
+        endpoints: &[String],
+        policy: FailoverPolicy,
+        config: &MeterConfig,
+    ) -> Result<Self, ExporterBuildError> {
+        Self::metrics_with_headers(endpoints, policy, config, &HeadersConfig::default())
+    }
+
+    /// Same as [`FailoverExporter::metrics_with_config`], sending the metric `headers`.
+    pub fn metrics_with_headers(
+        endpoints: &[String],
+        policy: FailoverPolicy,
+        config: &MeterConfig,
+        headers: &HeadersConfig,
+    ) -> Result<Self, ExporterBuildError> {
+        let exporters = endpoints
+            .iter()
+            .map(|endpoint| {
+                let mut exporter = opentelemetry_otlp::MetricExporter::builder()
+                    .with_tonic()
+                    .with_endpoint(endpoint)
+                    .with_metadata(headers.metadata(Signal::Metrics));
+                if let Some(timeout) = config.export_timeout {
+                    exporter = exporter.with_timeout(timeout);
+                }
+                let exporter = exporter.with_temporality(config.temporality.into()).build()?;
+                Ok((endpoint.clone(), exporter))
+            })
+            .collect::<Result<_, ExporterBuildError>>()?;
+        Ok(Self::new("metrics", exporters, policy))
+    }
+}
+
+impl<E: SpanExporter> SpanExporter for FailoverExporter<E> {
+    async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+        self.export_with(|exporter| exporter.export(batch.clone())).await
+    }
+
+    fn shutdown_with_timeout(&mut self, timeout: Duration) -> OTelSdkResult {
+        self.each_mut(|exporter| exporter.shutdown_with_timeout(timeout))
+    }
+
+    fn force_flush(&mut self) -> OTelSdkResult {
+        self.each_mut(SpanExporter::force_flush)
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        for (_, exporter) in &mut self.endpoints {
+            exporter.set_resource(resource);
+        }
+    }
+}
+
+impl<E: LogExporter> LogExporter for FailoverExporter<E> {
+    async fn export(&self, batch: LogBatch<'_>) -> OTelSdkResult {
+        let records: Vec<_> = batch.iter().collect();
+        self.export_with(|exporter| exporter.export(LogBatch::new(&records))).await
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.each(|exporter| exporter.shutdown_with_timeout(timeout))
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        for (_, exporter) in &mut self.endpoints {
+            exporter.set_resource(resource);
+        }
+    }
+}
+
+impl<E: PushMetricExporter> PushMetricExporter for FailoverExporter<E> {
+    async fn export(&self, metrics: &ResourceMetrics) -> OTelSdkResult {
+        self.export_with(|exporter| exporter.export(metrics)).await
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.each(PushMetricExporter::force_flush)
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.each(|exporter| exporter.shutdown_with_timeout(timeout))
+    }
+
+    fn temporality(&self) -> Temporality {
+        self.endpoints[0].1.temporality()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry_sdk::error::OTelSdkError;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    #[derive(Debug, Default)]
+    struct Collector {
+        down: AtomicBool,
+        received: AtomicUsize,
+    }
+
+    impl SpanExporter for &'static Collector {
+        async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+            if self.down.load(Ordering::SeqCst) {
+                return Err(OTelSdkError::InternalFailure("connection refused".to_owned()));
+            }
+            self.received.fetch_add(batch.len(), Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    fn collector() -> &'static Collector {
+        Box::leak(Box::default())
+    }
+
+    fn span() -> Vec<SpanData> {
+        use opentelemetry::trace::{SpanContext, SpanId, SpanKind, Status};
+        vec![SpanData {
+            span_context: SpanContext::empty_context(),
+            parent_span_id: SpanId::INVALID,
+            parent_span_is_remote: false,
+            span_kind: SpanKind::Internal,
+            name: "work".into(),
+            start_time: std::time::SystemTime::now(),
+            end_time: std::time::SystemTime::now(),
+            attributes: Vec::new(),
+            dropped_attributes_count: 0,
+            events: Default::default(),
+            links: Default::default(),
+            status: Status::Unset,
+            instrumentation_scope: Default::default(),
+        }]
+    }
+
+    #[tokio::test]
+    async fn switches_after_consecutive_failures_and_back_once_the_primary_answers() {
+        let (primary, secondary) = (collector(), collector());
+        let exporter = FailoverExporter::new(
+            "traces",
+            vec![("http://a:4317".to_owned(), primary), ("http://b:4317".to_owned(), secondary)],
+            FailoverPolicy::new()
+                .with_failures_before_switch(2)
+                .with_probe_interval(Duration::from_millis(50)),
+        );
+
+        primary.down.store(true, Ordering::SeqCst);
+        assert!(exporter.export(span()).await.is_err());
+        assert_eq!(exporter.active_endpoint(), "http://a:4317");
+        assert!(exporter.export(span()).await.is_err());
+        assert_eq!(exporter.active_endpoint(), "http://b:4317");
+        assert!(exporter.export(span()).await.is_ok());
+        assert_eq!(secondary.received.load(Ordering::SeqCst), 1);
+
+        primary.down.store(false, Ordering::SeqCst);
+        assert!(exporter.export(span()).await.is_ok());
+        assert_eq!(exporter.active_endpoint(), "http://b:4317", "probed before the interval");
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(exporter.export(span()).await.is_ok());
+        assert_eq!(exporter.active_endpoint(), "http://a:4317");
+        assert_eq!(primary.received.load(Ordering::SeqCst), 1);
+        assert_eq!(secondary.received.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn retries_count_as_one_failure() {
+        let (primary, secondary) = (collector(), collector());
+        let exporter = FailoverExporter::new(
+            "traces",
+            vec![("http://a:4317".to_owned(), primary), ("http://b:4317".to_owned(), secondary)],
+            FailoverPolicy::new().with_failures_before_switch(2).with_retries(3),
+        );
+        primary.down.store(true, Ordering::SeqCst);
+        assert!(exporter.export(span()).await.is_err());
+        assert_eq!(exporter.active_endpoint(), "http://a:4317");
+    }
+}
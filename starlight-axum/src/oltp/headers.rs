@@ -0,0 +1,302 @@
+use crate::config::{ConfigError, TelemetryConfig};
+use crate::oltp::Signal;
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use opentelemetry_otlp::tonic_types::metadata::MetadataMap;
+use std::fmt::Write;
+
+/// The headers sent to the collector with every export, such as credentials.
+///
+/// Headers apply to all three signals; those given [per signal](Self::with_signal) are
+/// added for that signal only, replacing common ones of the same name. Names and values
+/// are checked by [`validate`](Self::validate), which
+/// [`config_oltp_with_config`](crate::oltp::config_oltp_with_config) runs, rather than
+/// by the builder methods.
+///
+/// ```
+/// use starlight_axum::oltp::{HeadersConfig, Signal};
+///
+/// let headers = HeadersConfig::new()
+///     .bearer_token("s3cr3t")
+///     .with_signal(Signal::Metrics, HeadersConfig::new().api_key("DD-API-KEY", "k=1"));
+/// assert_eq!(headers.to_env(None).unwrap(), "authorization=Bearer%20s3cr3t");
+/// assert_eq!(
+///     headers.to_env(Some(Signal::Metrics)).unwrap(),
+///     "authorization=Bearer%20s3cr3t,dd-api-key=k%3D1"
+/// );
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeadersConfig {
+    common: Vec<(String, String)>,
+    signals: Vec<(Signal, Vec<(String, String)>)>,
+}
+
+impl HeadersConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `Authorization: Bearer <token>`.
+    pub fn bearer_token(self, token: impl AsRef<str>) -> Self {
+        self.header("authorization", format!("Bearer {}", token.as_ref()))
+    }
+
+    /// A vendor key header, such as `DD-API-KEY` or `x-honeycomb-team`.
+    pub fn api_key(self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.header(name, value)
+    }
+
+    /// Sets `name`, replacing a previous value. Names are case-insensitive.
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        insert(&mut self.common, name.into(), value.into());
+        self
+    }
+
+    /// Adds the headers of `headers` to those sent with `signal`.
+    pub fn with_signal(mut self, signal: Signal, headers: HeadersConfig) -> Self {
+        let index = match self.signals.iter().position(|(existing, _)| *existing == signal) {
+            Some(index) => index,
+            None => {
+                self.signals.push((signal, Vec::new()));
+                self.signals.len() - 1
+            }
+        };
+        for (name, value) in headers.common {
+            insert(&mut self.signals[index].1, name, value);
+        }
+        self
+    }
+
+    /// Parses the `key=value,key2=value2` form of `OTEL_EXPORTER_OTLP_HEADERS`, with
+    /// percent-encoded values, reporting problems as errors of the setting `field`.
+    pub fn parse(field: &'static str, headers: &str) -> Result<Self, Vec<ConfigError>> {
+        let mut parsed = HeadersConfig::new();
+        let mut errors = Vec::new();
+        for (index, pair) in headers
+            .split(',')
+            .enumerate()
+            .filter(|(_, pair)| !pair.trim().is_empty())
+        {
+            let Some((name, value)) = pair.split_once('=').filter(|(name, _)| !name.trim().is_empty()) else {
+                errors.push(ConfigError::new(
+                    field,
+                    format!("entry {} is not in key=value form", index + 1),
+                ));
+                continue;
+            };
+            match percent_decode(value.trim()) {
+                Some(value) => parsed = parsed.header(name.trim(), value),
+                None => errors.push(ConfigError::new(
+                    field,
+                    format!("the value of {} is not percent-encoded UTF-8", name.trim()),
+                )),
+            }
+        }
+        if errors.is_empty() { Ok(parsed) } else { Err(errors) }
+    }
+
+    /// The headers of `otlp_headers` and the per-signal `otlp_*_headers` of `config`.
+    pub fn from_telemetry(config: &TelemetryConfig) -> Result<Self, Vec<ConfigError>> {
+        let mut errors = Vec::new();
+        let mut parse = |field, headers: &Option<String>| match headers.as_deref().map(|h| Self::parse(field, h)) {
+            Some(Ok(headers)) => headers,
+            Some(Err(err)) => {
+                errors.extend(err);
+                HeadersConfig::new()
+            }
+            None => HeadersConfig::new(),
+        };
+        let mut headers = parse("otlp.headers", &config.otlp_headers);
+        let traces = parse("otlp.traces_headers", &config.otlp_traces_headers);
+        let metrics = parse("otlp.metrics_headers", &config.otlp_metrics_headers);
+        let logs = parse("otlp.logs_headers", &config.otlp_logs_headers);
+        for (signal, signal_headers) in [
+            (Signal::Traces, traces),
+            (Signal::Metrics, metrics),
+            (Signal::Logs, logs),
+        ] {
+            if signal_headers != HeadersConfig::new() {
+                headers = headers.with_signal(signal, signal_headers);
+            }
+        }
+        if let Err(invalid) = headers.validate() {
+            errors.extend(invalid);
+        }
+        if errors.is_empty() { Ok(headers) } else { Err(errors) }
+    }
+
+    /// Checks that every name and value can be sent as gRPC metadata. The errors name
+    /// the headers, never their values.
+    pub fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = Vec::new();
+        let all = self.common.iter().map(|header| ("otlp.headers", header)).chain(
+            self.signals
+                .iter()
+                .flat_map(|(signal, headers)| headers.iter().map(move |header| (field(*signal), header))),
+        );
+        for (field, (name, value)) in all {
+            if HeaderName::from_bytes(name.as_bytes()).is_err() {
+                errors.push(ConfigError::new(
+                    field,
+                    format!("{:?} is not a valid header name", name),
+                ));
+            } else if name.ends_with("-bin") {
+                errors.push(ConfigError::new(field, format!("{} is a binary gRPC header", name)));
+            } else if HeaderValue::from_str(value).is_err() {
+                errors.push(ConfigError::new(
+                    field,
+                    format!("the value of {} is not a valid header value", name),
+                ));
+            }
+        }
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    /// The headers sent with `signal`.
+    pub fn for_signal(&self, signal: Signal) -> Vec<(String, String)> {
+        let mut headers = self.common.clone();
+        let overrides = self.signals.iter().filter(|(existing, _)| *existing == signal);
+        for (name, value) in overrides.flat_map(|(_, headers)| headers) {
+            insert(&mut headers, name.clone(), value.clone());
+        }
+        headers
+    }
+
+    /// The common headers, or those of `signal` when it has its own, in the form of
+    /// `OTEL_EXPORTER_OTLP_HEADERS` or `OTEL_EXPORTER_OTLP_<SIGNAL>_HEADERS`, which
+    /// replaces the common variable. `None` when there is nothing to set.
+    pub fn to_env(&self, signal: Option<Signal>) -> Option<String> {
+        let headers = match signal {
+            None => self.common.clone(),
+            Some(signal) if self.signals.iter().any(|(existing, _)| *existing == signal) => self.for_signal(signal),
+            Some(_) => Vec::new(),
+        };
+        if headers.is_empty() {
+            return None;
+        }
+        let pairs: Vec<String> = headers
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, percent_encode(value)))
+            .collect();
+        Some(pairs.join(","))
+    }
+
+    /// The headers of `signal` as gRPC metadata, skipping invalid ones.
+    pub fn metadata(&self, signal: Signal) -> MetadataMap {
+        let headers: HeaderMap = self
+            .for_signal(signal)
+            .into_iter()
+            .filter_map(|(name, value)| {
+                Some((
+                    HeaderName::from_bytes(name.as_bytes()).ok()?,
+                    HeaderValue::from_str(&value).ok()?,
+                ))
+            })
+            .collect();
+        MetadataMap::from_headers(headers)
+    }
+}
+
+fn field(signal: Signal) -> &'static str {
+    match signal {
+        Signal::Traces => "otlp.traces_headers",
+        Signal::Metrics => "otlp.metrics_headers",
+        Signal::Logs => "otlp.logs_headers",
+    }
+}
+
+fn insert(headers: &mut Vec<(String, String)>, name: String, value: String) {
+    let name = name.trim().to_ascii_lowercase();
+    match headers.iter_mut().find(|(existing, _)| *existing == name) {
+        Some((_, existing)) => *existing = value,
+        None => headers.push((name, value)),
+    }
+}
+
+/// Percent-encodes everything but the unreserved characters of RFC 3986, so values
+/// survive the `,` and `=` separators.
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            let _ = write!(encoded, "%{:02X}", byte);
+        }
+    }
+    encoded
+}
+
+fn percent_decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(index + 1..index + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            index += 3;
+        } else {
+            decoded.push(bytes[index]);
+            index += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_the_env_form() {
+        let headers = HeadersConfig::new()
+            .bearer_token("a+b/c==")
+            .header("X-Scope", "team a, team b")
+            .with_signal(Signal::Logs, HeadersConfig::new().header("x-scope", "logs"));
+        let env = headers.to_env(None).unwrap();
+        assert_eq!(
+            env,
+            "authorization=Bearer%20a%2Bb%2Fc%3D%3D,x-scope=team%20a%2C%20team%20b"
+        );
+        assert_eq!(
+            HeadersConfig::parse("otlp.headers", &env)
+                .unwrap()
+                .for_signal(Signal::Traces),
+            headers.for_signal(Signal::Traces)
+        );
+        assert_eq!(headers.to_env(Some(Signal::Traces)), None);
+        assert_eq!(
+            headers.to_env(Some(Signal::Logs)).unwrap(),
+            "authorization=Bearer%20a%2Bb%2Fc%3D%3D,x-scope=logs"
+        );
+    }
+
+    #[test]
+    fn reports_invalid_names_and_values_without_the_values() {
+        let headers = HeadersConfig::new()
+            .header("x tenant", "acme")
+            .api_key("dd-api-key", "line\nbreak")
+            .with_signal(Signal::Metrics, HeadersConfig::new().header("trace-bin", "AAA"));
+        let errors: Vec<String> = headers
+            .validate()
+            .unwrap_err()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                "otlp.headers: \"x tenant\" is not a valid header name",
+                "otlp.headers: the value of dd-api-key is not a valid header value",
+                "otlp.metrics_headers: trace-bin is a binary gRPC header",
+            ]
+        );
+        assert_eq!(
+            HeadersConfig::parse("otlp.headers", "a=%zz").unwrap_err(),
+            [ConfigError::new(
+                "otlp.headers",
+                "the value of a is not percent-encoded UTF-8"
+            )]
+        );
+    }
+}
@@ -0,0 +1,273 @@
+//! Offset and cursor pagination for list endpoints.
+//!
+//! ```ignore
+//! async fn list(page: Pagination<OrderCursor>) -> Paginated<Order> {
+//!     let orders = repo.after(page.cursor.as_ref(), page.per_page + 1).await;
+//!     let next = (orders.len() > page.per_page as usize).then(|| OrderCursor::after(&orders));
+//!     Paginated::cursor(orders, next.as_ref(), &page)
+//! }
+//! ```
+
+use crate::rejection::{Rejection, reject};
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::http::{HeaderValue, StatusCode, Uri, header};
+use axum::response::{IntoResponse, Response};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+/// What happens to a `per_page` above the maximum.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverMax {
+    /// Serve the maximum instead.
+    #[default]
+    Clamp,
+    /// Answer 400.
+    Reject,
+}
+
+/// Limits of [`Pagination`], installed for a route with
+/// `.route_layer(Extension(PaginationConfig::new().with_max_per_page(50)))`; the
+/// defaults apply without one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaginationConfig {
+    default_per_page: u32,
+    max_per_page: u32,
+    over_max: OverMax,
+}
+
+impl Default for PaginationConfig {
+    fn default() -> Self {
+        PaginationConfig {
+            default_per_page: 20,
+            max_per_page: 100,
+            over_max: OverMax::default(),
+        }
+    }
+}
+
+impl PaginationConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 20 by default.
+    pub fn with_default_per_page(mut self, per_page: u32) -> Self {
+        self.default_per_page = per_page.max(1);
+        self
+    }
+
+    /// 100 by default.
+    pub fn with_max_per_page(mut self, per_page: u32) -> Self {
+        self.max_per_page = per_page.max(1);
+        self
+    }
+
+    pub fn with_over_max(mut self, over_max: OverMax) -> Self {
+        self.over_max = over_max;
+        self
+    }
+}
+
+/// An opaque cursor for `cursor`: the value as base64url encoded JSON.
+pub fn encode_cursor<C: Serialize>(cursor: &C) -> String {
+    let json = serde_json::to_vec(cursor).expect("cursors serialize to JSON");
+    URL_SAFE_NO_PAD.encode(json)
+}
+
+pub fn decode_cursor<C: DeserializeOwned>(cursor: &str) -> Option<C> {
+    let json = URL_SAFE_NO_PAD.decode(cursor.trim_end_matches('=')).ok()?;
+    serde_json::from_slice(&json).ok()
+}
+
+/// The page a list endpoint is asked for, from `?page=&per_page=` or
+/// `?cursor=&per_page=`.
+///
+/// `page` starts at 1. `cursor` is what [`Paginated::cursor`] handed out for the next
+/// page, decoded into `C`; it cannot be combined with `page`. Malformed values, and a
+/// `per_page` above the maximum unless the [`PaginationConfig`] clamps it, are
+/// rejected with 400 problem details whose reason is `pagination.page`,
+/// `pagination.per_page` or `pagination.cursor`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pagination<C = ()> {
+    pub page: u32,
+    pub per_page: u32,
+    pub cursor: Option<C>,
+    uri: Uri,
+}
+
+impl<C> Pagination<C> {
+    /// Items to skip in offset mode.
+    pub fn offset(&self) -> u64 {
+        (self.page as u64 - 1) * self.per_page as u64
+    }
+
+    pub fn limit(&self) -> u32 {
+        self.per_page
+    }
+
+    /// The request target with the pagination parameters replaced by `params`.
+    fn link(&self, params: &[(&str, String)]) -> String {
+        let mut query: Vec<String> = self
+            .uri
+            .query()
+            .unwrap_or_default()
+            .split('&')
+            .filter(|pair| {
+                let key = pair.split('=').next().unwrap_or_default();
+                !pair.is_empty() && !matches!(key, "page" | "per_page" | "cursor")
+            })
+            .map(str::to_owned)
+            .collect();
+        query.extend(params.iter().map(|(key, value)| format!("{}={}", key, value)));
+        format!("{}?{}", self.uri.path(), query.join("&"))
+    }
+}
+
+fn number(value: Option<&str>, name: &str) -> Result<Option<u32>, String> {
+    match value {
+        None => Ok(None),
+        Some(value) => match value.parse::<u32>() {
+            Ok(0) | Err(_) => Err(format!("{} must be a positive integer, got {:?}", name, value)),
+            Ok(number) => Ok(Some(number)),
+        },
+    }
+}
+
+impl<S, C> FromRequestParts<S> for Pagination<C>
+where
+    S: Send + Sync,
+    C: DeserializeOwned + Send,
+{
+    type Rejection = Rejection;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let config = parts.extensions.get::<PaginationConfig>().copied().unwrap_or_default();
+        let bad_request = |reason, detail| reject(&parts.extensions, StatusCode::BAD_REQUEST, reason, detail);
+        let axum::extract::Query(params) = axum::extract::Query::<Vec<(String, String)>>::try_from_uri(&parts.uri)
+            .map_err(|rejection| bad_request("query.invalid", rejection.body_text()))?;
+        let param = |name: &str| params.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str());
+
+        let page = number(param("page"), "page").map_err(|detail| bad_request("pagination.page", detail))?;
+        let per_page = number(param("per_page"), "per_page")
+            .map_err(|detail| bad_request("pagination.per_page", detail))?
+            .unwrap_or(config.default_per_page);
+        let per_page = match config.over_max {
+            _ if per_page <= config.max_per_page => per_page,
+            OverMax::Clamp => config.max_per_page,
+            OverMax::Reject => {
+                let detail = format!("per_page must be at most {}, got {}", config.max_per_page, per_page);
+                return Err(bad_request("pagination.per_page", detail));
+            }
+        };
+        let cursor = match param("cursor").filter(|cursor| !cursor.is_empty()) {
+            None => None,
+            Some(_) if page.is_some() => {
+                return Err(bad_request("pagination.cursor", "cursor and page cannot be combined".to_owned()));
+            }
+            Some(cursor) => Some(
+                decode_cursor(cursor)
+                    .ok_or_else(|| bad_request("pagination.cursor", "cursor is malformed".to_owned()))?,
+            ),
+        };
+
+        Ok(Pagination {
+            page: page.unwrap_or(1),
+            per_page,
+            cursor,
+            uri: parts.uri.clone(),
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct Body<T> {
+    items: Vec<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_cursor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total: Option<u64>,
+}
+
+/// A page of a list, answered as `{"items": [..], "total": n}` in offset mode or
+/// `{"items": [..], "next_cursor": ".."}` in cursor mode, with a `Link` header
+/// (RFC 8288) pointing at the neighbouring pages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Paginated<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+    pub total: Option<u64>,
+    links: Vec<(String, &'static str)>,
+}
+
+impl<T> Paginated<T> {
+    /// The page `pagination` asked for out of `total` items, linking to the first,
+    /// previous, next and last pages.
+    pub fn offset<C>(items: Vec<T>, total: u64, pagination: &Pagination<C>) -> Self {
+        let per_page = pagination.per_page as u64;
+        let last = total.div_ceil(per_page).max(1);
+        let page = pagination.page as u64;
+        let link = |page: u64| {
+            pagination.link(&[("page", page.to_string()), ("per_page", pagination.per_page.to_string())])
+        };
+        let mut links = vec![(link(1), "first")];
+        if page > 1 {
+            links.push((link((page - 1).min(last)), "prev"));
+        }
+        if page < last {
+            links.push((link(page + 1), "next"));
+        }
+        links.push((link(last), "last"));
+        Paginated {
+            items,
+            next_cursor: None,
+            total: Some(total),
+            links,
+        }
+    }
+
+    /// A page in cursor mode, `next` being where the following page starts, `None` on
+    /// the last page.
+    pub fn cursor<C: Serialize>(items: Vec<T>, next: Option<&C>, pagination: &Pagination<C>) -> Self {
+        let per_page = ("per_page", pagination.per_page.to_string());
+        let next_cursor = next.map(encode_cursor);
+        let mut links = vec![(pagination.link(std::slice::from_ref(&per_page)), "first")];
+        if let Some(cursor) = &next_cursor {
+            links.push((pagination.link(&[("cursor", cursor.clone()), per_page]), "next"));
+        }
+        Paginated {
+            items,
+            next_cursor,
+            total: None,
+            links,
+        }
+    }
+
+    /// The `Link` header value.
+    pub fn link_header(&self) -> String {
+        let links: Vec<String> = self
+            .links
+            .iter()
+            .map(|(target, rel)| format!("<{}>; rel=\"{}\"", target, rel))
+            .collect();
+        links.join(", ")
+    }
+}
+
+impl<T: Serialize> IntoResponse for Paginated<T> {
+    fn into_response(self) -> Response {
+        let link = HeaderValue::from_str(&self.link_header()).ok();
+        let body = Body {
+            items: self.items,
+            next_cursor: self.next_cursor,
+            total: self.total,
+        };
+        let mut response = axum::Json(body).into_response();
+        if let Some(link) = link {
+            response.headers_mut().insert(header::LINK, link);
+        }
+        response
+    }
+}
@@ -0,0 +1,296 @@
+use axum::extract::{MatchedPath, Request};
+use axum::http::HeaderName;
+use axum::response::Response;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::{Pin, pin};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::{Instant, MissedTickBehavior};
+use tower::{Layer, Service};
+use tracing::Span;
+use tracing::span::Id;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::registry::LookupSpan;
+
+/// Requests carrying this header, with any value, are profiled.
+pub const X_PROFILE: &str = "x-profile";
+
+/// The time spent in the handler outside of any of its spans.
+const HANDLER: &str = "(handler)";
+
+/// Requests being profiled; nothing is tracked while there are none.
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+/// The profile of each request being profiled, by the ID of its request span.
+static PROFILES: LazyLock<Mutex<HashMap<Id, Arc<Profile>>>> = LazyLock::new(Default::default);
+
+/// The spans under a request span which are still open, most recently entered last.
+#[derive(Debug, Default)]
+struct Profile {
+    open: Mutex<Vec<(Id, &'static str)>>,
+}
+
+impl Profile {
+    fn current(&self) -> &'static str {
+        let open = self.open.lock().unwrap_or_else(|e| e.into_inner());
+        open.last().map_or(HANDLER, |(_, name)| name)
+    }
+}
+
+/// Tells [`ProfilerLayer`] which span of the handler is active. Add it to the
+/// subscriber; [`LoggerConfig::build_layers`](crate::logger::LoggerConfig::build_layers)
+/// includes it. It does nothing while no request is profiled.
+#[derive(Debug, Default)]
+pub struct ProfiledSpans;
+
+impl ProfiledSpans {
+    pub fn new() -> Self {
+        ProfiledSpans
+    }
+}
+
+impl<S> tracing_subscriber::Layer<S> for ProfiledSpans
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_enter(&self, id: &Id, ctx: tracing_subscriber::layer::Context<'_, S>) {
+        if ACTIVE.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let profiles = PROFILES.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(profile) = span.scope().skip(1).find_map(|ancestor| profiles.get(&ancestor.id())) else {
+            return;
+        };
+        // Futures enter their span on every poll: the one polled last is the one running.
+        let mut open = profile.open.lock().unwrap_or_else(|e| e.into_inner());
+        open.retain(|(open, _)| open != id);
+        open.push((id.clone(), span.name()));
+    }
+
+    fn on_close(&self, id: Id, _ctx: tracing_subscriber::layer::Context<'_, S>) {
+        if ACTIVE.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let profiles = PROFILES.lock().unwrap_or_else(|e| e.into_inner());
+        for profile in profiles.values() {
+            let mut open = profile.open.lock().unwrap_or_else(|e| e.into_inner());
+            open.retain(|(open, _)| *open != id);
+        }
+    }
+}
+
+/// Registers a profile for the request span, unregistered when dropped.
+struct Profiling {
+    span: Id,
+    profile: Arc<Profile>,
+}
+
+impl Profiling {
+    /// `None` when the request has no span or `max_concurrent` requests are profiled.
+    fn start(span: &Span, max_concurrent: usize) -> Option<Self> {
+        let id = span.id()?;
+        if ACTIVE.fetch_add(1, Ordering::SeqCst) >= max_concurrent {
+            ACTIVE.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+        let profile = Arc::new(Profile::default());
+        let mut profiles = PROFILES.lock().unwrap_or_else(|e| e.into_inner());
+        profiles.insert(id.clone(), profile.clone());
+        Some(Profiling { span: id, profile })
+    }
+}
+
+impl Drop for Profiling {
+    fn drop(&mut self) {
+        let mut profiles = PROFILES.lock().unwrap_or_else(|e| e.into_inner());
+        profiles.remove(&self.span);
+        ACTIVE.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// The time attributed to each span, in the order they were first sampled.
+#[derive(Debug, Default)]
+struct Samples {
+    count: u64,
+    spans: Vec<(&'static str, Duration)>,
+}
+
+impl Samples {
+    fn add(&mut self, span: &'static str, elapsed: Duration) {
+        self.count += 1;
+        match self.spans.iter_mut().find(|(name, _)| *name == span) {
+            Some((_, total)) => *total += elapsed,
+            None => self.spans.push((span, elapsed)),
+        }
+    }
+
+    /// `name=ms` pairs, slowest first, e.g. `load_user=62,render=31,(handler)=2`.
+    fn summary(mut self) -> String {
+        self.spans.sort_by_key(|(_, total)| std::cmp::Reverse(*total));
+        let pairs: Vec<String> = self
+            .spans
+            .iter()
+            .map(|(name, total)| format!("{}={}", name, total.as_millis()))
+            .collect();
+        pairs.join(",")
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ProfilerConfig {
+    header: Option<HeaderName>,
+    latency_threshold: Option<Duration>,
+    interval: Duration,
+    max_concurrent: usize,
+}
+
+/// Samples where slow requests spend their time, by the spans of their handler.
+///
+/// A request is profiled when it carries the [`X_PROFILE`] header, or once it has run
+/// for longer than the latency threshold, from then on. Every interval (10 ms by
+/// default) the time since the last sample is attributed to the span of the handler
+/// that was entered last and is still open, such as an `#[instrument]`ed function
+/// being awaited, or to `(handler)` when there is none. The totals are set on the
+/// request span as the `profile.summary` attribute, `name=ms` pairs slowest first
+/// (`load_user=62,render=31`), with `profile.trigger` (`header` or `latency`) and
+/// `profile.samples`.
+///
+/// [`ProfiledSpans`] must be a layer of the subscriber. Requests which are not profiled
+/// pay only for the latency timer when a threshold is set, and at most 4 requests are
+/// profiled at once by default.
+#[derive(Debug, Clone)]
+pub struct ProfilerLayer {
+    config: Arc<ProfilerConfig>,
+}
+
+impl Default for ProfilerLayer {
+    fn default() -> Self {
+        ProfilerLayer {
+            config: Arc::new(ProfilerConfig {
+                header: Some(HeaderName::from_static(X_PROFILE)),
+                latency_threshold: None,
+                interval: Duration::from_millis(10),
+                max_concurrent: 4,
+            }),
+        }
+    }
+}
+
+impl ProfilerLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The header asking for a profile, `None` to profile slow requests only.
+    pub fn with_header(mut self, header: Option<HeaderName>) -> Self {
+        Arc::make_mut(&mut self.config).header = header;
+        self
+    }
+
+    /// Profiles requests still running after `threshold`.
+    pub fn with_latency_threshold(mut self, threshold: Duration) -> Self {
+        Arc::make_mut(&mut self.config).latency_threshold = Some(threshold);
+        self
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        Arc::make_mut(&mut self.config).interval = interval;
+        self
+    }
+
+    pub fn with_max_concurrent(mut self, max_concurrent: usize) -> Self {
+        Arc::make_mut(&mut self.config).max_concurrent = max_concurrent;
+        self
+    }
+}
+
+impl<S> Layer<S> for ProfilerLayer {
+    type Service = ProfilerService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ProfilerService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ProfilerService<S> {
+    inner: S,
+    layer: ProfilerLayer,
+}
+
+impl<S, B> Service<Request<B>> for ProfilerService<S>
+where
+    S: Service<Request<B>, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        let config = self.layer.config.clone();
+        let requested = config
+            .header
+            .as_ref()
+            .is_some_and(|header| req.headers().contains_key(header));
+        if !requested && config.latency_threshold.is_none() {
+            return Box::pin(self.inner.call(req));
+        }
+        let route = req
+            .extensions()
+            .get::<MatchedPath>()
+            .map_or_else(|| req.uri().path().to_owned(), |path| path.as_str().to_owned());
+        let span = Span::current();
+        let future = self.inner.call(req);
+        Box::pin(async move {
+            let mut future = pin!(future);
+            let trigger = match config.latency_threshold {
+                Some(threshold) if !requested => tokio::select! {
+                    response = &mut future => return response,
+                    _ = tokio::time::sleep(threshold) => "latency",
+                },
+                _ => "header",
+            };
+            let Some(profiling) = Profiling::start(&span, config.max_concurrent) else {
+                return future.await;
+            };
+
+            let mut samples = Samples::default();
+            let mut ticks = tokio::time::interval_at(Instant::now() + config.interval, config.interval);
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            let mut last = Instant::now();
+            let response = loop {
+                tokio::select! {
+                    response = &mut future => break response,
+                    _ = ticks.tick() => {
+                        let now = Instant::now();
+                        samples.add(profiling.profile.current(), now - last);
+                        last = now;
+                    }
+                }
+            };
+            drop(profiling);
+
+            let count = samples.count;
+            let summary = samples.summary();
+            debug!("profiled {} ({}): {}", route, trigger, summary);
+            span.set_attribute("profile.summary", summary);
+            span.set_attribute("profile.trigger", trigger);
+            span.set_attribute("profile.samples", count as i64);
+            response
+        })
+    }
+}
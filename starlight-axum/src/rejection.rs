@@ -0,0 +1,219 @@
+//! Drop-in replacements for axum's `Json`, `Query` and `Path` extractors whose
+//! rejections are problem details with a machine-readable `reason` instead of plain
+//! text. Handlers switch by importing `starlight_axum::{Json, Path, Query}`.
+//!
+//! Every rejection is logged at debug with the route and counted on
+//! `http.server.request.rejections`, labelled with the `reason`.
+
+use crate::meter::GLOBAL_METER;
+use axum::extract::rejection::{JsonRejection, PathRejection, QueryRejection};
+use axum::extract::{FromRequest, FromRequestParts, MatchedPath, Request};
+use axum::http::request::Parts;
+use axum::http::{Extensions, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Counter, Meter};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::sync::OnceLock;
+
+pub const REQUEST_REJECTIONS: &str = "http.server.request.rejections";
+
+/// Where rejections are counted, installed for a router with
+/// `.layer(Extension(RejectionConfig::with_meter(&meter)))`; the global meter is used
+/// without one.
+#[derive(Debug, Clone)]
+pub struct RejectionConfig {
+    rejections: Counter<u64>,
+}
+
+impl Default for RejectionConfig {
+    fn default() -> Self {
+        Self::with_meter(&GLOBAL_METER)
+    }
+}
+
+impl RejectionConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_meter(meter: &Meter) -> Self {
+        RejectionConfig {
+            rejections: meter
+                .u64_counter(REQUEST_REJECTIONS)
+                .with_description("Requests rejected by an extractor")
+                .build(),
+        }
+    }
+}
+
+fn default_config() -> RejectionConfig {
+    static DEFAULT: OnceLock<RejectionConfig> = OnceLock::new();
+    DEFAULT.get_or_init(RejectionConfig::new).clone()
+}
+
+/// A request an extractor could not make sense of, answered with a problem details body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejection {
+    pub status: StatusCode,
+    /// `json.syntax`, `json.data`, `json.content_type`, `json.body`, `query.missing`,
+    /// `query.invalid`, `path.type`, `path.missing`, `path.invalid`, or those of other
+    /// extractors such as `pagination.per_page`.
+    pub reason: &'static str,
+    pub detail: String,
+}
+
+impl std::fmt::Display for Rejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.detail, self.reason)
+    }
+}
+
+impl std::error::Error for Rejection {}
+
+impl IntoResponse for Rejection {
+    fn into_response(self) -> Response {
+        let body = serde_json::json!({
+            "type": "about:blank",
+            "title": self.status.canonical_reason().unwrap_or("Bad Request"),
+            "status": self.status.as_u16(),
+            "detail": self.detail,
+            "reason": self.reason,
+        });
+        (self.status, [(header::CONTENT_TYPE, "application/problem+json")], body.to_string()).into_response()
+    }
+}
+
+/// What a rejection is logged and counted with, taken before the extractor consumes
+/// the request.
+struct Context {
+    route: Option<MatchedPath>,
+    config: Option<RejectionConfig>,
+}
+
+impl Context {
+    fn of(extensions: &Extensions) -> Self {
+        Context {
+            route: extensions.get::<MatchedPath>().cloned(),
+            config: extensions.get::<RejectionConfig>().cloned(),
+        }
+    }
+
+    fn reject(self, status: StatusCode, reason: &'static str, detail: String) -> Rejection {
+        let route = self.route.as_ref().map_or("", MatchedPath::as_str);
+        debug!(route, reason, status = status.as_u16(), "rejected request: {}", detail);
+        let config = self.config.unwrap_or_else(default_config);
+        config.rejections.add(1, &[KeyValue::new("reason", reason)]);
+        Rejection { status, reason, detail }
+    }
+}
+
+/// A rejection by another extractor of the crate, logged and counted like these.
+pub(crate) fn reject(extensions: &Extensions, status: StatusCode, reason: &'static str, detail: String) -> Rejection {
+    Context::of(extensions).reject(status, reason, detail)
+}
+
+fn json_reason(rejection: &JsonRejection) -> &'static str {
+    match rejection {
+        JsonRejection::JsonSyntaxError(_) => "json.syntax",
+        JsonRejection::JsonDataError(_) => "json.data",
+        JsonRejection::MissingJsonContentType(_) => "json.content_type",
+        _ => "json.body",
+    }
+}
+
+fn query_reason(rejection: &QueryRejection) -> &'static str {
+    // serde_urlencoded only says which field is missing in its message.
+    if rejection.body_text().contains("missing field") {
+        "query.missing"
+    } else {
+        "query.invalid"
+    }
+}
+
+fn path_reason(rejection: &PathRejection) -> &'static str {
+    use axum::extract::path::ErrorKind;
+    match rejection {
+        PathRejection::FailedToDeserializePathParams(err) => match err.kind() {
+            ErrorKind::ParseError { .. } | ErrorKind::ParseErrorAtKey { .. } | ErrorKind::ParseErrorAtIndex { .. } => {
+                "path.type"
+            }
+            _ => "path.invalid",
+        },
+        PathRejection::MissingPathParams(_) => "path.missing",
+        _ => "path.invalid",
+    }
+}
+
+/// [`axum::Json`] with problem details rejections. Also a response, like axum's.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Json<T>(pub T);
+
+impl<T, S> FromRequest<S> for Json<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Rejection;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let context = Context::of(req.extensions());
+        match axum::Json::<T>::from_request(req, state).await {
+            Ok(axum::Json(value)) => Ok(Json(value)),
+            Err(rejection) => Err(context.reject(rejection.status(), json_reason(&rejection), rejection.body_text())),
+        }
+    }
+}
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+/// [`axum::extract::Query`] with problem details rejections.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Query<T>(pub T);
+
+impl<T, S> FromRequestParts<S> for Query<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Rejection;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match axum::extract::Query::<T>::from_request_parts(parts, state).await {
+            Ok(axum::extract::Query(value)) => Ok(Query(value)),
+            Err(rejection) => Err(Context::of(&parts.extensions).reject(
+                rejection.status(),
+                query_reason(&rejection),
+                rejection.body_text(),
+            )),
+        }
+    }
+}
+
+/// [`axum::extract::Path`] with problem details rejections.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Path<T>(pub T);
+
+impl<T, S> FromRequestParts<S> for Path<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = Rejection;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match axum::extract::Path::<T>::from_request_parts(parts, state).await {
+            Ok(axum::extract::Path(value)) => Ok(Path(value)),
+            Err(rejection) => Err(Context::of(&parts.extensions).reject(
+                rejection.status(),
+                path_reason(&rejection),
+                rejection.body_text(),
+            )),
+        }
+    }
+}
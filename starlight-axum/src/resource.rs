@@ -1,4 +1,5 @@
-use crate::{get_env_or_default, get_env_or_panic};
+use crate::config::TelemetryConfig;
+use crate::env::{self, EnvErrors};
 use opentelemetry::KeyValue;
 use opentelemetry_sdk::Resource;
 use opentelemetry_semantic_conventions::attribute::{
@@ -6,21 +7,66 @@ use opentelemetry_semantic_conventions::attribute::{
 };
 use std::sync::OnceLock;
 
+static RESOURCE: OnceLock<Resource> = OnceLock::new();
+
 pub fn get_resource() -> Resource {
-    static RESOURCE: OnceLock<Resource> = OnceLock::new();
     RESOURCE
         .get_or_init(|| {
-            Resource::builder()
-                .with_service_name(get_env_or_panic("CARGO_PKG_NAME"))
-                .with_attributes([
-                    KeyValue::new(SERVICE_NAME, get_env_or_panic("CARGO_PKG_NAME")),
-                    KeyValue::new(SERVICE_VERSION, get_env_or_panic("CARGO_PKG_VERSION")),
-                    KeyValue::new(
-                        DEPLOYMENT_ENVIRONMENT_NAME,
-                        get_env_or_default("CARGO_ENV", "development".to_owned()),
-                    ),
-                ])
-                .build()
+            let (name, version, environment) = service_from_env().unwrap_or_else(|errors| panic!("{}", errors));
+            build(name, version, environment)
         })
         .clone()
 }
+
+/// Describes the service with `config` rather than the environment, unless the
+/// resource was already built.
+pub(crate) fn init_resource(config: &TelemetryConfig) {
+    RESOURCE.get_or_init(|| {
+        let mut errors = EnvErrors::new();
+        let mut setting = |value: &Option<String>, variable: &str| {
+            value.clone().or_else(|| errors.check(env::required(variable)))
+        };
+        let name = setting(&config.service_name, "CARGO_PKG_NAME");
+        let version = setting(&config.service_version, "CARGO_PKG_VERSION");
+        let environment = match &config.environment {
+            Some(environment) => Some(environment.clone()),
+            None => errors
+                .check(env::optional("CARGO_ENV"))
+                .map(|environment| environment.unwrap_or_else(default_environment)),
+        };
+        match (name, version, environment) {
+            (Some(name), Some(version), Some(environment)) => build(name, version, environment),
+            _ => panic!("{}", errors),
+        }
+    });
+}
+
+/// The service name, version and environment from `CARGO_PKG_NAME`,
+/// `CARGO_PKG_VERSION` and `CARGO_ENV`, reporting every missing variable.
+pub(crate) fn service_from_env() -> Result<(String, String, String), EnvErrors> {
+    let mut errors = EnvErrors::new();
+    let name = errors.check(env::required("CARGO_PKG_NAME"));
+    let version = errors.check(env::required("CARGO_PKG_VERSION"));
+    let environment = errors.check(env::optional("CARGO_ENV"));
+    match (name, version, environment) {
+        (Some(name), Some(version), Some(environment)) => {
+            Ok((name, version, environment.unwrap_or_else(default_environment)))
+        }
+        _ => Err(errors),
+    }
+}
+
+fn default_environment() -> String {
+    "development".to_owned()
+}
+
+fn build(name: String, version: String, environment: String) -> Resource {
+    Resource::builder()
+        .with_service_name(name.clone())
+        .with_attributes([
+            KeyValue::new(SERVICE_NAME, name),
+            KeyValue::new(SERVICE_VERSION, version),
+            KeyValue::new(DEPLOYMENT_ENVIRONMENT_NAME, environment),
+        ])
+        .build()
+}
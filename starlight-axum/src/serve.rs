@@ -0,0 +1,723 @@
+use crate::deadline::Deadline;
+use crate::health::InFlightTracker;
+use crate::oltp::shutdown_oltp_before;
+use crate::tls::TlsConfig;
+use connections::{Busy, ConnectionGuard, IdleTimeout, ListenerConnections};
+use axum::Router;
+use axum::extract::ConnectInfo;
+use axum::extract::Request;
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto::Builder;
+use hyper_util::server::graceful::{GracefulShutdown, Watcher};
+use socket2::{Domain, Protocol, SockRef, Socket, TcpKeepalive, Type};
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tower::ServiceExt;
+
+mod connections;
+
+pub use connections::{ConnectionStats, connection_stats};
+
+/// Serve `router` over HTTPS on `addr`.
+///
+/// Handshake failures are logged at debug level since they are mostly port scanners
+/// and clients without a valid certificate, and counted in the [`ConnectionStats`] of
+/// the listener.
+pub async fn serve_tls(addr: SocketAddr, router: Router, config: TlsConfig) -> io::Result<()> {
+    let acceptor = config.into_acceptor()?;
+    let listener = ListenerConfig::default().bind(addr)?;
+    let name = format!("https://{}", listener.local_addr()?);
+    info!("listening on {}", name);
+    let connections = ListenerConnections::register(name, None);
+
+    let graceful = GracefulShutdown::new();
+    loop {
+        let (stream, remote_addr) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                warn!("failed to accept connection: {}", err);
+                continue;
+            }
+        };
+
+        let Some(connection) = connections.admit() else {
+            continue;
+        };
+        let acceptor = acceptor.clone();
+        let router = router.clone();
+        let watcher = graceful.watcher();
+        tokio::spawn(async move {
+            match acceptor.accept(stream).await {
+                Ok(stream) => {
+                    serve_connection(stream, ConnectInfo(remote_addr), router, watcher, connection, None).await
+                }
+                Err(err) => {
+                    connection.handshake_failed();
+                    debug!("TLS handshake with {} failed: {}", remote_addr, err)
+                }
+            }
+        });
+    }
+}
+
+/// Socket options of a TCP listener, applied with socket2 before the socket is handed
+/// to tokio.
+///
+/// The defaults match [`TcpListener::bind`]: `SO_REUSEADDR` on unix, a backlog of 1024
+/// and the platform's IPv6 behaviour. [`ListenerConfig::dual_stack`] lets `[::]` accept
+/// IPv4 as v4-mapped addresses, [`ListenerConfig::ipv6_only`] leaves IPv4 to a second
+/// listener. `TCP_NODELAY` and keepalive are set on every accepted connection.
+/// Options the platform lacks, like `SO_REUSEPORT` on windows, are skipped with a
+/// warning.
+///
+/// [`ListenerConfig::with_max_connections`] and [`ListenerConfig::with_idle_timeout`]
+/// apply when serving through [`serve_many`] and the like, not to [`ListenerConfig::bind`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListenerConfig {
+    only_v6: Option<bool>,
+    reuse_address: bool,
+    reuse_port: bool,
+    nodelay: bool,
+    keepalive: Option<Duration>,
+    keepalive_interval: Option<Duration>,
+    keepalive_retries: Option<u32>,
+    backlog: u32,
+    max_connections: Option<usize>,
+    idle_timeout: Option<Duration>,
+}
+
+impl Default for ListenerConfig {
+    fn default() -> Self {
+        ListenerConfig {
+            only_v6: None,
+            reuse_address: cfg!(unix),
+            reuse_port: false,
+            nodelay: false,
+            keepalive: None,
+            keepalive_interval: None,
+            keepalive_retries: None,
+            backlog: 1024,
+            max_connections: None,
+            idle_timeout: None,
+        }
+    }
+}
+
+impl ListenerConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// IPv6 listeners also accept IPv4 connections, as v4-mapped addresses.
+    pub fn dual_stack(mut self) -> Self {
+        self.only_v6 = Some(false);
+        self
+    }
+
+    /// IPv6 listeners accept IPv6 connections only.
+    pub fn ipv6_only(mut self) -> Self {
+        self.only_v6 = Some(true);
+        self
+    }
+
+    pub fn with_reuse_address(mut self, reuse_address: bool) -> Self {
+        self.reuse_address = reuse_address;
+        self
+    }
+
+    /// Lets several processes bind the same port, so a new server can start accepting
+    /// before the old one stops.
+    pub fn with_reuse_port(mut self, reuse_port: bool) -> Self {
+        self.reuse_port = reuse_port;
+        self
+    }
+
+    pub fn with_nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+        self
+    }
+
+    /// Idle time before the first keepalive probe.
+    pub fn with_keepalive(mut self, idle: Duration) -> Self {
+        self.keepalive = Some(idle);
+        self
+    }
+
+    /// Time between keepalive probes, where the platform supports it.
+    pub fn with_keepalive_interval(mut self, interval: Duration) -> Self {
+        self.keepalive_interval = Some(interval);
+        self
+    }
+
+    /// Unanswered probes before the connection is dropped, where the platform supports it.
+    pub fn with_keepalive_retries(mut self, retries: u32) -> Self {
+        self.keepalive_retries = Some(retries);
+        self
+    }
+
+    pub fn with_backlog(mut self, backlog: u32) -> Self {
+        self.backlog = backlog;
+        self
+    }
+
+    /// Connections accepted while `max` are open are closed right away and counted as
+    /// refused, whatever the request limits.
+    pub fn with_max_connections(mut self, max: usize) -> Self {
+        self.max_connections = Some(max);
+        self
+    }
+
+    /// Closes connections on which nothing was read or written for `timeout` while no
+    /// request was being handled.
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Binds `addr` with these options. Errors name the address and the options.
+    ///
+    /// Must be called within a tokio runtime.
+    pub fn bind(&self, addr: SocketAddr) -> io::Result<TcpListener> {
+        self.try_bind(addr)
+            .map_err(|err| io::Error::new(err.kind(), format!("failed to bind {} with {}: {}", addr, self, err)))
+    }
+
+    fn try_bind(&self, addr: SocketAddr) -> io::Result<TcpListener> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+        if let (SocketAddr::V6(_), Some(only_v6)) = (addr, self.only_v6) {
+            socket.set_only_v6(only_v6)?;
+        }
+        socket.set_reuse_address(self.reuse_address)?;
+        if self.reuse_port {
+            #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin"))))]
+            socket.set_reuse_port(true)?;
+            #[cfg(not(all(unix, not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin")))))]
+            warn!("SO_REUSEPORT is not supported on this platform, binding {} without it", addr);
+        }
+        socket.set_nonblocking(true)?;
+        socket.bind(&addr.into())?;
+        socket.listen(self.backlog.min(i32::MAX as u32) as i32)?;
+        TcpListener::from_std(socket.into())
+    }
+
+    /// Applies the per-connection options to an accepted stream.
+    fn configure(&self, stream: &TcpStream) -> io::Result<()> {
+        if self.nodelay {
+            stream.set_nodelay(true)?;
+        }
+        let Some(idle) = self.keepalive else {
+            return Ok(());
+        };
+        #[allow(unused_mut)]
+        let mut keepalive = TcpKeepalive::new().with_time(idle);
+        #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "freebsd"))]
+        {
+            if let Some(interval) = self.keepalive_interval {
+                keepalive = keepalive.with_interval(interval);
+            }
+            if let Some(retries) = self.keepalive_retries {
+                keepalive = keepalive.with_retries(retries);
+            }
+        }
+        SockRef::from(stream).set_tcp_keepalive(&keepalive)
+    }
+}
+
+impl std::fmt::Display for ListenerConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.only_v6 {
+            Some(true) => f.write_str("ipv6_only")?,
+            Some(false) => f.write_str("dual_stack")?,
+            None => f.write_str("default_stack")?,
+        }
+        write!(
+            f,
+            ", reuse_address={}, reuse_port={}, nodelay={}, backlog={}",
+            self.reuse_address, self.reuse_port, self.nodelay, self.backlog
+        )?;
+        if let Some(idle) = self.keepalive {
+            write!(f, ", keepalive={:?}", idle)?;
+        }
+        if let Some(max) = self.max_connections {
+            write!(f, ", max_connections={}", max)?;
+        }
+        if let Some(timeout) = self.idle_timeout {
+            write!(f, ", idle_timeout={:?}", timeout)?;
+        }
+        Ok(())
+    }
+}
+
+/// An address to accept connections on.
+#[derive(Debug, Clone)]
+pub enum Listener {
+    Tcp(SocketAddr, ListenerConfig),
+    /// A unix domain socket. A stale socket file left behind by a previous run is
+    /// removed on bind; `mode` sets the socket file permissions (e.g. `0o660`).
+    #[cfg(unix)]
+    Unix { path: PathBuf, mode: Option<u32> },
+}
+
+impl Listener {
+    pub fn tcp(addr: SocketAddr) -> Self {
+        Listener::Tcp(addr, ListenerConfig::default())
+    }
+
+    #[cfg(unix)]
+    pub fn unix(path: impl Into<PathBuf>) -> Self {
+        Listener::Unix {
+            path: path.into(),
+            mode: None,
+        }
+    }
+
+    /// Set the socket options. Ignored for unix sockets.
+    pub fn with_config(self, config: ListenerConfig) -> Self {
+        match self {
+            Listener::Tcp(addr, _) => Listener::Tcp(addr, config),
+            #[cfg(unix)]
+            other => {
+                let _ = config;
+                other
+            }
+        }
+    }
+
+    /// Set the permissions of the socket file. Ignored for TCP listeners.
+    pub fn with_mode(self, mode: u32) -> Self {
+        match self {
+            #[cfg(unix)]
+            Listener::Unix { path, .. } => Listener::Unix {
+                path,
+                mode: Some(mode),
+            },
+            other => {
+                let _ = mode;
+                other
+            }
+        }
+    }
+
+    pub(crate) async fn bind(self) -> io::Result<BoundListener> {
+        match self {
+            Listener::Tcp(addr, config) => Ok(BoundListener::Tcp(config.bind(addr)?, config)),
+            #[cfg(unix)]
+            Listener::Unix { path, mode } => {
+                remove_stale_socket(&path).await?;
+                let listener = UnixListener::bind(&path)?;
+                if let Some(mode) = mode {
+                    use std::os::unix::fs::PermissionsExt;
+                    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode))?;
+                }
+                Ok(BoundListener::Unix(listener, path))
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Listener {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Listener::Tcp(addr, _) => write!(f, "http://{}", addr),
+            #[cfg(unix)]
+            Listener::Unix { path, .. } => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// Credentials of the process on the other end of a unix socket, available in
+/// request extensions for connections accepted on a [`Listener::Unix`].
+#[cfg(unix)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnixPeer {
+    pub uid: u32,
+    pub gid: u32,
+    pub pid: Option<i32>,
+}
+
+pub(crate) enum BoundListener {
+    Tcp(TcpListener, ListenerConfig),
+    #[cfg(unix)]
+    Unix(UnixListener, PathBuf),
+}
+
+enum Accepted {
+    Tcp(TcpStream, SocketAddr),
+    #[cfg(unix)]
+    Unix(UnixStream, UnixPeer),
+}
+
+impl BoundListener {
+    pub(crate) fn describe(&self) -> String {
+        match self {
+            BoundListener::Tcp(listener, _) => match listener.local_addr() {
+                Ok(addr) => format!("http://{}", addr),
+                Err(_) => "tcp".to_owned(),
+            },
+            #[cfg(unix)]
+            BoundListener::Unix(_, path) => format!("unix:{}", path.display()),
+        }
+    }
+
+    fn config(&self) -> Option<&ListenerConfig> {
+        match self {
+            BoundListener::Tcp(_, config) => Some(config),
+            #[cfg(unix)]
+            BoundListener::Unix(..) => None,
+        }
+    }
+
+    async fn accept(&self) -> io::Result<Accepted> {
+        match self {
+            BoundListener::Tcp(listener, config) => {
+                let (stream, addr) = listener.accept().await?;
+                if let Err(err) = config.configure(&stream) {
+                    warn!("failed to set socket options for {}: {}", addr, err);
+                }
+                Ok(Accepted::Tcp(stream, addr))
+            }
+            #[cfg(unix)]
+            BoundListener::Unix(listener, _) => {
+                let (stream, _) = listener.accept().await?;
+                let cred = stream.peer_cred()?;
+                let peer = UnixPeer {
+                    uid: cred.uid(),
+                    gid: cred.gid(),
+                    pid: cred.pid(),
+                };
+                Ok(Accepted::Unix(stream, peer))
+            }
+        }
+    }
+}
+
+impl Drop for BoundListener {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let BoundListener::Unix(_, path) = self {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return Ok(());
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists and is not a socket", path.display()),
+        ));
+    }
+    // Somebody is still serving on it: refuse rather than steal the socket.
+    if UnixStream::connect(path).await.is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("{} is in use by another process", path.display()),
+        ));
+    }
+    debug!("removing stale socket {}", path.display());
+    std::fs::remove_file(path)
+}
+
+/// Serve every `(listener, router)` pair concurrently until ctrl-c or SIGTERM.
+pub async fn serve_many(listeners: Vec<(Listener, Router)>) -> io::Result<()> {
+    serve_many_with_shutdown(listeners, shutdown_signal()).await
+}
+
+/// Serve every `(listener, router)` pair concurrently until `signal` resolves, then stop
+/// accepting and wait for in-flight connections to finish.
+///
+/// All listeners are bound before any of them starts serving, so a bad address fails fast.
+pub async fn serve_many_with_shutdown<F>(
+    listeners: Vec<(Listener, Router)>,
+    signal: F,
+) -> io::Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    serve_many_with_tracker(listeners, signal, InFlightTracker::new()).await
+}
+
+/// Like [`serve_many_with_shutdown`], counting requests with `tracker` and starting its
+/// drain as soon as `signal` resolves, so a [`readiness_router`](crate::health::readiness_router)
+/// on one of the listeners reports unready while the connections drain.
+pub async fn serve_many_with_tracker<F>(
+    listeners: Vec<(Listener, Router)>,
+    signal: F,
+    tracker: InFlightTracker,
+) -> io::Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let mut bound = Vec::with_capacity(listeners.len());
+    for (listener, router) in listeners {
+        bound.push((listener.bind().await?, router));
+    }
+
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let mut tasks = JoinSet::new();
+    for (listener, router) in bound {
+        tasks.spawn(accept_loop(listener, router.layer(tracker.layer()), shutdown_rx.clone()));
+    }
+
+    signal.await;
+    info!("shutdown signal received, draining connections");
+    tracker.start_drain();
+    let _ = shutdown_tx.send(true);
+
+    while let Some(result) = tasks.join_next().await {
+        result.map_err(io::Error::other)?;
+    }
+    // Upgraded connections outlive their HTTP connection.
+    while !tracker.wait_idle(Duration::from_secs(10)).await {
+        info!("waiting for {} connections to close", tracker.in_flight());
+    }
+    Ok(())
+}
+
+/// How a shutdown divides its time between draining connections and flushing telemetry,
+/// so together they fit in a termination grace period such as Kubernetes'.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShutdownBudget {
+    total: Duration,
+    drain_fraction: f64,
+}
+
+impl ShutdownBudget {
+    /// 80% of `total` for draining, the rest for telemetry.
+    pub fn new(total: Duration) -> Self {
+        ShutdownBudget {
+            total,
+            drain_fraction: 0.8,
+        }
+    }
+
+    /// The share of the total for draining, between 0 and 1.
+    pub fn with_drain_fraction(mut self, fraction: f64) -> Self {
+        self.drain_fraction = fraction.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    pub fn drain(&self) -> Duration {
+        self.total.mul_f64(self.drain_fraction)
+    }
+
+    /// Telemetry also gets whatever the drain leaves unused.
+    pub fn telemetry(&self) -> Duration {
+        self.total.saturating_sub(self.drain())
+    }
+
+    /// Drains until the drain slice runs out, warning with `stage = "drain"` when
+    /// connections are still open by then. Returns whether everything closed in time.
+    pub(crate) async fn drain_within(&self, drained: impl Future<Output = ()>) -> bool {
+        let drained = tokio::time::timeout(self.drain(), drained).await.is_ok();
+        if !drained {
+            warn!(stage = "drain", budget_ms = self.drain().as_millis() as u64, "shutdown stage exceeded its budget");
+        }
+        drained
+    }
+
+    pub(crate) fn telemetry_failed(&self, deadline: Deadline, err: &dyn std::error::Error) {
+        if deadline.is_expired() {
+            warn!(
+                stage = "telemetry",
+                budget_ms = self.telemetry().as_millis() as u64,
+                error = %err,
+                "shutdown stage exceeded its budget"
+            );
+        } else {
+            warn!(stage = "telemetry", error = %err, "failed to shut down telemetry");
+        }
+    }
+}
+
+/// Like [`serve_many_with_tracker`], but the whole shutdown fits in `budget`: connections
+/// get the drain slice, then the OTLP providers are shut down until the total runs out.
+/// A stage over its slice is logged as a warning with the stage, `drain` or `telemetry`,
+/// and cut short, so a hung client or a dead collector cannot outlast the grace period.
+pub async fn serve_graceful<F>(
+    listeners: Vec<(Listener, Router)>,
+    signal: F,
+    tracker: InFlightTracker,
+    budget: ShutdownBudget,
+) -> io::Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let mut bound = Vec::with_capacity(listeners.len());
+    for (listener, router) in listeners {
+        bound.push((listener.bind().await?, router));
+    }
+
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let mut tasks = JoinSet::new();
+    for (listener, router) in bound {
+        tasks.spawn(accept_loop(listener, router.layer(tracker.layer()), shutdown_rx.clone()));
+    }
+
+    signal.await;
+    let deadline = Deadline::after(budget.total());
+    info!(budget_ms = budget.total().as_millis() as u64, "shutdown signal received, draining connections");
+    tracker.start_drain();
+    let _ = shutdown_tx.send(true);
+
+    let drained = budget
+        .drain_within(async {
+            while let Some(result) = tasks.join_next().await {
+                if let Err(err) = result {
+                    warn!("listener failed while draining: {}", err);
+                }
+            }
+            // Upgraded connections outlive their HTTP connection.
+            while !tracker.wait_idle(Duration::from_millis(100)).await {}
+        })
+        .await;
+    if !drained {
+        info!("{} requests still in flight", tracker.in_flight());
+    }
+
+    if let Err(err) = shutdown_oltp_before(deadline).await {
+        budget.telemetry_failed(deadline, &*err);
+    }
+    Ok(())
+}
+
+/// Counts the connections of `listener` under its name, refusing those over its
+/// [`ListenerConfig::with_max_connections`].
+pub(crate) async fn accept_loop(listener: BoundListener, router: Router, mut shutdown: watch::Receiver<bool>) {
+    let name = listener.describe();
+    info!("listening on {}", name);
+    let max_connections = listener.config().and_then(|config| config.max_connections);
+    let idle_timeout = listener.config().and_then(|config| config.idle_timeout);
+    let connections = ListenerConnections::register(name, max_connections);
+    let graceful = GracefulShutdown::new();
+
+    loop {
+        let accepted = tokio::select! {
+            biased;
+            _ = shutdown.wait_for(|stop| *stop) => break,
+            accepted = listener.accept() => accepted,
+        };
+
+        let accepted = match accepted {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                warn!("failed to accept connection on {}: {}", listener.describe(), err);
+                continue;
+            }
+        };
+        let Some(connection) = connections.admit() else {
+            // Dropping the stream closes it.
+            debug!("refused a connection on {}, {:?} are open", listener.describe(), max_connections);
+            continue;
+        };
+        let router = router.clone();
+        let watcher = graceful.watcher();
+        match accepted {
+            Accepted::Tcp(stream, addr) => {
+                tokio::spawn(serve_connection(stream, ConnectInfo(addr), router, watcher, connection, idle_timeout));
+            }
+            #[cfg(unix)]
+            Accepted::Unix(stream, peer) => {
+                tokio::spawn(serve_connection(stream, peer, router, watcher, connection, idle_timeout));
+            }
+        }
+    }
+
+    drop(listener);
+    graceful.shutdown().await;
+}
+
+/// Resolve on ctrl-c, or SIGTERM on unix.
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install ctrl-c handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+/// Drive a single HTTP/1.1 or HTTP/2 connection to completion. `info` is inserted into
+/// the extensions of every request on the connection. How the connection ended is
+/// counted on `connection`.
+pub(crate) async fn serve_connection<I, T>(
+    io: I,
+    info: T,
+    router: Router,
+    watcher: Watcher,
+    connection: ConnectionGuard,
+    idle_timeout: Option<Duration>,
+) where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T: Clone + Send + Sync + 'static,
+{
+    let busy = Busy::default();
+    let requests = busy.clone();
+    let service = hyper::service::service_fn(move |mut req: Request<Incoming>| {
+        req.extensions_mut().insert(info.clone());
+        let handling = requests.enter();
+        let response = router.clone().oneshot(req);
+        async move {
+            let _handling = handling;
+            response.await
+        }
+    });
+
+    let builder = Builder::new(TokioExecutor::new());
+    let (result, timed_out) = match idle_timeout {
+        Some(timeout) => {
+            let (io, timed_out) = IdleTimeout::new(io, timeout, busy);
+            let conn = builder.serve_connection_with_upgrades(TokioIo::new(io), service);
+            (watcher.watch(conn.into_owned()).await, timed_out.load(Ordering::SeqCst))
+        }
+        None => {
+            let conn = builder.serve_connection_with_upgrades(TokioIo::new(io), service);
+            (watcher.watch(conn.into_owned()).await, false)
+        }
+    };
+    // hyper ends idle keep-alive connections cleanly on a read error.
+    match result {
+        _ if timed_out => connection.idle_timed_out(),
+        Ok(()) => {}
+        Err(err) => {
+            connection.errored();
+            debug!("connection closed with error: {}", err);
+        }
+    }
+}
@@ -0,0 +1,313 @@
+use crate::meter::GLOBAL_METER;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Counter, Meter, UpDownCounter};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{Instant, Sleep};
+
+/// The connections of a listener since the process started, served by
+/// [`AdminRouter`](crate::admin::AdminRouter) on `GET /admin/connections`.
+///
+/// `closed` counts every connection that ended; `errored`, `idle_timeouts` and
+/// `tls_handshake_failures` are the ones among them which ended for that reason.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConnectionStats {
+    pub listener: String,
+    pub open: u64,
+    pub max_connections: Option<usize>,
+    pub accepted: u64,
+    pub refused: u64,
+    pub closed: u64,
+    pub errored: u64,
+    pub idle_timeouts: u64,
+    pub tls_handshake_failures: u64,
+}
+
+/// The counts of every listener served by this process, by listener name such as
+/// `http://0.0.0.0:8080`.
+pub fn connection_stats() -> Vec<ConnectionStats> {
+    let listeners = LISTENERS.lock().unwrap_or_else(|e| e.into_inner());
+    listeners
+        .iter()
+        .map(|(listener, connections)| connections.stats(listener))
+        .collect()
+}
+
+static LISTENERS: LazyLock<Mutex<BTreeMap<String, Arc<ListenerConnections>>>> = LazyLock::new(Default::default);
+
+static INSTRUMENTS: LazyLock<Instruments> = LazyLock::new(|| Instruments::new(&GLOBAL_METER));
+
+struct Instruments {
+    open: UpDownCounter<i64>,
+    accepted: Counter<u64>,
+    refused: Counter<u64>,
+    closed: Counter<u64>,
+    errored: Counter<u64>,
+    idle_timeouts: Counter<u64>,
+    tls_handshake_failures: Counter<u64>,
+}
+
+impl Instruments {
+    fn new(meter: &Meter) -> Self {
+        let counter = |name: &'static str, description: &'static str| {
+            meter.u64_counter(name).with_description(description).build()
+        };
+        Instruments {
+            open: meter
+                .i64_up_down_counter("http.server.connections.open")
+                .with_description("Connections currently open")
+                .build(),
+            accepted: counter("http.server.connections.accepted", "Connections accepted"),
+            refused: counter(
+                "http.server.connections.refused",
+                "Connections closed on accept because the listener was at its limit",
+            ),
+            closed: counter("http.server.connections.closed", "Connections closed"),
+            errored: counter("http.server.connections.errored", "Connections closed with an error"),
+            idle_timeouts: counter(
+                "http.server.connections.idle_timeouts",
+                "Connections closed for being idle",
+            ),
+            tls_handshake_failures: counter("http.server.tls.handshake_failures", "Failed TLS handshakes"),
+        }
+    }
+}
+
+/// Counters of one listener, shared by every listener bound under the same name.
+pub(crate) struct ListenerConnections {
+    attributes: [KeyValue; 1],
+    max_connections: AtomicUsize,
+    open: AtomicU64,
+    accepted: AtomicU64,
+    refused: AtomicU64,
+    closed: AtomicU64,
+    errored: AtomicU64,
+    idle_timeouts: AtomicU64,
+    tls_handshake_failures: AtomicU64,
+}
+
+impl ListenerConnections {
+    /// The counters of `listener`, created on its first bind.
+    pub(crate) fn register(listener: String, max_connections: Option<usize>) -> Arc<Self> {
+        let mut listeners = LISTENERS.lock().unwrap_or_else(|e| e.into_inner());
+        let connections = listeners.entry(listener.clone()).or_insert_with(|| {
+            Arc::new(ListenerConnections {
+                attributes: [KeyValue::new("server.listener", listener)],
+                max_connections: AtomicUsize::new(usize::MAX),
+                open: AtomicU64::new(0),
+                accepted: AtomicU64::new(0),
+                refused: AtomicU64::new(0),
+                closed: AtomicU64::new(0),
+                errored: AtomicU64::new(0),
+                idle_timeouts: AtomicU64::new(0),
+                tls_handshake_failures: AtomicU64::new(0),
+            })
+        });
+        connections
+            .max_connections
+            .store(max_connections.unwrap_or(usize::MAX), Ordering::Relaxed);
+        connections.clone()
+    }
+
+    /// Counts an accepted connection, or a refused one when the listener is at its limit.
+    /// Only the accept loop of the listener admits connections, so the check cannot race
+    /// with another admission.
+    pub(crate) fn admit(self: &Arc<Self>) -> Option<ConnectionGuard> {
+        let max = self.max_connections.load(Ordering::Relaxed);
+        if self.open.load(Ordering::SeqCst) >= max as u64 {
+            self.refused.fetch_add(1, Ordering::Relaxed);
+            INSTRUMENTS.refused.add(1, &self.attributes);
+            return None;
+        }
+        self.open.fetch_add(1, Ordering::SeqCst);
+        self.accepted.fetch_add(1, Ordering::Relaxed);
+        INSTRUMENTS.open.add(1, &self.attributes);
+        INSTRUMENTS.accepted.add(1, &self.attributes);
+        Some(ConnectionGuard {
+            connections: self.clone(),
+        })
+    }
+
+    fn stats(&self, listener: &str) -> ConnectionStats {
+        let max = self.max_connections.load(Ordering::Relaxed);
+        ConnectionStats {
+            listener: listener.to_owned(),
+            open: self.open.load(Ordering::SeqCst),
+            max_connections: (max != usize::MAX).then_some(max),
+            accepted: self.accepted.load(Ordering::Relaxed),
+            refused: self.refused.load(Ordering::Relaxed),
+            closed: self.closed.load(Ordering::Relaxed),
+            errored: self.errored.load(Ordering::Relaxed),
+            idle_timeouts: self.idle_timeouts.load(Ordering::Relaxed),
+            tls_handshake_failures: self.tls_handshake_failures.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// An open connection; dropping it counts the connection as closed.
+pub(crate) struct ConnectionGuard {
+    connections: Arc<ListenerConnections>,
+}
+
+impl ConnectionGuard {
+    pub(crate) fn errored(&self) {
+        self.connections.errored.fetch_add(1, Ordering::Relaxed);
+        INSTRUMENTS.errored.add(1, &self.connections.attributes);
+    }
+
+    pub(crate) fn idle_timed_out(&self) {
+        self.connections.idle_timeouts.fetch_add(1, Ordering::Relaxed);
+        INSTRUMENTS.idle_timeouts.add(1, &self.connections.attributes);
+    }
+
+    pub(crate) fn handshake_failed(&self) {
+        self.connections.tls_handshake_failures.fetch_add(1, Ordering::Relaxed);
+        INSTRUMENTS.tls_handshake_failures.add(1, &self.connections.attributes);
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let connections = &self.connections;
+        connections.open.fetch_sub(1, Ordering::SeqCst);
+        connections.closed.fetch_add(1, Ordering::Relaxed);
+        INSTRUMENTS.open.add(-1, &connections.attributes);
+        INSTRUMENTS.closed.add(1, &connections.attributes);
+    }
+}
+
+/// Requests of a connection still being handled, during which it is not idle.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Busy(Arc<AtomicUsize>);
+
+impl Busy {
+    pub(crate) fn enter(&self) -> BusyGuard {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        BusyGuard(self.0.clone())
+    }
+
+    fn is_busy(&self) -> bool {
+        self.0.load(Ordering::SeqCst) > 0
+    }
+}
+
+pub(crate) struct BusyGuard(Arc<AtomicUsize>);
+
+impl Drop for BusyGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Fails reads and writes with [`io::ErrorKind::TimedOut`] once nothing was read or
+/// written for `timeout` while no request was being handled, which closes the
+/// connection.
+pub(crate) struct IdleTimeout<I> {
+    io: I,
+    timeout: Duration,
+    sleep: Pin<Box<Sleep>>,
+    busy: Busy,
+    timed_out: Arc<AtomicBool>,
+}
+
+impl<I> IdleTimeout<I> {
+    /// Also returns the flag set once the timeout closed the connection.
+    pub(crate) fn new(io: I, timeout: Duration, busy: Busy) -> (Self, Arc<AtomicBool>) {
+        let timed_out = Arc::new(AtomicBool::new(false));
+        let io = IdleTimeout {
+            io,
+            timeout,
+            sleep: Box::pin(tokio::time::sleep(timeout)),
+            busy,
+            timed_out: timed_out.clone(),
+        };
+        (io, timed_out)
+    }
+
+    fn touch(&mut self) {
+        let deadline = Instant::now() + self.timeout;
+        self.sleep.as_mut().reset(deadline);
+    }
+
+    fn poll_idle(&mut self, cx: &mut Context<'_>) -> Poll<io::Error> {
+        while self.sleep.as_mut().poll(cx).is_ready() {
+            if !self.busy.is_busy() {
+                self.timed_out.store(true, Ordering::SeqCst);
+                return Poll::Ready(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("connection idle for {:?}", self.timeout),
+                ));
+            }
+            self.touch();
+        }
+        Poll::Pending
+    }
+}
+
+impl<I: AsyncRead + Unpin> AsyncRead for IdleTimeout<I> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        match Pin::new(&mut self.io).poll_read(cx, buf) {
+            Poll::Ready(Ok(())) => {
+                if buf.filled().len() > filled {
+                    self.touch();
+                }
+                Poll::Ready(Ok(()))
+            }
+            Poll::Pending => self.poll_idle(cx).map(Err),
+            ready => ready,
+        }
+    }
+}
+
+impl<I: AsyncWrite + Unpin> AsyncWrite for IdleTimeout<I> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match Pin::new(&mut self.io).poll_write(cx, buf) {
+            Poll::Ready(Ok(written)) => {
+                if written > 0 {
+                    self.touch();
+                }
+                Poll::Ready(Ok(written))
+            }
+            Poll::Pending => self.poll_idle(cx).map(Err),
+            ready => ready,
+        }
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match Pin::new(&mut self.io).poll_write_vectored(cx, bufs) {
+            Poll::Ready(Ok(written)) => {
+                if written > 0 {
+                    self.touch();
+                }
+                Poll::Ready(Ok(written))
+            }
+            Poll::Pending => self.poll_idle(cx).map(Err),
+            ready => ready,
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,215 @@
+use crate::health::InFlightTracker;
+use crate::meter::GLOBAL_METER;
+use crate::deadline::Deadline;
+use crate::oltp::flush_oltp_before;
+use crate::serve::{Listener, ShutdownBudget, accept_loop};
+use anyhow::Context;
+use axum::Router;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Counter, Meter, UpDownCounter};
+use starlight_tokio::{CancellationToken, LifecycleEvent, LifecycleObserver, ServiceContext, StarlightServiceV2};
+use std::sync::Arc;
+use tokio::sync::watch;
+
+pub const SERVICE_RUNNING: &str = "service.running";
+pub const SERVICE_STARTS: &str = "service.starts";
+pub const SERVICE_COMPLETIONS: &str = "service.completions";
+pub const SERVICE_FAILURES: &str = "service.failures";
+pub const SERVICE_RESTARTS: &str = "service.restarts";
+pub const SERVICE_ABORTS: &str = "service.aborts";
+pub const SERVICE_LONG_POLLS: &str = "service.long_polls";
+
+/// Serves a [`Router`] as a starlight-tokio service, so the server shuts down with the
+/// rest of the [`ServiceManager`](starlight_tokio::ServiceManager) instead of on its own
+/// signal handler.
+///
+/// The listener is bound when the service starts and it reports ready once listening.
+/// On shutdown it stops accepting and drains open connections, then flushes telemetry,
+/// within the manager's grace period: the drain gets 80% of it by default, see
+/// [`AxumService::with_drain_fraction`], and the flush whatever is left.
+pub struct AxumService {
+    name: String,
+    listener: Listener,
+    router: Router,
+    flush_telemetry: bool,
+    drain_fraction: f64,
+    tracker: Option<InFlightTracker>,
+}
+
+impl AxumService {
+    pub fn new(listener: Listener, router: Router) -> Self {
+        AxumService {
+            name: "axum".to_owned(),
+            listener,
+            router,
+            flush_telemetry: true,
+            drain_fraction: 0.8,
+            tracker: None,
+        }
+    }
+
+    /// The service name, `axum` by default. Needed to run several servers in one manager.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Counts requests with `tracker` and starts its drain on shutdown, for a
+    /// [`readiness_router`](crate::health::readiness_router).
+    pub fn with_tracker(mut self, tracker: InFlightTracker) -> Self {
+        self.tracker = Some(tracker);
+        self
+    }
+
+    /// Whether to flush telemetry once the connections are drained, on by default.
+    pub fn with_flush_telemetry(mut self, flush_telemetry: bool) -> Self {
+        self.flush_telemetry = flush_telemetry;
+        self
+    }
+
+    /// The share of the grace period for draining connections, see
+    /// [`ShutdownBudget::with_drain_fraction`].
+    pub fn with_drain_fraction(mut self, fraction: f64) -> Self {
+        self.drain_fraction = fraction;
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl StarlightServiceV2 for AxumService {
+    async fn run(self: Arc<Self>, shutdown: CancellationToken) -> anyhow::Result<()> {
+        let context = ServiceContext::detached(&self.name, shutdown);
+        self.run_with_context(context).await
+    }
+
+    async fn run_with_context(self: Arc<Self>, context: ServiceContext) -> anyhow::Result<()> {
+        let listener = self.listener.clone().bind().await.with_context(|| format!("failed to bind {:?}", self.listener))?;
+        let described = listener.describe();
+        context.ready().ready();
+
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let router = match &self.tracker {
+            Some(tracker) => self.router.clone().layer(tracker.layer()),
+            None => self.router.clone(),
+        };
+        let budget = ShutdownBudget::new(context.grace_period()).with_drain_fraction(self.drain_fraction);
+        let mut deadline = Deadline::after(budget.total());
+        let mut serve = std::pin::pin!(accept_loop(listener, router, shutdown_rx));
+        tokio::select! {
+            _ = &mut serve => {}
+            _ = context.shutdown().cancelled() => {
+                info!(service = %self.name, "shutting down {}, draining connections", described);
+                deadline = Deadline::after(budget.total());
+                if let Some(tracker) = &self.tracker {
+                    tracker.start_drain();
+                }
+                let _ = shutdown_tx.send(true);
+                budget
+                    .drain_within(async {
+                        serve.await;
+                        // Upgraded connections outlive their HTTP connection.
+                        if let Some(tracker) = &self.tracker {
+                            tracker.wait_idle(budget.drain()).await;
+                        }
+                    })
+                    .await;
+            }
+        }
+
+        if self.flush_telemetry
+            && let Err(err) = flush_oltp_before(deadline).await
+        {
+            budget.telemetry_failed(deadline, &*err);
+            return Err(anyhow::anyhow!("failed to flush telemetry: {}", err));
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Records service lifecycle metrics through the meter, labeled by `service`. Set it with
+/// [`ServiceManager::with_observer`](starlight_tokio::ServiceManager::with_observer).
+#[derive(Debug, Clone)]
+pub struct MeterLifecycleObserver {
+    running: UpDownCounter<i64>,
+    starts: Counter<u64>,
+    completions: Counter<u64>,
+    failures: Counter<u64>,
+    restarts: Counter<u64>,
+    aborts: Counter<u64>,
+    long_polls: Counter<u64>,
+}
+
+impl Default for MeterLifecycleObserver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MeterLifecycleObserver {
+    pub fn new() -> Self {
+        Self::with_meter(&GLOBAL_METER)
+    }
+
+    pub fn with_meter(meter: &Meter) -> Self {
+        MeterLifecycleObserver {
+            running: meter
+                .i64_up_down_counter(SERVICE_RUNNING)
+                .with_description("Services currently running")
+                .build(),
+            starts: meter.u64_counter(SERVICE_STARTS).with_description("Services started").build(),
+            completions: meter
+                .u64_counter(SERVICE_COMPLETIONS)
+                .with_description("Services which returned successfully")
+                .build(),
+            failures: meter
+                .u64_counter(SERVICE_FAILURES)
+                .with_description("Services which failed or panicked")
+                .build(),
+            restarts: meter
+                .u64_counter(SERVICE_RESTARTS)
+                .with_description("Restarts of supervised services")
+                .build(),
+            aborts: meter
+                .u64_counter(SERVICE_ABORTS)
+                .with_description("Services aborted for not stopping in time")
+                .build(),
+            long_polls: meter
+                .u64_counter(SERVICE_LONG_POLLS)
+                .with_description("Polls of budgeted services exceeding their threshold")
+                .build(),
+        }
+    }
+}
+
+impl LifecycleObserver for MeterLifecycleObserver {
+    fn observe(&self, service: &str, event: &LifecycleEvent<'_>) {
+        let attributes = [KeyValue::new("service", service.to_owned())];
+        let stopped = match event {
+            LifecycleEvent::Started => {
+                self.starts.add(1, &attributes);
+                self.running.add(1, &attributes);
+                None
+            }
+            LifecycleEvent::Restarting { .. } => {
+                self.restarts.add(1, &attributes);
+                None
+            }
+            LifecycleEvent::LongPoll { .. } => {
+                self.long_polls.add(1, &attributes);
+                None
+            }
+            LifecycleEvent::WarmingUp | LifecycleEvent::WarmedUp { .. } | LifecycleEvent::PreStop { .. } => None,
+            LifecycleEvent::Completed => Some(&self.completions),
+            LifecycleEvent::Failed(_) => Some(&self.failures),
+            LifecycleEvent::Aborted => Some(&self.aborts),
+        };
+        if let Some(counter) = stopped {
+            counter.add(1, &attributes);
+            self.running.add(-1, &attributes);
+        }
+    }
+}
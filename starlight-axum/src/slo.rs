@@ -0,0 +1,246 @@
+use crate::meter::GLOBAL_METER;
+use axum::extract::{MatchedPath, Request};
+use axum::http::StatusCode;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Counter, Meter};
+use std::future::Future;
+use std::ops::RangeInclusive;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tower::{Layer, Service};
+
+pub const SLO_REQUESTS_TOTAL: &str = "slo.requests.total";
+pub const SLO_REQUESTS_GOOD: &str = "slo.requests.good";
+
+/// A latency/availability objective for the routes matching `route_pattern`.
+///
+/// `route_pattern` is compared with the matched route (e.g. `/users/{id}`); a trailing
+/// `*` matches any route with that prefix. A request is good when its status is in
+/// `success_statuses` and it completed within `latency_threshold`.
+#[derive(Debug, Clone)]
+pub struct SloConfig {
+    pub name: String,
+    pub route_pattern: String,
+    pub latency_threshold: Duration,
+    pub success_statuses: Vec<RangeInclusive<u16>>,
+}
+
+impl SloConfig {
+    /// An objective counting every non-5xx response under `latency_threshold` as good.
+    pub fn new(name: &str, route_pattern: &str, latency_threshold: Duration) -> Self {
+        SloConfig {
+            name: name.to_owned(),
+            route_pattern: route_pattern.to_owned(),
+            latency_threshold,
+            success_statuses: vec![100..=499],
+        }
+    }
+
+    pub fn with_success_statuses(mut self, statuses: impl IntoIterator<Item = RangeInclusive<u16>>) -> Self {
+        self.success_statuses = statuses.into_iter().collect();
+        self
+    }
+
+    pub fn matches_route(&self, route: &str) -> bool {
+        match self.route_pattern.strip_suffix('*') {
+            Some(prefix) => route.starts_with(prefix),
+            None => route == self.route_pattern,
+        }
+    }
+
+    pub fn is_good(&self, status: StatusCode, latency: Duration) -> bool {
+        latency <= self.latency_threshold
+            && self
+                .success_statuses
+                .iter()
+                .any(|range| range.contains(&status.as_u16()))
+    }
+}
+
+#[derive(Debug)]
+struct SloInstruments {
+    objectives: Vec<SloConfig>,
+    total: Counter<u64>,
+    good: Counter<u64>,
+}
+
+/// Emits `slo.requests.total` and `slo.requests.good`, labelled with `slo.name`, for
+/// every objective whose route pattern matches the request. The burn rate is then
+/// `1 - good / total` over the window of interest.
+#[derive(Debug, Clone)]
+pub struct SloLayer {
+    instruments: Arc<SloInstruments>,
+}
+
+impl SloLayer {
+    pub fn new(objectives: Vec<SloConfig>) -> Self {
+        Self::with_meter(objectives, &GLOBAL_METER)
+    }
+
+    pub fn with_meter(objectives: Vec<SloConfig>, meter: &Meter) -> Self {
+        SloLayer {
+            instruments: Arc::new(SloInstruments {
+                objectives,
+                total: meter
+                    .u64_counter(SLO_REQUESTS_TOTAL)
+                    .with_description("Requests counted against a service level objective")
+                    .with_unit("requests")
+                    .build(),
+                good: meter
+                    .u64_counter(SLO_REQUESTS_GOOD)
+                    .with_description("Requests meeting their service level objective")
+                    .with_unit("requests")
+                    .build(),
+            }),
+        }
+    }
+}
+
+impl<S> Layer<S> for SloLayer {
+    type Service = SloService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SloService {
+            inner,
+            instruments: self.instruments.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SloService<S> {
+    inner: S,
+    instruments: Arc<SloInstruments>,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for SloService<S>
+where
+    S: Service<Request<B>, Response = axum::http::Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        let route = req
+            .extensions()
+            .get::<MatchedPath>()
+            .map(|path| path.as_str().to_owned())
+            .unwrap_or_else(|| req.uri().path().to_owned());
+        let matching: Vec<usize> = self
+            .instruments
+            .objectives
+            .iter()
+            .enumerate()
+            .filter(|(_, slo)| slo.matches_route(&route))
+            .map(|(index, _)| index)
+            .collect();
+
+        let instruments = self.instruments.clone();
+        let started = Instant::now();
+        let future = self.inner.call(req);
+        Box::pin(async move {
+            let result = future.await;
+            let latency = started.elapsed();
+            // Errors from the inner service never produced a response; count them as bad.
+            let status = result
+                .as_ref()
+                .map(|response| response.status())
+                .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
+            for index in matching {
+                let slo = &instruments.objectives[index];
+                let labels = [KeyValue::new("slo.name", slo.name.clone())];
+                instruments.total.add(1, &labels);
+                if slo.is_good(status, latency) {
+                    instruments.good.add(1, &labels);
+                }
+            }
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Router;
+    use axum::body::Body;
+    use axum::routing::get;
+    use opentelemetry::metrics::MeterProvider;
+    use opentelemetry_sdk::metrics::data::{AggregatedMetrics, MetricData};
+    use opentelemetry_sdk::metrics::{InMemoryMetricExporter, PeriodicReader, SdkMeterProvider};
+    use tower::ServiceExt;
+
+    fn sum_for(exporter: &InMemoryMetricExporter, name: &str, slo: &str) -> u64 {
+        let metrics = exporter.get_finished_metrics().unwrap();
+        let Some(last) = metrics.last() else { return 0 };
+        last.scope_metrics()
+            .flat_map(|scope| scope.metrics())
+            .filter(|metric| metric.name() == name)
+            .filter_map(|metric| match metric.data() {
+                AggregatedMetrics::U64(MetricData::Sum(sum)) => Some(
+                    sum.data_points()
+                        .filter(|point| {
+                            point
+                                .attributes()
+                                .any(|kv| kv.key.as_str() == "slo.name" && kv.value.as_str() == slo)
+                        })
+                        .map(|point| point.value())
+                        .sum::<u64>(),
+                ),
+                _ => None,
+            })
+            .sum()
+    }
+
+    #[tokio::test]
+    async fn counts_good_and_total_per_objective() {
+        let exporter = InMemoryMetricExporter::default();
+        let provider = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(exporter.clone()).build())
+            .build();
+        let meter = provider.meter("slo-test");
+
+        let layer = SloLayer::with_meter(
+            vec![
+                SloConfig::new("checkout-fast", "/checkout/{mode}", Duration::from_millis(50)),
+                SloConfig::new("checkout-available", "/checkout/*", Duration::from_secs(10)),
+            ],
+            &meter,
+        );
+        let app = Router::new()
+            .route(
+                "/checkout/{mode}",
+                get(|axum::extract::Path(mode): axum::extract::Path<String>| async move {
+                    match mode.as_str() {
+                        "slow" => {
+                            tokio::time::sleep(Duration::from_millis(100)).await;
+                            StatusCode::OK
+                        }
+                        "error" => StatusCode::INTERNAL_SERVER_ERROR,
+                        _ => StatusCode::OK,
+                    }
+                }),
+            )
+            .layer(layer);
+
+        for mode in ["fast", "slow", "error"] {
+            let request = Request::get(format!("/checkout/{}", mode)).body(Body::empty()).unwrap();
+            app.clone().oneshot(request).await.unwrap();
+        }
+        provider.force_flush().unwrap();
+
+        assert_eq!(sum_for(&exporter, SLO_REQUESTS_TOTAL, "checkout-fast"), 3);
+        assert_eq!(sum_for(&exporter, SLO_REQUESTS_GOOD, "checkout-fast"), 1);
+        assert_eq!(sum_for(&exporter, SLO_REQUESTS_TOTAL, "checkout-available"), 3);
+        assert_eq!(sum_for(&exporter, SLO_REQUESTS_GOOD, "checkout-available"), 2);
+    }
+}
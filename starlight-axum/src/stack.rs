@@ -0,0 +1,347 @@
+//! The recommended middleware composition, checked for ordering mistakes, and the
+//! summary of it that services log once at startup.
+
+use crate::config::TelemetryConfig;
+use crate::deadline::DeadlineLayer;
+use crate::middleware::{generate_request_id_middleware, oltp_middleware_with_attributes, trace_middleware};
+use crate::serve::Listener;
+use axum::Router;
+use axum::extract::Request;
+use axum::response::IntoResponse;
+use axum::routing::Route;
+use std::convert::Infallible;
+use std::time::Duration;
+use tower::{Layer, Service};
+use tower_http::catch_panic::CatchPanicLayer;
+
+/// A built-in layer of the stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    /// Sets and propagates `starlight-request-id`.
+    RequestId,
+    /// The request span, parented to the caller's trace context.
+    Trace,
+    /// HTTP server metrics.
+    Metrics,
+    /// Turns a panicking handler into a 500.
+    CatchPanic,
+    /// The [`DeadlineLayer`].
+    Timeout,
+}
+
+impl Stage {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::RequestId => "request_id",
+            Stage::Trace => "trace",
+            Stage::Metrics => "metrics",
+            Stage::CatchPanic => "catch_panic",
+            Stage::Timeout => "timeout",
+        }
+    }
+}
+
+impl std::fmt::Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Stages that must wrap another one, and why.
+const CONSTRAINTS: &[(Stage, Stage, &str)] = &[
+    (Stage::Trace, Stage::Timeout, "timed out requests must still be traced"),
+    (Stage::Metrics, Stage::Timeout, "timed out requests must still be measured"),
+    (
+        Stage::CatchPanic,
+        Stage::Timeout,
+        "a panic inside the timeout would abort it instead of answering 500",
+    ),
+];
+
+/// Why a [`StackBuilder`] could not be built.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StackError {
+    /// The named layer sits outside the request id, so it cannot see it.
+    RequestIdNotOutermost(String),
+    /// `outer` must wrap `inner`, but sits inside it.
+    Misordered {
+        outer: Stage,
+        inner: Stage,
+        reason: &'static str,
+    },
+    /// A layer was inserted next to a stage the stack does not have.
+    MissingStage(Stage),
+}
+
+impl std::fmt::Display for StackError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StackError::RequestIdNotOutermost(name) => {
+                write!(f, "{} is outside request_id, which must be the outermost layer", name)
+            }
+            StackError::Misordered { outer, inner, reason } => {
+                write!(f, "{} must be outside {}: {}", outer, inner, reason)
+            }
+            StackError::MissingStage(stage) => write!(f, "the stack has no {} layer", stage),
+        }
+    }
+}
+
+impl std::error::Error for StackError {}
+
+/// A layer of the stack with the configuration shown in the startup summary.
+pub struct StackLayer {
+    name: String,
+    stage: Option<Stage>,
+    config: Vec<(String, String)>,
+    apply: Box<dyn FnOnce(Router) -> Router + Send>,
+}
+
+impl StackLayer {
+    pub fn new<L>(name: impl Into<String>, layer: L) -> Self
+    where
+        L: Layer<Route> + Clone + Send + Sync + 'static,
+        L::Service: Service<Request> + Clone + Send + Sync + 'static,
+        <L::Service as Service<Request>>::Response: IntoResponse + 'static,
+        <L::Service as Service<Request>>::Error: Into<Infallible> + 'static,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        StackLayer {
+            name: name.into(),
+            stage: None,
+            config: Vec::new(),
+            apply: Box::new(move |router: Router| router.layer(layer)),
+        }
+    }
+
+    /// A configuration value to show in the startup summary.
+    pub fn with_config(mut self, key: impl Into<String>, value: impl std::fmt::Display) -> Self {
+        self.config.push((key.into(), value.to_string()));
+        self
+    }
+
+    fn stage(mut self, stage: Stage) -> Self {
+        self.stage = Some(stage);
+        self
+    }
+
+    fn summary(&self) -> LayerSummary {
+        LayerSummary {
+            name: self.name.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+impl std::fmt::Debug for StackLayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StackLayer")
+            .field("name", &self.name)
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Composes the middleware of a service, outermost first, and refuses orders that
+/// break it: the request id must be outermost so every other layer sees it, and trace,
+/// metrics and panic catching must sit outside the timeout.
+///
+/// ```ignore
+/// let stack = StackBuilder::recommended(Duration::from_secs(30))
+///     .insert_after(Stage::Metrics, StackLayer::new("cors", cors));
+/// let summary = StartupSummary::new(&stack).with_listener(&listener).with_routes(12);
+/// let app = stack.build(routes)?;
+/// summary.emit();
+/// ```
+#[derive(Debug, Default)]
+pub struct StackBuilder {
+    layers: Vec<StackLayer>,
+    missing: Option<Stage>,
+}
+
+impl StackBuilder {
+    pub fn new() -> Self {
+        StackBuilder::default()
+    }
+
+    /// Request id, trace, metrics, panic catching and a `timeout` deadline, in that
+    /// order.
+    pub fn recommended(timeout: Duration) -> Self {
+        StackBuilder::new()
+            .with_request_id()
+            .with_trace()
+            .with_metrics()
+            .with_catch_panic()
+            .with_timeout(timeout)
+    }
+
+    /// Add a built-in stage at the end, or replace it where it already is.
+    fn with_stage(mut self, layer: StackLayer) -> Self {
+        match self.layers.iter().position(|existing| existing.stage == layer.stage) {
+            Some(index) => self.layers[index] = layer,
+            None => self.layers.push(layer),
+        }
+        self
+    }
+
+    pub fn with_request_id(self) -> Self {
+        let header = starlight_protocol::constants::STARLIGHT_REQUEST_ID;
+        let layer = StackLayer::new(Stage::RequestId.name(), generate_request_id_middleware());
+        self.with_stage(layer.with_config("header", header).stage(Stage::RequestId))
+    }
+
+    pub fn with_trace(self) -> Self {
+        let layer = StackLayer::new(Stage::Trace.name(), trace_middleware());
+        self.with_stage(layer.stage(Stage::Trace))
+    }
+
+    pub fn with_metrics(self) -> Self {
+        let layer = StackLayer::new(Stage::Metrics.name(), oltp_middleware_with_attributes());
+        self.with_stage(layer.stage(Stage::Metrics))
+    }
+
+    pub fn with_catch_panic(self) -> Self {
+        let layer = StackLayer::new(Stage::CatchPanic.name(), CatchPanicLayer::new());
+        self.with_stage(layer.stage(Stage::CatchPanic))
+    }
+
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        let layer = StackLayer::new(Stage::Timeout.name(), DeadlineLayer::new(timeout));
+        self.with_stage(layer.with_config("timeout", format!("{:?}", timeout)).stage(Stage::Timeout))
+    }
+
+    /// Add a layer inside all the others.
+    pub fn with_layer(mut self, layer: StackLayer) -> Self {
+        self.layers.push(layer);
+        self
+    }
+
+    /// Add a layer just outside `stage`.
+    pub fn insert_before(self, stage: Stage, layer: StackLayer) -> Self {
+        self.insert_at(stage, 0, layer)
+    }
+
+    /// Add a layer just inside `stage`.
+    pub fn insert_after(self, stage: Stage, layer: StackLayer) -> Self {
+        self.insert_at(stage, 1, layer)
+    }
+
+    fn insert_at(mut self, stage: Stage, offset: usize, layer: StackLayer) -> Self {
+        match self.layers.iter().position(|existing| existing.stage == Some(stage)) {
+            Some(index) => self.layers.insert(index + offset, layer),
+            None => {
+                self.missing.get_or_insert(stage);
+            }
+        }
+        self
+    }
+
+    /// The layers in order, outermost first.
+    pub fn layers(&self) -> Vec<LayerSummary> {
+        self.layers.iter().map(StackLayer::summary).collect()
+    }
+
+    pub fn validate(&self) -> Result<(), StackError> {
+        if let Some(stage) = self.missing {
+            return Err(StackError::MissingStage(stage));
+        }
+        let position = |stage| self.layers.iter().position(|layer| layer.stage == Some(stage));
+        if let Some(index) = position(Stage::RequestId)
+            && index > 0
+        {
+            return Err(StackError::RequestIdNotOutermost(self.layers[0].name.clone()));
+        }
+        for (outer, inner, reason) in CONSTRAINTS {
+            if let (Some(outer_index), Some(inner_index)) = (position(*outer), position(*inner))
+                && outer_index > inner_index
+            {
+                return Err(StackError::Misordered {
+                    outer: *outer,
+                    inner: *inner,
+                    reason,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Wrap `router` in the stack, once it is [valid](StackBuilder::validate).
+    pub fn build(self, router: Router) -> Result<Router, StackError> {
+        self.validate()?;
+        // The last layer applied ends up outermost.
+        Ok(self.layers.into_iter().rev().fold(router, |router, layer| (layer.apply)(router)))
+    }
+}
+
+/// A layer as shown in the startup summary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayerSummary {
+    pub name: String,
+    pub config: Vec<(String, String)>,
+}
+
+impl std::fmt::Display for LayerSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.name)?;
+        if !self.config.is_empty() {
+            let config: Vec<String> = self.config.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+            write!(f, "({})", config.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// What a service runs with, logged as one event on the `starlight::startup` target with
+/// `listeners`, `middleware`, `telemetry` and `routes` fields.
+///
+/// Axum does not expose the routes of a [`Router`], so the count is the caller's.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StartupSummary {
+    pub listeners: Vec<String>,
+    pub middleware: Vec<LayerSummary>,
+    pub telemetry: Vec<String>,
+    pub routes: Option<usize>,
+}
+
+impl StartupSummary {
+    pub fn new(stack: &StackBuilder) -> Self {
+        StartupSummary {
+            middleware: stack.layers(),
+            ..StartupSummary::default()
+        }
+    }
+
+    pub fn with_listener(mut self, listener: &Listener) -> Self {
+        self.listeners.push(listener.to_string());
+        self
+    }
+
+    /// The OTLP exporter endpoint of `config`.
+    pub fn with_telemetry(self, config: &TelemetryConfig) -> Self {
+        let protocol = config.otlp_protocol.as_deref().unwrap_or("grpc");
+        self.with_telemetry_endpoint("otlp", format!("{} ({})", config.otlp_endpoint, protocol))
+    }
+
+    /// Another telemetry endpoint, like the admin server's `/metrics`.
+    pub fn with_telemetry_endpoint(mut self, name: &str, endpoint: impl std::fmt::Display) -> Self {
+        self.telemetry.push(format!("{}={}", name, endpoint));
+        self
+    }
+
+    pub fn with_routes(mut self, routes: usize) -> Self {
+        self.routes = Some(routes);
+        self
+    }
+
+    pub fn emit(&self) {
+        let middleware: Vec<String> = self.middleware.iter().map(LayerSummary::to_string).collect();
+        info!(
+            target: "starlight::startup",
+            listeners = %self.listeners.join(", "),
+            middleware = %middleware.join(" > "),
+            telemetry = %self.telemetry.join(", "),
+            routes = %self.routes.map_or_else(|| "unknown".to_owned(), |routes| routes.to_string()),
+            "starting"
+        );
+    }
+}
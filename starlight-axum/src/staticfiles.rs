@@ -0,0 +1,372 @@
+use crate::middleware::etag::{none_match, strong_etag};
+use axum::Router;
+use axum::body::{Body, Bytes};
+use axum::extract::Request;
+use axum::http::{HeaderMap, HeaderValue, Method, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+pub const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+pub const NO_CACHE: &str = "no-cache";
+
+/// A file compiled into the binary, see [`embed_assets!`](crate::embed_assets).
+#[cfg(feature = "embed")]
+#[derive(Debug, Clone, Copy)]
+pub struct EmbeddedFile {
+    /// Relative to the asset directory, with `/` separators.
+    pub path: &'static str,
+    pub contents: &'static [u8],
+}
+
+/// Embeds the listed files of `dir`, relative to the calling crate's manifest, for
+/// [`embedded_spa_router`]:
+///
+/// ```ignore
+/// static ASSETS: &[EmbeddedFile] = embed_assets!("ui/dist", ["index.html", "assets/app.3f9a1c2e.js"]);
+/// ```
+///
+/// Pre-compressed variants (`.gz`, `.br`) are served when listed too.
+#[cfg(feature = "embed")]
+#[macro_export]
+macro_rules! embed_assets {
+    ($dir:literal, [$($file:literal),* $(,)?]) => {
+        &[$($crate::staticfiles::EmbeddedFile {
+            path: $file,
+            contents: include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/", $dir, "/", $file)),
+        }),*]
+    };
+}
+
+#[derive(Debug, Clone)]
+struct CacheRule {
+    pattern: String,
+    cache_control: HeaderValue,
+}
+
+/// How [`spa_router`] serves a single page application.
+///
+/// `Cache-Control` is taken from the first matching rule added with
+/// [`SpaConfig::with_cache_control`], then: `no-cache` for the index, `immutable` for
+/// hashed assets (a file name with a segment of 8 or more hex digits, like
+/// `app.3f9a1c2e.js` or `app-3f9a1c2e.js`), and `default_cache_control` for the rest.
+#[derive(Debug, Clone)]
+pub struct SpaConfig {
+    index: String,
+    fallback: bool,
+    precompressed: bool,
+    rules: Vec<CacheRule>,
+    default_cache_control: HeaderValue,
+}
+
+impl Default for SpaConfig {
+    fn default() -> Self {
+        SpaConfig {
+            index: "index.html".to_owned(),
+            fallback: true,
+            precompressed: true,
+            rules: Vec::new(),
+            default_cache_control: HeaderValue::from_static("public, max-age=3600"),
+        }
+    }
+}
+
+impl SpaConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_index(mut self, index: impl Into<String>) -> Self {
+        self.index = index.into();
+        self
+    }
+
+    /// Whether paths without a file extension that match no file get the index, so the
+    /// client-side router can handle them. On by default.
+    pub fn with_fallback(mut self, fallback: bool) -> Self {
+        self.fallback = fallback;
+        self
+    }
+
+    /// Whether to serve `<file>.br` or `<file>.gz` to clients accepting them. On by default.
+    pub fn with_precompressed(mut self, precompressed: bool) -> Self {
+        self.precompressed = precompressed;
+        self
+    }
+
+    /// `Cache-Control` for files matching `pattern`, where `*` matches any characters,
+    /// e.g. `fonts/*` or `*.woff2`.
+    ///
+    /// # Panics
+    ///
+    /// If `cache_control` is not a valid header value.
+    pub fn with_cache_control(mut self, pattern: impl Into<String>, cache_control: &str) -> Self {
+        self.rules.push(CacheRule {
+            pattern: pattern.into(),
+            cache_control: HeaderValue::from_str(cache_control).expect("invalid Cache-Control value"),
+        });
+        self
+    }
+
+    pub fn with_default_cache_control(mut self, cache_control: &str) -> Self {
+        self.default_cache_control = HeaderValue::from_str(cache_control).expect("invalid Cache-Control value");
+        self
+    }
+
+    fn cache_control(&self, path: &str) -> HeaderValue {
+        if let Some(rule) = self.rules.iter().find(|rule| glob_match(&rule.pattern, path)) {
+            return rule.cache_control.clone();
+        }
+        if path == self.index {
+            HeaderValue::from_static(NO_CACHE)
+        } else if is_hashed(path) {
+            HeaderValue::from_static(IMMUTABLE)
+        } else {
+            self.default_cache_control.clone()
+        }
+    }
+}
+
+/// `*` matches any run of characters, everything else itself.
+fn glob_match(pattern: &str, path: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = path.strip_prefix(first) else { return false };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// Whether the file name has a content hash segment, as bundlers add for cache busting.
+fn is_hashed(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    name.split(['.', '-', '_'])
+        .any(|segment| segment.len() >= 8 && segment.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// The `/` separated path below the asset root for a request path, or `None` when it
+/// would leave the root or is not valid UTF-8 once decoded.
+fn asset_path(request_path: &str) -> Option<String> {
+    let decoded = percent_decode(request_path)?;
+    if decoded.contains(['\\', '\0']) {
+        return None;
+    }
+    let mut parts = Vec::new();
+    for component in Path::new(decoded.trim_start_matches('/')).components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_str()?),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(parts.join("/"))
+}
+
+fn percent_decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = value.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// The pre-compressed encodings the client accepts, preferred first.
+fn accepted_encodings(headers: &HeaderMap) -> Vec<(&'static str, &'static str)> {
+    let accepted: Vec<&str> = headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter(|coding| !coding.split(';').skip(1).any(|param| param.trim() == "q=0"))
+        .map(|coding| coding.split(';').next().unwrap_or_default().trim())
+        .collect();
+    [("br", "br"), ("gzip", "gz")]
+        .into_iter()
+        .filter(|(coding, _)| accepted.contains(coding))
+        .collect()
+}
+
+enum Assets {
+    Dir(PathBuf),
+    #[cfg(feature = "embed")]
+    Embedded(&'static [EmbeddedFile]),
+}
+
+impl Assets {
+    async fn load(&self, path: &str) -> Option<Bytes> {
+        match self {
+            Assets::Dir(root) => {
+                let file = root.join(path);
+                // Symlinks may still point out of the root.
+                let canonical = tokio::fs::canonicalize(&file).await.ok()?;
+                if !canonical.starts_with(root) || !canonical.is_file() {
+                    return None;
+                }
+                tokio::fs::read(canonical).await.ok().map(Bytes::from)
+            }
+            #[cfg(feature = "embed")]
+            Assets::Embedded(files) => files
+                .iter()
+                .find(|file| file.path == path)
+                .map(|file| Bytes::from_static(file.contents)),
+        }
+    }
+}
+
+struct Spa {
+    assets: Assets,
+    config: SpaConfig,
+}
+
+impl Spa {
+    async fn serve(&self, req: Request) -> Response {
+        if req.method() != Method::GET && req.method() != Method::HEAD {
+            return (StatusCode::METHOD_NOT_ALLOWED, [(header::ALLOW, "GET, HEAD")]).into_response();
+        }
+        let Some(mut path) = asset_path(req.uri().path()) else {
+            return (StatusCode::BAD_REQUEST, "invalid path").into_response();
+        };
+        if path.is_empty() || path.ends_with('/') {
+            path.push_str(&self.config.index);
+        }
+
+        if let Some(response) = self.file(&path, req.headers()).await {
+            return response;
+        }
+        let client_route = !path.rsplit('/').next().unwrap_or_default().contains('.');
+        if self.config.fallback && client_route {
+            let index = self.config.index.clone();
+            if let Some(response) = self.file(&index, req.headers()).await {
+                return response;
+            }
+        }
+        StatusCode::NOT_FOUND.into_response()
+    }
+
+    async fn file(&self, path: &str, headers: &HeaderMap) -> Option<Response> {
+        let mut encoding = None;
+        let mut contents = None;
+        if self.config.precompressed {
+            for (coding, extension) in accepted_encodings(headers) {
+                if let Some(compressed) = self.assets.load(&format!("{}.{}", path, extension)).await {
+                    encoding = Some(coding);
+                    contents = Some(compressed);
+                    break;
+                }
+            }
+        }
+        let contents = match contents {
+            Some(contents) => contents,
+            None => self.assets.load(path).await?,
+        };
+
+        let etag = HeaderValue::from_str(&strong_etag(&contents)).expect("ETags are valid header values");
+        let mut response = if none_match(headers, &etag) {
+            StatusCode::NOT_MODIFIED.into_response()
+        } else {
+            Response::new(Body::from(contents))
+        };
+        let headers = response.headers_mut();
+        let mime = mime_guess::from_path(path).first_or_octet_stream();
+        let textual = mime.type_() == mime_guess::mime::TEXT
+            || mime.subtype() == mime_guess::mime::JAVASCRIPT
+            || mime.subtype() == mime_guess::mime::JSON;
+        let content_type = if textual {
+            format!("{}; charset=utf-8", mime.essence_str())
+        } else {
+            mime.essence_str().to_owned()
+        };
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_str(&content_type).expect("MIME types are valid"));
+        headers.insert(header::ETAG, etag);
+        headers.insert(header::CACHE_CONTROL, self.config.cache_control(path));
+        if self.config.precompressed {
+            headers.insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+        }
+        if let Some(encoding) = encoding {
+            headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static(encoding));
+        }
+        Some(response)
+    }
+}
+
+fn router(spa: Spa) -> Router {
+    let spa = Arc::new(spa);
+    Router::new().fallback(move |req: Request| {
+        let spa = spa.clone();
+        async move { spa.serve(req).await }
+    })
+}
+
+/// Serves the single page application in `dir`, e.g.
+/// `Router::new().nest_service("/ui", spa_router("ui/dist", SpaConfig::new()))`.
+///
+/// Files get their `Content-Type`, a strong `ETag` answered with 304 on revalidation,
+/// and the `Cache-Control` of the [`SpaConfig`]. Paths escaping `dir`, through `..` or
+/// symlinks, are rejected.
+///
+/// # Panics
+///
+/// If `dir` does not exist.
+pub fn spa_router(dir: impl AsRef<Path>, config: SpaConfig) -> Router {
+    let dir = dir.as_ref();
+    let root = std::fs::canonicalize(dir).unwrap_or_else(|err| panic!("cannot serve {}: {}", dir.display(), err));
+    router(Spa {
+        assets: Assets::Dir(root),
+        config,
+    })
+}
+
+/// Like [`spa_router`] for files compiled in with [`embed_assets!`](crate::embed_assets).
+#[cfg(feature = "embed")]
+pub fn embedded_spa_router(files: &'static [EmbeddedFile], config: SpaConfig) -> Router {
+    router(Spa {
+        assets: Assets::Embedded(files),
+        config,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_patterns_and_hashes() {
+        assert!(glob_match("fonts/*", "fonts/inter.woff2"));
+        assert!(glob_match("*.woff2", "fonts/inter.woff2"));
+        assert!(glob_match("assets/*/*.js", "assets/chunks/a.js"));
+        assert!(!glob_match("*.css", "app.js"));
+        assert!(!glob_match("index.html", "index.html.br"));
+
+        assert!(is_hashed("assets/app.3f9a1c2e.js"));
+        assert!(!is_hashed("assets/index-BkD2a9f0.css"));
+        assert!(is_hashed("chunk-0a1b2c3d4e.js"));
+        assert!(!is_hashed("favicon.ico"));
+    }
+
+    #[test]
+    fn keeps_paths_below_the_root() {
+        assert_eq!(asset_path("/assets/app.js").as_deref(), Some("assets/app.js"));
+        assert_eq!(asset_path("/./a/%20b").as_deref(), Some("a/ b"));
+        assert_eq!(asset_path("/../../etc/passwd"), None);
+        assert_eq!(asset_path("/assets/%2e%2e/%2e%2e/etc/passwd"), None);
+        assert_eq!(asset_path("/a%5c..%5csecret"), None);
+        assert_eq!(asset_path("/%zz"), None);
+    }
+}
@@ -0,0 +1,279 @@
+use axum::http::HeaderMap;
+use opentelemetry::global;
+use opentelemetry::propagation::Extractor;
+use opentelemetry::trace::{SpanContext, TraceContextExt};
+use opentelemetry_http::HeaderExtractor;
+use std::any::Any;
+use std::future::Future;
+use std::panic::{AssertUnwindSafe, catch_unwind, resume_unwind};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::task::JoinHandle;
+use tracing::field::Empty;
+use tracing::instrument::Instrumented;
+use tracing::{Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Spawns `future` in a span named `name`, a child of the current span, so follow-up
+/// work stays in the request's trace. A panic is recorded on the span before it
+/// propagates to the [`JoinHandle`].
+pub fn spawn_traced<F>(name: &str, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    tokio::spawn(traced_task(name, future))
+}
+
+/// Spawns `future` in a new trace, linked to the current span rather than parented by
+/// it. Use it for work that outlives the request, such as cache warming or webhooks.
+pub fn spawn_detached_traced<F>(name: &str, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let link = Span::current().context().span().span_context().clone();
+    let span = info_span!(parent: None, "task", otel.name = %name, otel.status_code = Empty);
+    if link.is_valid() {
+        span.add_link(link);
+    }
+    tokio::spawn(CatchPanic { inner: future, span: span.clone() }.instrument(span))
+}
+
+/// Spawns `future` in a new trace linked to each of `contexts`, such as the producers
+/// of a batch of messages; parenting the batch to one of them would be wrong. Invalid
+/// contexts are skipped. See [`BatchSpan`] to build the span by hand.
+pub fn spawn_linked<F>(name: &str, contexts: Vec<opentelemetry::Context>, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let span = BatchSpan::builder(name).with_contexts(contexts).build();
+    tokio::spawn(CatchPanic { inner: future, span: span.clone() }.instrument(span))
+}
+
+/// The trace context a producer propagated in `carrier`, with the global propagator.
+pub fn extract_context(carrier: &dyn Extractor) -> opentelemetry::Context {
+    global::get_text_map_propagator(|prop| prop.extract(carrier))
+}
+
+/// [`extract_context`] for HTTP headers, such as those of a message broker.
+pub fn extract_header_context(headers: &HeaderMap) -> opentelemetry::Context {
+    extract_context(&HeaderExtractor(headers))
+}
+
+/// A span for processing a batch, starting a new trace with one link per upstream
+/// context.
+///
+/// ```ignore
+/// let span = BatchSpan::builder("orders.consume")
+///     .with_headers(messages.iter().map(|message| &message.headers))
+///     .build();
+/// process(messages).instrument(span).await;
+/// ```
+#[derive(Debug, Default)]
+pub struct BatchSpan {
+    name: String,
+    links: Vec<SpanContext>,
+}
+
+impl BatchSpan {
+    pub fn builder(name: &str) -> Self {
+        BatchSpan {
+            name: name.to_owned(),
+            links: Vec::new(),
+        }
+    }
+
+    pub fn with_context(mut self, context: &opentelemetry::Context) -> Self {
+        let span_context = context.span().span_context().clone();
+        if span_context.is_valid() {
+            self.links.push(span_context);
+        }
+        self
+    }
+
+    pub fn with_contexts(self, contexts: impl IntoIterator<Item = opentelemetry::Context>) -> Self {
+        contexts
+            .into_iter()
+            .fold(self, |builder, context| builder.with_context(&context))
+    }
+
+    /// Links the context extracted from each carrier, see [`extract_context`].
+    pub fn with_carriers<'a>(self, carriers: impl IntoIterator<Item = &'a dyn Extractor>) -> Self {
+        self.with_contexts(carriers.into_iter().map(extract_context))
+    }
+
+    /// Links the context extracted from each header map, see [`extract_header_context`].
+    pub fn with_headers<'a>(self, headers: impl IntoIterator<Item = &'a HeaderMap>) -> Self {
+        self.with_contexts(headers.into_iter().map(extract_header_context))
+    }
+
+    /// The span, with `messaging.batch.message_count` set to the number of links.
+    pub fn build(self) -> Span {
+        let span = info_span!(
+            parent: None,
+            "batch",
+            otel.name = %self.name,
+            otel.status_code = Empty,
+            messaging.batch.message_count = self.links.len(),
+        );
+        for link in self.links {
+            span.add_link(link);
+        }
+        span
+    }
+}
+
+/// Wraps `future` in a span named `name` under the current span, recording panics on
+/// it. For long-running loops inside a `StarlightService::run` that are not spawned
+/// through [`spawn_traced`].
+pub fn traced_task<F: Future>(name: &str, future: F) -> Instrumented<CatchPanic<F>> {
+    let span = info_span!("task", otel.name = %name, otel.status_code = Empty);
+    CatchPanic { inner: future, span: span.clone() }.instrument(span)
+}
+
+pin_project_lite::pin_project! {
+    /// Records a panic raised while polling `inner` as an error event on the span.
+    pub struct CatchPanic<F> {
+        #[pin]
+        inner: F,
+        span: Span,
+    }
+}
+
+impl<F: Future> Future for CatchPanic<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let this = self.project();
+        let (inner, span) = (this.inner, this.span);
+        match catch_unwind(AssertUnwindSafe(|| inner.poll(cx))) {
+            Ok(poll) => poll,
+            Err(payload) => {
+                span.record("otel.status_code", "ERROR");
+                error!(parent: &*span, panic = %panic_message(&*payload), "task panicked");
+                resume_unwind(payload)
+            }
+        }
+    }
+}
+
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("non-string panic payload")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{Status, TracerProvider};
+    use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider, SpanData};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    fn capture() -> (InMemorySpanExporter, SdkTracerProvider, tracing::subscriber::DefaultGuard) {
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("task-test")));
+        (exporter, provider, tracing::subscriber::set_default(subscriber))
+    }
+
+    fn span<'a>(spans: &'a [SpanData], name: &str) -> &'a SpanData {
+        spans.iter().find(|span| span.name == name).unwrap()
+    }
+
+    #[tokio::test]
+    async fn spawned_task_is_a_child_and_detached_task_is_linked() {
+        let (exporter, provider, _guard) = capture();
+
+        async {
+            spawn_traced("audit.write", async {}).await.unwrap();
+            spawn_detached_traced("webhook.deliver", async {}).await.unwrap();
+        }
+        .instrument(info_span!("request"))
+        .await;
+        provider.force_flush().unwrap();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let request = span(&spans, "request");
+        let child = span(&spans, "audit.write");
+        let detached = span(&spans, "webhook.deliver");
+
+        assert_eq!(child.span_context.trace_id(), request.span_context.trace_id());
+        assert_eq!(child.parent_span_id, request.span_context.span_id());
+        assert_ne!(detached.span_context.trace_id(), request.span_context.trace_id());
+        let links: Vec<_> = detached.links.iter().map(|link| link.span_context.span_id()).collect();
+        assert_eq!(links, [request.span_context.span_id()]);
+    }
+
+    #[tokio::test]
+    async fn batch_span_links_every_upstream_trace() {
+        use opentelemetry::trace::{SpanId, TraceFlags, TraceId, TraceState};
+        use opentelemetry_sdk::propagation::TraceContextPropagator;
+
+        let (exporter, provider, _guard) = capture();
+        let upstream: Vec<_> = (1..=3u128)
+            .map(|i| {
+                let span_context = SpanContext::new(
+                    TraceId::from(i),
+                    SpanId::from(i as u64),
+                    TraceFlags::SAMPLED,
+                    true,
+                    TraceState::default(),
+                );
+                opentelemetry::Context::new().with_remote_span_context(span_context)
+            })
+            .collect();
+
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let mut headers = HeaderMap::new();
+        global::get_text_map_propagator(|prop| {
+            prop.inject_context(&upstream[0], &mut opentelemetry_http::HeaderInjector(&mut headers))
+        });
+        assert_eq!(
+            extract_header_context(&headers).span().span_context(),
+            upstream[0].span().span_context()
+        );
+
+        let mut contexts = upstream.clone();
+        contexts.push(opentelemetry::Context::new());
+        spawn_linked("orders.consume", contexts, async {}).await.unwrap();
+        provider.force_flush().unwrap();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let batch = span(&spans, "orders.consume");
+        let linked: Vec<_> = batch.links.iter().map(|link| link.span_context.trace_id()).collect();
+        let expected: Vec<_> = upstream
+            .iter()
+            .map(|context| context.span().span_context().trace_id())
+            .collect();
+        assert_eq!(linked, expected);
+        assert!(!expected.contains(&batch.span_context.trace_id()));
+    }
+
+    #[tokio::test]
+    async fn panic_is_recorded_on_the_span() {
+        let (exporter, provider, _guard) = capture();
+
+        let result = spawn_traced("job", async { panic!("kaboom") }).await;
+        assert!(result.unwrap_err().is_panic());
+        provider.force_flush().unwrap();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let job = span(&spans, "job");
+        assert!(matches!(job.status, Status::Error { .. }));
+        let event = job.events.iter().find(|event| event.name == "task panicked").unwrap();
+        assert!(
+            event
+                .attributes
+                .iter()
+                .any(|kv| kv.key.as_str() == "panic" && kv.value.as_str() == "kaboom")
+        );
+    }
+}
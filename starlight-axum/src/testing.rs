@@ -0,0 +1,139 @@
+//! In-memory telemetry for application tests.
+//!
+//! [`TelemetryCapture::install`] builds its own tracer, meter and logger providers and
+//! installs them as the thread's default subscriber, so tests running in parallel do
+//! not see each other's telemetry. Run the test on the current thread (the default for
+//! `#[tokio::test]`); work on other threads is not captured.
+//!
+//! [`StackProbe`] replays canned requests through a middleware stack and snapshots what
+//! it answered.
+
+use opentelemetry::metrics::{Meter, MeterProvider};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_appender_tracing::layer::OpenTelemetryTracingBridge;
+use opentelemetry_sdk::logs::{InMemoryLogExporter, SdkLogRecord, SdkLoggerProvider};
+use opentelemetry_sdk::metrics::data::{AggregatedMetrics, Metric, MetricData};
+use opentelemetry_sdk::metrics::{InMemoryMetricExporter, PeriodicReader, SdkMeterProvider};
+use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider, SpanData};
+use tracing::subscriber::DefaultGuard;
+use tracing_opentelemetry::{MetricsLayer, OpenTelemetryLayer};
+use tracing_subscriber::layer::SubscriberExt;
+
+mod probe;
+
+pub use probe::{Exchange, ProbeRequest, Scrubber, StackProbe, Transcript, UPDATE_SNAPSHOTS};
+
+pub struct TelemetryCapture {
+    spans: InMemorySpanExporter,
+    metrics: InMemoryMetricExporter,
+    logs: InMemoryLogExporter,
+    tracer_provider: SdkTracerProvider,
+    meter_provider: SdkMeterProvider,
+    logger_provider: SdkLoggerProvider,
+    _guard: DefaultGuard,
+}
+
+impl std::fmt::Debug for TelemetryCapture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TelemetryCapture").finish_non_exhaustive()
+    }
+}
+
+impl TelemetryCapture {
+    /// Captures spans, metrics recorded through `tracing` events (`monotonic_counter.*`,
+    /// `counter.*`, `histogram.*`) and through [`TelemetryCapture::meter`], and log
+    /// events, until the capture is dropped.
+    pub fn install() -> Self {
+        let spans = InMemorySpanExporter::default();
+        let metrics = InMemoryMetricExporter::default();
+        let logs = InMemoryLogExporter::default();
+
+        let tracer_provider = SdkTracerProvider::builder()
+            .with_simple_exporter(spans.clone())
+            .build();
+        let meter_provider = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(metrics.clone()).build())
+            .build();
+        let logger_provider = SdkLoggerProvider::builder()
+            .with_simple_exporter(logs.clone())
+            .build();
+
+        let subscriber = tracing_subscriber::registry()
+            .with(OpenTelemetryLayer::new(tracer_provider.tracer("starlight-testing")))
+            .with(MetricsLayer::new(meter_provider.clone()))
+            .with(OpenTelemetryTracingBridge::new(&logger_provider));
+        #[cfg(feature = "profiling")]
+        let subscriber = subscriber.with(crate::profiling::ProfiledSpans::new());
+
+        TelemetryCapture {
+            spans,
+            metrics,
+            logs,
+            tracer_provider,
+            meter_provider,
+            logger_provider,
+            _guard: tracing::subscriber::set_default(subscriber),
+        }
+    }
+
+    /// A meter backed by this capture, for layers that accept one (e.g. `SloLayer::with_meter`).
+    pub fn meter(&self) -> Meter {
+        self.meter_provider.meter("starlight-testing")
+    }
+
+    /// Finished spans, oldest first.
+    pub fn spans(&self) -> Vec<SpanData> {
+        let _ = self.tracer_provider.force_flush();
+        self.spans.get_finished_spans().unwrap_or_default()
+    }
+
+    pub fn spans_named(&self, name: &str) -> Vec<SpanData> {
+        self.spans().into_iter().filter(|span| span.name == name).collect()
+    }
+
+    /// The sum of every data point of the counter `name`, or 0 if it was never recorded.
+    pub fn metric_sum(&self, name: &str) -> f64 {
+        self.with_metric(name, |metric| match metric.data() {
+            AggregatedMetrics::U64(MetricData::Sum(sum)) => sum.data_points().map(|p| p.value() as f64).sum(),
+            AggregatedMetrics::I64(MetricData::Sum(sum)) => sum.data_points().map(|p| p.value() as f64).sum(),
+            AggregatedMetrics::F64(MetricData::Sum(sum)) => sum.data_points().map(|p| p.value()).sum(),
+            _ => 0.0,
+        })
+    }
+
+    /// The number of recordings of the histogram `name`.
+    pub fn histogram_count(&self, name: &str) -> u64 {
+        self.with_metric(name, |metric| match metric.data() {
+            AggregatedMetrics::U64(MetricData::Histogram(h)) => h.data_points().map(|p| p.count()).sum(),
+            AggregatedMetrics::I64(MetricData::Histogram(h)) => h.data_points().map(|p| p.count()).sum(),
+            AggregatedMetrics::F64(MetricData::Histogram(h)) => h.data_points().map(|p| p.count()).sum(),
+            _ => 0,
+        })
+    }
+
+    fn with_metric<T: std::iter::Sum<T>>(&self, name: &str, value: impl Fn(&Metric) -> T) -> T {
+        let _ = self.meter_provider.force_flush();
+        let exported = self.metrics.get_finished_metrics().unwrap_or_default();
+        // Cumulative temporality: the latest export holds the totals.
+        exported
+            .last()
+            .into_iter()
+            .flat_map(|resource| resource.scope_metrics())
+            .flat_map(|scope| scope.metrics())
+            .filter(|metric| metric.name() == name)
+            .map(value)
+            .sum()
+    }
+
+    /// Log records emitted through `tracing` with the given target.
+    pub fn events_with_target(&self, target: &str) -> Vec<SdkLogRecord> {
+        let _ = self.logger_provider.force_flush();
+        self.logs
+            .get_emitted_logs()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|log| log.record)
+            .filter(|record| record.target().is_some_and(|t| t == target))
+            .collect()
+    }
+}
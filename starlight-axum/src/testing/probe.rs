@@ -0,0 +1,319 @@
+use axum::Router;
+use axum::body::{Body, Bytes};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, Request};
+use http_body_util::BodyExt;
+use regex::Regex;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+use tower::ServiceExt;
+
+/// Set to `1` to rewrite snapshots that differ instead of failing.
+pub const UPDATE_SNAPSHOTS: &str = "STARLIGHT_UPDATE_SNAPSHOTS";
+
+/// A canned request replayed by [`StackProbe`].
+#[derive(Debug, Clone)]
+pub struct ProbeRequest {
+    method: Method,
+    path: String,
+    headers: HeaderMap,
+    body: Bytes,
+    fields: Vec<String>,
+}
+
+impl ProbeRequest {
+    pub fn new(method: Method, path: impl Into<String>) -> Self {
+        ProbeRequest {
+            method,
+            path: path.into(),
+            headers: HeaderMap::new(),
+            body: Bytes::new(),
+            fields: Vec::new(),
+        }
+    }
+
+    pub fn get(path: impl Into<String>) -> Self {
+        Self::new(Method::GET, path)
+    }
+
+    pub fn post(path: impl Into<String>) -> Self {
+        Self::new(Method::POST, path)
+    }
+
+    /// Panics on a header that is not valid, as tests want to know.
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        let name = HeaderName::try_from(name).expect("a valid header name");
+        let value = HeaderValue::try_from(value).expect("a valid header value");
+        self.headers.append(name, value);
+        self
+    }
+
+    pub fn with_body(mut self, body: impl Into<Bytes>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// `value` as the body, with `content-type: application/json`.
+    pub fn with_json(self, value: &impl Serialize) -> Self {
+        let body = serde_json::to_vec(value).expect("the body serializes to JSON");
+        self.with_header("content-type", "application/json").with_body(body)
+    }
+
+    /// A part of the response body to keep in the transcript, as a JSON pointer such as
+    /// `/status`; `""` keeps the whole body. Bodies which are not JSON are a string.
+    pub fn with_field(mut self, pointer: impl Into<String>) -> Self {
+        self.fields.push(pointer.into());
+        self
+    }
+
+    fn into_request(self) -> Request<Body> {
+        let mut request = Request::builder()
+            .method(self.method)
+            .uri(self.path)
+            .body(Body::from(self.body))
+            .expect("a valid request");
+        *request.headers_mut() = self.headers;
+        request
+    }
+}
+
+/// Replaces what matches `pattern` in header values and body fields, so values that
+/// change on every run do not break snapshots.
+#[derive(Debug, Clone)]
+pub struct Scrubber {
+    pattern: Regex,
+    replacement: String,
+}
+
+impl Scrubber {
+    /// Panics when `pattern` is not a valid regular expression.
+    pub fn new(pattern: &str, replacement: impl Into<String>) -> Self {
+        Scrubber {
+            pattern: Regex::new(pattern).expect("a valid scrubber pattern"),
+            replacement: replacement.into(),
+        }
+    }
+
+    /// `traceparent` values, UUIDs such as request ids, HTTP and RFC 3339 dates, and
+    /// trace and span ids, i.e. 32 and 16 lowercase hex digits.
+    pub fn defaults() -> Vec<Scrubber> {
+        vec![
+            Scrubber::new(r"\b[0-9a-f]{2}-[0-9a-f]{32}-[0-9a-f]{16}-[0-9a-f]{2}\b", "[traceparent]"),
+            Scrubber::new(
+                r"\b[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}\b",
+                "[uuid]",
+            ),
+            Scrubber::new(
+                r"\b(Mon|Tue|Wed|Thu|Fri|Sat|Sun), \d{2} [A-Z][a-z]{2} \d{4} \d{2}:\d{2}:\d{2} GMT",
+                "[date]",
+            ),
+            Scrubber::new(
+                r"\b\d{4}-\d{2}-\d{2}[Tt ]\d{2}:\d{2}:\d{2}(\.\d+)?([Zz]|[+-]\d{2}:\d{2})",
+                "[timestamp]",
+            ),
+            Scrubber::new(r"\b[0-9a-f]{32}\b", "[trace_id]"),
+            Scrubber::new(r"\b[0-9a-f]{16}\b", "[span_id]"),
+        ]
+    }
+
+    fn scrub(&self, value: &str) -> String {
+        self.pattern.replace_all(value, self.replacement.as_str()).into_owned()
+    }
+}
+
+/// One request of a [`Transcript`] and what the stack answered.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Exchange {
+    pub method: String,
+    pub path: String,
+    pub request_headers: BTreeMap<String, String>,
+    pub status: u16,
+    pub response_headers: BTreeMap<String, String>,
+    /// The [fields](ProbeRequest::with_field) kept, by pointer.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub body: BTreeMap<String, Value>,
+}
+
+/// What a [`StackProbe`] observed, scrubbed and in a stable order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Transcript {
+    pub exchanges: Vec<Exchange>,
+}
+
+impl Transcript {
+    /// Pretty printed JSON, ending with a newline.
+    pub fn to_json(&self) -> String {
+        let mut json = serde_json::to_string_pretty(self).expect("transcripts serialize to JSON");
+        json.push('\n');
+        json
+    }
+
+    /// Compares the transcript with the snapshot at `path`, relative to the package
+    /// root under `cargo test`.
+    ///
+    /// A missing or different snapshot fails the assertion and leaves the new transcript
+    /// next to it as `<path>.new` to review, unless [`UPDATE_SNAPSHOTS`] is `1`, which
+    /// writes the snapshot instead.
+    pub fn assert_snapshot(&self, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+        let actual = self.to_json();
+        let expected = std::fs::read_to_string(path).ok();
+        if expected.as_deref() == Some(actual.as_str()) {
+            return;
+        }
+        if std::env::var(UPDATE_SNAPSHOTS).is_ok_and(|update| update == "1") {
+            std::fs::write(path, actual).expect("the snapshot is written");
+            return;
+        }
+        let mut new = path.as_os_str().to_owned();
+        new.push(".new");
+        std::fs::write(&new, &actual).expect("the new snapshot is written");
+        match expected {
+            None => panic!(
+                "no snapshot at {}; review {} and rename it, or rerun with {}=1",
+                path.display(),
+                Path::new(&new).display(),
+                UPDATE_SNAPSHOTS
+            ),
+            Some(expected) => panic!(
+                "the transcript differs from {}\n--- expected\n{}+++ actual\n{}\nrerun with {}=1 to accept it",
+                path.display(),
+                expected,
+                actual,
+                UPDATE_SNAPSHOTS
+            ),
+        }
+    }
+}
+
+/// Replays canned requests through a router, usually one wrapped in the service's
+/// middleware, and records a [`Transcript`] to assert with
+/// [`Transcript::assert_snapshot`].
+///
+/// ```ignore
+/// let app = StackBuilder::recommended(Duration::from_secs(5)).build(routes)?;
+/// StackProbe::new(app)
+///     .with_request(ProbeRequest::get("/health").with_field("/status"))
+///     .with_request(ProbeRequest::post("/orders").with_json(&order))
+///     .run()
+///     .await
+///     .assert_snapshot("tests/snapshots/stack.json");
+/// ```
+///
+/// Requests are sent one after the other, without a connection. Header values and
+/// string body fields go through the [default scrubbers](Scrubber::defaults) and those
+/// added with [`StackProbe::with_scrubber`], in order.
+#[derive(Debug, Clone)]
+pub struct StackProbe {
+    router: Router,
+    requests: Vec<ProbeRequest>,
+    scrubbers: Vec<Scrubber>,
+}
+
+impl StackProbe {
+    pub fn new(router: Router) -> Self {
+        StackProbe {
+            router,
+            requests: Vec::new(),
+            scrubbers: Scrubber::defaults(),
+        }
+    }
+
+    pub fn with_request(mut self, request: ProbeRequest) -> Self {
+        self.requests.push(request);
+        self
+    }
+
+    pub fn with_scrubber(mut self, scrubber: Scrubber) -> Self {
+        self.scrubbers.push(scrubber);
+        self
+    }
+
+    pub fn without_default_scrubbers(mut self) -> Self {
+        self.scrubbers.clear();
+        self
+    }
+
+    pub async fn run(mut self) -> Transcript {
+        let requests = std::mem::take(&mut self.requests);
+        let mut exchanges = Vec::with_capacity(requests.len());
+        for request in requests {
+            let method = request.method.to_string();
+            let path = request.path.clone();
+            let request_headers = self.headers(&request.headers);
+            let fields = request.fields.clone();
+            let response = self
+                .router
+                .clone()
+                .oneshot(request.into_request())
+                .await
+                .unwrap_or_else(|err| match err {});
+            let status = response.status().as_u16();
+            let response_headers = self.headers(response.headers());
+            let bytes = response
+                .into_body()
+                .collect()
+                .await
+                .expect("the response body is read")
+                .to_bytes();
+            let body = if fields.is_empty() {
+                BTreeMap::new()
+            } else {
+                let value = serde_json::from_slice(&bytes)
+                    .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()));
+                fields
+                    .into_iter()
+                    .map(|pointer| {
+                        let field = value.pointer(&pointer).cloned().unwrap_or(Value::Null);
+                        (pointer, self.scrub_value(field))
+                    })
+                    .collect()
+            };
+            exchanges.push(Exchange {
+                method,
+                path,
+                request_headers,
+                status,
+                response_headers,
+                body,
+            });
+        }
+        Transcript { exchanges }
+    }
+
+    fn scrub(&self, value: &str) -> String {
+        self.scrubbers
+            .iter()
+            .fold(value.to_owned(), |value, scrubber| scrubber.scrub(&value))
+    }
+
+    fn scrub_value(&self, value: Value) -> Value {
+        match value {
+            Value::String(string) => Value::String(self.scrub(&string)),
+            Value::Array(items) => Value::Array(items.into_iter().map(|item| self.scrub_value(item)).collect()),
+            Value::Object(fields) => Value::Object(
+                fields
+                    .into_iter()
+                    .map(|(key, field)| (key, self.scrub_value(field)))
+                    .collect(),
+            ),
+            other => other,
+        }
+    }
+
+    /// Lowercase names in order, the values of repeated headers joined with `, `.
+    fn headers(&self, headers: &HeaderMap) -> BTreeMap<String, String> {
+        let mut map: BTreeMap<String, String> = BTreeMap::new();
+        for (name, value) in headers {
+            let value = self.scrub(&String::from_utf8_lossy(value.as_bytes()));
+            map.entry(name.to_string())
+                .and_modify(|joined| {
+                    joined.push_str(", ");
+                    joined.push_str(&value);
+                })
+                .or_insert(value);
+        }
+        map
+    }
+}
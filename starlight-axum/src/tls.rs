@@ -0,0 +1,176 @@
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier};
+use rustls::sign::CertifiedKey;
+use rustls::{RootCertStore, ServerConfig};
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio_rustls::TlsAcceptor;
+
+/// Where a PEM document comes from. Only `Path` sources take part in hot-reload.
+#[derive(Debug, Clone)]
+pub enum PemSource {
+    Path(PathBuf),
+    Bytes(Vec<u8>),
+}
+
+impl PemSource {
+    fn read(&self) -> io::Result<Vec<u8>> {
+        match self {
+            PemSource::Path(path) => std::fs::read(path).map_err(|err| {
+                io::Error::new(err.kind(), format!("failed to read {}: {}", path.display(), err))
+            }),
+            PemSource::Bytes(bytes) => Ok(bytes.clone()),
+        }
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        match self {
+            PemSource::Path(path) => std::fs::metadata(path).and_then(|m| m.modified()).ok(),
+            PemSource::Bytes(_) => None,
+        }
+    }
+}
+
+/// TLS settings for [`crate::serve::serve_tls`].
+///
+/// ALPN always advertises `h2` and `http/1.1`. When the certificate or key is loaded
+/// from a file, the files are re-stat'ed every `reload_interval` and the served
+/// certificate is swapped in place when either one changes.
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    cert: PemSource,
+    key: PemSource,
+    client_ca: Option<PemSource>,
+    reload_interval: Duration,
+}
+
+impl TlsConfig {
+    pub fn from_pem_files(cert: impl Into<PathBuf>, key: impl Into<PathBuf>) -> Self {
+        Self::new(PemSource::Path(cert.into()), PemSource::Path(key.into()))
+    }
+
+    pub fn from_pem(cert: impl Into<Vec<u8>>, key: impl Into<Vec<u8>>) -> Self {
+        Self::new(PemSource::Bytes(cert.into()), PemSource::Bytes(key.into()))
+    }
+
+    pub fn new(cert: PemSource, key: PemSource) -> Self {
+        TlsConfig {
+            cert,
+            key,
+            client_ca: None,
+            reload_interval: Duration::from_secs(30),
+        }
+    }
+
+    /// Require clients to present a certificate signed by one of the CAs in the bundle (mTLS).
+    pub fn with_client_ca(mut self, ca_bundle: PemSource) -> Self {
+        self.client_ca = Some(ca_bundle);
+        self
+    }
+
+    pub fn with_reload_interval(mut self, interval: Duration) -> Self {
+        self.reload_interval = interval;
+        self
+    }
+
+    /// Build the acceptor and, for file based certificates, spawn the reload watcher.
+    /// Must be called from within a tokio runtime.
+    pub(crate) fn into_acceptor(self) -> io::Result<TlsAcceptor> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let resolver = Arc::new(ReloadableCertResolver {
+            current: RwLock::new(Arc::new(load_certified_key(&self.cert, &self.key, &provider)?)),
+        });
+
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(invalid_data)?;
+        let builder = match &self.client_ca {
+            Some(ca_bundle) => {
+                let mut roots = RootCertStore::empty();
+                for cert in load_certs(ca_bundle)? {
+                    roots.add(cert).map_err(invalid_data)?;
+                }
+                let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+                    .build()
+                    .map_err(invalid_data)?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        let mut server_config = builder.with_cert_resolver(resolver.clone());
+        server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        if matches!(self.cert, PemSource::Path(_)) || matches!(self.key, PemSource::Path(_)) {
+            tokio::spawn(watch_certificate(self, resolver, provider));
+        }
+
+        Ok(TlsAcceptor::from(Arc::new(server_config)))
+    }
+}
+
+#[derive(Debug)]
+struct ReloadableCertResolver {
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl ResolvesServerCert for ReloadableCertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap_or_else(|e| e.into_inner()).clone())
+    }
+}
+
+async fn watch_certificate(
+    config: TlsConfig,
+    resolver: Arc<ReloadableCertResolver>,
+    provider: Arc<CryptoProvider>,
+) {
+    let mut last_seen = (config.cert.modified(), config.key.modified());
+    let mut interval = tokio::time::interval(config.reload_interval);
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+        let seen = (config.cert.modified(), config.key.modified());
+        if seen == last_seen {
+            continue;
+        }
+
+        match load_certified_key(&config.cert, &config.key, &provider) {
+            Ok(key) => {
+                *resolver.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(key);
+                last_seen = seen;
+                info!("reloaded TLS certificate");
+            }
+            // Keep serving the previous certificate; the files may be mid-rotation.
+            Err(err) => warn!("failed to reload TLS certificate: {}", err),
+        }
+    }
+}
+
+fn load_certified_key(
+    cert: &PemSource,
+    key: &PemSource,
+    provider: &CryptoProvider,
+) -> io::Result<CertifiedKey> {
+    let certs = load_certs(cert)?;
+    if certs.is_empty() {
+        return Err(invalid_data("no certificate found in PEM input"));
+    }
+    let key = PrivateKeyDer::from_pem_slice(&key.read()?).map_err(invalid_data)?;
+    CertifiedKey::from_der(certs, key, provider).map_err(invalid_data)
+}
+
+fn load_certs(source: &PemSource) -> io::Result<Vec<CertificateDer<'static>>> {
+    CertificateDer::pem_slice_iter(&source.read()?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(invalid_data)
+}
+
+fn invalid_data<E: ToString>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
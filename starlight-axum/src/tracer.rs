@@ -1,5 +1,5 @@
+use crate::oltp::{FailoverExporter, FailoverPolicy, HeadersConfig};
 use crate::resource::get_resource;
-use opentelemetry_otlp::WithExportConfig;
 use opentelemetry_sdk::trace::{RandomIdGenerator, Sampler, SdkTracerProvider};
 use std::sync::OnceLock;
 
@@ -10,14 +10,29 @@ pub fn get_tracer_provider() -> &'static SdkTracerProvider {
         .expect("Failed to get tracer provider")
 }
 
+pub(crate) fn try_get_tracer_provider() -> Option<&'static SdkTracerProvider> {
+    SDK_TRACER_PROVIDER.get()
+}
+
 pub fn get_or_init_tracer_provider(oltp_grpc_url: &str) -> SdkTracerProvider {
+    get_or_init_tracer_provider_with_failover(&[oltp_grpc_url.to_owned()], FailoverPolicy::default())
+}
+
+/// Same as [`get_or_init_tracer_provider`], failing over between `endpoints` in order.
+pub fn get_or_init_tracer_provider_with_failover(endpoints: &[String], policy: FailoverPolicy) -> SdkTracerProvider {
+    get_or_init_tracer_provider_with_headers(endpoints, policy, &HeadersConfig::default())
+}
+
+/// Same as [`get_or_init_tracer_provider_with_failover`], sending `headers` to the collector.
+pub fn get_or_init_tracer_provider_with_headers(
+    endpoints: &[String],
+    policy: FailoverPolicy,
+    headers: &HeadersConfig,
+) -> SdkTracerProvider {
     SDK_TRACER_PROVIDER
         .get_or_init(|| {
-            let exporter = opentelemetry_otlp::SpanExporter::builder()
-                .with_tonic()
-                .with_endpoint(oltp_grpc_url)
-                .build()
-                .expect("Failed to create exporter");
+            let exporter =
+                FailoverExporter::spans_with_headers(endpoints, policy, headers).expect("Failed to create exporter");
 
             SdkTracerProvider::builder()
                 .with_resource(get_resource())
@@ -0,0 +1,292 @@
+use crate::health::{InFlightGuard, InFlightTracker};
+use crate::meter::GLOBAL_METER;
+use axum::Error;
+use axum::extract::ws::WebSocketUpgrade;
+use axum::extract::ws::rejection::WebSocketUpgradeRejection;
+use axum::extract::{FromRequestParts, MatchedPath};
+use axum::http::request::Parts;
+use axum::response::Response;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Counter, Meter, UpDownCounter};
+use starlight_tokio::CancellationToken;
+use std::future::Future;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+pub use axum::extract::ws::{CloseFrame, Message, Utf8Bytes, close_code};
+
+pub const WEBSOCKET_CONNECTIONS: &str = "websocket.server.connections";
+pub const WEBSOCKET_MESSAGES: &str = "websocket.server.messages";
+
+#[derive(Debug, Clone)]
+struct Config {
+    close: CloseFrame,
+    close_timeout: Duration,
+    max_message_bytes: usize,
+    connections: UpDownCounter<i64>,
+    messages: Counter<u64>,
+}
+
+/// Settings of [`GracefulWebSocket`], installed for a router with
+/// `.layer(Extension(WebSocketConfig::new()))`; the defaults apply without one.
+#[derive(Debug, Clone)]
+pub struct WebSocketConfig {
+    config: Arc<Config>,
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        Self::with_meter(&GLOBAL_METER)
+    }
+}
+
+impl WebSocketConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_meter(meter: &Meter) -> Self {
+        WebSocketConfig {
+            config: Arc::new(Config {
+                close: CloseFrame {
+                    code: close_code::AWAY,
+                    reason: Utf8Bytes::from_static("server shutting down"),
+                },
+                close_timeout: Duration::from_secs(5),
+                max_message_bytes: 16 * 1024 * 1024,
+                connections: meter
+                    .i64_up_down_counter(WEBSOCKET_CONNECTIONS)
+                    .with_description("Open WebSocket connections")
+                    .with_unit("{connection}")
+                    .build(),
+                messages: meter
+                    .u64_counter(WEBSOCKET_MESSAGES)
+                    .with_description("WebSocket messages received and sent")
+                    .with_unit("{message}")
+                    .build(),
+            }),
+        }
+    }
+
+    /// The Close frame sent when shutdown starts, 1001 "server shutting down" by default.
+    pub fn with_close(mut self, code: u16, reason: impl Into<Utf8Bytes>) -> Self {
+        Arc::make_mut(&mut self.config).close = CloseFrame {
+            code,
+            reason: reason.into(),
+        };
+        self
+    }
+
+    /// How long [`WebSocket::close`] waits for the client's Close frame, 5 seconds by
+    /// default.
+    pub fn with_close_timeout(mut self, close_timeout: Duration) -> Self {
+        Arc::make_mut(&mut self.config).close_timeout = close_timeout;
+        self
+    }
+
+    /// Larger messages fail the connection, 16 MiB by default.
+    pub fn with_max_message_bytes(mut self, max_message_bytes: usize) -> Self {
+        Arc::make_mut(&mut self.config).max_message_bytes = max_message_bytes;
+        self
+    }
+}
+
+fn default_config() -> WebSocketConfig {
+    static DEFAULT: OnceLock<WebSocketConfig> = OnceLock::new();
+    DEFAULT.get_or_init(WebSocketConfig::new).clone()
+}
+
+/// Axum's [`WebSocketUpgrade`] for sockets that close properly when the server shuts
+/// down:
+///
+/// ```ignore
+/// async fn echo(ws: GracefulWebSocket) -> Response {
+///     ws.on_upgrade(|socket| async move {
+///         let _ = socket.run(|message| async move { Some(message) }).await;
+///     })
+/// }
+/// ```
+///
+/// Behind an [`InFlightTracker`] layer every connection is counted as in flight until it
+/// closes, so the server waits for it on shutdown, and [`WebSocket::run`] answers the
+/// start of the drain with the Close frame of the [`WebSocketConfig`]. Open connections
+/// and messages are counted on `websocket.server.connections` and
+/// `websocket.server.messages`, labelled `http.route`.
+pub struct GracefulWebSocket {
+    upgrade: WebSocketUpgrade,
+    tracker: Option<InFlightTracker>,
+    route: String,
+    config: WebSocketConfig,
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for GracefulWebSocket {
+    type Rejection = WebSocketUpgradeRejection;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let upgrade = WebSocketUpgrade::from_request_parts(parts, state).await?;
+        Ok(GracefulWebSocket {
+            upgrade,
+            tracker: parts.extensions.get::<InFlightTracker>().cloned(),
+            route: parts
+                .extensions
+                .get::<MatchedPath>()
+                .map_or_else(|| parts.uri.path().to_owned(), |path| path.as_str().to_owned()),
+            config: parts.extensions.get::<WebSocketConfig>().cloned().unwrap_or_else(default_config),
+        })
+    }
+}
+
+impl GracefulWebSocket {
+    /// The Close frame sent on shutdown for this connection.
+    pub fn with_close(mut self, code: u16, reason: impl Into<Utf8Bytes>) -> Self {
+        self.config = self.config.with_close(code, reason);
+        self
+    }
+
+    /// Answers the upgrade and runs `callback` with the socket once it completes.
+    pub fn on_upgrade<F, Fut>(self, callback: F) -> Response
+    where
+        F: FnOnce(WebSocket) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let GracefulWebSocket {
+            upgrade,
+            tracker,
+            route,
+            config,
+        } = self;
+        let guard = tracker.as_ref().map(InFlightTracker::track);
+        let shutdown = tracker.map_or_else(CancellationToken::new, |tracker| tracker.shutdown_token());
+        let failed_route = route.clone();
+        upgrade
+            .max_message_size(config.config.max_message_bytes)
+            .on_failed_upgrade(move |err| debug!("WebSocket upgrade of {} failed: {}", failed_route, err))
+            .on_upgrade(move |socket| async move {
+                let connection = Connection::open(config.clone(), route, guard);
+                callback(WebSocket {
+                    socket,
+                    shutdown,
+                    config,
+                    connection,
+                })
+                .await;
+            })
+    }
+}
+
+/// Counts the connection while it is open.
+struct Connection {
+    config: WebSocketConfig,
+    route: KeyValue,
+    _guard: Option<InFlightGuard>,
+}
+
+impl Connection {
+    fn open(config: WebSocketConfig, route: String, guard: Option<InFlightGuard>) -> Self {
+        let route = KeyValue::new("http.route", route);
+        config.config.connections.add(1, std::slice::from_ref(&route));
+        Connection {
+            config,
+            route,
+            _guard: guard,
+        }
+    }
+
+    fn message(&self, direction: &'static str) {
+        let attributes = [self.route.clone(), KeyValue::new("direction", direction)];
+        self.config.config.messages.add(1, &attributes);
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.config.config.connections.add(-1, std::slice::from_ref(&self.route));
+    }
+}
+
+/// An upgraded connection, see [`GracefulWebSocket`].
+pub struct WebSocket {
+    socket: axum::extract::ws::WebSocket,
+    shutdown: CancellationToken,
+    config: WebSocketConfig,
+    connection: Connection,
+}
+
+impl WebSocket {
+    /// Cancelled when the server starts shutting down, for handlers with a loop of their
+    /// own; [`WebSocket::run`] watches it already.
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    /// The next message, `None` once the connection is closed. Pings and a Close frame
+    /// from the client are answered by axum.
+    pub async fn recv(&mut self) -> Option<Result<Message, Error>> {
+        let message = self.socket.recv().await;
+        if let Some(Ok(Message::Text(_) | Message::Binary(_))) = message {
+            self.connection.message("received");
+        }
+        message
+    }
+
+    pub async fn send(&mut self, message: Message) -> Result<(), Error> {
+        let counted = matches!(message, Message::Text(_) | Message::Binary(_));
+        self.socket.send(message).await?;
+        if counted {
+            self.connection.message("sent");
+        }
+        Ok(())
+    }
+
+    /// Sends a Close frame, waits for the client's for up to the close timeout and
+    /// closes the connection.
+    pub async fn close(mut self, code: u16, reason: impl Into<Utf8Bytes>) -> Result<(), Error> {
+        let close = CloseFrame {
+            code,
+            reason: reason.into(),
+        };
+        self.socket.send(Message::Close(Some(close))).await?;
+        let close_timeout = self.config.config.close_timeout;
+        let closed = async {
+            while let Some(Ok(message)) = self.recv().await {
+                if let Message::Close(_) = message {
+                    break;
+                }
+            }
+        };
+        if tokio::time::timeout(close_timeout, closed).await.is_err() {
+            debug!("WebSocket client did not close within {:?}", close_timeout);
+        }
+        Ok(())
+    }
+
+    /// Answers text and binary messages with `handler` until the client closes the
+    /// connection or the server shuts down, when it sends the configured Close frame.
+    pub async fn run<F, Fut>(mut self, mut handler: F) -> Result<(), Error>
+    where
+        F: FnMut(Message) -> Fut,
+        Fut: Future<Output = Option<Message>>,
+    {
+        let shutdown = self.shutdown.clone();
+        loop {
+            let message = tokio::select! {
+                biased;
+                _ = shutdown.cancelled() => break,
+                message = self.recv() => message,
+            };
+            match message {
+                Some(Ok(message @ (Message::Text(_) | Message::Binary(_)))) => {
+                    if let Some(reply) = handler(message).await {
+                        self.send(reply).await?;
+                    }
+                }
+                // Axum sends the reply to the client's Close frame on the next read.
+                Some(Ok(_)) => {}
+                None => return Ok(()),
+                Some(Err(err)) => return Err(err),
+            }
+        }
+        let close = self.config.config.close.clone();
+        self.close(close.code, close.reason).await
+    }
+}
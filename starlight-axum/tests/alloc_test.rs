@@ -0,0 +1,44 @@
+use starlight_axum::alloc::{AllocationLayer, AllocationStats, CountingAlloc};
+use starlight_axum::axum::Router;
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::{Request, StatusCode};
+use starlight_axum::axum::routing::get;
+use starlight_axum::tower::ServiceExt;
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc::system();
+
+const TEN_MB: u64 = 10 * 1024 * 1024;
+
+fn app() -> Router {
+    Router::new()
+        .route(
+            "/balloon",
+            get(|| async {
+                let buffer = std::hint::black_box(vec![1u8; TEN_MB as usize]);
+                buffer.iter().map(|byte| *byte as u64).sum::<u64>().to_string()
+            }),
+        )
+        .route("/small", get(|| async { "ok" }))
+        .layer(AllocationLayer::new().with_warn_threshold(TEN_MB))
+}
+
+async fn stats(path: &str) -> AllocationStats {
+    let response = app().oneshot(Request::get(path).body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    *response.extensions().get::<AllocationStats>().unwrap()
+}
+
+#[tokio::test]
+async fn records_what_the_handler_allocates() {
+    assert!(CountingAlloc::<std::alloc::System>::is_installed());
+
+    let balloon = stats("/balloon").await;
+    // Frees of memory the request did not allocate lower the peak a little.
+    assert!(balloon.peak_bytes >= TEN_MB * 9 / 10, "{:?}", balloon);
+    assert!(balloon.peak_bytes < 2 * TEN_MB, "{:?}", balloon);
+    assert!(balloon.allocated_bytes >= TEN_MB, "{:?}", balloon);
+
+    let small = stats("/small").await;
+    assert!(small.allocated_bytes < 1024 * 1024, "{:?}", small);
+}
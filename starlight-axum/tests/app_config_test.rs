@@ -0,0 +1,157 @@
+use starlight_axum::Listener;
+use starlight_axum::config::{
+    AppConfig, AppConfigError, CorsSettings, MaintenanceSettings, RateLimitConfig, ServerConfig, TelemetryConfig,
+    TimeoutConfig, validate,
+};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const FIXTURE: &str = "tests/fixtures/app.toml";
+
+fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+    vars.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+}
+
+/// A config file with `contents`, removed when dropped.
+struct TempConfig(PathBuf);
+
+impl TempConfig {
+    fn new(name: &str, contents: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("{}-{}", std::process::id(), name));
+        std::fs::File::create(&path).unwrap().write_all(contents.as_bytes()).unwrap();
+        TempConfig(path)
+    }
+}
+
+impl Drop for TempConfig {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+#[test]
+fn loads_the_file_with_environment_overrides() {
+    let config = AppConfig::load_with_env(
+        FIXTURE,
+        env(&[("COLLECTOR_HOST", "otel-collector"), ("STARLIGHT__SERVER__BIND", "127.0.0.1:9090"), ("UNRELATED", "x")]),
+    )
+    .unwrap();
+
+    assert_eq!(
+        config,
+        AppConfig {
+            telemetry: TelemetryConfig {
+                service_name: Some("orders".to_owned()),
+                service_version: Some("1.4.0".to_owned()),
+                environment: Some("staging".to_owned()),
+                otlp_endpoint: "http://otel-collector:4317".to_owned(),
+                rust_log: Some("info,tower_http=debug".to_owned()),
+                ..TelemetryConfig::default()
+            },
+            server: ServerConfig {
+                bind: "127.0.0.1:9090".parse().unwrap(),
+                ..ServerConfig::default()
+            },
+            cors: CorsSettings {
+                allowed_origins: vec!["https://app.example.com".to_owned(), "https://*.example.dev".to_owned()],
+                allowed_methods: Some(vec!["GET".to_owned(), "POST".to_owned()]),
+                allow_credentials: true,
+                max_age_secs: Some(600),
+                ..CorsSettings::default()
+            },
+            rate_limit: RateLimitConfig {
+                requests_per_second: Some(200),
+                burst: None,
+            },
+            timeouts: TimeoutConfig {
+                request_secs: 10,
+                client_secs: 30,
+            },
+            maintenance: MaintenanceSettings::default(),
+        }
+    );
+    assert_eq!(validate(&config.telemetry), Ok(()));
+    assert!(matches!(config.server.listener(), Listener::Tcp(addr, _) if addr.port() == 9090));
+    assert_eq!(config.timeouts.request(), Duration::from_secs(10));
+    let _layer = config.cors.cors_config().unwrap().into_layer().unwrap();
+}
+
+#[test]
+fn yaml_files_load_like_toml() {
+    let vars = env(&[("COLLECTOR_HOST", "otel-collector"), ("STARLIGHT__TIMEOUTS__CLIENT_SECS", "5")]);
+    let yaml = AppConfig::load_with_env("tests/fixtures/app.yaml", vars.clone()).unwrap();
+    assert_eq!(yaml, AppConfig::load_with_env(FIXTURE, vars).unwrap());
+    assert_eq!(yaml.timeouts.client(), Duration::from_secs(5));
+
+    let file = TempConfig::new("mismatch.yml", "timeouts:\n  request_secs: ten\n");
+    let error = AppConfig::load_with_env(&file.0, env(&[])).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        format!("{}: timeouts.request_secs: invalid type: string \"ten\", expected u64", file.0.display())
+    );
+
+    let file = TempConfig::new("syntax.yaml", "server:\n  bind: [0.0.0.0:80\n");
+    let error = AppConfig::load_with_env(&file.0, env(&[])).unwrap_err();
+    assert!(matches!(error, AppConfigError::Syntax { line: 3, .. }), "{}", error);
+}
+
+#[test]
+fn environment_overrides_are_typed_like_the_field() {
+    let config = AppConfig::load_with_env(
+        FIXTURE,
+        env(&[
+            ("COLLECTOR_HOST", "collector"),
+            ("DEPLOY_ENV", "production"),
+            ("STARLIGHT__CORS__ALLOWED_ORIGINS", "https://a.example.com, https://b.example.com"),
+            ("STARLIGHT__CORS__ALLOW_CREDENTIALS", "false"),
+            ("STARLIGHT__RATE_LIMIT__BURST", "50"),
+            ("STARLIGHT__TELEMETRY__SERVICE_VERSION", "2.0"),
+        ]),
+    )
+    .unwrap();
+
+    assert_eq!(config.telemetry.environment.as_deref(), Some("production"));
+    assert_eq!(config.cors.allowed_origins, ["https://a.example.com", "https://b.example.com"]);
+    assert!(!config.cors.allow_credentials);
+    assert_eq!(config.rate_limit.burst, Some(50));
+    assert_eq!(config.telemetry.service_version.as_deref(), Some("2.0"));
+}
+
+#[test]
+fn type_mismatches_name_the_file_and_key() {
+    let file = TempConfig::new("mismatch.toml", "[timeouts]\nrequest_secs = \"ten\"\n");
+    let error = AppConfig::load_with_env(&file.0, env(&[])).unwrap_err();
+    assert!(matches!(&error, AppConfigError::Invalid { key, variable: None, .. } if key == "timeouts.request_secs"));
+    assert_eq!(
+        error.to_string(),
+        format!("{}: timeouts.request_secs: invalid type: string \"ten\", expected u64", file.0.display())
+    );
+
+    let error = AppConfig::load_with_env(FIXTURE, env(&[("COLLECTOR_HOST", "c"), ("STARLIGHT__SERVER__BIND", "localhost")])).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "tests/fixtures/app.toml: server.bind (from STARLIGHT__SERVER__BIND): invalid socket address syntax"
+    );
+}
+
+#[test]
+fn other_problems_name_the_file() {
+    let error = AppConfig::load_with_env(FIXTURE, env(&[])).unwrap_err();
+    assert_eq!(error.to_string(), "tests/fixtures/app.toml:6: environment variable COLLECTOR_HOST is not set");
+
+    let file = TempConfig::new("typo.toml", "[server]\nbnid = \"0.0.0.0:80\"\n");
+    let error = AppConfig::load_with_env(&file.0, env(&[])).unwrap_err();
+    assert!(error.to_string().ends_with("server.bnid: unknown field `bnid`, expected one of `bind`, `unix_socket`, `socket_mode`"), "{}", error);
+
+    let file = TempConfig::new("syntax.toml", "[server]\nbind = 0.0.0.0:80\n");
+    let error = AppConfig::load_with_env(&file.0, env(&[])).unwrap_err();
+    assert!(matches!(error, AppConfigError::Syntax { line: 2, .. }), "{}", error);
+
+    let file = TempConfig::new("cors.json", r#"{"cors": {"allowed_methods": ["GET", "NOT A METHOD"]}}"#);
+    let error = AppConfig::load_with_env(&file.0, env(&[])).unwrap_err();
+    assert!(error.to_string().ends_with("cors: invalid CORS configuration: invalid method \"NOT A METHOD\""), "{}", error);
+
+    let error = AppConfig::load_with_env(Path::new("tests/fixtures/missing.toml"), env(&[])).unwrap_err();
+    assert!(matches!(error, AppConfigError::Read { .. }));
+}
@@ -0,0 +1,95 @@
+use opentelemetry::{Array, Key, KeyValue, StringValue, Value};
+use starlight_axum::attrs;
+use starlight_axum::testing::TelemetryCapture;
+use std::borrow::Cow;
+use std::sync::Arc;
+
+#[test]
+fn expands_every_supported_value_type() {
+    let route = "/orders/{id}";
+    let attributes = attrs! {
+        "http.route" => route,
+        "tenant.id" => String::from("acme"),
+        "cow" => Cow::Borrowed("borrowed"),
+        "arc" => Arc::<str>::from("shared"),
+        "string_value" => StringValue::from("value"),
+        "retry.enabled" => true,
+        "small" => -8i8,
+        "status" => 404u16,
+        "attempt" => 3i32,
+        "bytes" => 1_000_000u32,
+        "offset" => -5i64,
+        "count" => 7usize,
+        "huge" => u64::MAX,
+        "ratio" => 0.5f32,
+        "latency" => 1.25,
+        "tags" => Array::String(vec!["a".into(), "b".into()]),
+        "raw" => Value::from(1i64),
+    };
+
+    let expected = [
+        KeyValue::new("http.route", "/orders/{id}"),
+        KeyValue::new("tenant.id", "acme"),
+        KeyValue::new("cow", "borrowed"),
+        KeyValue::new("arc", "shared"),
+        KeyValue::new("string_value", "value"),
+        KeyValue::new("retry.enabled", true),
+        KeyValue::new("small", -8i64),
+        KeyValue::new("status", 404i64),
+        KeyValue::new("attempt", 3i64),
+        KeyValue::new("bytes", 1_000_000i64),
+        KeyValue::new("offset", -5i64),
+        KeyValue::new("count", 7i64),
+        KeyValue::new("huge", i64::MAX),
+        KeyValue::new("ratio", 0.5f64),
+        KeyValue::new("latency", 1.25f64),
+        KeyValue::new("tags", Value::Array(Array::String(vec!["a".into(), "b".into()]))),
+        KeyValue::new("raw", 1i64),
+    ];
+    assert_eq!(attributes, expected);
+    assert!(attrs! {}.is_empty());
+}
+
+#[test]
+fn attributes_serve_metrics_and_spans() {
+    let capture = TelemetryCapture::install();
+    let counter = capture.meter().u64_counter("orders.created").build();
+    counter.add(2, &attrs! { "order.channel" => "web" });
+    assert_eq!(capture.metric_sum("orders.created"), 2.0);
+
+    let span = tracing::info_span!("order");
+    starlight_axum::attrs::record(&span, attrs! { "order.items" => 3u32, "order.paid" => true });
+    drop(span);
+    let span = capture.spans_named("order").remove(0);
+    let value = |key: &'static str| {
+        span.attributes
+            .iter()
+            .find(|attribute| attribute.key == Key::from_static_str(key))
+            .map(|attribute| attribute.value.clone())
+    };
+    assert_eq!(value("order.items"), Some(Value::I64(3)));
+    assert_eq!(value("order.paid"), Some(Value::Bool(true)));
+}
+
+#[test]
+fn high_cardinality_keys_warn_once_unless_allowed() {
+    let capture = TelemetryCapture::install();
+    let warnings = || capture.events_with_target("starlight_axum::attrs");
+
+    for user in ["u-1", "u-2"] {
+        let _ = attrs! { #[allow_high_cardinality] "user.id" => user, "http.route" => "/me" };
+    }
+    assert!(warnings().is_empty());
+
+    for user in ["u-1", "u-2"] {
+        let _ = attrs! { "customer.email" => user };
+    }
+    let warned = warnings();
+    assert_eq!(warned.len(), 1);
+    let body = warned[0].body().map(|body| format!("{:?}", body)).unwrap_or_default();
+    assert!(body.contains("customer.email"), "{}", body);
+
+    // Each place warns on its own.
+    let _ = attrs! { "session_id" => "s-1" };
+    assert_eq!(warnings().len(), 2);
+}
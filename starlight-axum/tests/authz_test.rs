@@ -0,0 +1,134 @@
+use opentelemetry::{Key, Value};
+use starlight_axum::axum::Router;
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::{Request, StatusCode, header};
+use starlight_axum::axum::response::Response;
+use starlight_axum::axum::routing::{get, post};
+use starlight_axum::middleware::authz::{Claims, Denied, RequireScopes};
+use starlight_axum::testing::TelemetryCapture;
+use starlight_axum::tower::ServiceExt;
+use tracing::Instrument;
+
+fn claims(value: serde_json::Value) -> Claims {
+    match value {
+        serde_json::Value::Object(map) => Claims::new(map),
+        _ => panic!("claims must be an object"),
+    }
+}
+
+fn app() -> Router {
+    Router::new()
+        .route(
+            "/orders",
+            post(|| async { "created" }).route_layer(RequireScopes::new(["orders:write"])),
+        )
+        .route("/orders", get(|| async { "listed" }))
+        .route(
+            "/reports",
+            get(|| async { "report" }).route_layer(RequireScopes::new(["reports:read"]).with_claim("permissions")),
+        )
+}
+
+async fn call(request: Request<Body>) -> Response {
+    app().oneshot(request).await.unwrap()
+}
+
+fn request(method: &str, path: &str, claims: Option<Claims>) -> Request<Body> {
+    let mut request = Request::builder().method(method).uri(path).body(Body::empty()).unwrap();
+    if let Some(claims) = claims {
+        request.extensions_mut().insert(claims);
+    }
+    request
+}
+
+async fn problem(response: Response) -> serde_json::Value {
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "application/problem+json");
+    let bytes = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[tokio::test]
+async fn reads_space_separated_and_array_scopes() {
+    let string = claims(serde_json::json!({ "sub": "u1", "scope": "orders:read orders:write" }));
+    assert_eq!(call(request("POST", "/orders", Some(string))).await.status(), StatusCode::OK);
+
+    let array = claims(serde_json::json!({ "permissions": ["reports:read"] }));
+    assert_eq!(call(request("GET", "/reports", Some(array))).await.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn rejects_insufficient_scopes_with_problem_details() {
+    let read_only = claims(serde_json::json!({ "scope": "orders:read" }));
+    let body = problem(call(request("POST", "/orders", Some(read_only.clone()))).await).await;
+    assert_eq!(body["status"], 403);
+    assert_eq!(body["title"], "Forbidden");
+    assert_eq!(body["missing_scopes"], serde_json::json!(["orders:write"]));
+    assert_eq!(body["detail"], "missing required scopes: orders:write");
+
+    // Only the route with the layer is guarded.
+    assert_eq!(call(request("GET", "/orders", Some(read_only))).await.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn tags_the_current_span_with_the_decision() {
+    let capture = TelemetryCapture::install();
+    let read_only = claims(serde_json::json!({ "scope": "orders:read" }));
+    app()
+        .oneshot(request("POST", "/orders", Some(read_only)))
+        .instrument(tracing::info_span!("http.request"))
+        .await
+        .unwrap();
+
+    let span = capture.spans_named("http.request").remove(0);
+    let decision = span
+        .attributes
+        .iter()
+        .find(|attribute| attribute.key == Key::from_static_str("authz.decision"))
+        .map(|attribute| attribute.value.clone());
+    assert_eq!(decision, Some(Value::from("denied")));
+}
+
+#[tokio::test]
+async fn treats_a_missing_claim_as_no_scopes() {
+    let other_claim = claims(serde_json::json!({ "scope": "reports:read" }));
+    let body = problem(call(request("GET", "/reports", Some(other_claim))).await).await;
+    assert_eq!(body["missing_scopes"], serde_json::json!(["reports:read"]));
+
+    let unauthenticated = call(request("POST", "/orders", None)).await;
+    assert_eq!(unauthenticated.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn combines_requirements() {
+    let writer_or_admin = RequireScopes::any_of([
+        RequireScopes::new(["orders:write"]),
+        RequireScopes::new(["admin"]),
+    ]);
+    let granted = |scopes: &str| claims(serde_json::json!({ "scope": scopes }));
+
+    assert_eq!(writer_or_admin.check(Some(&granted("admin"))), Ok(()));
+    assert_eq!(writer_or_admin.check(Some(&granted("orders:write"))), Ok(()));
+    assert_eq!(
+        writer_or_admin.check(Some(&granted("orders:read"))),
+        Err(Denied::MissingScopes(vec!["orders:write".to_owned(), "admin".to_owned()]))
+    );
+
+    let audited_writer = RequireScopes::all_of([
+        writer_or_admin.clone(),
+        RequireScopes::new(["audit", "orders:write"]),
+    ]);
+    assert_eq!(audited_writer.check(Some(&granted("orders:write audit"))), Ok(()));
+    assert_eq!(
+        audited_writer.check(Some(&granted("admin"))),
+        Err(Denied::MissingScopes(vec!["audit".to_owned(), "orders:write".to_owned()]))
+    );
+    assert_eq!(
+        audited_writer.check(Some(&granted(""))),
+        Err(Denied::MissingScopes(vec!["orders:write".to_owned(), "admin".to_owned(), "audit".to_owned()]))
+    );
+    assert_eq!(audited_writer.check(None), Err(Denied::Unauthenticated));
+}
@@ -0,0 +1,71 @@
+#![cfg(unix)]
+
+use starlight_axum::axum::Router;
+use starlight_axum::axum::routing::get;
+use starlight_axum::starlight_tokio::{CancellationToken, ServiceManager};
+use starlight_axum::{AxumService, Listener};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+
+fn socket_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("starlight-axum-service-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join("app.sock")
+}
+
+fn router() -> Router {
+    Router::new().route(
+        "/slow",
+        get(|| async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            "done"
+        }),
+    )
+}
+
+#[tokio::test]
+async fn drains_in_flight_requests_on_shutdown() {
+    let path = socket_path("drain");
+    let manager = ServiceManager::new().with_service(
+        AxumService::new(Listener::unix(&path), router()).with_flush_telemetry(false),
+    );
+
+    let shutdown = CancellationToken::new();
+    let (summary, response) = tokio::join!(manager.run(shutdown.clone()), async {
+        manager.wait_ready(Duration::from_secs(1)).await.unwrap();
+        let mut stream = UnixStream::connect(&path).await.unwrap();
+        stream
+            .write_all(b"GET /slow HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        // Shut down while the request is in flight.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        shutdown.cancel();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    });
+
+    assert!(summary.is_success());
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    assert!(response.ends_with("done"));
+    assert!(!path.exists(), "socket file should be removed on shutdown");
+}
+
+#[tokio::test]
+async fn bind_failures_are_service_errors() {
+    let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = taken.local_addr().unwrap();
+    let manager = ServiceManager::new().with_service(
+        AxumService::new(Listener::tcp(addr), router()).with_name("api").with_flush_telemetry(false),
+    );
+
+    let summary = manager.run(CancellationToken::new()).await;
+
+    let failure = summary.failures().next().unwrap();
+    assert_eq!(failure.name, "api");
+    let err = failure.result.as_ref().unwrap_err();
+    assert!(err.to_string().starts_with("failed to bind Tcp("), "{:#}", err);
+}
@@ -0,0 +1,132 @@
+use starlight_axum::axum::Router;
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::{Request, header};
+use starlight_axum::axum::routing::post;
+use starlight_axum::middleware::body_capture::{BodyCaptureLayer, redact_json};
+use starlight_axum::tower::ServiceExt;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Instrument, Subscriber};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::{Layer, Registry};
+
+type Events = Arc<Mutex<Vec<HashMap<String, String>>>>;
+
+#[derive(Clone, Default)]
+struct Recorder(Events);
+
+struct Fields<'a>(&'a mut HashMap<String, String>);
+
+impl Visit for Fields<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.insert(field.name().to_owned(), format!("{:?}", value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_owned(), value.to_owned());
+    }
+}
+
+impl<S: Subscriber> Layer<S> for Recorder {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut fields = HashMap::new();
+        event.record(&mut Fields(&mut fields));
+        self.0.lock().unwrap().push(fields);
+    }
+}
+
+fn app(capture: BodyCaptureLayer) -> Router {
+    Router::new()
+        .route("/echo", post(|body: String| async move { ([(header::CONTENT_TYPE, "application/json")], body) }))
+        .route("/private", post(|body: String| async move { ([(header::CONTENT_TYPE, "application/json")], body) }))
+        .layer(capture)
+}
+
+async fn send(app: Router, path: &str, content_type: &str, body: &str) -> (String, Vec<HashMap<String, String>>) {
+    let recorder = Recorder::default();
+    let _default = tracing::subscriber::set_default(Registry::default().with(recorder.clone()));
+
+    let request = Request::post(path)
+        .header(header::CONTENT_TYPE, content_type)
+        .body(Body::from(body.to_owned()))
+        .unwrap();
+    let response = app
+        .oneshot(request)
+        .instrument(tracing::info_span!("http.request"))
+        .await
+        .unwrap();
+    let bytes = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+
+    let events = recorder.0.lock().unwrap().clone();
+    (String::from_utf8(bytes.to_vec()).unwrap(), events)
+}
+
+fn event<'a>(events: &'a [HashMap<String, String>], name: &str) -> Option<&'a HashMap<String, String>> {
+    events.iter().find(|fields| fields.get("message").is_some_and(|m| m == name))
+}
+
+fn layer() -> BodyCaptureLayer {
+    BodyCaptureLayer::new()
+        .with_routes(|route| route == "/echo")
+        .with_redacted_fields(["password", "card_number"])
+}
+
+#[tokio::test]
+async fn truncates_at_the_cap_without_altering_the_body() {
+    let body = r#"{"items":[1,2,3,4,5,6,7,8,9,10]}"#;
+    let (echoed, events) = send(app(layer().with_max_bytes(10)), "/echo", "application/json", body).await;
+    assert_eq!(echoed, body);
+
+    for name in ["http.request.body", "http.response.body"] {
+        let fields = event(&events, name).unwrap();
+        assert_eq!(fields["http.body"], r#"{"items":["#);
+        assert_eq!(fields["http.body.captured_bytes"], "10");
+        assert_eq!(fields["http.body.truncated"], "true");
+    }
+}
+
+#[tokio::test]
+async fn redacts_configured_fields() {
+    let body = r#"{"user":"ann","password":"hunter2","card":{"card_number":"4111111111111111"}}"#;
+    let (echoed, events) = send(app(layer()), "/echo", "application/json; charset=utf-8", body).await;
+    assert_eq!(echoed, body);
+
+    let captured = &event(&events, "http.request.body").unwrap()["http.body"];
+    assert!(captured.contains(r#""user":"ann""#));
+    assert!(captured.contains(r#""password":"***""#));
+    assert!(!captured.contains("hunter2"));
+    assert!(!captured.contains("4111"));
+    assert_eq!(event(&events, "http.request.body").unwrap()["http.body.truncated"], "false");
+}
+
+#[tokio::test]
+async fn skips_binary_content_types() {
+    let (_, events) = send(app(layer()), "/echo", "application/octet-stream", "\u{0}\u{1}\u{2}").await;
+    assert!(event(&events, "http.request.body").is_none());
+    assert!(event(&events, "http.response.body").is_some());
+}
+
+#[tokio::test]
+async fn routes_not_opted_in_pass_through() {
+    let body = r#"{"password":"hunter2"}"#;
+    let (echoed, events) = send(app(layer()), "/private", "application/json", body).await;
+    assert_eq!(echoed, body);
+    assert!(events.is_empty());
+}
+
+#[test]
+fn redacts_values_cut_off_by_truncation() {
+    let fields = vec!["password".to_owned()];
+    assert_eq!(
+        redact_json(r#"{"user":"ann","Password": "hun"#, &fields),
+        r#"{"user":"ann","Password": "***""#
+    );
+    assert_eq!(
+        redact_json(r#"{"password":{"old":"a","new":"b"},"x":1,"#, &fields),
+        r#"{"password":"***","x":1,"#
+    );
+}
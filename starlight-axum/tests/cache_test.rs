@@ -0,0 +1,159 @@
+use starlight_axum::axum::Router;
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::{Request, StatusCode, header};
+use starlight_axum::axum::response::Response;
+use starlight_axum::axum::routing::get;
+use starlight_axum::middleware::cache::{MemoryCacheStore, ResponseCacheLayer, X_CACHE};
+use starlight_axum::tower::ServiceExt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+fn app(calls: Arc<AtomicUsize>) -> Router {
+    let counted = |calls: Arc<AtomicUsize>, headers: &'static [(&'static str, &'static str)], size: usize| {
+        get(move || async move {
+            let n = calls.fetch_add(1, Ordering::SeqCst) + 1;
+            let mut response = Response::new(Body::from(format!("{:<1$}", n, size)));
+            for (name, value) in headers {
+                response.headers_mut().insert(*name, value.parse().unwrap());
+            }
+            response
+        })
+    };
+    Router::new()
+        .route("/rates", counted(calls.clone(), &[], 1))
+        .route("/flags", counted(calls.clone(), &[("cache-control", "no-store")], 1))
+        .route("/large", counted(calls.clone(), &[], 2048))
+        .route(
+            "/localized",
+            counted(calls.clone(), &[("vary", "accept-encoding, accept-language")], 1),
+        )
+        .route("/anything", counted(calls.clone(), &[("vary", "*")], 1))
+        .route(
+            "/forever",
+            counted(calls, &[("cache-control", "max-age=18446744073709551615")], 1),
+        )
+        .layer(
+            ResponseCacheLayer::new(MemoryCacheStore::new(100))
+                .with_ttl(Duration::from_millis(200))
+                .with_max_body_bytes(1024),
+        )
+}
+
+async fn get_path(app: &Router, path: &str) -> (Response, String) {
+    let response = app
+        .clone()
+        .oneshot(Request::get(path).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let (parts, body) = response.into_parts();
+    let bytes = http_body_util::BodyExt::collect(body).await.unwrap().to_bytes();
+    (
+        Response::from_parts(parts, Body::empty()),
+        String::from_utf8(bytes.to_vec()).unwrap().trim().to_owned(),
+    )
+}
+
+#[tokio::test]
+async fn serves_hits_from_the_cache() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let app = app(calls.clone());
+
+    let (miss, body) = get_path(&app, "/rates").await;
+    assert_eq!(miss.headers()[X_CACHE], "MISS");
+    assert!(!miss.headers().contains_key(header::AGE));
+    assert_eq!(body, "1");
+
+    let (hit, body) = get_path(&app, "/rates").await;
+    assert_eq!(hit.status(), StatusCode::OK);
+    assert_eq!(hit.headers()[X_CACHE], "HIT");
+    assert_eq!(hit.headers()[header::AGE], "0");
+    assert_eq!(body, "1");
+
+    let (other, body) = get_path(&app, "/rates?currency=EUR").await;
+    assert_eq!(other.headers()[X_CACHE], "MISS");
+    assert_eq!(body, "2");
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn entries_expire_after_the_ttl() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let app = app(calls.clone());
+
+    assert_eq!(get_path(&app, "/rates").await.1, "1");
+    assert_eq!(get_path(&app, "/rates").await.1, "1");
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    let (response, body) = get_path(&app, "/rates").await;
+    assert_eq!(response.headers()[X_CACHE], "MISS");
+    assert_eq!(body, "2");
+}
+
+#[tokio::test]
+async fn a_huge_max_age_is_clamped() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let app = app(calls.clone());
+
+    assert_eq!(get_path(&app, "/forever").await.1, "1");
+    let (hit, body) = get_path(&app, "/forever").await;
+    assert_eq!(hit.headers()[X_CACHE], "HIT");
+    assert_eq!(body, "1");
+}
+
+#[tokio::test]
+async fn no_store_and_large_responses_are_not_cached() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let app = app(calls.clone());
+
+    for path in ["/flags", "/flags", "/large", "/large"] {
+        let (response, _) = get_path(&app, path).await;
+        assert_eq!(response.headers()[X_CACHE], "MISS", "{}", path);
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 4);
+}
+
+#[tokio::test]
+async fn requests_with_cookies_bypass_the_cache() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let app = app(calls.clone());
+    let with_session = |session: &str| {
+        Request::get("/rates")
+            .header(header::COOKIE, format!("session={}", session))
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let alice = app.clone().oneshot(with_session("alice")).await.unwrap();
+    let bob = app.clone().oneshot(with_session("bob")).await.unwrap();
+    assert!(!alice.headers().contains_key(X_CACHE));
+    assert!(!bob.headers().contains_key(X_CACHE));
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+    // Nothing was stored for anonymous requests either.
+    let (miss, _) = get_path(&app, "/rates").await;
+    assert_eq!(miss.headers()[X_CACHE], "MISS");
+}
+
+#[tokio::test]
+async fn responses_varying_on_other_headers_are_not_cached() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let app = app(calls.clone());
+
+    for path in ["/localized", "/anything"] {
+        let (first, _) = get_path(&app, path).await;
+        let (second, _) = get_path(&app, path).await;
+        assert_eq!(first.headers()[X_CACHE], "MISS");
+        assert_eq!(second.headers()[X_CACHE], "MISS");
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 4);
+
+    let cached = Router::new()
+        .route(
+            "/localized",
+            get(|| async { ([(header::VARY, "Accept-Language")], "hello") }),
+        )
+        .layer(ResponseCacheLayer::new(MemoryCacheStore::new(100)).with_vary([header::ACCEPT_LANGUAGE]));
+    get_path(&cached, "/localized").await;
+    let (hit, _) = get_path(&cached, "/localized").await;
+    assert_eq!(hit.headers()[X_CACHE], "HIT");
+}
@@ -0,0 +1,170 @@
+use opentelemetry::metrics::MeterProvider;
+use opentelemetry_sdk::metrics::data::{AggregatedMetrics, MetricData};
+use opentelemetry_sdk::metrics::{InMemoryMetricExporter, PeriodicReader, SdkMeterProvider};
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::{Request, StatusCode, header};
+use starlight_axum::axum::response::IntoResponse;
+use starlight_axum::axum::routing::get;
+use starlight_axum::axum::{Router, serve};
+use starlight_axum::client::{
+    CIRCUIT_BREAKER_STATE, CIRCUIT_BREAKER_TRANSITIONS, CircuitBreaker, CircuitBreakerPolicy, CircuitState,
+    ClientError, TracedClient,
+};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+
+#[derive(Default)]
+struct Upstream {
+    hits: AtomicUsize,
+    failing: AtomicBool,
+}
+
+/// Answers 503 while `failing` is set, 200 otherwise.
+async fn upstream() -> (SocketAddr, Arc<Upstream>) {
+    let state = Arc::new(Upstream::default());
+    let handler_state = state.clone();
+    let router = Router::new().route(
+        "/",
+        get(move || async move {
+            handler_state.hits.fetch_add(1, Ordering::SeqCst);
+            if handler_state.failing.load(Ordering::SeqCst) {
+                StatusCode::SERVICE_UNAVAILABLE
+            } else {
+                StatusCode::OK
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { serve(listener, router).await.unwrap() });
+    (addr, state)
+}
+
+async fn call(client: &TracedClient, addr: SocketAddr) -> Result<StatusCode, ClientError> {
+    let req = Request::get(format!("http://{}/", addr)).body(Body::empty()).unwrap();
+    client.request(req).await.map(|response| response.status())
+}
+
+#[tokio::test]
+async fn walks_from_closed_to_open_to_half_open_to_closed() {
+    let exporter = InMemoryMetricExporter::default();
+    let provider = SdkMeterProvider::builder()
+        .with_reader(PeriodicReader::builder(exporter.clone()).build())
+        .build();
+    let policy = CircuitBreakerPolicy::new()
+        .with_consecutive_failures(3)
+        .with_cool_down(Duration::from_millis(200))
+        .with_probes(2);
+    let breaker = CircuitBreaker::with_meter(policy, &provider.meter("client"));
+    let client = TracedClient::new().with_circuit_breaker(breaker.clone());
+    let (addr, upstream) = upstream().await;
+    let destination = addr.to_string();
+    upstream.failing.store(true, Ordering::SeqCst);
+
+    // Closed: every call reaches the upstream until the third failure in a row.
+    for _ in 0..3 {
+        assert_eq!(call(&client, addr).await.unwrap(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+    assert_eq!(upstream.hits.load(Ordering::SeqCst), 3);
+    assert_eq!(breaker.state(&destination), CircuitState::Open);
+
+    // Open: clones of the client fail fast without calling.
+    let clone = client.clone();
+    for _ in 0..5 {
+        let err = call(&clone, addr).await.unwrap_err();
+        assert!(matches!(err, ClientError::CircuitOpen { .. }), "{:?}", err);
+    }
+    assert_eq!(upstream.hits.load(Ordering::SeqCst), 3);
+    let response = call(&client, addr).await.unwrap_err().into_response();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+
+    // Half-open: after the cool-down, probes reach the recovered upstream.
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    upstream.failing.store(false, Ordering::SeqCst);
+    assert_eq!(call(&client, addr).await.unwrap(), StatusCode::OK);
+    assert_eq!(breaker.state(&destination), CircuitState::HalfOpen);
+    assert_eq!(upstream.hits.load(Ordering::SeqCst), 4);
+
+    // Closed once enough probes succeeded.
+    assert_eq!(call(&clone, addr).await.unwrap(), StatusCode::OK);
+    assert_eq!(breaker.state(&destination), CircuitState::Closed);
+    for _ in 0..3 {
+        assert_eq!(call(&client, addr).await.unwrap(), StatusCode::OK);
+    }
+    assert_eq!(upstream.hits.load(Ordering::SeqCst), 8);
+
+    provider.force_flush().unwrap();
+    let metrics = exporter.get_finished_metrics().unwrap();
+    let metric = |name: &str| {
+        metrics
+            .last()
+            .into_iter()
+            .flat_map(|resource| resource.scope_metrics())
+            .flat_map(|scope| scope.metrics())
+            .find(|metric| metric.name() == name)
+            .map(|metric| metric.data())
+            .unwrap()
+    };
+    let AggregatedMetrics::U64(MetricData::Gauge(gauge)) = metric(CIRCUIT_BREAKER_STATE) else {
+        panic!("{} is not a u64 gauge", CIRCUIT_BREAKER_STATE);
+    };
+    let point = gauge.data_points().next().unwrap();
+    assert_eq!(point.value(), 0);
+    assert_eq!(point.attributes().next().unwrap().value.to_string(), destination);
+    let AggregatedMetrics::U64(MetricData::Sum(transitions)) = metric(CIRCUIT_BREAKER_TRANSITIONS) else {
+        panic!("{} is not a u64 counter", CIRCUIT_BREAKER_TRANSITIONS);
+    };
+    let mut states: Vec<(String, u64)> = transitions
+        .data_points()
+        .map(|point| {
+            let state = point.attributes().find(|kv| kv.key.as_str() == "state").unwrap();
+            (state.value.to_string(), point.value())
+        })
+        .collect();
+    states.sort();
+    assert_eq!(
+        states,
+        [
+            ("closed".to_owned(), 1),
+            ("half_open".to_owned(), 1),
+            ("open".to_owned(), 1)
+        ]
+    );
+}
+
+#[tokio::test]
+async fn opens_on_the_failure_rate_and_reopens_on_a_failed_probe() {
+    let policy = CircuitBreakerPolicy::new()
+        .with_consecutive_failures(0)
+        .with_failure_rate(0.5)
+        .with_min_calls(4)
+        .with_cool_down(Duration::from_millis(100));
+    let breaker = CircuitBreaker::new(policy);
+    let client = TracedClient::new().with_circuit_breaker(breaker.clone());
+    let (addr, upstream) = upstream().await;
+    let destination = addr.to_string();
+
+    // Half of four calls failing opens the breaker, not fewer calls.
+    for failing in [true, false, true] {
+        upstream.failing.store(failing, Ordering::SeqCst);
+        call(&client, addr).await.unwrap();
+        assert_eq!(breaker.state(&destination), CircuitState::Closed);
+    }
+    upstream.failing.store(false, Ordering::SeqCst);
+    call(&client, addr).await.unwrap();
+    assert_eq!(breaker.state(&destination), CircuitState::Open);
+
+    // A failed probe opens it again for another cool-down.
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    upstream.failing.store(true, Ordering::SeqCst);
+    assert_eq!(call(&client, addr).await.unwrap(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(breaker.state(&destination), CircuitState::Open);
+    assert!(matches!(
+        call(&client, addr).await,
+        Err(ClientError::CircuitOpen { .. })
+    ));
+    assert_eq!(upstream.hits.load(Ordering::SeqCst), 5);
+}
@@ -0,0 +1,177 @@
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::{Request, StatusCode};
+use starlight_axum::axum::routing::get;
+use starlight_axum::axum::{Router, serve};
+use starlight_axum::client::{ClientError, RetryPolicy, TracedClient};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+struct Upstream {
+    hits: AtomicUsize,
+    cancelled: AtomicBool,
+}
+
+/// Sets `cancelled` when the handler is dropped before answering.
+struct CancelGuard(Arc<Upstream>, bool);
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        if !self.1 {
+            self.0.cancelled.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+/// `/stall` never answers the first attempt and `/hang` any attempt, `/flaky` answers 503
+/// to the first attempt and `/unavailable` to every one.
+async fn upstream() -> (SocketAddr, Arc<Upstream>) {
+    let state = Arc::new(Upstream::default());
+    let hit = |state: &Arc<Upstream>| state.hits.fetch_add(1, Ordering::SeqCst);
+    let router = Router::new()
+        .route(
+            "/stall",
+            get({
+                let state = state.clone();
+                move || async move {
+                    let mut guard = CancelGuard(state.clone(), false);
+                    if hit(&state) == 0 {
+                        tokio::time::sleep(Duration::from_secs(10)).await;
+                    }
+                    guard.1 = true;
+                    "answered"
+                }
+            }),
+        )
+        .route(
+            "/hang",
+            get({
+                let state = state.clone();
+                move || async move {
+                    hit(&state);
+                    std::future::pending::<()>().await;
+                }
+            }),
+        )
+        .route(
+            "/flaky",
+            get({
+                let state = state.clone();
+                move || async move {
+                    match hit(&state) {
+                        0 => (StatusCode::SERVICE_UNAVAILABLE, "try again"),
+                        _ => (StatusCode::OK, "answered"),
+                    }
+                }
+            }),
+        )
+        .route(
+            "/unavailable",
+            get({
+                let state = state.clone();
+                move || async move {
+                    hit(&state);
+                    StatusCode::SERVICE_UNAVAILABLE
+                }
+            })
+            .post({
+                let state = state.clone();
+                move || async move {
+                    hit(&state);
+                    StatusCode::SERVICE_UNAVAILABLE
+                }
+            }),
+        );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { serve(listener, router).await.unwrap() });
+    (addr, state)
+}
+
+fn get_request(addr: SocketAddr, path: &str) -> Request<Body> {
+    Request::get(format!("http://{}{}", addr, path)).body(Body::empty()).unwrap()
+}
+
+async fn text(response: starlight_axum::axum::http::Response<Body>) -> String {
+    let bytes = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn hedge_wins_over_a_stalled_attempt() {
+    let (addr, upstream) = upstream().await;
+    let client = TracedClient::new().with_retry_policy(
+        "127.0.0.1",
+        RetryPolicy::new().with_hedge_delay(Duration::from_millis(50)),
+    );
+
+    let started = Instant::now();
+    let response = client.request(get_request(addr, "/stall")).await.unwrap();
+    assert!(started.elapsed() < Duration::from_secs(5), "{:?}", started.elapsed());
+    assert_eq!(text(response).await, "answered");
+    assert_eq!(upstream.hits.load(Ordering::SeqCst), 2);
+
+    // The losing attempt is cancelled, which closes its connection.
+    for _ in 0..100 {
+        if upstream.cancelled.load(Ordering::SeqCst) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("the stalled attempt was not cancelled");
+}
+
+#[tokio::test]
+async fn retries_idempotent_requests_but_never_a_post() {
+    let (addr, upstream) = upstream().await;
+    let client = TracedClient::new().with_retry_policy("127.0.0.1", RetryPolicy::new().with_max_attempts(3));
+
+    let flaky = client.request(get_request(addr, "/flaky")).await.unwrap();
+    assert_eq!(flaky.status(), StatusCode::OK);
+    assert_eq!(upstream.hits.swap(0, Ordering::SeqCst), 2);
+
+    let unavailable = client.request(get_request(addr, "/unavailable")).await.unwrap();
+    assert_eq!(unavailable.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(upstream.hits.swap(0, Ordering::SeqCst), 3);
+
+    let post = Request::post(format!("http://{}/unavailable", addr)).body(Body::from("{}")).unwrap();
+    assert_eq!(client.request(post).await.unwrap().status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(upstream.hits.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn stops_retrying_once_the_budget_is_spent() {
+    let (addr, upstream) = upstream().await;
+    let client = TracedClient::new()
+        .with_retry_policy("127.0.0.1", RetryPolicy::new().with_max_attempts(3))
+        .with_retry_budget(Duration::from_secs(1), 0, 0.0);
+
+    let response = client.request(get_request(addr, "/unavailable")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(upstream.hits.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn the_timeout_bounds_every_attempt_together() {
+    let (addr, upstream) = upstream().await;
+    let client = TracedClient::new()
+        .with_timeout(Some(Duration::from_millis(300)))
+        .with_retry_policy(
+            "127.0.0.1",
+            RetryPolicy::new()
+                .with_max_attempts(5)
+                .with_per_try_timeout(Duration::from_millis(200)),
+        );
+
+    // The first attempt times out after 200ms, the retry has 100ms left of the 300ms.
+    let started = Instant::now();
+    let result = client.request(get_request(addr, "/hang")).await;
+    assert!(matches!(result, Err(ClientError::Timeout(timeout)) if timeout == Duration::from_millis(300)), "{:?}", result);
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert_eq!(upstream.hits.load(Ordering::SeqCst), 2);
+}
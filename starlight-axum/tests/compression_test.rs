@@ -0,0 +1,100 @@
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use starlight_axum::axum::Router;
+use starlight_axum::axum::body::{Body, Bytes};
+use starlight_axum::axum::http::{Request, StatusCode, header};
+use starlight_axum::axum::response::Response;
+use starlight_axum::axum::routing::{get, post};
+use starlight_axum::middleware::compression::CompressionConfig;
+use starlight_axum::tower::ServiceExt;
+use std::io::Write;
+
+fn large_json() -> String {
+    let items: Vec<String> = (0..200).map(|n| format!(r#"{{"id":{},"name":"item"}}"#, n)).collect();
+    format!("[{}]", items.join(","))
+}
+
+fn app() -> Router {
+    Router::new()
+        .route(
+            "/items",
+            get(|| async { ([(header::CONTENT_TYPE, "application/json")], large_json()) }),
+        )
+        .route("/small", get(|| async { ([(header::CONTENT_TYPE, "application/json")], "{}") }))
+        .route(
+            "/logo.png",
+            get(|| async { ([(header::CONTENT_TYPE, "image/png")], vec![0u8; 4096]) }),
+        )
+        .route("/upload", post(|body: Bytes| async move { body.len().to_string() }))
+        .layer(
+            CompressionConfig::new()
+                .with_request_decompression(1024)
+                .into_layer(),
+        )
+}
+
+async fn get_with_gzip(path: &str) -> Response {
+    app()
+        .oneshot(
+            Request::get(path)
+                .header(header::ACCEPT_ENCODING, "gzip")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+async fn body_of(response: Response) -> Bytes {
+    http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes()
+}
+
+#[tokio::test]
+async fn large_json_is_compressed() {
+    let response = get_with_gzip("/items").await;
+    assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+    assert!(response.headers().get_all(header::VARY).iter().any(|v| v == "accept-encoding"));
+
+    let compressed = body_of(response).await;
+    let mut decoder = flate2::read::GzDecoder::new(&compressed[..]);
+    let mut decoded = String::new();
+    std::io::Read::read_to_string(&mut decoder, &mut decoded).unwrap();
+    assert_eq!(decoded, large_json());
+    assert!(compressed.len() < decoded.len());
+}
+
+#[tokio::test]
+async fn small_bodies_and_images_are_untouched() {
+    for path in ["/small", "/logo.png"] {
+        let response = get_with_gzip(path).await;
+        assert!(response.headers().get(header::CONTENT_ENCODING).is_none(), "{}", path);
+    }
+    assert_eq!(body_of(get_with_gzip("/logo.png").await).await.len(), 4096);
+}
+
+#[tokio::test]
+async fn gzipped_request_bodies_are_decompressed_up_to_the_limit() {
+    let upload = |data: &[u8]| {
+        Request::post("/upload")
+            .header(header::CONTENT_ENCODING, "gzip")
+            .body(Body::from(gzip(data)))
+            .unwrap()
+    };
+
+    let accepted = app().oneshot(upload(&[b'a'; 1000])).await.unwrap();
+    assert_eq!(accepted.status(), StatusCode::OK);
+    assert_eq!(body_of(accepted).await, "1000");
+
+    // A few dozen compressed bytes expanding past the cap.
+    let bomb = app().oneshot(upload(&[0u8; 1024 * 1024])).await.unwrap();
+    assert_eq!(bomb.status(), StatusCode::PAYLOAD_TOO_LARGE);
+}
@@ -0,0 +1,163 @@
+use starlight_axum::admin::AdminRouter;
+use starlight_axum::axum::Router;
+use starlight_axum::axum::routing::get;
+use starlight_axum::serve::{ConnectionStats, Listener, ListenerConfig, connection_stats, serve_many_with_shutdown};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::oneshot;
+use tokio::time::{sleep, timeout};
+
+/// A free port nothing listens on yet.
+fn reserve_addr() -> SocketAddr {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+}
+
+fn router() -> Router {
+    Router::new().route("/ping", get(|| async { "pong" }))
+}
+
+/// Sends a request on a kept-alive connection, returning the response.
+async fn ping(stream: &mut TcpStream) -> String {
+    stream
+        .write_all(b"GET /ping HTTP/1.1\r\nhost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = Vec::new();
+    let mut buf = [0; 1024];
+    while !response.ends_with(b"pong") {
+        let read = stream.read(&mut buf).await.unwrap();
+        assert!(read > 0, "closed after {:?}", String::from_utf8_lossy(&response));
+        response.extend_from_slice(&buf[..read]);
+    }
+    String::from_utf8(response).unwrap()
+}
+
+async fn connect(addr: SocketAddr) -> TcpStream {
+    for _ in 0..100 {
+        if let Ok(stream) = TcpStream::connect(addr).await {
+            return stream;
+        }
+        sleep(Duration::from_millis(10)).await;
+    }
+    panic!("server did not start on {}", addr);
+}
+
+/// Whether the server closed `stream` within a second.
+async fn is_closed(stream: &mut TcpStream) -> bool {
+    let mut buf = [0; 64];
+    matches!(
+        timeout(Duration::from_secs(1), stream.read(&mut buf)).await,
+        Ok(Ok(0) | Err(_))
+    )
+}
+
+fn stats_of(addr: SocketAddr) -> ConnectionStats {
+    let name = format!("http://{}", addr);
+    connection_stats()
+        .into_iter()
+        .find(|stats| stats.listener == name)
+        .unwrap()
+}
+
+#[tokio::test]
+async fn connections_over_the_cap_are_refused() {
+    let (app, admin) = (reserve_addr(), reserve_addr());
+    let admin_router = AdminRouter::builder()
+        .dangerously_unauthenticated()
+        .with_connections()
+        .build()
+        .unwrap();
+    let (stop_tx, stop_rx) = oneshot::channel::<()>();
+    let server = tokio::spawn(serve_many_with_shutdown(
+        vec![
+            (
+                Listener::tcp(app).with_config(ListenerConfig::new().with_max_connections(2)),
+                router(),
+            ),
+            (Listener::tcp(admin), admin_router),
+        ],
+        async move {
+            let _ = stop_rx.await;
+        },
+    ));
+
+    let mut first = connect(app).await;
+    assert!(ping(&mut first).await.starts_with("HTTP/1.1 200 OK"));
+    let mut second = connect(app).await;
+    assert!(ping(&mut second).await.starts_with("HTTP/1.1 200 OK"));
+
+    for _ in 0..3 {
+        let mut excess = TcpStream::connect(app).await.unwrap();
+        assert!(is_closed(&mut excess).await);
+    }
+    assert!(ping(&mut first).await.starts_with("HTTP/1.1 200 OK"));
+    assert!(ping(&mut second).await.starts_with("HTTP/1.1 200 OK"));
+
+    let mut client = connect(admin).await;
+    client
+        .write_all(b"GET /admin/connections HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    client.read_to_string(&mut response).await.unwrap();
+    let body: serde_json::Value = serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+    let app_stats = body["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|stats| stats["listener"] == format!("http://{}", app))
+        .unwrap();
+    assert_eq!(app_stats["open"], 2);
+    assert_eq!(app_stats["max_connections"], 2);
+    assert_eq!(app_stats["accepted"], 2);
+    assert_eq!(app_stats["refused"], 3);
+    assert_eq!(app_stats["closed"], 0);
+
+    // A slot freed by a closed connection is available again.
+    drop(first);
+    sleep(Duration::from_millis(50)).await;
+    let mut third = connect(app).await;
+    assert!(ping(&mut third).await.starts_with("HTTP/1.1 200 OK"));
+    let stats = stats_of(app);
+    assert_eq!((stats.open, stats.accepted, stats.closed, stats.errored), (2, 3, 1, 0));
+
+    drop((second, third));
+    stop_tx.send(()).unwrap();
+    server.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn idle_connections_are_closed() {
+    let addr = reserve_addr();
+    let config = ListenerConfig::new().with_idle_timeout(Duration::from_millis(200));
+    let (stop_tx, stop_rx) = oneshot::channel::<()>();
+    let server = tokio::spawn(serve_many_with_shutdown(
+        vec![(Listener::tcp(addr).with_config(config), router())],
+        async move {
+            let _ = stop_rx.await;
+        },
+    ));
+
+    let mut stream = connect(addr).await;
+    // Activity keeps the connection open past the timeout.
+    for _ in 0..3 {
+        assert!(ping(&mut stream).await.starts_with("HTTP/1.1 200 OK"));
+        sleep(Duration::from_millis(120)).await;
+    }
+    assert!(is_closed(&mut stream).await);
+    sleep(Duration::from_millis(20)).await;
+
+    let stats = stats_of(addr);
+    assert_eq!(
+        (stats.open, stats.closed, stats.idle_timeouts, stats.errored),
+        (0, 1, 1, 0)
+    );
+
+    stop_tx.send(()).unwrap();
+    server.await.unwrap().unwrap();
+}
@@ -0,0 +1,43 @@
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_appender_tracing::layer::OpenTelemetryTracingBridge;
+use opentelemetry_sdk::logs::{InMemoryLogExporter, SdkLoggerProvider};
+use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider};
+use starlight_axum::crash::PanicHook;
+use std::time::Duration;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::layer::SubscriberExt;
+
+#[test]
+fn a_panic_flushes_buffered_spans_and_logs() {
+    let spans = InMemorySpanExporter::default();
+    let logs = InMemoryLogExporter::default();
+    // Batching exporters only export on their schedule or when flushed.
+    let tracer_provider = SdkTracerProvider::builder().with_batch_exporter(spans.clone()).build();
+    let logger_provider = SdkLoggerProvider::builder().with_batch_exporter(logs.clone()).build();
+    PanicHook::new()
+        .with_tracer_provider(tracer_provider.clone())
+        .with_logger_provider(logger_provider.clone())
+        .with_flush_timeout(Duration::from_secs(5))
+        .install();
+
+    let subscriber = tracing_subscriber::registry()
+        .with(OpenTelemetryLayer::new(tracer_provider.tracer("crash-test")))
+        .with(OpenTelemetryTracingBridge::new(&logger_provider));
+    let worker = std::thread::spawn(move || {
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("before.panic").in_scope(|| ());
+            panic!("worker gave up");
+        })
+    });
+    assert!(worker.join().is_err());
+
+    let spans = spans.get_finished_spans().unwrap();
+    assert!(spans.iter().any(|span| span.name == "before.panic"), "{:?}", spans);
+    let logs = logs.get_emitted_logs().unwrap();
+    let panic = logs
+        .iter()
+        .find(|log| log.record.target().is_some_and(|target| target == "starlight::panic"))
+        .expect("the panic is logged");
+    let body = format!("{:?}", panic.record.body());
+    assert!(body.contains("worker gave up"), "{}", body);
+}
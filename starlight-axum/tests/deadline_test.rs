@@ -0,0 +1,117 @@
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::{HeaderMap, Request, StatusCode};
+use starlight_axum::axum::response::{IntoResponse, Response};
+use starlight_axum::axum::routing::get;
+use starlight_axum::axum::{Router, serve};
+use starlight_axum::client::TracedClient;
+use starlight_axum::deadline::{Deadline, DeadlineLayer, X_REQUEST_DEADLINE};
+use starlight_axum::tower::ServiceExt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+async fn upstream() -> SocketAddr {
+    let router = Router::new()
+        .route(
+            "/budget",
+            get(|headers: HeaderMap| async move {
+                headers
+                    .get(X_REQUEST_DEADLINE)
+                    .map(|value| value.to_str().unwrap().to_owned())
+                    .unwrap_or_default()
+            }),
+        )
+        .route(
+            "/slow",
+            get(|| async {
+                tokio::time::sleep(Duration::from_secs(2)).await;
+                "late"
+            }),
+        );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { serve(listener, router).await.unwrap() });
+    addr
+}
+
+fn app(upstream: SocketAddr, called: Arc<AtomicBool>) -> Router {
+    let proxy = move |path: &'static str| {
+        let called = called.clone();
+        get(move || async move {
+            called.store(true, Ordering::SeqCst);
+            // Most of the budget is spent before the outbound call.
+            tokio::time::sleep(Duration::from_millis(120)).await;
+            let request = Request::get(format!("http://{}{}", upstream, path))
+                .body(Body::empty())
+                .unwrap();
+            match TracedClient::new().request(request).await {
+                Ok(response) => response,
+                Err(err) => err.into_response(),
+            }
+        })
+    };
+    Router::new()
+        .route("/budget", proxy("/budget"))
+        .route("/slow", proxy("/slow"))
+        .layer(DeadlineLayer::new(Duration::from_secs(10)))
+}
+
+async fn call(app: Router, path: &str, deadline: &str) -> Response {
+    app.oneshot(
+        Request::get(path)
+            .header(X_REQUEST_DEADLINE, deadline)
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await
+    .unwrap()
+}
+
+async fn text(response: Response) -> String {
+    let bytes = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn outbound_call_gets_the_remaining_budget() {
+    let addr = upstream().await;
+    let response = call(app(addr, Arc::default()), "/budget", "200").await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let forwarded: u64 = text(response).await.parse().unwrap();
+    assert!(forwarded > 0 && forwarded <= 80, "forwarded budget was {}ms", forwarded);
+}
+
+#[tokio::test]
+async fn outbound_call_is_cut_at_the_deadline() {
+    let addr = upstream().await;
+    let started = Instant::now();
+    let response = call(app(addr, Arc::default()), "/slow", "300").await;
+    assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+    assert!(started.elapsed() < Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn expired_deadline_is_rejected_before_the_handler() {
+    let addr = upstream().await;
+    let called = Arc::new(AtomicBool::new(false));
+    let response = call(app(addr, called.clone()), "/budget", "2000-01-01T00:00:00Z").await;
+    assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+    assert!(!called.load(Ordering::SeqCst));
+}
+
+#[tokio::test]
+async fn caller_budget_is_capped() {
+    let mut headers = HeaderMap::new();
+    headers.insert(X_REQUEST_DEADLINE, "3600000".parse().unwrap());
+    let deadline = Deadline::from_headers(&headers, Duration::from_secs(5)).unwrap();
+    assert!(deadline.remaining() <= Duration::from_secs(5));
+
+    headers.insert(X_REQUEST_DEADLINE, "9999-01-01T00:00:00Z".parse().unwrap());
+    let deadline = Deadline::from_headers(&headers, Duration::from_secs(5)).unwrap();
+    assert!(deadline.remaining() > Duration::from_secs(4));
+}
@@ -0,0 +1,190 @@
+use starlight_axum::admin::AdminRouter;
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::{Request, StatusCode, header};
+use starlight_axum::axum::routing::get;
+use starlight_axum::axum::{Router, serve};
+use starlight_axum::client::TracedClient;
+use starlight_axum::config::{AppConfig, DynamicConfig, RateLimitConfig};
+use starlight_axum::middleware::maintenance::{MaintenanceLayer, MaintenanceSwitch};
+use starlight_axum::middleware::rate_limit::RateLimitLayer;
+use starlight_axum::tower::ServiceExt;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+fn limit(requests_per_second: u32) -> RateLimitConfig {
+    RateLimitConfig {
+        requests_per_second: Some(requests_per_second),
+        burst: None,
+    }
+}
+
+async fn spawn(app: Router) -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { serve(listener, app).await.unwrap() });
+    addr
+}
+
+/// How many of `count` requests to `/orders` the server answered with 200.
+async fn served(client: &TracedClient, addr: SocketAddr, count: usize) -> usize {
+    let mut served = 0;
+    for _ in 0..count {
+        let request = Request::get(format!("http://{}/orders", addr))
+            .body(Body::empty())
+            .unwrap();
+        let response = client.request(request).await.unwrap();
+        match response.status() {
+            StatusCode::OK => served += 1,
+            StatusCode::TOO_MANY_REQUESTS => assert!(response.headers().contains_key(header::RETRY_AFTER)),
+            status => panic!("unexpected {}", status),
+        }
+    }
+    served
+}
+
+#[tokio::test]
+async fn a_new_rate_limit_applies_to_the_running_server() {
+    let limits = DynamicConfig::new(limit(2));
+    let app = Router::new()
+        .route("/orders", get(|| async { "orders" }))
+        .layer(RateLimitLayer::new(limits.clone()));
+    let addr = spawn(app).await;
+    let client = TracedClient::new();
+
+    assert_eq!(served(&client, addr, 5).await, 2);
+
+    limits.set(limit(1000));
+    assert_eq!(served(&client, addr, 20).await, 20);
+
+    limits.set(RateLimitConfig {
+        requests_per_second: Some(1),
+        burst: Some(3),
+    });
+    assert_eq!(served(&client, addr, 5).await, 3);
+
+    limits.set(RateLimitConfig::default());
+    assert_eq!(served(&client, addr, 50).await, 50);
+}
+
+#[tokio::test]
+async fn mapped_sections_follow_their_config() {
+    let config = DynamicConfig::new(AppConfig::default());
+    let prefixes = config.map(|config| config.maintenance.allowed_prefixes.clone());
+    let switch = MaintenanceSwitch::new(true);
+    let app = Router::new()
+        .route("/orders", get(|| async { "orders" }))
+        .layer(MaintenanceLayer::new(switch).with_dynamic_allowed_prefixes(prefixes.clone()));
+    let call = |path: &'static str| {
+        let app = app.clone();
+        async move {
+            app.oneshot(Request::get(path).body(Body::empty()).unwrap())
+                .await
+                .unwrap()
+                .status()
+        }
+    };
+    assert_eq!(call("/orders").await, StatusCode::SERVICE_UNAVAILABLE);
+
+    let mut reloaded = AppConfig::default();
+    reloaded.maintenance.allowed_prefixes.push("/orders".to_owned());
+    config.set(reloaded);
+    assert_eq!(*prefixes.get(), ["/health", "/admin", "/orders"]);
+    assert_eq!(call("/orders").await, StatusCode::OK);
+}
+
+/// A config file with `contents`, removed when dropped.
+struct TempConfig(PathBuf);
+
+impl TempConfig {
+    fn new(name: &str, contents: &str) -> Self {
+        let config = TempConfig(std::env::temp_dir().join(format!("{}-{}", std::process::id(), name)));
+        config.write(contents);
+        config
+    }
+
+    fn write(&self, contents: &str) {
+        std::fs::File::create(&self.0)
+            .unwrap()
+            .write_all(contents.as_bytes())
+            .unwrap();
+    }
+}
+
+impl Drop for TempConfig {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+async fn wait_for(config: &DynamicConfig<AppConfig>, requests_per_second: u32) {
+    let mut changes = config.subscribe();
+    tokio::time::timeout(
+        Duration::from_secs(5),
+        changes.wait_for(|config| config.rate_limit.requests_per_second == Some(requests_per_second)),
+    )
+    .await
+    .expect("the file was not reloaded")
+    .unwrap();
+}
+
+#[tokio::test]
+async fn watch_reloads_valid_files_and_keeps_the_last_good_one() {
+    let file = TempConfig::new("watched.toml", "[rate_limit]\nrequests_per_second = 10\n");
+    let config = AppConfig::watch_every(&file.0, Duration::from_millis(20)).unwrap();
+    let limits = config.map(|config| config.rate_limit.clone());
+    assert_eq!(limits.get().requests_per_second, Some(10));
+
+    // Modification times may be as coarse as a second.
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    file.write("[rate_limit]\nrequests_per_second = 20\n");
+    wait_for(&config, 20).await;
+    assert_eq!(limits.get().requests_per_second, Some(20));
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    file.write("[rate_limit]\nrequests_per_second = \"many\"\n");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(config.get().rate_limit.requests_per_second, Some(20));
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    file.write("[rate_limit]\nrequests_per_second = 30\n");
+    wait_for(&config, 30).await;
+}
+
+#[tokio::test]
+async fn the_admin_router_serves_the_effective_config() {
+    let config = DynamicConfig::new(AppConfig::default());
+    let router = AdminRouter::builder()
+        .dangerously_unauthenticated()
+        .with_app_config(config.clone())
+        .build()
+        .unwrap();
+    let current = || async {
+        let response = router
+            .clone()
+            .oneshot(Request::get("/admin/config").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = http_body_util::BodyExt::collect(response.into_body())
+            .await
+            .unwrap()
+            .to_bytes();
+        serde_json::from_slice::<serde_json::Value>(&bytes).unwrap()["data"].clone()
+    };
+    assert_eq!(
+        current().await["rate_limit"]["requests_per_second"],
+        serde_json::Value::Null
+    );
+
+    let mut reloaded = AppConfig {
+        rate_limit: limit(50),
+        ..AppConfig::default()
+    };
+    reloaded.telemetry.otlp_headers = Some("authorization=Bearer s3cret".to_owned());
+    config.set(reloaded);
+    let served = current().await;
+    assert_eq!(served["rate_limit"]["requests_per_second"], 50);
+    assert!(!served.to_string().contains("s3cret"));
+}
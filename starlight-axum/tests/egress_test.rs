@@ -0,0 +1,111 @@
+use opentelemetry_sdk::trace::SpanData;
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::{Request, StatusCode};
+use starlight_axum::axum::response::IntoResponse;
+use starlight_axum::axum::routing::get;
+use starlight_axum::axum::{Router, serve};
+use starlight_axum::client::{ClientError, TracedClient};
+use starlight_axum::egress::{EGRESS_DECISIONS, EgressDecision, EgressPolicy, OnViolation};
+use starlight_axum::testing::TelemetryCapture;
+use std::net::SocketAddr;
+
+async fn upstream() -> SocketAddr {
+    let router = Router::new().route("/", get(|| async { "upstream" }));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { serve(listener, router).await.unwrap() });
+    addr
+}
+
+fn get_request(uri: String) -> Request<Body> {
+    Request::get(uri).body(Body::empty()).unwrap()
+}
+
+/// The egress decisions recorded on `span`, without the connection events of hyper.
+fn decisions(span: &SpanData) -> Vec<String> {
+    span.events
+        .iter()
+        .map(|event| event.name.to_string())
+        .filter(|name| name.starts_with("egress"))
+        .collect()
+}
+
+#[tokio::test]
+async fn allows_listed_destinations_and_blocks_the_rest() {
+    let capture = TelemetryCapture::install();
+    let addr = upstream().await;
+    let policy = EgressPolicy::new()
+        .allow_cidr_port("127.0.0.0/8".parse().unwrap(), addr.port())
+        .with_meter(&capture.meter());
+    let client = TracedClient::new().with_egress_policy(policy);
+
+    let allowed = client.request(get_request(format!("http://{}/", addr))).await.unwrap();
+    assert_eq!(allowed.status(), StatusCode::OK);
+
+    let blocked = client.request(get_request("http://blocked.example/".to_owned())).await;
+    assert!(
+        matches!(&blocked, Err(ClientError::EgressDenied(destination)) if destination == "blocked.example"),
+        "{:?}",
+        blocked
+    );
+    assert_eq!(blocked.unwrap_err().into_response().status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    let spans = capture.spans_named("GET");
+    assert_eq!(spans.len(), 2);
+    assert_eq!(decisions(&spans[0]), ["egress allowed"]);
+    assert_eq!(decisions(&spans[1]), ["egress denied"]);
+    assert_eq!(capture.metric_sum(EGRESS_DECISIONS), 2.0);
+}
+
+#[test]
+fn wildcards_match_subdomains_only() {
+    let policy = EgressPolicy::new()
+        .allow_host("*.example.com")
+        .allow_host_port("api.partner.io", 443);
+
+    assert_eq!(policy.decide("api.example.com", 443), EgressDecision::Allowed);
+    assert_eq!(policy.decide("a.b.Example.COM.", 8080), EgressDecision::Allowed);
+    assert_eq!(policy.decide("example.com", 443), EgressDecision::Denied);
+    assert_eq!(policy.decide("evilexample.com", 443), EgressDecision::Denied);
+    assert_eq!(policy.decide("api.partner.io", 443), EgressDecision::Allowed);
+    assert_eq!(policy.decide("api.partner.io", 80), EgressDecision::Denied);
+}
+
+#[test]
+fn clones_can_be_extended_independently() {
+    let base = EgressPolicy::new().allow_host("api.example.com");
+    let extended = base.clone().allow_host("api.partner.io");
+
+    assert_eq!(extended.decide("api.partner.io", 443), EgressDecision::Allowed);
+    assert_eq!(extended.decide("api.example.com", 443), EgressDecision::Allowed);
+    assert_eq!(base.decide("api.partner.io", 443), EgressDecision::Denied);
+}
+
+#[tokio::test]
+async fn log_only_mode_sends_denied_calls() {
+    let capture = TelemetryCapture::install();
+    let addr = upstream().await;
+    let policy = EgressPolicy::new()
+        .with_on_violation(OnViolation::Log)
+        .with_meter(&capture.meter());
+    let client = TracedClient::new().with_egress_policy(policy);
+
+    let response = client.request(get_request(format!("http://{}/", addr))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let span = capture.spans_named("GET").remove(0);
+    assert_eq!(decisions(&span), ["egress denied"]);
+    assert_eq!(capture.metric_sum(EGRESS_DECISIONS), 1.0);
+}
+
+#[tokio::test]
+async fn resolved_addresses_outside_the_cidr_blocks_are_refused() {
+    let _capture = TelemetryCapture::install();
+    let addr = upstream().await;
+    let policy = EgressPolicy::new()
+        .allow_host("localhost")
+        .allow_cidr("10.0.0.0/8".parse().unwrap());
+    let client = TracedClient::restricted(policy);
+
+    let result = client.request(get_request(format!("http://localhost:{}/", addr.port()))).await;
+    assert!(matches!(result, Err(ClientError::Request(_))), "{:?}", result);
+}
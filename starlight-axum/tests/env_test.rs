@@ -0,0 +1,95 @@
+use starlight_axum::env::{Env, EnvError, EnvErrorKind, EnvErrors, parse_bool, parse_duration, parse_list};
+use std::time::Duration;
+
+fn vars(pairs: &[(&str, &str)]) -> Env {
+    Env::from_vars(pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())))
+}
+
+#[test]
+fn parses_booleans() {
+    for value in ["1", "true", "YES", " on "] {
+        assert_eq!(parse_bool(value), Ok(true), "{}", value);
+    }
+    for value in ["0", "False", "no", "off"] {
+        assert_eq!(parse_bool(value), Ok(false), "{}", value);
+    }
+    assert!(parse_bool("enabled").is_err());
+}
+
+#[test]
+fn parses_durations() {
+    assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+    assert_eq!(parse_duration("5m"), Ok(Duration::from_secs(300)));
+    assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+    assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+    assert_eq!(parse_duration("2d"), Ok(Duration::from_secs(172800)));
+    assert_eq!(parse_duration("30"), Err("30 is missing a unit (ms, s, m, h or d)".to_owned()));
+    assert!(parse_duration("5 minutes").is_err());
+    assert!(parse_duration("99999999999999999999d").is_err());
+}
+
+#[test]
+fn parses_lists() {
+    assert_eq!(parse_list::<String>("a, b,,c "), Ok(vec!["a".to_owned(), "b".to_owned(), "c".to_owned()]));
+    assert_eq!(parse_list::<u16>("80,443"), Ok(vec![80, 443]));
+    assert_eq!(parse_list::<u16>("80,http").unwrap_err(), "item \"http\": invalid digit found in string");
+}
+
+#[test]
+fn reads_typed_variables_under_a_prefix() {
+    let env = vars(&[
+        ("STARLIGHT_WORKERS", "4"),
+        ("STARLIGHT_DEBUG", "on"),
+        ("STARLIGHT_DRAIN", "10s"),
+        ("STARLIGHT_PORTS", "80, 443"),
+        ("STARLIGHT_EMPTY", " "),
+        ("WORKERS", "8"),
+    ])
+    .with_prefix("STARLIGHT_");
+
+    assert_eq!(env.required::<usize>("WORKERS"), Ok(4));
+    assert_eq!(env.flag("DEBUG"), Ok(Some(true)));
+    assert_eq!(env.duration("DRAIN"), Ok(Some(Duration::from_secs(10))));
+    assert_eq!(env.list::<u16>("PORTS"), Ok(vec![80, 443]));
+    assert_eq!(env.optional::<String>("EMPTY"), Ok(None));
+    assert_eq!(env.list::<u16>("UNSET"), Ok(vec![]));
+
+    let err = env.required::<String>("REGION").unwrap_err();
+    assert_eq!(err.kind, EnvErrorKind::Missing);
+    assert_eq!(err.to_string(), "STARLIGHT_REGION is not set");
+}
+
+#[test]
+fn names_the_variable_and_the_parse_failure() {
+    let env = vars(&[("WORKERS", "four")]);
+    let err = env.required::<usize>("WORKERS").unwrap_err();
+    assert_eq!(
+        err,
+        EnvError {
+            variable: "WORKERS".to_owned(),
+            kind: EnvErrorKind::Invalid {
+                value: "four".to_owned(),
+                reason: "invalid digit found in string".to_owned(),
+            },
+        }
+    );
+    assert_eq!(err.to_string(), "WORKERS=\"four\" is invalid: invalid digit found in string");
+}
+
+#[test]
+fn aggregates_every_problem() {
+    let env = vars(&[("APP_TIMEOUT", "soon"), ("APP_WORKERS", "2")]).with_prefix("APP_");
+    let mut errors = EnvErrors::new();
+    let name = errors.check(env.required::<String>("NAME"));
+    let version = errors.check(env.required::<String>("VERSION"));
+    let timeout = errors.check(env.duration("TIMEOUT"));
+    let workers = errors.check(env.required::<usize>("WORKERS"));
+
+    assert_eq!((name, version, timeout, workers), (None, None, None, Some(2)));
+    assert_eq!(errors.errors().len(), 3);
+    assert_eq!(
+        errors.into_result().unwrap_err().to_string(),
+        "invalid environment: APP_NAME is not set; APP_VERSION is not set; \
+         APP_TIMEOUT=\"soon\" is invalid: expected a number at \"soon\""
+    );
+}
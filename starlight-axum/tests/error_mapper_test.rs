@@ -0,0 +1,207 @@
+use opentelemetry::trace::Status;
+use opentelemetry::{Key, Value};
+use opentelemetry_sdk::trace::SpanData;
+use starlight_axum::axum::Router;
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::{Request, StatusCode, header};
+use starlight_axum::axum::response::Response;
+use starlight_axum::axum::routing::get;
+use starlight_axum::error::{ErrorMapperLayer, HandlerError, I18nError};
+use starlight_axum::middleware::locale::LocaleLayer;
+use starlight_axum::testing::TelemetryCapture;
+use starlight_axum::tower::ServiceExt;
+use starlight_i18n::I18nCode;
+use starlight_protocol::i18n::Catalog;
+use std::sync::Arc;
+use tracing::Instrument;
+
+#[derive(I18nCode, Debug, Clone)]
+#[i18n(protocol)]
+enum ApiError {
+    #[i18n_code("api.internal")]
+    Internal,
+    #[i18n_code("api.order_not_found")]
+    OrderNotFound,
+    #[i18n_code("api.quota_exceeded")]
+    QuotaExceeded { used: u64 },
+}
+
+impl I18nError for ApiError {
+    fn status(&self) -> StatusCode {
+        match self {
+            ApiError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::OrderNotFound => StatusCode::NOT_FOUND,
+            ApiError::QuotaExceeded { .. } => StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+}
+
+/// An error of the storage, known to the mapper.
+#[derive(Debug)]
+struct MissingRow(u64);
+
+impl std::fmt::Display for MissingRow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "no row with id {}", self.0)
+    }
+}
+
+impl std::error::Error for MissingRow {}
+
+#[derive(Debug)]
+struct OverQuota(u64);
+
+impl std::fmt::Display for OverQuota {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} requests used", self.0)
+    }
+}
+
+impl std::error::Error for OverQuota {}
+
+async fn missing_order() -> Result<&'static str, HandlerError> {
+    let row: Result<&'static str, MissingRow> = Err(MissingRow(7));
+    Ok(anyhow::Context::context(row, "loading order 7")?)
+}
+
+async fn broken_pool() -> Result<&'static str, HandlerError> {
+    let pool: Result<&'static str, std::io::Error> = Err(std::io::Error::other("password authentication failed"));
+    Ok(anyhow::Context::context(pool, "connecting to the database")?)
+}
+
+async fn over_quota() -> Result<&'static str, HandlerError> {
+    Err(OverQuota(1234567).into())
+}
+
+async fn panics() -> &'static str {
+    panic!("invariant violated: negative stock")
+}
+
+fn app() -> Router {
+    let catalog = Catalog::new()
+        .with("en", "api.internal", "Something went wrong")
+        .with("vi", "api.order_not_found", "Không tìm thấy đơn hàng")
+        .with("en", "api.quota_exceeded", "{used} requests used this month")
+        .with("vi", "api.quota_exceeded", "Đã dùng {used} yêu cầu trong tháng");
+    Router::new()
+        .route("/orders/7", get(missing_order))
+        .route("/pool", get(broken_pool))
+        .route("/quota", get(over_quota))
+        .route("/panic", get(panics))
+        .route("/ok", get(|| async { "ok" }))
+        .layer(
+            ErrorMapperLayer::new(ApiError::Internal)
+                .with_mapper(|err| {
+                    if let Some(quota) = err.downcast_ref::<OverQuota>() {
+                        return Some(ApiError::QuotaExceeded { used: quota.0 });
+                    }
+                    err.downcast_ref::<MissingRow>().map(|_| ApiError::OrderNotFound)
+                })
+                .with_translator(Arc::new(catalog)),
+        )
+        .layer(LocaleLayer::new(["en", "vi"], "en"))
+}
+
+async fn call(path: &str, language: &str) -> Response {
+    let request = Request::get(path)
+        .header(header::ACCEPT_LANGUAGE, language)
+        .body(Body::empty())
+        .unwrap();
+    app()
+        .oneshot(request)
+        .instrument(tracing::info_span!("http.request"))
+        .await
+        .unwrap()
+}
+
+async fn json(response: Response) -> serde_json::Value {
+    let bytes = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+fn attribute(span: &SpanData, key: &'static str) -> Option<Value> {
+    span.attributes
+        .iter()
+        .find(|attribute| attribute.key == Key::from_static_str(key))
+        .map(|attribute| attribute.value.clone())
+}
+
+#[tokio::test]
+async fn known_errors_are_downcast_to_their_variant() {
+    let capture = TelemetryCapture::install();
+    let response = call("/orders/7", "vi").await;
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let body = json(response).await;
+    assert_eq!(body["code"], "api.order_not_found");
+    assert_eq!(body["message"], "Không tìm thấy đơn hàng");
+
+    let span = capture.spans_named("http.request").remove(0);
+    assert_eq!(attribute(&span, "error.type"), Some(Value::from("api.order_not_found")));
+    assert_eq!(
+        attribute(&span, "error.chain"),
+        Some(Value::from("loading order 7: no row with id 7"))
+    );
+    assert_eq!(span.status, Status::Unset);
+}
+
+#[tokio::test]
+async fn other_errors_fall_back_without_leaking_their_message() {
+    let capture = TelemetryCapture::install();
+    let response = call("/pool", "en").await;
+
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let body = json(response).await;
+    assert_eq!(
+        body,
+        serde_json::json!({"code": "api.internal", "message": "Something went wrong"})
+    );
+
+    let span = capture.spans_named("http.request").remove(0);
+    let chain = "connecting to the database: password authentication failed";
+    assert_eq!(attribute(&span, "error.chain"), Some(Value::from(chain)));
+    assert_eq!(span.status, Status::error(chain));
+}
+
+#[tokio::test]
+async fn panics_are_answered_with_the_fallback() {
+    let capture = TelemetryCapture::install();
+    let response = call("/panic", "en").await;
+
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let body = json(response).await;
+    assert_eq!(body["code"], "api.internal");
+    assert!(!body.to_string().contains("stock"));
+
+    let span = capture.spans_named("http.request").remove(0);
+    assert_eq!(
+        attribute(&span, "error.chain"),
+        Some(Value::from("panicked: invariant violated: negative stock"))
+    );
+}
+
+#[tokio::test]
+async fn successful_responses_pass_through() {
+    let response = call("/ok", "en").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        http_body_util::BodyExt::collect(response.into_body())
+            .await
+            .unwrap()
+            .to_bytes(),
+        "ok"
+    );
+}
+
+#[tokio::test]
+async fn message_params_are_formatted_for_the_locale() {
+    let response = call("/quota", "en").await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(json(response).await["message"], "1,234,567 requests used this month");
+
+    let response = call("/quota", "vi").await;
+    assert_eq!(json(response).await["message"], "Đã dùng 1.234.567 yêu cầu trong tháng");
+}
@@ -0,0 +1,170 @@
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::request::Parts;
+use starlight_axum::axum::http::{Request, StatusCode, header};
+use starlight_axum::axum::response::{IntoResponse, Response};
+use starlight_axum::axum::routing::get;
+use starlight_axum::axum::{Json, Router};
+use starlight_axum::middleware::etag::{CurrentEtag, EtagLayer, IfMatch, PreconditionFailed, strong_etag};
+use starlight_axum::tower::{Layer, ServiceExt};
+
+const DOC: &str = r#"{"id":1,"name":"starlight"}"#;
+
+fn routes() -> Router {
+    Router::new()
+        .route(
+            "/doc",
+            get(|| async { ([(header::CONTENT_TYPE, "application/json")], DOC) }).put(
+                |if_match: IfMatch, Json(_): Json<serde_json::Value>| async move {
+                    if_match.check(&strong_etag(DOC.as_bytes()))?;
+                    Ok::<_, PreconditionFailed>(StatusCode::NO_CONTENT)
+                },
+            ),
+        )
+        .route("/large", get(|| async { "x".repeat(1024) }))
+        .route("/missing", get(|| async { StatusCode::NOT_FOUND.into_response() }))
+}
+
+fn etag_layer() -> EtagLayer {
+    EtagLayer::new().with_max_bytes(512).with_routes(|_| true)
+}
+
+fn app() -> Router {
+    routes().layer(etag_layer())
+}
+
+async fn get_with(path: &str, if_none_match: Option<&str>) -> Response {
+    let mut request = Request::get(path);
+    if let Some(value) = if_none_match {
+        request = request.header(header::IF_NONE_MATCH, value);
+    }
+    app().oneshot(request.body(Body::empty()).unwrap()).await.unwrap()
+}
+
+async fn text(response: Response) -> String {
+    let bytes = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn matching_etag_gets_not_modified() {
+    let first = get_with("/doc", None).await;
+    assert_eq!(first.status(), StatusCode::OK);
+    let etag = first.headers()[header::ETAG].to_str().unwrap().to_owned();
+    assert_eq!(etag, strong_etag(DOC.as_bytes()));
+    assert_eq!(text(first).await, DOC);
+
+    let second = get_with("/doc", Some(&etag)).await;
+    assert_eq!(second.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(second.headers()[header::ETAG], etag.as_str());
+    assert_eq!(second.headers()[header::CONTENT_TYPE], "application/json");
+    assert_eq!(text(second).await, "");
+}
+
+#[tokio::test]
+async fn stale_etag_gets_the_body() {
+    let response = get_with("/doc", Some("\"0123\"")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(text(response).await, DOC);
+}
+
+#[tokio::test]
+async fn any_of_several_etags_or_a_star_matches() {
+    let etag = strong_etag(DOC.as_bytes());
+    let list = format!("\"0123\", W/{}", etag);
+    assert_eq!(get_with("/doc", Some(&list)).await.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(get_with("/doc", Some("*")).await.status(), StatusCode::NOT_MODIFIED);
+}
+
+#[tokio::test]
+async fn oversized_and_error_responses_are_skipped() {
+    let large = get_with("/large", Some("*")).await;
+    assert_eq!(large.status(), StatusCode::OK);
+    assert!(large.headers().get(header::ETAG).is_none());
+
+    let missing = get_with("/missing", Some("*")).await;
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn write_with_a_stale_if_match_fails_the_precondition() {
+    let put = |if_match: String| {
+        Request::put("/doc")
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::IF_MATCH, if_match)
+            .body(Body::from("{}"))
+            .unwrap()
+    };
+
+    let stale = app().oneshot(put("\"0123\"".to_owned())).await.unwrap();
+    assert_eq!(stale.status(), StatusCode::PRECONDITION_FAILED);
+    assert_eq!(stale.headers()[header::ETAG], strong_etag(DOC.as_bytes()).as_str());
+
+    let weak = app().oneshot(put(format!("W/{}", strong_etag(DOC.as_bytes())))).await.unwrap();
+    assert_eq!(weak.status(), StatusCode::PRECONDITION_FAILED);
+
+    let current = app().oneshot(put(strong_etag(DOC.as_bytes()))).await.unwrap();
+    assert_eq!(current.status(), StatusCode::NO_CONTENT);
+}
+
+/// Documents by path, as a store the layer looks current ETags up in.
+struct Documents(&'static [(&'static str, &'static str)]);
+
+impl CurrentEtag for Documents {
+    async fn current_etag(&self, parts: &Parts) -> Option<String> {
+        let (_, doc) = self.0.iter().find(|(path, _)| *path == parts.uri.path())?;
+        Some(strong_etag(doc.as_bytes()))
+    }
+}
+
+#[tokio::test]
+async fn the_layer_enforces_if_match_with_a_current_etag() {
+    let app = Router::new()
+        .route("/doc", get(|| async { DOC }).put(|| async { StatusCode::NO_CONTENT }))
+        .route("/gone", get(|| async { DOC }).delete(|| async { StatusCode::NO_CONTENT }))
+        .layer(etag_layer().with_current_etag(Documents(&[("/doc", DOC)])));
+    let write = |method: &str, path: &str, if_match: Option<String>| {
+        let mut request = Request::builder().method(method).uri(path);
+        if let Some(if_match) = if_match {
+            request = request.header(header::IF_MATCH, if_match);
+        }
+        request.body(Body::empty()).unwrap()
+    };
+
+    let stale = app.clone().oneshot(write("PUT", "/doc", Some("\"0123\"".to_owned()))).await.unwrap();
+    assert_eq!(stale.status(), StatusCode::PRECONDITION_FAILED);
+    assert_eq!(stale.headers()[header::ETAG], strong_etag(DOC.as_bytes()).as_str());
+
+    let current = write("PUT", "/doc", Some(strong_etag(DOC.as_bytes())));
+    assert_eq!(app.clone().oneshot(current).await.unwrap().status(), StatusCode::NO_CONTENT);
+    let unconditional = write("PUT", "/doc", None);
+    assert_eq!(app.clone().oneshot(unconditional).await.unwrap().status(), StatusCode::NO_CONTENT);
+
+    let missing = write("DELETE", "/gone", Some("*".to_owned()));
+    assert_eq!(app.oneshot(missing).await.unwrap().status(), StatusCode::PRECONDITION_FAILED);
+}
+
+#[tokio::test]
+async fn head_gets_the_etag_of_get() {
+    let get = get_with("/doc", None).await;
+    let etag = get.headers()[header::ETAG].clone();
+    // Around the router, HEAD responses arrive with their body already dropped.
+    let around_router = etag_layer().layer(routes());
+    for service in [app().boxed_clone(), around_router.boxed_clone()] {
+        let head = service
+            .clone()
+            .oneshot(Request::head("/doc").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(head.status(), StatusCode::OK);
+        assert_eq!(head.headers()[header::ETAG], etag);
+        assert_eq!(head.headers()[header::CONTENT_LENGTH], DOC.len().to_string().as_str());
+        assert_eq!(text(head).await, "");
+
+        let request = Request::head("/doc").header(header::IF_NONE_MATCH, etag.clone());
+        let revalidated = service.oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(revalidated.status(), StatusCode::NOT_MODIFIED);
+    }
+}
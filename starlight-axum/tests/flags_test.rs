@@ -0,0 +1,130 @@
+use opentelemetry::{Key, Value};
+use starlight_axum::axum::Router;
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::{HeaderName, Request};
+use starlight_axum::axum::response::Response;
+use starlight_axum::axum::routing::get;
+use starlight_axum::middleware::authz::Claims;
+use starlight_axum::middleware::flags::{
+    EnvFlagProvider, FeatureFlagLayer, FeatureFlags, FlagContext, FlagRule, Flags, rollout_bucket,
+};
+use starlight_axum::middleware::tenant::{HeaderTenantResolver, TenantLayer};
+use starlight_axum::testing::TelemetryCapture;
+use starlight_axum::tower::ServiceExt;
+use tracing::Instrument;
+
+async fn text(response: Response) -> String {
+    let bytes = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+#[test]
+fn percentage_rollouts_are_deterministic() {
+    assert_eq!(rollout_bucket("new_checkout", "user-1"), rollout_bucket("new_checkout", "user-1"));
+
+    let provider = EnvFlagProvider::new().with_flag("new-checkout", FlagRule::Percentage(30));
+    let enabled = |user: &str| provider.enabled("new_checkout", &FlagContext::new().with_user(user));
+    let users: Vec<String> = (0..2000).map(|i| format!("user-{}", i)).collect();
+    let first: Vec<bool> = users.iter().map(|user| enabled(user)).collect();
+    let second: Vec<bool> = users.iter().map(|user| enabled(user)).collect();
+    assert_eq!(first, second);
+
+    let share = first.iter().filter(|enabled| **enabled).count() as f64 / users.len() as f64;
+    assert!((0.25..0.35).contains(&share), "{}", share);
+
+    let none = EnvFlagProvider::new().with_flag("a", FlagRule::Percentage(0));
+    let all = EnvFlagProvider::new().with_flag("a", FlagRule::Percentage(100));
+    assert!(users.iter().all(|user| !none.enabled("a", &FlagContext::new().with_user(user.as_str()))));
+    assert!(users.iter().all(|user| all.enabled("a", &FlagContext::new().with_user(user.as_str()))));
+}
+
+#[test]
+fn reads_flags_from_the_environment() {
+    let vars = [("FEATURE_NEW_CHECKOUT", "on"), ("FEATURE_DARK_MODE", "0"), ("PATH", "/bin")];
+    let provider = EnvFlagProvider::from_vars("FEATURE_", vars.map(|(k, v)| (k.to_owned(), v.to_owned()))).unwrap();
+    assert!(provider.enabled("new-checkout", &FlagContext::new()));
+    assert!(!provider.enabled("dark_mode", &FlagContext::new()));
+    assert!(!provider.enabled("unknown", &FlagContext::new()));
+
+    let vars = [("FEATURE_A", "half"), ("FEATURE_B", "150%")];
+    let err = EnvFlagProvider::from_vars("FEATURE_", vars.map(|(k, v)| (k.to_owned(), v.to_owned()))).unwrap_err();
+    assert_eq!(err.errors().len(), 2);
+}
+
+#[tokio::test]
+async fn the_extractor_evaluates_for_the_tenant_and_user() {
+    let provider = EnvFlagProvider::new().with_flag("beta", FlagRule::On);
+    let app = Router::new()
+        .route(
+            "/flags",
+            get(|flags: Flags| async move {
+                let context = flags.context();
+                format!(
+                    "{} {} {} {} {}",
+                    flags.enabled("beta"),
+                    flags.enabled("gamma"),
+                    context.tenant.as_deref().unwrap_or("-"),
+                    context.user.as_deref().unwrap_or("-"),
+                    context.attributes["x-client"]
+                )
+            }),
+        )
+        .layer(FeatureFlagLayer::new(provider).with_attribute_headers([HeaderName::from_static("x-client")]))
+        .layer(TenantLayer::new(HeaderTenantResolver::default()))
+        .layer(starlight_axum::axum::Extension(Claims::new(
+            serde_json::json!({"sub": "user-7"}).as_object().unwrap().clone(),
+        )));
+
+    let request = Request::get("/flags")
+        .header("x-tenant-id", "acme")
+        .header("x-client", "ios")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(text(response).await, "true false acme user-7 ios");
+}
+
+#[tokio::test]
+async fn clones_can_be_configured_further() {
+    let base = FeatureFlagLayer::new(EnvFlagProvider::new());
+    let app = Router::new()
+        .route("/user", get(|flags: Flags| async move { flags.context().user.clone().unwrap_or_default() }))
+        .layer(base.clone().with_user_claim("email"))
+        .layer(starlight_axum::axum::Extension(Claims::new(
+            serde_json::json!({"sub": "user-7", "email": "ada@example.com"}).as_object().unwrap().clone(),
+        )));
+
+    let response = app.oneshot(Request::get("/user").body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(text(response).await, "ada@example.com");
+}
+
+#[tokio::test]
+async fn records_evaluated_flags_on_the_request_span() {
+    let capture = TelemetryCapture::install();
+    let provider = EnvFlagProvider::new()
+        .with_flag("beta", FlagRule::On)
+        .with_flag("unused", FlagRule::On);
+    let app = Router::new()
+        .route("/", get(|flags: Flags| async move { flags.enabled("beta").to_string() }))
+        .layer(FeatureFlagLayer::new(provider));
+
+    let response = app
+        .oneshot(Request::get("/").body(Body::empty()).unwrap())
+        .instrument(tracing::info_span!("http.request"))
+        .await
+        .unwrap();
+    assert_eq!(text(response).await, "true");
+
+    let span = capture.spans_named("http.request").remove(0);
+    let attribute = |key: &'static str| {
+        span.attributes
+            .iter()
+            .find(|attribute| attribute.key == Key::from_static_str(key))
+            .map(|attribute| attribute.value.clone())
+    };
+    assert_eq!(attribute("feature_flag.beta"), Some(Value::Bool(true)));
+    assert_eq!(attribute("feature_flag.unused"), None);
+}
@@ -0,0 +1,97 @@
+use opentelemetry::{Key, Value};
+use opentelemetry_sdk::trace::SpanData;
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::{Method, Request, StatusCode, header};
+use starlight_axum::client::TracedClient;
+use starlight_axum::service::{SERVICE_COMPLETIONS, SERVICE_STARTS};
+use starlight_axum::starlight_tokio::{CancellationToken, ServiceManager};
+use starlight_axum::testing::TelemetryCapture;
+use starlight_axum::{Listener, MeterLifecycleObserver};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+#[allow(dead_code)]
+#[path = "../examples/fullstack.rs"]
+mod fullstack;
+
+async fn send(addr: SocketAddr, method: Method, path: &str, body: &str) -> (StatusCode, serde_json::Value) {
+    let request = Request::builder()
+        .method(method)
+        .uri(format!("http://{}{}", addr, path))
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::ACCEPT_LANGUAGE, "vi")
+        .body(Body::from(body.to_owned()))
+        .unwrap();
+    let response = TracedClient::new().request(request).await.unwrap();
+    let status = response.status();
+    let bytes = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    (status, serde_json::from_slice(&bytes).unwrap())
+}
+
+fn attribute(span: &SpanData, key: &'static str) -> Option<Value> {
+    span.attributes
+        .iter()
+        .find(|attribute| attribute.key == Key::from_static_str(key))
+        .map(|attribute| attribute.value.clone())
+}
+
+#[tokio::test]
+async fn contacts_service_composes_the_crates_and_shuts_down_cleanly() {
+    let capture = TelemetryCapture::install();
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let manager = ServiceManager::new()
+        .with_observer(MeterLifecycleObserver::with_meter(&capture.meter()))
+        .with_service(fullstack::service(Listener::tcp(addr)).with_flush_telemetry(false));
+
+    let shutdown = CancellationToken::new();
+    let (summary, ()) = tokio::join!(manager.run(shutdown.clone()), async {
+        manager.wait_ready(Duration::from_secs(1)).await.unwrap();
+
+        let (status, body) = send(addr, Method::PUT, "/contacts/0912-345-678", r#"{"name": "Lan"}"#).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body,
+            serde_json::json!({"name": "Lan", "phone": "+84912345678", "country": "VN"})
+        );
+        let (status, body) = send(addr, Method::GET, "/contacts/+84912345678", "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["name"], "Lan");
+
+        let (status, body) = send(addr, Method::GET, "/contacts/12ab", "").await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            body,
+            serde_json::json!({"code": "contacts.invalid_phone", "message": "Số điện thoại không hợp lệ"})
+        );
+        let (status, body) = send(addr, Method::GET, "/contacts/0987654321", "").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["code"], "contacts.not_found");
+
+        shutdown.cancel();
+    });
+
+    assert!(summary.is_success(), "{:?}", summary.first_failure());
+    assert!(std::net::TcpStream::connect(addr).is_err(), "the listener is closed");
+
+    let requests = capture.spans_named("http.request");
+    assert_eq!(requests.len(), 4);
+    let errors: Vec<Option<Value>> = requests.iter().map(|span| attribute(span, "error.type")).collect();
+    assert!(
+        errors.contains(&Some(Value::from("contacts.invalid_phone"))),
+        "{:?}",
+        errors
+    );
+    assert!(
+        errors.contains(&Some(Value::from("contacts.not_found"))),
+        "{:?}",
+        errors
+    );
+    assert_eq!(capture.metric_sum(SERVICE_STARTS), 1.0);
+    assert_eq!(capture.metric_sum(SERVICE_COMPLETIONS), 1.0);
+}
@@ -0,0 +1,75 @@
+use opentelemetry::global;
+use opentelemetry::trace::SpanKind;
+use opentelemetry_proto::tonic::collector::trace::v1::trace_service_client::TraceServiceClient;
+use opentelemetry_proto::tonic::collector::trace::v1::trace_service_server::{TraceService, TraceServiceServer};
+use opentelemetry_proto::tonic::collector::trace::v1::{ExportTraceServiceRequest, ExportTraceServiceResponse};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use starlight_axum::grpc::GrpcTraceLayer;
+use starlight_axum::testing::TelemetryCapture;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::{Channel, Server};
+use tower::ServiceBuilder;
+
+const EXPORT: &str = "opentelemetry.proto.collector.trace.v1.TraceService/Export";
+
+/// Answers after a yield, so the handler span is entered after the layer's `call` returns.
+struct Collector;
+
+#[tonic::async_trait]
+impl TraceService for Collector {
+    async fn export(
+        &self,
+        _request: tonic::Request<ExportTraceServiceRequest>,
+    ) -> Result<tonic::Response<ExportTraceServiceResponse>, tonic::Status> {
+        tokio::task::yield_now().await;
+        let _handler = tracing::info_span!("handler").entered();
+        Ok(tonic::Response::new(ExportTraceServiceResponse::default()))
+    }
+}
+
+async fn serve() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .layer(GrpcTraceLayer::server())
+            .add_service(TraceServiceServer::new(Collector))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    addr
+}
+
+#[tokio::test]
+async fn handlers_run_inside_the_call_span() {
+    let capture = TelemetryCapture::install();
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    let addr = serve().await;
+
+    let channel = Channel::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect_timeout(Duration::from_secs(5))
+        .connect()
+        .await
+        .unwrap();
+    let mut client = TraceServiceClient::new(ServiceBuilder::new().layer(GrpcTraceLayer::client()).service(channel));
+    client.export(ExportTraceServiceRequest::default()).await.unwrap();
+
+    let calls = capture.spans_named(EXPORT);
+    let server = calls
+        .iter()
+        .find(|span| span.span_kind == SpanKind::Server)
+        .expect("server span");
+    let client = calls
+        .iter()
+        .find(|span| span.span_kind == SpanKind::Client)
+        .expect("client span");
+    assert_eq!(server.parent_span_id, client.span_context.span_id());
+    assert_eq!(server.span_context.trace_id(), client.span_context.trace_id());
+
+    let handler = capture.spans_named("handler").pop().expect("handler span");
+    assert_eq!(handler.parent_span_id, server.span_context.span_id());
+    assert_eq!(handler.span_context.trace_id(), server.span_context.trace_id());
+}
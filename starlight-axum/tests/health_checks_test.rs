@@ -0,0 +1,174 @@
+use opentelemetry::metrics::MeterProvider;
+use opentelemetry_sdk::metrics::data::{AggregatedMetrics, MetricData};
+use opentelemetry_sdk::metrics::{InMemoryMetricExporter, PeriodicReader, SdkMeterProvider};
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::{Request, StatusCode};
+use starlight_axum::health::{
+    CheckStatus, Criticality, HEALTH_CHECK_UP, HealthCheck, HealthChecks, InFlightTracker, Readiness,
+    readiness_router_with_checks,
+};
+use starlight_axum::tower::ServiceExt;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+fn passing(name: &str, criticality: Criticality) -> HealthCheck {
+    HealthCheck::new(name, criticality, || async { Ok(()) })
+}
+
+fn failing(name: &str, criticality: Criticality) -> HealthCheck {
+    HealthCheck::new(name, criticality, || async {
+        Err(anyhow::anyhow!("connection refused"))
+    })
+}
+
+async fn readiness(checks: HealthChecks) -> (StatusCode, Readiness) {
+    let request = Request::get("/health/ready").body(Body::empty()).unwrap();
+    let response = readiness_router_with_checks(InFlightTracker::new(), checks)
+        .oneshot(request)
+        .await
+        .unwrap();
+    let status = response.status();
+    let bytes = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    (status, serde_json::from_slice(&bytes).unwrap())
+}
+
+fn statuses(readiness: &Readiness) -> Vec<(&str, CheckStatus)> {
+    readiness
+        .checks
+        .iter()
+        .map(|report| (report.name.as_str(), report.status))
+        .collect()
+}
+
+#[tokio::test]
+async fn only_a_failing_critical_check_makes_the_service_unready() {
+    let (status, body) = readiness(
+        HealthChecks::new()
+            .with_check(passing("database", Criticality::Critical))
+            .with_check(failing("recommendations", Criticality::Degraded))
+            .with_check(failing("analytics", Criticality::Informational)),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.ready);
+    assert_eq!(
+        statuses(&body),
+        [
+            ("database", CheckStatus::Up),
+            ("recommendations", CheckStatus::Degraded),
+            ("analytics", CheckStatus::Failed),
+        ]
+    );
+    assert_eq!(body.checks[1].error.as_deref(), Some("connection refused"));
+
+    let (status, body) = readiness(
+        HealthChecks::new()
+            .with_check(failing("database", Criticality::Critical))
+            .with_check(passing("recommendations", Criticality::Degraded)),
+    )
+    .await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert!(!body.ready);
+    assert_eq!(
+        statuses(&body),
+        [("database", CheckStatus::Down), ("recommendations", CheckStatus::Up)]
+    );
+}
+
+#[tokio::test]
+async fn slow_checks_fail_at_their_timeout() {
+    let slow = HealthCheck::new("search", Criticality::Critical, || async {
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        Ok(())
+    })
+    .with_timeout(Duration::from_millis(20));
+    let (status, body) = readiness(HealthChecks::new().with_check(slow)).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body.checks[0].error.as_deref(), Some("timed out after 20ms"));
+}
+
+#[tokio::test]
+async fn dependents_of_a_failed_check_are_skipped_without_probing() {
+    let probes = Arc::new(AtomicUsize::new(0));
+    let counted = |name: &str, criticality| {
+        let probes = probes.clone();
+        HealthCheck::new(name, criticality, move || {
+            probes.fetch_add(1, Ordering::SeqCst);
+            async { Ok(()) }
+        })
+    };
+    let checks = HealthChecks::new()
+        .with_check(counted("payments", Criticality::Critical).requires("proxy"))
+        .with_check(counted("refunds", Criticality::Degraded).requires("payments"))
+        .with_check(failing("proxy", Criticality::Degraded))
+        .with_check(counted("cache", Criticality::Degraded))
+        .with_check(counted("ledger", Criticality::Informational).requires("cache"))
+        .with_check(counted("orphan", Criticality::Critical).requires("missing"));
+
+    let (status, body) = readiness(checks).await;
+    assert_eq!(probes.load(Ordering::SeqCst), 2);
+    // A skipped critical check is not known to be down.
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        statuses(&body),
+        [
+            ("payments", CheckStatus::Skipped),
+            ("refunds", CheckStatus::Skipped),
+            ("proxy", CheckStatus::Degraded),
+            ("cache", CheckStatus::Up),
+            ("ledger", CheckStatus::Up),
+            ("orphan", CheckStatus::Skipped),
+        ]
+    );
+    assert_eq!(
+        body.checks[1].error.as_deref(),
+        Some("requires payments, which did not pass")
+    );
+}
+
+#[tokio::test]
+async fn each_check_is_exported_as_a_gauge() {
+    let exporter = InMemoryMetricExporter::default();
+    let provider = SdkMeterProvider::builder()
+        .with_reader(PeriodicReader::builder(exporter.clone()).build())
+        .build();
+    let checks = HealthChecks::with_meter(&provider.meter("health"))
+        .with_check(passing("database", Criticality::Critical))
+        .with_check(failing("recommendations", Criticality::Degraded));
+    checks.run().await;
+    provider.force_flush().unwrap();
+
+    let mut up = HashMap::new();
+    for metric in exporter
+        .get_finished_metrics()
+        .unwrap()
+        .iter()
+        .flat_map(|resource| resource.scope_metrics())
+        .flat_map(|scope| scope.metrics())
+        .filter(|metric| metric.name() == HEALTH_CHECK_UP)
+    {
+        let AggregatedMetrics::U64(MetricData::Gauge(gauge)) = metric.data() else {
+            panic!("{} is not a u64 gauge", HEALTH_CHECK_UP);
+        };
+        for point in gauge.data_points() {
+            let attribute = |key: &str| {
+                point
+                    .attributes()
+                    .find(|attribute| attribute.key.as_str() == key)
+                    .map(|attribute| attribute.value.to_string())
+                    .unwrap()
+            };
+            up.insert(
+                (attribute("health.check.name"), attribute("health.check.criticality")),
+                point.value(),
+            );
+        }
+    }
+    assert_eq!(up[&("database".to_owned(), "critical".to_owned())], 1);
+    assert_eq!(up[&("recommendations".to_owned(), "degraded".to_owned())], 0);
+}
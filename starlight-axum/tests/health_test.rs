@@ -0,0 +1,129 @@
+#![cfg(unix)]
+
+use starlight_axum::axum::Router;
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::{Request, StatusCode};
+use starlight_axum::axum::routing::get;
+use starlight_axum::health::{InFlightTracker, Readiness, readiness_router};
+use starlight_axum::serve::{Listener, serve_many_with_tracker};
+use starlight_axum::tower::ServiceExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+use tokio::sync::{Notify, oneshot};
+
+fn socket_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("starlight-health-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join("app.sock")
+}
+
+async fn get_slow(path: &Path) -> String {
+    for _ in 0..100 {
+        if let Ok(mut stream) = UnixStream::connect(path).await {
+            stream
+                .write_all(b"GET /slow HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+                .await
+                .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            return response;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("server did not start on {}", path.display());
+}
+
+async fn readiness(tracker: &InFlightTracker) -> (StatusCode, Readiness) {
+    let request = Request::get("/health/ready").body(Body::empty()).unwrap();
+    let response = readiness_router(tracker.clone()).oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    (status, serde_json::from_slice(&bytes).unwrap())
+}
+
+async fn eventually(condition: impl Fn() -> bool) {
+    for _ in 0..200 {
+        if condition() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    panic!("condition not met");
+}
+
+#[tokio::test]
+async fn reports_the_drain_until_in_flight_requests_complete() {
+    let path = socket_path("drain");
+    let tracker = InFlightTracker::new();
+    let release = Arc::new(Notify::new());
+    let router = {
+        let release = release.clone();
+        Router::new().route(
+            "/slow",
+            get(move || async move {
+                release.notified().await;
+                "done"
+            }),
+        )
+    };
+    let (stop_tx, stop_rx) = oneshot::channel::<()>();
+    let server = tokio::spawn(serve_many_with_tracker(
+        vec![(Listener::unix(&path), router)],
+        async move {
+            let _ = stop_rx.await;
+        },
+        tracker.clone(),
+    ));
+
+    let (status, ready) = readiness(&tracker).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(ready.ready && !ready.draining && ready.drain_elapsed_ms.is_none());
+
+    let slow = tokio::spawn({
+        let path = path.clone();
+        async move { get_slow(&path).await }
+    });
+    eventually(|| tracker.in_flight() == 1).await;
+
+    stop_tx.send(()).unwrap();
+    eventually(|| tracker.is_draining()).await;
+    let (status, draining) = readiness(&tracker).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert!(!draining.ready && draining.draining);
+    assert_eq!(draining.in_flight, 1);
+    assert!(draining.drain_elapsed_ms.is_some());
+
+    assert!(!tracker.wait_idle(Duration::from_millis(50)).await);
+    release.notify_one();
+    assert!(tracker.wait_idle(Duration::from_secs(5)).await);
+    assert!(slow.await.unwrap().ends_with("done"));
+    server.await.unwrap().unwrap();
+    assert_eq!(readiness(&tracker).await.1.in_flight, 0);
+}
+
+#[tokio::test]
+async fn wait_idle_resolves_when_the_last_request_completes() {
+    let tracker = InFlightTracker::new();
+    assert!(tracker.wait_idle(Duration::ZERO).await);
+
+    let first = tracker.track();
+    let second = tracker.track();
+    let waiter = tokio::spawn({
+        let tracker = tracker.clone();
+        async move { tracker.wait_idle(Duration::from_secs(30)).await }
+    });
+    drop(first);
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(!waiter.is_finished());
+    drop(second);
+    assert!(waiter.await.unwrap());
+
+    let _stuck = tracker.track();
+    assert!(!tracker.wait_idle(Duration::from_millis(20)).await);
+}
@@ -0,0 +1,155 @@
+use starlight_axum::axum::Router;
+use starlight_axum::axum::body::{Body, Bytes};
+use starlight_axum::axum::http::{HeaderMap, HeaderValue, Request, StatusCode};
+use starlight_axum::axum::response::{IntoResponse, Response};
+use starlight_axum::axum::routing::post;
+use starlight_axum::middleware::hooks::{Hook, HookDecision, HookLayer};
+use starlight_axum::tower::ServiceExt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Echoes the body, and the `x-trail` header of the request in the response.
+fn app(calls: Arc<AtomicUsize>, layer: HookLayer) -> Router {
+    let echo = move |headers: HeaderMap, body: Bytes| async move {
+        calls.fetch_add(1, Ordering::SeqCst);
+        let trail = headers.get("x-trail").cloned().unwrap_or(HeaderValue::from_static(""));
+        ([("x-trail", trail)], body)
+    };
+    Router::new()
+        .route("/echo", post(echo.clone()))
+        .route("/canary/echo", post(echo))
+        .layer(layer)
+}
+
+async fn send(app: Router, path: &str, headers: &[(&str, &str)], body: &'static str) -> (Response, String) {
+    let mut request = Request::post(path);
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let response = app.oneshot(request.body(Body::from(body)).unwrap()).await.unwrap();
+    let (parts, body) = response.into_parts();
+    let bytes = http_body_util::BodyExt::collect(body).await.unwrap().to_bytes();
+    (
+        Response::from_parts(parts, Body::empty()),
+        String::from_utf8(bytes.to_vec()).unwrap(),
+    )
+}
+
+fn append(headers: &mut HeaderMap, step: &str) {
+    let trail = match headers.get("x-trail").and_then(|value| value.to_str().ok()) {
+        Some(trail) if !trail.is_empty() => format!("{},{}", trail, step),
+        _ => step.to_owned(),
+    };
+    headers.insert("x-trail", HeaderValue::from_str(&trail).unwrap());
+}
+
+/// A hook adding `name` to the trail of the request and the response.
+fn tracing_hook(name: &'static str, order: i32) -> Hook {
+    Hook::new(name)
+        .with_order(order)
+        .on_request(move |parts, _body| {
+            Box::pin(async move {
+                append(&mut parts.headers, name);
+                HookDecision::Continue
+            })
+        })
+        .on_response(move |parts, _body| Box::pin(async move { append(&mut parts.headers, name) }))
+}
+
+#[tokio::test]
+async fn hooks_run_in_order_on_the_request_and_in_reverse_on_the_response() {
+    let uppercase = Hook::new("uppercase").with_order(2).on_request(|_parts, body| {
+        Box::pin(async move {
+            let upper = body.get().map(|bytes| bytes.to_ascii_uppercase()).unwrap_or_default();
+            body.set(upper);
+            HookDecision::Continue
+        })
+    });
+    let layer = HookLayer::new()
+        .with_hook(tracing_hook("second", 2))
+        .with_hook(uppercase)
+        .with_hook(tracing_hook("first", 1));
+    let calls = Arc::new(AtomicUsize::new(0));
+
+    let (response, body) = send(app(calls.clone(), layer), "/echo", &[], "hello").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["x-trail"], "first,second,second,first");
+    assert_eq!(body, "HELLO");
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn a_hook_can_answer_instead_of_the_handler() {
+    let canary = Hook::new("canary").with_paths(["/canary"]).on_request(|parts, _body| {
+        Box::pin(async move {
+            if parts.headers.contains_key("x-canary") {
+                HookDecision::ShortCircuit((StatusCode::IM_A_TEAPOT, "canary").into_response())
+            } else {
+                HookDecision::Continue
+            }
+        })
+    });
+    let layer = HookLayer::new().with_hook(canary).with_hook(tracing_hook("after", 1));
+    let calls = Arc::new(AtomicUsize::new(0));
+
+    let (response, body) = send(
+        app(calls.clone(), layer.clone()),
+        "/canary/echo",
+        &[("x-canary", "1")],
+        "hi",
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::IM_A_TEAPOT);
+    assert_eq!(body, "canary");
+    assert!(!response.headers().contains_key("x-trail"));
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+    // Only under its paths.
+    let (response, body) = send(app(calls.clone(), layer), "/echo", &[("x-canary", "1")], "hi").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(body, "hi");
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn a_panicking_hook_is_disabled_and_the_request_carries_on() {
+    let runs = Arc::new(AtomicUsize::new(0));
+    let counted = runs.clone();
+    let broken = Hook::new("broken").on_request(move |_parts, _body| {
+        counted.fetch_add(1, Ordering::SeqCst);
+        Box::pin(async { panic!("experiment went wrong") })
+    });
+    let layer = HookLayer::new()
+        .with_hook(broken.clone())
+        .with_hook(tracing_hook("healthy", 1));
+    let calls = Arc::new(AtomicUsize::new(0));
+
+    for _ in 0..2 {
+        let (response, body) = send(app(calls.clone(), layer.clone()), "/echo", &[], "hi").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-trail"], "healthy,healthy");
+        assert_eq!(body, "hi");
+    }
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+    assert!(broken.is_disabled());
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn bodies_over_the_budget_stream_through_unbuffered() {
+    let seen = Arc::new(AtomicUsize::new(usize::MAX));
+    let observed = seen.clone();
+    let peek = Hook::new("peek").on_request(move |_parts, body| {
+        observed.store(body.get().map_or(0, Bytes::len), Ordering::SeqCst);
+        Box::pin(async { HookDecision::Continue })
+    });
+    let layer = HookLayer::new().with_hook(peek).with_body_budget(4);
+    let calls = Arc::new(AtomicUsize::new(0));
+
+    let (_, body) = send(app(calls.clone(), layer.clone()), "/echo", &[], "too long").await;
+    assert_eq!(body, "too long");
+    assert_eq!(seen.load(Ordering::SeqCst), 0);
+
+    send(app(calls, layer), "/echo", &[], "tiny").await;
+    assert_eq!(seen.load(Ordering::SeqCst), 4);
+}
@@ -0,0 +1,157 @@
+use starlight_axum::axum::Router;
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::{Request, StatusCode};
+use starlight_axum::axum::response::Response;
+use starlight_axum::axum::routing::post;
+use starlight_axum::middleware::idempotency::{
+    IdempotencyLayer, IdempotencyStore, MemoryIdempotencyStore, StoredResponse,
+};
+use starlight_axum::tower::ServiceExt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+
+fn app(calls: Arc<AtomicUsize>, delay: Duration) -> Router {
+    Router::new()
+        .route(
+            "/payments",
+            post(move || async move {
+                let n = calls.fetch_add(1, Ordering::SeqCst) + 1;
+                tokio::time::sleep(delay).await;
+                format!("payment #{}", n)
+            }),
+        )
+        .layer(IdempotencyLayer::new(MemoryIdempotencyStore::new(100)))
+}
+
+fn payment(key: &str) -> Request<Body> {
+    Request::post("/payments")
+        .header("idempotency-key", key)
+        .body(Body::empty())
+        .unwrap()
+}
+
+async fn body_of(response: Response) -> String {
+    let bytes = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn retry_replays_the_original_response() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let app = app(calls.clone(), Duration::ZERO);
+
+    let first = app.clone().oneshot(payment("abc")).await.unwrap();
+    assert!(first.headers().get("idempotency-replayed").is_none());
+    assert_eq!(body_of(first).await, "payment #1");
+
+    let retry = app.clone().oneshot(payment("abc")).await.unwrap();
+    assert_eq!(retry.status(), StatusCode::OK);
+    assert_eq!(retry.headers()["idempotency-replayed"], "true");
+    assert_eq!(body_of(retry).await, "payment #1");
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn concurrent_duplicate_gets_conflict() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let app = app(calls.clone(), Duration::from_millis(200));
+
+    let first = tokio::spawn(app.clone().oneshot(payment("slow")));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let duplicate = app.clone().oneshot(payment("slow")).await.unwrap();
+    assert_eq!(duplicate.status(), StatusCode::CONFLICT);
+
+    assert_eq!(first.await.unwrap().unwrap().status(), StatusCode::OK);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn different_keys_execute_independently() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let app = app(calls.clone(), Duration::ZERO);
+
+    let a = app.clone().oneshot(payment("a")).await.unwrap();
+    let b = app.clone().oneshot(payment("b")).await.unwrap();
+    assert_eq!(body_of(a).await, "payment #1");
+    assert_eq!(body_of(b).await, "payment #2");
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn keys_are_scoped_to_the_caller() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let app = app(calls.clone(), Duration::ZERO);
+    let as_caller = |token: &str| {
+        Request::post("/payments")
+            .header("idempotency-key", "shared")
+            .header("authorization", format!("Bearer {}", token))
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let alice = app.clone().oneshot(as_caller("alice")).await.unwrap();
+    let mallory = app.clone().oneshot(as_caller("mallory")).await.unwrap();
+    assert!(mallory.headers().get("idempotency-replayed").is_none());
+    assert_eq!(body_of(alice).await, "payment #1");
+    assert_eq!(body_of(mallory).await, "payment #2");
+
+    let retry = app.clone().oneshot(as_caller("alice")).await.unwrap();
+    assert_eq!(retry.headers()["idempotency-replayed"], "true");
+    assert_eq!(body_of(retry).await, "payment #1");
+}
+
+/// Holds lookups for a while once `slow` is set, after reading the entry.
+struct SlowLookupStore {
+    inner: MemoryIdempotencyStore,
+    slow: Arc<AtomicBool>,
+}
+
+impl IdempotencyStore for SlowLookupStore {
+    async fn get(&self, key: &str) -> Option<StoredResponse> {
+        let stored = self.inner.get(key).await;
+        if self.slow.load(Ordering::SeqCst) {
+            tokio::time::sleep(Duration::from_millis(150)).await;
+        }
+        stored
+    }
+
+    async fn put(&self, key: &str, response: StoredResponse, ttl: Duration) {
+        self.inner.put(key, response, ttl).await
+    }
+}
+
+#[tokio::test]
+async fn retry_missing_the_store_as_the_first_finishes_replays() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let slow = Arc::new(AtomicBool::new(false));
+    let handler_calls = calls.clone();
+    let app = Router::new()
+        .route(
+            "/payments",
+            post(move || async move {
+                let n = handler_calls.fetch_add(1, Ordering::SeqCst) + 1;
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                format!("payment #{}", n)
+            }),
+        )
+        .layer(IdempotencyLayer::new(SlowLookupStore {
+            inner: MemoryIdempotencyStore::new(100),
+            slow: slow.clone(),
+        }));
+
+    let first = tokio::spawn(app.clone().oneshot(payment("race")));
+    tokio::time::sleep(Duration::from_millis(30)).await;
+    // The retry misses the store, then the first stores its response and leaves
+    // before the retry claims the key.
+    slow.store(true, Ordering::SeqCst);
+    let retry = app.clone().oneshot(payment("race")).await.unwrap();
+
+    assert_eq!(body_of(first.await.unwrap().unwrap()).await, "payment #1");
+    assert_eq!(retry.headers()["idempotency-replayed"], "true");
+    assert_eq!(body_of(retry).await, "payment #1");
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
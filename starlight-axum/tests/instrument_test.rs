@@ -0,0 +1,128 @@
+use opentelemetry::trace::Status;
+use opentelemetry::{Key, Value};
+use opentelemetry_sdk::trace::SpanData;
+use starlight_axum::deadline::Deadline;
+use starlight_axum::instrument::{MAX_STATEMENT_LEN, OperationError, QueryLogger, Reveal, SpanExt, op, query};
+use starlight_axum::testing::TelemetryCapture;
+use std::fmt::Display;
+use std::time::Duration;
+use tracing::Instrument;
+
+fn attribute(span: &SpanData, key: &'static str) -> Option<Value> {
+    span.attributes
+        .iter()
+        .find(|attribute| attribute.key == Key::from_static_str(key))
+        .map(|attribute| attribute.value.clone())
+}
+
+#[tokio::test]
+async fn operations_are_children_of_the_request_span() {
+    let capture = TelemetryCapture::install();
+    let request = tracing::info_span!("http.request");
+    let rows = async {
+        op("db.query", &[("db.system", "postgres")], async { Ok::<_, String>(3) }).await
+    }
+    .instrument(request.clone())
+    .await;
+    let cached = request
+        .child("cache.get", &[("db.system", "redis")])
+        .with_attribute("db.operation.name", "GET")
+        .run(async { Ok::<_, String>("hit") })
+        .await;
+    drop(request);
+
+    assert_eq!(rows, Ok(3));
+    assert_eq!(cached, Ok("hit"));
+    let parent = capture.spans_named("http.request").remove(0);
+    let query = capture.spans_named("db.query").remove(0);
+    let get = capture.spans_named("cache.get").remove(0);
+    assert_eq!(query.parent_span_id, parent.span_context.span_id());
+    assert_eq!(get.parent_span_id, parent.span_context.span_id());
+    assert_eq!(query.span_kind, opentelemetry::trace::SpanKind::Client);
+    assert_eq!(attribute(&query, "db.system"), Some(Value::from("postgres")));
+    assert_eq!(attribute(&get, "db.operation.name"), Some(Value::from("GET")));
+    assert_eq!(query.status, Status::Unset);
+}
+
+#[tokio::test]
+async fn records_errors_returned_by_the_operation() {
+    let capture = TelemetryCapture::install();
+    let result = op("db.query", &[("db.system", "postgres")], async {
+        Err::<(), _>("connection refused")
+    })
+    .await;
+
+    assert_eq!(result, Err(OperationError::Failed("connection refused")));
+    let query = capture.spans_named("db.query").remove(0);
+    assert_eq!(query.status, Status::error("connection refused"));
+    assert_eq!(attribute(&query, "error.type"), Some(Value::from("&str")));
+}
+
+#[tokio::test]
+async fn cancels_operations_when_the_deadline_passes() {
+    let capture = TelemetryCapture::install();
+    let deadline = Deadline::after(Duration::from_millis(20));
+    let result = deadline
+        .scope(op("db.query", &[], async {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            Ok::<_, String>(())
+        }))
+        .await;
+
+    assert_eq!(result, Err(OperationError::DeadlineExceeded));
+    let query = capture.spans_named("db.query").remove(0);
+    assert_eq!(query.status, Status::error("request deadline exceeded"));
+    assert_eq!(attribute(&query, "error.type"), Some(Value::from("deadline_exceeded")));
+}
+
+#[tokio::test]
+async fn queries_are_logged_without_their_literals() {
+    let capture = TelemetryCapture::install();
+    let request = tracing::info_span!("http.request");
+    let long = format!("SELECT id FROM users WHERE id IN ({})", vec!["1234"; 1000].join(", "));
+    request.in_scope(|| {
+        query(
+            "db.insert_user",
+            "INSERT INTO users (name, email, age, score)\n  VALUES ('Ada Lovelace', 'ada@example.com', 36, -1.5)",
+            &[],
+        );
+        request.query("db.find_users", &long, &[]);
+    });
+    drop(request);
+
+    let insert = capture.spans_named("db.insert_user").remove(0);
+    assert_eq!(
+        attribute(&insert, "db.statement"),
+        Some(Value::from("INSERT INTO users (name, email, age, score) VALUES (?, ?, ?, -?)"))
+    );
+    assert_eq!(attribute(&insert, "db.query.parameter_count"), Some(Value::I64(0)));
+    let find = capture.spans_named("db.find_users").remove(0);
+    let statement = attribute(&find, "db.statement").unwrap().as_str().into_owned();
+    assert_eq!(statement.chars().count(), MAX_STATEMENT_LEN + 3);
+    assert!(statement.starts_with("SELECT id FROM users WHERE id IN (?, ?, "));
+    assert!(statement.ends_with("..."));
+    assert!(!statement.contains("1234"));
+}
+
+#[tokio::test]
+async fn only_revealed_parameters_are_logged() {
+    let capture = TelemetryCapture::install();
+    let (name, card, age) = ("Ada Lovelace", "4111111111111111", 36);
+    let params: [&dyn Display; 3] = [&name, &card, &age];
+    let guard = query(
+        "db.insert_user",
+        "INSERT INTO users (name, card, age) VALUES ($1, $2, $3)",
+        &params,
+    )
+    .log_params(&[Reveal::Last(1, 4), Reveal::Full(2), Reveal::Full(7)]);
+    guard.rows_affected(1);
+    drop(guard);
+
+    let insert = capture.spans_named("db.insert_user").remove(0);
+    assert_eq!(attribute(&insert, "db.query.parameter_count"), Some(Value::I64(3)));
+    assert_eq!(attribute(&insert, "db.query.parameter.0"), None);
+    assert_eq!(attribute(&insert, "db.query.parameter.1"), Some(Value::from("…1111")));
+    assert_eq!(attribute(&insert, "db.query.parameter.2"), Some(Value::from("36")));
+    assert_eq!(attribute(&insert, "db.response.rows_affected"), Some(Value::I64(1)));
+    assert!(insert.attributes.iter().all(|attribute| !attribute.value.as_str().contains("Ada")));
+}
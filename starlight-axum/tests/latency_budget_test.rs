@@ -0,0 +1,82 @@
+use opentelemetry::{Key, Value};
+use opentelemetry_sdk::trace::SpanData;
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::Request;
+use starlight_axum::axum::routing::get;
+use starlight_axum::axum::{Extension, Router};
+use starlight_axum::middleware::latency_budget::{LATENCY_BUDGET_EXCEEDED, LatencyBudget, LatencyBudgetLayer, Millis};
+use starlight_axum::middleware::trace_middleware;
+use starlight_axum::testing::TelemetryCapture;
+use starlight_axum::tower::ServiceExt;
+use std::time::Duration;
+
+fn attribute(span: &SpanData, key: &'static str) -> Option<Value> {
+    span.attributes
+        .iter()
+        .find(|attribute| attribute.key == Key::from_static_str(key))
+        .map(|attribute| attribute.value.clone())
+}
+
+async fn sleep_for(millis: u64) -> &'static str {
+    tokio::time::sleep(Duration::from_millis(millis)).await;
+    "done"
+}
+
+#[tokio::test]
+async fn only_routes_over_their_budget_are_flagged() {
+    let capture = TelemetryCapture::install();
+    let meter = capture.meter();
+    let app = Router::new()
+        .route(
+            "/search",
+            get(|| sleep_for(60)).route_layer(LatencyBudgetLayer::with_meter(20.ms(), &meter)),
+        )
+        .route(
+            "/checkout",
+            get(
+                |Extension(LatencyBudget(budget)): Extension<LatencyBudget>| async move {
+                    sleep_for(10).await;
+                    format!("{:?}", budget)
+                },
+            )
+            .route_layer(LatencyBudgetLayer::with_meter(800.ms(), &meter)),
+        )
+        .route("/health", get(|| sleep_for(60)))
+        .layer(trace_middleware());
+
+    for path in ["/search", "/checkout", "/health"] {
+        let response = app
+            .clone()
+            .oneshot(Request::get(path).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+    }
+
+    let spans = capture.spans_named("http.request");
+    assert_eq!(spans.len(), 3);
+    let flags: Vec<_> = spans
+        .iter()
+        .map(|span| {
+            (
+                attribute(span, "latency.budget_ms"),
+                attribute(span, "latency.budget_exceeded"),
+            )
+        })
+        .collect();
+    assert_eq!(
+        flags,
+        [
+            (Some(Value::I64(20)), Some(Value::Bool(true))),
+            (Some(Value::I64(800)), Some(Value::Bool(false))),
+            (None, None),
+        ]
+    );
+
+    assert_eq!(capture.metric_sum(LATENCY_BUDGET_EXCEEDED), 1.0);
+    let warnings = capture.events_with_target("starlight_axum::middleware::latency_budget");
+    assert_eq!(warnings.len(), 1);
+    let message = format!("{:?}", warnings[0].body());
+    assert!(message.contains("/search took"), "{}", message);
+    assert!(message.contains("over its budget of 20ms"), "{}", message);
+}
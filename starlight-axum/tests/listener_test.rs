@@ -0,0 +1,69 @@
+use starlight_axum::ListenerConfig;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+
+fn count_accepts(listener: TcpListener) -> Arc<AtomicUsize> {
+    let accepted = Arc::new(AtomicUsize::new(0));
+    let counter = accepted.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            counter.fetch_add(1, Ordering::SeqCst);
+            drop(stream);
+        }
+    });
+    accepted
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn reuse_port_listeners_share_the_port() {
+    let config = ListenerConfig::new().with_reuse_port(true).with_nodelay(true);
+    let first = config.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = first.local_addr().unwrap();
+    let second = config.bind(addr).unwrap();
+
+    let (first, second) = (count_accepts(first), count_accepts(second));
+    for _ in 0..64 {
+        TcpStream::connect(addr).await.unwrap();
+    }
+    for _ in 0..200 {
+        if first.load(Ordering::SeqCst) + second.load(Ordering::SeqCst) == 64 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    assert!(first.load(Ordering::SeqCst) > 0);
+    assert!(second.load(Ordering::SeqCst) > 0);
+}
+
+#[tokio::test]
+async fn bind_errors_name_the_address_and_options() {
+    let taken = ListenerConfig::new().bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = taken.local_addr().unwrap();
+    let err = ListenerConfig::new().with_backlog(16).bind(addr).unwrap_err();
+    let message = err.to_string();
+    assert!(message.contains(&addr.to_string()), "{}", message);
+    assert!(message.contains("reuse_port=false"), "{}", message);
+    assert!(message.contains("backlog=16"), "{}", message);
+}
+
+#[tokio::test]
+async fn dual_stack_listeners_accept_ipv4() {
+    let Ok(listener) = ListenerConfig::new().dual_stack().bind("[::]:0".parse().unwrap()) else {
+        // No IPv6 on this host.
+        return;
+    };
+    let port = listener.local_addr().unwrap().port();
+    let accepted = count_accepts(listener);
+    TcpStream::connect(SocketAddr::from(([127, 0, 0, 1], port))).await.unwrap();
+    for _ in 0..200 {
+        if accepted.load(Ordering::SeqCst) == 1 {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    panic!("the IPv4 connection was not accepted");
+}
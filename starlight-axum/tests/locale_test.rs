@@ -0,0 +1,143 @@
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::{HeaderMap, Request, StatusCode, Uri, header};
+use starlight_axum::axum::routing::get;
+use starlight_axum::axum::{Extension, Router};
+use starlight_axum::middleware::locale::{I18nErrorResponse, Locale, LocaleLayer, parse_accept_language};
+use starlight_axum::tower::ServiceExt;
+use starlight_i18n::I18nCode;
+use starlight_protocol::i18n::Catalog;
+use starlight_protocol::validation::ValidationErrors;
+use std::sync::Arc;
+
+#[derive(I18nCode, Debug)]
+#[i18n(protocol)]
+enum OrderError {
+    #[i18n_code("order.not_found")]
+    NotFound,
+    #[i18n_code("order.invalid_quantity")]
+    InvalidQuantity,
+}
+
+fn layer() -> LocaleLayer {
+    LocaleLayer::new(["en", "vi"], "en")
+        .with_query_param("lang")
+        .with_cookie("lang")
+}
+
+fn headers(pairs: &[(header::HeaderName, &str)]) -> HeaderMap {
+    pairs
+        .iter()
+        .map(|(name, value)| (name.clone(), value.parse().unwrap()))
+        .collect()
+}
+
+#[test]
+fn accept_language_is_ordered_by_quality() {
+    assert_eq!(
+        parse_accept_language("en;q=0.5, vi-VN, fr;q=0.8, de;q=0, *;q=0.1"),
+        vec!["vi-VN", "fr", "en"]
+    );
+}
+
+#[test]
+fn query_beats_cookie_beats_header() {
+    let layer = layer();
+    let uri: Uri = "/orders?lang=vi".parse().unwrap();
+    let both = headers(&[(header::COOKIE, "a=1; lang=en"), (header::ACCEPT_LANGUAGE, "en")]);
+    assert_eq!(layer.resolve(&uri, &both).as_str(), "vi");
+
+    let uri: Uri = "/orders".parse().unwrap();
+    let cookie = headers(&[(header::COOKIE, "lang=vi"), (header::ACCEPT_LANGUAGE, "en")]);
+    assert_eq!(layer.resolve(&uri, &cookie).as_str(), "vi");
+
+    let header_only = headers(&[(header::ACCEPT_LANGUAGE, "fr, vi-VN;q=0.9, en;q=0.8")]);
+    assert_eq!(layer.resolve(&uri, &header_only).as_str(), "vi");
+}
+
+#[test]
+fn query_values_are_percent_decoded() {
+    let uri: Uri = "/orders?lang=%76%69".parse().unwrap();
+    assert_eq!(layer().resolve(&uri, &HeaderMap::new()).as_str(), "vi");
+
+    let layer = LocaleLayer::new(["en", "zh-Hant"], "en").with_query_param("lang");
+    let uri: Uri = "/orders?page=2&lang=zh%2DHant".parse().unwrap();
+    assert_eq!(layer.resolve(&uri, &HeaderMap::new()).as_str(), "zh-Hant");
+}
+
+#[test]
+fn unsupported_locales_fall_back_to_default() {
+    let layer = layer();
+    let uri: Uri = "/orders?lang=ja".parse().unwrap();
+    let unsupported = headers(&[(header::ACCEPT_LANGUAGE, "fr, de")]);
+    assert_eq!(layer.resolve(&uri, &unsupported).as_str(), "en");
+    assert_eq!(layer.resolve(&uri, &HeaderMap::new()).as_str(), "en");
+}
+
+#[tokio::test]
+async fn handler_error_is_translated_into_request_locale() {
+    let catalog = Arc::new(
+        Catalog::new()
+            .with("en", "order.not_found", "Order not found")
+            .with("vi", "order.not_found", "Không tìm thấy đơn hàng"),
+    );
+    let app = Router::new()
+        .route(
+            "/orders/1",
+            get(
+                |locale: Locale, Extension(catalog): Extension<Arc<Catalog>>| async move {
+                    I18nErrorResponse::new(StatusCode::NOT_FOUND, OrderError::NotFound, &locale, &*catalog)
+                },
+            ),
+        )
+        .layer(Extension(catalog))
+        .layer(layer());
+
+    for (accept, message) in [("vi", "Không tìm thấy đơn hàng"), ("en-GB", "Order not found")] {
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get("/orders/1")
+                    .header(header::ACCEPT_LANGUAGE, accept)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let body = http_body_util::BodyExt::collect(response.into_body())
+            .await
+            .unwrap()
+            .to_bytes();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "order.not_found");
+        assert_eq!(body["message"], message);
+    }
+}
+
+#[tokio::test]
+async fn validation_errors_are_unprocessable() {
+    let app = Router::new().route(
+        "/orders",
+        get(|| async {
+            ValidationErrors::field("items[0].qty", OrderError::InvalidQuantity)
+                .with("items[1].qty", OrderError::InvalidQuantity)
+        }),
+    );
+
+    let response = app
+        .oneshot(Request::get("/orders").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+
+    let body = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["code"], "error.validation_failed");
+    assert_eq!(body["errors"][1]["field"], "items[1].qty");
+    assert_eq!(body["errors"][1]["code"], "order.invalid_quantity");
+}
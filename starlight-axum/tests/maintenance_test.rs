@@ -0,0 +1,100 @@
+use starlight_axum::admin::maintenance_router;
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::{Request, StatusCode, header};
+use starlight_axum::axum::response::Response;
+use starlight_axum::axum::routing::get;
+use starlight_axum::axum::{Router, serve};
+use starlight_axum::middleware::maintenance::{MaintenanceLayer, MaintenanceSwitch};
+use starlight_axum::tower::ServiceExt;
+use std::time::Duration;
+
+fn app(switch: MaintenanceSwitch) -> Router {
+    Router::new()
+        .route("/orders", get(|| async { "orders" }))
+        .route("/health/live", get(|| async { "ok" }))
+        .route("/health", get(|| async { "ok" }))
+        .route("/healthz", get(|| async { "ok" }))
+        .route("/administrator", get(|| async { "root" }))
+        .merge(maintenance_router("secret", switch.clone()).unwrap())
+        .layer(MaintenanceLayer::new(switch).with_retry_after(Duration::from_secs(30)))
+}
+
+async fn call(app: &Router, path: &str) -> Response {
+    app.clone()
+        .oneshot(Request::get(path).body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+}
+
+async fn json(response: Response) -> serde_json::Value {
+    let bytes = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[tokio::test]
+async fn switch_blocks_all_but_allowlisted_routes_at_runtime() {
+    let switch = MaintenanceSwitch::default();
+    let app = app(switch.clone());
+    assert_eq!(call(&app, "/orders").await.status(), StatusCode::OK);
+
+    switch.enable();
+    let blocked = call(&app, "/orders").await;
+    assert_eq!(blocked.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(blocked.headers()[header::RETRY_AFTER], "30");
+    let body = json(blocked).await;
+    assert_eq!(body["error"], "maintenance");
+    assert_eq!(body["retry_after"], 30);
+    assert_eq!(call(&app, "/health/live").await.status(), StatusCode::OK);
+
+    switch.disable();
+    assert_eq!(call(&app, "/orders").await.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn prefixes_match_whole_path_segments() {
+    let switch = MaintenanceSwitch::default();
+    switch.enable();
+    let app = app(switch);
+    assert_eq!(call(&app, "/health").await.status(), StatusCode::OK);
+    assert_eq!(call(&app, "/healthz").await.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(call(&app, "/administrator").await.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    let trailing = Router::new()
+        .route("/admin/users", get(|| async { "users" }))
+        .layer(MaintenanceLayer::new(MaintenanceSwitch::new(true)).with_allowed_prefixes(["/admin/"]));
+    assert_eq!(call(&trailing, "/admin/users").await.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn admin_router_toggles_a_running_server() {
+    let switch = MaintenanceSwitch::default();
+    let mut changes = switch.subscribe();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { serve(listener, app(switch)).await.unwrap() });
+
+    let client = starlight_axum::client::TracedClient::new();
+    let toggle = |enabled: bool, token: &str| {
+        Request::put(format!("http://{}/admin/maintenance", addr))
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(format!("{{\"enabled\":{}}}", enabled)))
+            .unwrap()
+    };
+    let orders = || Request::get(format!("http://{}/orders", addr)).body(Body::empty()).unwrap();
+
+    let denied = client.request(toggle(true, "wrong")).await.unwrap();
+    assert_eq!(denied.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(client.request(orders()).await.unwrap().status(), StatusCode::OK);
+
+    assert_eq!(client.request(toggle(true, "secret")).await.unwrap().status(), StatusCode::OK);
+    changes.changed().await.unwrap();
+    assert!(*changes.borrow());
+    assert_eq!(client.request(orders()).await.unwrap().status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    assert_eq!(client.request(toggle(false, "secret")).await.unwrap().status(), StatusCode::OK);
+    assert_eq!(client.request(orders()).await.unwrap().status(), StatusCode::OK);
+}
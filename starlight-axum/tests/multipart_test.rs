@@ -0,0 +1,142 @@
+use serde_json::Value;
+use starlight_axum::axum::Router;
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::extract::Extension;
+use starlight_axum::axum::http::{Request, StatusCode, header};
+use starlight_axum::axum::response::Response;
+use starlight_axum::axum::routing::post;
+use starlight_axum::multipart::{Multipart, MultipartConfig, PartData};
+use starlight_axum::rejection::Rejection;
+use starlight_axum::tower::ServiceExt;
+use std::path::{Path, PathBuf};
+
+const BOUNDARY: &str = "starlight-boundary";
+
+/// Names, sizes and whether each part was spilled, checking spilled files exist
+/// while the handler holds them.
+async fn upload(mut form: Multipart) -> Result<String, Rejection> {
+    let mut parts = Vec::new();
+    while let Some(part) = form.next_part().await? {
+        let (len, spilled) = match part.data() {
+            PartData::Memory(bytes) => (bytes.len() as u64, false),
+            PartData::File(file) => {
+                assert!(file.path().exists());
+                (file.len(), true)
+            }
+        };
+        parts.push(format!("{}:{}:{}", part.name(), len, spilled));
+    }
+    Ok(parts.join(","))
+}
+
+fn temp_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("starlight-multipart-{}-{}", test, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn app(dir: &Path) -> Router {
+    let config = MultipartConfig::new()
+        .with_max_field_bytes(1024)
+        .with_max_total_bytes(4096)
+        .with_spill_above(64)
+        .with_temp_dir(dir)
+        .with_content_types("avatar", ["image/*"]);
+    Router::new()
+        .route("/upload", post(upload))
+        .route_layer(Extension(config))
+}
+
+fn form(parts: &[(&str, &str, Vec<u8>)]) -> Request<Body> {
+    let mut body = Vec::new();
+    for (name, content_type, data) in parts {
+        body.extend_from_slice(format!("--{BOUNDARY}\r\n").as_bytes());
+        body.extend_from_slice(
+            format!("Content-Disposition: form-data; name=\"{name}\"; filename=\"{name}.bin\"\r\n").as_bytes(),
+        );
+        body.extend_from_slice(format!("Content-Type: {content_type}\r\n\r\n").as_bytes());
+        body.extend_from_slice(data);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{BOUNDARY}--\r\n").as_bytes());
+    Request::post("/upload")
+        .header(header::CONTENT_TYPE, format!("multipart/form-data; boundary={BOUNDARY}"))
+        .body(Body::from(body))
+        .unwrap()
+}
+
+async fn problem(response: Response) -> Value {
+    let content_type = response.headers()[header::CONTENT_TYPE].to_str().unwrap().to_owned();
+    assert_eq!(content_type, "application/problem+json");
+    let body = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    serde_json::from_slice(&body).unwrap()
+}
+
+fn is_empty(dir: &Path) -> bool {
+    std::fs::read_dir(dir).unwrap().next().is_none()
+}
+
+#[tokio::test]
+async fn small_parts_stay_in_memory_and_large_ones_spill() {
+    let dir = temp_dir("spill");
+    let response = app(&dir)
+        .oneshot(form(&[
+            ("title", "text/plain", b"holiday".to_vec()),
+            ("avatar", "image/png", vec![7; 512]),
+        ]))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    assert_eq!(&body[..], b"title:7:false,avatar:512:true");
+    assert!(is_empty(&dir), "spilled parts are removed after the handler");
+    std::fs::remove_dir(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn oversized_part_is_rejected_and_its_temp_file_removed() {
+    let dir = temp_dir("oversized");
+    let response = app(&dir)
+        .oneshot(form(&[
+            ("title", "text/plain", b"holiday".to_vec()),
+            ("avatar", "image/png", vec![7; 2048]),
+            ("caption", "text/plain", b"beach".to_vec()),
+        ]))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let problem = problem(response).await;
+    assert_eq!(problem["status"], 413);
+    assert_eq!(problem["reason"], "multipart.field_too_large");
+    assert!(is_empty(&dir), "the partly written part is removed");
+    std::fs::remove_dir(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn form_over_the_total_limit_is_rejected() {
+    let dir = temp_dir("total");
+    let parts: Vec<_> = (0..6).map(|_| ("doc", "text/plain", vec![1; 1000])).collect();
+    let response = app(&dir).oneshot(form(&parts)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(problem(response).await["reason"], "multipart.too_large");
+    assert!(is_empty(&dir));
+    std::fs::remove_dir(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn disallowed_content_type_is_unsupported() {
+    let dir = temp_dir("content-type");
+    let response = app(&dir)
+        .oneshot(form(&[("avatar", "application/pdf", b"%PDF".to_vec())]))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(problem(response).await["reason"], "multipart.content_type");
+    std::fs::remove_dir(&dir).unwrap();
+}
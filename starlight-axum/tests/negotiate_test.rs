@@ -0,0 +1,125 @@
+use serde::{Deserialize, Serialize};
+use starlight_axum::axum::Router;
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::{Request, StatusCode, header};
+use starlight_axum::axum::routing::{get, post};
+use starlight_axum::negotiate::{Format, Negotiate, ResponseFormat};
+use starlight_axum::tower::ServiceExt;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Order {
+    id: u64,
+    customer: String,
+    total: f64,
+    discount: i32,
+    items: Vec<String>,
+    note: Option<String>,
+}
+
+fn order() -> Order {
+    Order {
+        id: 42,
+        customer: "ada".to_owned(),
+        total: 99.5,
+        discount: -10,
+        items: vec!["tea".to_owned(), "scones".to_owned()],
+        note: None,
+    }
+}
+
+fn app() -> Router {
+    Router::new()
+        .route(
+            "/orders",
+            post(|order: Negotiate<Order>| async move {
+                let mut saved = order.value.clone();
+                saved.note = Some("saved".to_owned());
+                order.respond(saved)
+            }),
+        )
+        .route("/orders/42", get(|format: ResponseFormat| async move { format.respond(order()) }))
+}
+
+async fn send(request: Request<Body>) -> (StatusCode, Option<String>, Vec<u8>) {
+    let response = app().oneshot(request).await.unwrap();
+    let status = response.status();
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .map(|value| value.to_str().unwrap().to_owned());
+    let body = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes()
+        .to_vec();
+    (status, content_type, body)
+}
+
+#[tokio::test]
+async fn round_trips_every_format() {
+    for format in [Format::Json, Format::MessagePack, Format::Cbor] {
+        let request = Request::post("/orders")
+            .header(header::CONTENT_TYPE, format.media_type())
+            .header(header::ACCEPT, format.media_type())
+            .body(Body::from(format.encode(&order()).unwrap()))
+            .unwrap();
+        let (status, content_type, body) = send(request).await;
+        assert_eq!(status, StatusCode::OK, "{:?}", format);
+        assert_eq!(content_type.as_deref(), Some(format.media_type()));
+        let saved: Order = format.decode(&body).unwrap();
+        assert_eq!(saved, Order { note: Some("saved".to_owned()), ..order() });
+    }
+}
+
+#[tokio::test]
+async fn answers_in_the_preferred_accepted_format() {
+    let request = Request::get("/orders/42")
+        .header(header::ACCEPT, "application/json;q=0.5, application/cbor;q=0.9, application/x-msgpack;q=0.7")
+        .body(Body::empty())
+        .unwrap();
+    let (status, content_type, body) = send(request).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(content_type.as_deref(), Some("application/cbor"));
+    assert_eq!(Format::Cbor.decode::<Order>(&body).unwrap(), order());
+
+    let (_, content_type, _) = send(Request::get("/orders/42").body(Body::empty()).unwrap()).await;
+    assert_eq!(content_type.as_deref(), Some("application/json"));
+}
+
+#[tokio::test]
+async fn unsupported_types_list_the_supported_ones() {
+    let request = Request::get("/orders/42")
+        .header(header::ACCEPT, "text/html, application/json;q=0")
+        .body(Body::empty())
+        .unwrap();
+    let (status, content_type, body) = send(request).await;
+    assert_eq!(status, StatusCode::NOT_ACCEPTABLE);
+    assert_eq!(content_type.as_deref(), Some("application/problem+json"));
+    let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(problem["status"], 406);
+    assert_eq!(
+        problem["supported"],
+        serde_json::json!(["application/json", "application/msgpack", "application/cbor"])
+    );
+
+    let request = Request::post("/orders")
+        .header(header::CONTENT_TYPE, "application/xml")
+        .body(Body::from("<order/>"))
+        .unwrap();
+    let (status, _, _) = send(request).await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    for (content_type, body) in [
+        ("application/msgpack", vec![0xc1]),
+        ("application/msgpack", vec![0x91; 2000]),
+        ("application/cbor", vec![0x5b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
+        ("application/cbor", vec![0x81; 2000]),
+    ] {
+        let request = Request::post("/orders")
+            .header(header::CONTENT_TYPE, content_type)
+            .body(Body::from(body))
+            .unwrap();
+        let (status, _, _) = send(request).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", content_type);
+    }
+}
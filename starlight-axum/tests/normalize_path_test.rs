@@ -0,0 +1,95 @@
+use starlight_axum::axum::Router;
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::extract::{MatchedPath, OriginalUri, Path};
+use starlight_axum::axum::http::{Request, StatusCode, header};
+use starlight_axum::axum::routing::get;
+use starlight_axum::middleware::normalize_path::{Normalization, NormalizePathLayer, TrailingSlash};
+use starlight_axum::tower::ServiceExt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+fn app(layer: NormalizePathLayer, calls: Arc<AtomicUsize>) -> Router {
+    let router = Router::new()
+        .route(
+            "/users",
+            get(move |OriginalUri(original): OriginalUri| async move {
+                calls.fetch_add(1, Ordering::SeqCst);
+                original.to_string()
+            }),
+        )
+        .route(
+            "/users/{id}/",
+            get(|path: MatchedPath, Path(id): Path<String>| async move { format!("{} {}", path.as_str(), id) }),
+        )
+        .route("/files/{id}", get(|Path(id): Path<String>| async move { id }));
+    layer.wrap(router)
+}
+
+async fn send(app: &Router, uri: &str) -> (StatusCode, Option<String>, String) {
+    let response = app
+        .clone()
+        .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let location = response
+        .headers()
+        .get(header::LOCATION)
+        .map(|value| value.to_str().unwrap().to_owned());
+    let body = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    (status, location, String::from_utf8(body.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn rewrites_paths_before_routing() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let layer = NormalizePathLayer::new()
+        .with_case_insensitive(true)
+        .with_trailing_slash(TrailingSlash::Require);
+    let required = app(layer, Arc::default());
+    let app = app(NormalizePathLayer::new().with_case_insensitive(true), calls.clone());
+
+    let (status, location, body) = send(&app, "//Users///?page=2").await;
+    assert_eq!((status, location), (StatusCode::OK, None));
+    assert_eq!(body, "//Users///?page=2");
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    // Routes are matched, and named, by their normalized path.
+    let (status, _, body) = send(&required, "/USERS/Ada").await;
+    assert_eq!((status, body.as_str()), (StatusCode::OK, "/users/{id}/ ada"));
+}
+
+#[tokio::test]
+async fn redirects_to_the_normalized_path() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let layer = NormalizePathLayer::new()
+        .with_case_insensitive(true)
+        .with_normalization(Normalization::Redirect);
+    let app = app(layer, calls.clone());
+
+    let (status, location, _) = send(&app, "/Users/?page=2").await;
+    assert_eq!(status, StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(location.as_deref(), Some("/users?page=2"));
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+    let (status, location, _) = send(&app, "/users?page=2").await;
+    assert_eq!((status, location), (StatusCode::OK, None));
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn keeps_the_case_of_exempt_parameters() {
+    let layer = NormalizePathLayer::new()
+        .with_case_insensitive(true)
+        .with_case_sensitive("/files/{id}");
+    let app = app(layer, Arc::default());
+
+    assert_eq!(send(&app, "/FILES/aGVsbG8=/").await.2, "aGVsbG8=");
+    assert_eq!(send(&app, "/files/QUJD").await.2, "QUJD");
+    // Other routes are lowercased whole.
+    let (status, _, _) = send(&app, "/Users/Ada/").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
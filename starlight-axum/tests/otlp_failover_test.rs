@@ -0,0 +1,116 @@
+use opentelemetry::trace::{SpanContext, SpanId, SpanKind, Status};
+use opentelemetry_proto::tonic::collector::trace::v1::trace_service_server::{TraceService, TraceServiceServer};
+use opentelemetry_proto::tonic::collector::trace::v1::{ExportTraceServiceRequest, ExportTraceServiceResponse};
+use opentelemetry_sdk::trace::{SpanData, SpanExporter};
+use starlight_axum::oltp::{FailoverExporter, FailoverPolicy};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+use tokio_stream::wrappers::TcpListenerStream;
+
+/// Counts the spans it receives.
+#[derive(Clone, Default)]
+struct MockCollector {
+    spans: Arc<AtomicUsize>,
+}
+
+#[tonic::async_trait]
+impl TraceService for MockCollector {
+    async fn export(
+        &self,
+        request: tonic::Request<ExportTraceServiceRequest>,
+    ) -> Result<tonic::Response<ExportTraceServiceResponse>, tonic::Status> {
+        let spans: usize = request
+            .get_ref()
+            .resource_spans
+            .iter()
+            .flat_map(|resource| &resource.scope_spans)
+            .map(|scope| scope.spans.len())
+            .sum();
+        self.spans.fetch_add(spans, Ordering::SeqCst);
+        Ok(tonic::Response::new(ExportTraceServiceResponse::default()))
+    }
+}
+
+impl MockCollector {
+    fn received(&self) -> usize {
+        self.spans.load(Ordering::SeqCst)
+    }
+
+    /// Serves on `addr` until the returned sender is dropped.
+    async fn serve(&self, addr: SocketAddr) -> oneshot::Sender<()> {
+        let listener = TcpListener::bind(addr).await.unwrap();
+        let (stop, stopped) = oneshot::channel::<()>();
+        let service = TraceServiceServer::new(self.clone());
+        tokio::spawn(async move {
+            tonic::transport::Server::builder()
+                .add_service(service)
+                .serve_with_incoming_shutdown(TcpListenerStream::new(listener), async {
+                    let _ = stopped.await;
+                })
+                .await
+                .unwrap();
+        });
+        stop
+    }
+}
+
+async fn free_addr() -> SocketAddr {
+    TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap()
+}
+
+fn batch() -> Vec<SpanData> {
+    vec![SpanData {
+        span_context: SpanContext::empty_context(),
+        parent_span_id: SpanId::INVALID,
+        parent_span_is_remote: false,
+        span_kind: SpanKind::Server,
+        name: "GET /orders".into(),
+        start_time: SystemTime::now(),
+        end_time: SystemTime::now(),
+        attributes: Vec::new(),
+        dropped_attributes_count: 0,
+        events: Default::default(),
+        links: Default::default(),
+        status: Status::Unset,
+        instrumentation_scope: Default::default(),
+    }]
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fails_over_to_the_second_collector_and_back() {
+    let (primary, secondary) = (MockCollector::default(), MockCollector::default());
+    let (primary_addr, secondary_addr) = (free_addr().await, free_addr().await);
+    let stop_primary = primary.serve(primary_addr).await;
+    let _stop_secondary = secondary.serve(secondary_addr).await;
+
+    let endpoints = [format!("http://{primary_addr}"), format!("http://{secondary_addr}")];
+    let exporter = FailoverExporter::spans(
+        &endpoints,
+        FailoverPolicy::new()
+            .with_failures_before_switch(2)
+            .with_probe_interval(Duration::from_millis(200)),
+    )
+    .unwrap();
+
+    exporter.export(batch()).await.unwrap();
+    assert_eq!(primary.received(), 1);
+
+    drop(stop_primary);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(exporter.export(batch()).await.is_err());
+    assert!(exporter.export(batch()).await.is_err());
+    assert_eq!(exporter.active_endpoint(), endpoints[1]);
+    exporter.export(batch()).await.unwrap();
+    assert_eq!(secondary.received(), 1);
+
+    let _stop_primary = primary.serve(primary_addr).await;
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    exporter.export(batch()).await.unwrap();
+    assert_eq!(exporter.active_endpoint(), endpoints[0]);
+    assert_eq!(primary.received(), 2);
+    assert_eq!(secondary.received(), 1);
+}
@@ -0,0 +1,122 @@
+use opentelemetry::trace::{SpanContext, SpanId, SpanKind, Status};
+use opentelemetry_proto::tonic::collector::trace::v1::trace_service_server::{TraceService, TraceServiceServer};
+use opentelemetry_proto::tonic::collector::trace::v1::{ExportTraceServiceRequest, ExportTraceServiceResponse};
+use opentelemetry_sdk::trace::{SpanData, SpanExporter};
+use starlight_axum::config::{TelemetryConfig, dump_redacted};
+use starlight_axum::oltp::{FailoverExporter, FailoverPolicy, HeadersConfig, Signal};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::metadata::MetadataMap;
+
+/// Keeps the metadata of the requests it receives.
+#[derive(Clone, Default)]
+struct MockCollector {
+    metadata: Arc<Mutex<Vec<MetadataMap>>>,
+}
+
+#[tonic::async_trait]
+impl TraceService for MockCollector {
+    async fn export(
+        &self,
+        request: tonic::Request<ExportTraceServiceRequest>,
+    ) -> Result<tonic::Response<ExportTraceServiceResponse>, tonic::Status> {
+        self.metadata.lock().unwrap().push(request.metadata().clone());
+        Ok(tonic::Response::new(ExportTraceServiceResponse::default()))
+    }
+}
+
+impl MockCollector {
+    async fn serve(&self) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let service = TraceServiceServer::new(self.clone());
+        tokio::spawn(async move {
+            tonic::transport::Server::builder()
+                .add_service(service)
+                .serve_with_incoming(TcpListenerStream::new(listener))
+                .await
+                .unwrap();
+        });
+        addr
+    }
+
+    /// The value of `name` in the last request.
+    fn header(&self, name: &str) -> Option<String> {
+        let metadata = self.metadata.lock().unwrap();
+        let value = metadata.last().expect("a request was received").get(name)?;
+        Some(value.to_str().unwrap().to_owned())
+    }
+}
+
+fn batch() -> Vec<SpanData> {
+    vec![SpanData {
+        span_context: SpanContext::empty_context(),
+        parent_span_id: SpanId::INVALID,
+        parent_span_is_remote: false,
+        span_kind: SpanKind::Server,
+        name: "GET /orders".into(),
+        start_time: SystemTime::now(),
+        end_time: SystemTime::now(),
+        attributes: Vec::new(),
+        dropped_attributes_count: 0,
+        events: Default::default(),
+        links: Default::default(),
+        status: Status::Unset,
+        instrumentation_scope: Default::default(),
+    }]
+}
+
+fn headers() -> HeadersConfig {
+    HeadersConfig::new()
+        .bearer_token("s3cr3t")
+        .api_key("x-honeycomb-team", "team, key=1")
+        .with_signal(Signal::Traces, HeadersConfig::new().header("x-dataset", "traces"))
+        .with_signal(Signal::Metrics, HeadersConfig::new().api_key("DD-API-KEY", "d0g"))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sends_the_headers_of_the_signal_to_the_collector() {
+    let collector = MockCollector::default();
+    let endpoints = [format!("http://{}", collector.serve().await)];
+    let exporter = FailoverExporter::spans_with_headers(&endpoints, FailoverPolicy::new(), &headers()).unwrap();
+    exporter.export(batch()).await.unwrap();
+
+    assert_eq!(collector.header("authorization").as_deref(), Some("Bearer s3cr3t"));
+    assert_eq!(collector.header("x-honeycomb-team").as_deref(), Some("team, key=1"));
+    assert_eq!(collector.header("x-dataset").as_deref(), Some("traces"));
+    assert_eq!(collector.header("dd-api-key"), None);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn headers_survive_the_environment_form() {
+    let config = TelemetryConfig {
+        otlp_endpoint: "http://collector:4317".to_owned(),
+        ..TelemetryConfig::default()
+    }
+    .with_headers(&headers());
+    assert_eq!(
+        config.otlp_headers.as_deref(),
+        Some("authorization=Bearer%20s3cr3t,x-honeycomb-team=team%2C%20key%3D1")
+    );
+    let parsed = HeadersConfig::from_telemetry(&config).unwrap();
+    assert_eq!(
+        parsed.for_signal(Signal::Metrics),
+        headers().for_signal(Signal::Metrics)
+    );
+
+    let collector = MockCollector::default();
+    let endpoints = [format!("http://{}", collector.serve().await)];
+    let exporter = FailoverExporter::spans_with_headers(&endpoints, FailoverPolicy::new(), &parsed).unwrap();
+    exporter.export(batch()).await.unwrap();
+    assert_eq!(collector.header("x-honeycomb-team").as_deref(), Some("team, key=1"));
+
+    let dump = dump_redacted(&config).to_string();
+    assert!(
+        !dump.contains("s3cr3t") && !dump.contains("d0g") && !dump.contains("team%2C"),
+        "{}",
+        dump
+    );
+}
@@ -0,0 +1,148 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use starlight_axum::axum::Router;
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::extract::Extension;
+use starlight_axum::axum::http::{Request, StatusCode, header};
+use starlight_axum::axum::response::Response;
+use starlight_axum::axum::routing::get;
+use starlight_axum::pagination::{OverMax, Paginated, Pagination, PaginationConfig, decode_cursor, encode_cursor};
+use starlight_axum::tower::ServiceExt;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct After {
+    id: u64,
+    created: String,
+}
+
+const TOTAL: u64 = 45;
+
+async fn offset(page: Pagination) -> Paginated<u64> {
+    let items = (page.offset()..TOTAL).take(page.per_page as usize).collect();
+    Paginated::offset(items, TOTAL, &page)
+}
+
+async fn cursor(page: Pagination<After>) -> Paginated<u64> {
+    let start = page.cursor.as_ref().map_or(0, |after| after.id + 1);
+    let items: Vec<u64> = (start..TOTAL).take(page.per_page as usize).collect();
+    let next = items
+        .last()
+        .filter(|last| **last + 1 < TOTAL)
+        .map(|last| After { id: *last, created: "2024-01-01".to_owned() });
+    Paginated::cursor(items, next.as_ref(), &page)
+}
+
+fn app() -> Router {
+    Router::new()
+        .route("/orders", get(offset))
+        .route(
+            "/strict",
+            get(offset).route_layer(Extension(
+                PaginationConfig::new()
+                    .with_default_per_page(5)
+                    .with_max_per_page(10)
+                    .with_over_max(OverMax::Reject),
+            )),
+        )
+        .route("/feed", get(cursor))
+}
+
+async fn send(uri: &str) -> (StatusCode, Option<String>, Value) {
+    let response: Response = app().oneshot(Request::get(uri).body(Body::empty()).unwrap()).await.unwrap();
+    let status = response.status();
+    let link = response
+        .headers()
+        .get(header::LINK)
+        .map(|value| value.to_str().unwrap().to_owned());
+    let bytes = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    (status, link, serde_json::from_slice(&bytes).unwrap())
+}
+
+#[tokio::test]
+async fn applies_the_defaults() {
+    let (status, _, body) = send("/orders").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["items"].as_array().unwrap().len(), 20);
+    assert_eq!(body["items"][0], 0);
+    assert_eq!(body["total"], TOTAL);
+    assert!(body.get("next_cursor").is_none());
+
+    let (_, _, body) = send("/strict").await;
+    assert_eq!(body["items"].as_array().unwrap().len(), 5);
+}
+
+#[tokio::test]
+async fn clamps_or_rejects_pages_above_the_maximum() {
+    let (status, _, body) = send("/orders?per_page=500").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["items"].as_array().unwrap().len(), 45);
+
+    let (status, _, body) = send("/strict?per_page=11").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["reason"], "pagination.per_page");
+    assert_eq!(body["detail"], "per_page must be at most 10, got 11");
+
+    for (uri, reason) in [
+        ("/orders?page=0", "pagination.page"),
+        ("/orders?page=two", "pagination.page"),
+        ("/orders?per_page=-1", "pagination.per_page"),
+        ("/feed?cursor=not-a-cursor", "pagination.cursor"),
+        ("/feed?cursor=e30&page=2", "pagination.cursor"),
+    ] {
+        let (status, _, body) = send(uri).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", uri);
+        assert_eq!(body["reason"], reason, "{}", uri);
+        assert_eq!(body["status"], 400, "{}", uri);
+    }
+}
+
+#[tokio::test]
+async fn cursors_round_trip() {
+    let after = After { id: 9, created: "2024-01-01".to_owned() };
+    assert_eq!(decode_cursor::<After>(&encode_cursor(&after)), Some(after));
+
+    let (_, _, first) = send("/feed?per_page=10").await;
+    assert_eq!(first["items"][0], 0);
+    let next = first["next_cursor"].as_str().unwrap();
+    assert_eq!(decode_cursor::<After>(next).unwrap().id, 9);
+
+    let (_, link, second) = send(&format!("/feed?per_page=10&cursor={}", next)).await;
+    assert_eq!(second["items"][0], 10);
+    assert!(second.get("total").is_none());
+    let next = second["next_cursor"].as_str().unwrap();
+    assert_eq!(
+        link.as_deref(),
+        Some(format!(r#"</feed?per_page=10>; rel="first", </feed?cursor={}&per_page=10>; rel="next""#, next).as_str())
+    );
+
+    let (_, _, last) = send("/feed?per_page=10&cursor=eyJpZCI6MzksImNyZWF0ZWQiOiIifQ").await;
+    assert_eq!(last["items"].as_array().unwrap().len(), 5);
+    assert!(last.get("next_cursor").is_none());
+}
+
+#[tokio::test]
+async fn links_to_the_neighbouring_pages() {
+    let (_, link, _) = send("/orders?status=open&page=2&per_page=10").await;
+    assert_eq!(
+        link.as_deref(),
+        Some(
+            "</orders?status=open&page=1&per_page=10>; rel=\"first\", \
+             </orders?status=open&page=1&per_page=10>; rel=\"prev\", \
+             </orders?status=open&page=3&per_page=10>; rel=\"next\", \
+             </orders?status=open&page=5&per_page=10>; rel=\"last\""
+        )
+    );
+
+    let (_, link, body) = send("/orders?page=5&per_page=10").await;
+    assert_eq!(body["items"].as_array().unwrap().len(), 5);
+    assert_eq!(
+        link.as_deref(),
+        Some(
+            "</orders?page=1&per_page=10>; rel=\"first\", </orders?page=4&per_page=10>; rel=\"prev\", \
+             </orders?page=5&per_page=10>; rel=\"last\""
+        )
+    );
+}
@@ -0,0 +1,105 @@
+use opentelemetry::{Key, Value};
+use starlight_axum::axum::Router;
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::Request;
+use starlight_axum::axum::routing::get;
+use starlight_axum::profiling::{ProfilerLayer, X_PROFILE};
+use starlight_axum::testing::TelemetryCapture;
+use starlight_axum::tower::ServiceExt;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::sleep;
+use tracing::Instrument;
+
+/// Spends about 80 ms loading and 40 ms rendering.
+async fn handler() -> &'static str {
+    sleep(Duration::from_millis(80))
+        .instrument(tracing::info_span!("load_order"))
+        .await;
+    sleep(Duration::from_millis(40))
+        .instrument(tracing::info_span!("render"))
+        .await;
+    "done"
+}
+
+fn app(layer: ProfilerLayer) -> Router {
+    Router::new().route("/orders/{id}", get(handler)).layer(layer)
+}
+
+async fn send(app: Router, request: Request<Body>) {
+    app.oneshot(request)
+        .instrument(tracing::info_span!("http.request"))
+        .await
+        .unwrap();
+}
+
+fn attribute(capture: &TelemetryCapture, key: &'static str) -> Option<Value> {
+    let span = capture.spans_named("http.request").remove(0);
+    span.attributes
+        .iter()
+        .find(|attribute| attribute.key == Key::from_static_str(key))
+        .map(|attribute| attribute.value.clone())
+}
+
+/// The milliseconds of each span in `profile.summary`.
+fn summary(capture: &TelemetryCapture) -> HashMap<String, f64> {
+    let summary = attribute(capture, "profile.summary").expect("the request was profiled");
+    summary
+        .as_str()
+        .split(',')
+        .map(|pair| {
+            let (name, ms) = pair.split_once('=').unwrap();
+            (name.to_owned(), ms.parse().unwrap())
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn profiles_requests_asking_for_it() {
+    let capture = TelemetryCapture::install();
+    let request = Request::get("/orders/1")
+        .header(X_PROFILE, "1")
+        .body(Body::empty())
+        .unwrap();
+    send(
+        app(ProfilerLayer::new().with_interval(Duration::from_millis(5))),
+        request,
+    )
+    .await;
+
+    let summary = summary(&capture);
+    let (load, render) = (summary["load_order"], summary["render"]);
+    assert!((60.0..=120.0).contains(&load), "{:?}", summary);
+    assert!((25.0..=70.0).contains(&render), "{:?}", summary);
+    assert!((1.4..=2.8).contains(&(load / render)), "{:?}", summary);
+    assert_eq!(attribute(&capture, "profile.trigger"), Some(Value::from("header")));
+}
+
+#[tokio::test]
+async fn profiles_slow_requests_from_the_threshold() {
+    let capture = TelemetryCapture::install();
+    let layer = ProfilerLayer::new()
+        .with_header(None)
+        .with_latency_threshold(Duration::from_millis(50))
+        .with_interval(Duration::from_millis(5));
+    let request = Request::get("/orders/1")
+        .header(X_PROFILE, "1")
+        .body(Body::empty())
+        .unwrap();
+    send(app(layer), request).await;
+
+    // Only the 70 ms after the threshold are sampled.
+    let summary = summary(&capture);
+    assert!((15.0..=50.0).contains(&summary["load_order"]), "{:?}", summary);
+    assert!((25.0..=70.0).contains(&summary["render"]), "{:?}", summary);
+    assert_eq!(attribute(&capture, "profile.trigger"), Some(Value::from("latency")));
+}
+
+#[tokio::test]
+async fn other_requests_are_not_profiled() {
+    let capture = TelemetryCapture::install();
+    let layer = ProfilerLayer::new().with_latency_threshold(Duration::from_secs(1));
+    send(app(layer), Request::get("/orders/1").body(Body::empty()).unwrap()).await;
+
+    assert_eq!(attribute(&capture, "profile.summary"), None);
+}
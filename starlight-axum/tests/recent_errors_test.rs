@@ -0,0 +1,64 @@
+use starlight_axum::admin::{AdminRouterError, errors_router};
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::{Request, StatusCode, header};
+use starlight_axum::logger::RecentErrorsLayer;
+use starlight_axum::tower::ServiceExt;
+use tracing_subscriber::layer::SubscriberExt;
+
+#[tokio::test]
+async fn endpoint_returns_the_latest_errors_newest_first() {
+    let subscriber = tracing_subscriber::registry().with(RecentErrorsLayer::new(5));
+    let _default = tracing::subscriber::set_default(subscriber);
+
+    for n in 0..8 {
+        tracing::error!(target: "payments", attempt = n, "charge failed #{}", n);
+        tracing::info!("not recorded #{}", n);
+    }
+    tracing::warn!(target: "cache", "cache miss storm");
+
+    let router = errors_router("s3cret").unwrap();
+    let unauthorized = router
+        .clone()
+        .oneshot(Request::get("/admin/errors").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(unauthorized.status(), StatusCode::UNAUTHORIZED);
+    let bytes = http_body_util::BodyExt::collect(unauthorized.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(body["error"], "unauthorized");
+
+    let response = router
+        .oneshot(
+            Request::get("/admin/errors")
+                .header(header::AUTHORIZATION, "Bearer s3cret")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let bytes = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    let events: Vec<serde_json::Value> = serde_json::from_slice(&bytes).unwrap();
+
+    let messages: Vec<&str> = events.iter().map(|e| e["message"].as_str().unwrap()).collect();
+    assert_eq!(
+        messages,
+        ["cache miss storm", "charge failed #7", "charge failed #6", "charge failed #5", "charge failed #4"]
+    );
+    assert_eq!(events[0]["level"], "WARN");
+    assert_eq!(events[1]["target"], "payments");
+    assert_eq!(events[1]["fields"]["attempt"], "7");
+    assert!(events[1]["timestamp"].as_str().unwrap().contains('T'));
+}
+
+#[test]
+fn endpoint_refuses_an_empty_token() {
+    assert_eq!(errors_router("").err(), Some(AdminRouterError::EmptyToken));
+    assert_eq!(errors_router("  ").err(), Some(AdminRouterError::EmptyToken));
+}
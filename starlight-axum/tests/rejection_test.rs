@@ -0,0 +1,94 @@
+use serde::Deserialize;
+use serde_json::Value;
+use starlight_axum::axum::Router;
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::extract::Extension;
+use starlight_axum::axum::http::{Request, StatusCode, header};
+use starlight_axum::axum::routing::{get, post};
+use starlight_axum::rejection::{REQUEST_REJECTIONS, RejectionConfig};
+use starlight_axum::testing::TelemetryCapture;
+use starlight_axum::tower::ServiceExt;
+use starlight_axum::{Json, Path, Query};
+
+#[derive(Debug, Deserialize, serde::Serialize)]
+struct Order {
+    item: String,
+    quantity: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct Page {
+    page: u32,
+}
+
+fn app(config: RejectionConfig) -> Router {
+    Router::new()
+        .route("/orders", post(|Json(order): Json<Order>| async move { Json(order) }))
+        .route("/orders", get(|Query(page): Query<Page>| async move { page.page.to_string() }))
+        .route("/orders/{id}", get(|Path(id): Path<u64>| async move { id.to_string() }))
+        .layer(Extension(config))
+}
+
+async fn send(app: Router, request: Request<Body>) -> (StatusCode, Option<String>, Value) {
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .map(|value| value.to_str().unwrap().to_owned());
+    let bytes = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    (status, content_type, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
+
+fn assert_envelope(body: &Value, reason: &str) {
+    assert_eq!(body["type"], "about:blank");
+    assert_eq!(body["title"], "Bad Request");
+    assert_eq!(body["status"], 400);
+    assert_eq!(body["reason"], reason);
+    assert!(body["detail"].as_str().is_some_and(|detail| !detail.is_empty()), "{}", body);
+}
+
+#[tokio::test]
+async fn malformed_requests_get_problem_details_with_reasons() {
+    let capture = TelemetryCapture::install();
+    let app = app(RejectionConfig::with_meter(&capture.meter()));
+
+    let request = Request::post("/orders")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"item": "tea","#))
+        .unwrap();
+    let (status, content_type, body) = send(app.clone(), request).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(content_type.as_deref(), Some("application/problem+json"));
+    assert_envelope(&body, "json.syntax");
+
+    let (status, _, body) = send(app.clone(), Request::get("/orders?size=10").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_envelope(&body, "query.missing");
+    assert!(body["detail"].as_str().unwrap().contains("page"), "{}", body);
+
+    let (status, _, body) = send(app.clone(), Request::get("/orders/abc").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_envelope(&body, "path.type");
+
+    assert_eq!(capture.metric_sum(REQUEST_REJECTIONS), 3.0);
+}
+
+#[tokio::test]
+async fn valid_requests_pass_through() {
+    let app = app(RejectionConfig::new());
+    let request = Request::post("/orders")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"item": "tea", "quantity": 2}"#))
+        .unwrap();
+    let (status, content_type, body) = send(app.clone(), request).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(content_type.as_deref(), Some("application/json"));
+    assert_eq!(body, serde_json::json!({"item": "tea", "quantity": 2}));
+
+    let response = app.oneshot(Request::get("/orders/42").body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
@@ -0,0 +1,118 @@
+use serde_json::{Value, json};
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::{Method, Request, StatusCode, header};
+use starlight_axum::axum::routing::{get, post};
+use starlight_axum::axum::{Json, Router};
+use starlight_axum::middleware::schema::{JsonSchema, ResponseValidation, SCHEMA_VIOLATIONS, SchemaValidationLayer};
+use starlight_axum::testing::TelemetryCapture;
+use starlight_axum::tower::ServiceExt;
+
+fn layer(capture: &TelemetryCapture) -> SchemaValidationLayer {
+    SchemaValidationLayer::with_meter(&capture.meter())
+        .with_request(
+            Method::POST,
+            "/orders",
+            JsonSchema::from_file("tests/fixtures/schemas/create_order.json").unwrap(),
+        )
+        .with_response(
+            Method::GET,
+            "/orders/{id}",
+            JsonSchema::parse(include_str!("fixtures/schemas/order.json")).unwrap(),
+        )
+}
+
+fn app(layer: SchemaValidationLayer) -> Router {
+    Router::new()
+        .route("/orders", post(|Json(order): Json<Value>| async move { Json(order) }))
+        .route(
+            "/orders/{id}",
+            get(|| async { Json(json!({"id": "o-1", "status": "refunded"})) }),
+        )
+        .route_layer(layer)
+}
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+fn create(body: &Value) -> Request<Body> {
+    Request::post("/orders")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn valid_requests_reach_the_handler() {
+    let capture = TelemetryCapture::install();
+    let app = app(layer(&capture));
+
+    let order = json!({"customer": "c-1", "items": [{"sku": "A-1", "quantity": 2}]});
+    let (status, body) = send(&app, create(&order)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, order);
+    assert_eq!(capture.metric_sum(SCHEMA_VIOLATIONS), 0.0);
+}
+
+#[tokio::test]
+async fn invalid_requests_are_answered_with_their_violations() {
+    let capture = TelemetryCapture::install();
+    let small = app(layer(&capture).with_max_body_bytes(16));
+    let app = app(layer(&capture));
+
+    let order = json!({"items": [{"sku": "A-1", "quantity": 0}]});
+    let (status, body) = send(&app, create(&order)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["reason"], "schema.request");
+    assert_eq!(body["detail"], "the body does not match the schema of POST /orders");
+    assert_eq!(
+        body["violations"],
+        json!([
+            {"pointer": "", "message": "\"customer\" is a required property"},
+            {"pointer": "/items/0/quantity", "message": "0 is less than the minimum of 1"},
+        ])
+    );
+    assert_eq!(capture.metric_sum(SCHEMA_VIOLATIONS), 2.0);
+
+    // Other content types and oversized bodies.
+    let text = Request::post("/orders")
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body(Body::from("{}"))
+        .unwrap();
+    assert_eq!(send(&app, text).await.0, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    let (status, body) = send(&small, create(&order)).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(body["reason"], "schema.body_too_large");
+}
+
+#[tokio::test]
+async fn invalid_responses_are_logged() {
+    let capture = TelemetryCapture::install();
+    let app = app(layer(&capture));
+
+    let (status, body) = send(&app, Request::get("/orders/o-1").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "refunded");
+    assert_eq!(capture.metric_sum(SCHEMA_VIOLATIONS), 1.0);
+}
+
+#[tokio::test]
+async fn invalid_responses_are_replaced_when_strict() {
+    let capture = TelemetryCapture::install();
+    let app = app(layer(&capture).with_response_validation(ResponseValidation::Strict));
+
+    let (status, body) = send(&app, Request::get("/orders/o-1").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(body["reason"], "schema.response");
+    assert_eq!(
+        body["detail"],
+        "the response of GET /orders/{id} does not match its schema"
+    );
+    assert_eq!(capture.metric_sum(SCHEMA_VIOLATIONS), 1.0);
+}
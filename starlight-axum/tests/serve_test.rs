@@ -0,0 +1,96 @@
+#![cfg(unix)]
+
+use starlight_axum::axum::routing::get;
+use starlight_axum::axum::{Extension, Router};
+use starlight_axum::serve::{Listener, UnixPeer, serve_many_with_shutdown};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+use tokio::sync::oneshot;
+
+fn socket_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("starlight-serve-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join("app.sock")
+}
+
+fn router() -> Router {
+    Router::new().route(
+        "/whoami",
+        get(|Extension(peer): Extension<UnixPeer>| async move { format!("uid={}", peer.uid) }),
+    )
+}
+
+async fn get_whoami(path: &Path) -> String {
+    let mut stream = UnixStream::connect(path).await.unwrap();
+    stream
+        .write_all(b"GET /whoami HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+async fn wait_for_socket(path: &Path) {
+    for _ in 0..100 {
+        if UnixStream::connect(path).await.is_ok() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("server did not start on {}", path.display());
+}
+
+#[tokio::test]
+async fn serves_over_unix_socket_with_peer_credentials() {
+    let path = socket_path("peer");
+    let (stop_tx, stop_rx) = oneshot::channel::<()>();
+    let server = tokio::spawn(serve_many_with_shutdown(
+        vec![(Listener::unix(&path).with_mode(0o600), router())],
+        async move {
+            let _ = stop_rx.await;
+        },
+    ));
+    wait_for_socket(&path).await;
+
+    let response = get_whoami(&path).await;
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    assert!(response.ends_with(&format!("uid={}", current_uid())));
+
+    stop_tx.send(()).unwrap();
+    server.await.unwrap().unwrap();
+    assert!(!path.exists(), "socket file should be removed on shutdown");
+}
+
+#[tokio::test]
+async fn replaces_stale_socket_on_restart() {
+    let path = socket_path("stale");
+    // A socket file nobody is listening on, as left behind by a crashed process.
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+    assert!(path.exists());
+
+    let (stop_tx, stop_rx) = oneshot::channel::<()>();
+    let server = tokio::spawn(serve_many_with_shutdown(
+        vec![(Listener::unix(&path), router())],
+        async move {
+            let _ = stop_rx.await;
+        },
+    ));
+    wait_for_socket(&path).await;
+    assert!(get_whoami(&path).await.starts_with("HTTP/1.1 200 OK"));
+
+    stop_tx.send(()).unwrap();
+    server.await.unwrap().unwrap();
+}
+
+fn current_uid() -> u32 {
+    use std::os::unix::fs::MetadataExt;
+    // The test process owns the files it creates, so its uid is the owner of a fresh file.
+    let probe = std::env::temp_dir().join(format!("starlight-uid-{}", std::process::id()));
+    std::fs::write(&probe, b"").unwrap();
+    let uid = std::fs::metadata(&probe).unwrap().uid();
+    let _ = std::fs::remove_file(&probe);
+    uid
+}
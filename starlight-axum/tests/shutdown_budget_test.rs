@@ -0,0 +1,64 @@
+use opentelemetry::trace::{Tracer, TracerProvider};
+use starlight_axum::axum::Router;
+use starlight_axum::axum::routing::get;
+use starlight_axum::health::InFlightTracker;
+use starlight_axum::serve::{Listener, ShutdownBudget, serve_graceful};
+use starlight_axum::tracer::get_or_init_tracer_provider;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+
+#[test]
+fn splits_the_total_between_drain_and_telemetry() {
+    let budget = ShutdownBudget::new(Duration::from_secs(30));
+    assert_eq!(budget.drain(), Duration::from_secs(24));
+    assert_eq!(budget.telemetry(), Duration::from_secs(6));
+
+    let budget = budget.with_drain_fraction(1.5);
+    assert_eq!(budget.drain(), Duration::from_secs(30));
+    assert_eq!(budget.telemetry(), Duration::ZERO);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_hung_request_and_a_blackholed_collector_stay_within_the_budget() {
+    // Accepts connections but never answers, like a collector behind a dropped route.
+    let blackhole = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let provider = get_or_init_tracer_provider(&format!("http://{}", blackhole.local_addr().unwrap()));
+    for _ in 0..10 {
+        provider.tracer("shutdown").in_span("work", |_| {});
+    }
+
+    let addr = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+    let router = Router::new().route("/hang", get(std::future::pending::<&'static str>));
+    let tracker = InFlightTracker::new();
+    let (stop_tx, stop_rx) = oneshot::channel::<()>();
+    let budget = ShutdownBudget::new(Duration::from_millis(1500)).with_drain_fraction(0.5);
+    let server = tokio::spawn(serve_graceful(
+        vec![(Listener::tcp(addr), router)],
+        async move {
+            let _ = stop_rx.await;
+        },
+        tracker.clone(),
+        budget,
+    ));
+
+    let mut client = loop {
+        if let Ok(stream) = TcpStream::connect(addr).await {
+            break stream;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+    client.write_all(b"GET /hang HTTP/1.1\r\nhost: localhost\r\n\r\n").await.unwrap();
+    while tracker.in_flight() == 0 {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+
+    let started = Instant::now();
+    stop_tx.send(()).unwrap();
+    server.await.unwrap().unwrap();
+    let elapsed = started.elapsed();
+    // The collector never answers, so the telemetry stage runs until the total is spent.
+    assert!(elapsed >= budget.total() - Duration::from_millis(100), "returned after {:?}", elapsed);
+    assert!(elapsed < budget.total() + Duration::from_millis(250), "returned after {:?}", elapsed);
+}
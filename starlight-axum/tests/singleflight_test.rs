@@ -0,0 +1,137 @@
+use starlight_axum::axum::Router;
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::{Request, StatusCode};
+use starlight_axum::axum::response::Response;
+use starlight_axum::axum::routing::get;
+use starlight_axum::middleware::singleflight::{SingleflightLayer, X_COALESCED};
+use starlight_axum::tower::ServiceExt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+fn app(calls: Arc<AtomicUsize>, status: StatusCode, layer: SingleflightLayer) -> Router {
+    Router::new()
+        .route(
+            "/rates",
+            get(move || async move {
+                let n = calls.fetch_add(1, Ordering::SeqCst) + 1;
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                (status, format!("rates {}", n))
+            }),
+        )
+        .layer(layer)
+}
+
+async fn get_path(app: Router, path: &str, authorization: Option<&str>) -> (Response, String) {
+    let mut request = Request::get(path);
+    if let Some(authorization) = authorization {
+        request = request.header("authorization", authorization);
+    }
+    let response = app.oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+    let (parts, body) = response.into_parts();
+    let bytes = http_body_util::BodyExt::collect(body).await.unwrap().to_bytes();
+    (
+        Response::from_parts(parts, Body::empty()),
+        String::from_utf8(bytes.to_vec()).unwrap(),
+    )
+}
+
+async fn burst(
+    app: &Router,
+    path: &'static str,
+    n: usize,
+    authorization: Option<&'static str>,
+) -> Vec<(Response, String)> {
+    let requests: Vec<_> = (0..n)
+        .map(|_| tokio::spawn(get_path(app.clone(), path, authorization)))
+        .collect();
+    let mut responses = Vec::with_capacity(n);
+    for request in requests {
+        responses.push(request.await.unwrap());
+    }
+    responses
+}
+
+#[tokio::test]
+async fn runs_the_handler_once_for_a_burst_of_identical_requests() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let app = app(calls.clone(), StatusCode::OK, SingleflightLayer::new());
+
+    let responses = burst(&app, "/rates", 50, None).await;
+
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    assert!(
+        responses
+            .iter()
+            .all(|(response, body)| response.status() == StatusCode::OK && body == "rates 1")
+    );
+    let coalesced = responses
+        .iter()
+        .filter(|(response, _)| response.headers().get(X_COALESCED).is_some_and(|value| value == "true"))
+        .count();
+    assert_eq!(coalesced, 49);
+
+    // The flight is over, the next request runs again.
+    let (_, body) = get_path(app, "/rates", None).await;
+    assert_eq!(body, "rates 2");
+}
+
+#[tokio::test]
+async fn different_queries_and_credentials_are_not_coalesced() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let app = app(calls.clone(), StatusCode::OK, SingleflightLayer::new());
+
+    let (first, second) = tokio::join!(
+        get_path(app.clone(), "/rates?currency=eur", None),
+        get_path(app.clone(), "/rates?currency=usd", None)
+    );
+    assert!(first.0.headers().get(X_COALESCED).is_none() && second.0.headers().get(X_COALESCED).is_none());
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+    burst(&app, "/rates", 3, Some("Bearer token")).await;
+    assert_eq!(calls.load(Ordering::SeqCst), 5);
+}
+
+#[tokio::test]
+async fn errors_are_not_shared_unless_configured() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let unshared = app(calls.clone(), StatusCode::SERVICE_UNAVAILABLE, SingleflightLayer::new());
+    let responses = burst(&unshared, "/rates", 5, None).await;
+    assert_eq!(calls.load(Ordering::SeqCst), 5);
+    assert!(
+        responses
+            .iter()
+            .all(|(response, _)| response.headers().get(X_COALESCED).is_none())
+    );
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let shared = app(
+        calls.clone(),
+        StatusCode::SERVICE_UNAVAILABLE,
+        SingleflightLayer::new().with_share_errors(true),
+    );
+    let responses = burst(&shared, "/rates", 5, None).await;
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    assert!(
+        responses
+            .iter()
+            .all(|(response, _)| response.status() == StatusCode::SERVICE_UNAVAILABLE)
+    );
+}
+
+#[tokio::test]
+async fn oversized_responses_bypass_coalescing() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let app = app(
+        calls.clone(),
+        StatusCode::OK,
+        SingleflightLayer::new().with_max_body_bytes(4),
+    );
+    let responses = burst(&app, "/rates", 5, None).await;
+    assert_eq!(calls.load(Ordering::SeqCst), 5);
+    assert!(
+        responses
+            .iter()
+            .all(|(response, _)| response.headers().get(X_COALESCED).is_none())
+    );
+}
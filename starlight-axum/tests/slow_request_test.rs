@@ -0,0 +1,114 @@
+use starlight_axum::admin::AdminRouter;
+use starlight_axum::axum::Router;
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::{Request, StatusCode};
+use starlight_axum::axum::routing::get;
+use starlight_axum::logger::{RecentErrorsLayer, recent_errors};
+use starlight_axum::middleware::slow::{SlowRequestLayer, SlowRequestLog, Timings};
+use starlight_axum::tower::ServiceExt;
+use std::time::Duration;
+use tracing_subscriber::layer::SubscriberExt;
+
+async fn report(timings: Timings) -> &'static str {
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    timings.time("serialize", || std::thread::sleep(Duration::from_millis(5)));
+    "report"
+}
+
+async fn fast() -> &'static str {
+    "fast"
+}
+
+fn app(layer: SlowRequestLayer) -> Router {
+    Router::new()
+        .route("/reports/{id}", get(report))
+        .route("/exports", get(report))
+        .route("/fast", get(fast))
+        .route_layer(layer)
+}
+
+async fn get_status(app: &Router, uri: &str) -> StatusCode {
+    let request = Request::get(uri)
+        .header("starlight-request-id", "req-1")
+        .body(Body::empty())
+        .unwrap();
+    app.clone().oneshot(request).await.unwrap().status()
+}
+
+#[tokio::test]
+async fn slow_requests_are_logged_and_listed_on_the_admin_router() {
+    let subscriber = tracing_subscriber::registry().with(RecentErrorsLayer::new(10));
+    let _default = tracing::subscriber::set_default(subscriber);
+
+    let layer = SlowRequestLayer::with_log(Duration::from_millis(50), SlowRequestLog::new(2))
+        .with_route("/exports", Duration::from_secs(10));
+    let log = layer.log();
+    let app = app(layer);
+
+    assert_eq!(get_status(&app, "/fast").await, StatusCode::OK);
+    assert_eq!(get_status(&app, "/exports").await, StatusCode::OK);
+    assert_eq!(get_status(&app, "/reports/7").await, StatusCode::OK);
+
+    let warnings: Vec<_> = recent_errors()
+        .into_iter()
+        .filter(|event| event.message == "slow request")
+        .collect();
+    assert_eq!(warnings.len(), 1, "only the report is over its threshold");
+    let warning = &warnings[0];
+    assert_eq!(warning.level, "WARN");
+    assert_eq!(warning.fields["http.route"], "/reports/{id}");
+    assert_eq!(warning.fields["http.response.status_code"], "200");
+    assert_eq!(warning.fields["request_id"], "req-1");
+    assert!(warning.fields["marks"].contains("serialize"));
+
+    let admin = AdminRouter::builder()
+        .dangerously_unauthenticated()
+        .with_slow_requests(log)
+        .build()
+        .unwrap();
+    let response = admin
+        .oneshot(Request::get("/admin/slow-requests").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let slowest = body["data"].as_array().unwrap();
+    assert_eq!(slowest.len(), 1);
+    assert_eq!(slowest[0]["route"], "/reports/{id}");
+    assert_eq!(slowest[0]["method"], "GET");
+    assert_eq!(slowest[0]["request_id"], "req-1");
+    assert!(slowest[0]["duration_ms"].as_f64().unwrap() >= 60.0);
+    assert!(slowest[0]["marks"]["serialize"].as_f64().unwrap() >= 5.0);
+    assert!(slowest[0]["marks"]["handler"].as_f64().unwrap() >= 60.0);
+}
+
+#[tokio::test]
+async fn log_keeps_only_the_slowest() {
+    let layer = SlowRequestLayer::with_log(Duration::ZERO, SlowRequestLog::new(2));
+    let log = layer.log();
+    let app = app(layer);
+
+    for uri in ["/fast", "/reports/1", "/fast", "/exports", "/fast"] {
+        assert_eq!(get_status(&app, uri).await, StatusCode::OK);
+    }
+
+    let routes: Vec<_> = log.slowest().into_iter().map(|request| request.route).collect();
+    assert_eq!(routes.len(), 2);
+    assert!(routes.iter().all(|route| route != "/fast"), "{routes:?}");
+}
+
+#[tokio::test]
+async fn clones_can_be_configured_further() {
+    let base = SlowRequestLayer::with_log(Duration::ZERO, SlowRequestLog::new(5));
+    let app = app(base.clone().with_route("/reports/{id}", Duration::from_secs(10)));
+
+    assert_eq!(get_status(&app, "/reports/1").await, StatusCode::OK);
+    assert_eq!(get_status(&app, "/exports").await, StatusCode::OK);
+
+    let routes: Vec<_> = base.log().slowest().into_iter().map(|request| request.route).collect();
+    assert_eq!(routes, ["/exports"]);
+}
@@ -0,0 +1,44 @@
+use starlight_axum::axum::Router;
+use starlight_axum::axum::http::StatusCode;
+use starlight_axum::axum::routing::get;
+use starlight_axum::stack::StackBuilder;
+use starlight_axum::testing::{ProbeRequest, Scrubber, StackProbe};
+use std::time::Duration;
+
+async fn boom() -> &'static str {
+    panic!("handler failed")
+}
+
+async fn slow() -> &'static str {
+    tokio::time::sleep(Duration::from_secs(5)).await;
+    "too late"
+}
+
+/// Guards what the recommended stack adds to responses, and how it maps panics and
+/// timeouts, against silent changes. Rerun with `STARLIGHT_UPDATE_SNAPSHOTS=1` after an
+/// intended change and review the diff.
+#[tokio::test]
+async fn recommended_stack() {
+    let routes = Router::new()
+        .route("/orders", get(|| async { r#"{"items":[],"total":0}"# }))
+        .route("/missing", get(|| async { StatusCode::NOT_FOUND }))
+        .route("/boom", get(boom))
+        .route("/slow", get(slow));
+    let app = StackBuilder::recommended(Duration::from_millis(50)).build(routes).unwrap();
+
+    StackProbe::new(app)
+        .with_request(ProbeRequest::get("/orders").with_field(""))
+        .with_request(
+            ProbeRequest::get("/orders")
+                .with_header("starlight-request-id", "caller-chosen-id")
+                .with_header("traceparent", "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"),
+        )
+        .with_request(ProbeRequest::get("/missing"))
+        .with_request(ProbeRequest::get("/boom"))
+        .with_request(ProbeRequest::get("/slow"))
+        .with_request(ProbeRequest::get("/unrouted"))
+        .with_scrubber(Scrubber::new("caller-chosen-id", "[caller id]"))
+        .run()
+        .await
+        .assert_snapshot("tests/snapshots/recommended_stack.json");
+}
@@ -0,0 +1,105 @@
+use starlight_axum::axum::Router;
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::{Request, StatusCode};
+use starlight_axum::axum::routing::get;
+use starlight_axum::serve::Listener;
+use starlight_axum::stack::{StackBuilder, StackError, StackLayer, Stage, StartupSummary};
+use starlight_axum::tower::ServiceExt;
+use starlight_axum::tower_http::cors::CorsLayer;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::{Layer, Registry};
+
+type Events = Arc<Mutex<Vec<(String, HashMap<String, String>)>>>;
+
+#[derive(Clone, Default)]
+struct Recorder(Events);
+
+struct Fields<'a>(&'a mut HashMap<String, String>);
+
+impl Visit for Fields<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.insert(field.name().to_owned(), format!("{:?}", value));
+    }
+}
+
+impl<S: Subscriber> Layer<S> for Recorder {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut fields = HashMap::new();
+        event.record(&mut Fields(&mut fields));
+        self.0.lock().unwrap().push((event.metadata().target().to_owned(), fields));
+    }
+}
+
+#[test]
+fn rejects_layers_in_invalid_positions() {
+    let stack = StackBuilder::new()
+        .with_request_id()
+        .with_timeout(Duration::from_secs(5))
+        .with_catch_panic();
+    assert!(matches!(
+        stack.build(Router::new()),
+        Err(StackError::Misordered {
+            outer: Stage::CatchPanic,
+            inner: Stage::Timeout,
+            ..
+        })
+    ));
+
+    let stack = StackBuilder::recommended(Duration::from_secs(5))
+        .insert_before(Stage::RequestId, StackLayer::new("cors", CorsLayer::permissive()));
+    let err = stack.build(Router::new()).unwrap_err();
+    assert_eq!(err, StackError::RequestIdNotOutermost("cors".to_owned()));
+    assert_eq!(err.to_string(), "cors is outside request_id, which must be the outermost layer");
+
+    let stack = StackBuilder::new().insert_after(Stage::Trace, StackLayer::new("cors", CorsLayer::permissive()));
+    assert_eq!(stack.validate(), Err(StackError::MissingStage(Stage::Trace)));
+}
+
+async fn boom() -> &'static str {
+    panic!("boom")
+}
+
+#[tokio::test]
+async fn builds_the_recommended_stack_with_custom_layers() {
+    let stack = StackBuilder::recommended(Duration::from_secs(5))
+        .insert_after(Stage::Metrics, StackLayer::new("cors", CorsLayer::permissive()));
+    let names: Vec<String> = stack.layers().into_iter().map(|layer| layer.name).collect();
+    assert_eq!(names, ["request_id", "trace", "metrics", "cors", "catch_panic", "timeout"]);
+
+    let app = stack
+        .build(Router::new().route("/panic", get(boom)))
+        .unwrap();
+    let response = app.oneshot(Request::get("/panic").body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(response.headers().contains_key("starlight-request-id"));
+}
+
+#[test]
+fn emits_one_startup_summary_event() {
+    let stack = StackBuilder::recommended(Duration::from_secs(30))
+        .with_layer(StackLayer::new("cors", CorsLayer::permissive()).with_config("origins", "*"));
+    let summary = StartupSummary::new(&stack)
+        .with_listener(&Listener::tcp("0.0.0.0:8080".parse().unwrap()))
+        .with_telemetry_endpoint("otlp", "http://collector:4317")
+        .with_routes(3);
+
+    let recorder = Recorder::default();
+    tracing::subscriber::with_default(Registry::default().with(recorder.clone()), || summary.emit());
+
+    let events = recorder.0.lock().unwrap();
+    let startup: Vec<_> = events.iter().filter(|(target, _)| target == "starlight::startup").collect();
+    assert_eq!(startup.len(), 1);
+    let fields = &startup[0].1;
+    assert_eq!(fields["listeners"], "http://0.0.0.0:8080");
+    assert_eq!(
+        fields["middleware"],
+        "request_id(header=starlight-request-id) > trace > metrics > catch_panic > timeout(timeout=30s) > cors(origins=*)"
+    );
+    assert_eq!(fields["telemetry"], "otlp=http://collector:4317");
+    assert_eq!(fields["routes"], "3");
+}
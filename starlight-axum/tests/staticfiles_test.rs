@@ -0,0 +1,95 @@
+use starlight_axum::axum::Router;
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::{Request, StatusCode, header};
+use starlight_axum::axum::response::Response;
+use starlight_axum::staticfiles::{IMMUTABLE, NO_CACHE, SpaConfig, spa_router};
+use starlight_axum::tower::ServiceExt;
+use std::io::Write;
+use std::path::PathBuf;
+
+const INDEX: &str = "<!doctype html><div id=app></div>";
+const APP: &str = "console.log('starlight')";
+
+fn assets(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("starlight-static-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(dir.join("assets")).unwrap();
+    std::fs::write(dir.join("index.html"), INDEX).unwrap();
+    std::fs::write(dir.join("assets/app.1a2b3c4d.js"), APP).unwrap();
+    let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gzip.write_all(APP.as_bytes()).unwrap();
+    std::fs::write(dir.join("assets/app.1a2b3c4d.js.gz"), gzip.finish().unwrap()).unwrap();
+    std::fs::write(dir.join("robots.txt"), "User-agent: *").unwrap();
+    dir
+}
+
+fn app(name: &str, config: SpaConfig) -> Router {
+    Router::new().nest_service("/ui", spa_router(assets(name), config))
+}
+
+async fn get(app: Router, path: &str, headers: &[(header::HeaderName, &str)]) -> Response {
+    let mut request = Request::get(path);
+    for (name, value) in headers {
+        request = request.header(name, *value);
+    }
+    app.oneshot(request.body(Body::empty()).unwrap()).await.unwrap()
+}
+
+async fn text(response: Response) -> String {
+    let bytes = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn hashed_assets_are_immutable_and_revalidated() {
+    let app = app("hashed", SpaConfig::new());
+    let response = get(app.clone(), "/ui/assets/app.1a2b3c4d.js", &[]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CACHE_CONTROL], IMMUTABLE);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "text/javascript; charset=utf-8");
+    let etag = response.headers()[header::ETAG].to_str().unwrap().to_owned();
+    assert_eq!(text(response).await, APP);
+
+    let response = get(app, "/ui/assets/app.1a2b3c4d.js", &[(header::IF_NONE_MATCH, &etag)]).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()[header::ETAG], etag.as_str());
+    assert_eq!(text(response).await, "");
+}
+
+#[tokio::test]
+async fn serves_precompressed_variants() {
+    let app = app("precompressed", SpaConfig::new());
+    let response = get(app, "/ui/assets/app.1a2b3c4d.js", &[(header::ACCEPT_ENCODING, "br;q=0, gzip")]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+    assert_eq!(response.headers()[header::VARY], "accept-encoding");
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "text/javascript; charset=utf-8");
+}
+
+#[tokio::test]
+async fn client_routes_fall_back_to_the_index() {
+    let app = app("fallback", SpaConfig::new().with_cache_control("*.txt", "public, max-age=60"));
+    for path in ["/ui", "/ui/", "/ui/orders/42"] {
+        let response = get(app.clone(), path, &[]).await;
+        assert_eq!(response.status(), StatusCode::OK, "{}", path);
+        assert_eq!(response.headers()[header::CACHE_CONTROL], NO_CACHE);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/html; charset=utf-8");
+        assert_eq!(text(response).await, INDEX);
+    }
+
+    let response = get(app.clone(), "/ui/robots.txt", &[]).await;
+    assert_eq!(response.headers()[header::CACHE_CONTROL], "public, max-age=60");
+    let response = get(app, "/ui/assets/missing.js", &[]).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn rejects_paths_leaving_the_directory() {
+    let app = app("traversal", SpaConfig::new());
+    for path in ["/ui/../../etc/passwd", "/ui/%2e%2e/%2e%2e/etc/passwd", "/ui/assets%5c..%5c..%5cetc"] {
+        let response = get(app.clone(), path, &[]).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", path);
+    }
+}
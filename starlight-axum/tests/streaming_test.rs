@@ -0,0 +1,101 @@
+use futures_util::stream::{self, Stream};
+use starlight_axum::axum::Router;
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::{Request, StatusCode};
+use starlight_axum::axum::response::Response;
+use starlight_axum::axum::response::sse::{Event, Sse};
+use starlight_axum::axum::routing::get;
+use starlight_axum::deadline::DeadlineLayer;
+use starlight_axum::middleware::streaming::StreamingLayer;
+use starlight_axum::tower::ServiceExt;
+use http_body_util::BodyExt;
+use std::convert::Infallible;
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_millis(300);
+
+/// `count` events, `interval` apart, then stalls forever when `stall` is set.
+fn events(count: usize, interval: Duration, stall: bool) -> impl Stream<Item = Result<Event, Infallible>> {
+    stream::unfold(0, move |sent| async move {
+        if sent == count {
+            if stall {
+                std::future::pending::<()>().await;
+            }
+            return None;
+        }
+        if sent > 0 {
+            tokio::time::sleep(interval).await;
+        }
+        Some((Ok(Event::default().data(format!("event {}", sent))), sent + 1))
+    })
+}
+
+fn app() -> Router {
+    // Configured after a clone, as when one base layer is tuned per router.
+    let base = StreamingLayer::new();
+    let streaming = base.clone().with_idle_timeout(Duration::from_millis(200));
+    Router::new()
+        .route(
+            "/events",
+            get(|| async { Sse::new(events(7, Duration::from_millis(100), false)) }).route_layer(streaming.clone()),
+        )
+        .route(
+            "/stalled",
+            get(|| async { Sse::new(events(1, Duration::ZERO, true)) }).route_layer(streaming.clone()),
+        )
+        .route(
+            "/poll",
+            get(|| async {
+                tokio::time::sleep(TIMEOUT * 2).await;
+                "changed"
+            })
+            .route_layer(streaming),
+        )
+        .route(
+            "/slow",
+            get(|| async {
+                tokio::time::sleep(TIMEOUT * 2).await;
+                "late"
+            }),
+        )
+        .layer(DeadlineLayer::new(TIMEOUT))
+}
+
+async fn call(path: &str) -> Response {
+    app().oneshot(Request::get(path).body(Body::empty()).unwrap()).await.unwrap()
+}
+
+#[tokio::test]
+async fn streams_past_the_request_timeout() {
+    let started = Instant::now();
+    let response = call("/events").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["x-accel-buffering"], "no");
+
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    assert!(started.elapsed() >= TIMEOUT * 2, "{:?}", started.elapsed());
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    let received: Vec<&str> = body.lines().filter_map(|line| line.strip_prefix("data: ")).collect();
+    assert_eq!(received, (0..7).map(|n| format!("event {}", n)).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn long_polls_are_exempt_from_the_deadline() {
+    let response = call("/poll").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.into_body().collect().await.unwrap().to_bytes(), "changed");
+
+    assert_eq!(call("/slow").await.status(), StatusCode::GATEWAY_TIMEOUT);
+}
+
+#[tokio::test]
+async fn idle_streams_are_closed() {
+    let started = Instant::now();
+    let mut body = call("/stalled").await.into_body();
+
+    let first = body.frame().await.unwrap().unwrap().into_data().unwrap();
+    assert_eq!(first, "data: event 0\n\n");
+    let error = body.frame().await.unwrap().unwrap_err();
+    assert!(error.to_string().contains("stream idle for 200ms"), "{}", error);
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
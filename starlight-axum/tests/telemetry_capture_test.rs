@@ -0,0 +1,90 @@
+use starlight_axum::axum::Router;
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::extract::Path;
+use starlight_axum::axum::http::Request;
+use starlight_axum::axum::routing::get;
+use starlight_axum::middleware::trace_middleware;
+use starlight_axum::MeterLifecycleObserver;
+use starlight_axum::service::{SERVICE_COMPLETIONS, SERVICE_RUNNING, SERVICE_STARTS};
+use starlight_axum::slo::{SLO_REQUESTS_TOTAL, SloConfig, SloLayer};
+use starlight_axum::starlight_tokio::{CancellationToken, IntervalService, ServiceManager};
+use starlight_axum::testing::TelemetryCapture;
+use starlight_axum::tower::ServiceExt;
+use std::time::Duration;
+
+async fn get_user(Path(id): Path<u64>) -> String {
+    tracing::info!(target: "access_log", user_id = id, "user fetched");
+    tracing::info!(monotonic_counter.users.fetched = 1u64);
+    format!("user {}", id)
+}
+
+#[tokio::test]
+async fn request_through_the_middleware_stack() {
+    let capture = TelemetryCapture::install();
+    let app = Router::new()
+        .route("/users/{id}", get(get_user))
+        .layer(SloLayer::with_meter(
+            vec![SloConfig::new("users", "/users/*", Duration::from_secs(1))],
+            &capture.meter(),
+        ))
+        .layer(trace_middleware());
+
+    for id in [1, 2] {
+        let request = Request::get(format!("/users/{}", id)).body(Body::empty()).unwrap();
+        app.clone().oneshot(request).await.unwrap();
+    }
+
+    let spans = capture.spans_named("http.request");
+    assert_eq!(spans.len(), 2);
+    assert!(spans[0].attributes.iter().any(|kv| kv.key.as_str() == "method" && kv.value.as_str() == "GET"));
+
+    assert_eq!(capture.metric_sum("users.fetched"), 2.0);
+    assert_eq!(capture.metric_sum(SLO_REQUESTS_TOTAL), 2.0);
+
+    let access = capture.events_with_target("access_log");
+    assert_eq!(access.len(), 2);
+    // Log records carry the trace of the request that emitted them.
+    let trace_id = access[0].trace_context().unwrap().trace_id;
+    assert_eq!(trace_id, spans[0].span_context.trace_id());
+}
+
+#[tokio::test]
+async fn service_lifecycle_metrics() {
+    let capture = TelemetryCapture::install();
+    let manager = ServiceManager::new()
+        .with_observer(MeterLifecycleObserver::with_meter(&capture.meter()))
+        .with_service(IntervalService::new("tick", Duration::from_secs(60), Duration::ZERO, || async { Ok(()) }));
+
+    let shutdown = CancellationToken::new();
+    shutdown.cancel();
+    assert!(manager.run(shutdown).await.is_success());
+
+    assert_eq!(capture.metric_sum(SERVICE_STARTS), 1.0);
+    assert_eq!(capture.metric_sum(SERVICE_COMPLETIONS), 1.0);
+    assert_eq!(capture.metric_sum(SERVICE_RUNNING), 0.0);
+}
+
+#[tokio::test]
+async fn streamed_responses_are_measured_separately() {
+    use starlight_axum::axum::body::Bytes;
+    use starlight_axum::axum::response::Response;
+    use starlight_axum::middleware::streaming::StreamingLayer;
+
+    let capture = TelemetryCapture::install();
+    let app = Router::new()
+        .route(
+            "/stream",
+            get(|| async {
+                let chunks = [Bytes::from("hello "), Bytes::from("world")].map(Ok::<_, std::io::Error>);
+                Response::new(Body::from_stream(futures_util::stream::iter(chunks)))
+            }),
+        )
+        .route_layer(StreamingLayer::with_meter(&capture.meter()));
+
+    let response = app.oneshot(Request::get("/stream").body(Body::empty()).unwrap()).await.unwrap();
+    http_body_util::BodyExt::collect(response.into_body()).await.unwrap();
+
+    assert_eq!(capture.metric_sum("http.server.stream.bytes"), 11.0);
+    assert_eq!(capture.histogram_count("http.server.stream.duration"), 1);
+    assert_eq!(capture.histogram_count("http.server.time_to_first_byte"), 1);
+}
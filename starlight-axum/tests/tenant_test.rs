@@ -0,0 +1,130 @@
+use opentelemetry::{Key, KeyValue, Value};
+use opentelemetry_instrumentation_tower::ResponseAttributeExtractor;
+use starlight_axum::axum::Router;
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::{HeaderName, Request, StatusCode};
+use starlight_axum::axum::response::Response;
+use starlight_axum::axum::routing::get;
+use starlight_axum::middleware::tenant::{
+    ClaimsTenantResolver, HeaderTenantResolver, TenantAttribute, TenantId, TenantLayer, UnresolvedTenant,
+};
+use starlight_axum::testing::TelemetryCapture;
+use starlight_axum::tower::ServiceExt;
+use tracing::Instrument;
+
+#[derive(Clone)]
+struct Claims {
+    tenant: Option<String>,
+}
+
+fn app(layer: TenantLayer) -> Router {
+    Router::new()
+        .route("/tenant", get(|tenant: TenantId| async move { tenant.to_string() }))
+        .layer(layer)
+}
+
+async fn text(response: Response) -> String {
+    let bytes = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+fn tenant_attribute(response: &Response) -> Vec<KeyValue> {
+    TenantAttribute.extract_attributes(response)
+}
+
+#[tokio::test]
+async fn resolves_the_tenant_from_a_header() {
+    let layer = TenantLayer::new(HeaderTenantResolver::default()).with_allowlist(["acme"]);
+    let request = Request::get("/tenant").header("x-tenant-id", "acme").body(Body::empty()).unwrap();
+    let response = app(layer).oneshot(request).await.unwrap();
+
+    assert_eq!(response.extensions().get::<TenantId>(), Some(&TenantId::new("acme")));
+    assert_eq!(tenant_attribute(&response), [KeyValue::new("tenant.id", "acme")]);
+    assert_eq!(text(response).await, "acme");
+
+    let custom = TenantLayer::new(HeaderTenantResolver::new(HeaderName::from_static("x-org")));
+    let request = Request::get("/tenant").header("x-org", "globex").body(Body::empty()).unwrap();
+    assert_eq!(text(app(custom).oneshot(request).await.unwrap()).await, "globex");
+}
+
+#[tokio::test]
+async fn resolves_the_tenant_from_claims() {
+    let layer = TenantLayer::new(ClaimsTenantResolver::new(|claims: &Claims| claims.tenant.clone()));
+    let request = Request::get("/tenant")
+        .header("x-tenant-id", "ignored")
+        .extension(Claims {
+            tenant: Some("initech".to_owned()),
+        })
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(text(app(layer.clone()).oneshot(request).await.unwrap()).await, "initech");
+
+    let without_tenant = Request::get("/tenant")
+        .extension(Claims { tenant: None })
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(text(app(layer.clone()).oneshot(without_tenant).await.unwrap()).await, "unknown");
+
+    let unauthenticated = Request::get("/tenant").body(Body::empty()).unwrap();
+    let response = app(layer).oneshot(unauthenticated).await.unwrap();
+    assert_eq!(tenant_attribute(&response), [KeyValue::new("tenant.id", "unknown")]);
+    assert_eq!(text(response).await, "unknown");
+}
+
+#[tokio::test]
+async fn labels_tenants_outside_the_allowlist_as_other() {
+    let layer = TenantLayer::new(HeaderTenantResolver::default()).with_allowlist(["acme", "globex"]);
+    let request = Request::get("/tenant").header("x-tenant-id", "small-shop").body(Body::empty()).unwrap();
+    let response = app(layer.clone()).oneshot(request).await.unwrap();
+
+    assert_eq!(tenant_attribute(&response), [KeyValue::new("tenant.id", "other")]);
+    assert_eq!(text(response).await, "small-shop");
+    assert_eq!(layer.metrics_label(&TenantId::new("globex")), "globex");
+}
+
+#[test]
+fn clones_can_be_configured_further() {
+    let layer = TenantLayer::new(HeaderTenantResolver::default()).with_allowlist(["acme"]);
+    let extended = layer.clone().with_allowlist(["acme", "globex"]);
+
+    assert_eq!(extended.metrics_label(&TenantId::new("globex")), "globex");
+    assert_eq!(layer.metrics_label(&TenantId::new("globex")), "other");
+}
+
+#[tokio::test]
+async fn rejects_unresolved_tenants_when_configured() {
+    let layer = TenantLayer::new(HeaderTenantResolver::default()).with_unresolved(UnresolvedTenant::Reject);
+    let missing = Request::get("/tenant").body(Body::empty()).unwrap();
+    let response = app(layer.clone()).oneshot(missing).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(text(response).await.contains("tenant could not be resolved"));
+
+    let blank = Request::get("/tenant").header("x-tenant-id", " ").body(Body::empty()).unwrap();
+    assert_eq!(app(layer.clone()).oneshot(blank).await.unwrap().status(), StatusCode::BAD_REQUEST);
+
+    let resolved = Request::get("/tenant").header("x-tenant-id", "acme").body(Body::empty()).unwrap();
+    assert_eq!(app(layer).oneshot(resolved).await.unwrap().status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn tags_the_current_span_with_the_tenant() {
+    let capture = TelemetryCapture::install();
+    let layer = TenantLayer::new(HeaderTenantResolver::default());
+    let request = Request::get("/tenant").header("x-tenant-id", "acme").body(Body::empty()).unwrap();
+    app(layer)
+        .oneshot(request)
+        .instrument(tracing::info_span!("http.request"))
+        .await
+        .unwrap();
+
+    let span = capture.spans_named("http.request").remove(0);
+    let tenant = span
+        .attributes
+        .iter()
+        .find(|attribute| attribute.key == Key::from_static_str("tenant.id"))
+        .map(|attribute| attribute.value.clone());
+    assert_eq!(tenant, Some(Value::from("acme")));
+}
@@ -0,0 +1,162 @@
+use rcgen::{BasicConstraints, CertificateParams, CertifiedIssuer, IsCa, KeyPair};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::{ClientConfig, RootCertStore};
+use starlight_axum::axum::Router;
+use starlight_axum::axum::routing::get;
+use starlight_axum::serve_tls;
+use starlight_axum::tls::{PemSource, TlsConfig};
+use std::io;
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::client::TlsStream;
+
+/// A CA with a server certificate for `localhost` signed by it.
+struct Pki {
+    ca: CertifiedIssuer<'static, KeyPair>,
+    cert: String,
+    key: String,
+}
+
+impl Pki {
+    fn new() -> Self {
+        let mut params = CertificateParams::new(Vec::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = CertifiedIssuer::self_signed(params, KeyPair::generate().unwrap()).unwrap();
+        let (cert, key) = Self::issue(&ca, "localhost");
+        Pki { ca, cert, key }
+    }
+
+    /// A certificate and key in PEM, signed by `ca`.
+    fn issue(ca: &CertifiedIssuer<'static, KeyPair>, name: &str) -> (String, String) {
+        let key = KeyPair::generate().unwrap();
+        let cert = CertificateParams::new(vec![name.to_owned()])
+            .unwrap()
+            .signed_by(&key, ca)
+            .unwrap();
+        (cert.pem(), key.serialize_pem())
+    }
+
+    fn client_config(&self, client_cert: Option<(String, String)>) -> ClientConfig {
+        let mut roots = RootCertStore::empty();
+        roots.add(self.ca.der().clone()).unwrap();
+        let builder = ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots);
+        match client_cert {
+            Some((cert, key)) => builder
+                .with_client_auth_cert(
+                    vec![CertificateDer::from_pem_slice(cert.as_bytes()).unwrap()],
+                    PrivateKeyDer::from_pem_slice(key.as_bytes()).unwrap(),
+                )
+                .unwrap(),
+            None => builder.with_no_client_auth(),
+        }
+    }
+}
+
+fn free_addr() -> SocketAddr {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap()
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("starlight-tls-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn start(config: TlsConfig) -> SocketAddr {
+    let addr = free_addr();
+    let router = Router::new().route("/", get(|| async { "hello over tls" }));
+    tokio::spawn(serve_tls(addr, router, config));
+    addr
+}
+
+async fn connect(addr: SocketAddr, config: ClientConfig) -> io::Result<TlsStream<TcpStream>> {
+    let mut attempts = 0;
+    let stream = loop {
+        match TcpStream::connect(addr).await {
+            Ok(stream) => break stream,
+            Err(_) if attempts < 100 => {
+                attempts += 1;
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            Err(err) => return Err(err),
+        }
+    };
+    let name = ServerName::try_from("localhost").unwrap();
+    TlsConnector::from(Arc::new(config)).connect(name, stream).await
+}
+
+async fn get_root(addr: SocketAddr, config: ClientConfig) -> io::Result<String> {
+    let mut stream = connect(addr, config).await?;
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+        .await?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    Ok(response)
+}
+
+#[tokio::test]
+async fn serves_https() {
+    let pki = Pki::new();
+    let addr = start(TlsConfig::from_pem(pki.cert.clone(), pki.key.clone()));
+
+    let response = get_root(addr, pki.client_config(None)).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.ends_with("hello over tls"), "{}", response);
+}
+
+#[tokio::test]
+async fn mtls_rejects_clients_without_a_trusted_certificate() {
+    let pki = Pki::new();
+    let config = TlsConfig::from_pem(pki.cert.clone(), pki.key.clone())
+        .with_client_ca(PemSource::Bytes(pki.ca.pem().into_bytes()));
+    let addr = start(config);
+
+    assert!(get_root(addr, pki.client_config(None)).await.is_err());
+
+    let other = Pki::new();
+    let untrusted = Pki::issue(&other.ca, "client");
+    assert!(get_root(addr, pki.client_config(Some(untrusted))).await.is_err());
+
+    let trusted = Pki::issue(&pki.ca, "client");
+    let response = get_root(addr, pki.client_config(Some(trusted))).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+}
+
+#[tokio::test]
+async fn reloads_rotated_certificate_files() {
+    let pki = Pki::new();
+    let dir = temp_dir("reload");
+    let (cert_path, key_path) = (dir.join("cert.pem"), dir.join("key.pem"));
+    std::fs::write(&cert_path, &pki.cert).unwrap();
+    std::fs::write(&key_path, &pki.key).unwrap();
+    let addr = start(TlsConfig::from_pem_files(&cert_path, &key_path).with_reload_interval(Duration::from_millis(20)));
+
+    let served = |stream: &TlsStream<TcpStream>| stream.get_ref().1.peer_certificates().unwrap()[0].clone();
+    let first = served(&connect(addr, pki.client_config(None)).await.unwrap());
+    assert_eq!(first, CertificateDer::from_pem_slice(pki.cert.as_bytes()).unwrap());
+
+    let (cert, key) = Pki::issue(&pki.ca, "localhost");
+    std::fs::write(&key_path, &key).unwrap();
+    std::fs::write(&cert_path, &cert).unwrap();
+    let rotated = CertificateDer::from_pem_slice(cert.as_bytes()).unwrap();
+    for _ in 0..200 {
+        if served(&connect(addr, pki.client_config(None)).await.unwrap()) == rotated {
+            let response = get_root(addr, pki.client_config(None)).await.unwrap();
+            assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+            let _ = std::fs::remove_dir_all(&dir);
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("the rotated certificate was not served");
+}
@@ -0,0 +1,3 @@
+fn main() {
+    let _attributes = starlight_axum::attrs! { "HTTP Route" => "/orders" };
+}
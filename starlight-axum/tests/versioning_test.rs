@@ -0,0 +1,120 @@
+use opentelemetry::{Key, KeyValue, Value};
+use opentelemetry_instrumentation_tower::{HTTPMetricsLayer, ResponseAttributeExtractor};
+use starlight_axum::axum::Router;
+use starlight_axum::axum::body::Body;
+use starlight_axum::axum::http::{Request, StatusCode, header};
+use starlight_axum::axum::response::Response;
+use starlight_axum::axum::routing::get;
+use starlight_axum::middleware::versioning::{
+    ApiVersion, ApiVersionAttribute, ApiVersionLayer, parse_accept_versions, versioned,
+};
+use starlight_axum::middleware::{oltp_middleware, oltp_middleware_with_versioning};
+use starlight_axum::testing::TelemetryCapture;
+use starlight_axum::tower::ServiceExt;
+use tracing::Instrument;
+
+fn app() -> Router {
+    Router::new()
+        .route("/version", get(|version: ApiVersion| async move { version.to_string() }))
+        .route(
+            "/users",
+            versioned([
+                (1, get(|| async { "users v1" })),
+                (2, get(|| async { "users v2" })),
+            ]),
+        )
+        .layer(ApiVersionLayer::new([1, 2, 3], 1))
+}
+
+async fn call(path: &str, headers: &[(&str, &str)]) -> Response {
+    let mut request = Request::get(path);
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    app().oneshot(request.body(Body::empty()).unwrap()).await.unwrap()
+}
+
+async fn text(response: Response) -> String {
+    let bytes = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+#[test]
+fn parses_vendor_media_types() {
+    assert_eq!(
+        parse_accept_versions("application/vnd.example.v2+json, application/json;q=0.5"),
+        [2]
+    );
+    assert_eq!(parse_accept_versions("application/vnd.example+json"), Vec::<u32>::new());
+}
+
+#[tokio::test]
+async fn negotiates_from_accept_then_header_then_default() {
+    let accept = call(
+        "/version",
+        &[(header::ACCEPT.as_str(), "application/vnd.example.v3+json"), ("x-api-version", "2")],
+    )
+    .await;
+    assert_eq!(text(accept).await, "v3");
+
+    let fallback = call("/version", &[("x-api-version", "v2")]).await;
+    assert_eq!(text(fallback).await, "v2");
+
+    let default = call("/version", &[]).await;
+    assert_eq!(text(default).await, "v1");
+}
+
+#[tokio::test]
+async fn unsupported_version_is_not_acceptable() {
+    let response = call("/version", &[("x-api-version", "9")]).await;
+    assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+    let body: serde_json::Value = serde_json::from_str(&text(response).await).unwrap();
+    assert_eq!(body["supported"], serde_json::json!(["v1", "v2", "v3"]));
+}
+
+#[tokio::test]
+async fn routes_to_the_handler_for_each_version() {
+    assert_eq!(text(call("/users", &[]).await).await, "users v1");
+    assert_eq!(text(call("/users", &[("x-api-version", "2")]).await).await, "users v2");
+
+    // Supported by the API but not mounted on this path.
+    let missing = call("/users", &[("x-api-version", "3")]).await;
+    assert_eq!(missing.status(), StatusCode::NOT_ACCEPTABLE);
+    assert!(text(missing).await.contains(r#""supported":["v1","v2"]"#));
+}
+
+#[tokio::test]
+async fn metrics_layer_labels_versions_without_changing_oltp_middleware() {
+    let _plain: HTTPMetricsLayer = oltp_middleware();
+    let app = app().layer(oltp_middleware_with_versioning());
+    let request = Request::get("/users").header("x-api-version", "2").body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(text(response).await, "users v2");
+}
+
+#[tokio::test]
+async fn versions_suffix_the_route_label_and_tag_the_span() {
+    let capture = TelemetryCapture::install();
+    let request = Request::get("/users").header("x-api-version", "2").body(Body::empty()).unwrap();
+    let response = app()
+        .oneshot(request)
+        .instrument(tracing::info_span!("http.request"))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        ApiVersionAttribute.extract_attributes(&response),
+        [KeyValue::new("http.route", "/users@v2")]
+    );
+    let span = capture.spans_named("http.request").remove(0);
+    let version = span
+        .attributes
+        .iter()
+        .find(|attribute| attribute.key == Key::from_static_str("api.version"))
+        .map(|attribute| attribute.value.clone());
+    assert_eq!(version, Some(Value::from("v2")));
+}
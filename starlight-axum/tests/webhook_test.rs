@@ -0,0 +1,170 @@
+use opentelemetry::{Key, Value};
+use starlight_axum::axum::Router;
+use starlight_axum::axum::body::{Body, Bytes};
+use starlight_axum::axum::http::{Request, StatusCode};
+use starlight_axum::axum::response::Response;
+use starlight_axum::axum::routing::post;
+use starlight_axum::middleware::webhook::{SignatureLayer, VerificationError, sign};
+use starlight_axum::testing::TelemetryCapture;
+use starlight_axum::tower::ServiceExt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::Instrument;
+
+const SECRET: &[u8] = b"whsec_test";
+
+fn app(layer: SignatureLayer) -> Router {
+    Router::new()
+        .route("/webhook", post(|body: Bytes| async move { body }))
+        .layer(layer)
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+fn delivery(body: &'static [u8], timestamp: u64, signature: &str) -> Request<Body> {
+    Request::post("/webhook")
+        .header("x-webhook-timestamp", timestamp.to_string())
+        .header("x-webhook-signature", signature)
+        .body(Body::from(body))
+        .unwrap()
+}
+
+async fn bytes(response: Response) -> Bytes {
+    http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes()
+}
+
+async fn error(response: Response) -> serde_json::Value {
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    serde_json::from_slice(&bytes(response).await).unwrap()
+}
+
+#[tokio::test]
+async fn passes_the_identical_raw_body_to_the_handler() {
+    let body: &[u8] = b"{ \"type\":  \"invoice.paid\",\n  \"amount\": 1200 }\r\n\xff";
+    let timestamp = now();
+    let signature = format!("v1={}", sign(SECRET, timestamp, body));
+    let response = app(SignatureLayer::new(SECRET)).oneshot(delivery(body, timestamp, &signature)).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(bytes(response).await, body);
+}
+
+#[tokio::test]
+async fn rejects_a_tampered_body() {
+    let timestamp = now();
+    let signature = sign(SECRET, timestamp, b"{\"amount\":1200}");
+    let response = app(SignatureLayer::new(SECRET))
+        .oneshot(delivery(b"{\"amount\":9900}", timestamp, &signature))
+        .await
+        .unwrap();
+
+    let body = error(response).await;
+    assert_eq!(body["error"], "invalid_signature");
+    assert_eq!(body["message"], "the signature does not match the payload");
+}
+
+#[tokio::test]
+async fn tags_the_current_span_with_the_outcome() {
+    let capture = TelemetryCapture::install();
+    let timestamp = now();
+    let signature = sign(SECRET, timestamp, b"{\"amount\":1200}");
+    app(SignatureLayer::new(SECRET))
+        .oneshot(delivery(b"{\"amount\":9900}", timestamp, &signature))
+        .instrument(tracing::info_span!("http.request"))
+        .await
+        .unwrap();
+
+    let span = capture.spans_named("http.request").remove(0);
+    let attribute = |key: &'static str| {
+        span.attributes
+            .iter()
+            .find(|attribute| attribute.key == Key::from_static_str(key))
+            .map(|attribute| attribute.value.clone())
+    };
+    assert_eq!(attribute("webhook.verified"), Some(Value::Bool(false)));
+    assert_eq!(attribute("webhook.failure"), Some(Value::from("invalid_signature")));
+}
+
+#[tokio::test]
+async fn rejects_timestamps_outside_the_replay_window() {
+    let body = b"{}";
+    let expired = now() - 600;
+    let signature = sign(SECRET, expired, body);
+    let response = app(SignatureLayer::new(SECRET)).oneshot(delivery(body, expired, &signature)).await.unwrap();
+    assert_eq!(error(response).await["error"], "expired");
+
+    let tolerant = SignatureLayer::new(SECRET).with_tolerance(Duration::from_secs(900));
+    let response = app(tolerant).oneshot(delivery(body, expired, &signature)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[test]
+fn clones_can_be_configured_further() {
+    let body = b"{}";
+    let expired = now() - 600;
+    let mut headers = starlight_axum::axum::http::HeaderMap::new();
+    headers.insert("x-webhook-timestamp", expired.to_string().parse().unwrap());
+    headers.insert("x-webhook-signature", sign(SECRET, expired, body).parse().unwrap());
+
+    let strict = SignatureLayer::new(SECRET);
+    let tolerant = strict.clone().with_tolerance(Duration::from_secs(900));
+    assert_eq!(tolerant.verify(&headers, body, SystemTime::now()), Ok(()));
+    assert_eq!(strict.verify(&headers, body, SystemTime::now()), Err(VerificationError::Expired));
+}
+
+#[tokio::test]
+async fn looks_up_keys_by_id_and_accepts_any_listed_signature() {
+    let layer = SignatureLayer::with_key_lookup(|key_id| match key_id {
+        Some("2024") => Some(b"old".to_vec()),
+        Some("2025") => Some(b"new".to_vec()),
+        _ => None,
+    });
+    let body = b"{}";
+    let timestamp = now();
+    let signatures = format!("v1={},v1={}", sign(b"old", timestamp, body), sign(b"new", timestamp, body));
+    let request = |key_id: &str| {
+        let mut request = delivery(body, timestamp, &signatures);
+        request.headers_mut().insert("x-webhook-key-id", key_id.parse().unwrap());
+        request
+    };
+
+    assert_eq!(app(layer.clone()).oneshot(request("2025")).await.unwrap().status(), StatusCode::OK);
+    assert_eq!(error(app(layer).oneshot(request("2023")).await.unwrap()).await["error"], "unknown_key");
+}
+
+#[test]
+fn reports_missing_and_malformed_headers() {
+    let layer = SignatureLayer::new(SECRET);
+    let verify = |headers: &[(&'static str, &str)]| {
+        let mut map = starlight_axum::axum::http::HeaderMap::new();
+        for (name, value) in headers {
+            map.insert(*name, value.parse().unwrap());
+        }
+        layer.verify(&map, b"", SystemTime::now())
+    };
+
+    assert_eq!(verify(&[("x-webhook-timestamp", "1")]), Err(VerificationError::MissingSignature));
+    assert_eq!(verify(&[("x-webhook-signature", "00")]), Err(VerificationError::MissingTimestamp));
+    assert_eq!(
+        verify(&[("x-webhook-signature", "00"), ("x-webhook-timestamp", "yesterday")]),
+        Err(VerificationError::InvalidTimestamp)
+    );
+    let timestamp = now().to_string();
+    assert_eq!(
+        verify(&[("x-webhook-signature", "not hex"), ("x-webhook-timestamp", &timestamp)]),
+        Err(VerificationError::InvalidSignature)
+    );
+}
+
+#[tokio::test]
+async fn rejects_bodies_over_the_limit() {
+    let body = b"0123456789";
+    let timestamp = now();
+    let layer = SignatureLayer::new(SECRET).with_max_body_bytes(4);
+    let response = app(layer).oneshot(delivery(body, timestamp, &sign(SECRET, timestamp, body))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+}
@@ -0,0 +1,133 @@
+#![cfg(unix)]
+
+use futures_util::{SinkExt, StreamExt};
+use starlight_axum::axum::Router;
+use starlight_axum::axum::extract::Extension;
+use starlight_axum::axum::response::Response;
+use starlight_axum::axum::routing::get;
+use starlight_axum::health::InFlightTracker;
+use starlight_axum::serve::{Listener, serve_many_with_tracker};
+use starlight_axum::testing::TelemetryCapture;
+use starlight_axum::ws::{GracefulWebSocket, Message, WEBSOCKET_CONNECTIONS, WEBSOCKET_MESSAGES, WebSocketConfig};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+use tokio::sync::oneshot;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::{self, Message as ClientMessage};
+use tokio_tungstenite::{WebSocketStream, client_async};
+
+fn socket_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("starlight-ws-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join("app.sock")
+}
+
+async fn echo(ws: GracefulWebSocket) -> Response {
+    ws.on_upgrade(|socket| async move {
+        let _ = socket
+            .run(|message| async move {
+                match message {
+                    Message::Text(text) => Some(Message::Text(format!("echo {}", text.as_str()).into())),
+                    other => Some(other),
+                }
+            })
+            .await;
+    })
+}
+
+async fn unix_stream(path: &Path) -> UnixStream {
+    for _ in 0..100 {
+        if let Ok(stream) = UnixStream::connect(path).await {
+            return stream;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("server did not start on {}", path.display());
+}
+
+async fn connect(path: &Path) -> WebSocketStream<UnixStream> {
+    let (socket, response) = client_async("ws://localhost/echo", unix_stream(path).await)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 101);
+    socket
+}
+
+async fn eventually(condition: impl Fn() -> bool) {
+    for _ in 0..200 {
+        if condition() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    panic!("condition not met");
+}
+
+#[tokio::test]
+async fn closes_connections_when_shutdown_starts() {
+    let capture = TelemetryCapture::install();
+    let path = socket_path("shutdown");
+    let tracker = InFlightTracker::new();
+    let router = Router::new()
+        .route("/echo", get(echo))
+        .layer(Extension(WebSocketConfig::with_meter(&capture.meter()).with_close(1012, "restarting")));
+    let (stop_tx, stop_rx) = oneshot::channel::<()>();
+    let server = tokio::spawn(serve_many_with_tracker(
+        vec![(Listener::unix(&path), router)],
+        async move {
+            let _ = stop_rx.await;
+        },
+        tracker.clone(),
+    ));
+
+    let mut socket = connect(&path).await;
+    socket.send(ClientMessage::text("hello")).await.unwrap();
+    assert_eq!(socket.next().await.unwrap().unwrap(), ClientMessage::text("echo hello"));
+    assert_eq!(capture.metric_sum(WEBSOCKET_CONNECTIONS), 1.0);
+    assert_eq!(tracker.in_flight(), 1);
+
+    stop_tx.send(()).unwrap();
+    let Some(Ok(ClientMessage::Close(Some(close)))) = socket.next().await else {
+        panic!("expected a close frame");
+    };
+    assert_eq!(close.code, CloseCode::Restart);
+    assert_eq!(close.reason.as_str(), "restarting");
+    // The client answers the Close frame and the server ends the connection.
+    assert!(matches!(
+        socket.next().await,
+        None | Some(Err(tungstenite::Error::ConnectionClosed))
+    ));
+
+    tokio::time::timeout(Duration::from_secs(5), server).await.unwrap().unwrap().unwrap();
+    eventually(|| tracker.in_flight() == 0).await;
+    assert_eq!(capture.metric_sum(WEBSOCKET_CONNECTIONS), 0.0);
+    assert_eq!(capture.metric_sum(WEBSOCKET_MESSAGES), 2.0);
+}
+
+#[tokio::test]
+async fn rejects_requests_that_are_not_upgrades() {
+    let path = socket_path("plain");
+    let router = Router::new().route("/echo", get(echo));
+    let (stop_tx, stop_rx) = oneshot::channel::<()>();
+    let server = tokio::spawn(serve_many_with_tracker(
+        vec![(Listener::unix(&path), router)],
+        async move {
+            let _ = stop_rx.await;
+        },
+        InFlightTracker::new(),
+    ));
+
+    let mut stream = unix_stream(&path).await;
+    stream
+        .write_all(b"GET /echo HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 400"), "{}", response);
+
+    stop_tx.send(()).unwrap();
+    server.await.unwrap().unwrap();
+}
@@ -1,19 +1,75 @@
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{parse_macro_input, DeriveInput, Data, Fields};
+use syn::{parse_macro_input, DeriveInput, Data, Fields, GenericArgument, PathArguments, Type};
 
-#[proc_macro_derive(I18nCode, attributes(i18n_code))]
+/// `#[i18n_code("...")]` names the code of a variant. `#[i18n_code(transparent)]` on a
+/// variant with a single field takes the code of that field instead, through a `Box`,
+/// `Arc` or `Rc`, so errors can wrap other errors, or themselves.
+///
+/// `#[i18n(metrics)]` on the enum also generates `KEY_COUNT`, `key_index` and
+/// `key_by_index`, so counters can be kept in an array indexed by variant instead of
+/// labelled by code. Indices follow the declaration order: appending variants keeps
+/// them, reordering or removing variants changes them. Every variant needs a code.
+///
+/// `#[i18n(protocol)]` on the enum also implements
+/// [`I18nCode`](starlight_protocol::i18n::I18nCode) of starlight-protocol, which
+/// translated error responses and validation errors take; the crate then needs
+/// starlight-protocol as a dependency of its own.
+///
+/// With it, the fields of a variant are the parameters of its message, `{name}` for
+/// named fields and `{0}`, `{1}`, .. for tuple fields. Numbers and
+/// [`Date`](starlight_protocol::i18n::Date)s are formatted for the locale, other `Display` values as text; fields of other types
+/// are skipped. `#[i18n(format = "date")]` renders a field as a date (see
+/// [`ToDate`](starlight_protocol::i18n::ToDate)), `#[i18n(format = "currency(VND)")]`
+/// as an amount of that currency and `#[i18n(format = "phone")]` as a masked phone
+/// number (see [`ToPhone`](starlight_protocol::i18n::ToPhone)). Transparent variants have
+/// the parameters of their field, which must implement the trait as well.
+///
+/// Codes are lowercase ASCII words of letters, digits and `_` separated by dots, checked
+/// at compile time. `#[i18n(key_style = "kebab")]` on the enum takes `-` instead of `_`
+/// within words, `#[i18n(key_style = "any")]` turns the check off.
+/// `#[i18n(prefix = "orders")]` prepends `orders.` to the code of every variant, and the
+/// joined code is checked. The diagnostics are pinned by the cases of `tests/ui`.
+///
+/// ```
+/// # use starlight_i18n::I18nCode;
+/// #[derive(I18nCode)]
+/// #[i18n(key_style = "kebab", prefix = "order")]
+/// enum OrderError {
+///     #[i18n_code("not-found")]
+///     NotFound,
+/// }
+///
+/// #[derive(I18nCode)]
+/// #[i18n(key_style = "any")]
+/// enum LegacyError {
+///     #[i18n_code("Legacy.NotFound")]
+///     NotFound,
+/// }
+///
+/// assert_eq!(OrderError::NotFound.get_i18n_code(), "order.not-found");
+/// assert_eq!(LegacyError::NotFound.get_i18n_code(), "Legacy.NotFound");
+/// ```
+#[proc_macro_derive(I18nCode, attributes(i18n_code, i18n))]
 pub fn derive_i18n_key(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     let enum_name = &input.ident;
+    let options = match EnumOptions::parse(&input.attrs) {
+        Ok(options) => options,
+        Err(err) => return err.to_compile_error().into(),
+    };
 
     let Data::Enum(data_enum) = input.data else {
         panic!("I18nCode can only be derived for enums");
     };
 
     let mut match_arms = Vec::new();
+    let mut param_arms = Vec::new();
+    let mut index_arms = Vec::new();
+    let mut codes = Vec::new();
+    let mut errors: Option<syn::Error> = None;
 
-    for variant in data_enum.variants {
+    for (index, variant) in data_enum.variants.into_iter().enumerate() {
         let ident = &variant.ident;
 
         let attr = variant
@@ -22,9 +78,44 @@ pub fn derive_i18n_key(input: TokenStream) -> TokenStream {
             .find(|a| a.path().is_ident("i18n_code"))
             .expect("Missing #[i18n_code(\"...\")] attribute");
 
+        let transparent = attr
+            .parse_args::<syn::Ident>()
+            .is_ok_and(|arg| arg == "transparent");
+        index_arms.push(match variant.fields {
+            Fields::Named(_) => quote! { Self::#ident { .. } => #index },
+            Fields::Unnamed(_) => quote! { Self::#ident ( .. ) => #index },
+            Fields::Unit => quote! { Self::#ident => #index },
+        });
+        if transparent {
+            if options.metrics {
+                let err = syn::Error::new_spanned(
+                    attr,
+                    format!("#[i18n(metrics)] needs a code on every variant, `{}` is transparent", ident),
+                );
+                match &mut errors {
+                    Some(errors) => errors.combine(err),
+                    None => errors = Some(err),
+                }
+            }
+            let (code_arm, params_arm) = transparent_arms(ident, &variant.fields);
+            match_arms.push(code_arm);
+            param_arms.push(params_arm);
+            continue;
+        }
+
         let key: syn::LitStr = attr
             .parse_args()
-            .expect("i18n attribute must be a string literal");
+            .expect("i18n attribute must be a string literal or `transparent`");
+        let key = match &options.prefix {
+            Some(prefix) => syn::LitStr::new(&format!("{}.{}", prefix.value(), key.value()), key.span()),
+            None => key,
+        };
+        if let Err(err) = options.key_style.check(&key) {
+            match &mut errors {
+                Some(errors) => errors.combine(err),
+                None => errors = Some(err),
+            }
+        }
 
         let arm = match variant.fields {
             Fields::Named(_) => quote! { Self::#ident { .. } => #key },
@@ -32,8 +123,63 @@ pub fn derive_i18n_key(input: TokenStream) -> TokenStream {
             Fields::Unit => quote! { Self::#ident => #key },
         };
         match_arms.push(arm);
+        param_arms.push(params_arm(ident, &variant.fields));
+        codes.push(key);
     }
 
+    if let Some(errors) = errors {
+        return errors.to_compile_error().into();
+    }
+
+    let metrics_helpers = if options.metrics {
+        let count = codes.len();
+        let indices = 0..count;
+        quote! {
+            impl #enum_name {
+                /// The number of variants, and of codes.
+                pub const KEY_COUNT: usize = #count;
+
+                /// The position of the variant in declaration order, below `KEY_COUNT`.
+                pub fn key_index(&self) -> usize {
+                    match self {
+                        #(#index_arms),*
+                    }
+                }
+
+                /// The code of the variant at `index`, the inverse of `key_index`.
+                pub fn key_by_index(index: usize) -> Option<&'static str> {
+                    match index {
+                        #(#indices => Some(#codes),)*
+                        _ => None,
+                    }
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    let protocol_impl = if options.protocol {
+        quote! {
+            impl ::starlight_protocol::i18n::I18nCode for #enum_name {
+                fn get_i18n_code(&self) -> &'static str {
+                    #enum_name::get_i18n_code(self)
+                }
+
+                #[allow(unused_variables)]
+                fn i18n_params(&self) -> ::std::vec::Vec<::starlight_protocol::i18n::Param> {
+                    #[allow(unused_imports)]
+                    use ::starlight_protocol::i18n::__private::{DisplayParam as _, NoParam as _, ValueParam as _};
+                    match self {
+                        #(#param_arms),*
+                    }
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
     quote! {
         impl #enum_name {
             pub fn get_i18n_code(&self) -> &'static str {
@@ -42,6 +188,229 @@ pub fn derive_i18n_key(input: TokenStream) -> TokenStream {
                 }
             }
         }
+
+        #protocol_impl
+
+        #metrics_helpers
     }
         .into()
-}
\ No newline at end of file
+}
+
+/// The options of `#[i18n(...)]` on the enum.
+struct EnumOptions {
+    key_style: KeyStyle,
+    prefix: Option<syn::LitStr>,
+    protocol: bool,
+    metrics: bool,
+}
+
+impl EnumOptions {
+    fn parse(attrs: &[syn::Attribute]) -> syn::Result<Self> {
+        let mut options = EnumOptions {
+            key_style: KeyStyle::Snake,
+            prefix: None,
+            protocol: false,
+            metrics: false,
+        };
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("i18n")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("key_style") {
+                    let style: syn::LitStr = meta.value()?.parse()?;
+                    options.key_style = match style.value().as_str() {
+                        "snake" => KeyStyle::Snake,
+                        "kebab" => KeyStyle::Kebab,
+                        "any" => KeyStyle::Any,
+                        _ => {
+                            return Err(syn::Error::new(
+                                style.span(),
+                                "unknown key_style, expected \"snake\", \"kebab\" or \"any\"",
+                            ));
+                        }
+                    };
+                    Ok(())
+                } else if meta.path.is_ident("prefix") {
+                    options.prefix = Some(meta.value()?.parse()?);
+                    Ok(())
+                } else if meta.path.is_ident("protocol") {
+                    options.protocol = true;
+                    Ok(())
+                } else if meta.path.is_ident("metrics") {
+                    options.metrics = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `key_style = \"...\"`, `prefix = \"...\"`, `protocol` or `metrics`"))
+                }
+            })?;
+        }
+        Ok(options)
+    }
+}
+
+/// The shape of codes accepted by the derive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyStyle {
+    /// `^[a-z0-9_]+(\.[a-z0-9_]+)*$`
+    Snake,
+    /// `^[a-z0-9-]+(\.[a-z0-9-]+)*$`
+    Kebab,
+    Any,
+}
+
+impl KeyStyle {
+    /// An error at the span of `key` when it does not have this shape.
+    fn check(self, key: &syn::LitStr) -> syn::Result<()> {
+        let (separator, pattern) = match self {
+            KeyStyle::Snake => (b'_', "[a-z0-9_]+(\\.[a-z0-9_]+)*"),
+            KeyStyle::Kebab => (b'-', "[a-z0-9-]+(\\.[a-z0-9-]+)*"),
+            KeyStyle::Any => return Ok(()),
+        };
+        let code = key.value();
+        let valid = code.split('.').all(|word| {
+            !word.is_empty()
+                && word
+                    .bytes()
+                    .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == separator)
+        });
+        if valid {
+            return Ok(());
+        }
+        Err(syn::Error::new(
+            key.span(),
+            format!(
+                "i18n code {:?} does not match `{}`; add #[i18n(key_style = \"any\")] to the enum to allow it",
+                code, pattern
+            ),
+        ))
+    }
+}
+
+fn transparent_arms(ident: &syn::Ident, fields: &Fields) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+    let field = match fields {
+        Fields::Named(named) if named.named.len() == 1 => &named.named[0],
+        Fields::Unnamed(unnamed) if unnamed.unnamed.len() == 1 => &unnamed.unnamed[0],
+        _ => panic!("#[i18n_code(transparent)] needs a variant with exactly one field"),
+    };
+    let inner = if is_smart_pointer(&field.ty) {
+        quote! { &**inner }
+    } else {
+        quote! { inner }
+    };
+    let pattern = match &field.ident {
+        Some(name) => quote! { Self::#ident { #name: inner } },
+        None => quote! { Self::#ident ( inner ) },
+    };
+    (
+        quote! { #pattern => (#inner).get_i18n_code() },
+        quote! { #pattern => ::starlight_protocol::i18n::I18nCode::i18n_params(#inner) },
+    )
+}
+
+/// Binds the fields of the variant and makes a parameter of each.
+fn params_arm(ident: &syn::Ident, fields: &Fields) -> proc_macro2::TokenStream {
+    let fields: Vec<_> = match fields {
+        Fields::Named(named) => named.named.iter().collect(),
+        Fields::Unnamed(unnamed) => unnamed.unnamed.iter().collect(),
+        Fields::Unit => return quote! { Self::#ident => ::std::vec::Vec::new() },
+    };
+    if fields.is_empty() {
+        return quote! { Self::#ident { .. } => ::std::vec::Vec::new() };
+    }
+    let bindings: Vec<_> = (0..fields.len()).map(|i| quote::format_ident!("field_{}", i)).collect();
+    let params = fields.iter().zip(&bindings).enumerate().map(|(i, (field, binding))| {
+        let name = field.ident.as_ref().map_or_else(|| i.to_string(), ToString::to_string);
+        // `Box<u64>` is formatted as the number it holds, not as its `Display` text.
+        let mut ty = &field.ty;
+        let mut binding = quote! { #binding };
+        while let Some(pointee) = pointee(ty) {
+            ty = pointee;
+            binding = quote! { &**#binding };
+        }
+        match field_format(field).as_deref() {
+            None => quote! {
+                (&&&::starlight_protocol::i18n::__private::Wrap(#binding)).i18n_param(#name)
+            },
+            Some("date") => quote! {
+                ::std::option::Option::Some(::starlight_protocol::i18n::Param::new(
+                    #name,
+                    ::starlight_protocol::i18n::ToDate::to_date(#binding),
+                ))
+            },
+            Some("phone") => quote! {
+                ::std::option::Option::Some(::starlight_protocol::i18n::Param::new(
+                    #name,
+                    ::starlight_protocol::i18n::ToPhone::to_phone(#binding),
+                ))
+            },
+            Some(format) => {
+                let currency = format
+                    .strip_prefix("currency(")
+                    .and_then(|rest| rest.strip_suffix(')'))
+                    .filter(|code| code.len() == 3 && code.bytes().all(|b| b.is_ascii_uppercase()))
+                    .unwrap_or_else(|| {
+                        panic!(
+                            "unknown format {:?}, expected \"date\", \"phone\" or \"currency(XXX)\" with an ISO 4217 code",
+                            format
+                        )
+                    });
+                quote! {
+                    ::std::option::Option::Some(::starlight_protocol::i18n::Param::new(
+                        #name,
+                        ::starlight_protocol::i18n::ParamValue::Currency {
+                            amount: ::starlight_protocol::i18n::ToAmount::to_amount(#binding),
+                            currency: #currency,
+                        },
+                    ))
+                }
+            }
+        }
+    });
+    let pattern = match fields[0].ident {
+        Some(_) => {
+            let names = fields.iter().map(|field| &field.ident);
+            quote! { Self::#ident { #(#names: #bindings),* } }
+        }
+        None => quote! { Self::#ident ( #(#bindings),* ) },
+    };
+    quote! {
+        #pattern => [#(#params),*].into_iter().flatten().collect()
+    }
+}
+
+/// The `format` of `#[i18n(format = "...")]` on a field.
+fn field_format(field: &syn::Field) -> Option<String> {
+    let attr = field.attrs.iter().find(|attr| attr.path().is_ident("i18n"))?;
+    let mut format = None;
+    attr.parse_nested_meta(|meta| {
+        if meta.path.is_ident("format") {
+            format = Some(meta.value()?.parse::<syn::LitStr>()?.value());
+            Ok(())
+        } else {
+            Err(meta.error("expected `format = \"...\"`"))
+        }
+    })
+    .unwrap_or_else(|err| panic!("invalid #[i18n] attribute: {}", err));
+    format
+}
+
+/// `Box<T>`, `Arc<T>` or `Rc<T>`, by name, as a derive cannot resolve paths.
+fn is_smart_pointer(ty: &Type) -> bool {
+    pointee(ty).is_some()
+}
+
+/// The `T` of a [smart pointer](is_smart_pointer).
+fn pointee(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let last = path.path.segments.last()?;
+    let PathArguments::AngleBracketed(args) = &last.arguments else {
+        return None;
+    };
+    if !matches!(last.ident.to_string().as_str(), "Box" | "Arc" | "Rc") || args.args.len() != 1 {
+        return None;
+    }
+    match args.args.first() {
+        Some(GenericArgument::Type(ty)) => Some(ty),
+        _ => None,
+    }
+}
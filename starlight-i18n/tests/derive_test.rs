@@ -2,6 +2,7 @@ use starlight_i18n::I18nCode;
 
 /// Test enum with unit variants only
 #[derive(I18nCode)]
+#[i18n(protocol)]
 pub enum SimpleError {
     #[i18n_code("error.not_found")]
     NotFound,
@@ -11,6 +12,7 @@ pub enum SimpleError {
 
 /// Test enum with unnamed fields (tuple variant)
 #[derive(I18nCode, Clone)]
+#[i18n(protocol)]
 pub enum TupleError {
     #[i18n_code("error.invalid_id")]
     InvalidId(i32),
@@ -83,3 +85,126 @@ fn test_mixed_variant_keys() {
         "error.detailed"
     );
 }
+
+#[test]
+fn test_protocol_trait_impl() {
+    fn code_of(error: &dyn starlight_protocol::i18n::I18nCode) -> &'static str {
+        error.get_i18n_code()
+    }
+
+    assert_eq!(code_of(&SimpleError::Unauthorized), "error.unauthorized");
+    assert_eq!(code_of(&TupleError::InvalidId(1)), "error.invalid_id");
+}
+
+/// Without `#[i18n(protocol)]` the trait is left to the enum, e.g. to add parameters by hand
+#[derive(I18nCode)]
+pub enum QuotaError {
+    #[i18n_code("quota.exceeded")]
+    Exceeded(u32),
+}
+
+impl starlight_protocol::i18n::I18nCode for QuotaError {
+    fn get_i18n_code(&self) -> &'static str {
+        QuotaError::get_i18n_code(self)
+    }
+
+    fn i18n_params(&self) -> Vec<starlight_protocol::i18n::Param> {
+        let QuotaError::Exceeded(limit) = self;
+        vec![starlight_protocol::i18n::Param::new("limit", *limit)]
+    }
+}
+
+#[test]
+fn test_protocol_trait_impl_is_opt_in() {
+    let error: &dyn starlight_protocol::i18n::I18nCode = &QuotaError::Exceeded(3);
+    assert_eq!(error.get_i18n_code(), "quota.exceeded");
+    assert_eq!(error.i18n_params().len(), 1);
+}
+
+/// Recursive enum delegating through smart pointers
+#[derive(I18nCode)]
+pub enum ParseError {
+    #[i18n_code("parse.eof")]
+    UnexpectedEof,
+    #[i18n_code(transparent)]
+    Nested(Box<ParseError>),
+    #[i18n_code(transparent)]
+    Shared { inner: std::sync::Arc<TupleError> },
+    #[i18n_code(transparent)]
+    Plain(SimpleError),
+    #[i18n_code("parse.context")]
+    Context(Box<ParseError>),
+}
+
+#[test]
+fn test_transparent_variants_delegate_through_pointers() {
+    let two_deep = ParseError::Nested(Box::new(ParseError::Nested(Box::new(ParseError::UnexpectedEof))));
+    assert_eq!(two_deep.get_i18n_code(), "parse.eof");
+
+    let shared = ParseError::Shared {
+        inner: std::sync::Arc::new(TupleError::OutOfRange(1, 2)),
+    };
+    assert_eq!(shared.get_i18n_code(), "error.range");
+    assert_eq!(ParseError::Plain(SimpleError::NotFound).get_i18n_code(), "error.not_found");
+}
+
+#[test]
+fn test_boxed_field_without_transparent_keeps_own_code() {
+    let error = ParseError::Context(Box::new(ParseError::UnexpectedEof));
+    assert_eq!(error.get_i18n_code(), "parse.context");
+    let ParseError::Context(inner) = &error else {
+        unreachable!()
+    };
+    assert_eq!(inner.get_i18n_code(), "parse.eof");
+}
+
+#[derive(I18nCode)]
+#[i18n(metrics)]
+pub enum CountedError {
+    #[i18n_code("error.not_found")]
+    NotFound,
+    #[i18n_code("error.invalid_id")]
+    InvalidId(i32),
+    #[i18n_code("error.validation")]
+    ValidationFailed { field: String },
+}
+
+#[test]
+fn test_metrics_key_count_matches_the_variants() {
+    assert_eq!(CountedError::KEY_COUNT, 3);
+    assert_eq!(CountedError::key_by_index(CountedError::KEY_COUNT), None);
+}
+
+#[test]
+fn test_metrics_key_index_round_trips() {
+    let errors = [
+        CountedError::NotFound,
+        CountedError::InvalidId(7),
+        CountedError::ValidationFailed {
+            field: "email".to_string(),
+        },
+    ];
+    for (expected, error) in errors.iter().enumerate() {
+        assert_eq!(error.key_index(), expected);
+        assert_eq!(CountedError::key_by_index(error.key_index()), Some(error.get_i18n_code()));
+    }
+
+    let mut counters = [0u64; CountedError::KEY_COUNT];
+    counters[CountedError::InvalidId(1).key_index()] += 1;
+    assert_eq!(counters, [0, 1, 0]);
+}
+
+#[derive(I18nCode)]
+#[i18n(prefix = "billing")]
+pub enum BillingError {
+    #[i18n_code("card_declined")]
+    CardDeclined,
+    #[i18n_code(transparent)]
+    Simple(SimpleError),
+}
+
+#[test]
+fn test_prefix_is_joined_to_own_codes_only() {
+    assert_eq!(BillingError::CardDeclined.get_i18n_code(), "billing.card_declined");
+    assert_eq!(BillingError::Simple(SimpleError::NotFound).get_i18n_code(), "error.not_found");
+}
@@ -0,0 +1,155 @@
+use starlight_i18n::I18nCode;
+use starlight_protocol::i18n::{
+    Catalog, CldrFormatter, Date, I18nCode as _, Param, ParamValue, Translator, ValueFormatter,
+};
+use starlight_protocol::validation::ValidationErrors;
+
+#[derive(I18nCode)]
+#[i18n(protocol)]
+enum OrderError {
+    #[i18n_code("order.limit_exceeded")]
+    LimitExceeded { total: f64, limit: u64 },
+    #[i18n_code("order.expired")]
+    Expired {
+        #[i18n(format = "date")]
+        on: i64,
+    },
+    #[i18n_code("order.insufficient_funds")]
+    InsufficientFunds(#[i18n(format = "currency(VND)")] u64, String),
+    #[i18n_code("order.nested")]
+    Nested(Box<OrderError>),
+    #[i18n_code(transparent)]
+    Wrapped(Box<OrderError>),
+    #[i18n_code("order.shared_limit_exceeded")]
+    SharedLimitExceeded {
+        total: std::sync::Arc<f64>,
+        limit: Box<u64>,
+    },
+}
+
+fn catalog() -> Catalog {
+    Catalog::new()
+        .with(
+            "en",
+            "order.limit_exceeded",
+            "Total {total} is above the limit of {limit}",
+        )
+        .with("vi", "order.limit_exceeded", "Tổng {total} vượt quá hạn mức {limit}")
+        .with("en", "order.expired", "The order expired on {on}")
+        .with("vi", "order.expired", "Đơn hàng đã hết hạn vào {on}")
+        .with("en", "order.insufficient_funds", "{1} needs {0}")
+        .with("vi", "order.insufficient_funds", "{1} cần {0}")
+}
+
+fn render(error: &OrderError, locale: &str) -> String {
+    catalog()
+        .translate_with(locale, error.get_i18n_code(), &error.i18n_params(), &CldrFormatter)
+        .unwrap()
+}
+
+#[test]
+fn numeric_params_use_the_locale_separators() {
+    let error = OrderError::LimitExceeded {
+        total: 1234567.891,
+        limit: 1000000,
+    };
+    assert_eq!(
+        render(&error, "en"),
+        "Total 1,234,567.891 is above the limit of 1,000,000"
+    );
+    assert_eq!(render(&error, "vi"), "Tổng 1.234.567,891 vượt quá hạn mức 1.000.000");
+}
+
+#[test]
+fn numbers_behind_smart_pointers_are_numeric_params() {
+    let error = OrderError::SharedLimitExceeded {
+        total: std::sync::Arc::new(1234567.891),
+        limit: Box::new(1000000),
+    };
+    assert_eq!(
+        error.i18n_params(),
+        [Param::new("total", 1234567.891), Param::new("limit", 1000000u64)]
+    );
+    let rendered = catalog()
+        .with("vi", "order.shared_limit_exceeded", "Tổng {total} vượt quá hạn mức {limit}")
+        .translate_with("vi", error.get_i18n_code(), &error.i18n_params(), &CldrFormatter)
+        .unwrap();
+    assert_eq!(rendered, "Tổng 1.234.567,891 vượt quá hạn mức 1.000.000");
+}
+
+#[test]
+fn formatted_fields_render_as_dates_and_amounts() {
+    let expired = OrderError::Expired { on: 1_772_582_400 };
+    assert_eq!(render(&expired, "en"), "The order expired on 3/4/2026");
+    assert_eq!(render(&expired, "vi"), "Đơn hàng đã hết hạn vào 04/03/2026");
+
+    let funds = OrderError::InsufficientFunds(250000, "Lan".to_owned());
+    assert_eq!(render(&funds, "en"), "Lan needs ₫250,000");
+    assert_eq!(render(&funds, "vi"), "Lan cần 250.000 ₫");
+}
+
+#[test]
+fn params_are_named_after_the_fields() {
+    let error = OrderError::Expired { on: 0 };
+    assert_eq!(error.i18n_params(), [Param::new("on", Date::new(1970, 1, 1))]);
+
+    // Fields which are not values nor `Display` are skipped; transparent variants have
+    // the parameters of their field.
+    let nested = OrderError::Nested(Box::new(OrderError::Expired { on: 0 }));
+    assert!(nested.i18n_params().is_empty());
+    let wrapped = OrderError::Wrapped(Box::new(OrderError::InsufficientFunds(5, "Lan".to_owned())));
+    assert_eq!(
+        wrapped.i18n_params(),
+        [
+            Param::new(
+                "0",
+                ParamValue::Currency {
+                    amount: 5.0,
+                    currency: "VND"
+                }
+            ),
+            Param::new("1", "Lan"),
+        ]
+    );
+}
+
+#[test]
+fn validation_messages_format_the_error_params() {
+    let errors = ValidationErrors::field(
+        "total",
+        OrderError::LimitExceeded {
+            total: 1500.5,
+            limit: 1000,
+        },
+    );
+    assert_eq!(errors.errors()[0].params["total"], "1500.5");
+
+    let en = errors.clone().translate("en", &catalog());
+    assert_eq!(
+        en.errors()[0].message.as_deref(),
+        Some("Total 1,500.5 is above the limit of 1,000")
+    );
+    let vi = errors.with_param("limit", "1k").translate("vi", &catalog());
+    assert_eq!(
+        vi.errors()[0].message.as_deref(),
+        Some("Tổng 1.500,5 vượt quá hạn mức 1k")
+    );
+}
+
+/// Stands in for an ICU backed formatter.
+struct Iso;
+
+impl ValueFormatter for Iso {
+    fn format(&self, _locale: &str, value: &ParamValue) -> String {
+        value.to_string()
+    }
+}
+
+#[test]
+fn formatters_are_pluggable() {
+    let error = OrderError::Expired { on: 1_772_582_400 };
+    assert_eq!(
+        catalog().translate_with("vi", "order.expired", &error.i18n_params(), &Iso),
+        Some("Đơn hàng đã hết hạn vào 2026-03-04".to_owned())
+    );
+}
@@ -0,0 +1,66 @@
+use starlight_i18n::I18nCode;
+use starlight_protocol::i18n::{Catalog, CldrFormatter, I18nCode as _, Translator};
+use starlight_protocol::validation::ValidationErrors;
+use starlight_utils::{PhoneNumber, normalize_phone};
+
+#[derive(I18nCode)]
+#[i18n(protocol)]
+enum OtpError {
+    #[i18n_code("otp.failed")]
+    OtpFailed {
+        #[i18n(format = "phone")]
+        phone: PhoneNumber,
+    },
+    #[i18n_code("otp.blocked")]
+    Blocked { phone: PhoneNumber },
+}
+
+fn catalog() -> Catalog {
+    Catalog::new()
+        .with("vi", "otp.failed", "Không gửi được mã OTP tới {phone}")
+        .with("en", "otp.failed", "Could not send the OTP to {phone}")
+        .with("vi", "otp.blocked", "Số {phone} đã bị khóa")
+}
+
+fn render(error: &OtpError, locale: &str) -> String {
+    catalog()
+        .translate_with(locale, error.get_i18n_code(), &error.i18n_params(), &CldrFormatter)
+        .unwrap()
+}
+
+fn vn_number() -> PhoneNumber {
+    normalize_phone("0912 345 678", "VN").unwrap()
+}
+
+#[test]
+fn tagged_phones_render_masked_in_the_national_format() {
+    let error = OtpError::OtpFailed { phone: vn_number() };
+    assert_eq!(render(&error, "vi"), "Không gửi được mã OTP tới 091* *** 678");
+    assert_eq!(render(&error, "en"), "Could not send the OTP to +84 91* *** 678");
+
+    // Untagged phones are the masked E.164.
+    let blocked = OtpError::Blocked { phone: vn_number() };
+    assert_eq!(render(&blocked, "vi"), "Số +8491****678 đã bị khóa");
+}
+
+#[test]
+fn the_full_number_is_nowhere_in_the_envelope() {
+    let errors = ValidationErrors::field("phone", OtpError::OtpFailed { phone: vn_number() })
+        .with("phone", OtpError::Blocked { phone: vn_number() })
+        .translate("vi", &catalog());
+    let envelope = serde_json::to_value(&errors).unwrap();
+    assert_eq!(
+        envelope["errors"][0],
+        serde_json::json!({
+            "field": "phone",
+            "code": "otp.failed",
+            "params": {"phone": "+8491****678"},
+            "message": "Không gửi được mã OTP tới 091* *** 678",
+        })
+    );
+    assert_eq!(envelope["errors"][1]["params"]["phone"], "+8491****678");
+
+    let body = envelope.to_string();
+    assert!(!body.contains("912345678"));
+    assert!(!body.contains("0912 345 678"));
+}
@@ -0,0 +1,19 @@
+use starlight_i18n::I18nCode;
+
+#[derive(I18nCode)]
+#[i18n(key_style = "any")]
+enum IoError {
+    #[i18n_code("io.closed")]
+    Closed,
+}
+
+#[derive(I18nCode)]
+#[i18n(metrics)]
+enum OrderError {
+    #[i18n_code("order.not_found")]
+    NotFound,
+    #[i18n_code(transparent)]
+    Io(IoError),
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use starlight_i18n::I18nCode;
+
+#[derive(I18nCode)]
+#[i18n(key_style = "kebab")]
+enum OrderError {
+    #[i18n_code("order.not_found")]
+    NotFound,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use starlight_i18n::I18nCode;
+
+#[derive(I18nCode)]
+enum OrderError {
+    #[i18n_code("order.NotFound")]
+    NotFound,
+}
+
+fn main() {}
@@ -0,0 +1,6 @@
+/// The diagnostics of invalid derives, compared with the `.stderr` next to each case.
+/// Regenerate them with `TRYBUILD=overwrite cargo test --test ui_test`.
+#[test]
+fn invalid_derives_are_rejected() {
+    trybuild::TestCases::new().compile_fail("tests/ui/*.rs");
+}
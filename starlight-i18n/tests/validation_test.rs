@@ -0,0 +1,136 @@
+use starlight_i18n::I18nCode;
+use starlight_protocol::i18n::Catalog;
+use starlight_protocol::validation::{ValidateI18n, ValidationErrors};
+
+#[derive(I18nCode)]
+#[i18n(protocol)]
+enum PayloadError {
+    #[i18n_code("error.required")]
+    Required,
+    #[i18n_code("error.invalid_format")]
+    InvalidFormat,
+    #[i18n_code("error.out_of_range")]
+    OutOfRange,
+}
+
+struct Address {
+    city: String,
+}
+
+struct Item {
+    qty: u32,
+}
+
+struct Order {
+    email: String,
+    address: Address,
+    items: Vec<Item>,
+}
+
+impl ValidateI18n for Address {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        if self.city.is_empty() {
+            errors.add("city", PayloadError::Required);
+        }
+        errors.into_result()
+    }
+}
+
+impl ValidateI18n for Item {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        if (1..=10).contains(&self.qty) {
+            return Ok(());
+        }
+        Err(ValidationErrors::field("qty", PayloadError::OutOfRange)
+            .with_param("min", 1)
+            .with_param("max", 10))
+    }
+}
+
+impl ValidateI18n for Order {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        if !self.email.contains('@') {
+            errors.add("email", PayloadError::InvalidFormat);
+        }
+        errors.nest("address", self.address.validate());
+        for (index, item) in self.items.iter().enumerate() {
+            errors.nest_index("items", index, item.validate());
+        }
+        errors.into_result()
+    }
+}
+
+fn invalid_order() -> Order {
+    Order {
+        email: "not-an-email".to_owned(),
+        address: Address { city: String::new() },
+        items: vec![Item { qty: 1 }, Item { qty: 2 }, Item { qty: 0 }],
+    }
+}
+
+#[test]
+fn valid_payload_has_no_errors() {
+    let order = Order {
+        email: "a@example.com".to_owned(),
+        address: Address { city: "Hanoi".to_owned() },
+        items: vec![Item { qty: 3 }],
+    };
+    assert_eq!(order.validate(), Ok(()));
+}
+
+#[test]
+fn all_field_errors_are_collected_with_nested_paths() {
+    let errors = invalid_order().validate().unwrap_err();
+    let fields: Vec<_> = errors.errors().iter().map(|error| error.field.as_str()).collect();
+    assert_eq!(fields, ["email", "address.city", "items[2].qty"]);
+    assert_eq!(
+        errors.to_string(),
+        "3 field(s) failed validation: email (error.invalid_format), address.city (error.required), \
+         items[2].qty (error.out_of_range)"
+    );
+}
+
+#[test]
+fn envelope_snapshot() {
+    let errors = invalid_order().validate().unwrap_err();
+    assert_eq!(
+        serde_json::to_value(&errors).unwrap(),
+        serde_json::json!({
+            "code": "error.validation_failed",
+            "message": "error.validation_failed",
+            "errors": [
+                {"field": "email", "code": "error.invalid_format"},
+                {"field": "address.city", "code": "error.required"},
+                {"field": "items[2].qty", "code": "error.out_of_range", "params": {"max": "10", "min": "1"}},
+            ],
+        })
+    );
+}
+
+#[test]
+fn translated_envelope_snapshot() {
+    let catalog = Catalog::new()
+        .with("en", "error.validation_failed", "The request has invalid fields")
+        .with("en", "error.out_of_range", "Must be between {min} and {max}")
+        .with("en", "error.required", "Required");
+    let errors = invalid_order().validate().unwrap_err().translate("en", &catalog);
+    assert_eq!(
+        serde_json::to_value(&errors).unwrap(),
+        serde_json::json!({
+            "code": "error.validation_failed",
+            "message": "The request has invalid fields",
+            "errors": [
+                {"field": "email", "code": "error.invalid_format", "message": "error.invalid_format"},
+                {"field": "address.city", "code": "error.required", "message": "Required"},
+                {
+                    "field": "items[2].qty",
+                    "code": "error.out_of_range",
+                    "params": {"max": "10", "min": "1"},
+                    "message": "Must be between 1 and 10",
+                },
+            ],
+        })
+    );
+}
@@ -0,0 +1,69 @@
+mod format;
+mod params;
+
+pub use format::{CldrFormatter, ValueFormatter, interpolate};
+pub use params::{Date, Param, ParamValue, Phone, ToAmount, ToDate, ToPhone};
+
+#[doc(hidden)]
+pub use params::private as __private;
+
+use std::collections::HashMap;
+
+/// Implemented by `#[derive(I18nCode)]` from starlight-i18n on enums marked
+/// `#[i18n(protocol)]`.
+pub trait I18nCode {
+    fn get_i18n_code(&self) -> &'static str;
+
+    /// The values substituted for `{name}` in the message, by default none. The derive
+    /// makes one of each field, named after it (`0`, `1`, .. in tuple variants).
+    fn i18n_params(&self) -> Vec<Param> {
+        Vec::new()
+    }
+}
+
+/// Looks up the message for an i18n code in a locale.
+pub trait Translator: Send + Sync {
+    fn translate(&self, locale: &str, code: &str) -> Option<String>;
+
+    /// The message with `params` substituted, formatted for `locale` by `formatter`.
+    fn translate_with(
+        &self,
+        locale: &str,
+        code: &str,
+        params: &[Param],
+        formatter: &dyn ValueFormatter,
+    ) -> Option<String> {
+        let message = self.translate(locale, code)?;
+        Some(interpolate(&message, locale, params, formatter))
+    }
+}
+
+/// In-memory message catalog keyed by locale, then by i18n code.
+#[derive(Debug, Clone, Default)]
+pub struct Catalog {
+    messages: HashMap<String, HashMap<String, String>>,
+}
+
+impl Catalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, locale: &str, code: &str, message: &str) -> Self {
+        self.insert(locale, code, message);
+        self
+    }
+
+    pub fn insert(&mut self, locale: &str, code: &str, message: &str) {
+        self.messages
+            .entry(locale.to_owned())
+            .or_default()
+            .insert(code.to_owned(), message.to_owned());
+    }
+}
+
+impl Translator for Catalog {
+    fn translate(&self, locale: &str, code: &str) -> Option<String> {
+        self.messages.get(locale)?.get(code).cloned()
+    }
+}
@@ -0,0 +1,332 @@
+use super::params::{Date, Param, ParamValue, Phone};
+
+/// Renders parameter values for a locale. [`CldrFormatter`] covers a few locales; an
+/// implementation backed by ICU can replace it.
+pub trait ValueFormatter: Send + Sync {
+    fn format(&self, locale: &str, value: &ParamValue) -> String;
+}
+
+/// Formats numbers, dates and amounts with the separators and patterns of the CLDR data
+/// of `en`, `en-GB`, `vi`, `de`, `fr` and `ja`; other locales are formatted as `en`, and
+/// regional variants as their language. Text is left as is. Phone numbers are in their
+/// national format when of the region of the locale, `VN` for `vi`, `US` for `en` or
+/// the region of `en-GB`, and international otherwise.
+///
+/// Floats keep up to 3 fraction digits, amounts the digits of their currency:
+///
+/// ```
+/// use starlight_protocol::i18n::{CldrFormatter, ParamValue, ValueFormatter};
+///
+/// let amount = ParamValue::Float(1234.567);
+/// assert_eq!(CldrFormatter.format("en", &amount), "1,234.567");
+/// assert_eq!(CldrFormatter.format("vi-VN", &amount), "1.234,567");
+/// let price = ParamValue::Currency { amount: 1234567.0, currency: "VND" };
+/// assert_eq!(CldrFormatter.format("vi", &price), "1.234.567 ₫");
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CldrFormatter;
+
+#[derive(Debug, Clone, Copy)]
+enum DateOrder {
+    /// Month, day, year, without leading zeros.
+    Mdy,
+    Dmy,
+    Ymd,
+}
+
+#[derive(Debug)]
+struct LocaleData {
+    locale: &'static str,
+    decimal: &'static str,
+    group: &'static str,
+    date: (DateOrder, char),
+    /// Whether the currency symbol follows the amount, after a space.
+    symbol_after: bool,
+    /// The region of a locale without one, e.g. `VN` for `vi`.
+    region: &'static str,
+}
+
+const LOCALES: &[LocaleData] = &[
+    LocaleData {
+        locale: "en",
+        decimal: ".",
+        group: ",",
+        date: (DateOrder::Mdy, '/'),
+        symbol_after: false,
+        region: "US",
+    },
+    LocaleData {
+        locale: "en-gb",
+        decimal: ".",
+        group: ",",
+        date: (DateOrder::Dmy, '/'),
+        symbol_after: false,
+        region: "GB",
+    },
+    LocaleData {
+        locale: "vi",
+        decimal: ",",
+        group: ".",
+        date: (DateOrder::Dmy, '/'),
+        symbol_after: true,
+        region: "VN",
+    },
+    LocaleData {
+        locale: "de",
+        decimal: ",",
+        group: ".",
+        date: (DateOrder::Dmy, '.'),
+        symbol_after: true,
+        region: "DE",
+    },
+    LocaleData {
+        locale: "fr",
+        decimal: ",",
+        group: "\u{202f}",
+        date: (DateOrder::Dmy, '/'),
+        symbol_after: true,
+        region: "FR",
+    },
+    LocaleData {
+        locale: "ja",
+        decimal: ".",
+        group: ",",
+        date: (DateOrder::Ymd, '/'),
+        symbol_after: false,
+        region: "JP",
+    },
+];
+
+/// Symbols and fraction digits of common currencies; others show their code.
+const CURRENCIES: &[(&str, &str, usize)] = &[
+    ("EUR", "€", 2),
+    ("GBP", "£", 2),
+    ("JPY", "¥", 0),
+    ("KRW", "₩", 0),
+    ("USD", "$", 2),
+    ("VND", "₫", 0),
+];
+
+fn locale_data(locale: &str) -> &'static LocaleData {
+    let locale = locale.to_ascii_lowercase().replace('_', "-");
+    let language = locale.split('-').next().unwrap_or_default();
+    LOCALES
+        .iter()
+        .find(|data| data.locale == locale)
+        .or_else(|| LOCALES.iter().find(|data| data.locale == language))
+        .unwrap_or(&LOCALES[0])
+}
+
+impl ValueFormatter for CldrFormatter {
+    fn format(&self, locale: &str, value: &ParamValue) -> String {
+        let data = locale_data(locale);
+        match value {
+            ParamValue::Integer(value) => {
+                let sign = if *value < 0 { "-" } else { "" };
+                format!("{}{}", sign, group(&value.unsigned_abs().to_string(), data.group))
+            }
+            ParamValue::Float(value) => decimal(*value, 3, true, data),
+            ParamValue::Text(value) => value.clone(),
+            ParamValue::Date(date) => format_date(date, data),
+            ParamValue::Currency { amount, currency } => {
+                let (symbol, digits) = CURRENCIES
+                    .iter()
+                    .find(|(code, _, _)| code == currency)
+                    .map_or((*currency, 2), |(_, symbol, digits)| (*symbol, *digits));
+                let number = decimal(amount.abs(), digits, false, data);
+                let sign = if *amount < 0.0 { "-" } else { "" };
+                match (data.symbol_after, symbol.chars().count() > 1) {
+                    (true, _) => format!("{}{} {}", sign, number, symbol),
+                    (false, true) => format!("{}{} {}", sign, symbol, number),
+                    (false, false) => format!("{}{}{}", sign, symbol, number),
+                }
+            }
+            ParamValue::Phone(phone) => format_phone(phone, locale, data),
+        }
+    }
+}
+
+/// `value` with `digits` fraction digits, trailing zeros removed when `trim`.
+fn decimal(value: f64, digits: usize, trim: bool, data: &LocaleData) -> String {
+    if value.is_nan() {
+        return "NaN".to_owned();
+    }
+    if value.is_infinite() {
+        return if value < 0.0 { "-∞" } else { "∞" }.to_owned();
+    }
+    let rounded = format!("{:.*}", digits, value.abs());
+    let (integer, fraction) = rounded.split_once('.').unwrap_or((&rounded, ""));
+    let fraction = if trim { fraction.trim_end_matches('0') } else { fraction };
+    let sign = if value < 0.0 && rounded.bytes().any(|b| matches!(b, b'1'..=b'9')) {
+        "-"
+    } else {
+        ""
+    };
+    match fraction {
+        "" => format!("{}{}", sign, group(integer, data.group)),
+        fraction => format!("{}{}{}{}", sign, group(integer, data.group), data.decimal, fraction),
+    }
+}
+
+/// Inserts `separator` between groups of three digits.
+fn group(digits: &str, separator: &str) -> String {
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3 * separator.len());
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push_str(separator);
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+fn format_date(date: &Date, data: &LocaleData) -> String {
+    let (order, separator) = data.date;
+    match order {
+        DateOrder::Mdy => format!("{}{sep}{}{sep}{}", date.month, date.day, date.year, sep = separator),
+        DateOrder::Dmy => format!(
+            "{:02}{sep}{:02}{sep}{}",
+            date.day,
+            date.month,
+            date.year,
+            sep = separator
+        ),
+        DateOrder::Ymd => format!(
+            "{}{sep}{:02}{sep}{:02}",
+            date.year,
+            date.month,
+            date.day,
+            sep = separator
+        ),
+    }
+}
+
+fn format_phone(phone: &Phone, locale: &str, data: &LocaleData) -> String {
+    let region = locale
+        .split(['-', '_'])
+        .nth(1)
+        .filter(|region| region.len() == 2)
+        .unwrap_or(data.region);
+    match phone.region {
+        Some(number_region) if number_region.eq_ignore_ascii_case(region) => phone.national.clone(),
+        _ => phone.international.clone(),
+    }
+}
+
+/// Substitutes the parameters for `{name}` in `message`, formatted for `locale`.
+/// Placeholders without a parameter are kept.
+pub fn interpolate(message: &str, locale: &str, params: &[Param], formatter: &dyn ValueFormatter) -> String {
+    let mut output = String::with_capacity(message.len());
+    let mut rest = message;
+    while let Some(open) = rest.find('{') {
+        output.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let param = after.find('}').and_then(|close| {
+            params
+                .iter()
+                .find(|param| param.name == after[..close])
+                .map(|param| (close, param))
+        });
+        match param {
+            Some((close, param)) => {
+                output.push_str(&formatter.format(locale, &param.value));
+                rest = &after[close + 1..];
+            }
+            None => {
+                output.push('{');
+                rest = after;
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(locale: &str, value: impl Into<ParamValue>) -> String {
+        CldrFormatter.format(locale, &value.into())
+    }
+
+    #[test]
+    fn groups_digits_per_locale() {
+        assert_eq!(format("en", 1234567), "1,234,567");
+        assert_eq!(format("vi", 1234567), "1.234.567");
+        assert_eq!(format("de", -1234567i64), "-1.234.567");
+        assert_eq!(format("fr", 1234567u64), "1\u{202f}234\u{202f}567");
+        assert_eq!(format("en", 999), "999");
+        assert_eq!(format("en", 0), "0");
+    }
+
+    #[test]
+    fn formats_fractions_per_locale() {
+        assert_eq!(format("en", 1234567.891), "1,234,567.891");
+        assert_eq!(format("vi", 1234.5), "1.234,5");
+        assert_eq!(format("de", 1234.0), "1.234");
+        assert_eq!(format("en", 0.1234), "0.123");
+        assert_eq!(format("en", -0.0001), "0");
+        assert_eq!(format("en", f64::NEG_INFINITY), "-∞");
+    }
+
+    #[test]
+    fn falls_back_to_the_language_then_english() {
+        assert_eq!(format("vi_VN", 1234.5), "1.234,5");
+        assert_eq!(format("de-AT", 1234.5), "1.234,5");
+        assert_eq!(format("xx", 1234.5), "1,234.5");
+    }
+
+    #[test]
+    fn formats_dates_per_locale() {
+        let date = Date::new(2026, 3, 4);
+        assert_eq!(format("en", date), "3/4/2026");
+        assert_eq!(format("en-GB", date), "04/03/2026");
+        assert_eq!(format("vi", date), "04/03/2026");
+        assert_eq!(format("de", date), "04.03.2026");
+        assert_eq!(format("ja", date), "2026/03/04");
+    }
+
+    #[test]
+    fn formats_amounts_with_the_currency_digits() {
+        let amount = |amount, currency| ParamValue::Currency { amount, currency };
+        assert_eq!(format("en", amount(1234.5, "USD")), "$1,234.50");
+        assert_eq!(format("en", amount(-5.0, "EUR")), "-€5.00");
+        assert_eq!(format("de", amount(1234.5, "EUR")), "1.234,50 €");
+        assert_eq!(format("vi", amount(1234567.4, "VND")), "1.234.567 ₫");
+        assert_eq!(format("en", amount(1234567.0, "VND")), "₫1,234,567");
+        assert_eq!(format("en", amount(12.0, "CHF")), "CHF 12.00");
+    }
+
+    #[test]
+    fn formats_phones_of_the_locale_region_nationally() {
+        let phone = Phone {
+            region: Some("VN"),
+            e164: "+8491****678".to_owned(),
+            national: "091* *** 678".to_owned(),
+            international: "+84 91* *** 678".to_owned(),
+        };
+        assert_eq!(format("vi", phone.clone()), "091* *** 678");
+        assert_eq!(format("en-VN", phone.clone()), "091* *** 678");
+        assert_eq!(format("en", phone.clone()), "+84 91* *** 678");
+        assert_eq!(format("vi-US", phone), "+84 91* *** 678");
+    }
+
+    #[test]
+    fn converts_unix_timestamps_to_dates() {
+        assert_eq!(Date::from_unix_seconds(0), Date::new(1970, 1, 1));
+        assert_eq!(Date::from_unix_seconds(1_772_582_400), Date::new(2026, 3, 4));
+        assert_eq!(Date::from_unix_seconds(951_782_400), Date::new(2000, 2, 29));
+        assert_eq!(Date::from_unix_seconds(-1), Date::new(1969, 12, 31));
+    }
+
+    #[test]
+    fn interpolates_known_placeholders_only() {
+        let params = [Param::new("count", 1234), Param::new("name", "Lan")];
+        assert_eq!(
+            interpolate("{name} has {count} orders, {missing} {", "vi", &params, &CldrFormatter),
+            "Lan has 1.234 orders, {missing} {"
+        );
+        assert_eq!(interpolate("{{count}}", "en", &params, &CldrFormatter), "{1,234}");
+    }
+}
@@ -0,0 +1,261 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A calendar date, without time zone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    pub year: i32,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl Date {
+    pub fn new(year: i32, month: u8, day: u8) -> Self {
+        Date { year, month, day }
+    }
+
+    /// The UTC date of a Unix timestamp in seconds.
+    pub fn from_unix_seconds(seconds: i64) -> Self {
+        // Howard Hinnant's civil_from_days.
+        let days = seconds.div_euclid(86_400) + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days.rem_euclid(146_097);
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+        Date::new(year as i32, month as u8, day as u8)
+    }
+}
+
+impl fmt::Display for Date {
+    /// ISO 8601, `2026-03-14`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// A phone number of a message, masked by whoever made it so that messages and the
+/// `params` of errors never carry the full number.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Phone {
+    /// ISO 3166-1 alpha-2 of the number when known, e.g. `VN`.
+    pub region: Option<&'static str>,
+    /// E.164, e.g. `+8491****678`.
+    pub e164: String,
+    /// As dialled within its country, e.g. `091* *** 678`.
+    pub national: String,
+    /// As dialled from abroad, e.g. `+84 91* *** 678`.
+    pub international: String,
+}
+
+impl fmt::Display for Phone {
+    /// The E.164 form.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.e164)
+    }
+}
+
+/// The value of a message parameter, formatted for the locale by a
+/// [`ValueFormatter`](super::ValueFormatter).
+#[derive(Debug, Clone)]
+pub enum ParamValue {
+    Integer(i128),
+    Float(f64),
+    Text(String),
+    Date(Date),
+    /// An amount of an ISO 4217 currency, such as `VND`.
+    Currency {
+        amount: f64,
+        currency: &'static str,
+    },
+    /// Rendered in the national format in locales of its region.
+    Phone(Phone),
+}
+
+/// Floats compare by their bits, so `NaN` equals itself.
+impl PartialEq for ParamValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (ParamValue::Integer(a), ParamValue::Integer(b)) => a == b,
+            (ParamValue::Float(a), ParamValue::Float(b)) => a.to_bits() == b.to_bits(),
+            (ParamValue::Text(a), ParamValue::Text(b)) => a == b,
+            (ParamValue::Date(a), ParamValue::Date(b)) => a == b,
+            (ParamValue::Phone(a), ParamValue::Phone(b)) => a == b,
+            (
+                ParamValue::Currency { amount, currency },
+                ParamValue::Currency {
+                    amount: other_amount,
+                    currency: other_currency,
+                },
+            ) => amount.to_bits() == other_amount.to_bits() && currency == other_currency,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for ParamValue {}
+
+impl fmt::Display for ParamValue {
+    /// The value without locale formatting, e.g. for the `params` of a
+    /// [`FieldError`](crate::validation::FieldError).
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParamValue::Integer(value) => write!(f, "{}", value),
+            ParamValue::Float(value) => write!(f, "{}", value),
+            ParamValue::Text(value) => f.write_str(value),
+            ParamValue::Date(value) => write!(f, "{}", value),
+            ParamValue::Currency { amount, currency } => write!(f, "{} {}", amount, currency),
+            ParamValue::Phone(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+macro_rules! from_values {
+    ($variant:ident: $($t:ty),*) => {
+        $(impl From<$t> for ParamValue {
+            fn from(value: $t) -> Self {
+                ParamValue::$variant(value.into())
+            }
+        })*
+    };
+}
+
+from_values!(Integer: i8, i16, i32, i64, i128, u8, u16, u32, u64);
+from_values!(Float: f32, f64);
+from_values!(Text: &str, String, Cow<'_, str>);
+from_values!(Date: Date);
+from_values!(Phone: Phone);
+
+impl From<isize> for ParamValue {
+    fn from(value: isize) -> Self {
+        ParamValue::Integer(value as i128)
+    }
+}
+
+impl From<usize> for ParamValue {
+    fn from(value: usize) -> Self {
+        ParamValue::Integer(value as i128)
+    }
+}
+
+/// A named value substituted for `{name}` in a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Param {
+    pub name: Cow<'static, str>,
+    pub value: ParamValue,
+}
+
+impl Param {
+    pub fn new(name: impl Into<Cow<'static, str>>, value: impl Into<ParamValue>) -> Self {
+        Param {
+            name: name.into(),
+            value: value.into(),
+        }
+    }
+}
+
+/// Fields rendered as dates with `#[i18n(format = "date")]`: [`Date`]s, [`SystemTime`]s
+/// and Unix timestamps in seconds.
+pub trait ToDate {
+    fn to_date(&self) -> Date;
+}
+
+impl ToDate for Date {
+    fn to_date(&self) -> Date {
+        *self
+    }
+}
+
+impl ToDate for SystemTime {
+    fn to_date(&self) -> Date {
+        let seconds = match self.duration_since(UNIX_EPOCH) {
+            Ok(since) => since.as_secs() as i64,
+            Err(before) => -(before.duration().as_secs_f64().ceil() as i64),
+        };
+        Date::from_unix_seconds(seconds)
+    }
+}
+
+impl ToDate for i64 {
+    fn to_date(&self) -> Date {
+        Date::from_unix_seconds(*self)
+    }
+}
+
+/// Fields rendered as amounts with `#[i18n(format = "currency(VND)")]`.
+pub trait ToAmount {
+    fn to_amount(&self) -> f64;
+}
+
+macro_rules! to_amount {
+    ($($t:ty),*) => {
+        $(impl ToAmount for $t {
+            fn to_amount(&self) -> f64 {
+                *self as f64
+            }
+        })*
+    };
+}
+
+to_amount!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64);
+
+/// Fields rendered as phone numbers with `#[i18n(format = "phone")]`, such as the
+/// `PhoneNumber` of starlight-utils with its `i18n` feature.
+pub trait ToPhone {
+    fn to_phone(&self) -> Phone;
+}
+
+impl ToPhone for Phone {
+    fn to_phone(&self) -> Phone {
+        self.clone()
+    }
+}
+
+/// Used by `#[derive(I18nCode)]` to make parameters of the fields: values convertible to
+/// [`ParamValue`] keep their type, other `Display` values become text and the rest are
+/// skipped, picked through auto-ref by `(&&&Wrap(field)).i18n_param(name)`.
+#[doc(hidden)]
+pub mod private {
+    use super::{Param, ParamValue};
+    use std::fmt::Display;
+
+    pub struct Wrap<'a, T: ?Sized>(pub &'a T);
+
+    pub trait ValueParam {
+        fn i18n_param(&self, name: &'static str) -> Option<Param>;
+    }
+
+    impl<T: Clone + Into<ParamValue>> ValueParam for &&Wrap<'_, T> {
+        fn i18n_param(&self, name: &'static str) -> Option<Param> {
+            Some(Param::new(name, self.0.clone()))
+        }
+    }
+
+    pub trait DisplayParam {
+        fn i18n_param(&self, name: &'static str) -> Option<Param>;
+    }
+
+    impl<T: Display + ?Sized> DisplayParam for &Wrap<'_, T> {
+        fn i18n_param(&self, name: &'static str) -> Option<Param> {
+            Some(Param::new(name, self.0.to_string()))
+        }
+    }
+
+    pub trait NoParam {
+        fn i18n_param(&self, name: &'static str) -> Option<Param>;
+    }
+
+    impl<T: ?Sized> NoParam for Wrap<'_, T> {
+        fn i18n_param(&self, _name: &'static str) -> Option<Param> {
+            None
+        }
+    }
+}
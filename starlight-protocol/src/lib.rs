@@ -1 +1,3 @@
 pub mod constants;
+pub mod i18n;
+pub mod validation;
@@ -0,0 +1,209 @@
+//! Field errors of a request payload, collected so a handler can report all of them at
+//! once instead of the first.
+
+use crate::i18n::{CldrFormatter, I18nCode, Param, ParamValue, Translator, ValueFormatter, interpolate};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// The i18n code of the envelope around the field errors.
+pub const VALIDATION_FAILED: &str = "error.validation_failed";
+
+/// Implemented by request payloads which check themselves after deserializing.
+pub trait ValidateI18n {
+    fn validate(&self) -> Result<(), ValidationErrors>;
+}
+
+/// One field which failed validation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    /// Dotted, with indices in brackets, such as `address.city` or `items[2].qty`.
+    pub field: String,
+    pub code: String,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub params: BTreeMap<String, String>,
+    /// The parameters of the error, formatted for the locale by
+    /// [`ValidationErrors::translate`]; `params` has them unformatted.
+    #[serde(skip)]
+    pub values: Vec<Param>,
+    /// Set by [`ValidationErrors::translate`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// Field errors in the order they were found, rendered as the error envelope:
+///
+/// ```json
+/// {"code": "error.validation_failed", "message": "...",
+///  "errors": [{"field": "items[2].qty", "code": "error.out_of_range", "params": {"max": "10"}}]}
+/// ```
+///
+/// Responds with 422 Unprocessable Entity with the `axum` feature.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationErrors {
+    errors: Vec<FieldError>,
+    message: Option<String>,
+}
+
+impl ValidationErrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A single error of `field`.
+    pub fn field(field: impl Into<String>, error: impl I18nCode) -> Self {
+        let mut errors = Self::new();
+        errors.add(field, error);
+        errors
+    }
+
+    /// Adds an error of `field`, with the [parameters](I18nCode::i18n_params) of `error`.
+    pub fn add(&mut self, field: impl Into<String>, error: impl I18nCode) {
+        let values = error.i18n_params();
+        self.errors.push(FieldError {
+            field: field.into(),
+            code: error.get_i18n_code().to_owned(),
+            params: values
+                .iter()
+                .map(|param| (param.name.to_string(), param.value.to_string()))
+                .collect(),
+            values,
+            message: None,
+        });
+    }
+
+    pub fn with(mut self, field: impl Into<String>, error: impl I18nCode) -> Self {
+        self.add(field, error);
+        self
+    }
+
+    /// Adds a message parameter to the error added last.
+    ///
+    /// Panics when no error was added.
+    pub fn with_param(mut self, name: impl Into<String>, value: impl ToString) -> Self {
+        let last = self.errors.last_mut().expect("an error to add the parameter to");
+        let name = name.into();
+        last.values.retain(|param| param.name != name);
+        last.params.insert(name, value.to_string());
+        self
+    }
+
+    /// Keeps the errors of a nested payload, with their fields under `prefix`:
+    /// `city` under `address` is `address.city`.
+    pub fn nest(&mut self, prefix: &str, result: Result<(), ValidationErrors>) {
+        if let Err(nested) = result {
+            self.errors.extend(nested.errors.into_iter().map(|mut error| {
+                error.field = join(prefix, &error.field);
+                error
+            }));
+        }
+    }
+
+    /// [`nest`](Self::nest) for an element of a list: `qty` of the third of `items` is
+    /// `items[2].qty`.
+    pub fn nest_index(&mut self, prefix: &str, index: usize, result: Result<(), ValidationErrors>) {
+        self.nest(&format!("{prefix}[{index}]"), result);
+    }
+
+    /// Fills in the messages of the envelope and of each field in `locale`, falling back
+    /// to the i18n code. Parameters are substituted for `{name}` in the messages, numbers
+    /// and dates formatted for the locale by [`CldrFormatter`].
+    pub fn translate(self, locale: &str, translator: &dyn Translator) -> Self {
+        self.translate_with(locale, translator, &CldrFormatter)
+    }
+
+    /// [`translate`](Self::translate) with another [`ValueFormatter`].
+    pub fn translate_with(mut self, locale: &str, translator: &dyn Translator, formatter: &dyn ValueFormatter) -> Self {
+        self.message = Some(
+            translator
+                .translate(locale, VALIDATION_FAILED)
+                .unwrap_or_else(|| VALIDATION_FAILED.to_owned()),
+        );
+        for error in &mut self.errors {
+            let template = translator
+                .translate(locale, &error.code)
+                .unwrap_or_else(|| error.code.clone());
+            let mut params = error.values.clone();
+            params.extend(
+                error
+                    .params
+                    .iter()
+                    .filter(|(name, _)| !error.values.iter().any(|param| param.name == name.as_str()))
+                    .map(|(name, value)| Param::new(name.clone(), ParamValue::Text(value.clone()))),
+            );
+            error.message = Some(interpolate(&template, locale, &params, formatter));
+        }
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+
+    pub fn errors(&self) -> &[FieldError] {
+        &self.errors
+    }
+
+    /// `Ok` when nothing was added, for the end of [`ValidateI18n::validate`].
+    pub fn into_result(self) -> Result<(), ValidationErrors> {
+        if self.is_empty() { Ok(()) } else { Err(self) }
+    }
+}
+
+fn join(prefix: &str, field: &str) -> String {
+    if prefix.is_empty() {
+        field.to_owned()
+    } else if field.is_empty() {
+        prefix.to_owned()
+    } else if field.starts_with('[') {
+        format!("{prefix}{field}")
+    } else {
+        format!("{prefix}.{field}")
+    }
+}
+
+impl std::fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} field(s) failed validation", self.errors.len())?;
+        for (i, error) in self.errors.iter().enumerate() {
+            let separator = if i == 0 { ": " } else { ", " };
+            write!(f, "{separator}{} ({})", error.field, error.code)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+impl Serialize for ValidationErrors {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Envelope<'a> {
+            code: &'static str,
+            message: &'a str,
+            errors: &'a [FieldError],
+        }
+        Envelope {
+            code: VALIDATION_FAILED,
+            message: self.message.as_deref().unwrap_or(VALIDATION_FAILED),
+            errors: &self.errors,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "axum")]
+impl axum::response::IntoResponse for ValidationErrors {
+    fn into_response(self) -> axum::response::Response {
+        let body = serde_json::to_string(&self).expect("validation errors serialize to JSON");
+        (
+            axum::http::StatusCode::UNPROCESSABLE_ENTITY,
+            [(axum::http::header::CONTENT_TYPE, "application/json")],
+            body,
+        )
+            .into_response()
+    }
+}
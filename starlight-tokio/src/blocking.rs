@@ -0,0 +1,143 @@
+use crate::crash::panic_message;
+use crate::context::{Heartbeat, ServiceContext};
+use crate::runnable_service::StarlightServiceV2;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::AbortOnDropHandle;
+
+type Job = Box<dyn FnOnce(ShutdownProbe) -> anyhow::Result<()> + Send>;
+
+/// Lets blocking code check for shutdown without an async context.
+#[derive(Debug, Clone)]
+pub struct ShutdownProbe {
+    token: CancellationToken,
+    cancelled: Arc<(Mutex<bool>, Condvar)>,
+    heartbeat: Heartbeat,
+}
+
+impl ShutdownProbe {
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// Blocks until shutdown starts or `timeout` passes, returning whether it started.
+    pub fn wait_cancelled(&self, timeout: Duration) -> bool {
+        let (cancelled, condvar) = &*self.cancelled;
+        let cancelled = cancelled.lock().unwrap_or_else(|err| err.into_inner());
+        let (cancelled, _) = condvar
+            .wait_timeout_while(cancelled, timeout, |cancelled| !*cancelled)
+            .unwrap_or_else(|err| err.into_inner());
+        *cancelled || self.token.is_cancelled()
+    }
+
+    /// The service's [`Heartbeat`], for the watchdog.
+    pub fn heartbeat(&self) -> &Heartbeat {
+        &self.heartbeat
+    }
+}
+
+/// Runs a synchronous closure as a service, on the blocking thread pool or on a dedicated
+/// thread (see [`BlockingService::with_dedicated_thread`]).
+///
+/// The closure should poll its [`ShutdownProbe`]. Once shutdown starts it gets the
+/// manager's grace period to return; after that it is abandoned with a warning and keeps
+/// running detached. Panics are reported as failures like those of async services.
+///
+/// The closure runs once, so the service can't be restarted by [`Supervised`](crate::Supervised).
+pub struct BlockingService {
+    name: String,
+    job: Mutex<Option<Job>>,
+    dedicated_thread: bool,
+}
+
+impl BlockingService {
+    pub fn new<F>(name: impl Into<String>, f: F) -> Self
+    where
+        F: FnOnce(ShutdownProbe) -> anyhow::Result<()> + Send + 'static,
+    {
+        BlockingService {
+            name: name.into(),
+            job: Mutex::new(Some(Box::new(f))),
+            dedicated_thread: false,
+        }
+    }
+
+    /// Runs on a thread of its own instead of the runtime's blocking pool. Use it for
+    /// closures that live as long as the process: the runtime waits for blocking pool
+    /// tasks when it is dropped, but not for dedicated threads.
+    pub fn with_dedicated_thread(mut self, dedicated_thread: bool) -> Self {
+        self.dedicated_thread = dedicated_thread;
+        self
+    }
+
+    fn spawn(&self, job: Job, probe: ShutdownProbe) -> anyhow::Result<oneshot::Receiver<std::thread::Result<anyhow::Result<()>>>> {
+        let (tx, rx) = oneshot::channel();
+        let run = move || {
+            let _ = tx.send(std::panic::catch_unwind(AssertUnwindSafe(|| job(probe))));
+        };
+        if self.dedicated_thread {
+            std::thread::Builder::new().name(self.name.clone()).spawn(run)?;
+        } else {
+            drop(tokio::task::spawn_blocking(run));
+        }
+        Ok(rx)
+    }
+}
+
+#[async_trait::async_trait]
+impl StarlightServiceV2 for BlockingService {
+    async fn run(self: Arc<Self>, shutdown: CancellationToken) -> anyhow::Result<()> {
+        let context = ServiceContext::detached(&self.name, shutdown);
+        self.run_with_context(context).await
+    }
+
+    async fn run_with_context(self: Arc<Self>, context: ServiceContext) -> anyhow::Result<()> {
+        let job = self.job.lock().unwrap_or_else(|err| err.into_inner()).take();
+        let Some(job) = job else {
+            anyhow::bail!("blocking service {} has already run", self.name);
+        };
+
+        let shutdown = context.shutdown().clone();
+        let cancelled = Arc::new((Mutex::new(false), Condvar::new()));
+        let _relay = AbortOnDropHandle::new(tokio::spawn({
+            let shutdown = shutdown.clone();
+            let cancelled = cancelled.clone();
+            async move {
+                shutdown.cancelled().await;
+                let (cancelled, condvar) = &*cancelled;
+                *cancelled.lock().unwrap_or_else(|err| err.into_inner()) = true;
+                condvar.notify_all();
+            }
+        }));
+        let probe = ShutdownProbe {
+            token: shutdown.clone(),
+            cancelled,
+            heartbeat: context.heartbeat().clone(),
+        };
+
+        let mut done = self.spawn(job, probe)?;
+        context.ready().ready();
+        let joined = tokio::select! {
+            joined = &mut done => joined,
+            _ = shutdown.cancelled() => match tokio::time::timeout(context.grace_period(), done).await {
+                Ok(joined) => joined,
+                Err(_) => {
+                    tracing::warn!(service = %self.name, "blocking task ignored shutdown, abandoning it");
+                    return Ok(());
+                }
+            },
+        };
+        match joined {
+            Ok(Ok(result)) => result,
+            Ok(Err(payload)) => Err(anyhow::anyhow!("blocking task panicked with message {:?}", panic_message(&*payload))),
+            Err(_) => Err(anyhow::anyhow!("blocking task was dropped before finishing")),
+        }
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
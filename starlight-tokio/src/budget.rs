@@ -0,0 +1,151 @@
+use crate::context::ServiceContext;
+use crate::lifecycle::LifecycleEvent;
+use crate::runnable_service::StarlightServiceV2;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+/// Poll counts of a [`Budgeted`] service.
+#[derive(Debug, Default)]
+pub struct PollStats {
+    polls: AtomicU64,
+    long_polls: AtomicU64,
+    /// In microseconds.
+    longest: AtomicU64,
+}
+
+impl PollStats {
+    pub fn polls(&self) -> u64 {
+        self.polls.load(Ordering::Relaxed)
+    }
+
+    /// Polls which took longer than the threshold.
+    pub fn long_polls(&self) -> u64 {
+        self.long_polls.load(Ordering::Relaxed)
+    }
+
+    pub fn longest(&self) -> Duration {
+        Duration::from_micros(self.longest.load(Ordering::Relaxed))
+    }
+}
+
+/// Measures how long each poll of a service's future takes, to find services starving the
+/// runtime by running too long between `.await`s.
+///
+/// A poll longer than the threshold (100ms by default) is logged as a warning, counted
+/// in [`PollStats`] and reported to the manager's observer as
+/// [`LifecycleEvent::LongPoll`]. Only the service's own future is measured, not the tasks
+/// it spawns.
+pub struct Budgeted<S> {
+    service: Arc<S>,
+    threshold: Duration,
+    forced_yield: bool,
+    stats: Arc<PollStats>,
+}
+
+impl<S: StarlightServiceV2> Budgeted<S> {
+    pub fn new(service: S) -> Self {
+        Budgeted {
+            service: Arc::new(service),
+            threshold: Duration::from_millis(100),
+            forced_yield: false,
+            stats: Arc::default(),
+        }
+    }
+
+    pub fn with_threshold(mut self, threshold: Duration) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Yields to the runtime once more, like `tokio::task::yield_now`, whenever the service
+    /// is woken, so other tasks get a turn between its polls.
+    pub fn with_forced_yield(mut self, forced_yield: bool) -> Self {
+        self.forced_yield = forced_yield;
+        self
+    }
+
+    pub fn stats(&self) -> Arc<PollStats> {
+        self.stats.clone()
+    }
+}
+
+/// The future of a [`Budgeted`] service.
+struct Timed<F> {
+    future: Pin<Box<F>>,
+    context: ServiceContext,
+    threshold: Duration,
+    forced_yield: bool,
+    stats: Arc<PollStats>,
+    yield_next: bool,
+}
+
+impl<F: Future> Future for Timed<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        if self.yield_next {
+            self.yield_next = false;
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        let started = Instant::now();
+        let polled = self.future.as_mut().poll(cx);
+        let duration = started.elapsed();
+
+        self.stats.polls.fetch_add(1, Ordering::Relaxed);
+        self.stats.longest.fetch_max(duration.as_micros() as u64, Ordering::Relaxed);
+        if duration > self.threshold {
+            self.stats.long_polls.fetch_add(1, Ordering::Relaxed);
+            tracing::warn!(
+                service = %self.context.name(),
+                ?duration,
+                threshold = ?self.threshold,
+                "service ran for {:?} without yielding",
+                duration
+            );
+            self.context.observe(&LifecycleEvent::LongPoll {
+                duration,
+                threshold: self.threshold,
+            });
+        }
+        self.yield_next = self.forced_yield && polled.is_pending();
+        polled
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: StarlightServiceV2> StarlightServiceV2 for Budgeted<S> {
+    async fn run(self: Arc<Self>, shutdown: CancellationToken) -> anyhow::Result<()> {
+        let context = ServiceContext::detached(self.service.name(), shutdown);
+        self.run_with_context(context).await
+    }
+
+    async fn run_with_context(self: Arc<Self>, context: ServiceContext) -> anyhow::Result<()> {
+        Timed {
+            future: Box::pin(self.service.clone().run_with_context(context.clone())),
+            context,
+            threshold: self.threshold,
+            forced_yield: self.forced_yield,
+            stats: self.stats.clone(),
+            yield_next: false,
+        }
+        .await
+    }
+
+    async fn warmup(&self) -> anyhow::Result<()> {
+        self.service.warmup().await
+    }
+
+    fn name(&self) -> &str {
+        self.service.name()
+    }
+
+    fn shutdown_phase(&self) -> u32 {
+        self.service.shutdown_phase()
+    }
+}
@@ -0,0 +1,112 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, Weak};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+pub(crate) const DEFAULT_BUS_CAPACITY: usize = 256;
+
+/// Broadcast channels between the services of a [`ServiceManager`](crate::ServiceManager),
+/// one per message type, created on first use.
+pub(crate) struct Bus {
+    capacity: usize,
+    /// `None` once closed.
+    channels: Mutex<Option<HashMap<TypeId, Box<dyn Any + Send + Sync>>>>,
+}
+
+impl fmt::Debug for Bus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Bus").field("capacity", &self.capacity).finish_non_exhaustive()
+    }
+}
+
+impl Bus {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Bus {
+            capacity,
+            channels: Mutex::new(Some(HashMap::new())),
+        }
+    }
+
+    /// The sender for `T`, or `None` once the bus is closed.
+    fn sender<T: Clone + Send + 'static>(&self) -> Option<Arc<broadcast::Sender<T>>> {
+        let mut channels = self.channels.lock().unwrap_or_else(|err| err.into_inner());
+        let sender = channels
+            .as_mut()?
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Arc::new(broadcast::Sender::<T>::new(self.capacity))));
+        sender.downcast_ref::<Arc<broadcast::Sender<T>>>().cloned()
+    }
+
+    pub(crate) fn publisher<T: Clone + Send + 'static>(&self) -> Publisher<T> {
+        Publisher {
+            sender: self.sender().as_ref().map_or_else(Weak::new, Arc::downgrade),
+        }
+    }
+
+    pub(crate) fn subscribe<T: Clone + Send + 'static>(&self) -> Subscriber<T> {
+        // A closed bus hands out receivers which are closed straight away.
+        let receiver = match self.sender::<T>() {
+            Some(sender) => sender.subscribe(),
+            None => broadcast::channel(1).1,
+        };
+        Subscriber { receiver }
+    }
+
+    /// Drops every channel: subscribers get what was already published, then
+    /// `RecvError::Closed`.
+    pub(crate) fn close(&self) {
+        self.channels.lock().unwrap_or_else(|err| err.into_inner()).take();
+    }
+}
+
+/// Publishes messages of type `T` to every service subscribed to it. Messages published
+/// once shutdown has started are dropped.
+#[derive(Debug)]
+pub struct Publisher<T> {
+    /// Weak so that closing the bus closes the channel.
+    sender: Weak<broadcast::Sender<T>>,
+}
+
+impl<T> Clone for Publisher<T> {
+    fn clone(&self) -> Self {
+        Publisher {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+impl<T: Clone + Send + 'static> Publisher<T> {
+    /// Returns how many subscribers will see `message`.
+    pub fn publish(&self, message: T) -> usize {
+        match self.sender.upgrade() {
+            Some(sender) => sender.send(message).unwrap_or(0),
+            None => 0,
+        }
+    }
+}
+
+/// Receives the messages of type `T` published after it subscribed.
+#[derive(Debug)]
+pub struct Subscriber<T> {
+    receiver: broadcast::Receiver<T>,
+}
+
+impl<T: Clone + Send + 'static> Subscriber<T> {
+    /// The next message, or `Err(RecvError::Closed)` once the bus is closed and every
+    /// message was received. A subscriber falling more than the bus capacity behind gets
+    /// `Err(RecvError::Lagged(skipped))` and then continues with the oldest message kept.
+    pub async fn recv(&mut self) -> Result<T, RecvError> {
+        let received = self.receiver.recv().await;
+        if let Err(RecvError::Lagged(skipped)) = &received {
+            tracing::warn!(
+                message = std::any::type_name::<T>(),
+                monotonic_counter.bus.lagged = *skipped,
+                "subscriber fell behind, skipped {} messages",
+                skipped
+            );
+        }
+        received
+    }
+}
@@ -0,0 +1,127 @@
+//! Waiting on something unless the service is shut down first, without writing the
+//! `select!` on the shutdown token each time.
+//!
+//! ```ignore
+//! while let Some(job) = recv_or_cancelled(&mut rx, context.shutdown()).await? {
+//!     process(job).with_cancellation(context.shutdown()).await?;
+//! }
+//! ```
+
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::{Instant, Interval};
+use tokio_util::sync::CancellationToken;
+
+/// The token was cancelled before the awaited value was ready.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Adds [`with_cancellation`](WithCancellation::with_cancellation) to every future.
+pub trait WithCancellation: Future + Sized {
+    /// The output of the future, or [`Cancelled`] as soon as `token` is cancelled, in
+    /// which case the future is dropped. A token cancelled already wins over a ready
+    /// future.
+    fn with_cancellation(self, token: &CancellationToken) -> impl Future<Output = Result<Self::Output, Cancelled>>;
+}
+
+impl<F: Future> WithCancellation for F {
+    fn with_cancellation(self, token: &CancellationToken) -> impl Future<Output = Result<F::Output, Cancelled>> {
+        let token = token.clone();
+        async move {
+            tokio::select! {
+                biased;
+                _ = token.cancelled() => Err(Cancelled),
+                output = self => Ok(output),
+            }
+        }
+    }
+}
+
+/// A channel receiver [`recv_or_cancelled`] can wait on.
+pub trait Receiver {
+    type Item;
+
+    fn recv(&mut self) -> impl Future<Output = Option<Self::Item>> + Send;
+}
+
+impl<T: Send> Receiver for mpsc::Receiver<T> {
+    type Item = T;
+
+    fn recv(&mut self) -> impl Future<Output = Option<T>> + Send {
+        mpsc::Receiver::recv(self)
+    }
+}
+
+impl<T: Send> Receiver for mpsc::UnboundedReceiver<T> {
+    type Item = T;
+
+    fn recv(&mut self) -> impl Future<Output = Option<T>> + Send {
+        mpsc::UnboundedReceiver::recv(self)
+    }
+}
+
+/// The next message of `rx`, `None` once every sender is gone, or [`Cancelled`]. No
+/// message is lost on cancellation: it stays in the channel.
+pub async fn recv_or_cancelled<R: Receiver>(
+    rx: &mut R,
+    token: &CancellationToken,
+) -> Result<Option<R::Item>, Cancelled> {
+    rx.recv().with_cancellation(token).await
+}
+
+/// Sleeps for `duration`, or until `token` is cancelled.
+pub async fn sleep_or_cancelled(duration: Duration, token: &CancellationToken) -> Result<(), Cancelled> {
+    tokio::time::sleep(duration).with_cancellation(token).await
+}
+
+/// An [`Interval`] whose ticks stop when its token is cancelled.
+///
+/// ```ignore
+/// let mut ticks = CancellableInterval::new(Duration::from_secs(30), context.shutdown());
+/// while ticks.tick().await.is_some() {
+///     refresh().await;
+/// }
+/// ```
+#[derive(Debug)]
+pub struct CancellableInterval {
+    interval: Interval,
+    token: CancellationToken,
+}
+
+impl CancellableInterval {
+    /// Ticks every `period`, the first time right away, as [`tokio::time::interval`].
+    pub fn new(period: Duration, token: &CancellationToken) -> Self {
+        Self::from_interval(tokio::time::interval(period), token)
+    }
+
+    /// Ticks as `interval`, e.g. one with its own start or missed tick behavior.
+    pub fn from_interval(interval: Interval, token: &CancellationToken) -> Self {
+        CancellableInterval {
+            interval,
+            token: token.clone(),
+        }
+    }
+
+    /// The time of the next tick, `None` once the token is cancelled.
+    pub async fn tick(&mut self) -> Option<Instant> {
+        tokio::select! {
+            biased;
+            _ = self.token.cancelled() => None,
+            instant = self.interval.tick() => Some(instant),
+        }
+    }
+
+    pub fn period(&self) -> Duration {
+        self.interval.period()
+    }
+}
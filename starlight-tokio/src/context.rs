@@ -0,0 +1,198 @@
+use crate::bus::{Bus, DEFAULT_BUS_CAPACITY, Publisher, Subscriber};
+use crate::crash::{SharedCrashHook, catch_panic};
+use crate::lifecycle::{LifecycleEvent, SharedObserver};
+use crate::ready::ReadySignal;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tracing::Instrument;
+
+pub(crate) const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(30);
+
+/// Proof of life for the watchdog (see [`ServiceManager::with_watchdog`](crate::ServiceManager::with_watchdog)).
+/// Call [`Heartbeat::beat`] from the service's main loop; it is a single atomic store.
+#[derive(Debug, Clone)]
+pub struct Heartbeat {
+    origin: Instant,
+    /// Milliseconds since `origin`.
+    last: Arc<AtomicU64>,
+}
+
+impl Heartbeat {
+    fn new() -> Self {
+        Heartbeat {
+            origin: Instant::now(),
+            last: Arc::default(),
+        }
+    }
+
+    pub fn beat(&self) {
+        self.last.store(self.origin.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    pub fn since_last(&self) -> Duration {
+        self.origin.elapsed().saturating_sub(Duration::from_millis(self.last.load(Ordering::Relaxed)))
+    }
+}
+
+/// What a service gets from the [`ServiceManager`](crate::ServiceManager): its shutdown
+/// token, its [`ReadySignal`], its [`Heartbeat`] and a tracker for the tasks it spawns.
+///
+/// Tasks spawned through the context are waited for, up to the manager's grace period,
+/// after the service returns and before it counts as stopped. They should watch
+/// [`ServiceContext::shutdown`] themselves.
+#[derive(Debug, Clone)]
+pub struct ServiceContext {
+    name: Arc<str>,
+    shutdown: CancellationToken,
+    ready: ReadySignal,
+    tracker: TaskTracker,
+    heartbeat: Heartbeat,
+    stalled: Arc<Notify>,
+    grace_period: Duration,
+    observer: SharedObserver,
+    crash_hook: SharedCrashHook,
+    bus: Arc<Bus>,
+}
+
+impl ServiceContext {
+    pub(crate) fn new(name: &str, shutdown: CancellationToken, ready: ReadySignal, grace_period: Duration) -> Self {
+        ServiceContext {
+            name: Arc::from(name),
+            shutdown,
+            ready,
+            tracker: TaskTracker::new(),
+            heartbeat: Heartbeat::new(),
+            stalled: Arc::new(Notify::new()),
+            grace_period,
+            observer: SharedObserver::default(),
+            crash_hook: SharedCrashHook::default(),
+            bus: Arc::new(Bus::new(DEFAULT_BUS_CAPACITY)),
+        }
+    }
+
+    pub(crate) fn with_bus(mut self, bus: Arc<Bus>) -> Self {
+        self.bus = bus;
+        self
+    }
+
+    /// The same context with a different shutdown token.
+    pub(crate) fn with_shutdown(&self, shutdown: CancellationToken) -> Self {
+        ServiceContext {
+            shutdown,
+            ..self.clone()
+        }
+    }
+
+    pub(crate) fn with_observer(mut self, observer: SharedObserver) -> Self {
+        self.observer = observer;
+        self
+    }
+
+    pub(crate) fn with_crash_hook(mut self, crash_hook: SharedCrashHook) -> Self {
+        self.crash_hook = crash_hook;
+        self
+    }
+
+    pub(crate) fn crash_hook(&self) -> &SharedCrashHook {
+        &self.crash_hook
+    }
+
+    /// A context for running a service outside a manager.
+    pub fn detached(name: &str, shutdown: CancellationToken) -> Self {
+        Self::new(name, shutdown, ReadySignal::detached(), DEFAULT_GRACE_PERIOD)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn shutdown(&self) -> &CancellationToken {
+        &self.shutdown
+    }
+
+    pub fn ready(&self) -> &ReadySignal {
+        &self.ready
+    }
+
+    pub fn tracker(&self) -> &TaskTracker {
+        &self.tracker
+    }
+
+    pub fn heartbeat(&self) -> &Heartbeat {
+        &self.heartbeat
+    }
+
+    /// How long work still running at shutdown is waited for.
+    pub fn grace_period(&self) -> Duration {
+        self.grace_period
+    }
+
+    /// Resolves when the watchdog asks for the service to be restarted.
+    pub(crate) async fn stalled(&self) {
+        self.stalled.notified().await
+    }
+
+    pub(crate) fn notify_stalled(&self) {
+        self.stalled.notify_one();
+    }
+
+    pub(crate) fn observe(&self, event: &LifecycleEvent<'_>) {
+        self.observer.0.observe(&self.name, event);
+    }
+
+    /// Publishes messages of type `T` to the services of the same manager.
+    pub fn publisher<T: Clone + Send + 'static>(&self) -> Publisher<T> {
+        self.bus.publisher()
+    }
+
+    /// Receives the messages of type `T` published by the services of the same manager.
+    /// The subscription ends once shutdown starts and the messages already published
+    /// are received.
+    pub fn subscribe<T: Clone + Send + 'static>(&self) -> Subscriber<T> {
+        self.bus.subscribe()
+    }
+
+    /// Spawns a tracked task in a `service.task` span. A panic of the task is reported
+    /// (see [`CrashReport`](crate::CrashReport)) before it reaches the `JoinHandle`.
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let span = tracing::info_span!("service.task", service = %self.name);
+        self.tracker.spawn(self.reporting_crash("task", future).instrument(span))
+    }
+
+    /// Like [`ServiceContext::spawn`], naming the task in its span.
+    pub fn spawn_named<F>(&self, task: &str, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let span = tracing::info_span!("service.task", service = %self.name, task);
+        self.tracker.spawn(self.reporting_crash(task, future).instrument(span))
+    }
+
+    /// Reports a panic of `future` and resumes it.
+    fn reporting_crash<F: Future>(&self, task: &str, future: F) -> impl Future<Output = F::Output> + use<F> {
+        let (name, task, crash_hook) = (self.name.clone(), task.to_owned(), self.crash_hook.clone());
+        async move {
+            let started = Instant::now();
+            match catch_panic(future).await {
+                Ok(output) => output,
+                Err(panic) => {
+                    let report = panic.into_report(&name, Some(&task), started.elapsed());
+                    crash_hook.report(&report);
+                    std::panic::resume_unwind(Box::new(report.message))
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use futures_util::FutureExt;
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::future::Future;
+use std::panic::{AssertUnwindSafe, PanicHookInfo};
+use std::pin::Pin;
+use std::sync::{Arc, Once};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// A panic of a service, or of a task it spawned through its
+/// [`ServiceContext`](crate::ServiceContext).
+#[derive(Debug, Clone)]
+pub struct CrashReport {
+    pub service: String,
+    /// The task which panicked, `None` for the service itself. Tasks spawned without a
+    /// name are called `task`.
+    pub task: Option<String>,
+    /// The panic payload, if it was a string.
+    pub message: String,
+    pub backtrace: Arc<Backtrace>,
+    /// How long the service or task had been running.
+    pub uptime: Duration,
+}
+
+/// Called with every [`CrashReport`], e.g. to write a crash file or notify an error
+/// tracker. Set with [`ServiceManager::with_crash_hook`](crate::ServiceManager::with_crash_hook).
+pub trait CrashHook: Send + Sync + 'static {
+    fn on_crash(&self, report: &CrashReport);
+}
+
+impl<F: Fn(&CrashReport) + Send + Sync + 'static> CrashHook for F {
+    fn on_crash(&self, report: &CrashReport) {
+        self(report)
+    }
+}
+
+/// A shared hook, `Debug` so it can sit in a [`ServiceContext`](crate::ServiceContext).
+#[derive(Clone, Default)]
+pub(crate) struct SharedCrashHook(pub(crate) Option<Arc<dyn CrashHook>>);
+
+impl fmt::Debug for SharedCrashHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(if self.0.is_some() { "CrashHook" } else { "None" })
+    }
+}
+
+impl SharedCrashHook {
+    /// Logs the crash and hands it to the hook.
+    pub(crate) fn report(&self, report: &CrashReport) {
+        tracing::error!(
+            service = %report.service,
+            task = report.task.as_deref(),
+            uptime = ?report.uptime,
+            panic.message = %report.message,
+            backtrace = %report.backtrace,
+            "panicked: {}",
+            report.message
+        );
+        if let Some(hook) = &self.0 {
+            hook.on_crash(report);
+        }
+    }
+}
+
+/// A caught panic.
+pub(crate) struct Panic {
+    pub(crate) message: String,
+    pub(crate) backtrace: Backtrace,
+}
+
+impl Panic {
+    pub(crate) fn into_report(self, service: &str, task: Option<&str>, uptime: Duration) -> CrashReport {
+        CrashReport {
+            service: service.to_owned(),
+            task: task.map(ToOwned::to_owned),
+            message: self.message,
+            backtrace: Arc::new(self.backtrace),
+            uptime,
+        }
+    }
+}
+
+thread_local! {
+    /// How many [`catch_panic`] futures are being polled on this thread.
+    static CATCHING: Cell<usize> = const { Cell::new(0) };
+    /// The backtrace of the last panic caught on this thread.
+    static BACKTRACE: RefCell<Option<Backtrace>> = const { RefCell::new(None) };
+}
+
+/// Whether a panic on this thread would be caught by the [`ServiceManager`] running its
+/// service or task, which reports it as a crash instead of letting it end the process.
+/// For panic hooks which only act on panics nothing catches.
+///
+/// [`ServiceManager`]: crate::ServiceManager
+pub fn panic_is_caught() -> bool {
+    CATCHING.get() > 0
+}
+
+/// Chains a panic hook keeping the backtrace of panics [`catch_panic`] is about to catch,
+/// since it is gone once the stack has unwound.
+fn install_backtrace_hook() {
+    static INSTALLED: Once = Once::new();
+    INSTALLED.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info: &PanicHookInfo<'_>| {
+            if CATCHING.get() > 0 {
+                BACKTRACE.set(Some(Backtrace::force_capture()));
+            }
+            previous(info)
+        }));
+    });
+}
+
+/// Marks the thread as catching while `future` is polled.
+struct Catching<F>(Pin<Box<F>>);
+
+impl<F: Future> Future for Catching<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        struct Guard;
+        impl Drop for Guard {
+            fn drop(&mut self) {
+                CATCHING.set(CATCHING.get() - 1);
+            }
+        }
+        CATCHING.set(CATCHING.get() + 1);
+        let _guard = Guard;
+        self.0.as_mut().poll(cx)
+    }
+}
+
+/// Runs `future`, turning a panic into an error with its message and backtrace.
+pub(crate) async fn catch_panic<F: Future>(future: F) -> Result<F::Output, Panic> {
+    install_backtrace_hook();
+    AssertUnwindSafe(Catching(Box::pin(future)))
+        .catch_unwind()
+        .await
+        .map_err(|payload| Panic {
+            message: panic_message(&*payload).to_owned(),
+            backtrace: BACKTRACE.take().unwrap_or_else(Backtrace::disabled),
+        })
+}
+
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("Box<dyn Any>")
+}
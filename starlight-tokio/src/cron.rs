@@ -0,0 +1,190 @@
+use std::fmt;
+use std::str::FromStr;
+use time::{Duration, OffsetDateTime, Time};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronError {
+    pub expression: String,
+    pub message: String,
+}
+
+impl fmt::Display for CronError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid cron expression {:?}: {}", self.expression, self.message)
+    }
+}
+
+impl std::error::Error for CronError {}
+
+/// The values allowed in one field, as a bit set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Field {
+    bits: u64,
+    any: bool,
+}
+
+impl Field {
+    fn contains(&self, value: u8) -> bool {
+        self.bits & (1 << value) != 0
+    }
+
+    /// Parses `*`, `*/n`, `a`, `a-b`, `a-b/n` and comma separated lists of them.
+    fn parse(field: &str, min: u8, max: u8) -> Result<Field, String> {
+        let mut bits = 0u64;
+        for part in field.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => {
+                    let step: u8 = step.parse().map_err(|_| format!("invalid step in {:?}", part))?;
+                    if step == 0 {
+                        return Err(format!("zero step in {:?}", part));
+                    }
+                    (range, step)
+                }
+                None => (part, 1),
+            };
+            let (start, end) = match range {
+                "*" => (min, max),
+                range => match range.split_once('-') {
+                    Some((start, end)) => (parse_value(start, part)?, parse_value(end, part)?),
+                    None => {
+                        let value = parse_value(range, part)?;
+                        (value, if step > 1 { max } else { value })
+                    }
+                },
+            };
+            if start < min || end > max || start > end {
+                return Err(format!("{:?} is outside {}-{}", part, min, max));
+            }
+            for value in (start..=end).step_by(step as usize) {
+                bits |= 1 << value;
+            }
+        }
+        Ok(Field { bits, any: field == "*" })
+    }
+}
+
+fn parse_value(value: &str, part: &str) -> Result<u8, String> {
+    value.parse().map_err(|_| format!("invalid value in {:?}", part))
+}
+
+/// A six field cron schedule, `sec min hour day-of-month month day-of-week`, in UTC.
+///
+/// Days of the week run from 0 (Sunday) to 6, with 7 also meaning Sunday. As in classic
+/// cron, when both day fields are restricted a time matches either of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    seconds: Field,
+    minutes: Field,
+    hours: Field,
+    days_of_month: Field,
+    months: Field,
+    days_of_week: Field,
+}
+
+impl FromStr for CronSchedule {
+    type Err = CronError;
+
+    fn from_str(expression: &str) -> Result<Self, CronError> {
+        let error = |message: String| CronError {
+            expression: expression.to_owned(),
+            message,
+        };
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [seconds, minutes, hours, days_of_month, months, days_of_week] = fields[..] else {
+            return Err(error(format!("expected 6 fields, got {}", fields.len())));
+        };
+        let mut days_of_week = Field::parse(days_of_week, 0, 7).map_err(error)?;
+        if days_of_week.contains(7) {
+            days_of_week.bits |= 1;
+        }
+        Ok(CronSchedule {
+            seconds: Field::parse(seconds, 0, 59).map_err(error)?,
+            minutes: Field::parse(minutes, 0, 59).map_err(error)?,
+            hours: Field::parse(hours, 0, 23).map_err(error)?,
+            days_of_month: Field::parse(days_of_month, 1, 31).map_err(error)?,
+            months: Field::parse(months, 1, 12).map_err(error)?,
+            days_of_week,
+        })
+    }
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<Self, CronError> {
+        expression.parse()
+    }
+
+    fn matches_day(&self, at: OffsetDateTime) -> bool {
+        let day_of_month = self.days_of_month.contains(at.day());
+        let day_of_week = self.days_of_week.contains(at.weekday().number_days_from_sunday());
+        match (self.days_of_month.any, self.days_of_week.any) {
+            (false, false) => day_of_month || day_of_week,
+            _ => day_of_month && day_of_week,
+        }
+    }
+
+    /// The first matching time strictly after `after`, or `None` if there is none within
+    /// the next five years (e.g. `0 0 0 31 2 *`).
+    pub fn next_after(&self, after: OffsetDateTime) -> Option<OffsetDateTime> {
+        let limit = after + Duration::days(5 * 366);
+        let mut at = after.replace_nanosecond(0).ok()? + Duration::SECOND;
+        while at <= limit {
+            if !self.months.contains(u8::from(at.month())) {
+                let (year, month) = match at.month().next() {
+                    time::Month::January => (at.year() + 1, time::Month::January),
+                    month => (at.year(), month),
+                };
+                at = at.replace_day(1).ok()?.replace_time(Time::MIDNIGHT).replace_month(month).ok()?.replace_year(year).ok()?;
+            } else if !self.matches_day(at) {
+                at = at.replace_time(Time::MIDNIGHT) + Duration::DAY;
+            } else if !self.hours.contains(at.hour()) {
+                at = at.replace_time(Time::from_hms(at.hour(), 0, 0).ok()?) + Duration::HOUR;
+            } else if !self.minutes.contains(at.minute()) {
+                at = at.replace_time(Time::from_hms(at.hour(), at.minute(), 0).ok()?) + Duration::MINUTE;
+            } else if !self.seconds.contains(at.second()) {
+                at += Duration::SECOND;
+            } else {
+                return Some(at);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn next(expression: &str, after: OffsetDateTime) -> OffsetDateTime {
+        CronSchedule::parse(expression).unwrap().next_after(after).unwrap()
+    }
+
+    #[test]
+    fn steps_ranges_and_lists() {
+        let at = datetime!(2026-03-14 10:07:30 UTC);
+        assert_eq!(next("0 */5 * * * *", at), datetime!(2026-03-14 10:10:00 UTC));
+        assert_eq!(next("15,45 * * * * *", at), datetime!(2026-03-14 10:07:45 UTC));
+        assert_eq!(next("0 0 9-17 * * *", at), datetime!(2026-03-14 11:00:00 UTC));
+        assert_eq!(next("0 0 0 1 * *", at), datetime!(2026-04-01 0:00:00 UTC));
+        assert_eq!(next("0 30 6 * 1 *", at), datetime!(2027-01-01 6:30:00 UTC));
+    }
+
+    #[test]
+    fn days_of_week() {
+        // 2026-03-14 is a Saturday.
+        let at = datetime!(2026-03-14 10:07:30 UTC);
+        assert_eq!(next("0 0 8 * * 1-5", at), datetime!(2026-03-16 8:00:00 UTC));
+        assert_eq!(next("0 0 8 * * 7", at), datetime!(2026-03-15 8:00:00 UTC));
+        // Either day field matches when both are restricted.
+        assert_eq!(next("0 0 0 20 * 0", at), datetime!(2026-03-15 0:00:00 UTC));
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        assert!(CronSchedule::parse("* * * * *").is_err());
+        assert!(CronSchedule::parse("60 * * * * *").is_err());
+        assert!(CronSchedule::parse("*/0 * * * * *").is_err());
+        assert!(CronSchedule::parse("0 0 0 0 * *").is_err());
+        assert_eq!(CronSchedule::parse("0 0 0 31 2 *").unwrap().next_after(OffsetDateTime::UNIX_EPOCH), None);
+    }
+}
@@ -0,0 +1,111 @@
+//! Errors of several services at once, so none is lost when more than one fails.
+
+use serde::ser::{Serialize, SerializeSeq, SerializeStruct, Serializer};
+use std::fmt;
+
+/// The errors of the services which failed, each with its chain of causes, in the order
+/// they were added. Displayed one service per line, its causes indented below:
+///
+/// ```text
+/// 2 services failed:
+/// - cache: connecting to redis
+///     caused by: connection refused
+/// - queue: broker unreachable
+/// ```
+///
+/// It serializes for crash reports as
+/// `{"errors": [{"service": "cache", "error": "connecting to redis", "causes": ["connection refused"]}]}`.
+#[derive(Debug, Default)]
+pub struct Aggregate {
+    errors: Vec<(String, anyhow::Error)>,
+}
+
+impl Aggregate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, service: impl Into<String>, error: anyhow::Error) {
+        self.errors.push((service.into(), error));
+    }
+
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// The service names and their errors, whose [`chain`](anyhow::Error::chain) has the
+    /// causes.
+    pub fn sources(&self) -> impl Iterator<Item = (&str, &anyhow::Error)> {
+        self.errors.iter().map(|(service, error)| (service.as_str(), error))
+    }
+
+    pub fn into_errors(self) -> Vec<(String, anyhow::Error)> {
+        self.errors
+    }
+}
+
+impl From<Vec<(String, anyhow::Error)>> for Aggregate {
+    fn from(errors: Vec<(String, anyhow::Error)>) -> Self {
+        Aggregate { errors }
+    }
+}
+
+impl fmt::Display for Aggregate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.errors.len() {
+            1 => f.write_str("1 service failed:")?,
+            count => write!(f, "{} services failed:", count)?,
+        }
+        for (service, error) in &self.errors {
+            write!(f, "\n- {}: {}", service, error)?;
+            for cause in error.chain().skip(1) {
+                write!(f, "\n    caused by: {}", cause)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Aggregate {}
+
+impl Serialize for Aggregate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        struct Errors<'a>(&'a [(String, anyhow::Error)]);
+        struct Failure<'a>(&'a str, &'a anyhow::Error);
+        struct Causes<'a>(&'a anyhow::Error);
+
+        impl Serialize for Errors<'_> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+                for (service, error) in self.0 {
+                    seq.serialize_element(&Failure(service, error))?;
+                }
+                seq.end()
+            }
+        }
+
+        impl Serialize for Failure<'_> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let mut failure = serializer.serialize_struct("Failure", 3)?;
+                failure.serialize_field("service", self.0)?;
+                failure.serialize_field("error", &self.1.to_string())?;
+                failure.serialize_field("causes", &Causes(self.1))?;
+                failure.end()
+            }
+        }
+
+        impl Serialize for Causes<'_> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_seq(self.0.chain().skip(1).map(ToString::to_string))
+            }
+        }
+
+        let mut aggregate = serializer.serialize_struct("Aggregate", 1)?;
+        aggregate.serialize_field("errors", &Errors(&self.errors))?;
+        aggregate.end()
+    }
+}
@@ -0,0 +1,126 @@
+//! Initialization that waits for dependencies, e.g. a database still starting next to
+//! the service in docker-compose:
+//!
+//! ```no_run
+//! use starlight_tokio::init::{InitPolicy, retrying};
+//! use starlight_tokio::probe;
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let policy = InitPolicy::new();
+//! retrying("postgres", &policy, || probe::tcp("postgres:5432")).await?;
+//! retrying("schema-registry", &policy, || probe::http("http://registry:8081/subjects", 200)).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Called from [`StarlightServiceV2::warmup`](crate::StarlightServiceV2::warmup), or
+//! through [`ServiceFn::with_init`](crate::ServiceFn::with_init), the service is ready
+//! only once the initialization succeeded and fails if it never does.
+
+use crate::supervisor::ExponentialBackoff;
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// How [`retrying`] tries again: delays from `backoff` until `deadline` has passed since
+/// the first attempt.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InitPolicy {
+    pub backoff: ExponentialBackoff,
+    pub deadline: Duration,
+}
+
+impl Default for InitPolicy {
+    fn default() -> Self {
+        InitPolicy {
+            backoff: ExponentialBackoff {
+                max: Duration::from_secs(5),
+                ..ExponentialBackoff::default()
+            },
+            deadline: Duration::from_secs(60),
+        }
+    }
+}
+
+impl InitPolicy {
+    /// Delays from 100ms doubling up to 5s, for at most a minute.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_backoff(mut self, backoff: ExponentialBackoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = deadline;
+        self
+    }
+}
+
+/// Returned by [`retrying`] once the deadline passed without a successful attempt.
+#[derive(Debug)]
+pub struct InitError {
+    pub name: String,
+    pub attempts: u32,
+    pub elapsed: Duration,
+    /// The error of the last attempt.
+    pub last_error: anyhow::Error,
+}
+
+impl fmt::Display for InitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} not initialized after {} attempts in {:?}: {:#}",
+            self.name, self.attempts, self.elapsed, self.last_error
+        )
+    }
+}
+
+impl std::error::Error for InitError {}
+
+/// Runs `init` until it succeeds, sleeping between attempts as `policy` says. An attempt
+/// still running at the deadline is dropped. Each failed attempt is logged as a warning
+/// with its `attempt` number, `error` and `next_delay`.
+pub async fn retrying<T, F, Fut>(name: &str, policy: &InitPolicy, mut init: F) -> Result<T, InitError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    let started = Instant::now();
+    let deadline = started + policy.deadline;
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let error = match tokio::time::timeout_at(deadline, init()).await {
+            Ok(Ok(value)) => {
+                tracing::info!(init = name, attempts = attempt, elapsed = ?started.elapsed(), "initialized");
+                return Ok(value);
+            }
+            Ok(Err(err)) => err,
+            Err(_) => anyhow::anyhow!("attempt did not finish before the deadline"),
+        };
+
+        let delay = policy.backoff.delay(attempt - 1);
+        if Instant::now() + delay >= deadline {
+            tracing::error!(init = name, attempt, error = %format!("{:#}", error), "initialization failed, giving up");
+            return Err(InitError {
+                name: name.to_owned(),
+                attempts: attempt,
+                elapsed: started.elapsed(),
+                last_error: error,
+            });
+        }
+        tracing::warn!(
+            init = name,
+            attempt,
+            error = %format!("{:#}", error),
+            next_delay = ?delay,
+            "initialization failed, retrying"
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
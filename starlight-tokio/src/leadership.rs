@@ -0,0 +1,197 @@
+use crate::context::ServiceContext;
+use crate::runnable_service::StarlightServiceV2;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+
+/// Held while this instance is the leader; dropping it gives up the lease.
+pub struct LeaseGuard {
+    release: Option<Box<dyn FnOnce() + Send>>,
+}
+
+impl LeaseGuard {
+    /// A guard calling `release` when dropped, e.g. to delete the lease key.
+    pub fn new(release: impl FnOnce() + Send + 'static) -> Self {
+        LeaseGuard {
+            release: Some(Box::new(release)),
+        }
+    }
+
+    /// A guard with nothing to release, for leases which simply expire.
+    pub fn expiring() -> Self {
+        LeaseGuard { release: None }
+    }
+}
+
+impl fmt::Debug for LeaseGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LeaseGuard").finish_non_exhaustive()
+    }
+}
+
+impl Drop for LeaseGuard {
+    fn drop(&mut self) {
+        if let Some(release) = self.release.take() {
+            release();
+        }
+    }
+}
+
+/// Decides which instance is the leader, e.g. through a lease in Redis or etcd. See
+/// [`Gated`].
+#[async_trait::async_trait]
+pub trait LeadershipProvider: Send + Sync + 'static {
+    /// Waits until this instance holds the lease.
+    async fn acquire(&self) -> LeaseGuard;
+
+    /// Resolves once this instance no longer holds the lease it acquired, e.g. because it
+    /// could not be renewed in time.
+    async fn lost(&self);
+}
+
+/// In-process leadership, for a single node or tests. Candidates made with
+/// [`LocalLeadership::candidate`] compete for the same lease.
+#[derive(Debug, Clone)]
+pub struct LocalLeadership {
+    id: usize,
+    next_id: Arc<AtomicUsize>,
+    /// The id of the leader.
+    holder: Arc<watch::Sender<Option<usize>>>,
+}
+
+impl Default for LocalLeadership {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LocalLeadership {
+    pub fn new() -> Self {
+        LocalLeadership {
+            id: 0,
+            next_id: Arc::new(AtomicUsize::new(1)),
+            holder: Arc::new(watch::Sender::new(None)),
+        }
+    }
+
+    /// Another candidate for the same lease.
+    pub fn candidate(&self) -> Self {
+        LocalLeadership {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            next_id: self.next_id.clone(),
+            holder: self.holder.clone(),
+        }
+    }
+
+    pub fn is_leader(&self) -> bool {
+        *self.holder.borrow() == Some(self.id)
+    }
+
+    /// Takes the lease away from its holder, as if it had expired.
+    pub fn revoke(&self) {
+        self.holder.send_replace(None);
+    }
+}
+
+#[async_trait::async_trait]
+impl LeadershipProvider for LocalLeadership {
+    async fn acquire(&self) -> LeaseGuard {
+        let mut holder = self.holder.subscribe();
+        loop {
+            let acquired = self.holder.send_if_modified(|holder| {
+                if holder.is_some() {
+                    return false;
+                }
+                *holder = Some(self.id);
+                true
+            });
+            if acquired || self.is_leader() {
+                let (id, holder) = (self.id, self.holder.clone());
+                return LeaseGuard::new(move || {
+                    holder.send_if_modified(|holder| *holder == Some(id) && holder.take().is_some());
+                });
+            }
+            let _ = holder.wait_for(Option::is_none).await;
+        }
+    }
+
+    async fn lost(&self) {
+        let mut holder = self.holder.subscribe();
+        let _ = holder.wait_for(|holder| *holder != Some(self.id)).await;
+    }
+}
+
+/// Runs a service only on the instance holding the lease of a [`LeadershipProvider`],
+/// e.g. a scheduler that must run once across replicas.
+///
+/// The wrapper waits for the lease, then runs the service until it returns or the lease
+/// is lost. On loss the service is cancelled, as on shutdown, and dropped if it has not
+/// returned within the grace period, since another instance may be leading by then. It
+/// runs again once the lease is re-acquired. The wrapper reports ready while waiting:
+/// standing by is healthy.
+pub struct Gated<S> {
+    service: Arc<S>,
+    provider: Arc<dyn LeadershipProvider>,
+}
+
+impl<S: StarlightServiceV2> Gated<S> {
+    pub fn new(service: S, provider: impl LeadershipProvider) -> Self {
+        Gated {
+            service: Arc::new(service),
+            provider: Arc::new(provider),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: StarlightServiceV2> StarlightServiceV2 for Gated<S> {
+    async fn run(self: Arc<Self>, shutdown: CancellationToken) -> anyhow::Result<()> {
+        let context = ServiceContext::detached(self.service.name(), shutdown);
+        self.run_with_context(context).await
+    }
+
+    async fn run_with_context(self: Arc<Self>, context: ServiceContext) -> anyhow::Result<()> {
+        let name = self.service.name();
+        let shutdown = context.shutdown().clone();
+        context.ready().ready();
+        loop {
+            let _lease = tokio::select! {
+                lease = self.provider.acquire() => lease,
+                _ = shutdown.cancelled() => return Ok(()),
+            };
+            tracing::info!(service = %name, "acquired leadership, starting");
+
+            let paused = shutdown.child_token();
+            let mut run = std::pin::pin!(self.service.clone().run_with_context(context.with_shutdown(paused.clone())));
+            tokio::select! {
+                result = &mut run => return result,
+                _ = self.provider.lost() => {}
+            }
+            tracing::warn!(service = %name, "lost leadership, pausing");
+            paused.cancel();
+            match tokio::time::timeout(context.grace_period(), run).await {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => tracing::error!(service = %name, "service failed while pausing: {:#}", err),
+                Err(_) => tracing::error!(
+                    service = %name,
+                    grace_period = ?context.grace_period(),
+                    "service did not pause within the grace period, dropping it"
+                ),
+            }
+        }
+    }
+
+    async fn warmup(&self) -> anyhow::Result<()> {
+        self.service.warmup().await
+    }
+
+    fn name(&self) -> &str {
+        self.service.name()
+    }
+
+    fn shutdown_phase(&self) -> u32 {
+        self.service.shutdown_phase()
+    }
+}
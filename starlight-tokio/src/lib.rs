@@ -1,5 +1,41 @@
+mod blocking;
+mod budget;
+mod bus;
+pub mod cancel;
+mod context;
+mod crash;
+mod cron;
+pub mod errors;
+pub mod init;
+mod leadership;
+mod lifecycle;
+mod periodic;
+pub mod probe;
+mod queue;
+mod ready;
+pub mod runtime;
 mod runnable_service;
+mod service_fn;
+mod service_manager;
+mod signal;
+mod supervisor;
 
 pub use tokio;
+pub use tokio_util::sync::CancellationToken;
 
-pub use runnable_service::StarlightService;
+pub use blocking::{BlockingService, ShutdownProbe};
+pub use budget::{Budgeted, PollStats};
+pub use bus::{Publisher, Subscriber};
+pub use context::{Heartbeat, ServiceContext};
+pub use crash::{CrashHook, CrashReport, panic_is_caught};
+pub use cron::{CronError, CronSchedule};
+pub use leadership::{Gated, LeadershipProvider, LeaseGuard, LocalLeadership};
+pub use lifecycle::{LifecycleEvent, LifecycleObserver, TracingObserver};
+pub use periodic::{CronService, IntervalService, Overlap, PeriodicStats};
+pub use queue::{QueueSender, QueueService, QueueStats};
+pub use ready::{ReadySignal, Readiness};
+pub use runnable_service::{StarlightService, StarlightServiceV2};
+pub use service_fn::{ServiceFn, oneshot_service, service_fn};
+pub use service_manager::{FailurePolicy, RegisterError, RunSummary, ServiceManager, ServiceOutcome, WatchdogPolicy};
+pub use signal::{Signal, shutdown_signal, shutdown_signal_with};
+pub use supervisor::{ExponentialBackoff, RestartEvent, RestartPolicy, Supervised};
@@ -0,0 +1,109 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Something that happened to a service run by the [`ServiceManager`](crate::ServiceManager).
+#[derive(Debug)]
+pub enum LifecycleEvent<'a> {
+    /// The service was started. It may still be waiting for its dependencies.
+    Started,
+    /// Its dependencies are ready and [`warmup`](crate::StarlightServiceV2::warmup) was called.
+    WarmingUp,
+    /// The warmup succeeded after `elapsed`; the service runs next.
+    WarmedUp { elapsed: Duration },
+    /// Shutdown started: the service was marked not ready and is cancelled after `delay`
+    /// (see [`ServiceManager::with_pre_stop_delay`](crate::ServiceManager::with_pre_stop_delay)).
+    PreStop { delay: Duration },
+    /// [`Supervised`](crate::Supervised) is restarting the service after `delay`.
+    Restarting {
+        attempt: u32,
+        delay: Duration,
+        error: Option<&'a anyhow::Error>,
+    },
+    /// The service returned successfully.
+    Completed,
+    /// The service returned an error or panicked.
+    Failed(&'a anyhow::Error),
+    /// The service did not stop within its shutdown phase timeout and was aborted.
+    Aborted,
+    /// A poll of a [`Budgeted`](crate::Budgeted) service took `duration`, longer than its
+    /// `threshold`.
+    LongPoll { duration: Duration, threshold: Duration },
+}
+
+impl LifecycleEvent<'_> {
+    /// A short name for the event, e.g. for a metric attribute.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LifecycleEvent::Started => "started",
+            LifecycleEvent::WarmingUp => "warming_up",
+            LifecycleEvent::WarmedUp { .. } => "warmed_up",
+            LifecycleEvent::PreStop { .. } => "pre_stop",
+            LifecycleEvent::Restarting { .. } => "restarting",
+            LifecycleEvent::Completed => "completed",
+            LifecycleEvent::Failed(_) => "failed",
+            LifecycleEvent::Aborted => "aborted",
+            LifecycleEvent::LongPoll { .. } => "long_poll",
+        }
+    }
+}
+
+/// A sink for service [`LifecycleEvent`]s, set with
+/// [`ServiceManager::with_observer`](crate::ServiceManager::with_observer).
+///
+/// Every service gets one `Started` and then one of `Completed`, `Failed` or `Aborted`,
+/// so the number of running services is the difference.
+pub trait LifecycleObserver: Send + Sync + 'static {
+    fn observe(&self, service: &str, event: &LifecycleEvent<'_>);
+}
+
+/// The default observer: records metrics as `tracing` events (`updown_counter.service.running`,
+/// `monotonic_counter.service.starts`, `.completions`, `.failures`, `.restarts` and
+/// `.aborts`, `.long_polls`, and `histogram.service.warmup.duration` in seconds), for a subscriber which
+/// turns them into metrics.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TracingObserver;
+
+impl LifecycleObserver for TracingObserver {
+    fn observe(&self, service: &str, event: &LifecycleEvent<'_>) {
+        match event {
+            LifecycleEvent::Started => {
+                tracing::debug!(service, monotonic_counter.service.starts = 1u64, updown_counter.service.running = 1i64)
+            }
+            LifecycleEvent::WarmingUp => tracing::debug!(service, "warming up"),
+            LifecycleEvent::WarmedUp { elapsed } => {
+                tracing::debug!(service, histogram.service.warmup.duration = elapsed.as_secs_f64(), "warmed up")
+            }
+            LifecycleEvent::PreStop { delay } => tracing::debug!(service, ?delay, "waiting before stopping"),
+            LifecycleEvent::Restarting { attempt, .. } => {
+                tracing::debug!(service, attempt, monotonic_counter.service.restarts = 1u64)
+            }
+            LifecycleEvent::Completed => {
+                tracing::debug!(service, monotonic_counter.service.completions = 1u64, updown_counter.service.running = -1i64)
+            }
+            LifecycleEvent::Failed(_) => {
+                tracing::debug!(service, monotonic_counter.service.failures = 1u64, updown_counter.service.running = -1i64)
+            }
+            LifecycleEvent::Aborted => {
+                tracing::debug!(service, monotonic_counter.service.aborts = 1u64, updown_counter.service.running = -1i64)
+            }
+            LifecycleEvent::LongPoll { .. } => tracing::debug!(service, monotonic_counter.service.long_polls = 1u64),
+        }
+    }
+}
+
+/// A shared observer, `Debug` so it can sit in a [`ServiceContext`](crate::ServiceContext).
+#[derive(Clone)]
+pub(crate) struct SharedObserver(pub(crate) Arc<dyn LifecycleObserver>);
+
+impl Default for SharedObserver {
+    fn default() -> Self {
+        SharedObserver(Arc::new(TracingObserver))
+    }
+}
+
+impl fmt::Debug for SharedObserver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("LifecycleObserver")
+    }
+}
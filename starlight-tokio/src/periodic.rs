@@ -0,0 +1,248 @@
+use crate::cron::{CronError, CronSchedule};
+use crate::runnable_service::StarlightServiceV2;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+use time::OffsetDateTime;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+type Job = Arc<dyn Fn() -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>> + Send + Sync>;
+
+/// What to do when an execution is due while the previous one is still running.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Overlap {
+    /// Drop the execution.
+    #[default]
+    Skip,
+    /// Run it as soon as the previous one finishes.
+    Queue,
+}
+
+/// Execution counts of a periodic service.
+#[derive(Debug, Default)]
+pub struct PeriodicStats {
+    runs: AtomicU64,
+    failures: AtomicU64,
+    skipped: AtomicU64,
+}
+
+impl PeriodicStats {
+    pub fn runs(&self) -> u64 {
+        self.runs.load(Ordering::Relaxed)
+    }
+
+    pub fn failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    pub fn skipped(&self) -> u64 {
+        self.skipped.load(Ordering::Relaxed)
+    }
+}
+
+enum Schedule {
+    Interval { period: Duration, jitter: Duration },
+    Cron(CronSchedule),
+}
+
+/// State shared by [`IntervalService`] and [`CronService`].
+struct Periodic {
+    name: String,
+    schedule: Schedule,
+    job: Job,
+    overlap: Overlap,
+    run_on_start: bool,
+    fail_fast: bool,
+    stats: Arc<PeriodicStats>,
+}
+
+impl Periodic {
+    fn new<F, Fut>(name: String, schedule: Schedule, f: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        Periodic {
+            name,
+            schedule,
+            job: Arc::new(move || Box::pin(f())),
+            overlap: Overlap::default(),
+            run_on_start: false,
+            fail_fast: false,
+            stats: Arc::default(),
+        }
+    }
+
+    /// The next time an execution is due. Cron schedules follow the wall clock as it was
+    /// at `started`, advanced by the runtime clock.
+    fn next_due(&self, previous: Instant, started: (Instant, OffsetDateTime)) -> Option<Instant> {
+        match &self.schedule {
+            Schedule::Interval { period, .. } => Some(previous + *period),
+            Schedule::Cron(cron) => {
+                let now = Instant::now();
+                let wall = started.1 + (now - started.0);
+                let next = cron.next_after(wall)?;
+                Some(now + Duration::try_from(next - wall).unwrap_or_default())
+            }
+        }
+    }
+
+    /// A random delay added to each wait, so replicas don't run in lockstep.
+    fn jitter(&self) -> Duration {
+        match &self.schedule {
+            Schedule::Interval { jitter, .. } => jitter.mul_f64(rand::random::<f64>()),
+            Schedule::Cron(_) => Duration::ZERO,
+        }
+    }
+
+    fn execute(&self) -> JoinHandle<anyhow::Result<()>> {
+        self.stats.runs.fetch_add(1, Ordering::Relaxed);
+        let name = self.name.clone();
+        let stats = self.stats.clone();
+        let job = (self.job)();
+        tokio::spawn(async move {
+            let result = job.await;
+            if let Err(err) = &result {
+                stats.failures.fetch_add(1, Ordering::Relaxed);
+                tracing::error!(service = %name, "periodic task failed: {:#}", err);
+            }
+            result
+        })
+    }
+
+    async fn run(&self, shutdown: CancellationToken) -> anyhow::Result<()> {
+        let started = (Instant::now(), OffsetDateTime::from(SystemTime::now()));
+        let mut running: Option<JoinHandle<anyhow::Result<()>>> = None;
+        let mut due = Instant::now();
+        if !self.run_on_start {
+            due = match self.next_due(due, started) {
+                Some(due) => due,
+                None => return Ok(()),
+            };
+        }
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep_until(due + self.jitter()) => {}
+                _ = shutdown.cancelled() => break,
+            }
+            if let Some(handle) = running.take_if(|handle| handle.is_finished()) {
+                self.check(handle.await)?;
+            }
+            match (running.take(), self.overlap) {
+                (Some(handle), Overlap::Skip) => {
+                    self.stats.skipped.fetch_add(1, Ordering::Relaxed);
+                    tracing::warn!(service = %self.name, "previous run still in progress, skipping");
+                    running = Some(handle);
+                }
+                (Some(handle), Overlap::Queue) => {
+                    tokio::select! {
+                        joined = handle => self.check(joined)?,
+                        _ = shutdown.cancelled() => break,
+                    }
+                    running = Some(self.execute());
+                }
+                (None, _) => running = Some(self.execute()),
+            }
+            due = match self.next_due(due, started) {
+                // Don't try to catch up on executions missed while queued.
+                Some(next) => next.max(Instant::now()),
+                None => break,
+            };
+        }
+        // Let an execution in progress finish.
+        if let Some(handle) = running {
+            self.check(handle.await)?;
+        }
+        Ok(())
+    }
+
+    fn check(&self, joined: Result<anyhow::Result<()>, tokio::task::JoinError>) -> anyhow::Result<()> {
+        let result = joined.unwrap_or_else(|err| {
+            self.stats.failures.fetch_add(1, Ordering::Relaxed);
+            tracing::error!(service = %self.name, "periodic task panicked: {}", err);
+            Err(err.into())
+        });
+        match result {
+            Err(err) if self.fail_fast => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
+
+macro_rules! periodic_builders {
+    ($service:ident) => {
+        impl $service {
+            /// See [`Overlap`]; skipping is the default.
+            pub fn with_overlap(mut self, overlap: Overlap) -> Self {
+                self.0.overlap = overlap;
+                self
+            }
+
+            /// Runs once straight away instead of waiting for the first scheduled time.
+            pub fn with_run_on_start(mut self, run_on_start: bool) -> Self {
+                self.0.run_on_start = run_on_start;
+                self
+            }
+
+            /// Stops the service on the first failed execution instead of logging it.
+            pub fn with_fail_fast(mut self, fail_fast: bool) -> Self {
+                self.0.fail_fast = fail_fast;
+                self
+            }
+
+            pub fn stats(&self) -> Arc<PeriodicStats> {
+                self.0.stats.clone()
+            }
+        }
+
+        #[async_trait::async_trait]
+        impl StarlightServiceV2 for $service {
+            async fn run(self: Arc<Self>, shutdown: CancellationToken) -> anyhow::Result<()> {
+                self.0.run(shutdown).await
+            }
+
+            fn name(&self) -> &str {
+                &self.0.name
+            }
+        }
+    };
+}
+
+/// Runs an async closure every `period`, plus a random delay of up to `jitter`.
+///
+/// Failed executions are logged and counted in [`PeriodicStats`]; see
+/// [`IntervalService::with_fail_fast`].
+pub struct IntervalService(Periodic);
+
+impl IntervalService {
+    pub fn new<F, Fut>(name: impl Into<String>, period: Duration, jitter: Duration, f: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        IntervalService(Periodic::new(name.into(), Schedule::Interval { period, jitter }, f))
+    }
+}
+
+periodic_builders!(IntervalService);
+
+/// Runs an async closure on a [`CronSchedule`], e.g. `0 */5 * * * *` for every five
+/// minutes.
+pub struct CronService(Periodic);
+
+impl CronService {
+    pub fn new<F, Fut>(name: impl Into<String>, expression: &str, f: F) -> Result<Self, CronError>
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        let schedule = Schedule::Cron(CronSchedule::parse(expression)?);
+        Ok(CronService(Periodic::new(name.into(), schedule, f)))
+    }
+}
+
+periodic_builders!(CronService);
@@ -0,0 +1,77 @@
+//! Checks whether a dependency accepts connections, for [`init::retrying`](crate::init::retrying).
+
+use anyhow::{Context, bail};
+use std::fmt;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+/// Succeeds once a TCP connection to `addr` is established; the connection is closed.
+pub async fn tcp<A: ToSocketAddrs + fmt::Display>(addr: A) -> anyhow::Result<()> {
+    let context = format!("cannot connect to {}", addr);
+    TcpStream::connect(addr).await.context(context)?;
+    Ok(())
+}
+
+/// Succeeds once a `GET url` answers `expected_status`. Only plain `http://` URLs are
+/// supported; the response body is not read.
+pub async fn http(url: &str, expected_status: u16) -> anyhow::Result<()> {
+    let Some(rest) = url.strip_prefix("http://") else {
+        bail!("unsupported URL {:?}, expected http://host[:port]/path", url);
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(slash) => rest.split_at(slash),
+        None => (rest, "/"),
+    };
+    if authority.is_empty() {
+        bail!("missing host in {:?}", url);
+    }
+    let has_port = match authority.rfind(']') {
+        Some(bracket) => authority[bracket..].contains(':'),
+        None => authority.contains(':'),
+    };
+    let addr = match has_port {
+        true => authority.to_owned(),
+        false => format!("{}:80", authority),
+    };
+
+    let mut stream = TcpStream::connect(addr.as_str())
+        .await
+        .with_context(|| format!("cannot connect to {}", addr))?;
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: starlight-probe\r\nConnection: close\r\n\r\n",
+        path, authority
+    );
+    stream
+        .write_all(request.as_bytes())
+        .await
+        .with_context(|| format!("cannot send request to {}", url))?;
+
+    let mut head = Vec::with_capacity(64);
+    let mut buf = [0; 64];
+    while !head.contains(&b'\n') {
+        let read = stream
+            .read(&mut buf)
+            .await
+            .with_context(|| format!("cannot read response from {}", url))?;
+        if read == 0 {
+            bail!("{} closed the connection without a response", url);
+        }
+        head.extend_from_slice(&buf[..read]);
+    }
+    let line = String::from_utf8_lossy(&head);
+    let status = line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse::<u16>().ok())
+        .with_context(|| {
+            format!(
+                "invalid response from {}: {:?}",
+                url,
+                line.lines().next().unwrap_or_default()
+            )
+        })?;
+    if status != expected_status {
+        bail!("{} answered {}, expected {}", url, status, expected_status);
+    }
+    Ok(())
+}
@@ -0,0 +1,238 @@
+use crate::context::ServiceContext;
+use crate::runnable_service::StarlightServiceV2;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::mpsc::error::{SendError, TrySendError};
+use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore, mpsc};
+use tokio::task::{JoinError, JoinSet};
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+type Handler<T> = Arc<dyn Fn(T) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>> + Send + Sync>;
+
+/// Item counts of a [`QueueService`].
+#[derive(Debug, Default)]
+pub struct QueueStats {
+    processed: AtomicU64,
+    failed: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl QueueStats {
+    pub fn processed(&self) -> u64 {
+        self.processed.load(Ordering::Relaxed)
+    }
+
+    /// Items whose handler returned an error or panicked.
+    pub fn failed(&self) -> u64 {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    /// Items still queued or in flight when the grace period ran out.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Sends items to a [`QueueService`].
+#[derive(Debug)]
+pub struct QueueSender<T> {
+    sender: mpsc::Sender<T>,
+}
+
+impl<T> Clone for QueueSender<T> {
+    fn clone(&self) -> Self {
+        QueueSender {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+impl<T> QueueSender<T> {
+    /// Waits for room in the queue. Fails, giving the item back, once the service is
+    /// shutting down.
+    pub async fn send(&self, item: T) -> Result<(), SendError<T>> {
+        self.sender.send(item).await
+    }
+
+    /// Fails with `TrySendError::Full` instead of waiting when the queue is full.
+    pub fn try_send(&self, item: T) -> Result<(), TrySendError<T>> {
+        self.sender.try_send(item)
+    }
+}
+
+/// Processes the items sent through its [`QueueSender`]s, at most `concurrency` at a time.
+///
+/// Senders wait while `capacity` items are queued. Once shutdown starts the queue stops
+/// accepting items and the ones already queued are processed within the manager's grace
+/// period; those left when it runs out are dropped and counted in [`QueueStats::dropped`].
+pub struct QueueService<T> {
+    name: String,
+    sender: mpsc::Sender<T>,
+    receiver: Mutex<mpsc::Receiver<T>>,
+    concurrency: usize,
+    handler: Handler<T>,
+    dead_letter: bool,
+    stats: Arc<QueueStats>,
+}
+
+impl<T: Send + 'static> QueueService<T> {
+    pub fn new<F, Fut>(name: impl Into<String>, capacity: usize, concurrency: usize, handler: F) -> Self
+    where
+        F: Fn(T) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        assert!(capacity > 0, "queue capacity must be positive");
+        assert!(concurrency > 0, "queue concurrency must be positive");
+        let (sender, receiver) = mpsc::channel(capacity);
+        QueueService {
+            name: name.into(),
+            sender,
+            receiver: Mutex::new(receiver),
+            concurrency,
+            handler: Arc::new(move |item| Box::pin(handler(item))),
+            dead_letter: false,
+            stats: Arc::default(),
+        }
+    }
+
+    /// Hands the items whose handler failed to `dead_letter`, instead of logging the error.
+    pub fn with_dead_letter<D>(mut self, dead_letter: D) -> Self
+    where
+        T: Clone,
+        D: Fn(T, &anyhow::Error) + Send + Sync + 'static,
+    {
+        let handler = self.handler;
+        let dead_letter = Arc::new(dead_letter);
+        self.handler = Arc::new(move |item: T| {
+            let copy = item.clone();
+            let handling = handler(item);
+            let dead_letter = dead_letter.clone();
+            Box::pin(async move {
+                let result = handling.await;
+                if let Err(err) = &result {
+                    dead_letter(copy, err);
+                }
+                result
+            })
+        });
+        self.dead_letter = true;
+        self
+    }
+
+    pub fn sender(&self) -> QueueSender<T> {
+        QueueSender {
+            sender: self.sender.clone(),
+        }
+    }
+
+    pub fn stats(&self) -> Arc<QueueStats> {
+        self.stats.clone()
+    }
+
+    fn spawn(&self, tasks: &mut JoinSet<()>, item: T, permit: OwnedSemaphorePermit) {
+        let handling = (self.handler)(item);
+        let (name, stats, dead_letter) = (self.name.clone(), self.stats.clone(), self.dead_letter);
+        tasks.spawn(async move {
+            let _permit = permit;
+            match handling.await {
+                Ok(()) => {
+                    stats.processed.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(err) => {
+                    stats.failed.fetch_add(1, Ordering::Relaxed);
+                    if !dead_letter {
+                        tracing::warn!(service = %name, "failed to process queued item: {:#}", err);
+                    }
+                }
+            }
+        });
+    }
+
+    fn reap(&self, joined: Result<(), JoinError>) {
+        if let Err(err) = joined
+            && err.is_panic()
+        {
+            self.stats.failed.fetch_add(1, Ordering::Relaxed);
+            tracing::error!(service = %self.name, "queue handler panicked: {}", err);
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: Send + 'static> StarlightServiceV2 for QueueService<T> {
+    async fn run(self: Arc<Self>, shutdown: CancellationToken) -> anyhow::Result<()> {
+        let context = ServiceContext::detached(&self.name, shutdown);
+        self.run_with_context(context).await
+    }
+
+    async fn run_with_context(self: Arc<Self>, context: ServiceContext) -> anyhow::Result<()> {
+        let shutdown = context.shutdown().clone();
+        let mut receiver = self.receiver.lock().await;
+        let semaphore = Arc::new(Semaphore::new(self.concurrency));
+        let mut tasks = JoinSet::new();
+        context.ready().ready();
+
+        loop {
+            let permit = tokio::select! {
+                biased;
+                _ = shutdown.cancelled() => break,
+                permit = semaphore.clone().acquire_owned() => permit?,
+            };
+            let item = tokio::select! {
+                biased;
+                _ = shutdown.cancelled() => break,
+                item = receiver.recv() => match item {
+                    Some(item) => item,
+                    None => break,
+                },
+            };
+            self.spawn(&mut tasks, item, permit);
+            while let Some(joined) = tasks.try_join_next() {
+                self.reap(joined);
+            }
+        }
+
+        // Senders fail from now on, while what is already queued can still be received.
+        receiver.close();
+        let deadline = Instant::now() + context.grace_period();
+        let drain = async {
+            while let Ok(permit) = semaphore.clone().acquire_owned().await
+                && let Some(item) = receiver.recv().await
+            {
+                self.spawn(&mut tasks, item, permit);
+                while let Some(joined) = tasks.try_join_next() {
+                    self.reap(joined);
+                }
+            }
+            while let Some(joined) = tasks.join_next().await {
+                self.reap(joined);
+            }
+        };
+        if tokio::time::timeout_at(deadline, drain).await.is_err() {
+            while let Some(joined) = tasks.try_join_next() {
+                self.reap(joined);
+            }
+            let mut dropped = tasks.len() as u64;
+            tasks.abort_all();
+            while receiver.try_recv().is_ok() {
+                dropped += 1;
+            }
+            self.stats.dropped.fetch_add(dropped, Ordering::Relaxed);
+            tracing::warn!(
+                service = %self.name,
+                monotonic_counter.queue.dropped = dropped,
+                "queue not drained within {:?}, dropped {} items",
+                context.grace_period(),
+                dropped
+            );
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
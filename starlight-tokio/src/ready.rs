@@ -0,0 +1,84 @@
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Readiness {
+    Starting,
+    Ready,
+    NotReady(String),
+    /// A run-to-completion service finished successfully.
+    Done,
+    Stopped,
+}
+
+impl Readiness {
+    /// Whether dependents may start: the service is ready or has done its job.
+    pub fn is_ready(&self) -> bool {
+        matches!(self, Readiness::Ready | Readiness::Done)
+    }
+}
+
+impl fmt::Display for Readiness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Readiness::Starting => f.write_str("starting"),
+            Readiness::Ready => f.write_str("ready"),
+            Readiness::NotReady(reason) => write!(f, "not ready: {}", reason),
+            Readiness::Done => f.write_str("done"),
+            Readiness::Stopped => f.write_str("stopped"),
+        }
+    }
+}
+
+/// Lets a service report whether it can serve; services depending on it start once it
+/// reports ready. Clones report for the same service.
+#[derive(Debug, Clone)]
+pub struct ReadySignal {
+    state: Arc<watch::Sender<Readiness>>,
+}
+
+impl ReadySignal {
+    pub(crate) fn new() -> Self {
+        ReadySignal {
+            state: Arc::new(watch::Sender::new(Readiness::Starting)),
+        }
+    }
+
+    /// A signal nobody listens to, for running a service outside a
+    /// [`ServiceManager`](crate::ServiceManager).
+    pub fn detached() -> Self {
+        Self::new()
+    }
+
+    pub fn ready(&self) {
+        self.state.send_replace(Readiness::Ready);
+    }
+
+    pub fn not_ready(&self, reason: impl Into<String>) {
+        self.state.send_replace(Readiness::NotReady(reason.into()));
+    }
+
+    pub fn readiness(&self) -> Readiness {
+        self.state.borrow().clone()
+    }
+
+    pub(crate) fn done(&self) {
+        self.state.send_replace(Readiness::Done);
+    }
+
+    /// Marks the service stopped, unless it is done: that stays final.
+    pub(crate) fn stopped(&self) {
+        self.state.send_if_modified(|state| {
+            if *state == Readiness::Done {
+                return false;
+            }
+            *state = Readiness::Stopped;
+            true
+        });
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<Readiness> {
+        self.state.subscribe()
+    }
+}
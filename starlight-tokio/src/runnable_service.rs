@@ -1,7 +1,95 @@
+use crate::context::ServiceContext;
 use std::sync::Arc;
 use tokio::sync::watch;
 use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 
 pub trait StarlightService: Send + Sync + 'static {
     fn run(&self, shutdown_tx: Arc<watch::Sender<bool>>, shutdown_rx: watch::Receiver<bool>, ) -> JoinHandle<()>;
-}
\ No newline at end of file
+}
+
+/// A long running service. `run` should return once `shutdown` is cancelled; an error
+/// ends the service and is reported by the [`ServiceManager`](crate::ServiceManager).
+#[async_trait::async_trait]
+pub trait StarlightServiceV2: Send + Sync + 'static {
+    async fn run(self: Arc<Self>, shutdown: CancellationToken) -> anyhow::Result<()>;
+
+    /// Runs the service under a [`ServiceManager`](crate::ServiceManager). The default
+    /// reports ready straight away and calls [`StarlightServiceV2::run`]; override it to
+    /// report readiness later (e.g. once migrations are applied) or to spawn tracked tasks.
+    async fn run_with_context(self: Arc<Self>, context: ServiceContext) -> anyhow::Result<()> {
+        context.ready().ready();
+        self.run(context.shutdown().clone()).await
+    }
+
+    /// Prepares the service before it runs, e.g. filling caches, once its dependencies
+    /// are ready. Under a [`ServiceManager`](crate::ServiceManager) the service doesn't
+    /// start, and so can't report ready, until this returns; an error fails the service.
+    async fn warmup(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Used in logs and the run summary.
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+
+    /// The shutdown phase the service is registered in, unless overridden with
+    /// [`ServiceManager::with_shutdown_phase`](crate::ServiceManager::with_shutdown_phase).
+    fn shutdown_phase(&self) -> u32 {
+        0
+    }
+}
+
+/// Runs a new style service where the old trait is expected. The shutdown channel
+/// cancels the service's token; errors are logged since the old signature drops them.
+impl<T: StarlightServiceV2> StarlightService for Arc<T> {
+    fn run(&self, _shutdown_tx: Arc<watch::Sender<bool>>, mut shutdown_rx: watch::Receiver<bool>) -> JoinHandle<()> {
+        let service = self.clone();
+        tokio::spawn(async move {
+            let token = CancellationToken::new();
+            let watcher = tokio::spawn({
+                let token = token.clone();
+                async move {
+                    // A closed channel can no longer announce shutdown, so treat it as one.
+                    let _ = shutdown_rx.wait_for(|stop| *stop).await;
+                    token.cancel();
+                }
+            });
+            let name = service.name().to_owned();
+            let result = service.run(token).await;
+            watcher.abort();
+            if let Err(err) = result {
+                tracing::error!(service = %name, "service failed: {:#}", err);
+            }
+        })
+    }
+}
+
+/// Runs an old style service under the [`ServiceManager`](crate::ServiceManager).
+pub(crate) struct Legacy<S> {
+    pub(crate) service: S,
+    pub(crate) name: String,
+}
+
+#[async_trait::async_trait]
+impl<S: StarlightService> StarlightServiceV2 for Legacy<S> {
+    async fn run(self: Arc<Self>, shutdown: CancellationToken) -> anyhow::Result<()> {
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let shutdown_tx = Arc::new(shutdown_tx);
+        let forward = tokio::spawn({
+            let shutdown_tx = shutdown_tx.clone();
+            async move {
+                shutdown.cancelled().await;
+                let _ = shutdown_tx.send(true);
+            }
+        });
+        let result = self.service.run(shutdown_tx, shutdown_rx).await;
+        forward.abort();
+        result.map_err(anyhow::Error::from)
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
@@ -0,0 +1,157 @@
+//! A Tokio runtime with production defaults, in place of `#[tokio::main]`:
+//!
+//! ```no_run
+//! use starlight_tokio::{ServiceManager, runtime};
+//!
+//! fn main() -> anyhow::Result<()> {
+//!     let manager = ServiceManager::new();
+//!     // Register services...
+//!     runtime::Builder::new().block_on_with_manager(manager)?.into_result()
+//! }
+//! ```
+//!
+//! For a custom `main`, `runtime::Builder::new().build()?.block_on(async { ... })`.
+
+use crate::crash::{panic_is_caught, panic_message};
+use crate::service_manager::{RunSummary, ServiceManager};
+use crate::signal::shutdown_signal;
+use std::backtrace::Backtrace;
+use std::io;
+use std::num::NonZeroUsize;
+use std::panic::PanicHookInfo;
+use std::sync::{Arc, Once};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Overrides the number of worker threads.
+pub const STARLIGHT_WORKERS: &str = "STARLIGHT_WORKERS";
+
+/// Builds a multi-threaded runtime with:
+///
+/// - worker threads named `starlight-worker-N`, as many as [`STARLIGHT_WORKERS`] or
+///   the available CPUs,
+/// - at most 256 blocking threads with 2 MiB stacks,
+/// - a panic hook logging panics through `tracing` (see [`install_panic_hook`]).
+#[derive(Debug, Clone)]
+pub struct Builder {
+    worker_threads: Option<usize>,
+    max_blocking_threads: usize,
+    thread_stack_size: usize,
+    thread_name: String,
+    panic_hook: bool,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder {
+            worker_threads: None,
+            max_blocking_threads: 256,
+            thread_stack_size: 2 * 1024 * 1024,
+            thread_name: "starlight-worker".to_owned(),
+            panic_hook: true,
+        }
+    }
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes precedence over [`STARLIGHT_WORKERS`].
+    pub fn with_worker_threads(mut self, worker_threads: usize) -> Self {
+        self.worker_threads = Some(worker_threads);
+        self
+    }
+
+    pub fn with_max_blocking_threads(mut self, max_blocking_threads: usize) -> Self {
+        self.max_blocking_threads = max_blocking_threads;
+        self
+    }
+
+    pub fn with_thread_stack_size(mut self, thread_stack_size: usize) -> Self {
+        self.thread_stack_size = thread_stack_size;
+        self
+    }
+
+    /// Threads are named `<thread_name>-N`.
+    pub fn with_thread_name(mut self, thread_name: impl Into<String>) -> Self {
+        self.thread_name = thread_name.into();
+        self
+    }
+
+    pub fn with_panic_hook(mut self, panic_hook: bool) -> Self {
+        self.panic_hook = panic_hook;
+        self
+    }
+
+    fn worker_threads(&self) -> usize {
+        if let Some(worker_threads) = self.worker_threads {
+            return worker_threads;
+        }
+        let cpus = std::thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        match std::env::var(STARLIGHT_WORKERS) {
+            Ok(value) => match value.parse::<usize>() {
+                Ok(workers) if workers > 0 => workers,
+                _ => {
+                    tracing::warn!("ignoring invalid {}={:?}, using {} workers", STARLIGHT_WORKERS, value, cpus);
+                    cpus
+                }
+            },
+            Err(_) => cpus,
+        }
+    }
+
+    pub fn build(self) -> io::Result<tokio::runtime::Runtime> {
+        if self.panic_hook {
+            install_panic_hook();
+        }
+        let next = Arc::new(AtomicUsize::new(0));
+        let thread_name = self.thread_name.clone();
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(self.worker_threads())
+            .max_blocking_threads(self.max_blocking_threads)
+            .thread_stack_size(self.thread_stack_size)
+            .thread_name_fn(move || format!("{}-{}", thread_name, next.fetch_add(1, Ordering::Relaxed)))
+            .enable_all()
+            .build()
+    }
+
+    /// Builds the runtime and runs `manager` on it until its services stop, shutting
+    /// them down on SIGINT or SIGTERM (see [`shutdown_signal`](crate::shutdown_signal)).
+    pub fn block_on_with_manager(self, manager: ServiceManager) -> io::Result<RunSummary> {
+        let runtime = self.build()?;
+        Ok(runtime.block_on(async { manager.run(shutdown_signal()).await }))
+    }
+}
+
+/// Logs panics as `tracing` errors with the thread, location and, when `RUST_BACKTRACE`
+/// enables it, a backtrace, including those of spawned tasks which Tokio would otherwise only report through the
+/// `JoinHandle`. Panics of services and their tasks are left to the [`ServiceManager`],
+/// which logs them with the service once caught. Without a `tracing` subscriber the
+/// default hook prints them instead. Installing it more than once has no effect.
+pub fn install_panic_hook() {
+    static INSTALLED: Once = Once::new();
+    INSTALLED.call_once(|| {
+        let default = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info: &PanicHookInfo<'_>| log_panic(info, &default)));
+    });
+}
+
+fn log_panic(info: &PanicHookInfo<'_>, default: &(dyn Fn(&PanicHookInfo<'_>) + Send + Sync)) {
+    if !tracing::dispatcher::has_been_set() {
+        return default(info);
+    }
+    if panic_is_caught() {
+        return;
+    }
+    let message = panic_message(info.payload());
+    let thread = std::thread::current();
+    tracing::error!(
+        panic.message = message,
+        panic.location = info.location().map(ToString::to_string),
+        thread = thread.name().unwrap_or("<unnamed>"),
+        backtrace = %Backtrace::capture(),
+        "panicked: {}",
+        message
+    );
+}
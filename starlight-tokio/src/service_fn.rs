@@ -0,0 +1,110 @@
+use crate::context::ServiceContext;
+use crate::init::{InitPolicy, retrying};
+use crate::runnable_service::StarlightServiceV2;
+use crate::supervisor::{RestartPolicy, Supervised};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+
+type Body = Box<dyn Fn(ServiceContext) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>> + Send + Sync>;
+type Init = Box<dyn Fn() -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>> + Send + Sync>;
+
+/// A service made from an async closure, see [`service_fn`] and [`oneshot_service`].
+pub struct ServiceFn {
+    name: String,
+    body: Body,
+    oneshot: bool,
+    phase: u32,
+    init: Option<(InitPolicy, Init)>,
+}
+
+/// A service running `f` with its [`ServiceContext`]. It reports ready as it starts and
+/// `f` should return once [`ServiceContext::shutdown`] is cancelled.
+pub fn service_fn<F, Fut>(name: impl Into<String>, f: F) -> ServiceFn
+where
+    F: Fn(ServiceContext) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+{
+    ServiceFn {
+        name: name.into(),
+        body: Box::new(move |context| Box::pin(f(context))),
+        oneshot: false,
+        phase: 0,
+        init: None,
+    }
+}
+
+/// A run-to-completion service, e.g. migrations. `f` is not cancelled on shutdown, which
+/// waits for it up to the phase timeout. The service reports not ready while `f` runs
+/// and [`Readiness::Done`](crate::Readiness::Done) once it succeeds, so dependents start
+/// after it.
+pub fn oneshot_service<F, Fut>(name: impl Into<String>, f: F) -> ServiceFn
+where
+    F: Fn(ServiceContext) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+{
+    ServiceFn {
+        oneshot: true,
+        ..service_fn(name, f)
+    }
+}
+
+impl ServiceFn {
+    /// See [`StarlightServiceV2::shutdown_phase`].
+    pub fn with_phase(mut self, phase: u32) -> Self {
+        self.phase = phase;
+        self
+    }
+
+    /// Runs `init` with [`retrying`] as the service's warmup: the closure starts, and the
+    /// service reports ready, once `init` succeeded. The service fails if it doesn't
+    /// within the policy's deadline.
+    pub fn with_init<F, Fut>(mut self, policy: InitPolicy, init: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        self.init = Some((policy, Box::new(move || Box::pin(init()))));
+        self
+    }
+
+    /// Restarts the closure according to `policy`.
+    pub fn with_restart(self, policy: RestartPolicy) -> Supervised<Self> {
+        Supervised::new(self, policy)
+    }
+}
+
+#[async_trait::async_trait]
+impl StarlightServiceV2 for ServiceFn {
+    async fn run(self: Arc<Self>, shutdown: CancellationToken) -> anyhow::Result<()> {
+        let context = ServiceContext::detached(&self.name, shutdown);
+        self.run_with_context(context).await
+    }
+
+    async fn run_with_context(self: Arc<Self>, context: ServiceContext) -> anyhow::Result<()> {
+        if !self.oneshot {
+            context.ready().ready();
+            return (self.body)(context).await;
+        }
+        context.ready().not_ready("running");
+        (self.body)(context.with_shutdown(CancellationToken::new())).await?;
+        context.ready().done();
+        Ok(())
+    }
+
+    async fn warmup(&self) -> anyhow::Result<()> {
+        if let Some((policy, init)) = &self.init {
+            retrying(&self.name, policy, init).await?;
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn shutdown_phase(&self) -> u32 {
+        self.phase
+    }
+}
@@ -0,0 +1,747 @@
+use crate::bus::{Bus, DEFAULT_BUS_CAPACITY};
+use crate::context::{DEFAULT_GRACE_PERIOD, ServiceContext};
+use crate::crash::{CrashHook, CrashReport, SharedCrashHook, catch_panic};
+use crate::errors::Aggregate;
+use crate::lifecycle::{LifecycleEvent, LifecycleObserver, SharedObserver};
+use crate::ready::{ReadySignal, Readiness};
+use crate::runnable_service::{Legacy, StarlightService, StarlightServiceV2};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::{AbortHandle, JoinSet};
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::AbortOnDropHandle;
+use tracing::Instrument;
+
+/// How one service ended.
+#[derive(Debug)]
+pub struct ServiceOutcome {
+    pub name: String,
+    /// The service's own error, or its panic.
+    pub result: anyhow::Result<()>,
+    pub stopped_at: Instant,
+    /// Set if the service panicked.
+    pub crash: Option<CrashReport>,
+}
+
+/// The outcomes of every service, in the order they stopped. Services stopping at the
+/// same instant are in registration order.
+#[derive(Debug, Default)]
+pub struct RunSummary {
+    pub outcomes: Vec<ServiceOutcome>,
+}
+
+impl RunSummary {
+    pub fn is_success(&self) -> bool {
+        self.outcomes.iter().all(|outcome| outcome.result.is_ok())
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &ServiceOutcome> {
+        self.outcomes.iter().filter(|outcome| outcome.result.is_err())
+    }
+
+    pub fn first_failure(&self) -> Option<&ServiceOutcome> {
+        self.failures().next()
+    }
+
+    /// The error of the service which failed, naming it, if one failed, and an
+    /// [`Aggregate`] of the errors of every failed service, in the order they stopped,
+    /// if several did.
+    pub fn into_result(self) -> anyhow::Result<()> {
+        let mut failures: Vec<(String, anyhow::Error)> = self
+            .outcomes
+            .into_iter()
+            .filter_map(|outcome| Some((outcome.name, outcome.result.err()?)))
+            .collect();
+        match failures.len() {
+            0 => Ok(()),
+            1 => {
+                let (name, err) = failures.remove(0);
+                Err(err.context(format!("service {} failed", name)))
+            }
+            _ => Err(Aggregate::from(failures).into()),
+        }
+    }
+}
+
+/// What the [`ServiceManager`] does when a service fails.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum FailurePolicy {
+    /// Keep the other services running.
+    #[default]
+    Continue,
+    /// Stop every service.
+    FailFast,
+    /// Stop the services of the group (see [`ServiceManager::with_group`]) when one of
+    /// them fails. Failures outside the group are handled as with `Continue`.
+    FailFastGroup(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegisterError {
+    DuplicateService(String),
+    /// The services forming the cycle, starting and ending with the one registered.
+    DependencyCycle(Vec<String>),
+    /// A setting was given for a service which is not registered.
+    UnknownService(String),
+}
+
+impl fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegisterError::DuplicateService(name) => write!(f, "service {} is already registered", name),
+            RegisterError::DependencyCycle(cycle) => write!(f, "dependency cycle: {}", cycle.join(" -> ")),
+            RegisterError::UnknownService(name) => write!(f, "service {} is not registered", name),
+        }
+    }
+}
+
+impl std::error::Error for RegisterError {}
+
+/// What the watchdog does when a service misses its heartbeat interval.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchdogPolicy {
+    /// Log an error and count `service.watchdog.stalls`.
+    Log,
+    /// Also abort the current run so [`Supervised`](crate::Supervised) restarts it. Without
+    /// a `Supervised` wrapper this acts like `Log`.
+    Restart,
+    /// Also shut every service down.
+    Shutdown,
+}
+
+struct Entry {
+    name: String,
+    service: Arc<dyn StarlightServiceV2>,
+    depends_on: Vec<String>,
+    ready: ReadySignal,
+    phase: u32,
+    watchdog: Option<(Duration, WatchdogPolicy)>,
+    group: Option<String>,
+    startup_timeout: Option<Duration>,
+    warmup_timeout: Option<Duration>,
+}
+
+/// Runs a set of services until they all stop.
+///
+/// Services are stopped when the token passed to [`ServiceManager::run`] is cancelled. A
+/// failing service is logged and recorded while the others keep running, unless the
+/// [`FailurePolicy`] says to stop them too.
+///
+/// Services registered with dependencies start once all of them report ready (see
+/// [`ServiceContext::ready`]); a dependency stopping before that fails the dependent
+/// service.
+///
+/// Once shutdown starts, the services are marked not ready and keep running for the
+/// pre-stop delay, if any, so load balancers stop sending traffic before listeners close.
+/// Shutdown then proceeds in phases, lowest first: the services of a phase are cancelled
+/// together and the next phase starts once they have all stopped, or once the phase
+/// timeout has passed and the stragglers were aborted. Every service is in phase 0
+/// unless moved with [`ServiceManager::with_shutdown_phase`]. A service only counts as
+/// stopped once the tasks it spawned through its [`ServiceContext`] have finished, or the
+/// grace period (30 seconds by default) has passed.
+pub struct ServiceManager {
+    services: Vec<Entry>,
+    failure_policy: FailurePolicy,
+    phase_timeouts: HashMap<u32, Duration>,
+    grace_period: Duration,
+    observer: SharedObserver,
+    crash_hook: SharedCrashHook,
+    bus_capacity: usize,
+    pre_stop_delay: Duration,
+}
+
+impl Default for ServiceManager {
+    fn default() -> Self {
+        ServiceManager {
+            services: Vec::new(),
+            failure_policy: FailurePolicy::default(),
+            phase_timeouts: HashMap::new(),
+            grace_period: DEFAULT_GRACE_PERIOD,
+            observer: SharedObserver::default(),
+            crash_hook: SharedCrashHook::default(),
+            bus_capacity: DEFAULT_BUS_CAPACITY,
+            pre_stop_delay: Duration::ZERO,
+        }
+    }
+}
+
+impl fmt::Debug for ServiceManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServiceManager")
+            .field("services", &self.services.iter().map(|entry| &entry.name).collect::<Vec<_>>())
+            .field("failure_policy", &self.failure_policy)
+            .field("phase_timeouts", &self.phase_timeouts)
+            .field("grace_period", &self.grace_period)
+            .field("observer", &self.observer)
+            .field("crash_hook", &self.crash_hook)
+            .field("bus_capacity", &self.bus_capacity)
+            .field("pre_stop_delay", &self.pre_stop_delay)
+            .finish()
+    }
+}
+
+impl ServiceManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Shorthand for [`FailurePolicy::FailFast`] or [`FailurePolicy::Continue`].
+    pub fn with_fail_fast(self, fail_fast: bool) -> Self {
+        self.with_failure_policy(if fail_fast { FailurePolicy::FailFast } else { FailurePolicy::Continue })
+    }
+
+    pub fn with_failure_policy(mut self, failure_policy: FailurePolicy) -> Self {
+        self.failure_policy = failure_policy;
+        self
+    }
+
+    /// Puts the service `name` in `group`, for [`FailurePolicy::FailFastGroup`].
+    ///
+    /// Fails with [`RegisterError::UnknownService`] if no service `name` is registered.
+    pub fn with_group(mut self, name: &str, group: impl Into<String>) -> Result<Self, RegisterError> {
+        self.entry_mut(name)?.group = Some(group.into());
+        Ok(self)
+    }
+
+    /// Fails the service `name` if it doesn't report ready within `timeout` of starting
+    /// (once its dependencies are ready).
+    ///
+    /// Fails with [`RegisterError::UnknownService`] if no service `name` is registered.
+    pub fn with_startup_timeout(mut self, name: &str, timeout: Duration) -> Result<Self, RegisterError> {
+        self.entry_mut(name)?.startup_timeout = Some(timeout);
+        Ok(self)
+    }
+
+    /// Fails the service `name` if its [`warmup`](StarlightServiceV2::warmup) takes longer
+    /// than `timeout`.
+    ///
+    /// Fails with [`RegisterError::UnknownService`] if no service `name` is registered.
+    pub fn with_warmup_timeout(mut self, name: &str, timeout: Duration) -> Result<Self, RegisterError> {
+        self.entry_mut(name)?.warmup_timeout = Some(timeout);
+        Ok(self)
+    }
+
+    /// Moves the service `name` to shutdown phase `phase`, e.g. 0 for listeners, 1 for
+    /// workers draining their queues and 2 for connection pools.
+    ///
+    /// Fails with [`RegisterError::UnknownService`] if no service `name` is registered.
+    pub fn with_shutdown_phase(mut self, name: &str, phase: u32) -> Result<Self, RegisterError> {
+        self.entry_mut(name)?.phase = phase;
+        Ok(self)
+    }
+
+    /// Expects the service `name` to [`beat`](crate::Heartbeat::beat) at least every
+    /// `interval`, applying `policy` when it doesn't.
+    ///
+    /// Fails with [`RegisterError::UnknownService`] if no service `name` is registered.
+    pub fn with_watchdog(
+        mut self,
+        name: &str,
+        interval: Duration,
+        policy: WatchdogPolicy,
+    ) -> Result<Self, RegisterError> {
+        self.entry_mut(name)?.watchdog = Some((interval, policy));
+        Ok(self)
+    }
+
+    /// How long the services of `phase` get to stop before they are aborted. Without a
+    /// timeout shutdown waits for them indefinitely.
+    pub fn with_phase_timeout(mut self, phase: u32, timeout: Duration) -> Self {
+        self.phase_timeouts.insert(phase, timeout);
+        self
+    }
+
+    /// Where service [`LifecycleEvent`]s go, [`TracingObserver`](crate::TracingObserver) by
+    /// default.
+    pub fn with_observer(mut self, observer: impl LifecycleObserver) -> Self {
+        self.observer = SharedObserver(Arc::new(observer));
+        self
+    }
+
+    /// Called once for every panic of a service or of a task it spawned through its
+    /// [`ServiceContext`], after the panic is logged.
+    pub fn with_crash_hook(mut self, crash_hook: impl CrashHook) -> Self {
+        self.crash_hook = SharedCrashHook(Some(Arc::new(crash_hook)));
+        self
+    }
+
+    /// How many messages of each type the bus (see [`ServiceContext::publisher`]) keeps for
+    /// subscribers falling behind, 256 by default.
+    pub fn with_bus_capacity(mut self, bus_capacity: usize) -> Self {
+        self.bus_capacity = bus_capacity;
+        self
+    }
+
+    /// How long services keep running once shutdown starts, reporting not ready, before
+    /// they are cancelled. None by default; under Kubernetes it should cover the time for
+    /// the endpoint to be removed after SIGTERM.
+    pub fn with_pre_stop_delay(mut self, pre_stop_delay: Duration) -> Self {
+        self.pre_stop_delay = pre_stop_delay;
+        self
+    }
+
+    /// How long to wait for the tasks a service spawned after the service itself returns.
+    pub fn with_grace_period(mut self, grace_period: Duration) -> Self {
+        self.grace_period = grace_period;
+        self
+    }
+
+    /// Adds a service without dependencies, under its own [`name`](StarlightServiceV2::name).
+    ///
+    /// # Panics
+    ///
+    /// If a service with the same name is already registered, see
+    /// [`ServiceManager::try_with_service`].
+    pub fn with_service<S: StarlightServiceV2>(self, service: S) -> Self {
+        self.try_with_service(service).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Adds a service without dependencies, failing with [`RegisterError::DuplicateService`]
+    /// if a service with the same name is already registered.
+    pub fn try_with_service<S: StarlightServiceV2>(mut self, service: S) -> Result<Self, RegisterError> {
+        let name = service.name().to_owned();
+        self.push(name, Arc::new(service), Vec::new())?;
+        Ok(self)
+    }
+
+    /// Adds a service implementing the old [`StarlightService`] trait. Prefer
+    /// [`ServiceManager::with_service`]: old services cannot report errors.
+    ///
+    /// # Panics
+    ///
+    /// If a service with the same name is already registered, see
+    /// [`ServiceManager::try_with_legacy_service`].
+    pub fn with_legacy_service<S: StarlightService>(self, name: impl Into<String>, service: S) -> Self {
+        self.try_with_legacy_service(name, service).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Adds a service implementing the old [`StarlightService`] trait, failing with
+    /// [`RegisterError::DuplicateService`] if a service with the same name is already
+    /// registered.
+    pub fn try_with_legacy_service<S: StarlightService>(
+        mut self,
+        name: impl Into<String>,
+        service: S,
+    ) -> Result<Self, RegisterError> {
+        let name = name.into();
+        let legacy = Arc::new(Legacy {
+            service,
+            name: name.clone(),
+        });
+        self.push(name, legacy, Vec::new())?;
+        Ok(self)
+    }
+
+    /// Adds a service started only once every service in `depends_on` is ready.
+    /// Dependencies may be registered later, but not so as to form a cycle.
+    pub fn register_with<S, I, T>(&mut self, name: impl Into<String>, service: S, depends_on: I) -> Result<(), RegisterError>
+    where
+        S: StarlightServiceV2,
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        let depends_on = depends_on.into_iter().map(Into::into).collect();
+        self.push(name.into(), Arc::new(service), depends_on)
+    }
+
+    fn entry_mut(&mut self, name: &str) -> Result<&mut Entry, RegisterError> {
+        self.services
+            .iter_mut()
+            .find(|entry| entry.name == name)
+            .ok_or_else(|| RegisterError::UnknownService(name.to_owned()))
+    }
+
+    fn push(&mut self, name: String, service: Arc<dyn StarlightServiceV2>, depends_on: Vec<String>) -> Result<(), RegisterError> {
+        if self.services.iter().any(|entry| entry.name == name) {
+            return Err(RegisterError::DuplicateService(name));
+        }
+        if let Some(cycle) = self.find_path(&depends_on, &name, vec![name.clone()]) {
+            return Err(RegisterError::DependencyCycle(cycle));
+        }
+        self.services.push(Entry {
+            name,
+            depends_on,
+            ready: ReadySignal::new(),
+            phase: service.shutdown_phase(),
+            service,
+            watchdog: None,
+            group: None,
+            startup_timeout: None,
+            warmup_timeout: None,
+        });
+        Ok(())
+    }
+
+    /// A dependency path from any of `from` to `target`, appended to `path`.
+    fn find_path(&self, from: &[String], target: &str, path: Vec<String>) -> Option<Vec<String>> {
+        from.iter().find_map(|next| {
+            let mut path = path.clone();
+            path.push(next.clone());
+            if next == target {
+                return Some(path);
+            }
+            let entry = self.services.iter().find(|entry| &entry.name == next)?;
+            self.find_path(&entry.depends_on, target, path)
+        })
+    }
+
+    /// The current readiness of every service, in registration order.
+    pub fn readiness(&self) -> Vec<(String, Readiness)> {
+        self.services
+            .iter()
+            .map(|entry| (entry.name.clone(), entry.ready.readiness()))
+            .collect()
+    }
+
+    /// Resolves once every service has reported ready (or done), or fails after `timeout` with an
+    /// [`Aggregate`] holding one error per service which has not, its readiness as the message.
+    pub async fn wait_ready(&self, timeout: Duration) -> Result<(), Aggregate> {
+        let all_ready = async {
+            for entry in &self.services {
+                let mut state = entry.ready.subscribe();
+                let _ = state.wait_for(Readiness::is_ready).await;
+            }
+        };
+        if tokio::time::timeout(timeout, all_ready).await.is_ok() {
+            return Ok(());
+        }
+        let not_ready: Vec<(String, anyhow::Error)> = self
+            .readiness()
+            .into_iter()
+            .filter(|(_, readiness)| !readiness.is_ready())
+            .map(|(service, readiness)| (service, anyhow::anyhow!("{}", readiness)))
+            .collect();
+        Err(Aggregate::from(not_ready))
+    }
+
+    /// Runs every service until all of them have stopped, stopping them once `shutdown`
+    /// is cancelled.
+    ///
+    /// Unlike [`ServiceManager::wait_ready`], it does not fail with an [`Aggregate`]: the
+    /// services have stopped either way, and the [`RunSummary`] keeps what an error would
+    /// drop, the services which stopped cleanly and the [`CrashReport`] of those which
+    /// panicked. [`RunSummary::into_result`] gives the error, an [`Aggregate`] when
+    /// several services failed.
+    pub async fn run(&self, shutdown: CancellationToken) -> RunSummary {
+        // Cancelled by the caller, or by a failure under fail-fast.
+        let stop = shutdown.child_token();
+        let mut phases: BTreeMap<u32, CancellationToken> = BTreeMap::new();
+        let mut tasks = JoinSet::new();
+        let mut running = HashMap::new();
+        let mut watched = Vec::new();
+        let bus = Arc::new(Bus::new(self.bus_capacity));
+        for entry in &self.services {
+            let dependencies: Result<Vec<_>, _> = entry
+                .depends_on
+                .iter()
+                .map(|dependency| match self.services.iter().find(|other| &other.name == dependency) {
+                    Some(other) => Ok((dependency.clone(), other.ready.subscribe())),
+                    None => Err(anyhow::anyhow!("unknown dependency {}", dependency)),
+                })
+                .collect();
+            let service = entry.service.clone();
+            let ready = entry.ready.clone();
+            let token = phases.entry(entry.phase).or_default().child_token();
+            let context = ServiceContext::new(&entry.name, token.clone(), ready.clone(), self.grace_period)
+                .with_observer(self.observer.clone())
+                .with_crash_hook(self.crash_hook.clone())
+                .with_bus(bus.clone());
+            if let Some((interval, policy)) = entry.watchdog {
+                watched.push((context.clone(), interval, policy));
+            }
+            let grace_period = self.grace_period;
+            let startup_timeout = entry.startup_timeout;
+            let warmup_timeout = entry.warmup_timeout;
+            let span = tracing::info_span!(
+                "service.run",
+                service = %entry.name,
+                outcome = tracing::field::Empty,
+                otel.status_code = tracing::field::Empty,
+            );
+            self.observer.0.observe(&entry.name, &LifecycleEvent::Started);
+            let handle = tasks.spawn({
+                let token = token.clone();
+                async move {
+                    let started = Instant::now();
+                    let result = catch_panic(async {
+                        for (dependency, mut state) in dependencies? {
+                            tokio::select! {
+                                state = state.wait_for(|r| r.is_ready() || *r == Readiness::Stopped) => {
+                                    if state.is_ok_and(|state| *state == Readiness::Stopped) {
+                                        anyhow::bail!("dependency {} stopped before becoming ready", dependency);
+                                    }
+                                }
+                                _ = token.cancelled() => return Ok(()),
+                            }
+                        }
+                        context.observe(&LifecycleEvent::WarmingUp);
+                        let warming = Instant::now();
+                        let warmup = async {
+                            match warmup_timeout {
+                                Some(timeout) => tokio::time::timeout(timeout, service.warmup())
+                                    .await
+                                    .unwrap_or_else(|_| Err(anyhow::anyhow!("did not finish within {:?}", timeout))),
+                                None => service.warmup().await,
+                            }
+                        };
+                        tokio::select! {
+                            biased;
+                            warmed = warmup => warmed.map_err(|err| err.context("warmup failed"))?,
+                            _ = token.cancelled() => return Ok(()),
+                        }
+                        context.observe(&LifecycleEvent::WarmedUp { elapsed: warming.elapsed() });
+                        let mut run = std::pin::pin!(service.run_with_context(context.clone()));
+                        if let Some(timeout) = startup_timeout {
+                            let mut state = ready.subscribe();
+                            tokio::select! {
+                                result = &mut run => return result,
+                                started = tokio::time::timeout(timeout, state.wait_for(Readiness::is_ready)) => {
+                                    if started.is_err() {
+                                        anyhow::bail!("did not report ready within {:?}", timeout);
+                                    }
+                                }
+                            }
+                        }
+                        run.await
+                    })
+                    .await;
+                    let stopped_at = Instant::now();
+                    let (result, crash) = match result {
+                        Ok(result) => (result, None),
+                        Err(panic) => {
+                            let report = panic.into_report(context.name(), None, stopped_at - started);
+                            context.crash_hook().report(&report);
+                            (Err(anyhow::anyhow!("service panicked with message {:?}", report.message)), Some(report))
+                        }
+                    };
+                    let tracker = context.tracker();
+                    tracker.close();
+                    if tokio::time::timeout(grace_period, tracker.wait()).await.is_err() {
+                        tracing::warn!(service = %context.name(), tasks = tracker.len(), "tasks still running after the grace period");
+                    }
+                    ready.stopped();
+                    (result, stopped_at, crash)
+                }
+                .instrument(span.clone())
+            });
+            let task = Running {
+                name: entry.name.clone(),
+                ready: entry.ready.clone(),
+                phase: entry.phase,
+                abort: handle.clone(),
+                span,
+                token,
+                group: entry.group.clone(),
+            };
+            running.insert(handle.id(), task);
+        }
+
+        let _watchdog = (!watched.is_empty()).then(|| AbortOnDropHandle::new(tokio::spawn(watchdog(watched, stop.clone()))));
+
+        let mut run = Run {
+            summary: RunSummary::default(),
+            running,
+            aborted: HashSet::new(),
+            failure_policy: &self.failure_policy,
+            stop: &stop,
+            observer: &self.observer,
+            services: &self.services,
+        };
+        loop {
+            tokio::select! {
+                joined = tasks.join_next_with_id() => match joined {
+                    Some(joined) => run.record(joined),
+                    None => return run.finish(),
+                },
+                _ = stop.cancelled() => break,
+            }
+        }
+
+        if !self.pre_stop_delay.is_zero() {
+            let delay = self.pre_stop_delay;
+            tracing::info!(?delay, "marking services not ready before stopping them");
+            for task in run.running.values() {
+                if task.ready.readiness() == Readiness::Ready {
+                    task.ready.not_ready("stopping");
+                }
+                self.observer.0.observe(&task.name, &LifecycleEvent::PreStop { delay });
+            }
+            let mut deadline = std::pin::pin!(tokio::time::sleep(delay));
+            loop {
+                tokio::select! {
+                    joined = tasks.join_next_with_id() => match joined {
+                        Some(joined) => run.record(joined),
+                        None => break,
+                    },
+                    _ = &mut deadline => break,
+                }
+            }
+        }
+
+        bus.close();
+
+        for (phase, token) in phases {
+            let timeout = self.phase_timeouts.get(&phase).copied();
+            tracing::info!(phase, ?timeout, "stopping shutdown phase");
+            token.cancel();
+            let deadline = timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+            while run.in_phase(phase) {
+                tokio::select! {
+                    Some(joined) = tasks.join_next_with_id() => run.record(joined),
+                    _ = sleep_until(deadline) => {
+                        for (id, task) in run.running.iter().filter(|(_, task)| task.phase == phase) {
+                            tracing::warn!(service = %task.name, phase, "service did not stop in time, aborting");
+                            task.abort.abort();
+                            run.aborted.insert(*id);
+                        }
+                        // Collect the aborted tasks without waiting on later phases.
+                        while run.in_phase(phase) {
+                            match tasks.join_next_with_id().await {
+                                Some(joined) => run.record(joined),
+                                None => break,
+                            }
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+        while let Some(joined) = tasks.join_next_with_id().await {
+            run.record(joined);
+        }
+        run.finish()
+    }
+}
+
+/// Checks the heartbeats of `watched` services, flagging each stall once.
+async fn watchdog(watched: Vec<(ServiceContext, Duration, WatchdogPolicy)>, stop: CancellationToken) {
+    let period = watched.iter().map(|(_, interval, _)| *interval).min().unwrap_or_default() / 4;
+    let mut ticks = tokio::time::interval(period.max(Duration::from_millis(10)));
+    let mut stalled = vec![false; watched.len()];
+    loop {
+        ticks.tick().await;
+        for ((context, interval, policy), stalled) in watched.iter().zip(stalled.iter_mut()) {
+            let since_last = context.heartbeat().since_last();
+            if since_last <= *interval || context.shutdown().is_cancelled() {
+                *stalled = false;
+                continue;
+            }
+            if *stalled {
+                continue;
+            }
+            *stalled = true;
+            tracing::error!(
+                service = %context.name(),
+                monotonic_counter.service.watchdog.stalls = 1u64,
+                ?since_last,
+                ?policy,
+                "service missed its heartbeat"
+            );
+            match policy {
+                WatchdogPolicy::Log => {}
+                WatchdogPolicy::Restart => context.notify_stalled(),
+                WatchdogPolicy::Shutdown => stop.cancel(),
+            }
+        }
+    }
+}
+
+async fn sleep_until(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+type Joined = Result<(tokio::task::Id, (anyhow::Result<()>, Instant, Option<CrashReport>)), tokio::task::JoinError>;
+
+struct Running {
+    name: String,
+    ready: ReadySignal,
+    phase: u32,
+    abort: AbortHandle,
+    span: tracing::Span,
+    token: CancellationToken,
+    group: Option<String>,
+}
+
+/// Bookkeeping of a [`ServiceManager::run`].
+struct Run<'a> {
+    summary: RunSummary,
+    running: HashMap<tokio::task::Id, Running>,
+    aborted: HashSet<tokio::task::Id>,
+    failure_policy: &'a FailurePolicy,
+    stop: &'a CancellationToken,
+    observer: &'a SharedObserver,
+    services: &'a [Entry],
+}
+
+impl Run<'_> {
+    fn in_phase(&self, phase: u32) -> bool {
+        self.running.values().any(|task| task.phase == phase)
+    }
+
+    fn record(&mut self, joined: Joined) {
+        let (id, (result, stopped_at, crash), aborted) = match joined {
+            Ok((id, outcome)) => (id, outcome, false),
+            Err(err) if self.aborted.contains(&err.id()) => {
+                (err.id(), (Err(anyhow::anyhow!("did not stop in time")), Instant::now(), None), true)
+            }
+            Err(err) => (err.id(), (Err(anyhow::Error::from(err)), Instant::now(), None), false),
+        };
+        let Some(Running { name, ready, span, group, .. }) = self.running.remove(&id) else { return };
+        // An aborted service never got to mark itself stopped.
+        ready.stopped();
+        let event = match &result {
+            Ok(()) => LifecycleEvent::Completed,
+            Err(_) if aborted => LifecycleEvent::Aborted,
+            Err(err) => LifecycleEvent::Failed(err),
+        };
+        span.record("outcome", event.kind());
+        if result.is_err() {
+            span.record("otel.status_code", "ERROR");
+        }
+        self.observer.0.observe(&name, &event);
+        match &result {
+            Ok(()) => tracing::info!(service = %name, "service stopped"),
+            Err(err) => {
+                tracing::error!(service = %name, "service failed: {:#}", err);
+                match self.failure_policy {
+                    FailurePolicy::Continue => {}
+                    FailurePolicy::FailFast if !self.stop.is_cancelled() => {
+                        tracing::warn!(service = %name, "stopping the other services");
+                        self.stop.cancel();
+                    }
+                    FailurePolicy::FailFast => {}
+                    FailurePolicy::FailFastGroup(failing) if group.as_ref() == Some(failing) => {
+                        for task in self.running.values().filter(|task| task.group.as_ref() == Some(failing)) {
+                            if !task.token.is_cancelled() {
+                                tracing::warn!(service = %task.name, group = %failing, "stopping the group of a failed service");
+                                task.token.cancel();
+                            }
+                        }
+                    }
+                    FailurePolicy::FailFastGroup(_) => {}
+                }
+            }
+        }
+        self.summary.outcomes.push(ServiceOutcome {
+            name,
+            result,
+            stopped_at,
+            crash,
+        });
+    }
+
+    /// Orders the outcomes by when the services stopped, ties in registration order, so
+    /// the first failure doesn't depend on which task was joined first.
+    fn finish(mut self) -> RunSummary {
+        let services = self.services;
+        let position = |name: &str| services.iter().position(|entry| entry.name == name);
+        self.summary.outcomes.sort_by_key(|outcome| (outcome.stopped_at, position(&outcome.name)));
+        self.summary
+    }
+}
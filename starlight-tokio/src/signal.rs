@@ -0,0 +1,144 @@
+use std::fmt;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    Interrupt,
+    Terminate,
+    Quit,
+}
+
+impl Signal {
+    fn exit_code(self) -> i32 {
+        128 + match self {
+            Signal::Interrupt => 2,
+            Signal::Quit => 3,
+            Signal::Terminate => 15,
+        }
+    }
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Signal::Interrupt => "SIGINT",
+            Signal::Terminate => "SIGTERM",
+            Signal::Quit => "SIGQUIT",
+        })
+    }
+}
+
+/// A token cancelled on the first SIGINT, SIGTERM or (on unix) SIGQUIT.
+///
+/// A second signal while shutdown is in progress exits the process immediately. Must be
+/// called from within a Tokio runtime; the handlers are installed before it returns.
+pub fn shutdown_signal() -> CancellationToken {
+    shutdown_signal_with(CancellationToken::new())
+}
+
+/// Like [`shutdown_signal`], cancelling `token`. A shutdown started through `token`
+/// itself also makes the next signal exit immediately.
+pub fn shutdown_signal_with(token: CancellationToken) -> CancellationToken {
+    let signals = os_signals();
+    tokio::spawn(listen(signals, token.clone(), |signal| std::process::exit(signal.exit_code())));
+    token
+}
+
+#[cfg(unix)]
+fn os_signals() -> mpsc::UnboundedReceiver<Signal> {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    for (kind, name) in [
+        (SignalKind::interrupt(), Signal::Interrupt),
+        (SignalKind::terminate(), Signal::Terminate),
+        (SignalKind::quit(), Signal::Quit),
+    ] {
+        let mut stream = signal(kind).expect("failed to install signal handler");
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            while stream.recv().await.is_some() && tx.send(name).is_ok() {}
+        });
+    }
+    rx
+}
+
+#[cfg(not(unix))]
+fn os_signals() -> mpsc::UnboundedReceiver<Signal> {
+    use tokio::signal::windows;
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    let mut ctrl_c = windows::ctrl_c().expect("failed to install signal handler");
+    let mut close = windows::ctrl_close().expect("failed to install signal handler");
+    tokio::spawn(async move {
+        loop {
+            let signal = tokio::select! {
+                Some(()) = ctrl_c.recv() => Signal::Interrupt,
+                Some(()) = close.recv() => Signal::Terminate,
+                else => break,
+            };
+            if tx.send(signal).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+async fn listen(mut signals: mpsc::UnboundedReceiver<Signal>, token: CancellationToken, exit: impl FnOnce(Signal)) {
+    tokio::select! {
+        // A signal arriving after an external shutdown started is an escalation.
+        biased;
+        _ = token.cancelled() => {}
+        Some(signal) = signals.recv() => {
+            tracing::warn!(signal = %signal, "received {}, shutting down", signal);
+            token.cancel();
+        }
+    }
+    if let Some(signal) = signals.recv().await {
+        tracing::error!(signal = %signal, "received {} during shutdown, exiting immediately", signal);
+        exit(signal);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[tokio::test]
+    async fn second_signal_escalates() {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let token = CancellationToken::new();
+        let exited = Arc::new(Mutex::new(None));
+        let listener = tokio::spawn(listen(rx, token.clone(), {
+            let exited = exited.clone();
+            move |signal| *exited.lock().unwrap() = Some(signal)
+        }));
+
+        tx.send(Signal::Terminate).unwrap();
+        token.cancelled().await;
+        assert_eq!(*exited.lock().unwrap(), None);
+
+        tx.send(Signal::Interrupt).unwrap();
+        listener.await.unwrap();
+        assert_eq!(*exited.lock().unwrap(), Some(Signal::Interrupt));
+    }
+
+    #[tokio::test]
+    async fn signal_after_external_cancellation_escalates() {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let token = CancellationToken::new();
+        let exited = Arc::new(Mutex::new(None));
+        let listener = tokio::spawn(listen(rx, token.clone(), {
+            let exited = exited.clone();
+            move |signal| *exited.lock().unwrap() = Some(signal)
+        }));
+
+        token.cancel();
+        tx.send(Signal::Quit).unwrap();
+        listener.await.unwrap();
+        assert_eq!(*exited.lock().unwrap(), Some(Signal::Quit));
+    }
+}
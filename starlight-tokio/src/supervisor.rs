@@ -0,0 +1,189 @@
+use crate::context::ServiceContext;
+use crate::lifecycle::LifecycleEvent;
+use crate::runnable_service::StarlightServiceV2;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+/// Delays between restarts: `initial * multiplier^attempt`, capped at `max`, with up to
+/// `jitter` (a fraction of the delay) added or removed at random.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExponentialBackoff {
+    pub initial: Duration,
+    pub max: Duration,
+    pub multiplier: f64,
+    pub jitter: f64,
+}
+
+impl Default for ExponentialBackoff {
+    fn default() -> Self {
+        ExponentialBackoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+        }
+    }
+}
+
+impl ExponentialBackoff {
+    /// The delay before restart number `attempt`, counting from zero.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let base = self.initial.as_secs_f64() * self.multiplier.powi(attempt as i32);
+        let jitter = self.jitter.clamp(0.0, 1.0) * (rand::random::<f64>() * 2.0 - 1.0);
+        Duration::from_secs_f64((base * (1.0 + jitter)).clamp(0.0, self.max.as_secs_f64()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RestartPolicy {
+    Never,
+    /// Restart after an error or panic, giving up after `max_retries` restarts in a row.
+    OnFailure { max_retries: u32, backoff: ExponentialBackoff },
+    /// Restart whenever the service stops before shutdown.
+    Always { backoff: ExponentialBackoff },
+}
+
+/// Passed to [`Supervised::with_on_restart`] before each restart.
+#[derive(Debug)]
+pub struct RestartEvent<'a> {
+    pub service: &'a str,
+    /// Restarts in a row so far, including this one.
+    pub attempt: u32,
+    pub delay: Duration,
+    /// Why the service stopped; `None` when it returned `Ok` under [`RestartPolicy::Always`].
+    pub error: Option<&'a anyhow::Error>,
+}
+
+type RestartHook = Arc<dyn Fn(&RestartEvent<'_>) + Send + Sync>;
+
+/// Runs a service again after it fails, according to a [`RestartPolicy`].
+///
+/// Panics, and stalls reported by a watchdog with [`WatchdogPolicy::Restart`](crate::WatchdogPolicy::Restart),
+/// count as failures. The retry counter resets once a run has stayed up for the
+/// healthy uptime window (one minute by default). When retries are exhausted the last
+/// error is returned, which the [`ServiceManager`](crate::ServiceManager) reports.
+pub struct Supervised<S> {
+    service: Arc<S>,
+    policy: RestartPolicy,
+    healthy_uptime: Duration,
+    on_restart: Option<RestartHook>,
+}
+
+impl<S> std::fmt::Debug for Supervised<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Supervised")
+            .field("policy", &self.policy)
+            .field("healthy_uptime", &self.healthy_uptime)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S: StarlightServiceV2> Supervised<S> {
+    pub fn new(service: S, policy: RestartPolicy) -> Self {
+        Supervised {
+            service: Arc::new(service),
+            policy,
+            healthy_uptime: Duration::from_secs(60),
+            on_restart: None,
+        }
+    }
+
+    pub fn with_healthy_uptime(mut self, healthy_uptime: Duration) -> Self {
+        self.healthy_uptime = healthy_uptime;
+        self
+    }
+
+    /// Called before each restart, e.g. to count restarts in a metric.
+    pub fn with_on_restart(mut self, hook: impl Fn(&RestartEvent<'_>) + Send + Sync + 'static) -> Self {
+        self.on_restart = Some(Arc::new(hook));
+        self
+    }
+
+    /// Runs the service once, aborting it if the watchdog reports it stalled.
+    async fn run_once(&self, context: ServiceContext) -> anyhow::Result<()> {
+        context.heartbeat().beat();
+        let mut handle = tokio::spawn(self.service.clone().run_with_context(context.clone()));
+        tokio::select! {
+            joined = &mut handle => match joined {
+                Ok(result) => result,
+                Err(err) => Err(anyhow::Error::from(err).context("service panicked")),
+            },
+            _ = context.stalled() => {
+                handle.abort();
+                Err(anyhow::anyhow!("service stalled, no heartbeat"))
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: StarlightServiceV2> StarlightServiceV2 for Supervised<S> {
+    async fn run(self: Arc<Self>, shutdown: CancellationToken) -> anyhow::Result<()> {
+        let context = ServiceContext::detached(self.service.name(), shutdown);
+        self.run_with_context(context).await
+    }
+
+    async fn run_with_context(self: Arc<Self>, context: ServiceContext) -> anyhow::Result<()> {
+        let name = self.service.name();
+        let shutdown = context.shutdown().clone();
+        let mut attempt = 0;
+        loop {
+            let started = Instant::now();
+            let result = self.run_once(context.clone()).await;
+            if shutdown.is_cancelled() {
+                return result;
+            }
+            let (max_retries, backoff) = match (&self.policy, &result) {
+                (RestartPolicy::Never, _) | (RestartPolicy::OnFailure { .. }, Ok(())) => return result,
+                (RestartPolicy::OnFailure { max_retries, backoff }, Err(_)) => (Some(*max_retries), backoff),
+                (RestartPolicy::Always { backoff }, _) => (None, backoff),
+            };
+            if started.elapsed() >= self.healthy_uptime {
+                attempt = 0;
+            }
+            if max_retries.is_some_and(|max_retries| attempt >= max_retries) {
+                tracing::error!(service = %name, restarts = attempt, "giving up on service");
+                return result.map_err(|err| err.context(format!("gave up after {} restarts", attempt)));
+            }
+
+            let delay = backoff.delay(attempt);
+            attempt += 1;
+            context.ready().not_ready("restarting");
+            match &result {
+                Ok(()) => tracing::warn!(service = %name, attempt, ?delay, "service stopped, restarting"),
+                Err(err) => tracing::warn!(service = %name, attempt, ?delay, "service failed, restarting: {:#}", err),
+            }
+            context.observe(&LifecycleEvent::Restarting {
+                attempt,
+                delay,
+                error: result.as_ref().err(),
+            });
+            if let Some(hook) = &self.on_restart {
+                hook(&RestartEvent {
+                    service: name,
+                    attempt,
+                    delay,
+                    error: result.as_ref().err(),
+                });
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = shutdown.cancelled() => return Ok(()),
+            }
+        }
+    }
+
+    async fn warmup(&self) -> anyhow::Result<()> {
+        self.service.warmup().await
+    }
+
+    fn name(&self) -> &str {
+        self.service.name()
+    }
+
+    fn shutdown_phase(&self) -> u32 {
+        self.service.shutdown_phase()
+    }
+}
@@ -0,0 +1,59 @@
+use anyhow::Context;
+use starlight_tokio::errors::Aggregate;
+use starlight_tokio::{CancellationToken, ServiceManager, service_fn};
+use std::time::Duration;
+use tokio::time::sleep;
+
+fn fails_with_cause(name: &'static str, cause: &'static str, context: &'static str) -> starlight_tokio::ServiceFn {
+    service_fn(name, move |_| async move {
+        sleep(Duration::from_millis(100)).await;
+        Err(anyhow::anyhow!(cause)).context(context)
+    })
+}
+
+#[tokio::test(start_paused = true)]
+async fn concurrent_failures_are_all_kept_with_their_causes() {
+    let manager = ServiceManager::new()
+        .with_service(fails_with_cause("cache", "connection refused", "connecting to redis"))
+        .with_service(fails_with_cause("queue", "no route to host", "broker unreachable"));
+
+    let err = manager.run(CancellationToken::new()).await.into_result().unwrap_err();
+    let aggregate = err.downcast_ref::<Aggregate>().unwrap();
+    let failures: Vec<(&str, Vec<String>)> = aggregate
+        .sources()
+        .map(|(service, error)| (service, error.chain().map(ToString::to_string).collect()))
+        .collect();
+    assert_eq!(
+        failures,
+        [
+            (
+                "cache",
+                vec!["connecting to redis".to_owned(), "connection refused".to_owned()]
+            ),
+            (
+                "queue",
+                vec!["broker unreachable".to_owned(), "no route to host".to_owned()]
+            ),
+        ]
+    );
+    assert_eq!(
+        aggregate.to_string(),
+        "2 services failed:\n\
+         - cache: connecting to redis\n    caused by: connection refused\n\
+         - queue: broker unreachable\n    caused by: no route to host"
+    );
+    assert_eq!(
+        serde_json::to_value(aggregate).unwrap(),
+        serde_json::json!({"errors": [
+            {"service": "cache", "error": "connecting to redis", "causes": ["connection refused"]},
+            {"service": "queue", "error": "broker unreachable", "causes": ["no route to host"]},
+        ]})
+    );
+}
+
+#[test]
+fn converts_from_pairs() {
+    let aggregate = Aggregate::from(vec![("db".to_owned(), anyhow::anyhow!("migrations failed"))]);
+    assert_eq!(aggregate.len(), 1);
+    assert_eq!(aggregate.to_string(), "1 service failed:\n- db: migrations failed");
+}
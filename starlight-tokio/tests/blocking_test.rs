@@ -0,0 +1,82 @@
+use starlight_tokio::{BlockingService, CancellationToken, ServiceManager, StarlightServiceV2};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+// Blocking threads don't mix with paused time, so these run on the real clock.
+
+#[tokio::test]
+async fn blocking_loop_exits_on_the_probe() {
+    let exited = Arc::new(AtomicBool::new(false));
+    let manager = ServiceManager::new().with_service(BlockingService::new("lua", {
+        let exited = exited.clone();
+        move |probe| {
+            while !probe.wait_cancelled(Duration::from_millis(5)) {
+                probe.heartbeat().beat();
+            }
+            assert!(probe.is_cancelled());
+            exited.store(true, Ordering::SeqCst);
+            Ok(())
+        }
+    }));
+
+    let shutdown = CancellationToken::new();
+    let (summary, _) = tokio::join!(manager.run(shutdown.clone()), async {
+        manager.wait_ready(Duration::from_secs(1)).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        shutdown.cancel();
+    });
+
+    assert!(summary.is_success());
+    assert!(exited.load(Ordering::SeqCst));
+}
+
+#[tokio::test]
+async fn blocking_code_ignoring_shutdown_is_abandoned_after_the_grace_period() {
+    let finished = Arc::new(AtomicBool::new(false));
+    let service = BlockingService::new("c-client", {
+        let finished = finished.clone();
+        move |_probe| {
+            std::thread::sleep(Duration::from_secs(2));
+            finished.store(true, Ordering::SeqCst);
+            Ok(())
+        }
+    })
+    .with_dedicated_thread(true);
+    let manager = ServiceManager::new()
+        .with_grace_period(Duration::from_millis(100))
+        .with_service(service);
+
+    let shutdown = CancellationToken::new();
+    shutdown.cancel();
+    let started = Instant::now();
+    let summary = manager.run(shutdown).await;
+
+    assert!(summary.is_success());
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert!(!finished.load(Ordering::SeqCst));
+}
+
+#[tokio::test]
+async fn panics_are_reported_as_failures() {
+    let manager = ServiceManager::new().with_service(BlockingService::new("lua", |_probe| panic!("interpreter crashed")));
+
+    let summary = manager.run(CancellationToken::new()).await;
+
+    let failure = summary.failures().next().unwrap();
+    assert_eq!(failure.name, "lua");
+    assert_eq!(
+        failure.result.as_ref().unwrap_err().to_string(),
+        "blocking task panicked with message \"interpreter crashed\""
+    );
+}
+
+#[tokio::test]
+async fn errors_are_returned_and_the_closure_runs_once() {
+    let service = Arc::new(BlockingService::new("lua", |_probe| anyhow::bail!("script error")));
+
+    let err = service.clone().run(CancellationToken::new()).await.unwrap_err();
+    assert_eq!(err.to_string(), "script error");
+    let err = service.run(CancellationToken::new()).await.unwrap_err();
+    assert_eq!(err.to_string(), "blocking service lua has already run");
+}
@@ -0,0 +1,78 @@
+use starlight_tokio::{Budgeted, CancellationToken, LifecycleEvent, LifecycleObserver, ServiceManager, service_fn};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Default)]
+struct LongPolls(Arc<Mutex<Vec<String>>>);
+
+impl LifecycleObserver for LongPolls {
+    fn observe(&self, service: &str, event: &LifecycleEvent<'_>) {
+        if let LifecycleEvent::LongPoll { .. } = event {
+            self.0.lock().unwrap().push(service.to_owned());
+        }
+    }
+}
+
+/// Blocks the thread for `busy` between awaits, `rounds` times.
+fn hashing(name: &str, busy: Duration, rounds: u32) -> starlight_tokio::ServiceFn {
+    service_fn(name, move |context| async move {
+        for _ in 0..rounds {
+            std::thread::sleep(busy);
+            tokio::task::yield_now().await;
+        }
+        context.shutdown().cancelled().await;
+        Ok(())
+    })
+}
+
+// Real time: the busy loops block the thread.
+#[tokio::test]
+async fn long_polls_are_reported_for_busy_services_only() {
+    let captured = Captured::default();
+    let writer = captured.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(move || writer.clone())
+        .with_ansi(false)
+        .finish();
+    let _subscriber = tracing::subscriber::set_default(subscriber);
+
+    let long_polls = LongPolls::default();
+    let busy = Budgeted::new(hashing("busy", Duration::from_millis(30), 3)).with_threshold(Duration::from_millis(20));
+    let calm = Budgeted::new(hashing("calm", Duration::ZERO, 3)).with_threshold(Duration::from_millis(20));
+    let (busy_stats, calm_stats) = (busy.stats(), calm.stats());
+    let manager = ServiceManager::new()
+        .with_observer(long_polls.clone())
+        .with_service(busy)
+        .with_service(calm);
+
+    let shutdown = CancellationToken::new();
+    let (summary, _) = tokio::join!(manager.run(shutdown.clone()), async {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        shutdown.cancel();
+    });
+
+    assert!(summary.is_success());
+    assert_eq!(busy_stats.long_polls(), 3);
+    assert!(busy_stats.longest() >= Duration::from_millis(30));
+    assert_eq!(calm_stats.long_polls(), 0);
+    assert!(calm_stats.polls() > 0);
+    assert_eq!(*long_polls.0.lock().unwrap(), ["busy", "busy", "busy"]);
+
+    let logged = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+    assert_eq!(logged.matches("without yielding").count(), 3, "{}", logged);
+    assert!(logged.lines().filter(|line| line.contains("without yielding")).all(|line| line.contains("WARN") && line.contains("service=busy")));
+}
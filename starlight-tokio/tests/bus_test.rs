@@ -0,0 +1,104 @@
+use starlight_tokio::tokio::sync::broadcast::error::RecvError;
+use starlight_tokio::{CancellationToken, ServiceManager, service_fn};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::sleep;
+
+#[derive(Debug, Clone, PartialEq)]
+struct OrderPlaced {
+    id: u32,
+}
+
+/// Records what it receives until the subscription ends, without watching shutdown.
+fn recorder<T: Clone + Send + 'static>(
+    name: &str,
+    delay: Duration,
+    received: &Arc<Mutex<Vec<Result<T, RecvError>>>>,
+) -> starlight_tokio::ServiceFn {
+    let received = received.clone();
+    service_fn(name, move |context| {
+        let received = received.clone();
+        async move {
+            let mut orders = context.subscribe::<T>();
+            sleep(delay).await;
+            loop {
+                let message = orders.recv().await;
+                let closed = matches!(message, Err(RecvError::Closed));
+                received.lock().unwrap().push(message);
+                if closed {
+                    return Ok(());
+                }
+            }
+        }
+    })
+}
+
+fn publisher(count: u32) -> starlight_tokio::ServiceFn {
+    service_fn("orders", move |context| async move {
+        let orders = context.publisher::<OrderPlaced>();
+        for id in 0..count {
+            orders.publish(OrderPlaced { id });
+        }
+        context.shutdown().cancelled().await;
+        Ok(())
+    })
+}
+
+async fn run_for(manager: &ServiceManager, duration: Duration) {
+    let shutdown = CancellationToken::new();
+    let (summary, _) = tokio::join!(manager.run(shutdown.clone()), async {
+        sleep(duration).await;
+        shutdown.cancel();
+    });
+    assert!(summary.is_success());
+}
+
+#[tokio::test(start_paused = true)]
+async fn services_exchange_typed_events() {
+    let received = Arc::default();
+    let mut manager = ServiceManager::new();
+    // The publisher starts once billing has subscribed.
+    manager.register_with("billing", recorder::<OrderPlaced>("billing", Duration::ZERO, &received), Vec::<String>::new()).unwrap();
+    manager.register_with("orders", publisher(3), ["billing"]).unwrap();
+
+    run_for(&manager, Duration::from_secs(1)).await;
+
+    let received: Vec<_> = received.lock().unwrap().drain(..).collect();
+    assert_eq!(
+        received,
+        [Ok(OrderPlaced { id: 0 }), Ok(OrderPlaced { id: 1 }), Ok(OrderPlaced { id: 2 }), Err(RecvError::Closed)]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn slow_subscribers_lag() {
+    let received = Arc::default();
+    let mut manager = ServiceManager::new().with_bus_capacity(4);
+    manager.register_with("audit", recorder::<OrderPlaced>("audit", Duration::from_millis(100), &received), Vec::<String>::new()).unwrap();
+    manager.register_with("orders", publisher(10), ["audit"]).unwrap();
+
+    run_for(&manager, Duration::from_secs(1)).await;
+
+    let received: Vec<_> = received.lock().unwrap().drain(..).collect();
+    let ids: Vec<_> = received.iter().filter_map(|message| message.as_ref().ok().map(|order| order.id)).collect();
+    assert_eq!(received[0], Err(RecvError::Lagged(6)));
+    assert_eq!(ids, [6, 7, 8, 9]);
+    assert_eq!(received.last(), Some(&Err(RecvError::Closed)));
+}
+
+#[tokio::test(start_paused = true)]
+async fn subscriptions_end_at_shutdown_after_draining() {
+    let received = Arc::default();
+    let mut manager = ServiceManager::new();
+    // Still reading when shutdown starts.
+    manager.register_with("billing", recorder::<OrderPlaced>("billing", Duration::from_secs(5), &received), Vec::<String>::new()).unwrap();
+    manager.register_with("orders", publisher(2), ["billing"]).unwrap();
+    // Nobody publishes these.
+    let unused = Arc::default();
+    manager.register_with("refunds", recorder::<u64>("refunds", Duration::ZERO, &unused), Vec::<String>::new()).unwrap();
+
+    run_for(&manager, Duration::from_secs(1)).await;
+
+    assert_eq!(received.lock().unwrap().len(), 3);
+    assert_eq!(*unused.lock().unwrap(), [Err(RecvError::Closed)]);
+}
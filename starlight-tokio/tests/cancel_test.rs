@@ -0,0 +1,109 @@
+use starlight_tokio::CancellationToken;
+use starlight_tokio::cancel::{
+    CancellableInterval, Cancelled, WithCancellation, recv_or_cancelled, sleep_or_cancelled,
+};
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::{Instant, timeout};
+
+/// A token cancelled after 20 ms.
+fn cancelled_soon() -> CancellationToken {
+    let token = CancellationToken::new();
+    let cancel = token.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        cancel.cancel();
+    });
+    token
+}
+
+/// The output of `future`, which must resolve within a second.
+async fn promptly<F: Future>(future: F) -> F::Output {
+    timeout(Duration::from_secs(1), future)
+        .await
+        .expect("resolved promptly")
+}
+
+#[tokio::test]
+async fn futures_resolve_unless_cancelled() {
+    let token = CancellationToken::new();
+    assert_eq!(async { 42 }.with_cancellation(&token).await, Ok(42));
+
+    let token = cancelled_soon();
+    let started = Instant::now();
+    let result = promptly(std::future::pending::<()>().with_cancellation(&token)).await;
+    assert_eq!(result, Err(Cancelled));
+    assert!(started.elapsed() < Duration::from_millis(500));
+
+    // Already cancelled wins over ready.
+    assert_eq!(async { 42 }.with_cancellation(&token).await, Err(Cancelled));
+}
+
+#[tokio::test]
+async fn receives_until_cancelled() {
+    let token = cancelled_soon();
+    let (tx, mut rx) = mpsc::channel(4);
+    tx.send("job").await.unwrap();
+    assert_eq!(recv_or_cancelled(&mut rx, &token).await, Ok(Some("job")));
+    assert_eq!(promptly(recv_or_cancelled(&mut rx, &token)).await, Err(Cancelled));
+
+    // The message sent after cancellation stays in the channel.
+    tx.send("late").await.unwrap();
+    assert_eq!(recv_or_cancelled(&mut rx, &token).await, Err(Cancelled));
+    assert_eq!(rx.recv().await, Some("late"));
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<()>();
+    drop(tx);
+    assert_eq!(recv_or_cancelled(&mut rx, &CancellationToken::new()).await, Ok(None));
+}
+
+#[tokio::test]
+async fn sleeps_until_cancelled() {
+    let token = CancellationToken::new();
+    assert_eq!(sleep_or_cancelled(Duration::from_millis(5), &token).await, Ok(()));
+
+    let token = cancelled_soon();
+    let result = promptly(sleep_or_cancelled(Duration::from_secs(3600), &token)).await;
+    assert_eq!(result, Err(Cancelled));
+}
+
+#[tokio::test]
+async fn intervals_stop_ticking_when_cancelled() {
+    let token = cancelled_soon();
+    let mut ticks = CancellableInterval::new(Duration::from_millis(5), &token);
+    let mut count = 0;
+    while promptly(ticks.tick()).await.is_some() {
+        count += 1;
+    }
+    assert!(count >= 2, "{}", count);
+    assert_eq!(ticks.tick().await, None);
+}
+
+#[tokio::test]
+async fn cancelled_converts_into_anyhow() {
+    async fn job(token: &CancellationToken) -> anyhow::Result<u32> {
+        Ok(async { 7 }.with_cancellation(token).await?)
+    }
+    let token = CancellationToken::new();
+    assert_eq!(job(&token).await.unwrap(), 7);
+    token.cancel();
+    let err = job(&token).await.unwrap_err();
+    assert!(err.is::<Cancelled>());
+    assert_eq!(err.to_string(), "cancelled");
+}
+
+#[tokio::test]
+async fn cancellable_futures_can_be_spawned() {
+    fn spawn<F: Future<Output = u32> + Send + 'static>(future: F, token: &CancellationToken) {
+        let token = token.clone();
+        tokio::spawn(async move {
+            let mut rx = mpsc::channel::<u32>(1).1;
+            let _ = future.with_cancellation(&token).await;
+            let _ = recv_or_cancelled(&mut rx, &token).await;
+        });
+    }
+    let token = CancellationToken::new();
+    spawn(async { 1 }, &token);
+    token.cancel();
+}
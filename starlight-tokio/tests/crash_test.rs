@@ -0,0 +1,73 @@
+use starlight_tokio::{CancellationToken, CrashReport, ServiceManager, service_fn};
+use std::backtrace::BacktraceStatus;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::sleep;
+
+fn recording(reports: &Arc<Mutex<Vec<CrashReport>>>) -> impl Fn(&CrashReport) + Send + Sync + 'static {
+    let reports = reports.clone();
+    move |report| reports.lock().unwrap().push(report.clone())
+}
+
+#[tokio::test(start_paused = true)]
+async fn service_panics_are_reported_in_the_summary_and_to_the_hook() {
+    let reports = Arc::default();
+    let manager = ServiceManager::new()
+        .with_crash_hook(recording(&reports))
+        .with_service(service_fn("ledger", |_| async {
+            sleep(Duration::from_secs(5)).await;
+            panic!("balance went negative");
+        }))
+        .with_service(service_fn("api", |context| async move {
+            context.shutdown().cancelled().await;
+            Ok(())
+        }));
+
+    let shutdown = CancellationToken::new();
+    let (summary, _) = tokio::join!(manager.run(shutdown.clone()), async {
+        sleep(Duration::from_secs(10)).await;
+        shutdown.cancel();
+    });
+
+    let failure = summary.first_failure().unwrap();
+    assert_eq!(failure.name, "ledger");
+    assert_eq!(
+        failure.result.as_ref().unwrap_err().to_string(),
+        "service panicked with message \"balance went negative\""
+    );
+    let crash = failure.crash.as_ref().unwrap();
+    assert_eq!((crash.service.as_str(), crash.task.as_deref()), ("ledger", None));
+    assert_eq!(crash.message, "balance went negative");
+    assert_eq!(crash.uptime, Duration::from_secs(5));
+    assert_eq!(crash.backtrace.status(), BacktraceStatus::Captured);
+    assert!(summary.outcomes.iter().find(|outcome| outcome.name == "api").unwrap().crash.is_none());
+
+    let reports = reports.lock().unwrap();
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].message, "balance went negative");
+}
+
+#[tokio::test(start_paused = true)]
+async fn spawned_task_panics_are_reported_and_still_reach_the_join_handle() {
+    let reports = Arc::default();
+    let manager = ServiceManager::new()
+        .with_crash_hook(recording(&reports))
+        .with_service(service_fn("indexer", |context| async move {
+            let joined = context.spawn_named("compaction", async { panic!("segment corrupted") }).await;
+            assert!(joined.unwrap_err().is_panic());
+            context.shutdown().cancelled().await;
+            Ok(())
+        }));
+
+    let shutdown = CancellationToken::new();
+    let (summary, _) = tokio::join!(manager.run(shutdown.clone()), async {
+        sleep(Duration::from_secs(1)).await;
+        shutdown.cancel();
+    });
+
+    assert!(summary.is_success());
+    let reports = reports.lock().unwrap();
+    assert_eq!(reports.len(), 1);
+    assert_eq!((reports[0].service.as_str(), reports[0].task.as_deref()), ("indexer", Some("compaction")));
+    assert_eq!(reports[0].message, "segment corrupted");
+}
@@ -0,0 +1,124 @@
+use starlight_tokio::{CancellationToken, FailurePolicy, Readiness, ServiceManager, service_fn};
+use std::time::Duration;
+use tokio::time::{Instant, sleep};
+
+fn fails_after(name: &str, after: Duration, error: &'static str) -> starlight_tokio::ServiceFn {
+    service_fn(name, move |context| async move {
+        tokio::select! {
+            _ = sleep(after) => anyhow::bail!(error),
+            _ = context.shutdown().cancelled() => Ok(()),
+        }
+    })
+}
+
+fn runs_until_shutdown(name: &str) -> starlight_tokio::ServiceFn {
+    service_fn(name, |context| async move {
+        context.shutdown().cancelled().await;
+        Ok(())
+    })
+}
+
+#[tokio::test(start_paused = true)]
+async fn service_never_reporting_ready_fails_the_run() {
+    let mut manager = ServiceManager::new();
+    // Waits for a database that never answers.
+    let db = service_fn("db", |context| async move {
+        context.ready().not_ready("connecting");
+        context.shutdown().cancelled().await;
+        Ok(())
+    });
+    manager.register_with("db", db, Vec::<String>::new()).unwrap();
+    manager.register_with("api", runs_until_shutdown("api"), ["db"]).unwrap();
+    let manager = manager
+        .with_startup_timeout("db", Duration::from_secs(5))
+        .unwrap()
+        .with_failure_policy(FailurePolicy::FailFast);
+
+    let started = Instant::now();
+    let summary = manager.run(CancellationToken::new()).await;
+
+    assert_eq!(started.elapsed(), Duration::from_secs(5));
+    assert_eq!(summary.outcomes.len(), 2);
+    let err = summary.into_result().unwrap_err();
+    // The dependent service failing as well, both are reported.
+    assert_eq!(
+        format!("{:#}", err),
+        "2 services failed:\n- db: did not report ready within 5s\n- api: dependency db stopped before becoming ready"
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn fail_fast_cascades_and_reports_the_first_failure() {
+    let manager = ServiceManager::new()
+        .with_failure_policy(FailurePolicy::FailFast)
+        .with_service(runs_until_shutdown("http"))
+        .with_service(fails_after("cache", Duration::from_millis(200), "cache down"))
+        .with_service(fails_after("queue", Duration::from_millis(100), "queue down"))
+        // Fails at the same instant as queue, but registered after it.
+        .with_service(fails_after("search", Duration::from_millis(100), "search down"));
+
+    let started = Instant::now();
+    let summary = manager.run(CancellationToken::new()).await;
+
+    assert_eq!(started.elapsed(), Duration::from_millis(100));
+    let failures: Vec<_> = summary.failures().map(|outcome| outcome.name.as_str()).collect();
+    assert_eq!(failures, ["queue", "search"]);
+    assert_eq!(summary.first_failure().unwrap().name, "queue");
+    assert_eq!(summary.outcomes.len(), 4);
+}
+
+#[tokio::test(start_paused = true)]
+async fn continue_leaves_siblings_running() {
+    let manager = ServiceManager::new()
+        .with_service(runs_until_shutdown("http"))
+        .with_service(fails_after("cache", Duration::from_millis(100), "cache down"));
+
+    let shutdown = CancellationToken::new();
+    let (summary, _) = tokio::join!(manager.run(shutdown.clone()), async {
+        sleep(Duration::from_secs(1)).await;
+        assert_eq!(
+            manager.readiness(),
+            [("http".to_owned(), Readiness::Ready), ("cache".to_owned(), Readiness::Stopped)]
+        );
+        shutdown.cancel();
+    });
+
+    let stopped: Vec<_> = summary.outcomes.iter().map(|outcome| outcome.name.as_str()).collect();
+    assert_eq!(stopped, ["cache", "http"]);
+    assert_eq!(summary.failures().count(), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn fail_fast_group_stops_only_its_group() {
+    let manager = ServiceManager::new()
+        .with_failure_policy(FailurePolicy::FailFastGroup("storage".to_owned()))
+        .with_service(runs_until_shutdown("http"))
+        .with_service(fails_after("primary", Duration::from_millis(100), "primary down"))
+        .with_service(runs_until_shutdown("replica"))
+        .with_service(fails_after("metrics", Duration::from_millis(50), "metrics down"))
+        .with_group("primary", "storage")
+        .unwrap()
+        .with_group("replica", "storage")
+        .unwrap();
+
+    let shutdown = CancellationToken::new();
+    let (summary, _) = tokio::join!(manager.run(shutdown.clone()), async {
+        sleep(Duration::from_secs(1)).await;
+        shutdown.cancel();
+    });
+
+    let stopped: Vec<_> = summary
+        .outcomes
+        .iter()
+        .map(|outcome| (outcome.name.as_str(), outcome.stopped_at.duration_since(summary.outcomes[0].stopped_at)))
+        .collect();
+    assert_eq!(
+        stopped,
+        [
+            ("metrics", Duration::ZERO),
+            ("primary", Duration::from_millis(50)),
+            ("replica", Duration::from_millis(50)),
+            ("http", Duration::from_millis(950)),
+        ]
+    );
+}
@@ -0,0 +1,188 @@
+use starlight_tokio::init::{InitPolicy, retrying};
+use starlight_tokio::{CancellationToken, ExponentialBackoff, Readiness, ServiceManager, probe, service_fn};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::time::sleep;
+
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn policy(initial: Duration, deadline: Duration) -> InitPolicy {
+    InitPolicy::new()
+        .with_backoff(ExponentialBackoff {
+            initial,
+            max: Duration::from_secs(1),
+            multiplier: 2.0,
+            jitter: 0.0,
+        })
+        .with_deadline(deadline)
+}
+
+/// A free port nothing listens on yet.
+fn reserve_addr() -> SocketAddr {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+}
+
+/// Starts accepting on `addr` after `delay`, answering each request with the next of
+/// `statuses`, the last one repeating.
+fn serve_later(addr: SocketAddr, delay: Duration, statuses: &'static [u16]) {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    tokio::spawn(async move {
+        sleep(delay).await;
+        let listener = TcpListener::bind(addr).await.unwrap();
+        for served in 0.. {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf).await;
+            let status = statuses[served.min(statuses.len() - 1)];
+            let response = format!("HTTP/1.1 {} Whatever\r\ncontent-length: 0\r\n\r\n", status);
+            let _ = stream.write_all(response.as_bytes()).await;
+        }
+    });
+}
+
+// Real time: the probes use real sockets.
+#[tokio::test]
+async fn retries_until_the_listener_accepts() {
+    let captured = Captured::default();
+    let writer = captured.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(move || writer.clone())
+        .with_ansi(false)
+        .finish();
+    let _subscriber = tracing::subscriber::set_default(subscriber);
+
+    let addr = reserve_addr();
+    serve_later(addr, Duration::from_millis(150), &[200]);
+    let attempts = AtomicU32::new(0);
+    let policy = policy(Duration::from_millis(20), Duration::from_secs(5));
+    let result = retrying("postgres", &policy, || {
+        attempts.fetch_add(1, Ordering::SeqCst);
+        probe::tcp(addr)
+    })
+    .await;
+
+    assert!(result.is_ok(), "{:?}", result);
+    let attempts = attempts.into_inner();
+    assert!(attempts >= 4, "{}", attempts);
+    let logged = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+    let retries: Vec<&str> = logged.lines().filter(|line| line.contains("retrying")).collect();
+    assert_eq!(retries.len() as u32, attempts - 1, "{}", logged);
+    for (line, (attempt, delay)) in retries.iter().zip([(1, "20ms"), (2, "40ms"), (3, "80ms")]) {
+        assert!(line.contains("WARN") && line.contains("init=\"postgres\""), "{}", line);
+        assert!(line.contains(&format!("attempt={} ", attempt)), "{}", line);
+        assert!(line.contains(&format!("next_delay={}", delay)), "{}", line);
+        assert!(line.contains(&format!("cannot connect to {}", addr)), "{}", line);
+    }
+    assert!(
+        logged
+            .lines()
+            .last()
+            .unwrap()
+            .contains(&format!("initialized init=\"postgres\" attempts={}", attempts))
+    );
+}
+
+#[tokio::test]
+async fn http_probes_expect_the_status() {
+    let addr = reserve_addr();
+    serve_later(addr, Duration::ZERO, &[503, 204]);
+    sleep(Duration::from_millis(20)).await;
+
+    let url = format!("http://{}/health", addr);
+    let err = probe::http(&url, 204).await.unwrap_err();
+    assert_eq!(err.to_string(), format!("{} answered 503, expected 204", url));
+    assert!(probe::http(&url, 204).await.is_ok());
+    assert!(probe::http("https://example.com", 200).await.is_err());
+}
+
+#[tokio::test]
+async fn services_are_ready_once_initialized() {
+    let addr = reserve_addr();
+    serve_later(addr, Duration::from_millis(100), &[503, 200]);
+    let url = format!("http://{}/ready", addr);
+    let service = service_fn("orders", |context| async move {
+        context.shutdown().cancelled().await;
+        Ok(())
+    })
+    .with_init(policy(Duration::from_millis(20), Duration::from_secs(5)), move || {
+        let url = url.clone();
+        async move { probe::http(&url, 200).await }
+    });
+    let manager = Arc::new(ServiceManager::new().with_service(service));
+
+    let shutdown = CancellationToken::new();
+    let run = tokio::spawn({
+        let (manager, shutdown) = (manager.clone(), shutdown.clone());
+        async move { manager.run(shutdown).await }
+    });
+    sleep(Duration::from_millis(50)).await;
+    assert_eq!(manager.readiness(), [("orders".to_owned(), Readiness::Starting)]);
+    manager.wait_ready(Duration::from_secs(5)).await.unwrap();
+
+    shutdown.cancel();
+    assert!(run.await.unwrap().is_success());
+}
+
+#[tokio::test(start_paused = true)]
+async fn gives_up_at_the_deadline() {
+    let policy = policy(Duration::from_millis(100), Duration::from_secs(1));
+    let err = retrying("kafka", &policy, || async {
+        anyhow::bail!("connection refused") as anyhow::Result<()>
+    })
+    .await
+    .unwrap_err();
+
+    // Attempts at 0, 100ms, 300ms and 700ms; the next one would be after the deadline.
+    assert_eq!(err.name, "kafka");
+    assert_eq!(err.attempts, 4);
+    assert_eq!(err.elapsed, Duration::from_millis(700));
+    assert_eq!(err.last_error.to_string(), "connection refused");
+    assert_eq!(
+        err.to_string(),
+        "kafka not initialized after 4 attempts in 700ms: connection refused"
+    );
+
+    let service = service_fn("consumer", |_| async { Ok(()) })
+        .with_init(policy, || async { anyhow::bail!("connection refused") });
+    let summary = ServiceManager::new()
+        .with_service(service)
+        .run(CancellationToken::new())
+        .await;
+    let failure = summary.first_failure().unwrap();
+    assert_eq!(
+        format!("{:#}", failure.result.as_ref().unwrap_err()),
+        "warmup failed: consumer not initialized after 4 attempts in 700ms: connection refused"
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn slow_attempts_are_cut_at_the_deadline() {
+    let policy = policy(Duration::from_millis(100), Duration::from_secs(1));
+    let slow = || async {
+        sleep(Duration::from_secs(60)).await;
+        Ok(())
+    };
+    let err = retrying("slow", &policy, slow).await.unwrap_err();
+    assert_eq!(err.attempts, 1);
+    assert_eq!(err.elapsed, Duration::from_secs(1));
+    assert_eq!(err.last_error.to_string(), "attempt did not finish before the deadline");
+}
@@ -0,0 +1,108 @@
+use starlight_tokio::{CancellationToken, Gated, LocalLeadership, ServiceManager, StarlightServiceV2, service_fn};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+use tokio::time::sleep;
+
+/// Counts its runs and how many of them were cancelled.
+fn scheduler(name: &str, runs: &Arc<AtomicU32>, cancelled: &Arc<AtomicU32>) -> starlight_tokio::ServiceFn {
+    let (runs, cancelled) = (runs.clone(), cancelled.clone());
+    service_fn(name, move |context| {
+        let (runs, cancelled) = (runs.clone(), cancelled.clone());
+        async move {
+            runs.fetch_add(1, Ordering::SeqCst);
+            context.shutdown().cancelled().await;
+            cancelled.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    })
+}
+
+#[tokio::test(start_paused = true)]
+async fn lease_loss_cancels_and_reacquiring_restarts() {
+    let (runs, cancelled) = (Arc::default(), Arc::default());
+    let leadership = LocalLeadership::new();
+    let manager = ServiceManager::new().with_service(Gated::new(scheduler("scheduler", &runs, &cancelled), leadership.clone()));
+
+    let shutdown = CancellationToken::new();
+    let (summary, _) = tokio::join!(manager.run(shutdown.clone()), async {
+        sleep(Duration::from_secs(1)).await;
+        assert!(leadership.is_leader());
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        leadership.revoke();
+        sleep(Duration::from_secs(1)).await;
+        // Cancelled, then started again since nobody else wants the lease.
+        assert_eq!(cancelled.load(Ordering::SeqCst), 1);
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        shutdown.cancel();
+    });
+
+    assert!(summary.is_success());
+    assert_eq!(cancelled.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_service_ignoring_the_loss_is_dropped_after_the_grace_period() {
+    let runs = Arc::new(AtomicU32::new(0));
+    let stubborn = service_fn("stubborn", {
+        let runs = runs.clone();
+        move |context| {
+            let runs = runs.clone();
+            async move {
+                // The first run ignores being cancelled.
+                if runs.fetch_add(1, Ordering::SeqCst) == 0 {
+                    std::future::pending::<()>().await;
+                }
+                context.shutdown().cancelled().await;
+                Ok(())
+            }
+        }
+    });
+    let leadership = LocalLeadership::new();
+    let manager = ServiceManager::new()
+        .with_grace_period(Duration::from_secs(5))
+        .with_service(Gated::new(stubborn, leadership.clone()));
+
+    let shutdown = CancellationToken::new();
+    let (summary, _) = tokio::join!(manager.run(shutdown.clone()), async {
+        sleep(Duration::from_secs(1)).await;
+        leadership.revoke();
+        sleep(Duration::from_secs(4)).await;
+        // Still waiting for the service to pause.
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        sleep(Duration::from_secs(2)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        shutdown.cancel();
+    });
+
+    assert!(summary.is_success());
+}
+
+#[tokio::test(start_paused = true)]
+async fn only_the_leader_runs_and_a_standby_takes_over() {
+    let (runs_a, cancelled_a) = (Arc::default(), Arc::default());
+    let (runs_b, cancelled_b) = (Arc::default(), Arc::default());
+    let leadership = LocalLeadership::new();
+    let replica_a = Arc::new(Gated::new(scheduler("a", &runs_a, &cancelled_a), leadership.clone()));
+    let replica_b = Arc::new(Gated::new(scheduler("b", &runs_b, &cancelled_b), leadership.candidate()));
+
+    let (shutdown_a, shutdown_b) = (CancellationToken::new(), CancellationToken::new());
+    let a = tokio::spawn(replica_a.run(shutdown_a.clone()));
+    sleep(Duration::from_millis(10)).await;
+    let b = tokio::spawn(replica_b.run(shutdown_b.clone()));
+    sleep(Duration::from_secs(1)).await;
+    assert_eq!((runs_a.load(Ordering::SeqCst), runs_b.load(Ordering::SeqCst)), (1, 0));
+
+    // Stopping a releases the lease to b, which was standing by.
+    shutdown_a.cancel();
+    a.await.unwrap().unwrap();
+    sleep(Duration::from_secs(1)).await;
+    assert_eq!(cancelled_a.load(Ordering::SeqCst), 1);
+    assert_eq!(runs_b.load(Ordering::SeqCst), 1);
+
+    shutdown_b.cancel();
+    b.await.unwrap().unwrap();
+    assert_eq!(cancelled_b.load(Ordering::SeqCst), 1);
+    assert!(!leadership.is_leader());
+}
@@ -0,0 +1,114 @@
+use starlight_tokio::{
+    CancellationToken, ExponentialBackoff, LifecycleEvent, LifecycleObserver, RestartPolicy, ServiceManager,
+    StarlightServiceV2, Supervised,
+};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Clone, Default)]
+struct Recording(Arc<Mutex<Vec<String>>>);
+
+impl LifecycleObserver for Recording {
+    fn observe(&self, service: &str, event: &LifecycleEvent<'_>) {
+        let event = match event {
+            LifecycleEvent::Restarting { attempt, error, .. } => {
+                format!("restarting {} after {}", attempt, error.map(ToString::to_string).unwrap_or_default())
+            }
+            LifecycleEvent::Failed(err) => format!("failed: {}", err),
+            event => event.kind().to_owned(),
+        };
+        self.0.lock().unwrap().push(format!("{}: {}", service, event));
+    }
+}
+
+/// Fails its first run.
+struct FailsOnce {
+    runs: AtomicU32,
+}
+
+#[async_trait::async_trait]
+impl StarlightServiceV2 for FailsOnce {
+    async fn run(self: Arc<Self>, _shutdown: CancellationToken) -> anyhow::Result<()> {
+        if self.runs.fetch_add(1, Ordering::SeqCst) == 0 {
+            anyhow::bail!("connection refused");
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "consumer"
+    }
+}
+
+/// Ignores shutdown.
+struct Stubborn;
+
+#[async_trait::async_trait]
+impl StarlightServiceV2 for Stubborn {
+    async fn run(self: Arc<Self>, _shutdown: CancellationToken) -> anyhow::Result<()> {
+        std::future::pending().await
+    }
+
+    fn name(&self) -> &str {
+        "stubborn"
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn restarted_service_events() {
+    let recording = Recording::default();
+    let policy = RestartPolicy::OnFailure {
+        max_retries: 3,
+        backoff: ExponentialBackoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(1),
+            multiplier: 2.0,
+            jitter: 0.0,
+        },
+    };
+    let manager = ServiceManager::new()
+        .with_observer(recording.clone())
+        .with_service(Supervised::new(FailsOnce { runs: AtomicU32::new(0) }, policy));
+
+    assert!(manager.run(CancellationToken::new()).await.is_success());
+
+    assert_eq!(
+        *recording.0.lock().unwrap(),
+        [
+            "consumer: started",
+            "consumer: warming_up",
+            "consumer: warmed_up",
+            "consumer: restarting 1 after connection refused",
+            "consumer: completed",
+        ]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn failed_and_aborted_service_events() {
+    let recording = Recording::default();
+    let manager = ServiceManager::new()
+        .with_observer(recording.clone())
+        .with_service(FailsOnce { runs: AtomicU32::new(0) })
+        .with_service(Stubborn)
+        .with_fail_fast(true)
+        .with_phase_timeout(0, Duration::from_secs(1));
+
+    let summary = manager.run(CancellationToken::new()).await;
+
+    assert_eq!(summary.failures().count(), 2);
+    assert_eq!(
+        *recording.0.lock().unwrap(),
+        [
+            "consumer: started",
+            "stubborn: started",
+            "consumer: warming_up",
+            "consumer: warmed_up",
+            "stubborn: warming_up",
+            "stubborn: warmed_up",
+            "consumer: failed: connection refused",
+            "stubborn: aborted",
+        ]
+    );
+}
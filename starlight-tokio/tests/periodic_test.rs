@@ -0,0 +1,93 @@
+use starlight_tokio::{CancellationToken, CronService, IntervalService, Overlap, StarlightServiceV2};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+use tokio::time::{Instant, sleep};
+
+fn counting(count: &Arc<AtomicU32>, takes: Duration) -> impl Fn() -> std::pin::Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>> + Send + Sync + 'static {
+    let count = count.clone();
+    move || {
+        let count = count.clone();
+        Box::pin(async move {
+            count.fetch_add(1, Ordering::SeqCst);
+            sleep(takes).await;
+            Ok(())
+        })
+    }
+}
+
+/// Runs `service` for `duration` of (paused) time and returns how long shutdown took.
+async fn run_for<S: StarlightServiceV2>(service: Arc<S>, duration: Duration) -> anyhow::Result<Duration> {
+    let shutdown = CancellationToken::new();
+    let run = tokio::spawn(service.run(shutdown.clone()));
+    sleep(duration).await;
+    let stopping = Instant::now();
+    shutdown.cancel();
+    run.await.unwrap()?;
+    Ok(stopping.elapsed())
+}
+
+#[tokio::test(start_paused = true)]
+async fn interval_ticks_every_period() {
+    let count = Arc::new(AtomicU32::new(0));
+    let service = IntervalService::new("tick", Duration::from_secs(10), Duration::ZERO, counting(&count, Duration::ZERO));
+    run_for(Arc::new(service), Duration::from_secs(35)).await.unwrap();
+    assert_eq!(count.load(Ordering::SeqCst), 3);
+
+    let count = Arc::new(AtomicU32::new(0));
+    let service = IntervalService::new("tick", Duration::from_secs(10), Duration::ZERO, counting(&count, Duration::ZERO))
+        .with_run_on_start(true);
+    run_for(Arc::new(service), Duration::from_secs(35)).await.unwrap();
+    assert_eq!(count.load(Ordering::SeqCst), 4);
+}
+
+#[tokio::test(start_paused = true)]
+async fn overlapping_runs_are_skipped_or_queued() {
+    let count = Arc::new(AtomicU32::new(0));
+    let service = IntervalService::new("slow", Duration::from_secs(10), Duration::ZERO, counting(&count, Duration::from_secs(25)));
+    let stats = service.stats();
+    run_for(Arc::new(service), Duration::from_secs(65)).await.unwrap();
+    // Runs start at 10s and 40s; 20s, 30s, 50s and 60s are skipped.
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+    assert_eq!(stats.skipped(), 4);
+
+    let count = Arc::new(AtomicU32::new(0));
+    let service = IntervalService::new("slow", Duration::from_secs(10), Duration::ZERO, counting(&count, Duration::from_secs(25)))
+        .with_overlap(Overlap::Queue);
+    run_for(Arc::new(service), Duration::from_secs(65)).await.unwrap();
+    // Back to back from 10s: 10s, 35s and 60s.
+    assert_eq!(count.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test(start_paused = true)]
+async fn shutdown_is_prompt_mid_wait() {
+    let count = Arc::new(AtomicU32::new(0));
+    let service = IntervalService::new("hourly", Duration::from_secs(3600), Duration::ZERO, counting(&count, Duration::ZERO));
+    let took = run_for(Arc::new(service), Duration::from_secs(60)).await.unwrap();
+    assert_eq!(took, Duration::ZERO);
+    assert_eq!(count.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test(start_paused = true)]
+async fn failures_are_counted_unless_fail_fast() {
+    let failing = || async { anyhow::bail!("upstream down") };
+    let service = IntervalService::new("failing", Duration::from_secs(1), Duration::ZERO, failing);
+    let stats = service.stats();
+    run_for(Arc::new(service), Duration::from_millis(5500)).await.unwrap();
+    assert_eq!(stats.runs(), 5);
+    assert_eq!(stats.failures(), 5);
+
+    let service = IntervalService::new("failing", Duration::from_secs(1), Duration::ZERO, failing).with_fail_fast(true);
+    let err = run_for(Arc::new(service), Duration::from_millis(5500)).await.unwrap_err();
+    assert_eq!(err.to_string(), "upstream down");
+}
+
+#[tokio::test(start_paused = true)]
+async fn cron_follows_the_schedule() {
+    let count = Arc::new(AtomicU32::new(0));
+    let service = CronService::new("every-10s", "*/10 * * * * *", counting(&count, Duration::ZERO)).unwrap();
+    run_for(Arc::new(service), Duration::from_secs(60)).await.unwrap();
+    assert_eq!(count.load(Ordering::SeqCst), 6);
+
+    assert!(CronService::new("bad", "*/10 * * *", counting(&count, Duration::ZERO)).is_err());
+}
@@ -0,0 +1,112 @@
+use starlight_tokio::tokio::sync::mpsc::error::TrySendError;
+use starlight_tokio::{CancellationToken, QueueService, ServiceManager, StarlightServiceV2};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::{sleep, timeout};
+
+/// Handlers taking `takes` each, tracking how many run at once.
+fn tracked(takes: Duration, current: &Arc<AtomicU32>, max: &Arc<AtomicU32>) -> QueueService<u32> {
+    let (current, max) = (current.clone(), max.clone());
+    QueueService::new("jobs", 100, 3, move |_: u32| {
+        let (current, max) = (current.clone(), max.clone());
+        async move {
+            let running = current.fetch_add(1, Ordering::SeqCst) + 1;
+            max.fetch_max(running, Ordering::SeqCst);
+            sleep(takes).await;
+            current.fetch_sub(1, Ordering::SeqCst);
+            Ok(())
+        }
+    })
+}
+
+#[tokio::test(start_paused = true)]
+async fn senders_wait_on_a_full_queue() {
+    let service = Arc::new(QueueService::new("jobs", 2, 1, |_: u32| async { Ok(()) }));
+    let sender = service.sender();
+    sender.try_send(0).unwrap();
+    sender.try_send(1).unwrap();
+    assert!(matches!(sender.try_send(2), Err(TrySendError::Full(2))));
+    assert!(timeout(Duration::from_secs(1), sender.send(2)).await.is_err());
+
+    let shutdown = CancellationToken::new();
+    let run = tokio::spawn(service.clone().run(shutdown.clone()));
+    timeout(Duration::from_secs(1), sender.send(2)).await.unwrap().unwrap();
+    sleep(Duration::from_secs(1)).await;
+    shutdown.cancel();
+    run.await.unwrap().unwrap();
+
+    assert_eq!(service.stats().processed(), 3);
+    // Closed for good.
+    assert!(sender.send(3).await.is_err());
+}
+
+#[tokio::test(start_paused = true)]
+async fn at_most_concurrency_handlers_run_at_once() {
+    let (current, max) = (Arc::default(), Arc::default());
+    let service = Arc::new(tracked(Duration::from_secs(1), &current, &max));
+    let sender = service.sender();
+    for item in 0..10 {
+        sender.send(item).await.unwrap();
+    }
+
+    let shutdown = CancellationToken::new();
+    let run = tokio::spawn(service.clone().run(shutdown.clone()));
+    sleep(Duration::from_secs(10)).await;
+    shutdown.cancel();
+    run.await.unwrap().unwrap();
+
+    assert_eq!(max.load(Ordering::SeqCst), 3);
+    assert_eq!(service.stats().processed(), 10);
+}
+
+#[tokio::test(start_paused = true)]
+async fn shutdown_drains_within_the_grace_period_and_counts_the_rest() {
+    let service = QueueService::new("jobs", 10, 1, |_: u32| async {
+        sleep(Duration::from_secs(10)).await;
+        Ok(())
+    });
+    let (sender, stats) = (service.sender(), service.stats());
+    for item in 0..5 {
+        sender.send(item).await.unwrap();
+    }
+    let manager = ServiceManager::new().with_grace_period(Duration::from_secs(25)).with_service(service);
+
+    let shutdown = CancellationToken::new();
+    let (summary, _) = tokio::join!(manager.run(shutdown.clone()), async {
+        sleep(Duration::from_secs(1)).await;
+        shutdown.cancel();
+    });
+
+    assert!(summary.is_success());
+    // Items finish at 10s and 20s; the one in flight at 26s and the two queued are dropped.
+    assert_eq!((stats.processed(), stats.dropped()), (2, 3));
+}
+
+#[tokio::test(start_paused = true)]
+async fn failed_items_go_to_the_dead_letter() {
+    let dead = Arc::new(Mutex::new(Vec::new()));
+    let service = QueueService::new("jobs", 10, 2, |item: u32| async move {
+        anyhow::ensure!(item.is_multiple_of(2), "odd item {}", item);
+        Ok(())
+    })
+    .with_dead_letter({
+        let dead = dead.clone();
+        move |item, err| dead.lock().unwrap().push((item, err.to_string()))
+    });
+    let service = Arc::new(service);
+    for item in 0..4 {
+        service.sender().send(item).await.unwrap();
+    }
+
+    let shutdown = CancellationToken::new();
+    let run = tokio::spawn(service.clone().run(shutdown.clone()));
+    sleep(Duration::from_secs(1)).await;
+    shutdown.cancel();
+    run.await.unwrap().unwrap();
+
+    let mut dead = dead.lock().unwrap().clone();
+    dead.sort();
+    assert_eq!(dead, [(1, "odd item 1".to_owned()), (3, "odd item 3".to_owned())]);
+    assert_eq!((service.stats().processed(), service.stats().failed()), (2, 2));
+}
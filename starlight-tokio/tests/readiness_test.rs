@@ -0,0 +1,137 @@
+use starlight_tokio::{CancellationToken, Readiness, RegisterError, ServiceContext, ServiceManager, StarlightServiceV2};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+struct Migrator {
+    done: Arc<AtomicBool>,
+}
+
+#[async_trait::async_trait]
+impl StarlightServiceV2 for Migrator {
+    async fn run(self: Arc<Self>, shutdown: CancellationToken) -> anyhow::Result<()> {
+        self.run_with_context(ServiceContext::detached("migrator", shutdown)).await
+    }
+
+    async fn run_with_context(self: Arc<Self>, context: ServiceContext) -> anyhow::Result<()> {
+        context.ready().not_ready("applying migrations");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        self.done.store(true, Ordering::SeqCst);
+        context.ready().ready();
+        context.shutdown().cancelled().await;
+        Ok(())
+    }
+}
+
+struct Http {
+    migrated: Arc<AtomicBool>,
+    saw_migrations: Arc<AtomicBool>,
+}
+
+#[async_trait::async_trait]
+impl StarlightServiceV2 for Http {
+    async fn run(self: Arc<Self>, shutdown: CancellationToken) -> anyhow::Result<()> {
+        self.saw_migrations.store(self.migrated.load(Ordering::SeqCst), Ordering::SeqCst);
+        shutdown.cancelled().await;
+        Ok(())
+    }
+}
+
+/// Never reports ready.
+struct Stuck;
+
+#[async_trait::async_trait]
+impl StarlightServiceV2 for Stuck {
+    async fn run(self: Arc<Self>, shutdown: CancellationToken) -> anyhow::Result<()> {
+        shutdown.cancelled().await;
+        Ok(())
+    }
+
+    async fn run_with_context(self: Arc<Self>, context: ServiceContext) -> anyhow::Result<()> {
+        context.ready().not_ready("waiting for broker");
+        self.run(context.shutdown().clone()).await
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn dependents_start_after_their_dependencies_are_ready() {
+    let migrated = Arc::new(AtomicBool::new(false));
+    let saw_migrations = Arc::new(AtomicBool::new(false));
+    let mut manager = ServiceManager::new();
+    // Registered before its dependency.
+    manager
+        .register_with("http", Http { migrated: migrated.clone(), saw_migrations: saw_migrations.clone() }, ["migrator"])
+        .unwrap();
+    manager.register_with("migrator", Migrator { done: migrated }, Vec::<String>::new()).unwrap();
+
+    let shutdown = CancellationToken::new();
+    let (summary, ready) = tokio::join!(manager.run(shutdown.clone()), async {
+        let ready = manager.wait_ready(Duration::from_secs(5)).await;
+        shutdown.cancel();
+        ready
+    });
+
+    ready.unwrap();
+    assert!(summary.is_success());
+    assert!(saw_migrations.load(Ordering::SeqCst));
+}
+
+#[tokio::test(start_paused = true)]
+async fn wait_ready_times_out_listing_unready_services() {
+    let mut manager = ServiceManager::new();
+    manager.register_with("consumer", Stuck, Vec::<String>::new()).unwrap();
+    manager.register_with("api", Stuck, ["consumer"]).unwrap();
+
+    let shutdown = CancellationToken::new();
+    let (_, ready) = tokio::join!(manager.run(shutdown.clone()), async {
+        let ready = manager.wait_ready(Duration::from_secs(1)).await;
+        shutdown.cancel();
+        ready
+    });
+
+    let err = ready.unwrap_err();
+    let not_ready: Vec<(&str, String)> = err.sources().map(|(service, error)| (service, error.to_string())).collect();
+    assert_eq!(
+        not_ready,
+        [
+            ("consumer", Readiness::NotReady("waiting for broker".to_owned()).to_string()),
+            ("api", Readiness::Starting.to_string()),
+        ]
+    );
+    assert_eq!(
+        err.to_string(),
+        "2 services failed:\n- consumer: not ready: waiting for broker\n- api: starting"
+    );
+}
+
+#[test]
+fn dependency_cycles_are_rejected() {
+    let mut manager = ServiceManager::new();
+    manager.register_with("a", Stuck, ["b"]).unwrap();
+    manager.register_with("b", Stuck, ["c"]).unwrap();
+
+    let err = manager.register_with("c", Stuck, ["a"]).unwrap_err();
+    assert_eq!(err, RegisterError::DependencyCycle(vec!["c".into(), "a".into(), "b".into(), "c".into()]));
+    assert_eq!(manager.register_with("a", Stuck, ["x"]).unwrap_err(), RegisterError::DuplicateService("a".into()));
+    assert!(matches!(manager.register_with("d", Stuck, ["d"]), Err(RegisterError::DependencyCycle(_))));
+    manager.register_with("c", Stuck, Vec::<String>::new()).unwrap();
+}
+
+#[test]
+fn settings_for_unknown_services_are_rejected() {
+    let mut manager = ServiceManager::new();
+    manager.register_with("api", Stuck, Vec::<String>::new()).unwrap();
+
+    let err = manager.with_startup_timeout("apu", Duration::from_secs(5)).unwrap_err();
+    assert_eq!(err, RegisterError::UnknownService("apu".into()));
+    assert_eq!(err.to_string(), "service apu is not registered");
+}
+
+#[test]
+fn duplicate_services_are_rejected() {
+    let manager = ServiceManager::new().with_service(Stuck);
+    let name = Stuck.name().to_owned();
+
+    let err = manager.try_with_service(Stuck).unwrap_err();
+    assert_eq!(err, RegisterError::DuplicateService(name));
+}
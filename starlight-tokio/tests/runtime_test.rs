@@ -0,0 +1,55 @@
+use starlight_tokio::runtime::Builder;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn worker_threads_are_named() {
+    let runtime = Builder::new().with_worker_threads(2).build().unwrap();
+    let names = runtime.block_on(async {
+        let mut names = Vec::new();
+        for _ in 0..8 {
+            let name = tokio::spawn(async { std::thread::current().name().map(str::to_owned) });
+            names.push(name.await.unwrap().unwrap());
+        }
+        names
+    });
+
+    for name in names {
+        assert!(name == "starlight-worker-0" || name == "starlight-worker-1", "{}", name);
+    }
+}
+
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn panic_hook_logs_spawned_task_panics() {
+    let captured = Captured::default();
+    let writer = captured.clone();
+    // The panic happens on a worker thread, so the subscriber must be global.
+    tracing_subscriber::fmt()
+        .with_writer(move || writer.clone())
+        .with_ansi(false)
+        .init();
+
+    let runtime = Builder::new().with_worker_threads(1).build().unwrap();
+    let joined = runtime.block_on(runtime.spawn(async { panic!("invariant violated") }));
+    assert!(joined.unwrap_err().is_panic());
+
+    let logged = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+    assert!(logged.contains("ERROR"), "{}", logged);
+    assert!(logged.contains("panicked: invariant violated"), "{}", logged);
+    assert!(logged.contains("thread=\"starlight-worker-0\""), "{}", logged);
+    assert!(logged.contains("panic.location=\"starlight-tokio/tests/runtime_test.rs:"), "{}", logged);
+    assert!(logged.contains("backtrace="), "{}", logged);
+}
@@ -0,0 +1,73 @@
+use starlight_tokio::tokio::time::Instant;
+use starlight_tokio::{CancellationToken, ServiceContext, ServiceManager, StarlightServiceV2};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Spawns a handler per entry of `drains`, each taking that long to drain once cancelled,
+/// and returns as soon as shutdown starts.
+struct Acceptor {
+    drains: Vec<Duration>,
+    drained: Arc<Mutex<Vec<usize>>>,
+}
+
+#[async_trait::async_trait]
+impl StarlightServiceV2 for Acceptor {
+    async fn run(self: Arc<Self>, shutdown: CancellationToken) -> anyhow::Result<()> {
+        self.run_with_context(ServiceContext::detached("acceptor", shutdown)).await
+    }
+
+    async fn run_with_context(self: Arc<Self>, context: ServiceContext) -> anyhow::Result<()> {
+        for (i, drain) in self.drains.iter().copied().enumerate() {
+            let shutdown = context.shutdown().clone();
+            let drained = self.drained.clone();
+            context.spawn_named(&format!("connection-{}", i), async move {
+                shutdown.cancelled().await;
+                tokio::time::sleep(drain).await;
+                drained.lock().unwrap().push(i);
+            });
+        }
+        context.ready().ready();
+        context.shutdown().cancelled().await;
+        Ok(())
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn manager_waits_for_spawned_tasks() {
+    let drained = Arc::new(Mutex::new(Vec::new()));
+    let ms = Duration::from_millis;
+    let manager = ServiceManager::new().with_service(Acceptor {
+        drains: vec![ms(300), ms(100), ms(200)],
+        drained: drained.clone(),
+    });
+
+    let shutdown = CancellationToken::new();
+    let started = Instant::now();
+    let (summary, _) = tokio::join!(manager.run(shutdown.clone()), async {
+        manager.wait_ready(Duration::from_secs(1)).await.unwrap();
+        shutdown.cancel();
+    });
+
+    assert!(summary.is_success());
+    assert_eq!(*drained.lock().unwrap(), [1, 2, 0]);
+    assert_eq!(started.elapsed(), ms(300));
+}
+
+#[tokio::test(start_paused = true)]
+async fn grace_period_bounds_the_wait() {
+    let drained = Arc::new(Mutex::new(Vec::new()));
+    let manager = ServiceManager::new()
+        .with_grace_period(Duration::from_secs(1))
+        .with_service(Acceptor {
+            drains: vec![Duration::from_millis(10), Duration::from_secs(3600)],
+            drained: drained.clone(),
+        });
+
+    let shutdown = CancellationToken::new();
+    shutdown.cancel();
+    let started = Instant::now();
+    manager.run(shutdown).await;
+
+    assert_eq!(started.elapsed(), Duration::from_secs(1));
+    assert_eq!(*drained.lock().unwrap(), [0]);
+}
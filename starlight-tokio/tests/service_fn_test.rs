@@ -0,0 +1,130 @@
+use starlight_tokio::{
+    CancellationToken, ExponentialBackoff, Readiness, RestartPolicy, ServiceManager, oneshot_service, service_fn,
+};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::Duration;
+use tokio::sync::{Mutex, mpsc};
+
+#[tokio::test(start_paused = true)]
+async fn closure_pipeline_runs_and_shuts_down() {
+    let migrated = Arc::new(AtomicBool::new(false));
+    let consumed = Arc::new(AtomicU32::new(0));
+    let (tx, rx) = mpsc::channel::<u32>(16);
+    let rx = Arc::new(Mutex::new(rx));
+
+    let mut manager = ServiceManager::new();
+    let migrate = oneshot_service("migrate", {
+        let migrated = migrated.clone();
+        move |_context| {
+            let migrated = migrated.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                migrated.store(true, Ordering::SeqCst);
+                Ok(())
+            }
+        }
+    });
+    let produce = service_fn("produce", move |context| {
+        let tx = tx.clone();
+        async move {
+            let mut ticks = tokio::time::interval(Duration::from_millis(10));
+            loop {
+                tokio::select! {
+                    _ = ticks.tick() => tx.send(1).await?,
+                    _ = context.shutdown().cancelled() => return Ok(()),
+                }
+            }
+        }
+    });
+    // Stopped after the producer, draining what it sent.
+    let consume = service_fn("consume", {
+        let consumed = consumed.clone();
+        move |context| {
+            let (rx, consumed) = (rx.clone(), consumed.clone());
+            async move {
+                let mut rx = rx.lock().await;
+                loop {
+                    tokio::select! {
+                        Some(n) = rx.recv() => consumed.fetch_add(n, Ordering::SeqCst),
+                        _ = context.shutdown().cancelled() => break,
+                    };
+                }
+                while let Ok(n) = rx.try_recv() {
+                    consumed.fetch_add(n, Ordering::SeqCst);
+                }
+                Ok(())
+            }
+        }
+    })
+    .with_phase(1);
+    manager.register_with("migrate", migrate, Vec::<String>::new()).unwrap();
+    manager.register_with("produce", produce, ["migrate"]).unwrap();
+    manager.register_with("consume", consume, ["migrate"]).unwrap();
+
+    let shutdown = CancellationToken::new();
+    let (summary, _) = tokio::join!(manager.run(shutdown.clone()), async {
+        manager.wait_ready(Duration::from_secs(1)).await.unwrap();
+        assert!(migrated.load(Ordering::SeqCst));
+        tokio::time::sleep(Duration::from_millis(95)).await;
+        shutdown.cancel();
+    });
+
+    assert!(summary.is_success(), "{:?}", summary.failures().collect::<Vec<_>>());
+    assert_eq!(summary.outcomes.len(), 3);
+    assert_eq!(consumed.load(Ordering::SeqCst), 10);
+    assert_eq!(manager.readiness()[0], ("migrate".to_owned(), Readiness::Done));
+}
+
+#[tokio::test(start_paused = true)]
+async fn oneshot_is_not_cancelled_on_shutdown() {
+    let finished = Arc::new(AtomicBool::new(false));
+    let manager = ServiceManager::new().with_service(oneshot_service("backfill", {
+        let finished = finished.clone();
+        move |context| {
+            let finished = finished.clone();
+            async move {
+                assert!(!context.shutdown().is_cancelled());
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                finished.store(true, Ordering::SeqCst);
+                Ok(())
+            }
+        }
+    }));
+
+    let shutdown = CancellationToken::new();
+    shutdown.cancel();
+    assert!(manager.run(shutdown).await.is_success());
+    assert!(finished.load(Ordering::SeqCst));
+}
+
+#[tokio::test(start_paused = true)]
+async fn oneshot_with_restart_retries_until_done() {
+    let attempts = Arc::new(AtomicU32::new(0));
+    let backfill = oneshot_service("backfill", {
+        let attempts = attempts.clone();
+        move |_context| {
+            let attempts = attempts.clone();
+            async move {
+                if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                    anyhow::bail!("lock timeout");
+                }
+                Ok(())
+            }
+        }
+    })
+    .with_restart(RestartPolicy::OnFailure {
+        max_retries: 1,
+        backoff: ExponentialBackoff {
+            initial: Duration::from_millis(10),
+            max: Duration::from_millis(10),
+            multiplier: 1.0,
+            jitter: 0.0,
+        },
+    });
+    let manager = ServiceManager::new().with_service(backfill);
+
+    assert!(manager.run(CancellationToken::new()).await.is_success());
+    assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    assert_eq!(manager.readiness()[0].1, Readiness::Done);
+}
@@ -0,0 +1,93 @@
+use starlight_tokio::tokio::sync::watch;
+use starlight_tokio::tokio::task::JoinHandle;
+use starlight_tokio::{CancellationToken, ServiceManager, StarlightService, StarlightServiceV2};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+struct Failing;
+
+#[async_trait::async_trait]
+impl StarlightServiceV2 for Failing {
+    async fn run(self: Arc<Self>, _shutdown: CancellationToken) -> anyhow::Result<()> {
+        anyhow::bail!("could not bind")
+    }
+
+    fn name(&self) -> &str {
+        "failing"
+    }
+}
+
+struct Worker;
+
+#[async_trait::async_trait]
+impl StarlightServiceV2 for Worker {
+    async fn run(self: Arc<Self>, shutdown: CancellationToken) -> anyhow::Result<()> {
+        shutdown.cancelled().await;
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "worker"
+    }
+}
+
+struct OldWorker(Arc<AtomicBool>);
+
+impl StarlightService for OldWorker {
+    fn run(&self, _shutdown_tx: Arc<watch::Sender<bool>>, mut shutdown_rx: watch::Receiver<bool>) -> JoinHandle<()> {
+        let stopped = self.0.clone();
+        tokio::spawn(async move {
+            let _ = shutdown_rx.wait_for(|stop| *stop).await;
+            stopped.store(true, Ordering::SeqCst);
+        })
+    }
+}
+
+#[tokio::test]
+async fn failure_is_reported_without_stopping_siblings() {
+    let shutdown = CancellationToken::new();
+    let manager = Arc::new(ServiceManager::new().with_service(Failing).with_service(Worker));
+    let run = tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move { manager.run(shutdown).await }
+    });
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!run.is_finished(), "the worker should still be running");
+
+    shutdown.cancel();
+    let summary = run.await.unwrap();
+    assert!(!summary.is_success());
+    let failures: Vec<_> = summary.failures().collect();
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].name, "failing");
+    assert_eq!(failures[0].result.as_ref().unwrap_err().to_string(), "could not bind");
+}
+
+#[tokio::test]
+async fn fail_fast_cancels_siblings() {
+    let stopped = Arc::new(AtomicBool::new(false));
+    let manager = ServiceManager::new()
+        .with_fail_fast(true)
+        .with_service(Failing)
+        .with_service(Worker)
+        .with_legacy_service("old-worker", OldWorker(stopped.clone()));
+    let summary = tokio::time::timeout(Duration::from_secs(1), manager.run(CancellationToken::new()))
+        .await
+        .expect("siblings should be cancelled");
+
+    assert_eq!(summary.outcomes.len(), 3);
+    assert_eq!(summary.failures().count(), 1);
+    assert!(stopped.load(Ordering::SeqCst));
+}
+
+#[tokio::test]
+async fn new_services_run_through_the_old_trait() {
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let shutdown_tx = Arc::new(shutdown_tx);
+    let handle = StarlightService::run(&Arc::new(Worker), shutdown_tx.clone(), shutdown_rx);
+
+    shutdown_tx.send(true).unwrap();
+    tokio::time::timeout(Duration::from_secs(1), handle).await.unwrap().unwrap();
+}
@@ -0,0 +1,104 @@
+use starlight_tokio::tokio::time::Instant;
+use starlight_tokio::{CancellationToken, ServiceManager, StarlightServiceV2};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+type Finished = Arc<Mutex<Vec<(&'static str, Instant)>>>;
+
+/// Takes `drain` to stop after cancellation, then records when it finished.
+struct Draining {
+    name: &'static str,
+    drain: Duration,
+    finished: Finished,
+}
+
+#[async_trait::async_trait]
+impl StarlightServiceV2 for Draining {
+    async fn run(self: Arc<Self>, shutdown: CancellationToken) -> anyhow::Result<()> {
+        shutdown.cancelled().await;
+        tokio::time::sleep(self.drain).await;
+        self.finished.lock().unwrap().push((self.name, Instant::now()));
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        self.name
+    }
+}
+
+fn draining(name: &'static str, drain_ms: u64, finished: &Finished) -> Draining {
+    Draining {
+        name,
+        drain: Duration::from_millis(drain_ms),
+        finished: finished.clone(),
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn phases_stop_in_order() {
+    let finished = Finished::default();
+    // Later phases drain faster, so only the phase ordering keeps them last.
+    let manager = ServiceManager::new()
+        .with_service(draining("pools", 10, &finished))
+        .with_service(draining("workers", 50, &finished))
+        .with_service(draining("http", 100, &finished))
+        .with_shutdown_phase("http", 0)
+        .unwrap()
+        .with_shutdown_phase("workers", 1)
+        .unwrap()
+        .with_shutdown_phase("pools", 2)
+        .unwrap();
+
+    let shutdown = CancellationToken::new();
+    shutdown.cancel();
+    let started = Instant::now();
+    let summary = manager.run(shutdown).await;
+    assert!(summary.is_success());
+
+    let finished = finished.lock().unwrap();
+    let order: Vec<_> = finished.iter().map(|(name, _)| *name).collect();
+    assert_eq!(order, ["http", "workers", "pools"]);
+    let elapsed: Vec<_> = finished.iter().map(|(_, at)| at.duration_since(started).as_millis()).collect();
+    assert_eq!(elapsed, [100, 150, 160]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn single_phase_stops_concurrently() {
+    let finished = Finished::default();
+    let manager = ServiceManager::new()
+        .with_service(draining("http", 100, &finished))
+        .with_service(draining("workers", 50, &finished));
+
+    let shutdown = CancellationToken::new();
+    shutdown.cancel();
+    let started = Instant::now();
+    manager.run(shutdown).await;
+    assert_eq!(started.elapsed(), Duration::from_millis(100));
+    assert_eq!(finished.lock().unwrap()[0].0, "workers");
+}
+
+#[tokio::test(start_paused = true)]
+async fn phase_timeout_aborts_stragglers() {
+    let finished = Finished::default();
+    let manager = ServiceManager::new()
+        .with_service(draining("http", 10_000, &finished))
+        .with_service(draining("pools", 10, &finished))
+        .with_shutdown_phase("pools", 1)
+        .unwrap()
+        .with_phase_timeout(0, Duration::from_millis(200));
+
+    let shutdown = CancellationToken::new();
+    shutdown.cancel();
+    let started = Instant::now();
+    let summary = manager.run(shutdown).await;
+
+    let finished = finished.lock().unwrap();
+    assert_eq!(finished.len(), 1);
+    assert_eq!(finished[0].0, "pools");
+    assert_eq!(finished[0].1.duration_since(started), Duration::from_millis(210));
+
+    let failures: Vec<_> = summary.failures().collect();
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].name, "http");
+    assert_eq!(failures[0].result.as_ref().unwrap_err().to_string(), "did not stop in time");
+}
@@ -0,0 +1,15 @@
+#![cfg(unix)]
+
+use starlight_tokio::shutdown_signal;
+use std::time::Duration;
+
+#[tokio::test]
+async fn sigterm_cancels_the_token() {
+    let token = shutdown_signal();
+    assert!(!token.is_cancelled());
+
+    unsafe { libc::kill(libc::getpid(), libc::SIGTERM) };
+    tokio::time::timeout(Duration::from_secs(5), token.cancelled())
+        .await
+        .expect("token should be cancelled by SIGTERM");
+}
@@ -0,0 +1,116 @@
+use starlight_tokio::{CancellationToken, ExponentialBackoff, RestartPolicy, StarlightServiceV2, Supervised};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Fails (alternately by error and by panic) on its first `failures` runs, staying up
+/// for `uptime` each time.
+struct Flaky {
+    runs: AtomicU32,
+    failures: u32,
+    uptime: Duration,
+}
+
+impl Flaky {
+    fn new(failures: u32) -> Self {
+        Flaky {
+            runs: AtomicU32::new(0),
+            failures,
+            uptime: Duration::ZERO,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl StarlightServiceV2 for Flaky {
+    async fn run(self: Arc<Self>, _shutdown: CancellationToken) -> anyhow::Result<()> {
+        let run = self.runs.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(self.uptime).await;
+        match run {
+            run if run >= self.failures => Ok(()),
+            run if run % 2 == 1 => panic!("consumer crashed"),
+            _ => anyhow::bail!("broker unavailable"),
+        }
+    }
+}
+
+fn on_failure(max_retries: u32) -> RestartPolicy {
+    RestartPolicy::OnFailure {
+        max_retries,
+        backoff: ExponentialBackoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_millis(350),
+            multiplier: 2.0,
+            jitter: 0.0,
+        },
+    }
+}
+
+fn supervise(service: Flaky, policy: RestartPolicy, delays: &Arc<Mutex<Vec<(u32, Duration)>>>) -> Arc<Supervised<Flaky>> {
+    let delays = delays.clone();
+    Arc::new(Supervised::new(service, policy).with_on_restart(move |event| {
+        delays.lock().unwrap().push((event.attempt, event.delay));
+    }))
+}
+
+#[tokio::test(start_paused = true)]
+async fn restarts_with_backoff_until_success() {
+    let delays = Arc::default();
+    let supervised = supervise(Flaky::new(3), on_failure(5), &delays);
+
+    supervised.run(CancellationToken::new()).await.unwrap();
+
+    let ms = Duration::from_millis;
+    assert_eq!(*delays.lock().unwrap(), vec![(1, ms(100)), (2, ms(200)), (3, ms(350))]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn gives_up_after_max_retries() {
+    let delays = Arc::default();
+    let supervised = supervise(Flaky::new(10), on_failure(2), &delays);
+
+    let err = supervised.run(CancellationToken::new()).await.unwrap_err();
+    assert_eq!(err.to_string(), "gave up after 2 restarts");
+    assert_eq!(delays.lock().unwrap().len(), 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn healthy_uptime_resets_the_retry_counter() {
+    let restarts = Arc::new(AtomicU32::new(0));
+    let service = Flaky {
+        uptime: Duration::from_secs(2),
+        ..Flaky::new(4)
+    };
+    let supervised = Arc::new(
+        Supervised::new(service, on_failure(1))
+            .with_healthy_uptime(Duration::from_secs(1))
+            .with_on_restart({
+                let restarts = restarts.clone();
+                move |event| {
+                    assert_eq!(event.attempt, 1);
+                    restarts.fetch_add(1, Ordering::SeqCst);
+                }
+            }),
+    );
+
+    supervised.run(CancellationToken::new()).await.unwrap();
+    assert_eq!(restarts.load(Ordering::SeqCst), 4);
+}
+
+#[tokio::test(start_paused = true)]
+async fn shutdown_interrupts_the_backoff() {
+    let shutdown = CancellationToken::new();
+    let supervised = Arc::new(Supervised::new(
+        Flaky::new(u32::MAX),
+        RestartPolicy::Always {
+            backoff: ExponentialBackoff {
+                initial: Duration::from_secs(60),
+                ..ExponentialBackoff::default()
+            },
+        },
+    ));
+    let run = tokio::spawn(supervised.run(shutdown.clone()));
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    shutdown.cancel();
+    run.await.unwrap().unwrap();
+}
@@ -0,0 +1,132 @@
+use starlight_tokio::{
+    CancellationToken, LifecycleEvent, LifecycleObserver, Readiness, ServiceContext, ServiceManager, StarlightServiceV2,
+    service_fn,
+};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::{Instant, sleep};
+
+#[derive(Clone, Default)]
+struct Recording(Arc<Mutex<Vec<String>>>);
+
+impl LifecycleObserver for Recording {
+    fn observe(&self, service: &str, event: &LifecycleEvent<'_>) {
+        let event = match event {
+            LifecycleEvent::WarmedUp { elapsed } => format!("warmed up in {:?}", elapsed),
+            LifecycleEvent::PreStop { delay } => format!("stopping in {:?}", delay),
+            event => event.kind().to_owned(),
+        };
+        self.0.lock().unwrap().push(format!("{}: {}", service, event));
+    }
+}
+
+/// Fills its cache for `takes` before serving.
+struct Cache {
+    takes: Duration,
+}
+
+#[async_trait::async_trait]
+impl StarlightServiceV2 for Cache {
+    async fn run(self: Arc<Self>, shutdown: CancellationToken) -> anyhow::Result<()> {
+        shutdown.cancelled().await;
+        Ok(())
+    }
+
+    async fn warmup(&self) -> anyhow::Result<()> {
+        sleep(self.takes).await;
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "cache"
+    }
+}
+
+/// Records when its shutdown token was cancelled.
+fn api(cancelled_at: &Arc<Mutex<Option<Instant>>>) -> starlight_tokio::ServiceFn {
+    let cancelled_at = cancelled_at.clone();
+    service_fn("api", move |context: ServiceContext| {
+        let cancelled_at = cancelled_at.clone();
+        async move {
+            context.shutdown().cancelled().await;
+            *cancelled_at.lock().unwrap() = Some(Instant::now());
+            Ok(())
+        }
+    })
+}
+
+#[tokio::test(start_paused = true)]
+async fn services_are_ready_only_after_warming_up() {
+    let recording = Recording::default();
+    let cancelled_at = Arc::default();
+    let mut manager = ServiceManager::new().with_observer(recording.clone());
+    manager.register_with("cache", Cache { takes: Duration::from_secs(5) }, Vec::<String>::new()).unwrap();
+    manager.register_with("api", api(&cancelled_at), ["cache"]).unwrap();
+    let manager = Arc::new(manager);
+
+    let shutdown = CancellationToken::new();
+    let run = tokio::spawn({
+        let (manager, shutdown) = (manager.clone(), shutdown.clone());
+        async move { manager.run(shutdown).await }
+    });
+    sleep(Duration::from_secs(4)).await;
+    assert_eq!(manager.readiness(), [("cache".to_owned(), Readiness::Starting), ("api".to_owned(), Readiness::Starting)]);
+    sleep(Duration::from_secs(2)).await;
+    assert_eq!(manager.readiness(), [("cache".to_owned(), Readiness::Ready), ("api".to_owned(), Readiness::Ready)]);
+
+    shutdown.cancel();
+    assert!(run.await.unwrap().is_success());
+    assert_eq!(
+        recording.0.lock().unwrap()[..6],
+        [
+            "cache: started",
+            "api: started",
+            "cache: warming_up",
+            "cache: warmed up in 5s",
+            "api: warming_up",
+            "api: warmed up in 0ns",
+        ]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn slow_warmups_fail_the_service() {
+    let manager = ServiceManager::new()
+        .with_service(Cache { takes: Duration::from_secs(60) })
+        .with_warmup_timeout("cache", Duration::from_secs(10))
+        .unwrap();
+
+    let summary = manager.run(CancellationToken::new()).await;
+
+    let failure = summary.first_failure().unwrap();
+    assert_eq!(format!("{:#}", failure.result.as_ref().unwrap_err()), "warmup failed: did not finish within 10s");
+    assert_eq!(failure.stopped_at.elapsed(), Duration::ZERO);
+}
+
+#[tokio::test(start_paused = true)]
+async fn pre_stop_delay_reports_not_ready_before_cancelling() {
+    let recording = Recording::default();
+    let cancelled_at = Arc::default();
+    let manager = Arc::new(
+        ServiceManager::new()
+            .with_observer(recording.clone())
+            .with_pre_stop_delay(Duration::from_secs(10))
+            .with_service(api(&cancelled_at)),
+    );
+
+    let shutdown = CancellationToken::new();
+    let run = tokio::spawn({
+        let (manager, shutdown) = (manager.clone(), shutdown.clone());
+        async move { manager.run(shutdown).await }
+    });
+    sleep(Duration::from_secs(1)).await;
+    let stopping = Instant::now();
+    shutdown.cancel();
+    sleep(Duration::from_secs(5)).await;
+    assert_eq!(manager.readiness(), [("api".to_owned(), Readiness::NotReady("stopping".to_owned()))]);
+    assert!(cancelled_at.lock().unwrap().is_none());
+
+    assert!(run.await.unwrap().is_success());
+    assert_eq!(cancelled_at.lock().unwrap().unwrap() - stopping, Duration::from_secs(10));
+    assert!(recording.0.lock().unwrap().contains(&"api: stopping in 10s".to_owned()));
+}
@@ -0,0 +1,122 @@
+use starlight_tokio::tokio::time::Instant;
+use starlight_tokio::{
+    CancellationToken, ExponentialBackoff, RestartPolicy, ServiceContext, ServiceManager, StarlightServiceV2, Supervised,
+    WatchdogPolicy,
+};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+/// Beats every `every`. With `hang_after`, its first run stops beating (and ignores
+/// shutdown) after that long.
+struct Worker {
+    runs: Arc<AtomicU32>,
+    every: Duration,
+    hang_after: Option<Duration>,
+}
+
+impl Worker {
+    fn new(runs: &Arc<AtomicU32>, every: Duration, hang_after: Option<Duration>) -> Self {
+        Worker {
+            runs: runs.clone(),
+            every,
+            hang_after,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl StarlightServiceV2 for Worker {
+    async fn run(self: Arc<Self>, shutdown: CancellationToken) -> anyhow::Result<()> {
+        self.run_with_context(ServiceContext::detached("worker", shutdown)).await
+    }
+
+    async fn run_with_context(self: Arc<Self>, context: ServiceContext) -> anyhow::Result<()> {
+        let first = self.runs.fetch_add(1, Ordering::SeqCst) == 0;
+        context.ready().ready();
+        let started = Instant::now();
+        loop {
+            if first && self.hang_after.is_some_and(|hang_after| started.elapsed() >= hang_after) {
+                // Stuck: ignores shutdown too.
+                std::future::pending::<()>().await;
+            }
+            context.heartbeat().beat();
+            tokio::select! {
+                _ = tokio::time::sleep(self.every) => {}
+                _ = context.shutdown().cancelled() => return Ok(()),
+            }
+        }
+    }
+
+    fn name(&self) -> &str {
+        "worker"
+    }
+}
+
+fn always() -> RestartPolicy {
+    RestartPolicy::Always {
+        backoff: ExponentialBackoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(1),
+            multiplier: 2.0,
+            jitter: 0.0,
+        },
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn beating_services_are_left_alone() {
+    let runs = Arc::default();
+    let worker = Worker::new(&runs, Duration::from_secs(1), None);
+    let manager = ServiceManager::new()
+        .with_service(Supervised::new(worker, always()))
+        .with_watchdog("worker", Duration::from_secs(5), WatchdogPolicy::Restart)
+        .unwrap();
+
+    let shutdown = CancellationToken::new();
+    let (summary, _) = tokio::join!(manager.run(shutdown.clone()), async {
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        shutdown.cancel();
+    });
+
+    assert!(summary.is_success());
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn stalled_supervised_services_are_restarted() {
+    let runs = Arc::default();
+    let worker = Worker::new(&runs, Duration::from_secs(1), Some(Duration::from_secs(10)));
+    let manager = ServiceManager::new()
+        .with_service(Supervised::new(worker, always()))
+        .with_watchdog("worker", Duration::from_secs(5), WatchdogPolicy::Restart)
+        .unwrap();
+
+    let shutdown = CancellationToken::new();
+    let (summary, _) = tokio::join!(manager.run(shutdown.clone()), async {
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        shutdown.cancel();
+    });
+
+    assert!(summary.is_success());
+    assert_eq!(runs.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn shutdown_policy_stops_everything() {
+    let runs = Arc::default();
+    let worker = Worker::new(&runs, Duration::from_secs(1), Some(Duration::from_secs(10)));
+    let manager = ServiceManager::new()
+        .with_service(worker)
+        .with_watchdog("worker", Duration::from_secs(5), WatchdogPolicy::Shutdown)
+        .unwrap()
+        .with_phase_timeout(0, Duration::from_secs(1));
+
+    let started = Instant::now();
+    let summary = manager.run(CancellationToken::new()).await;
+
+    // Last beat at 9s, flagged within a quarter interval of 14s, then a 1s phase timeout.
+    assert!(!summary.is_success());
+    assert!(started.elapsed() > Duration::from_secs(14) && started.elapsed() < Duration::from_secs(17));
+    assert_eq!(summary.failures().next().unwrap().name, "worker");
+}
@@ -0,0 +1,219 @@
+//! Generates the numbering plan tables of `phone::data` from `data/numbering_plan.csv`
+//! and `data/areas.csv`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+
+const SOURCE: &str = "data/numbering_plan.csv";
+const AREAS: &str = "data/areas.csv";
+
+struct Country {
+    iso: String,
+    calling_code: String,
+    main: bool,
+    trunk_prefix: Option<String>,
+    national_lengths: Vec<usize>,
+    mobile_prefixes: Vec<String>,
+    landline_prefixes: Vec<String>,
+    format_groups: Vec<usize>,
+}
+
+struct Area {
+    calling_code: String,
+    prefix: String,
+    iso: String,
+    label: String,
+    population: u32,
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed={SOURCE}");
+    println!("cargo:rerun-if-changed={AREAS}");
+    let source = std::fs::read_to_string(SOURCE).unwrap_or_else(|err| panic!("cannot read {SOURCE}: {err}"));
+    let (version, countries) = parse(&source).unwrap_or_else(|err| panic!("{SOURCE}: {err}"));
+    let areas = std::fs::read_to_string(AREAS).unwrap_or_else(|err| panic!("cannot read {AREAS}: {err}"));
+    let areas = parse_areas(&areas, &countries).unwrap_or_else(|err| panic!("{AREAS}: {err}"));
+    let out = Path::new(&std::env::var_os("OUT_DIR").expect("cargo sets OUT_DIR")).join("phone_data.rs");
+    std::fs::write(&out, generate(&version, &countries, &areas)).expect("the generated tables are written");
+}
+
+fn parse(source: &str) -> Result<(String, Vec<Country>), String> {
+    let mut version = None;
+    let mut header = false;
+    let mut countries = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if let Some(comment) = line.strip_prefix('#') {
+            if let Some(value) = comment.trim().strip_prefix("version:") {
+                version = Some(value.trim().to_owned());
+            }
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+        if !header {
+            header = true;
+            continue;
+        }
+        let country = parse_row(line).map_err(|err| format!("line {}: {}", index + 1, err))?;
+        countries.push(country);
+    }
+    let version = version
+        .filter(|version| !version.is_empty())
+        .ok_or("missing `# version:` line")?;
+    Ok((version, countries))
+}
+
+fn parse_row(line: &str) -> Result<Country, String> {
+    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+    let [iso, calling_code, main, trunk_prefix, lengths, mobile, landline, groups] = fields[..] else {
+        return Err(format!("expected 8 fields, found {}", fields.len()));
+    };
+    let digits = |field: &str| -> Result<Vec<String>, String> {
+        field
+            .split_whitespace()
+            .map(|prefix| match prefix.bytes().all(|b| b.is_ascii_digit()) {
+                true => Ok(prefix.to_owned()),
+                false => Err(format!("`{prefix}` is not digits")),
+            })
+            .collect()
+    };
+    let numbers = |field: &str| -> Result<Vec<usize>, String> {
+        field
+            .split_whitespace()
+            .map(|n| n.parse().map_err(|_| format!("`{n}` is not a number")))
+            .collect()
+    };
+    if !matches!(main, "" | "main") {
+        return Err(format!("`main` is `main` or empty, not `{main}`"));
+    }
+    Ok(Country {
+        iso: iso.to_owned(),
+        calling_code: digits(calling_code)?.concat(),
+        main: main == "main",
+        trunk_prefix: Some(digits(trunk_prefix)?.concat()).filter(|trunk| !trunk.is_empty()),
+        national_lengths: numbers(lengths)?,
+        mobile_prefixes: digits(mobile)?,
+        landline_prefixes: digits(landline)?,
+        format_groups: numbers(groups)?,
+    })
+}
+
+/// The areas, sorted by calling code and prefix. Each area is in a country of its calling
+/// code, and no prefix is listed twice.
+fn parse_areas(source: &str, countries: &[Country]) -> Result<Vec<Area>, String> {
+    let mut areas = Vec::new();
+    let rows = source
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .skip(1);
+    for (index, line) in rows {
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let [calling_code, prefix, iso, label, population] = fields[..] else {
+            return Err(format!("line {}: expected 5 fields, found {}", index + 1, fields.len()));
+        };
+        if !countries
+            .iter()
+            .any(|country| country.iso == iso && country.calling_code == calling_code)
+        {
+            return Err(format!("line {}: {iso} with calling code {calling_code} is not in {SOURCE}", index + 1));
+        }
+        if prefix.is_empty() || !prefix.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!("line {}: prefix `{prefix}` is not digits", index + 1));
+        }
+        areas.push(Area {
+            calling_code: calling_code.to_owned(),
+            prefix: prefix.to_owned(),
+            iso: iso.to_owned(),
+            label: label.to_owned(),
+            population: population
+                .parse()
+                .map_err(|_| format!("line {}: `{population}` is not a number", index + 1))?,
+        });
+    }
+    areas.sort_by(|a, b| (&a.calling_code, &a.prefix).cmp(&(&b.calling_code, &b.prefix)));
+    if let Some(pair) = areas
+        .windows(2)
+        .find(|pair| (&pair[0].calling_code, &pair[0].prefix) == (&pair[1].calling_code, &pair[1].prefix))
+    {
+        return Err(format!("+{} {} is listed twice", pair[0].calling_code, pair[0].prefix));
+    }
+    Ok(areas)
+}
+
+/// The calling codes and the country each resolves to: the only one with the code, or
+/// the one marked `main`.
+fn main_countries(countries: &[Country]) -> BTreeMap<&str, &str> {
+    let mut by_code: BTreeMap<&str, Vec<&Country>> = BTreeMap::new();
+    for country in countries {
+        by_code.entry(&country.calling_code).or_default().push(country);
+    }
+    by_code
+        .into_iter()
+        .map(|(code, sharing)| {
+            let main = match sharing[..] {
+                [only] => only,
+                _ => match sharing.iter().filter(|country| country.main).collect::<Vec<_>>()[..] {
+                    [main] => main,
+                    _ => panic!("{SOURCE}: calling code {code} needs exactly one `main` country"),
+                },
+            };
+            (code, main.iso.as_str())
+        })
+        .collect()
+}
+
+fn generate(version: &str, countries: &[Country], areas: &[Area]) -> String {
+    let mut out = String::new();
+    writeln!(out, "// Generated by build.rs from {SOURCE}; edit that file instead.").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "/// Version of the numbering plan data, from `{SOURCE}`.").unwrap();
+    writeln!(out, "pub const DATA_VERSION: &str = {version:?};").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "/// The supported countries, by ISO code.").unwrap();
+    writeln!(out, "pub static COUNTRIES: &[CountryMetadata] = &[").unwrap();
+    for country in countries {
+        writeln!(
+            out,
+            "    CountryMetadata {{ iso: {:?}, calling_code: {:?}, trunk_prefix: {:?}, national_lengths: &{:?}, \
+             mobile_prefixes: &{:?}, landline_prefixes: &{:?}, format_groups: &{:?} }},",
+            country.iso,
+            country.calling_code,
+            country.trunk_prefix,
+            country.national_lengths,
+            country.mobile_prefixes,
+            country.landline_prefixes,
+            country.format_groups,
+        )
+        .unwrap();
+    }
+    writeln!(out, "];").unwrap();
+    writeln!(out).unwrap();
+    writeln!(
+        out,
+        "/// Calling codes and the ISO code of the country each resolves to, by code."
+    )
+    .unwrap();
+    writeln!(out, "pub static CALLING_CODES: &[(&str, &str)] = &[").unwrap();
+    for (code, iso) in main_countries(countries) {
+        writeln!(out, "    ({code:?}, {iso:?}),").unwrap();
+    }
+    writeln!(out, "];").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "/// Geographic areas by calling code and national prefix, from `{AREAS}`.").unwrap();
+    writeln!(out, "pub static AREAS: &[AreaMetadata] = &[").unwrap();
+    for area in areas {
+        writeln!(
+            out,
+            "    AreaMetadata {{ calling_code: {:?}, prefix: {:?}, iso: {:?}, label: {:?}, population: {} }},",
+            area.calling_code, area.prefix, area.iso, area.label, area.population,
+        )
+        .unwrap();
+    }
+    writeln!(out, "];").unwrap();
+    out
+}
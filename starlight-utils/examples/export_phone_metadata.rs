@@ -0,0 +1,18 @@
+//! Writes the phone metadata for the frontend build:
+//!
+//! ```sh
+//! cargo run -p starlight-utils --features metadata-export --example export_phone_metadata -- phone-metadata.json
+//! ```
+//!
+//! Prints it to stdout without a path.
+
+fn main() -> std::io::Result<()> {
+    let json = starlight_utils::phone::metadata::export_json();
+    match std::env::args_os().nth(1) {
+        Some(path) => std::fs::write(path, json),
+        None => {
+            print!("{json}");
+            Ok(())
+        }
+    }
+}
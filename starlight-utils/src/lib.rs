@@ -1,7 +1,8 @@
 pub mod phone;
 
 pub use phone::{
-    detect_country, is_valid_e164, normalize_phone, normalize_vn_phone, PhoneNumber,
+    detect_country, is_valid_e164, normalize_phone, normalize_vn_phone, region_bucket_from_e164, AsYouTypeFormatter,
+    PhoneKey, PhoneNumber, RegionBucket,
 };
 
 #[cfg(test)]
@@ -43,6 +44,53 @@ mod tests {
         assert_eq!(detect_country(&intl.e164).unwrap(), "VN");
     }
 
+    #[test]
+    fn differently_formatted_duplicates_are_one_number() {
+        use std::collections::HashSet;
+
+        let inputs = ["+84912345678", "0912345678", "0084 912 345 678", "+84 912-345-678"];
+        let numbers: HashSet<PhoneNumber> = inputs
+            .iter()
+            .map(|input| normalize_phone(input, "VN").unwrap())
+            .collect();
+        assert_eq!(numbers.len(), 1);
+
+        let keys: HashSet<PhoneKey> = inputs
+            .iter()
+            .map(|input| PhoneKey::from(&normalize_phone(input, "VN").unwrap()))
+            .collect();
+        assert_eq!(keys.len(), 1);
+
+        let other = normalize_phone("0912345679", "VN").unwrap();
+        assert!(!numbers.contains(&other));
+        assert!(!keys.contains(&PhoneKey::from(&other)));
+    }
+
+    #[test]
+    fn phone_key_is_compact_and_keeps_leading_zeros() {
+        fn assert_copy<T: Copy>() {}
+        assert_copy::<PhoneKey>();
+        assert_eq!(std::mem::size_of::<PhoneKey>(), 16);
+
+        let number = PhoneNumber {
+            raw: "+39 06 1234 5678".to_string(),
+            e164: "+390612345678".to_string(),
+            country_code: "39".to_string(),
+            national_number: "0612345678".to_string(),
+            iso_country: Some("IT"),
+        };
+        let key = PhoneKey::from(&number);
+        assert_eq!(key.country_code(), 39);
+        assert_eq!(key.national_number(), "0612345678");
+
+        let without_zero = PhoneNumber {
+            national_number: "612345678".to_string(),
+            ..number.clone()
+        };
+        assert_ne!(key, PhoneKey::from(&without_zero));
+        assert!(number < without_zero);
+    }
+
     #[test]
     fn rejects_invalid_numbers() {
         // Not a number
@@ -1,15 +1,34 @@
-/// Simple phone normalization utilities without external dependencies.
-///
-/// Main goals:
-/// - Normalize phone numbers to E.164: +<country_code><national_number>
-/// - Handle common user input variants (spaces, dashes, parentheses)
-/// - Understand Vietnamese numbers well, and provide a generic path for other countries
-/// - Detect country from E.164 (best-effort for common country codes)
-///
-/// Note: This is a lightweight heuristic implementation. It does not fully validate
-/// numbering plans for all countries.
+//! Simple phone normalization utilities without external dependencies.
+//!
+//! Main goals:
+//! - Normalize phone numbers to E.164: +<country_code><national_number>
+//! - Handle common user input variants (spaces, dashes, parentheses)
+//! - Understand Vietnamese numbers well, and provide a generic path for other countries
+//! - Detect country from E.164 (best-effort for common country codes)
+//!
+//! Note: This is a lightweight heuristic implementation. It does not fully validate
+//! numbering plans for all countries.
+
+mod as_you_type;
+pub mod data;
+mod format;
+#[cfg(feature = "i18n")]
+mod i18n;
+pub mod metadata;
+mod region;
+
+pub use as_you_type::AsYouTypeFormatter;
+pub use region::{Granularity, RegionBucket, region_bucket_from_e164, region_bucket_from_e164_with};
+
+/// The version of the numbering plan data this build normalizes with, see [`data`].
+pub fn data_version() -> &'static str {
+    data::DATA_VERSION
+}
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Equality, hashing and ordering only consider `country_code` and `national_number`,
+/// so differently formatted inputs of the same number are the same key in a `HashSet`
+/// or `BTreeMap`; `raw` is ignored, and `e164` and `iso_country` follow from the two.
+#[derive(Debug, Clone)]
 pub struct PhoneNumber {
     /// Original input
     pub raw: String,
@@ -23,6 +42,81 @@ pub struct PhoneNumber {
     pub iso_country: Option<&'static str>,
 }
 
+impl PhoneNumber {
+    fn key(&self) -> (&str, &str) {
+        (&self.country_code, &self.national_number)
+    }
+}
+
+impl PartialEq for PhoneNumber {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for PhoneNumber {}
+
+impl std::hash::Hash for PhoneNumber {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
+}
+
+impl PartialOrd for PhoneNumber {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PhoneNumber {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+/// A compact, `Copy` key of a [`PhoneNumber`] for sets of millions of numbers: 16 bytes
+/// instead of four strings. Equal exactly when the numbers are.
+///
+/// The national number is kept as an integer together with its digit count, so a
+/// leading zero is not lost.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PhoneKey {
+    /// The calling code in the low 32 bits, the national number's digit count above.
+    country: u64,
+    national: u64,
+}
+
+impl PhoneKey {
+    pub fn country_code(&self) -> u32 {
+        self.country as u32
+    }
+
+    /// The national significant number, with its leading zeros.
+    pub fn national_number(&self) -> String {
+        let digits = (self.country >> 32) as usize;
+        format!("{:0digits$}", self.national)
+    }
+}
+
+impl From<&PhoneNumber> for PhoneKey {
+    fn from(number: &PhoneNumber) -> Self {
+        let country_code = digits_to_u64(&number.country_code);
+        let digits = number.national_number.bytes().filter(u8::is_ascii_digit).count() as u64;
+        PhoneKey {
+            country: (digits << 32) | (country_code & u64::from(u32::MAX)),
+            national: digits_to_u64(&number.national_number),
+        }
+    }
+}
+
+/// E.164 numbers have at most 15 digits, which fit.
+fn digits_to_u64(digits: &str) -> u64 {
+    digits
+        .bytes()
+        .filter(u8::is_ascii_digit)
+        .fold(0u64, |n, digit| n.wrapping_mul(10).wrapping_add(u64::from(digit - b'0')))
+}
+
 /// Normalize a phone number into E.164 using a default country hint.
 /// The default_country can be:
 /// - ISO code like "VN", "US", "SG"
@@ -118,14 +212,8 @@ pub fn detect_country(e164: &str) -> Option<&'static str> {
     if !is_valid_e164(e164) {
         return None;
     }
-    let digits = &e164[1..];
-    let (cc, iso) = match_country_code_prefix(digits)?;
-    if iso.is_some() {
-        iso
-    } else {
-        // If we don't have ISO mapping, still return something meaningful if possible.
-        code_to_iso(cc)
-    }
+    let (_, iso) = match_country_code_prefix(&e164[1..])?;
+    iso
 }
 
 /// Check whether a string is a valid E.164 representation (syntax only).
@@ -162,52 +250,22 @@ fn resolve_country_hint(hint: &str) -> Option<(&'static str, Option<&'static str
     if up.starts_with('+') && up[1..].chars().all(|c| c.is_ascii_digit()) {
         let code = &up[1..];
         if !code.is_empty() && code.len() <= 3 {
-            return Some((code_to_cow_static(code)?, code_to_iso(code)));
+            return data::main_country(code).map(|(code, iso)| (code, Some(iso)));
         }
         return None;
     }
 
     // Accept "84" forms
     if up.chars().all(|c| c.is_ascii_digit()) && !up.is_empty() && up.len() <= 3 {
-        return Some((code_to_cow_static(&up)?, code_to_iso(&up)));
+        return data::main_country(&up).map(|(code, iso)| (code, Some(iso)));
     }
 
     // Accept ISO alpha-2 forms
-    match up.as_str() {
-        "VN" => Some(("84", Some("VN"))),
-        "US" => Some(("1", Some("US"))),   // ambiguous (US/CA); treat as US by default
-        "CA" => Some(("1", Some("CA"))),
-        "SG" => Some(("65", Some("SG"))),
-        "TH" => Some(("66", Some("TH"))),
-        "CN" => Some(("86", Some("CN"))),
-        "JP" => Some(("81", Some("JP"))),
-        "KR" => Some(("82", Some("KR"))),
-        "GB" => Some(("44", Some("GB"))),
-        "DE" => Some(("49", Some("DE"))),
-        "FR" => Some(("33", Some("FR"))),
-        "AU" => Some(("61", Some("AU"))),
-        "NZ" => Some(("64", Some("NZ"))),
-        "MY" => Some(("60", Some("MY"))),
-        "ID" => Some(("62", Some("ID"))),
-        "PH" => Some(("63", Some("PH"))),
-        "ES" => Some(("34", Some("ES"))),
-        "IT" => Some(("39", Some("IT"))),
-        "RU" => Some(("7", Some("RU"))),
-        "BR" => Some(("55", Some("BR"))),
-        "MX" => Some(("52", Some("MX"))),
-        "IN" => Some(("91", Some("IN"))),
-        "HK" => Some(("852", Some("HK"))),
-        "MO" => Some(("853", Some("MO"))),
-        "TW" => Some(("886", Some("TW"))),
-        _ => None,
-    }
+    metadata::lookup(&up).map(|country| (country.calling_code, Some(country.iso)))
 }
 
 fn is_trunk_zero_country(iso: &str) -> bool {
-    matches!(
-        iso,
-        "VN" | "GB" | "DE" | "FR" | "IT" | "TH" | "MY" | "ID" | "JP" | "KR"
-    )
+    metadata::lookup(iso).is_some_and(|country| country.trunk_prefix == Some("0"))
 }
 
 /// Given digits after '+', find the longest matching country calling code and ISO if known.
@@ -217,83 +275,9 @@ fn match_country_code_prefix(digits_after_plus: &str) -> Option<(&'static str, O
         if digits_after_plus.len() < len {
             continue;
         }
-        let cand = &digits_after_plus[..len];
-        if let Some(iso) = code_to_iso(cand) {
-            return Some((code_to_cow_static(cand)?, Some(iso)));
-        }
-        // Even if ISO unknown, if it's a plausible code from our list, accept it
-        if is_known_code(cand) {
-            return Some((code_to_cow_static(cand)?, None));
+        if let Some((code, iso)) = data::main_country(&digits_after_plus[..len]) {
+            return Some((code, Some(iso)));
         }
     }
     None
 }
-
-fn is_known_code(code: &str) -> bool {
-    matches!(
-        code,
-        "1" | "7" | "33" | "34" | "39" | "44" | "49" | "52" | "55" | "60" | "61" | "62" | "63"
-            | "64" | "65" | "66" | "81" | "82" | "84" | "86" | "852" | "853" | "886" | "91"
-    )
-}
-
-fn code_to_iso(code: &str) -> Option<&'static str> {
-    match code {
-        "84" => Some("VN"),
-        "1" => Some("US"),   // could also be CA et al; simplified
-        "44" => Some("GB"),
-        "49" => Some("DE"),
-        "33" => Some("FR"),
-        "81" => Some("JP"),
-        "82" => Some("KR"),
-        "65" => Some("SG"),
-        "66" => Some("TH"),
-        "86" => Some("CN"),
-        "852" => Some("HK"),
-        "853" => Some("MO"),
-        "886" => Some("TW"),
-        "62" => Some("ID"),
-        "60" => Some("MY"),
-        "63" => Some("PH"),
-        "61" => Some("AU"),
-        "64" => Some("NZ"),
-        "34" => Some("ES"),
-        "39" => Some("IT"),
-        "7" => Some("RU"),
-        "55" => Some("BR"),
-        "52" => Some("MX"),
-        "91" => Some("IN"),
-        _ => None,
-    }
-}
-
-fn code_to_cow_static(code: &str) -> Option<&'static str> {
-    // Lift numeric literals into 'static; we only support up to 3 digits.
-    match code {
-        "1" => Some("1"),
-        "7" => Some("7"),
-        "33" => Some("33"),
-        "34" => Some("34"),
-        "39" => Some("39"),
-        "44" => Some("44"),
-        "49" => Some("49"),
-        "52" => Some("52"),
-        "55" => Some("55"),
-        "60" => Some("60"),
-        "61" => Some("61"),
-        "62" => Some("62"),
-        "63" => Some("63"),
-        "64" => Some("64"),
-        "65" => Some("65"),
-        "66" => Some("66"),
-        "81" => Some("81"),
-        "82" => Some("82"),
-        "84" => Some("84"),
-        "86" => Some("86"),
-        "852" => Some("852"),
-        "853" => Some("853"),
-        "886" => Some("886"),
-        "91" => Some("91"),
-        _ => None,
-    }
-}
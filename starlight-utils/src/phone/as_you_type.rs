@@ -0,0 +1,265 @@
+//! Formatting a phone number while it is typed, with the digit groups of
+//! [`CountryMetadata::format_groups`], as phone inputs of the frontends do.
+
+use super::metadata::{self, CountryMetadata};
+use super::{PhoneNumber, match_country_code_prefix, normalize_phone, resolve_country_hint};
+
+/// Formats a phone number one character at a time:
+///
+/// ```
+/// use starlight_utils::phone::AsYouTypeFormatter;
+///
+/// let mut formatter = AsYouTypeFormatter::new("VN");
+/// for ch in "091234".chars() {
+///     formatter.push(ch);
+/// }
+/// assert_eq!(formatter.push('5'), "0912 345");
+/// assert_eq!(formatter.backspace(), "0912 34");
+/// ```
+///
+/// Numbers are national ones of the default country, keeping its trunk prefix in front
+/// of the first group, until a `+` is typed or pasted: the formatter then starts over
+/// with an international number, grouped once its calling code is known. Separators
+/// and other characters are ignored. Input longer than any number of the country is
+/// shown as typed, without groups.
+#[derive(Debug, Clone)]
+pub struct AsYouTypeFormatter {
+    default_country: String,
+    national: Option<&'static CountryMetadata>,
+    /// The digits typed, after a `+` in international mode.
+    input: String,
+    formatted: String,
+}
+
+impl AsYouTypeFormatter {
+    /// `default_country` is an ISO code like `VN` or a calling code like `84` or `+84`,
+    /// as for [`normalize_phone`].
+    pub fn new(default_country: &str) -> Self {
+        let national = resolve_country_hint(default_country)
+            .and_then(|(_, iso)| iso)
+            .and_then(metadata::lookup);
+        AsYouTypeFormatter {
+            default_country: default_country.to_owned(),
+            national,
+            input: String::new(),
+            formatted: String::new(),
+        }
+    }
+
+    /// Adds a typed character and returns the number formatted so far.
+    pub fn push(&mut self, ch: char) -> &str {
+        match ch {
+            '0'..='9' => self.input.push(ch),
+            // A `+` only starts a number, so one after digits begins a new international
+            // number, as when a full number is pasted over a partial one.
+            '+' if !self.input.starts_with('+') => {
+                self.input.clear();
+                self.input.push('+');
+            }
+            _ => return &self.formatted,
+        }
+        self.reformat()
+    }
+
+    /// Removes the last digit, or the `+` of an empty international number.
+    pub fn backspace(&mut self) -> &str {
+        self.input.pop();
+        self.reformat()
+    }
+
+    /// Forgets the input.
+    pub fn clear(&mut self) {
+        self.input.clear();
+        self.formatted.clear();
+    }
+
+    /// The number formatted so far.
+    pub fn formatted(&self) -> &str {
+        &self.formatted
+    }
+
+    /// The number once the input is a complete, valid one: its national number has one
+    /// of the country's lengths and a known mobile or fixed-line prefix.
+    pub fn current(&self) -> Option<PhoneNumber> {
+        let number = normalize_phone(&self.input, &self.default_country)?;
+        let country = number.iso_country.and_then(metadata::lookup)?;
+        let nsn = &number.national_number;
+        let known_prefix = |prefixes: &[&str]| prefixes.iter().any(|prefix| nsn.starts_with(prefix));
+        let prefixed = (country.mobile_prefixes.is_empty() && country.landline_prefixes.is_empty())
+            || known_prefix(country.mobile_prefixes)
+            || known_prefix(country.landline_prefixes);
+        (country.national_lengths.contains(&nsn.len()) && prefixed).then_some(number)
+    }
+
+    fn reformat(&mut self) -> &str {
+        self.formatted = match self.input.strip_prefix('+') {
+            Some(digits) => format_international(digits),
+            None => match self.national {
+                Some(country) => format_national(&self.input, country),
+                None => self.input.clone(),
+            },
+        };
+        &self.formatted
+    }
+}
+
+fn format_international(digits: &str) -> String {
+    let country = match_country_code_prefix(digits).and_then(|(code, iso)| Some((code, metadata::lookup(iso?)?)));
+    match country {
+        Some((code, country)) if digits.len() > code.len() => {
+            format!("+{} {}", code, group(&digits[code.len()..], country))
+        }
+        _ => format!("+{}", digits),
+    }
+}
+
+fn format_national(digits: &str, country: &CountryMetadata) -> String {
+    let trunk = country
+        .trunk_prefix
+        .filter(|trunk| digits.starts_with(trunk))
+        .unwrap_or("");
+    format!("{}{}", trunk, group(&digits[trunk.len()..], country))
+}
+
+/// `nsn` in the country's groups, the last one taking the digits beyond them.
+pub(super) fn group(nsn: &str, country: &CountryMetadata) -> String {
+    let longest = country.national_lengths.iter().copied().max().unwrap_or(0);
+    if nsn.len() > longest {
+        return nsn.to_owned();
+    }
+    let mut grouped = String::with_capacity(nsn.len() + country.format_groups.len());
+    let mut rest = nsn;
+    for (index, &size) in country.format_groups.iter().enumerate() {
+        if rest.is_empty() {
+            break;
+        }
+        let last = index + 1 == country.format_groups.len();
+        let (head, tail) = rest.split_at(if last { rest.len() } else { size.min(rest.len()) });
+        if !grouped.is_empty() {
+            grouped.push(' ');
+        }
+        grouped.push_str(head);
+        rest = tail;
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The formatted string after each character of `input`.
+    fn typed(formatter: &mut AsYouTypeFormatter, input: &str) -> Vec<String> {
+        input.chars().map(|ch| formatter.push(ch).to_owned()).collect()
+    }
+
+    #[test]
+    fn formats_a_vietnamese_mobile_number_as_it_is_typed() {
+        let mut formatter = AsYouTypeFormatter::new("VN");
+        let steps = typed(&mut formatter, "0912345678");
+        assert_eq!(
+            steps,
+            [
+                "0",
+                "09",
+                "091",
+                "0912",
+                "0912 3",
+                "0912 34",
+                "0912 345",
+                "0912 345 6",
+                "0912 345 67",
+                "0912 345 678"
+            ]
+        );
+    }
+
+    #[test]
+    fn completes_once_the_number_is_valid() {
+        let mut formatter = AsYouTypeFormatter::new("VN");
+        for ch in "091234567".chars() {
+            formatter.push(ch);
+            assert!(formatter.current().is_none(), "complete at {:?}", formatter.formatted());
+        }
+        formatter.push('8');
+        assert_eq!(formatter.current().unwrap().e164, "+84912345678");
+
+        formatter.backspace();
+        assert_eq!(formatter.formatted(), "0912 345 67");
+        assert!(formatter.current().is_none());
+    }
+
+    #[test]
+    fn formats_a_us_number() {
+        let mut formatter = AsYouTypeFormatter::new("US");
+        let steps = typed(&mut formatter, "(415) 555-2671");
+        let distinct: Vec<&str> = steps.iter().map(String::as_str).fold(Vec::new(), |mut distinct, step| {
+            if distinct.last() != Some(&step) {
+                distinct.push(step);
+            }
+            distinct
+        });
+        assert_eq!(
+            distinct,
+            [
+                "",
+                "4",
+                "41",
+                "415",
+                "415 5",
+                "415 55",
+                "415 555",
+                "415 555 2",
+                "415 555 26",
+                "415 555 267",
+                "415 555 2671"
+            ]
+        );
+        let number = formatter.current().unwrap();
+        assert_eq!(number.e164, "+14155552671");
+        assert_eq!(number.iso_country, Some("US"));
+    }
+
+    #[test]
+    fn a_plus_switches_to_an_international_number() {
+        let mut formatter = AsYouTypeFormatter::new("US");
+        typed(&mut formatter, "41");
+        let steps = typed(&mut formatter, "+84912345678");
+        assert_eq!(
+            steps,
+            [
+                "+",
+                "+8",
+                "+84",
+                "+84 9",
+                "+84 91",
+                "+84 912",
+                "+84 912 3",
+                "+84 912 34",
+                "+84 912 345",
+                "+84 912 345 6",
+                "+84 912 345 67",
+                "+84 912 345 678"
+            ]
+        );
+        assert_eq!(formatter.current().unwrap().iso_country, Some("VN"));
+
+        assert_eq!(formatter.push('+'), "+84 912 345 678");
+        for _ in 0..9 {
+            formatter.backspace();
+        }
+        assert_eq!(formatter.formatted(), "+84");
+        assert_eq!(formatter.backspace(), "+8");
+        assert_eq!(formatter.backspace(), "+");
+        assert_eq!(formatter.backspace(), "");
+    }
+
+    #[test]
+    fn overlong_input_is_left_ungrouped() {
+        let mut formatter = AsYouTypeFormatter::new("US");
+        typed(&mut formatter, "41555526719");
+        assert_eq!(formatter.formatted(), "41555526719");
+        assert!(formatter.current().is_none());
+        assert_eq!(formatter.backspace(), "415 555 2671");
+    }
+}
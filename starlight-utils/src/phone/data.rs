@@ -0,0 +1,128 @@
+//! The numbering plan tables, generated by `build.rs` from `data/numbering_plan.csv`
+//! and `data/areas.csv`: updating them is a change of those files, with a new
+//! `# version:` line in the first, and a rebuild.
+//! [`DATA_VERSION`] tells which version a build runs, e.g. in a service's build info.
+
+use super::metadata::{AreaMetadata, CountryMetadata};
+
+include!(concat!(env!("OUT_DIR"), "/phone_data.rs"));
+
+/// The calling code and ISO code of the country a calling code resolves to.
+pub(crate) fn main_country(calling_code: &str) -> Option<(&'static str, &'static str)> {
+    CALLING_CODES
+        .binary_search_by(|(code, _)| (*code).cmp(calling_code))
+        .ok()
+        .map(|index| CALLING_CODES[index])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    /// The ISO 3166-1 alpha-2 codes.
+    const ISO_3166: &str = "AD AE AF AG AI AL AM AO AQ AR AS AT AU AW AX AZ BA BB BD BE BF BG BH BI BJ BL BM BN BO \
+        BQ BR BS BT BV BW BY BZ CA CC CD CF CG CH CI CK CL CM CN CO CR CU CV CW CX CY CZ DE DJ DK DM DO DZ EC EE EG \
+        EH ER ES ET FI FJ FK FM FO FR GA GB GD GE GF GG GH GI GL GM GN GP GQ GR GS GT GU GW GY HK HM HN HR HT HU ID \
+        IE IL IM IN IO IQ IR IS IT JE JM JO JP KE KG KH KI KM KN KP KR KW KY KZ LA LB LC LI LK LR LS LT LU LV LY MA \
+        MC MD ME MF MG MH MK ML MM MN MO MP MQ MR MS MT MU MV MW MX MY MZ NA NC NE NF NG NI NL NO NP NR NU NZ OM PA \
+        PE PF PG PH PK PL PM PN PR PS PT PW PY QA RE RO RS RU RW SA SB SC SD SE SG SH SI SJ SK SL SM SN SO SR SS ST \
+        SV SX SY SZ TC TD TF TG TH TJ TK TL TM TN TO TR TT TV TW TZ UA UG UM US UY UZ VA VC VE VG VI VN VU WF WS YE \
+        YT ZA ZM ZW";
+
+    fn digits(s: &str) -> bool {
+        !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+    }
+
+    #[test]
+    fn countries_are_known_and_unique() {
+        assert!(!DATA_VERSION.is_empty());
+        let known: HashSet<&str> = ISO_3166.split_whitespace().collect();
+        let mut seen = HashSet::new();
+        for country in COUNTRIES {
+            assert!(known.contains(country.iso), "unknown ISO code {}", country.iso);
+            assert!(seen.insert(country.iso), "{} is listed twice", country.iso);
+            assert!(
+                digits(country.calling_code) && country.calling_code.len() <= 3,
+                "{}: calling code {:?}",
+                country.iso,
+                country.calling_code
+            );
+            assert!(country.trunk_prefix.is_none_or(digits), "{}: trunk prefix", country.iso);
+        }
+    }
+
+    #[test]
+    fn every_calling_code_resolves_to_one_of_its_countries() {
+        let codes: HashSet<&str> = COUNTRIES.iter().map(|country| country.calling_code).collect();
+        assert_eq!(CALLING_CODES.len(), codes.len());
+        assert!(CALLING_CODES.is_sorted_by_key(|(code, _)| *code));
+        for code in codes {
+            let (_, iso) = main_country(code).unwrap();
+            let country = COUNTRIES.iter().find(|country| country.iso == iso).unwrap();
+            assert_eq!(country.calling_code, code);
+        }
+        assert_eq!(main_country("1"), Some(("1", "US")));
+        assert_eq!(main_country("999"), None);
+    }
+
+    #[test]
+    fn prefixes_do_not_contradict_each_other() {
+        for country in COUNTRIES {
+            for prefix in country.mobile_prefixes.iter().chain(country.landline_prefixes) {
+                assert!(digits(prefix), "{}: prefix {:?}", country.iso, prefix);
+            }
+            // Mobile prefixes win, so a landline prefix under a mobile one could never match.
+            for landline in country.landline_prefixes {
+                let shadowing = country
+                    .mobile_prefixes
+                    .iter()
+                    .find(|mobile| landline.starts_with(*mobile));
+                assert!(
+                    shadowing.is_none(),
+                    "{}: landline prefix {} is under mobile prefix {:?}",
+                    country.iso,
+                    landline,
+                    shadowing
+                );
+            }
+            let unique: HashSet<_> = country
+                .mobile_prefixes
+                .iter()
+                .chain(country.landline_prefixes)
+                .collect();
+            assert_eq!(
+                unique.len(),
+                country.mobile_prefixes.len() + country.landline_prefixes.len(),
+                "{}: duplicate prefixes",
+                country.iso
+            );
+        }
+    }
+
+    #[test]
+    fn lengths_and_groups_agree() {
+        for country in COUNTRIES {
+            let lengths = country.national_lengths;
+            assert!(
+                !lengths.is_empty() && lengths.is_sorted(),
+                "{}: lengths {:?}",
+                country.iso,
+                lengths
+            );
+            assert!(
+                lengths.iter().all(|&length| (4..=14).contains(&length)),
+                "{}",
+                country.iso
+            );
+            let grouped: usize = country.format_groups.iter().sum();
+            assert!(
+                country.format_groups.iter().all(|&size| size > 0) && lengths.contains(&grouped),
+                "{}: groups {:?} do not add up to one of the lengths {:?}",
+                country.iso,
+                country.format_groups,
+                lengths
+            );
+        }
+    }
+}
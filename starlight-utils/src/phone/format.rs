@@ -0,0 +1,78 @@
+//! Displaying a [`PhoneNumber`] in the digit groups of its country, in full or masked
+//! for messages and logs.
+
+use super::PhoneNumber;
+use super::as_you_type::group;
+use super::metadata;
+
+/// Digits of the national number kept at its end when masked.
+const KEPT_LAST: usize = 3;
+/// Digits kept at its start, the operator or area prefix, when it has at least
+/// [`MIN_LEN_KEEPING_FIRST`] digits.
+const KEPT_FIRST: usize = 2;
+const MIN_LEN_KEEPING_FIRST: usize = 9;
+
+impl PhoneNumber {
+    /// As dialled within its country, with its trunk prefix, e.g. `0912 345 678`. The
+    /// national number is not grouped when its country is unknown.
+    pub fn national_format(&self) -> String {
+        national(self, &self.national_number)
+    }
+
+    /// [`e164`](Self::e164) with the middle digits replaced by `*`, e.g. `+8491****678`:
+    /// only the last 3 digits of the national number are kept, and its first 2 when it
+    /// has 9 digits or more.
+    pub fn masked_e164(&self) -> String {
+        format!("+{}{}", self.country_code, self.masked_national_number())
+    }
+
+    /// [`national_format`](Self::national_format) masked as [`masked_e164`](Self::masked_e164),
+    /// e.g. `091* *** 678`.
+    pub fn masked_national_format(&self) -> String {
+        national(self, &self.masked_national_number())
+    }
+
+    /// As dialled from abroad, masked as [`masked_e164`](Self::masked_e164), e.g.
+    /// `+84 91* *** 678`.
+    pub fn masked_international_format(&self) -> String {
+        let masked = self.masked_national_number();
+        match self.iso_country.and_then(metadata::lookup) {
+            Some(country) => format!("+{} {}", self.country_code, group(&masked, country)),
+            None => format!("+{} {}", self.country_code, masked),
+        }
+    }
+
+    fn masked_national_number(&self) -> String {
+        let len = self.national_number.chars().count();
+        let first = if len >= MIN_LEN_KEEPING_FIRST { KEPT_FIRST } else { 0 };
+        self.national_number
+            .chars()
+            .enumerate()
+            .map(|(i, digit)| if i < first || i + KEPT_LAST >= len { digit } else { '*' })
+            .collect()
+    }
+}
+
+fn national(number: &PhoneNumber, nsn: &str) -> String {
+    match number.iso_country.and_then(metadata::lookup) {
+        Some(country) => format!("{}{}", country.trunk_prefix.unwrap_or(""), group(nsn, country)),
+        None => nsn.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::phone::normalize_phone;
+
+    #[test]
+    fn masks_all_but_the_prefix_and_last_digits() {
+        let vn = normalize_phone("0912 345 678", "VN").unwrap();
+        assert_eq!(vn.national_format(), "0912 345 678");
+        assert_eq!(vn.masked_e164(), "+8491****678");
+        assert_eq!(vn.masked_national_format(), "091* *** 678");
+        assert_eq!(vn.masked_international_format(), "+84 91* *** 678");
+
+        let sg = normalize_phone("9123 4567", "SG").unwrap();
+        assert_eq!(sg.masked_e164(), "+65*****567");
+    }
+}
@@ -0,0 +1,24 @@
+//! Phone numbers as parameters of translated messages, with the feature `i18n`. They
+//! are masked either way: fields of `#[derive(I18nCode)]` errors render as the masked
+//! E.164, and those with `#[i18n(format = "phone")]` in the masked national format in
+//! locales of their region.
+
+use super::PhoneNumber;
+use starlight_protocol::i18n::{ParamValue, Phone, ToPhone};
+
+impl From<PhoneNumber> for ParamValue {
+    fn from(number: PhoneNumber) -> Self {
+        ParamValue::Text(number.masked_e164())
+    }
+}
+
+impl ToPhone for PhoneNumber {
+    fn to_phone(&self) -> Phone {
+        Phone {
+            region: self.iso_country,
+            e164: self.masked_e164(),
+            national: self.masked_national_format(),
+            international: self.masked_international_format(),
+        }
+    }
+}
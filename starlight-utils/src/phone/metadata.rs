@@ -0,0 +1,156 @@
+//! Per-country numbering data behind the normalization in [`crate::phone`], shared with
+//! frontends through [`export_json`] (feature `metadata-export`) so phone inputs
+//! follow the same rules.
+
+pub use super::data::{AREAS, COUNTRIES};
+
+/// Numbering data of one country.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CountryMetadata {
+    /// ISO 3166-1 alpha-2, e.g. `VN`.
+    pub iso: &'static str,
+    /// Country calling code without `+`, e.g. `84`.
+    pub calling_code: &'static str,
+    /// Dialled before national numbers and stripped by normalization, e.g. `0`.
+    pub trunk_prefix: Option<&'static str>,
+    /// Valid lengths of the national significant number.
+    pub national_lengths: &'static [usize],
+    /// Leading digits of mobile national numbers; empty where mobiles cannot be told
+    /// apart by prefix, as in the NANP. Checked before `landline_prefixes`.
+    pub mobile_prefixes: &'static [&'static str],
+    /// Leading digits of fixed-line national numbers.
+    pub landline_prefixes: &'static [&'static str],
+    /// Sizes of the digit groups a national number is displayed in, e.g. `[3, 3, 3]`
+    /// for `912 345 678`.
+    pub format_groups: &'static [usize],
+}
+
+/// A geographic area of a country: its national numbers starting with `prefix`, such
+/// as a Vietnamese landline area code or a NANP area code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AreaMetadata {
+    pub calling_code: &'static str,
+    pub prefix: &'static str,
+    /// ISO 3166-1 alpha-2 of the country of the area, e.g. `CA` for `+1 416`.
+    pub iso: &'static str,
+    /// The province or state, e.g. `Ho Chi Minh City` or `Ontario`.
+    pub label: &'static str,
+    /// People living in the whole area, rounded.
+    pub population: u32,
+}
+
+/// The metadata of a country by ISO code, case-insensitively.
+pub fn lookup(iso: &str) -> Option<&'static CountryMetadata> {
+    COUNTRIES.iter().find(|country| country.iso.eq_ignore_ascii_case(iso))
+}
+
+/// Version of the [`export_json`] schema, bumped on incompatible changes.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// [`COUNTRIES`] as pretty printed JSON ending with a newline, keys sorted so that
+/// diffs of the exported file are reviewable:
+///
+/// ```json
+/// {
+///   "countries": {
+///     "VN": {
+///       "calling_code": "84",
+///       "format_groups": [3, 3, 3],
+///       "landline_prefixes": ["2"],
+///       "mobile_prefixes": ["3", "5", "7", "8", "9"],
+///       "national_lengths": [9, 10],
+///       "trunk_prefix": "0"
+///     }
+///   },
+///   "data_version": "2026.10",
+///   "schema_version": 1
+/// }
+/// ```
+///
+/// `trunk_prefix` is `null` for countries without one.
+#[cfg(feature = "metadata-export")]
+pub fn export_json() -> String {
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+
+    // Fields are declared in alphabetical order, which is the order they serialize in.
+    #[derive(Serialize)]
+    struct Export {
+        countries: BTreeMap<&'static str, Country>,
+        data_version: &'static str,
+        schema_version: u32,
+    }
+
+    #[derive(Serialize)]
+    struct Country {
+        calling_code: &'static str,
+        format_groups: &'static [usize],
+        landline_prefixes: &'static [&'static str],
+        mobile_prefixes: &'static [&'static str],
+        national_lengths: &'static [usize],
+        trunk_prefix: Option<&'static str>,
+    }
+
+    let export = Export {
+        countries: COUNTRIES
+            .iter()
+            .map(|country| {
+                let exported = Country {
+                    calling_code: country.calling_code,
+                    format_groups: country.format_groups,
+                    landline_prefixes: country.landline_prefixes,
+                    mobile_prefixes: country.mobile_prefixes,
+                    national_lengths: country.national_lengths,
+                    trunk_prefix: country.trunk_prefix,
+                };
+                (country.iso, exported)
+            })
+            .collect(),
+        data_version: super::data::DATA_VERSION,
+        schema_version: SCHEMA_VERSION,
+    };
+    let mut json = serde_json::to_string_pretty(&export).expect("the metadata serializes to JSON");
+    json.push('\n');
+    json
+}
+
+#[cfg(all(test, feature = "metadata-export"))]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn export_round_trips_against_the_table() {
+        let exported: Value = serde_json::from_str(&export_json()).unwrap();
+        assert_eq!(exported["schema_version"], SCHEMA_VERSION);
+        assert_eq!(exported["data_version"], crate::phone::data::DATA_VERSION);
+        assert_eq!(exported["countries"].as_object().unwrap().len(), COUNTRIES.len());
+
+        for iso in ["VN", "US", "GB", "SG", "JP"] {
+            let expected = lookup(iso).unwrap();
+            let country = &exported["countries"][iso];
+            assert_eq!(country["calling_code"], expected.calling_code);
+            assert_eq!(country["trunk_prefix"].as_str(), expected.trunk_prefix);
+            let lengths: Vec<usize> = serde_json::from_value(country["national_lengths"].clone()).unwrap();
+            assert_eq!(lengths, expected.national_lengths);
+            let mobile: Vec<String> = serde_json::from_value(country["mobile_prefixes"].clone()).unwrap();
+            assert_eq!(mobile, expected.mobile_prefixes);
+            let landline: Vec<String> = serde_json::from_value(country["landline_prefixes"].clone()).unwrap();
+            assert_eq!(landline, expected.landline_prefixes);
+            let groups: Vec<usize> = serde_json::from_value(country["format_groups"].clone()).unwrap();
+            assert_eq!(groups, expected.format_groups);
+        }
+    }
+
+    #[test]
+    fn export_is_deterministic_and_sorted() {
+        let json = export_json();
+        assert_eq!(json, export_json());
+        let au = json.find("\"AU\"").unwrap();
+        let vn = json.find("\"VN\"").unwrap();
+        assert!(au < vn);
+        let calling_code = json.find("\"calling_code\"").unwrap();
+        let trunk_prefix = json.find("\"trunk_prefix\"").unwrap();
+        assert!(calling_code < trunk_prefix);
+    }
+}
@@ -0,0 +1,192 @@
+//! Coarse geography of phone numbers for analytics: the country, and the province or
+//! state of numbers whose leading digits tell it, from [`AREAS`], without keeping the
+//! number itself.
+
+use super::metadata::{self, AREAS, AreaMetadata};
+use super::{PhoneNumber, is_valid_e164, match_country_code_prefix};
+
+/// Where a number is from, no finer than a Vietnamese province or a NANP state or
+/// province.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RegionBucket {
+    /// ISO 3166-1 alpha-2; `None` for calling codes without numbering data.
+    pub iso_country: Option<&'static str>,
+    /// The province or state; `None` for mobiles, numbers of countries without area
+    /// data and areas too small for the [`Granularity`].
+    pub area_label: Option<&'static str>,
+}
+
+/// The smallest population an area must have to be reported: smaller areas are reported
+/// as their country, so a bucket cannot single out a few people.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Granularity {
+    pub min_population: u32,
+}
+
+impl Default for Granularity {
+    /// Areas of a million people or more.
+    fn default() -> Self {
+        Granularity {
+            min_population: 1_000_000,
+        }
+    }
+}
+
+impl Granularity {
+    pub fn new(min_population: u32) -> Self {
+        Granularity { min_population }
+    }
+}
+
+impl PhoneNumber {
+    /// The region of the number with the default [`Granularity`].
+    pub fn region_bucket(&self) -> RegionBucket {
+        self.region_bucket_with(Granularity::default())
+    }
+
+    pub fn region_bucket_with(&self, granularity: Granularity) -> RegionBucket {
+        locate(&self.country_code, self.iso_country, &self.national_number, granularity)
+    }
+}
+
+/// The region of an E.164 number with the default [`Granularity`], or `None` when it
+/// is not one. Nothing of the number is copied.
+///
+/// ```
+/// use starlight_utils::phone::{RegionBucket, region_bucket_from_e164};
+///
+/// let bucket = region_bucket_from_e164("+14165550123").unwrap();
+/// assert_eq!(bucket, RegionBucket { iso_country: Some("CA"), area_label: Some("Ontario") });
+/// ```
+pub fn region_bucket_from_e164(e164: &str) -> Option<RegionBucket> {
+    region_bucket_from_e164_with(e164, Granularity::default())
+}
+
+pub fn region_bucket_from_e164_with(e164: &str, granularity: Granularity) -> Option<RegionBucket> {
+    if !is_valid_e164(e164) {
+        return None;
+    }
+    let digits = &e164[1..];
+    let Some((calling_code, iso)) = match_country_code_prefix(digits) else {
+        return Some(RegionBucket::default());
+    };
+    Some(locate(calling_code, iso, &digits[calling_code.len()..], granularity))
+}
+
+fn locate(calling_code: &str, iso: Option<&'static str>, national: &str, granularity: Granularity) -> RegionBucket {
+    let trunk = iso.and_then(metadata::lookup).and_then(|country| country.trunk_prefix);
+    let national = trunk.and_then(|trunk| national.strip_prefix(trunk)).unwrap_or(national);
+    match area(calling_code, national) {
+        // The area tells the country where a calling code is shared, as in the NANP.
+        Some(area) => RegionBucket {
+            iso_country: Some(area.iso),
+            area_label: (area.population >= granularity.min_population).then_some(area.label),
+        },
+        None => RegionBucket {
+            iso_country: iso,
+            area_label: None,
+        },
+    }
+}
+
+/// The area with the longest prefix of `national`.
+fn area(calling_code: &str, national: &str) -> Option<&'static AreaMetadata> {
+    AREAS
+        .iter()
+        .filter(|area| area.calling_code == calling_code && national.starts_with(area.prefix))
+        .max_by_key(|area| area.prefix.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::phone::normalize_phone;
+
+    fn bucket(iso_country: Option<&'static str>, area_label: Option<&'static str>) -> RegionBucket {
+        RegionBucket {
+            iso_country,
+            area_label,
+        }
+    }
+
+    #[test]
+    fn vietnamese_landlines_have_their_province() {
+        let landline = normalize_phone("028 3822 8899", "VN").unwrap();
+        assert_eq!(landline.region_bucket(), bucket(Some("VN"), Some("Ho Chi Minh City")));
+        assert_eq!(
+            region_bucket_from_e164("+842438250000"),
+            Some(bucket(Some("VN"), Some("Ha Noi")))
+        );
+        assert_eq!(
+            region_bucket_from_e164("+842363822222"),
+            Some(bucket(Some("VN"), Some("Da Nang")))
+        );
+    }
+
+    #[test]
+    fn vietnamese_mobiles_are_not_geographic() {
+        // A Viettel number bought in Ho Chi Minh City: prefixes belong to carriers.
+        let mobile = normalize_phone("0903 123 456", "VN").unwrap();
+        assert_eq!(mobile.region_bucket(), bucket(Some("VN"), None));
+        assert_eq!(region_bucket_from_e164("+84903123456"), Some(bucket(Some("VN"), None)));
+    }
+
+    #[test]
+    fn nanp_area_codes_tell_the_country_and_state() {
+        let toronto = normalize_phone("+1 416 555 0123", "US").unwrap();
+        assert_eq!(toronto.iso_country, Some("US"));
+        assert_eq!(toronto.region_bucket(), bucket(Some("CA"), Some("Ontario")));
+        assert_eq!(
+            region_bucket_from_e164("+12125550123"),
+            Some(bucket(Some("US"), Some("New York")))
+        );
+        // Area codes missing from the table are the main country of the calling code.
+        assert_eq!(region_bucket_from_e164("+18005550123"), Some(bucket(Some("US"), None)));
+    }
+
+    #[test]
+    fn other_countries_have_no_area() {
+        assert_eq!(region_bucket_from_e164("+33123456789"), Some(bucket(Some("FR"), None)));
+        // Ukraine has no numbering data.
+        assert_eq!(region_bucket_from_e164("+380441234567"), Some(bucket(None, None)));
+        assert_eq!(region_bucket_from_e164("0903123456"), None);
+    }
+
+    #[test]
+    fn small_areas_roll_up_to_the_country() {
+        assert_eq!(region_bucket_from_e164("+13075550123"), Some(bucket(Some("US"), None)));
+        assert_eq!(region_bucket_from_e164("+18675550123"), Some(bucket(Some("CA"), None)));
+        assert_eq!(region_bucket_from_e164("+842133876543"), Some(bucket(Some("VN"), None)));
+
+        let everything = Granularity::new(0);
+        assert_eq!(
+            region_bucket_from_e164_with("+13075550123", everything),
+            Some(bucket(Some("US"), Some("Wyoming")))
+        );
+        assert_eq!(
+            region_bucket_from_e164_with("+842133876543", everything),
+            Some(bucket(Some("VN"), Some("Lai Chau")))
+        );
+
+        let coarse = Granularity::new(20_000_000);
+        let toronto = normalize_phone("+1 416 555 0123", "US").unwrap();
+        assert_eq!(toronto.region_bucket_with(coarse), bucket(Some("CA"), None));
+        assert_eq!(
+            region_bucket_from_e164_with("+842838228899", coarse),
+            Some(bucket(Some("VN"), None))
+        );
+    }
+
+    #[test]
+    fn areas_are_not_under_mobile_prefixes() {
+        for area in AREAS {
+            let country = metadata::lookup(area.iso).unwrap();
+            assert_eq!(country.calling_code, area.calling_code, "{}", area.label);
+            let mobile = country
+                .mobile_prefixes
+                .iter()
+                .find(|mobile| area.prefix.starts_with(*mobile));
+            assert!(mobile.is_none(), "{} {} is a mobile prefix", area.iso, area.prefix);
+        }
+    }
+}